@@ -0,0 +1,151 @@
+//! Driver for 25xx-series SPI EEPROMs, like the 25LC256
+//!
+//! This crate stands in for a third-party driver that is being tested on the
+//! test stand. It only depends on `embedded-hal`, and knows nothing about the
+//! test stand. See the README of this example for how it is wired up.
+
+
+#![no_std]
+
+
+use embedded_hal::{
+    blocking::spi::{
+        Transfer,
+        Write,
+    },
+    digital::v2::OutputPin,
+};
+
+
+/// The size of the EEPROM, in bytes
+pub const SIZE: usize = 32 * 1024;
+
+/// The size of a page, in bytes
+///
+/// A single write can't cross the boundary between two pages.
+pub const PAGE_SIZE: usize = 64;
+
+
+const READ:  u8 = 0x03;
+const WRITE: u8 = 0x02;
+const WREN:  u8 = 0x06;
+const RDSR:  u8 = 0x05;
+
+/// The write-in-progress bit of the status register
+const WIP: u8 = 0x01;
+
+
+/// A 25xx-series SPI EEPROM
+pub struct Eeprom<Spi, Cs> {
+    spi: Spi,
+    cs:  Cs,
+}
+
+impl<Spi, Cs, E> Eeprom<Spi, Cs>
+    where
+        Spi: Transfer<u8, Error = E> + Write<u8, Error = E>,
+        Cs:  OutputPin,
+{
+    /// Create a new instance of `Eeprom`
+    ///
+    /// `cs` is the chip select pin of the EEPROM. It is driven high, which
+    /// deselects the EEPROM.
+    pub fn new(spi: Spi, mut cs: Cs) -> Result<Self, Error<E, Cs::Error>> {
+        cs.set_high()
+            .map_err(|err| Error::Pin(err))?;
+
+        Ok(Self { spi, cs })
+    }
+
+    /// Read from the EEPROM, starting at `address`
+    ///
+    /// Fills all of `buf`. Reads can cross page boundaries.
+    pub fn read(&mut self, address: u16, buf: &mut [u8])
+        -> Result<(), Error<E, Cs::Error>>
+    {
+        if address as usize + buf.len() > SIZE {
+            return Err(Error::OutOfBounds);
+        }
+
+        self.transaction(|spi| {
+            let [high, low] = address.to_be_bytes();
+            spi.write(&[READ, high, low])?;
+
+            for b in buf.iter_mut() {
+                *b = 0;
+            }
+            spi.transfer(buf)?;
+
+            Ok(())
+        })
+    }
+
+    /// Write to the EEPROM, starting at `address`
+    ///
+    /// All of `data` must fit into the page that `address` is in. Returns
+    /// right after starting the write. Use `is_busy` to find out when it has
+    /// finished.
+    pub fn write_page(&mut self, address: u16, data: &[u8])
+        -> Result<(), Error<E, Cs::Error>>
+    {
+        let offset = address as usize % PAGE_SIZE;
+        if offset + data.len() > PAGE_SIZE {
+            return Err(Error::PageBoundary);
+        }
+        if address as usize + data.len() > SIZE {
+            return Err(Error::OutOfBounds);
+        }
+
+        self.transaction(|spi| spi.write(&[WREN]))?;
+        self.transaction(|spi| {
+            let [high, low] = address.to_be_bytes();
+            spi.write(&[WRITE, high, low])?;
+            spi.write(data)
+        })
+    }
+
+    /// Indicates whether the EEPROM is still busy writing
+    pub fn is_busy(&mut self) -> Result<bool, Error<E, Cs::Error>> {
+        let mut status = [RDSR, 0];
+        self.transaction(|spi| spi.transfer(&mut status).map(|_| ()))?;
+
+        Ok(status[1] & WIP != 0)
+    }
+
+    /// Release the SPI instance and chip select pin
+    pub fn free(self) -> (Spi, Cs) {
+        (self.spi, self.cs)
+    }
+
+    /// Select the EEPROM while `f` runs
+    ///
+    /// The EEPROM is deselected afterwards, even if `f` returns an error.
+    fn transaction(&mut self, f: impl FnOnce(&mut Spi) -> Result<(), E>)
+        -> Result<(), Error<E, Cs::Error>>
+    {
+        self.cs.set_low()
+            .map_err(|err| Error::Pin(err))?;
+        let result = f(&mut self.spi);
+        self.cs.set_high()
+            .map_err(|err| Error::Pin(err))?;
+
+        result.map_err(|err| Error::Spi(err))
+    }
+}
+
+
+/// An error returned by `Eeprom`
+#[derive(Debug)]
+pub enum Error<Spi, Pin> {
+    /// The SPI instance returned an error
+    Spi(Spi),
+
+    /// The chip select pin returned an error
+    Pin(Pin),
+
+    /// A write would have crossed the boundary between two pages
+    PageBoundary,
+
+    /// An access would have gone beyond the end of the EEPROM
+    OutOfBounds,
+}
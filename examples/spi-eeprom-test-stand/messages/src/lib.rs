@@ -0,0 +1,113 @@
+//! The messages of the SPI EEPROM test stand
+//!
+//! Every test stand defines its own messages, covering what its test suite
+//! needs to do with the hardware. This test stand only exercises the EEPROM
+//! driver. The messages of the LPC845 test stand show how to add the generic
+//! building blocks from `test-stand-infra/protocol`, once they're needed.
+
+
+#![no_std]
+
+
+use protocol::chunk::{
+    self,
+    Chunked,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+    Serializer,
+};
+
+
+/// The maximum number of bytes that can be read with one request
+///
+/// Keeps the reply within the buffer that the target firmware serializes its
+/// messages into.
+pub const READ_CAP: usize = 64;
+
+
+/// A message from the test suite on the host to the test target
+#[derive(Debug, Deserialize, Serialize)]
+pub enum HostToTarget<'r> {
+    /// Instruct the target to read from the EEPROM
+    ///
+    /// `len` must not be larger than `READ_CAP`. The target replies with
+    /// `ReadResult`.
+    Read {
+        address: u16,
+        len:     u8,
+    },
+
+    /// Instruct the target to write to the EEPROM
+    ///
+    /// All of `data` must fit into one page of the EEPROM. The target replies
+    /// with `WriteComplete`, once the EEPROM has finished writing.
+    Write {
+        address: u16,
+        data:    &'r [u8],
+    },
+
+    /// A part of a request that is too large for a single frame
+    ///
+    /// The host sends these automatically, and the firmware reassembles them.
+    /// Every message type the host sends needs this variant.
+    Chunk {
+        id:    u8,
+        index: u16,
+        total: u16,
+        data:  &'r [u8],
+    },
+}
+
+impl Chunked for HostToTarget<'_> {
+    fn serialize_chunk<S>(chunk: chunk::Chunk<'_>, serializer: S)
+        -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let chunk::Chunk { id, index, total, data } = chunk;
+        HostToTarget::Chunk { id, index, total, data }.serialize(serializer)
+    }
+
+    fn chunk(&self) -> Option<chunk::Chunk<'_>> {
+        match *self {
+            Self::Chunk { id, index, total, data } => {
+                Some(chunk::Chunk { id, index, total, data })
+            }
+            _ => {
+                None
+            }
+        }
+    }
+}
+
+
+/// A message from the test target to the test suite on the host
+#[derive(Debug, Deserialize, Serialize)]
+pub enum TargetToHost<'r> {
+    /// Reply to `Read`
+    ReadResult(&'r [u8]),
+
+    /// Reply to `Write`
+    WriteComplete,
+
+    /// Reply to any request, if the driver returned an error
+    DriverError(DriverError),
+}
+
+
+/// An error returned by the EEPROM driver
+///
+/// Mirrors the driver's own error type, without the errors of the SPI
+/// instance and pins, which the host doesn't need to know about.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum DriverError {
+    /// The SPI instance or the chip select pin returned an error
+    Bus,
+
+    /// A write would have crossed the boundary between two pages
+    PageBoundary,
+
+    /// An access would have gone beyond the end of the EEPROM
+    OutOfBounds,
+}
@@ -0,0 +1,41 @@
+/// Test-suite specific error module
+
+
+use super::{
+    target::{
+        TargetReadError,
+        TargetWriteError,
+    },
+    test_stand::TestStandInitError,
+};
+
+
+/// Result type specific to this test suite
+pub type Result<T = ()> = std::result::Result<T, Error>;
+
+
+/// Error type specific to this test suite
+#[derive(Debug)]
+pub enum Error {
+    TargetRead(TargetReadError),
+    TargetWrite(TargetWriteError),
+    TestStandInit(TestStandInitError),
+}
+
+impl From<TargetReadError> for Error {
+    fn from(err: TargetReadError) -> Self {
+        Self::TargetRead(err)
+    }
+}
+
+impl From<TargetWriteError> for Error {
+    fn from(err: TargetWriteError) -> Self {
+        Self::TargetWrite(err)
+    }
+}
+
+impl From<TestStandInitError> for Error {
+    fn from(err: TestStandInitError) -> Self {
+        Self::TestStandInit(err)
+    }
+}
@@ -0,0 +1,18 @@
+//! The library code that supports the SPI EEPROM test suite
+//!
+//! Wraps the generic test stand code from `host-lib`, and adds the requests
+//! that this test stand's firmware supports.
+
+
+pub mod error;
+pub mod target;
+pub mod test_stand;
+
+
+pub use self::{
+    error::{
+        Error,
+        Result,
+    },
+    test_stand::TestStand,
+};
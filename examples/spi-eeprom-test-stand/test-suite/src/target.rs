@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use host_lib::conn::{
+    Conn,
+    ConnReceiveError,
+    ConnSendError,
+};
+use spi_eeprom_messages::{
+    DriverError,
+    HostToTarget,
+    READ_CAP,
+    TargetToHost,
+};
+
+
+/// The connection to the test target
+pub struct Target {
+    conn: Conn,
+}
+
+impl Target {
+    pub(crate) fn new(conn: Conn) -> Self {
+        Self { conn }
+    }
+
+    /// Instruct the target to read `len` bytes from the EEPROM
+    ///
+    /// `len` must not be larger than `READ_CAP`.
+    pub fn read(&mut self, address: u16, len: usize, timeout: Duration)
+        -> Result<Vec<u8>, TargetReadError>
+    {
+        assert!(len <= READ_CAP);
+
+        self.conn
+            .send(&HostToTarget::Read { address, len: len as u8 })
+            .map_err(|err| TargetReadError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetReadError::Receive(err))?;
+
+        match message {
+            TargetToHost::ReadResult(data) => {
+                Ok(data.to_vec())
+            }
+            TargetToHost::DriverError(err) => {
+                Err(TargetReadError::Driver(err))
+            }
+            message => {
+                Err(
+                    TargetReadError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to write `data` to the EEPROM
+    ///
+    /// Returns once the EEPROM has finished writing.
+    pub fn write(&mut self, address: u16, data: &[u8], timeout: Duration)
+        -> Result<(), TargetWriteError>
+    {
+        self.conn
+            .send(&HostToTarget::Write { address, data })
+            .map_err(|err| TargetWriteError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetWriteError::Receive(err))?;
+
+        match message {
+            TargetToHost::WriteComplete => {
+                Ok(())
+            }
+            TargetToHost::DriverError(err) => {
+                Err(TargetWriteError::Driver(err))
+            }
+            message => {
+                Err(
+                    TargetWriteError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum TargetReadError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    Driver(DriverError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetWriteError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    Driver(DriverError),
+    UnexpectedMessage(String),
+}
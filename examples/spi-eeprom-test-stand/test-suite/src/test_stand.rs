@@ -0,0 +1,62 @@
+use std::sync::{
+    LockResult,
+    MutexGuard,
+};
+
+use host_lib::{
+    lock::DeviceLock,
+    test_stand::NotConfiguredError,
+    trace,
+};
+
+use super::target::Target;
+
+
+/// An instance of the test stand
+///
+/// Used to access all resources that a test case requires. This test stand
+/// only has a target. The EEPROM is connected to it directly, so no assistant
+/// is needed.
+pub struct TestStand {
+    _guard: LockResult<MutexGuard<'static, ()>>,
+    _trace: trace::Guard,
+
+    pub target: Target,
+
+    // Declared last, so the locks are released after the connection has been
+    // closed.
+    _locks: Vec<DeviceLock>,
+}
+
+impl TestStand {
+    /// Initializes the test stand
+    ///
+    /// Reads the `test-stand.toml` configuration file and initializes test
+    /// stand resources, as configured in there.
+    pub fn new() -> Result<Self, TestStandInitError> {
+        let test_stand = host_lib::TestStand::builder().build()
+            .map_err(|err| TestStandInitError::Inner(err))?;
+
+        Ok(
+            TestStand {
+                _guard: test_stand.guard,
+                _trace: test_stand.trace,
+                target: Target::new(test_stand.target?),
+                _locks: test_stand.locks,
+            }
+        )
+    }
+}
+
+
+#[derive(Debug)]
+pub enum TestStandInitError {
+    Inner(host_lib::test_stand::TestStandInitError),
+    NotConfigured(NotConfiguredError),
+}
+
+impl From<NotConfiguredError> for TestStandInitError {
+    fn from(err: NotConfiguredError) -> Self {
+        Self::NotConfigured(err)
+    }
+}
@@ -0,0 +1,52 @@
+//! Test Suite for the SPI EEPROM driver
+//!
+//! This test suite communicates with hardware. See the README.md of this
+//! example for wiring instructions.
+
+
+use std::time::Duration;
+
+use spi_eeprom_messages::DriverError;
+use spi_eeprom_test_suite::{
+    Result,
+    TestStand,
+    target::TargetWriteError,
+};
+
+
+#[test]
+fn it_should_read_back_written_data() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The second half of the first page. Writes to it don't affect any other
+    // test case.
+    let address = 32;
+
+    for &data in &[b"Hello, world!", b"Goodbye, all!"] {
+        test_stand.target.write(address, data, timeout)?;
+        let read = test_stand.target.read(address, data.len(), timeout)?;
+
+        assert_eq!(read, data);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_refuse_writes_across_page_boundaries() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // Pages are 64 bytes long, so this write would end on the next page.
+    let result = test_stand.target.write(60, b"12345678", timeout);
+
+    match result {
+        Err(TargetWriteError::Driver(DriverError::PageBoundary)) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    Ok(())
+}
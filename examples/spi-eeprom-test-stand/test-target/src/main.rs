@@ -0,0 +1,224 @@
+//! Firmware for the SPI EEPROM test stand
+//!
+//! Runs on an LPC845-BRK, with a 25xx-series SPI EEPROM connected to it. Makes
+//! the EEPROM driver available to the test suite on the host.
+//!
+//! This firmware is kept as simple as possible. It doesn't use interrupts, and
+//! polls the host link instead. The LPC845 test stand shows how to do the same
+//! with RTIC, once a test stand needs to handle multiple things at once.
+
+
+#![no_main]
+#![no_std]
+
+
+extern crate panic_rtt_target;
+
+
+use cortex_m_rt::entry;
+use lpc8xx_hal::{
+    Peripherals,
+    gpio,
+    spi,
+    syscon::frg,
+    usart,
+};
+use rtt_target::rprintln;
+use void::{
+    ResultVoidExt,
+    Void,
+};
+
+use firmware_lib::usart::{
+    Usart,
+    rx::ProcessError,
+};
+use protocol::chunk::Reassembly;
+use spi_eeprom::Eeprom;
+use spi_eeprom_messages::{
+    DriverError,
+    HostToTarget,
+    READ_CAP,
+    TargetToHost,
+};
+
+
+#[entry]
+fn main() -> ! {
+    // `cortex-m-rt` gives us safe access to these `static mut`s, as this
+    // function is only called once.
+    static mut HOST:       Usart = Usart::new();
+    static mut REASSEMBLY: Reassembly = Reassembly::new();
+
+    rtt_target::rtt_init_print!();
+    rprintln!("Starting target.");
+
+    // Get access to the device's peripherals. This can't panic, since this is
+    // the only place in this program where we call this method.
+    let p = Peripherals::take().unwrap_or_else(|| unreachable!());
+
+    let mut syscon = p.SYSCON.split();
+    let     swm    = p.SWM.split();
+    let     gpio   = p.GPIO.enable(&mut syscon.handle);
+
+    let mut swm_handle = swm.handle.enable(&mut syscon.handle);
+
+    // Configure the clock for USART0, using the Fractional Rate Generator
+    // (FRG) and the USART's own baud rate divider value (BRG). See user
+    // manual, section 17.7.1. The resulting rate is roughly 115200 baud.
+    let clock_config = {
+        syscon.frg0.select_clock(frg::Clock::FRO);
+        syscon.frg0.set_mult(22);
+        syscon.frg0.set_div(0xFF);
+        usart::Clock::new(&syscon.frg0, 5, 16)
+    };
+
+    // On the LPC845-BRK, these are the pins connected to the programmer, and
+    // bridged to the host via USB.
+    let (u0_rxd, _) = swm.movable_functions.u0_rxd.assign(
+        p.pins.pio0_24.into_swm_pin(),
+        &mut swm_handle,
+    );
+    let (u0_txd, _) = swm.movable_functions.u0_txd.assign(
+        p.pins.pio0_25.into_swm_pin(),
+        &mut swm_handle,
+    );
+
+    // No interrupts are enabled. Received data is polled in the loop below.
+    let host = p.USART0.enable_async(
+        &clock_config,
+        &mut syscon.handle,
+        u0_rxd,
+        u0_txd,
+        usart::Settings::default(),
+    );
+
+    // The EEPROM is connected to SPI0, and selected using a GPIO pin. See the
+    // README of this example for the wiring.
+    let (spi0_sck, _) = swm.movable_functions.spi0_sck.assign(
+        p.pins.pio0_16.into_swm_pin(),
+        &mut swm_handle,
+    );
+    let (spi0_mosi, _) = swm.movable_functions.spi0_mosi.assign(
+        p.pins.pio0_17.into_swm_pin(),
+        &mut swm_handle,
+    );
+    let (spi0_miso, _) = swm.movable_functions.spi0_miso.assign(
+        p.pins.pio0_18.into_swm_pin(),
+        &mut swm_handle,
+    );
+    let cs = p.pins.pio0_19.into_output_pin(
+        gpio.tokens.pio0_19,
+        gpio::Level::High,
+    );
+
+    let spi = p.SPI0.enable_as_master(
+        &spi::Clock::new(&syscon.iosc, 0x0fff),
+        &mut syscon.handle,
+        spi::MODE_0,
+        spi0_sck,
+        spi0_mosi,
+        spi0_miso,
+    );
+
+    // This is where the driver under test comes in. Everything else in this
+    // firmware is the same for any driver.
+    let mut eeprom = Eeprom::new(spi, cs)
+        .unwrap_or_else(|_| unreachable!());
+
+    let (mut host_rx_int, mut host_rx, mut host_tx) = HOST.init(host);
+    host_rx.reassembly = Some(REASSEMBLY);
+
+    let mut buf = [0; 256];
+
+    // Let the host know that we're ready.
+    host_tx.send_sync()
+        .void_unwrap();
+
+    loop {
+        host_rx_int.receive()
+            .expect("Error receiving from USART0");
+
+        let result = host_rx
+            .process_message(|message| {
+                let mut data = [0; READ_CAP];
+
+                let result = match message {
+                    HostToTarget::Read { address, len } => {
+                        let data = &mut data[..(len as usize).min(READ_CAP)];
+
+                        eeprom.read(address, data)
+                            .map(move |()| TargetToHost::ReadResult(data))
+                    }
+                    HostToTarget::Write { address, data } => {
+                        eeprom.write_page(address, data)
+                            .and_then(|()| {
+                                while eeprom.is_busy()? {}
+                                Ok(())
+                            })
+                            .map(|()| TargetToHost::WriteComplete)
+                    }
+                    HostToTarget::Chunk { .. } => {
+                        // Chunks are collected by the receiver. This one was
+                        // reassembled from other chunks, which the host never
+                        // sends. Drop it.
+                        rprintln!("Dropping nested chunk.");
+                        return Ok(());
+                    }
+                };
+
+                let reply = result
+                    .unwrap_or_else(|err| {
+                        let err = match err {
+                            spi_eeprom::Error::Spi(_) |
+                            spi_eeprom::Error::Pin(_) => {
+                                DriverError::Bus
+                            }
+                            spi_eeprom::Error::PageBoundary => {
+                                DriverError::PageBoundary
+                            }
+                            spi_eeprom::Error::OutOfBounds => {
+                                DriverError::OutOfBounds
+                            }
+                        };
+                        TargetToHost::DriverError(err)
+                    });
+
+                host_tx.send_message(&reply, &mut buf)
+                    .unwrap();
+
+                Ok::<_, Void>(())
+            });
+        match result {
+            Err(ProcessError::LinkSync) => {
+                // A host is attaching, and starts over.
+                host_tx.resync()
+                    .void_unwrap();
+            }
+            Err(ProcessError::Postcard(_)) => {
+                // Whatever was received before the host attached. Drop it.
+                rprintln!("Dropping undecodable host request.");
+            }
+            Err(ProcessError::ChecksumMismatch) => {
+                // No checksum is ever negotiated by this test stand, so this
+                // doesn't happen.
+                rprintln!("Dropping corrupted host request.");
+            }
+            Err(ProcessError::Chunk(err)) => {
+                // A chunk of a large write got lost. The host won't receive a
+                // reply, and can retransmit the request.
+                rprintln!("Dropping incomplete host request: {:?}", err);
+            }
+            Err(ProcessError::BufferFull) => {
+                // The request didn't fit into the receive buffer, and the rest
+                // of it is being skipped. Drop it.
+                rprintln!("Dropping oversized host request.");
+            }
+            Err(ProcessError::Other(err)) => {
+                match err {}
+            }
+            Ok(()) => {}
+        }
+        host_rx.clear_buf();
+    }
+}
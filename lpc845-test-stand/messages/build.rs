@@ -0,0 +1,215 @@
+//! Generates the message schema
+//!
+//! Parses the source code of this crate and the `protocol` crate it re-exports
+//! from, and writes a JSON description of all serializable types to
+//! `$OUT_DIR/schema.json`. The crate embeds this file as `SCHEMA`.
+//!
+//! Also derives the protocol version from the schema, and writes it to
+//! `$OUT_DIR/protocol_version.rs`. The crate embeds it as `PROTOCOL_VERSION`.
+
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use quote::ToTokens as _;
+use syn::{
+    Attribute,
+    Fields,
+    Generics,
+    Item,
+};
+
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let protocol     = manifest_dir.join("../../test-stand-infra/protocol/src");
+
+    // Modules are named relative to the root of this crate, which re-exports
+    // the modules of `protocol`. All of them are included, so the schema and
+    // the protocol version can't miss a module that has been added.
+    let mut sources = vec![("".to_owned(), protocol.join("lib.rs"))];
+    sources.extend(protocol_modules(&protocol));
+    sources.push(("".to_owned(), manifest_dir.join("src/lib.rs")));
+
+    // Picks up added modules, in addition to changed ones.
+    println!("cargo:rerun-if-changed={}", protocol.display());
+
+    let mut types = Vec::new();
+    for (module, path) in &sources {
+        println!("cargo:rerun-if-changed={}", path.display());
+        types.extend(parse_types(module, path));
+    }
+
+    let mut schema = String::new();
+    writeln!(schema, "{{").unwrap();
+    writeln!(schema, "  \"crate\": {},", json_str(env!("CARGO_PKG_NAME")))
+        .unwrap();
+    writeln!(schema, "  \"version\": {},",
+        json_str(&env::var("CARGO_PKG_VERSION").unwrap()))
+        .unwrap();
+    writeln!(schema, "  \"types\": [").unwrap();
+    for (i, ty) in types.iter().enumerate() {
+        let separator = if i + 1 < types.len() { "," } else { "" };
+        writeln!(schema, "    {}{}", ty, separator).unwrap();
+    }
+    writeln!(schema, "  ]").unwrap();
+    writeln!(schema, "}}").unwrap();
+
+    // The protocol version only depends on the types, not on the version of
+    // this crate, which isn't necessarily bumped when the messages change.
+    let protocol_version = fnv1a(types.concat().as_bytes());
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("schema.json"), schema).unwrap();
+    fs::write(
+        out_dir.join("protocol_version.rs"),
+        format!("{:#010x}", protocol_version),
+    )
+        .unwrap();
+}
+
+
+/// Returns the name and path of each module of the `protocol` crate
+///
+/// Sorted by name, so the protocol version doesn't depend on the order in
+/// which the file system lists them.
+fn protocol_modules(protocol: &Path) -> Vec<(String, PathBuf)> {
+    let entries = fs::read_dir(protocol)
+        .unwrap_or_else(|err| panic!("Error reading {:?}: {}", protocol, err));
+
+    let mut modules = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "rs"))
+        .filter_map(|path| {
+            let module = path.file_stem()?.to_str()?.to_owned();
+            if module == "lib" {
+                return None;
+            }
+            Some((module, path))
+        })
+        .collect::<Vec<_>>();
+    modules.sort();
+
+    modules
+}
+
+/// Computes the 32-bit FNV-1a hash of `data`
+fn fnv1a(data: &[u8]) -> u32 {
+    let mut hash = 0x811c9dc5_u32;
+    for &b in data {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+
+/// Returns the JSON descriptions of all serializable types in a source file
+fn parse_types(module: &str, path: &Path) -> Vec<String> {
+    let source = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Error reading {:?}: {}", path, err));
+    let file = syn::parse_file(&source)
+        .unwrap_or_else(|err| panic!("Error parsing {:?}: {}", path, err));
+
+    let mut types = Vec::new();
+
+    for item in file.items {
+        match item {
+            Item::Enum(item) if is_serializable(&item.attrs) => {
+                let variants = item.variants.iter()
+                    .enumerate()
+                    .map(|(index, variant)| {
+                        format!(
+                            "{{\"name\": {}, \"index\": {}, \"fields\": {}}}",
+                            json_str(&variant.ident.to_string()),
+                            index,
+                            fields(&variant.fields),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                types.push(format!(
+                    "{{\"name\": {}, \"module\": {}, \"kind\": \"enum\", \
+                    \"generics\": {}, \"variants\": [{}]}}",
+                    json_str(&item.ident.to_string()),
+                    json_str(module),
+                    generics(&item.generics),
+                    variants.join(", "),
+                ));
+            }
+            Item::Struct(item) if is_serializable(&item.attrs) => {
+                types.push(format!(
+                    "{{\"name\": {}, \"module\": {}, \"kind\": \"struct\", \
+                    \"generics\": {}, \"fields\": {}}}",
+                    json_str(&item.ident.to_string()),
+                    json_str(module),
+                    generics(&item.generics),
+                    fields(&item.fields),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    types
+}
+
+/// Indicates whether a type derives `Serialize` or `Deserialize`
+fn is_serializable(attrs: &[Attribute]) -> bool {
+    attrs.iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .any(|attr| {
+            let derives = attr.meta.to_token_stream().to_string();
+            derives.contains("Serialize") || derives.contains("Deserialize")
+        })
+}
+
+fn generics(generics: &Generics) -> String {
+    let params = generics.params.iter()
+        .map(|param| json_str(&tokens(param)))
+        .collect::<Vec<_>>();
+    format!("[{}]", params.join(", "))
+}
+
+fn fields(fields: &Fields) -> String {
+    let fields = fields.iter()
+        .map(|field| {
+            let name = match &field.ident {
+                Some(ident) => json_str(&ident.to_string()),
+                None        => String::from("null"),
+            };
+            format!(
+                "{{\"name\": {}, \"type\": {}}}",
+                name,
+                json_str(&tokens(&field.ty)),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", fields.join(", "))
+}
+
+/// Converts a syntax element to a compact string, like `pin::SetLevel<()>`
+fn tokens(tokens: &impl quote::ToTokens) -> String {
+    tokens.to_token_stream().to_string()
+        .replace(' ', "")
+}
+
+fn json_str(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c    => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
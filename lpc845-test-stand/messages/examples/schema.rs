@@ -0,0 +1,9 @@
+//! Prints the message schema
+//!
+//! Run with `cargo run --example schema > schema.json` to export the schema
+//! for use in external tools.
+
+
+fn main() {
+    print!("{}", lpc845_messages::SCHEMA);
+}
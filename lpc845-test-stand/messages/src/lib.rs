@@ -1,13 +1,38 @@
 #![no_std]
 
 
+#[cfg(feature = "host")]
+extern crate std;
+
+
 pub use protocol::{
     AssistantToHost,
     HostToAssistant,
     InputPin,
+    LINK_SYNC,
+    MonitorPin,
     OutputPin,
+    SYNC_PREAMBLE,
     UsartMode,
+    checksum,
+    chunk,
+    compress,
+    event,
+    flow,
+    i2c,
+    inject,
+    is_link_sync,
+    log,
+    monitor,
+    panic,
     pin,
+    reference,
+    reset,
+    spi,
+    trigger,
+    units,
+    usart,
+    waveform,
 };
 
 
@@ -16,7 +41,96 @@ use core::convert::TryFrom;
 use serde::{
     Deserialize,
     Serialize,
+    Serializer,
 };
+use test_stand_derive::Requests;
+
+
+/// JSON description of all messages and the types they're made of
+///
+/// Generated from the source code at build time. Lists the name, variant
+/// indexes, and field types of every serializable type, so tools that don't
+/// link this crate can decode the messages. Type names are relative to the
+/// module listed for each type.
+///
+/// Use `host_lib::schema::Schema` to load it.
+pub const SCHEMA: &str = include_str!(concat!(env!("OUT_DIR"), "/schema.json"));
+
+/// Version of the protocol defined by the messages in this crate
+///
+/// Derived from `SCHEMA` at build time, so it changes whenever any of the
+/// messages change. The host compares it to the version reported by the target
+/// firmware, to detect firmware that was built from a different revision.
+pub const PROTOCOL_VERSION: u32 =
+    include!(concat!(env!("OUT_DIR"), "/protocol_version.rs"));
+
+/// The maximum number of bytes an I2C transaction can write or read
+///
+/// Applies to both directions separately. The target firmware needs buffers of
+/// this size, for transactions that use DMA.
+pub const I2C_BUF_CAP: usize = 32;
+
+/// The maximum number of bytes an SPI transaction can write or read
+///
+/// Unlike with I2C, this applies to both directions combined, as writing and
+/// reading share a single buffer, for transactions that use DMA.
+pub const SPI_BUF_CAP: usize = 32;
+
+/// The largest chunk the target passes to its USART driver at once
+///
+/// Applies to `HostToTarget::StartUsartThroughputTest`. The target firmware
+/// needs a buffer of this size.
+pub const USART_CHUNK_CAP: usize = 64;
+
+/// The maximum number of bytes that can be written to or read from flash
+///
+/// Applies to `HostToTarget::WriteFlash` and `HostToTarget::ReadFlash`, so
+/// both fit into a single message.
+pub const FLASH_BUF_CAP: usize = 128;
+
+/// The maximum number of entries in the target's key-value store
+///
+/// See `HostToTarget::SetStoreEntry`.
+pub const STORE_CAP: usize = 32;
+
+/// The count of a capacitive touch measurement that has timed out
+///
+/// This is also the largest threshold that `HostToTarget::StartCapt` accepts.
+pub const CAPT_MAX_COUNT: u16 = 4095;
+
+/// The maximum number of samples in a DAC waveform
+///
+/// See `HostToTarget::StartDacWaveform`. The target firmware needs a buffer of
+/// this size.
+pub const DAC_WAVEFORM_CAP: usize = 32;
+
+/// The highest rate of a DAC waveform, in samples per second
+///
+/// See `HostToTarget::StartDacWaveform`. The target writes each sample from an
+/// interrupt handler, which limits how fast it can go.
+pub const DAC_WAVEFORM_MAX_RATE: u32 = 10_000;
+
+/// The maximum number of ADC samples the target takes at once
+///
+/// See `HostToTarget::SampleAdc`. All of them are sent in a single message.
+pub const ADC_SAMPLES_CAP: usize = 64;
+
+/// The USB vendor ID of targets that talk to the host over USB
+///
+/// This is the shared ID of pid.codes, for use in open source projects.
+pub const USB_VID: u16 = 0x1209;
+
+/// The USB product ID of targets that talk to the host over USB
+///
+/// Reserved by pid.codes for testing. Targets are told apart by the serial
+/// number of the device, which is derived from the unique ID of the chip.
+pub const USB_PID: u16 = 0x0001;
+
+/// How often the target sends `TargetToHost::Heartbeat`, in milliseconds
+///
+/// The host considers the target unresponsive, if it hasn't sent anything for
+/// a few of these intervals.
+pub const HEARTBEAT_INTERVAL_MS: u32 = 250;
 
 
 /// A message from the test suite on the host to the target
@@ -25,7 +139,11 @@ use serde::{
 /// since the assistant is still shared by these test suites, it still makes
 /// sense to have them here. Going forward, this might become more general, as
 /// the assistant itself becomes more general.
-#[derive(Debug, Deserialize, Serialize)]
+///
+/// Requests with a `#[request(...)]` attribute can be sent using the methods of
+/// `TargetRequests`, with the `host` feature enabled.
+#[derive(Debug, Deserialize, Requests, Serialize)]
+#[requests(name = TargetRequests, reply = TargetToHost)]
 pub enum HostToTarget<'r> {
     /// Instruct the target to send a message via USART
     SendUsart {
@@ -34,22 +152,34 @@ pub enum HostToTarget<'r> {
     },
 
     /// Instruct the target to ignore USART data until address is matched
+    ///
+    /// The target replies with `AddressMatched`, once it has received the
+    /// address. It doesn't process any other requests in the meantime.
     WaitForAddress(u8),
 
-    /// Instruct the device to change the electrical level of the pin
-    SetPin(pin::SetLevel<()>),
+    /// Instruct the target to change the electrical level of a pin
+    SetPin(pin::SetLevel<pin::PortPin>),
 
-    /// Ask the target for the current level of the input pin
-    ReadPin(pin::ReadLevel<()>),
+    /// Ask the target for the current level of a pin
+    ReadPin(pin::ReadLevel<pin::PortPin>),
 
     /// Instruct the target to start the timer interrupt
-    StartTimerInterrupt { period_ms: u32 },
+    StartTimerInterrupt { period: units::Microseconds },
 
     /// Instruct the target to stop the timer interrupt
     StopTimerInterrupt,
 
     /// Instruct the target to start the PWM signal
-    StartPwmSignal,
+    ///
+    /// `duty_permille` is the share of each period that the signal is high, in
+    /// thousandths, and must not be larger than 1000. Not every target can
+    /// change the period, and the signal can't stay high or low for too short
+    /// a time. If the target can't generate the signal, it replies with
+    /// `InvalidRequest`. Otherwise, there is no reply.
+    StartPwmSignal {
+        period:        units::Microseconds,
+        duty_permille: u16,
+    },
 
     /// Instruct the target to stop the PWM signal
     StopPwmSignal,
@@ -59,11 +189,23 @@ pub enum HostToTarget<'r> {
         /// Which mode to use for the transaction
         mode: DmaMode,
 
-        /// The address of the slave
+        /// The 7-bit address of the slave
+        ///
+        /// If no slave acknowledges this address, the target replies with
+        /// `I2cNack`, instead of `I2cReply`.
         address: u8,
 
-        /// The data to send to the slave
-        data: u8,
+        /// The data to write to the slave
+        ///
+        /// Must not be longer than `I2C_BUF_CAP`, or the target replies with
+        /// `InvalidRequest`. If this is empty, the transaction only reads.
+        write: &'r [u8],
+
+        /// The number of bytes to read from the slave, after writing
+        ///
+        /// Must not be larger than `I2C_BUF_CAP`, or the target replies with
+        /// `InvalidRequest`. If this is zero, the transaction only writes.
+        read_len: u16,
     },
 
     /// Instruct the target to start an SPI transaction
@@ -71,22 +213,476 @@ pub enum HostToTarget<'r> {
         /// Which mode to use for the transaction
         mode: DmaMode,
 
-        /// The data to send to the slave
-        data: u8,
+        /// The data to write to the slave
+        ///
+        /// The target discards whatever it receives while writing this. If
+        /// this is empty, the transaction only reads.
+        write: &'r [u8],
+
+        /// The number of bytes to read from the slave, after writing
+        ///
+        /// The target sends `0xff` while reading. `write` and `read_len`
+        /// combined must not be larger than `SPI_BUF_CAP`, or the target
+        /// replies with `InvalidRequest`.
+        read_len: u16,
     },
 
     /// Instruct the target to read from the ADC
-    ReadAdc,
+    ///
+    /// `channel` is the target's ADC channel that is to be read. The target
+    /// only supports the channel that is wired to the assistant, and replies
+    /// with `InvalidRequest` for any other.
+    #[request(name = read_adc_value, reply = AdcValue(u16))]
+    ReadAdc { channel: u8 },
+
+    /// Instruct the target to set the output of a DAC channel
+    ///
+    /// `value` is the raw 12-bit value that is written to the DAC. Targets
+    /// whose DAC has a lower resolution ignore the lowest bits. The target
+    /// replies with `InvalidRequest` for channels it doesn't support.
+    /// Otherwise, there is no reply.
+    SetDacValue {
+        channel: u8,
+        value:   u16,
+    },
+
+    /// Ask the target to compress the frames it sends to the host
+    ///
+    /// The target replies with `CompressionNegotiated`, before it starts using
+    /// the compression scheme.
+    NegotiateCompression(compress::Compression),
+
+    /// Ask the target to add a checksum to frames in both directions
+    ///
+    /// The target replies with `ChecksumNegotiated`, before it starts using the
+    /// checksum. This request itself must not carry the new checksum yet.
+    NegotiateChecksum(checksum::Checksum),
+
+    /// Ask the target which functionality it supports
+    ///
+    /// The target replies with `Capabilities`.
+    GetCapabilities,
+
+    /// Ask the target which version of the firmware it runs
+    ///
+    /// The target replies with `Version`.
+    GetVersion,
+
+    /// Check that the target is still responsive
+    ///
+    /// The target replies with `Pong`.
+    Ping,
+
+    /// Instruct the target to reset itself
+    ///
+    /// The target doesn't reply. Once it has restarted, it sends the sync
+    /// preamble, like after any other reset.
+    Reset,
+
+    /// Ask the target about its flash memory
+    ///
+    /// The target replies with `FlashInfo`.
+    #[request(name = flash_info, reply = FlashInfo(FlashInfo))]
+    GetFlashInfo,
+
+    /// Ask the target which interrupts are enabled and pending
+    ///
+    /// The target replies with `IrqState`.
+    GetIrqState,
+
+    /// Instruct the target to perform an action on the trigger's rising edge
+    ///
+    /// The target replies with `TriggerArmed`, once it is ready for the
+    /// trigger.
+    #[request(args(action), reply = TriggerArmed)]
+    ArmOnTrigger(trigger::Action<pin::PortPin>),
+
+    /// Instruct the target to sleep, until it receives data via USART
+    ///
+    /// The target replies with `Sleeping`, right before it enters sleep mode.
+    /// It wakes up on the start bit of the next byte its regular USART
+    /// receives, then sends `UsartWakeup`. The received data is reported via
+    /// `UsartReceive`, as usual.
+    SleepUntilUsartRx,
+
+    /// Instruct the target to reconfigure its USART to the assistant
+    ///
+    /// The target replies with `UsartConfigured`, once the new configuration
+    /// is in effect. The assistant needs to be reconfigured the same way.
+    ConfigureUsart {
+        mode:   UsartMode,
+        config: usart::Config,
+    },
+
+    /// Instruct the target to change the priority of an interrupt
+    ///
+    /// As in the NVIC, `0` is the highest priority. The LPC845 supports the
+    /// priorities `0` to `3`, and the firmware starts with all of its
+    /// interrupts at `3`. The target replies with `IrqPrioritySet`, or with
+    /// `InvalidRequest`, if it doesn't support `priority`.
+    SetIrqPriority {
+        irq:      u8,
+        priority: u8,
+    },
+
+    /// Instruct the target to run a workload, measuring interrupt latency
+    ///
+    /// The target pends all interrupts in `irqs` at the same time, and waits
+    /// for their handlers to run. It does this `rounds` times, then replies
+    /// with `IrqLatencies`. Bit `n` of `irqs` represents interrupt `n`.
+    ///
+    /// Only the interrupts of the USARTs and the pin interrupts 0 and 1 are
+    /// supported, as their handlers can run without a cause. If `irqs`
+    /// includes any other, the target replies with `InvalidRequest` instead.
+    RunIrqWorkload {
+        irqs:   u32,
+        rounds: u16,
+    },
+
+    /// Instruct the target to report the interrupts of its input pin
+    ///
+    /// While enabled, the target sends `PinInterrupt` whenever the level of
+    /// the pin changes. This is disabled by default, so tests that don't care
+    /// about interrupts don't receive them. The target replies with
+    /// `PinInterruptsReported`.
+    #[request(args(enable), reply = PinInterruptsReported)]
+    ReportPinInterrupts(bool),
+
+    /// Instruct the target to return to a known idle state
+    ///
+    /// Stops the timer interrupt, the PWM signal, and the echoing of USART
+    /// data, disables the reporting of pin interrupts, disarms the trigger, and
+    /// restores the configuration of pins changed by `ConfigurePin` or
+    /// `SetPinFilter`. DMA transfers started by the host complete before the
+    /// target replies to them, so none can be pending. The target replies with
+    /// `Quiesced`, after which it won't send any messages on its own.
+    Quiesce,
+
+    /// Instruct the target to act as an SPI slave for one transaction
+    ///
+    /// The target switches its SPI peripheral to slave mode and replies with
+    /// `SpiSlaveReady`. It sends `response` in the following transaction, then
+    /// `0xff`, once the response is exhausted. When the master deasserts the
+    /// slave select signal, the target sends `SpiSlaveReceived` and returns to
+    /// master mode. It doesn't process any other requests in the meantime.
+    ///
+    /// The response must not be longer than `SPI_BUF_CAP`.
+    ExpectSpiSlaveTransfer {
+        response: &'r [u8],
+    },
+
+    /// A part of a request that is too large for a single frame
+    ///
+    /// The target collects the chunks, then processes the reassembled request.
+    /// See the `chunk` module.
+    Chunk {
+        id:    u8,
+        index: u16,
+        total: u16,
+        data:  &'r [u8],
+    },
+
+    /// Ask the target for the events it has recorded recently
+    ///
+    /// The target replies with `Events`. See the `event` module.
+    DumpEvents,
+
+    /// Instruct the target to move the TX function of its USART to a pin
+    ///
+    /// The target reassigns the function in its switch matrix, so the USART's
+    /// output appears on `pin` from then on, and no longer on the pin it was on
+    /// before. Supported are the regular TX pin (PIO0_27), and the pin of the
+    /// green LED (PIO1_0), which is wired to an input of the assistant. The
+    /// target replies with `UsartTxMoved`.
+    #[request(args(pin), reply = UsartTxMoved)]
+    MoveUsartTx(pin::PortPin),
+
+    /// Instruct the target to drive the trigger line like an open-drain pin
+    ///
+    /// `Level::Low` pulls the line low. `Level::High` releases it, leaving it
+    /// to the pull-ups, so it only goes high, if no other test node pulls it
+    /// low. The target then waits a bit for the line to reach the driven level,
+    /// and replies with `SharedLine`.
+    DriveSharedLine(pin::Level),
+
+    /// Ask the target for the current value of its free-running timer
+    ///
+    /// The timer is the one that timestamps events, so the host can use the
+    /// reply to relate those timestamps to its own clock. The target replies
+    /// with `Time`.
+    #[request(name = time, reply = Time(u32))]
+    GetTime,
+
+    /// Instruct the target to send data via USART, as fast as it can
+    ///
+    /// The target sends `bytes` bytes in regular mode, passing `chunk_size`
+    /// bytes at a time to its USART driver, to measure the driver's
+    /// throughput. `chunk_size` is capped at `USART_CHUNK_CAP`. The data itself
+    /// is meaningless. The assistant counts it (see
+    /// `HostToAssistant::StartUsartCount`). The target replies with
+    /// `UsartThroughputSent`, once everything has been sent.
+    StartUsartThroughputTest {
+        bytes:      u32,
+        chunk_size: u16,
+    },
+
+    /// Instruct the target to send a break to the assistant via USART
+    ///
+    /// The target holds its regular TX line low for 2 ms, which is longer
+    /// than a whole frame at 9600 baud or faster. The target doesn't reply.
+    /// The assistant reports the break (see
+    /// `AssistantToHost::UsartBreakDetected`).
+    SendUsartBreak,
+
+    /// Instruct the target to enter a low-power mode, until it is woken up
+    ///
+    /// The target replies with `Sleeping`, right before it enters the mode. It
+    /// wakes up on `wakeup_source`, or after `timeout_ms`, whichever comes
+    /// first, then sends `WokeUp`. Data that woke it up is processed as usual.
+    /// Debugging is not supported in these modes, so RTT output might get lost
+    /// while the target sleeps.
+    EnterSleep {
+        mode:          SleepMode,
+        wakeup_source: WakeupSource,
+        timeout_ms:    u32,
+    },
+
+    /// Ask the target why it was last reset
+    ///
+    /// The target reads the reset status register once at startup, so the
+    /// reply stays the same until the next reset. The target replies with
+    /// `ResetCause`, also in safe mode.
+    #[request(name = reset_cause, reply = ResetCause(reset::Cause))]
+    GetResetCause,
+
+    /// Instruct the target to program data into its flash memory
+    ///
+    /// The target erases the pages starting at `address`, then programs `data`
+    /// into them, using the IAP commands in its ROM. Only the flash memory
+    /// between the firmware image and the reserved area at the end can be
+    /// written (see `FlashInfo`). `address` and the length of `data` must be
+    /// multiples of the page size, and `data` must not be longer than
+    /// `FLASH_BUF_CAP`.
+    ///
+    /// The target replies with `FlashWritten`, or `FlashError`.
+    WriteFlash {
+        address: u32,
+        data:    &'r [u8],
+    },
+
+    /// Ask the target for the contents of its flash memory
+    ///
+    /// Like `WriteFlash`, this is limited to the flash memory between the
+    /// firmware image and the reserved area. `len` must not be larger than
+    /// `FLASH_BUF_CAP`.
+    ///
+    /// The target replies with `FlashRead`, or `FlashError`.
+    ReadFlash {
+        address: u32,
+        len:     u32,
+    },
+
+    /// Ask the target for an entry of its key-value store
+    ///
+    /// The store is kept in the reserved area of the flash memory, so its
+    /// entries survive a reset. Test cases can use it to carry state from
+    /// before a reset to after it. The target replies with `StoreEntry`, which
+    /// is `None`, if the store has no entry for the key.
+    #[request(name = store_entry, args(key), reply = StoreEntry(Option<u32>))]
+    GetStoreEntry(u32),
+
+    /// Instruct the target to add or replace an entry of its key-value store
+    ///
+    /// `key` can be anything but `u32::MAX`. The store has room for
+    /// `STORE_CAP` entries. The target replies with `StoreUpdated`.
+    #[request(reply = StoreUpdated(Result<(), StoreError>))]
+    SetStoreEntry {
+        key:   u32,
+        value: u32,
+    },
+
+    /// Instruct the target to remove all entries from its key-value store
+    ///
+    /// The target replies with `StoreUpdated`.
+    #[request(reply = StoreUpdated(Result<(), StoreError>))]
+    ClearStore,
+
+    /// Instruct the target to start its multi-rate timer (MRT)
+    ///
+    /// The timer expires after `interval`, once or repeatedly, depending on
+    /// `mode`. Every time it does, the target toggles the blue LED, like the
+    /// timer interrupt does, and sends `MrtExpired`. `interval` must be shorter
+    /// than 2^31 cycles of the 12 MHz system clock, about 178 seconds.
+    /// Otherwise, the target replies with `InvalidRequest`.
+    ///
+    /// Restarts the timer, if it is already running.
+    StartMrt {
+        mode:     TimerMode,
+        interval: units::Microseconds,
+    },
+
+    /// Instruct the target to stop its multi-rate timer
+    StopMrt,
+
+    /// Instruct the target to start scanning its capacitive touch sensors
+    ///
+    /// Bit `n` of `pins` selects X pin `n` of the CAPT peripheral. Only the X
+    /// pins that the target's board connects to a sensor can be selected. A
+    /// measurement that counts more than `threshold` cycles of the CAPT clock
+    /// is a touch. At least one pin must be selected, and `threshold` must not
+    /// be larger than `CAPT_MAX_COUNT`. Otherwise, the target replies with
+    /// `InvalidRequest`.
+    ///
+    /// The target sends `CaptEvent` after the first measurement of each pin,
+    /// and whenever a pin crosses the threshold afterwards. Restarts scanning,
+    /// if it is already in progress.
+    StartCapt {
+        pins:      u16,
+        threshold: u16,
+    },
+
+    /// Instruct the target to stop scanning its capacitive touch sensors
+    StopCapt,
+
+    /// Instruct the target to output a waveform on a DAC channel
+    ///
+    /// The target writes `samples` to the DAC one after the other, `rate` times
+    /// per second, and starts over after the last one, until it receives
+    /// `StopDacWaveform`. The samples are raw 12-bit values, like the one in
+    /// `SetDacValue`, each encoded as two bytes, little-endian (see
+    /// `encode_samples`). There must be at least one, and no more than
+    /// `DAC_WAVEFORM_CAP`. `rate` must not be zero or larger than
+    /// `DAC_WAVEFORM_MAX_RATE`.
+    ///
+    /// Restarts the waveform, if one is already being output. The target
+    /// replies with `InvalidRequest`, if it doesn't support the channel, or if
+    /// `samples` or `rate` are out of range.
+    StartDacWaveform {
+        channel: u8,
+        samples: &'r [u8],
+        rate:    u32,
+    },
+
+    /// Instruct the target to stop outputting a DAC waveform
+    ///
+    /// The DAC keeps the level of the last sample it has output.
+    StopDacWaveform,
+
+    /// Instruct the target to sample an ADC channel repeatedly
+    ///
+    /// The target reads the channel `len` times, with `interval` between the
+    /// start of two readings, and replies with `AdcSamples`. `len` must not be
+    /// larger than `ADC_SAMPLES_CAP`. Otherwise, or for channels it doesn't
+    /// support, the target replies with `InvalidRequest`.
+    SampleAdc {
+        channel:  u8,
+        len:      u16,
+        interval: units::Microseconds,
+    },
+
+    /// Instruct the target to set the levels of a group of GPIO pins at once
+    ///
+    /// The group is one GPIO port of the target, which is chosen by the board.
+    /// Bit `n` of `mask` and `value` stands for pin `n` of that port. The pins
+    /// in `mask` are set high, if their bit in `value` is set, low otherwise,
+    /// all at the same time. The other pins are not changed.
+    ///
+    /// The target replies with `PinGroupSet`, or with `InvalidRequest`, if
+    /// `mask` includes pins it doesn't use as outputs.
+    #[request(reply = PinGroupSet)]
+    SetPinGroup {
+        mask:  u32,
+        value: u32,
+    },
+
+    /// Ask the target for the levels of a group of GPIO pins
+    ///
+    /// Uses the same group as `SetPinGroup`. All pins are read at the same
+    /// time. The target replies with `PinGroup`.
+    #[request(reply = PinGroup(u32))]
+    ReadPinGroup {
+        mask: u32,
+    },
+
+    /// Instruct the target to reconfigure a GPIO pin
+    ///
+    /// Changes the direction of the pin, the internal resistor it is connected
+    /// to, and whether it is driven as an open-drain output. An open-drain
+    /// output only pulls the pin low, and releases it when set high. The
+    /// target only supports the pins that its board lists as configurable.
+    /// `Quiesce` restores their initial configuration.
+    ///
+    /// The target replies with `PinConfigured`, or with `UnsupportedPin` for
+    /// any other pin.
+    #[request(reply = PinConfigured)]
+    ConfigurePin {
+        pin:        pin::PortPin,
+        direction:  pin::Direction,
+        pull:       pin::Pull,
+        open_drain: bool,
+    },
+
+    /// Instruct the target to enable or disable the input filter of a pin
+    ///
+    /// The filter samples the pin with the system clock, divided by
+    /// `clock_divider`, and rejects pulses that are shorter than `samples` of
+    /// those clock periods. `samples` can be `1` to `3`, or `0`, which disables
+    /// the filter. `clock_divider` must not be `0`, if the filter is enabled.
+    /// All filters share the same divider. The target only supports the pins
+    /// that its board lists as having a filter. `Quiesce` disables the filters.
+    ///
+    /// The target replies with `PinFilterSet`. It replies with `UnsupportedPin`
+    /// for any other pin, and with `InvalidRequest`, if `samples` or
+    /// `clock_divider` are out of range.
+    #[request(reply = PinFilterSet)]
+    SetPinFilter {
+        pin:           pin::PortPin,
+        samples:       u8,
+        clock_divider: u8,
+    },
+
+    /// Instruct the target to echo the data it receives via USART
+    ///
+    /// For `duration`, the target sends every byte it receives in regular mode
+    /// right back, instead of forwarding it to the host. While the assistant
+    /// keeps sending, both directions of the USART are busy at the same time.
+    /// Receive errors are counted, instead of being reported. Afterwards, the
+    /// target sends `UsartEchoed`. It keeps processing other requests in the
+    /// meantime.
+    StartUsartEcho {
+        duration: units::Microseconds,
+    },
 }
 
-impl From<pin::SetLevel<()>> for HostToTarget<'_> {
-    fn from(set_level: pin::SetLevel<()>) -> Self {
+impl chunk::Chunked for HostToTarget<'_> {
+    fn serialize_chunk<S>(chunk: chunk::Chunk<'_>, serializer: S)
+        -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let chunk::Chunk { id, index, total, data } = chunk;
+        HostToTarget::Chunk { id, index, total, data }.serialize(serializer)
+    }
+
+    fn chunk(&self) -> Option<chunk::Chunk<'_>> {
+        match *self {
+            Self::Chunk { id, index, total, data } => {
+                Some(chunk::Chunk { id, index, total, data })
+            }
+            _ => {
+                None
+            }
+        }
+    }
+}
+
+impl From<pin::SetLevel<pin::PortPin>> for HostToTarget<'_> {
+    fn from(set_level: pin::SetLevel<pin::PortPin>) -> Self {
         Self::SetPin(set_level)
     }
 }
 
-impl From<pin::ReadLevel<()>> for HostToTarget<'_> {
-    fn from(read_level: pin::ReadLevel<()>) -> Self {
+impl From<pin::ReadLevel<pin::PortPin>> for HostToTarget<'_> {
+    fn from(read_level: pin::ReadLevel<pin::PortPin>) -> Self {
         Self::ReadPin(read_level)
     }
 }
@@ -104,22 +700,332 @@ pub enum TargetToHost<'r> {
     UsartReceive {
         mode: UsartMode,
         data: &'r [u8],
+
+        /// When the last byte of `data` was received, in microseconds
+        ///
+        /// Taken from the target's free-running timer, so only meaningful
+        /// relative to other timestamps from the same target. `None`, if the
+        /// target doesn't timestamp received data.
+        timestamp: Option<u32>,
+    },
+
+    /// Notify the host that an error occurred, while receiving via USART
+    ///
+    /// The target keeps receiving after an error. Data received after the
+    /// error is reported via `UsartReceive`, as usual.
+    UsartError {
+        mode:  UsartMode,
+        error: UsartError,
     },
 
     /// Reply to a `ReadPin` request
-    ReadPinResult(Option<pin::ReadLevelResult<()>>),
+    ReadPinResult(Option<pin::ReadLevelResult<pin::PortPin>>),
+
+    /// Reply to a `SetPin` or `ReadPin` request for a pin that the target
+    /// doesn't let the host access
+    ///
+    /// Also the reply to `ConfigurePin` or `SetPinFilter` for a pin that the
+    /// target doesn't support.
+    UnsupportedPin(pin::PortPin),
+
+    /// Reply to a request that the target can't carry out as requested
+    ///
+    /// Sent instead of the usual reply, if any, when a field of the request is
+    /// out of range, or when the target doesn't support the request at all.
+    /// The target ignores the request otherwise.
+    InvalidRequest,
 
     /// Notify the host that the I2C transaction completed
-    I2cReply(u8),
+    ///
+    /// Carries the data read from the slave.
+    I2cReply(&'r [u8]),
+
+    /// Notify the host that the I2C slave did not acknowledge
+    ///
+    /// This happens, if no slave has the address of the transaction, or if the
+    /// slave refused the data written to it.
+    I2cNack,
+
+    /// Notify the host that the I2C transaction failed for another reason
+    ///
+    /// This happens, for example, if the target lost arbitration, or detected
+    /// a misplaced start or stop condition on the bus.
+    I2cError,
 
     /// Notify the host that the SPI transaction completed
-    SpiReply(u8),
+    ///
+    /// Carries the data read from the slave.
+    SpiReplyBuffer(&'r [u8]),
 
     /// Reply to `ReadAdc` request
+    ///
+    /// Carries the raw 12-bit result of the conversion.
     AdcValue(u16),
+
+    /// Reply to `NegotiateCompression`
+    ///
+    /// Carries the compression scheme the target is going to use from now on.
+    /// This reply itself is still sent using the previous scheme.
+    CompressionNegotiated(compress::Compression),
+
+    /// Reply to `NegotiateChecksum`
+    ///
+    /// Carries the checksum the target is going to use from now on. This reply
+    /// itself is still sent using the previous checksum.
+    ChecksumNegotiated(checksum::Checksum),
+
+    /// Reply to `GetCapabilities`
+    Capabilities(Capabilities),
+
+    /// Reply to `GetVersion`
+    Version {
+        /// The `PROTOCOL_VERSION` the firmware was built with
+        protocol: u32,
+
+        /// Identifies the revision of the firmware source code
+        firmware_hash: &'r str,
+
+        /// The version of the HAL that the firmware was built with
+        hal_version: &'r str,
+
+        /// The Cargo features that the firmware was built with
+        ///
+        /// Separated by commas.
+        features: &'r str,
+    },
+
+    /// Notify the host that the target has been reset by a fault
+    ///
+    /// Sent right after the sync preamble, when the target starts up again.
+    Fault(FaultKind),
+
+    /// Reply to `Ping`
+    Pong,
+
+    /// Reply to `GetFlashInfo`
+    FlashInfo(FlashInfo),
+
+    /// Reply to `GetIrqState`
+    IrqState(IrqState),
+
+    /// Reply to `ArmOnTrigger`
+    TriggerArmed,
+
+    /// Reply to `ConfigureUsart`
+    UsartConfigured,
+
+    /// Reply to `SleepUntilUsartRx` and `EnterSleep`, sent right before the
+    /// target sleeps
+    Sleeping,
+
+    /// Notify the host that the target has woken up from USART activity
+    UsartWakeup(UsartWakeup),
+
+    /// Reply to `SetIrqPriority`
+    IrqPrioritySet,
+
+    /// Reply to `RunIrqWorkload`
+    IrqLatencies(IrqLatencies),
+
+    /// Reply to `ReportPinInterrupts`
+    PinInterruptsReported,
+
+    /// Notify the host that an interrupt of an input pin has fired
+    PinInterrupt {
+        /// The pin whose level has changed
+        pin: pin::PortPin,
+
+        /// The change that triggered the interrupt
+        edge: pin::Edge,
+
+        /// When the interrupt was handled, in microseconds
+        ///
+        /// Taken from the target's free-running timer, so only meaningful
+        /// relative to other timestamps from the same target.
+        timestamp: u32,
+
+        /// When the edge reached the pin, in microseconds
+        ///
+        /// Captured in hardware by the same timer as `timestamp`, so the
+        /// difference between both is the latency of the interrupt handler. If
+        /// the level changed again before the interrupt was handled, this is
+        /// the time of the later change.
+        captured: u32,
+    },
+
+    /// Reply to `Quiesce`
+    Quiesced,
+
+    /// Reply to `ExpectSpiSlaveTransfer`, sent once the target is ready
+    SpiSlaveReady,
+
+    /// Notify the host that the target's SPI slave transaction has ended
+    ///
+    /// Carries the data received from the master, up to `SPI_BUF_CAP` bytes.
+    SpiSlaveReceived(&'r [u8]),
+
+    /// A log record
+    ///
+    /// Can be sent at any time, in between any other messages. The host takes
+    /// log records out of the stream of messages, before processing the rest.
+    Log {
+        level:   log::Level,
+        message: &'r str,
+    },
+
+    /// Reply to `DumpEvents`
+    Events(event::Events),
+
+    /// Notify the host that received data has been dropped
+    ///
+    /// An interrupt handler couldn't pass on received data (USART bytes or pin
+    /// interrupt events), because the queue to the idle loop was full. Sent
+    /// once the idle loop has drained the queues. `dropped` counts the items
+    /// dropped since the last notification.
+    BufferOverrun {
+        dropped: u32,
+    },
+
+    /// Reply to `MoveUsartTx`
+    UsartTxMoved,
+
+    /// Reply to `DriveSharedLine`
+    SharedLine {
+        /// The level of the line, after waiting for it to settle
+        level: pin::Level,
+
+        /// How long the line took to reach the driven level, in microseconds
+        ///
+        /// `None`, if it didn't reach it within 1 ms, which happens when
+        /// releasing the line, while another test node pulls it low.
+        settle_us: Option<u32>,
+    },
+
+    /// Let the host know that the target is still running
+    ///
+    /// Sent from the idle loop every `HEARTBEAT_INTERVAL_MS`, in between any
+    /// other messages. The host takes heartbeats out of the stream of
+    /// messages, before processing the rest.
+    Heartbeat,
+
+    /// Reply to `GetTime`
+    ///
+    /// Carries the value of the target's free-running timer, in microseconds.
+    Time(u32),
+
+    /// Notify the host that the target has panicked
+    ///
+    /// Sent from the panic handler, on a best-effort basis, right before the
+    /// target resets itself. Once it has started up again, the target reports
+    /// the panic a second time, as `Fault`.
+    Panic(panic::Report<'r>),
+
+    /// Encoded defmt frames
+    ///
+    /// Sent by firmware built with the `defmt` feature, from the idle loop, in
+    /// between any other messages. Frames can be split across messages. The
+    /// host decodes them using the firmware's ELF file, and takes them out of
+    /// the stream of messages, before processing the rest.
+    Defmt(&'r [u8]),
+
+    /// Reply to `StartUsartThroughputTest`
+    UsartThroughputSent,
+
+    /// Notify the host that the target has detected a break via USART
+    ///
+    /// Sent once the RX line of the target's regular USART has been low for
+    /// longer than a whole frame. The break is still reported as a framing
+    /// error first (see `UsartError`), as it looks like a data byte of `0`
+    /// without a stop bit, until then.
+    UsartBreakDetected,
+
+    /// Reply to `WaitForAddress`
+    AddressMatched {
+        /// The address, as received
+        ///
+        /// Includes the address bit, unless 9 data bits are configured.
+        address: u8,
+
+        /// The number of words the target discarded before the address
+        ///
+        /// Includes addresses that didn't match.
+        discarded: u32,
+    },
+
+    /// Notify the host that the target has woken up from `EnterSleep`
+    WokeUp {
+        cause: WakeupCause,
+
+        /// The time between entering the low-power mode and waking up
+        ///
+        /// Measured by the self-wake-up timer, which keeps running in
+        /// deep-sleep mode, with a resolution of 0.1 ms.
+        after_ms: u32,
+    },
+
+    /// Reply to `GetResetCause`
+    ResetCause(reset::Cause),
+
+    /// Reply to `WriteFlash`, once the data has been programmed
+    FlashWritten,
+
+    /// Reply to `ReadFlash`
+    ///
+    /// Carries the data read from flash.
+    FlashRead(&'r [u8]),
+
+    /// Reply to `WriteFlash` or `ReadFlash`, if the target couldn't do that
+    FlashError(FlashError),
+
+    /// Reply to `GetStoreEntry`
+    StoreEntry(Option<u32>),
+
+    /// Reply to `SetStoreEntry` and `ClearStore`
+    StoreUpdated(Result<(), StoreError>),
+
+    /// Notify the host that the multi-rate timer has expired
+    MrtExpired {
+        /// When the interrupt was handled, in microseconds
+        ///
+        /// Taken from the target's free-running timer, so only meaningful
+        /// relative to other timestamps from the same target.
+        timestamp: u32,
+    },
+
+    /// Notify the host that a capacitive touch sensor has been measured
+    ///
+    /// See `HostToTarget::StartCapt`.
+    CaptEvent(CaptEvent),
+
+    /// Reply to `SampleAdc`
+    ///
+    /// Carries the raw 12-bit results of the conversions, in the order they
+    /// were taken, each encoded as two bytes, little-endian (see
+    /// `decode_samples`).
+    AdcSamples(&'r [u8]),
+
+    /// Reply to `SetPinGroup`
+    PinGroupSet,
+
+    /// Reply to `ReadPinGroup`
+    ///
+    /// Has a bit set for each pin in the requested mask that is high. All
+    /// other bits are cleared.
+    PinGroup(u32),
+
+    /// Reply to `ConfigurePin`
+    PinConfigured,
+
+    /// Reply to `SetPinFilter`
+    PinFilterSet,
+
+    /// Notify the host that the target has stopped echoing USART data
+    ///
+    /// Sent once the duration of `StartUsartEcho` has passed.
+    UsartEchoed(UsartEcho),
 }
 
-impl<'r> TryFrom<TargetToHost<'r>> for pin::ReadLevelResult<()> {
+impl<'r> TryFrom<TargetToHost<'r>> for pin::ReadLevelResult<pin::PortPin> {
     type Error = TargetToHost<'r>;
 
     fn try_from(value: TargetToHost<'r>) -> Result<Self, Self::Error> {
@@ -141,3 +1047,359 @@ pub enum DmaMode {
     Regular,
     Dma,
 }
+
+
+/// Describes how the target woke up from USART activity
+///
+/// The target can't timestamp the start bit that woke it up. It can only
+/// timestamp the moment it is running again, and the moment the byte has been
+/// received. The wakeup latency is whatever is missing from the duration of the
+/// byte's frame.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct UsartWakeup {
+    /// Timer ticks between waking up and receiving the first byte
+    ///
+    /// This is `None`, if the first byte was lost.
+    pub received_after: Option<u32>,
+
+    /// The frequency of the timer, in Hz
+    pub timer_hz: u32,
+}
+
+
+/// A measurement of a capacitive touch sensor, as reported in `CaptEvent`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct CaptEvent {
+    /// The X pin that the sensor is connected to
+    pub pin: u8,
+
+    /// Whether the measurement was a touch
+    ///
+    /// A measurement that times out is a touch, too.
+    pub touched: bool,
+
+    /// The number of cycles of the CAPT clock that the measurement took
+    ///
+    /// This is `CAPT_MAX_COUNT`, if the measurement timed out.
+    pub count: u16,
+
+    /// When the measurement was handled, in microseconds
+    ///
+    /// Taken from the target's free-running timer, so only meaningful relative
+    /// to other timestamps from the same target.
+    pub timestamp: u32,
+}
+
+
+/// Whether a timer expires once, or repeatedly
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum TimerMode {
+    /// The timer expires once, then stops
+    OneShot,
+
+    /// The timer restarts every time it expires, until it is stopped
+    Repeat,
+}
+
+
+/// The low-power mode that `EnterSleep` enters
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum SleepMode {
+    /// Sleep mode, in which the peripherals keep running
+    Sleep,
+
+    /// Deep-sleep mode, in which the clocks of the peripherals are stopped
+    ///
+    /// Data that wakes up the target via USART is likely to be lost, as the
+    /// USART only gets its clock back after waking up.
+    DeepSleep,
+}
+
+
+/// What is supposed to wake up the target from `EnterSleep`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum WakeupSource {
+    /// The start bit of a byte received via the regular USART
+    UsartRx,
+
+    /// An edge on the target's input pin
+    PinInterrupt,
+}
+
+
+/// What actually woke up the target from `EnterSleep`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum WakeupCause {
+    UsartRx,
+    PinInterrupt,
+
+    /// Nothing woke up the target before the timeout
+    Timeout,
+}
+
+
+/// The data the target has echoed, as reported in `UsartEchoed`
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct UsartEcho {
+    /// The number of bytes received and sent back
+    pub bytes: u32,
+
+    /// The number of receive errors, like overruns
+    ///
+    /// Bytes received with an error are not sent back, and not included in
+    /// `bytes`.
+    pub errors: u32,
+
+    /// The number of bytes dropped, because the target couldn't keep up
+    pub dropped: u32,
+}
+
+
+/// An error that occurred while receiving via USART
+///
+/// Carries the data that was received along with the error.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum UsartError {
+    /// The stop bit was missing at the expected location
+    Framing(u8),
+
+    /// The data was corrupted by noise
+    Noise(u8),
+
+    /// The data was received, before the previous data had been read
+    Overrun(u8),
+
+    /// The parity bit didn't match the data
+    Parity(u8),
+}
+
+
+/// The kind of fault that caused the target to reset
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum FaultKind {
+    Panic     = 1,
+    HardFault = 2,
+    Watchdog  = 3,
+}
+
+
+/// The functionality supported by a test target
+///
+/// Depending on how it was built, the target firmware might only support a
+/// subset of the test suite. Test cases can use this to skip tests that the
+/// target can't support.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Capabilities {
+    pub usart:              bool,
+    pub usart_dma:          bool,
+    pub usart_flow_control: bool,
+    pub usart_sync:         bool,
+    pub i2c:                bool,
+    pub i2c_dma:            bool,
+    pub spi:                bool,
+    pub spi_dma:            bool,
+    pub adc:                bool,
+    pub dac:                bool,
+    pub pwm:                bool,
+    pub timer_interrupt:    bool,
+    pub trigger:            bool,
+    pub host_link:          HostLink,
+}
+
+
+/// The peripheral and pins that connect a test target to the host
+///
+/// The firmware can't test the peripherals that it needs to talk to the host.
+/// Test cases that need any of these should be skipped.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum HostLink {
+    /// A USART, usually bridged to USB by the board's on-board programmer
+    Usart {
+        /// The number of the USART instance, for example `0` for USART0
+        usart: u8,
+
+        /// The pin that receives from the host
+        rx: pin::PortPin,
+
+        /// The pin that transmits to the host
+        tx: pin::PortPin,
+    },
+
+    /// The target's own USB peripheral, as a CDC-ACM serial port
+    ///
+    /// The host finds the target by [`USB_VID`], [`USB_PID`], and the serial
+    /// number of the device.
+    Usb {
+        /// The D- pin
+        dm: pin::PortPin,
+
+        /// The D+ pin
+        dp: pin::PortPin,
+    },
+}
+
+impl HostLink {
+    /// Indicates whether the host link uses `pin`
+    pub fn uses_pin(&self, pin: pin::PortPin) -> bool {
+        match *self {
+            Self::Usart { rx, tx, .. } => pin == rx || pin == tx,
+            Self::Usb { dm, dp }       => pin == dm || pin == dp,
+        }
+    }
+
+    /// Indicates whether the host link uses the USART with number `usart`
+    pub fn uses_usart(&self, usart: u8) -> bool {
+        match *self {
+            Self::Usart { usart: used, .. } => usart == used,
+            Self::Usb { .. }                => false,
+        }
+    }
+}
+
+
+/// The layout of the target's flash memory, and how much of it is in use
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct FlashInfo {
+    /// The address at which the flash memory starts
+    pub start: u32,
+
+    /// The size of the flash memory, in bytes
+    pub size: u32,
+
+    /// The size of the smallest area that can be erased, in bytes
+    pub sector_size: u32,
+
+    /// The size of the smallest area that can be programmed, in bytes
+    pub page_size: u32,
+
+    /// The size of the firmware image, starting at `start`, in bytes
+    pub used: u32,
+
+    /// The size of the area at the end, which the target reserves, in bytes
+    ///
+    /// The target keeps its key-value store there (see
+    /// `HostToTarget::SetStoreEntry`).
+    pub reserved: u32,
+}
+
+
+/// An error that occurred while accessing the target's flash memory
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum FlashError {
+    /// The address or length is not a multiple of the page size
+    Unaligned,
+
+    /// The area is not between the firmware image and the reserved area
+    OutOfBounds,
+
+    /// An IAP command failed for another reason
+    ///
+    /// Carries the status code returned by the IAP command, as documented in
+    /// the user manual.
+    Iap(u32),
+}
+
+
+/// An error that occurred while updating the target's key-value store
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum StoreError {
+    /// The key is `u32::MAX`, which marks unused entries
+    InvalidKey,
+
+    /// The store already has `STORE_CAP` entries
+    Full,
+
+    /// Writing the store to flash memory failed
+    Flash(FlashError),
+}
+
+
+/// The state of the target's peripheral interrupts, as read from the NVIC
+///
+/// Interrupts are identified by their number, as listed in the target's
+/// reference manual. Bit `n % 32` of word `n / 32` represents interrupt `n`.
+/// Words beyond the number of interrupts that the target supports are `0`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct IrqState {
+    /// The interrupts that are enabled
+    pub enabled: [u32; 4],
+
+    /// The interrupts that are pending
+    pub pending: [u32; 4],
+}
+
+impl IrqState {
+    /// Indicates whether interrupt `irq` is enabled
+    pub fn is_enabled(&self, irq: u8) -> bool {
+        bit(&self.enabled, irq)
+    }
+
+    /// Indicates whether interrupt `irq` is pending
+    pub fn is_pending(&self, irq: u8) -> bool {
+        bit(&self.pending, irq)
+    }
+}
+
+
+/// The interrupt latencies measured by the target's workload
+///
+/// The latency of an interrupt is the time between it being pended and its
+/// handler being entered. This includes the time spent in the handlers of
+/// other interrupts that were pended at the same time, but have a higher
+/// priority.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct IrqLatencies {
+    /// The maximum latency of each interrupt, in timer ticks
+    ///
+    /// Indexed by interrupt number. Interrupts that weren't part of the
+    /// workload are `0`.
+    pub max: [u32; 32],
+
+    /// The frequency of the timer that measured the latencies, in Hz
+    pub timer_hz: u32,
+}
+
+impl IrqLatencies {
+    /// The maximum latency of interrupt `irq`, in seconds
+    pub fn max_secs(&self, irq: u8) -> f64 {
+        let ticks = self.max.get(irq as usize).copied().unwrap_or(0);
+        ticks as f64 / self.timer_hz as f64
+    }
+}
+
+
+/// Encode 12-bit samples for `StartDacWaveform` or `AdcSamples`
+///
+/// Writes two bytes per sample into `buf`, little-endian, and returns the part
+/// of `buf` that has been written.
+///
+/// # Panics
+///
+/// Panics, if `buf` is too short for all samples.
+pub fn encode_samples<I>(samples: I, buf: &mut [u8]) -> &[u8]
+    where I: IntoIterator<Item = u16>
+{
+    let mut len = 0;
+    for sample in samples {
+        buf[len .. len + 2].copy_from_slice(&sample.to_le_bytes());
+        len += 2;
+    }
+
+    &buf[..len]
+}
+
+/// Decode the samples of `StartDacWaveform` or `AdcSamples`
+///
+/// See `encode_samples`. A trailing odd byte is ignored.
+pub fn decode_samples(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    bytes
+        .chunks_exact(2)
+        .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+}
+
+
+fn bit(words: &[u32], n: u8) -> bool {
+    words.get(n as usize / 32)
+        .map(|word| word & 1 << (n % 32) != 0)
+        .unwrap_or(false)
+}
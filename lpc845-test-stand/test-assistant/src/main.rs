@@ -8,12 +8,19 @@
 #![no_std]
 
 
-extern crate panic_rtt_target;
-
-
-use core::marker::PhantomData;
+use core::{
+    marker::PhantomData,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
 
-use heapless::FnvIndexMap;
+use heapless::{
+    FnvIndexMap,
+    Vec,
+    spsc,
+};
 use lpc8xx_hal::{
     prelude::*,
     Peripherals,
@@ -21,6 +28,7 @@ use lpc8xx_hal::{
     gpio::{
         self,
         GpioPin,
+        direction::Dynamic,
         direction::Output,
         direction::Input,
     },
@@ -37,12 +45,16 @@ use lpc8xx_hal::{
         block,
     },
     pac::{
+        CTIMER0,
+        GPIO,
         I2C0,
         SPI0,
         USART0,
         USART1,
         USART2,
         USART3,
+        USART4,
+        usart0::RegisterBlock,
     },
     pinint::{
         PININT0,
@@ -51,10 +63,13 @@ use lpc8xx_hal::{
         PININT3,
     },
     pins::{
+        DynamicPinDirection,
         PIO0_8,
         PIO0_9,
         PIO0_20,
+        PIO0_21,
         PIO0_23,
+        PIO0_31,
         PIO1_0,
         PIO1_1,
         PIO1_2,
@@ -76,32 +91,140 @@ use lpc8xx_hal::{
     },
 };
 use rtt_target::rprintln;
+use void::{
+    ResultVoidExt,
+    Void,
+};
 
-#[cfg(feature = "sleep")]
 use lpc8xx_hal::cortex_m::asm;
 
 use firmware_lib::{
+    overrun,
+    panic,
     pin_interrupt::{
         self,
         PinInterrupt,
     },
     usart::{
         RxIdle,
-        RxInt,
-        Tx,
         Usart,
+        lpc8xx::{
+            RxInt,
+            Tx,
+        },
+        rx::{
+            ProcessError,
+            ReceiveError,
+        },
     },
 };
 use lpc845_messages::{
     AssistantToHost,
     HostToAssistant,
     InputPin,
+    MonitorPin,
     OutputPin,
     UsartMode,
+    chunk::Reassembly,
+    i2c::{
+        self as i2c_slave,
+        Nack,
+        SlaveConfig,
+        TransactionLog,
+    },
+    monitor,
     pin,
+    reference,
+    spi as spi_slave,
+    trigger,
+    units::{
+        Microseconds,
+        Nanoseconds,
+    },
+    usart::{
+        Count as UsartCount,
+        DUPLEX_CAP,
+        STREAM_PATTERN_CAP,
+        Stream as UsartStreamReport,
+        Throttle as UsartThrottle,
+    },
+    waveform,
 };
 
 
+// Panics are reported to the host, instead of only over RTT.
+firmware_lib::panic_handler!(AssistantToHost::Panic);
+
+
+/// How long the trigger line is held high, in system clock cycles
+///
+/// Keeps the pulse wide enough to be detected reliably by the other test
+/// nodes, even if the assistant has no action of its own to perform.
+const TRIGGER_PULSE_CYCLES: u32 = 120;
+
+/// The frequency of the system clock, in Hz
+const SYSTEM_CLOCK_HZ: u32 = 12_000_000;
+
+/// The longest pulse that `send_pulse` accepts, in nanoseconds
+const PULSE_MAX_NS: u32 = 1_000_000;
+
+/// How long a break is held on the target's RX line, in system clock cycles
+///
+/// This is 2 ms at 12 MHz.
+const BREAK_CYCLES: u32 = 24_000;
+
+/// How many bytes of a USART stream are sent at once
+///
+/// Keeps the bytes coming back from the target from piling up, while sending.
+const STREAM_CHUNK: usize = 8;
+
+/// The address of the I2C slave, until the host configures another one
+const I2C_ADDRESS: u8 = 0x48;
+
+/// Frequency of the timer that timestamps the reference clock, in Hz
+///
+/// The timer runs from the system clock, without a prescaler.
+const REFERENCE_TIMER_HZ: u32 = 12_000_000;
+
+/// Frequency of the clock that all USARTs run from, in Hz
+///
+/// This is the output of FRG0, as configured in `init`: the 12 MHz FRO, divided
+/// by `1 + 22/256`.
+const USART_CLOCK_HZ: u32 = 11_050_359;
+
+/// The oversampling value of all USARTs, as configured in `init`
+const USART_OVERSAMPLING: u32 = 16;
+
+/// How many monitored bytes can be buffered, until they're sent to the host
+const MONITOR_QUEUE_CAP: usize = 64;
+
+/// The time after which a partial host request is discarded
+///
+/// In ticks of the timer that timestamps the reference clock.
+const HOST_RX_TIMEOUT: u32 = REFERENCE_TIMER_HZ / 10;
+
+/// The GPIO ports and pins of the `OutputPin`s, indexed by their value
+const OUTPUT_PINS: [(usize, u32); 3] = [(0, 20), (0, 8), (1, 2)];
+
+/// The GPIO ports and pins of the `InputPin`s, indexed by their value
+const INPUT_PINS: [(usize, u32); 4] = [(1, 1), (1, 0), (0, 9), (0, 23)];
+
+
+/// Set while the data received from the target is counted
+///
+/// Receive errors are expected then, for example when testing the limits of
+/// the target's USART, so they are counted instead of being fatal.
+static COUNTING: AtomicBool = AtomicBool::new(false);
+
+/// Counts the receive errors on the target USART, while counting
+static RX_ERRORS: overrun::Counter = overrun::Counter::new();
+
+/// Set by the USART1 handler, when the target has sent a break
+///
+/// `idle` reports the break to the host, and resets this.
+static TARGET_BREAK: AtomicBool = AtomicBool::new(false);
+
+
 #[rtic::app(device = lpc8xx_hal::pac)]
 const APP: () = {
     struct Resources {
@@ -136,10 +259,23 @@ const APP: () = {
         pin_5: GpioPin<PIO0_20, Output>,
         cts: GpioPin<PIO0_8, Output>,
         red: GpioPin<PIO1_2, Output>,
+        trigger: GpioPin<PIO0_21, Dynamic>,
         green: GpioPin<PIO1_0, Input>,
+        touch: GpioPin<PIO0_31, Dynamic>,
 
-        i2c: i2c::Slave<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
-        spi: SPI<SPI0, Enabled<spi::Slave>>,
+        reference: Reference,
+
+        monitor:       Monitor,
+        monitor_queue: spsc::Consumer<
+            'static,
+            monitor::Byte,
+            MONITOR_QUEUE_CAP,
+        >,
+
+        i2c:       i2c::Slave<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
+        i2c_slave: I2cSlave,
+
+        spi_slave: SpiSlave,
     }
 
     #[init]
@@ -150,6 +286,7 @@ const APP: () = {
         // at the beginning of the method, we're opting into some RTFM magic
         // that gives us safe access to them.
         static mut HOST:        Usart = Usart::new();
+        static mut REASSEMBLY:  Reassembly = Reassembly::new();
         static mut TARGET:      Usart = Usart::new();
         static mut TARGET_SYNC: Usart = Usart::new();
 
@@ -158,6 +295,9 @@ const APP: () = {
         static mut RTS:   PinInterrupt = PinInterrupt::new();
         static mut PWM:   PinInterrupt = PinInterrupt::new();
 
+        static mut MONITOR_QUEUE:
+            spsc::Queue<monitor::Byte, MONITOR_QUEUE_CAP> = spsc::Queue::new();
+
         rtt_target::rtt_init_print!();
         rprintln!("Starting assistant.");
 
@@ -217,6 +357,28 @@ const APP: () = {
             gpio::Level::Low,
         );
 
+        // Configure the trigger line, which is connected to all test nodes.
+        // It can also be released, to share it as an open-drain line.
+        let trigger = p.pins.pio0_21
+            .into_input_pin(gpio.tokens.pio0_21)
+            .into_dynamic(gpio::Level::Low, DynamicPinDirection::Output);
+
+        // Configure the pin connected to the target's touch sensor. It's only
+        // driven low, to simulate a touch, and left at high-Z otherwise.
+        let touch = p.pins.pio0_31
+            .into_input_pin(gpio.tokens.pio0_31)
+            .into_dynamic(gpio::Level::Low, DynamicPinDirection::Input);
+
+        // Configure the reference clock input. CTIMER0 counts at the system
+        // clock frequency, and captures its count on every rising edge.
+        let (_t0_cap0, _) = swm.movable_functions.t0_cap0.assign(
+            p.pins.pio0_22.into_swm_pin(),
+            &mut swm_handle,
+        );
+        let reference = Reference::new(
+            p.CTIMER0.enable(u32::MAX, 0, &mut syscon.handle).free(),
+        );
+
         // Configure the clock for USART0, using the Fractional Rate Generator
         // (FRG) and the USART's own baud rate divider value (BRG). See user
         // manual, section 17.7.1.
@@ -259,9 +421,10 @@ const APP: () = {
             RXRDY: true,
             .. usart::Interrupts::default()
         });
+        panic::init::<USART0>();
 
         // Assign pins to USART1.
-        let (u1_rxd, _) = swm.movable_functions.u1_rxd.assign(
+        let (u1_rxd, target_tx_pin) = swm.movable_functions.u1_rxd.assign(
             p.pins.pio0_26.into_swm_pin(),
             &mut swm_handle,
         );
@@ -279,7 +442,8 @@ const APP: () = {
             usart::Settings::default(),
         );
         target.enable_interrupts(usart::Interrupts {
-            RXRDY: true,
+            RXRDY:      true,
+            DELTARXBRK: true,
             .. usart::Interrupts::default()
         });
 
@@ -293,6 +457,32 @@ const APP: () = {
         rts_int.enable_falling_edge();
         let (rts_int, rts_idle) = RTS.init(rts_int, timers.mrt2);
 
+        // Assign pins to USART4. Its RX function shares a pin with USART1, so
+        // it can listen to the target's TX line. USART4 never transmits, but
+        // its TX function needs to be assigned anyway. PIO0_30 isn't used
+        // otherwise.
+        let (u4_rxd, _) = swm.movable_functions.u4_rxd.assign(
+            target_tx_pin,
+            &mut swm_handle,
+        );
+        let (u4_txd, _) = swm.movable_functions.u4_txd.assign(
+            p.pins.pio0_30.into_swm_pin(),
+            &mut swm_handle,
+        );
+
+        // Use USART4 to monitor the target's TX line. The HAL doesn't support
+        // changing the baud rate of an enabled USART, so `Monitor` accesses the
+        // peripheral directly.
+        let monitor_usart = p.USART4.enable_async(
+            &clock_config,
+            &mut syscon.handle,
+            u4_rxd,
+            u4_txd,
+            usart::Settings::default(),
+        );
+        let (monitor_prod, monitor_queue) = MONITOR_QUEUE.split();
+        let monitor = Monitor::new(monitor_usart.free(), monitor_prod);
+
         // Assign pins to USART2.
         let (u2_rxd, _) = swm.movable_functions.u2_rxd.assign(
             p.pins.pio0_28.into_swm_pin(),
@@ -340,7 +530,10 @@ const APP: () = {
             .. usart::Interrupts::default()
         });
 
-        let (host_rx_int,   host_rx_idle,   host_tx)   = HOST.init(host);
+        let (host_rx_int, mut host_rx_idle, host_tx) = HOST.init(host);
+        host_rx_idle.timeout    = Some(HOST_RX_TIMEOUT);
+        host_rx_idle.reassembly = Some(REASSEMBLY);
+
         let (target_rx_int, target_rx_idle, target_tx) = TARGET.init(target);
         let (target_sync_rx_int, target_sync_rx_idle, target_sync_tx) =
             TARGET_SYNC.init(target_sync);
@@ -364,11 +557,12 @@ const APP: () = {
                 &mut syscon.handle,
             )
             .enable_slave_mode(
-                0x48,
+                I2C_ADDRESS,
             )
             .expect("Not using a valid address");
         i2c.enable_interrupts(i2c::Interrupts {
-            slave_pending: true,
+            slave_pending:  true,
+            slave_deselect: true,
             .. i2c::Interrupts::default()
         });
 
@@ -438,9 +632,18 @@ const APP: () = {
             red,
             green,
             cts,
+            trigger,
+            touch,
 
-            i2c: i2c.slave,
-            spi,
+            reference,
+
+            monitor,
+            monitor_queue,
+
+            i2c:       i2c.slave,
+            i2c_slave: I2cSlave::new(),
+
+            spi_slave: SpiSlave::new(spi),
         }
     }
 
@@ -461,6 +664,13 @@ const APP: () = {
             red,
             green,
             cts,
+            trigger,
+            touch,
+            reference,
+            monitor,
+            monitor_queue,
+            i2c_slave,
+            spi_slave,
         ]
     )]
     fn idle(cx: idle::Context) -> ! {
@@ -479,6 +689,13 @@ const APP: () = {
         let red            = cx.resources.red;
         let green          = cx.resources.green;
         let cts            = cx.resources.cts;
+        let trigger        = cx.resources.trigger;
+        let touch          = cx.resources.touch;
+        let mut reference  = cx.resources.reference;
+        let mut monitor    = cx.resources.monitor;
+        let monitor_queue  = cx.resources.monitor_queue;
+        let mut i2c_slave  = cx.resources.i2c_slave;
+        let mut spi_slave  = cx.resources.spi_slave;
 
         let mut pins = FnvIndexMap::<_, _, 8>::new();
 
@@ -489,33 +706,193 @@ const APP: () = {
         };
         pins.insert(InputPin::Green as usize, (level, None)).unwrap();
 
+        // The pins whose edges are being monitored
+        let mut pin_monitors = FnvIndexMap::<_, _, 8>::new();
+
         let mut buf = [0; 256];
 
+        // The action to perform when firing the trigger
+        let mut trigger_action = None;
+
+        // The pattern that fires the trigger, when seen on the monitored line
+        let mut pattern = Pattern::default();
+
+        // The data to send, once the target starts sending
+        let mut duplex = Vec::<u8, DUPLEX_CAP>::new();
+
+        // The data received from the target, while it is being counted
+        let mut usart_count: Option<UsartCount> = None;
+
+        // The data received from the target, while it is being throttled
+        let mut cts_throttle: Option<CtsThrottle> = None;
+
+        // The data sent to the target, and what came back, while streaming
+        let mut usart_stream: Option<UsartStream> = None;
+
+        // Let the host know that we're ready.
+        host_tx.send_sync()
+            .void_unwrap();
+
         loop {
-            target_rx
-                .process_raw(|data| {
-                    host_tx.send_message(
-                        &AssistantToHost::UsartReceive {
-                            mode: UsartMode::Regular,
-                            data,
-                        },
+            // This needs to happen before the received data is forwarded
+            // below, which takes it out of the queue.
+            if !duplex.is_empty() && target_rx.can_process() {
+                target_tx.send_raw(&duplex)
+                    .unwrap();
+                duplex.clear();
+            }
+
+            // Counted data isn't forwarded, so the host link can't slow down
+            // the measurement.
+            if let Some(usart_count) = &mut usart_count {
+                let now = reference.lock(|reference| reference.now());
+                target_rx
+                    .process_raw(|data| {
+                        usart_count.bytes += data.len() as u32;
+                        usart_count.first.get_or_insert(now);
+                        usart_count.last = Some(now);
+                        Ok::<_, Void>(())
+                    })
+                    .expect("Error processing USART data");
+                usart_count.dropped += target_rx.take_dropped();
+            }
+
+            // The same goes for throttled data. CTS is deasserted right after
+            // the data that reaches the threshold has been processed.
+            if let Some(cts_throttle) = &mut cts_throttle {
+                let now = reference.lock(|reference| reference.now());
+                target_rx
+                    .process_raw(|data| {
+                        cts_throttle.receive(data.len() as u32, now);
+                        Ok::<_, Void>(())
+                    })
+                    .expect("Error processing USART data");
+
+                let now = reference.lock(|reference| reference.now());
+                cts_throttle.update(now, cts);
+            }
+
+            // Streamed data is sent a few bytes at a time, so what comes back
+            // can be checked in between. Nothing is forwarded either.
+            if let Some(usart_stream) = &mut usart_stream {
+                let chunk = usart_stream.next_chunk();
+                if !chunk.is_empty() {
+                    target_tx.send_raw(chunk)
+                        .unwrap();
+                }
+
+                target_rx
+                    .process_raw(|data| {
+                        usart_stream.receive(data);
+                        Ok::<_, Void>(())
+                    })
+                    .expect("Error processing USART data");
+                usart_stream.report.dropped += target_rx.take_dropped();
+            }
+
+            // If the host has run out of credits, received data stays in the
+            // queues, until it grants more. Anything that doesn't fit into
+            // the queues in the meantime is dropped, and reported below.
+            if host_tx.credits.available() {
+                target_rx
+                    .process_raw(|data| {
+                        host_tx.send_credited(
+                            &AssistantToHost::UsartReceive {
+                                mode: UsartMode::Regular,
+                                data,
+                            },
+                            &mut buf,
+                        )
+                    })
+                    .expect("Error processing USART data");
+            }
+            if host_tx.credits.available() {
+                target_sync_rx
+                    .process_raw(|data| {
+                        host_tx.send_credited(
+                            &AssistantToHost::UsartReceive {
+                                mode: UsartMode::Sync,
+                                data,
+                            },
+                            &mut buf,
+                        )
+                    })
+                    .expect("Error processing USART data");
+            }
+
+            // No atomic swap on this MCU. The handler could set the flag again,
+            // in between loading and storing it.
+            let target_break = interrupt::free(|_| {
+                let detected = TARGET_BREAK.load(Ordering::Relaxed);
+                TARGET_BREAK.store(false, Ordering::Relaxed);
+                detected
+            });
+            if target_break {
+                host_tx
+                    .send_message(
+                        &AssistantToHost::UsartBreakDetected,
                         &mut buf,
                     )
-                })
-                .expect("Error processing USART data");
-            target_sync_rx
-                .process_raw(|data| {
-                    host_tx.send_message(
-                        &AssistantToHost::UsartReceive {
-                            mode: UsartMode::Sync,
-                            data,
-                        },
+                    .unwrap();
+            }
+
+            let dropped = [
+                (UsartMode::Regular, target_rx.take_dropped()),
+                (UsartMode::Sync,    target_sync_rx.take_dropped()),
+            ];
+            for &(mode, count) in &dropped {
+                if count > 0 {
+                    host_tx
+                        .send_message(
+                            &AssistantToHost::UsartDropped { mode, count },
+                            &mut buf,
+                        )
+                        .unwrap();
+                }
+            }
+
+            while let Some(byte) = monitor_queue.dequeue() {
+                // Fire the trigger first, as sending to the host takes a
+                // while.
+                let matched = pattern.push(byte.value);
+                if matched {
+                    fire_trigger(
+                        trigger,
+                        trigger_action.take(),
+                        pin_5,
+                        red,
+                        cts,
+                    );
+                }
+
+                host_tx
+                    .send_message(
+                        &AssistantToHost::MonitoredByte(byte),
                         &mut buf,
                     )
-                })
-                .expect("Error processing USART data");
+                    .unwrap();
+                if matched {
+                    host_tx
+                        .send_message(
+                            &AssistantToHost::PatternMatched {
+                                timestamp: byte.timestamp,
+                            },
+                            &mut buf,
+                        )
+                        .unwrap();
+                }
+            }
+
+            let now = reference.lock(|reference| reference.now());
+            if host_rx.discard_stale_frame(now) {
+                rprintln!("Discarding partial host request.");
+            }
+
+            // The receiver is borrowed while the message is processed, so a
+            // negotiated checksum can only be applied to it afterwards.
+            let mut negotiated_checksum = None;
 
-            host_rx
+            let result = host_rx
                 .process_message(|message| {
                     match message {
                         HostToAssistant::SendUsart {
@@ -543,6 +920,98 @@ const APP: () = {
                         } => {
                             target_sync_tx.send_raw(data)
                         }
+                        HostToAssistant::SendUsartCorrupted {
+                            data,
+                            corruptions,
+                        } => {
+                            let corrupted = data.iter()
+                                .enumerate()
+                                .filter_map(|(position, &byte)| {
+                                    corruptions.apply(position, byte)
+                                });
+                            for byte in corrupted {
+                                target_tx.send_raw(&[byte])?;
+                            }
+
+                            Ok(())
+                        }
+                        HostToAssistant::SendUsartWithWrongParity(data) => {
+                            send_misconfigured(target_tx, data, |usart| {
+                                usart.cfg.modify(|r, w| {
+                                    if r.paritysel().is_even_parity() {
+                                        w.paritysel().odd_parity()
+                                    }
+                                    else {
+                                        w.paritysel().even_parity()
+                                    }
+                                });
+                            })
+                        }
+                        HostToAssistant::SendUsartAtWrongBaud {
+                            data,
+                            baud,
+                        } => {
+                            send_misconfigured(target_tx, data, |usart| {
+                                let oversampling =
+                                    usart.osr.read().osrval().bits() as u32 + 1;
+                                let brgval = firmware_lib::usart::brgval(
+                                    USART_CLOCK_HZ,
+                                    oversampling,
+                                    baud,
+                                );
+
+                                usart.brg.write(|w| unsafe {
+                                    w.brgval().bits(brgval)
+                                });
+                            })
+                        }
+                        HostToAssistant::SendUsartDuplex(data) => {
+                            duplex = Vec::from_slice(data)
+                                .unwrap_or_default();
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::UsartDuplexArmed,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::SendUsartBreak => {
+                            // Sound, as the HAL doesn't use the break function
+                            // of USART1. We're only reading from the other
+                            // registers.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            while usart.stat.read().txidle().bit_is_clear() {}
+                            usart.ctl.modify(|_, w| w.txbrken().continous());
+                            asm::delay(BREAK_CYCLES);
+                            usart.ctl.modify(|_, w| w.txbrken().normal());
+
+                            Ok(())
+                        }
+                        HostToAssistant::SendUsartAddress(address) => {
+                            // Sound, as `send_raw` blocks until the HAL is
+                            // done with TXDAT, and we wait for it to be ready
+                            // again. We're only reading from the other
+                            // registers.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            let address_bit =
+                                if usart.cfg.read().datalen().is_bit_9() {
+                                    0x100
+                                }
+                                else {
+                                    0x80
+                                };
+
+                            while usart.stat.read().txrdy().bit_is_clear() {}
+                            usart.txdat.write(|w| unsafe {
+                                w.txdat().bits(address as u16 | address_bit)
+                            });
+
+                            Ok(())
+                        }
                         HostToAssistant::SetPin(
                             pin::SetLevel {
                                 pin: OutputPin::Pin5,
@@ -595,133 +1064,683 @@ const APP: () = {
                             cts.set_low();
                             Ok(())
                         }
-                        HostToAssistant::ReadPin(
-                            pin::ReadLevel { pin }
-                        ) => {
-                            let result = pins.get(&(pin as usize))
-                                .map(|&(level, period_ms)| {
-                                    pin::ReadLevelResult {
-                                        pin,
-                                        level,
-                                        period_ms,
-                                    }
-                                });
+                        HostToAssistant::ArmOnTrigger(action) => {
+                            trigger_action = Some(action);
+                            Ok(())
+                        }
+                        HostToAssistant::FireTrigger => {
+                            fire_trigger(
+                                trigger,
+                                trigger_action.take(),
+                                pin_5,
+                                red,
+                                cts,
+                            );
+                            Ok(())
+                        }
+                        HostToAssistant::StartUsartCount => {
+                            RX_ERRORS.take();
+                            COUNTING.store(true, Ordering::Relaxed);
+                            usart_count = Some(UsartCount {
+                                timer_hz: REFERENCE_TIMER_HZ,
+                                .. UsartCount::default()
+                            });
 
                             host_tx
                                 .send_message(
-                                    &AssistantToHost::ReadPinResult(result),
+                                    &AssistantToHost::UsartCountStarted,
                                     &mut buf,
                                 )
                                 .unwrap();
 
                             Ok(())
                         }
-                    }
-                })
-                .expect("Error processing host request");
-            host_rx.clear_buf();
+                        HostToAssistant::StopUsartCount => {
+                            COUNTING.store(false, Ordering::Relaxed);
+                            let mut count = usart_count.take()
+                                .unwrap_or_default();
+                            count.errors = RX_ERRORS.take();
 
-            handle_pin_interrupt(green_idle, InputPin::Green, &mut pins);
-            handle_pin_interrupt(blue,  InputPin::Blue,  &mut pins);
-            handle_pin_interrupt(rts,   InputPin::Rts,   &mut pins);
-            handle_pin_interrupt(pwm,   InputPin::Pwm,   &mut pins);
-
-            // We need this critical section to protect against a race
-            // conditions with the interrupt handlers. Otherwise, the following
-            // sequence of events could occur:
-            // 1. We check the queues here, they're empty.
-            // 2. New data is received, an interrupt handler adds it to a queue.
-            // 3. The interrupt handler is done, we're back here and going to
-            //    sleep.
-            //
-            // This might not be observable, if something else happens to wake
-            // us up before the test suite times out. But it could also lead to
-            // spurious test failures.
-            interrupt::free(|_| {
-                let should_sleep =
-                    !host_rx.can_process()
-                    && !target_rx.can_process()
-                    && !green_idle.is_ready();
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::UsartCounted(count),
+                                    &mut buf,
+                                )
+                                .unwrap();
 
-                if should_sleep {
-                    // On LPC84x MCUs, debug mode is not supported when
-                    // sleeping. This interferes with RTT communication. Only
-                    // sleep, if the user enables this through a compile-time
-                    // flag.
-                    #[cfg(feature = "sleep")]
-                    asm::wfi();
-                }
-            });
-        }
-    }
+                            Ok(())
+                        }
+                        HostToAssistant::ThrottleCts { after, duration } => {
+                            cts.set_low();
+                            cts_throttle =
+                                Some(CtsThrottle::new(after, duration));
 
-    #[task(binds = USART0, resources = [host_rx_int])]
-    fn usart0(cx: usart0::Context) {
-        cx.resources.host_rx_int.receive()
-            .expect("Error receiving from USART0");
-    }
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::CtsThrottleArmed,
+                                    &mut buf,
+                                )
+                                .unwrap();
 
-    #[task(binds = USART1, resources = [target_rx_int])]
-    fn usart1(cx: usart1::Context) {
-        cx.resources.target_rx_int.receive()
-            .expect("Error receiving from USART1");
-    }
+                            Ok(())
+                        }
+                        HostToAssistant::StopCtsThrottle => {
+                            cts.set_low();
+                            let throttle = cts_throttle.take()
+                                .map(|cts_throttle| cts_throttle.throttle)
+                                .unwrap_or_default();
 
-    #[task(binds = PIN_INT6_USART3, resources = [target_sync_rx_int])]
-    fn usart3(cx: usart3::Context) {
-        cx.resources.target_sync_rx_int.receive()
-            .expect("Error receiving from USART3");
-    }
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::CtsThrottled(throttle),
+                                    &mut buf,
+                                )
+                                .unwrap();
 
-    #[task(binds = PIN_INT0, resources = [green_int])]
-    fn pinint0(context: pinint0::Context) {
-        context.resources.green_int.handle_interrupt();
-    }
+                            Ok(())
+                        }
+                        HostToAssistant::StartUsartStream {
+                            pattern,
+                            bytes,
+                        } => {
+                            RX_ERRORS.take();
+                            COUNTING.store(true, Ordering::Relaxed);
+                            usart_stream =
+                                Some(UsartStream::new(pattern, bytes));
 
-    #[task(binds = PIN_INT1, resources = [blue_int])]
-    fn pinint1(context: pinint1::Context) {
-        context.resources.blue_int.handle_interrupt();
-    }
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::UsartStreamStarted,
+                                    &mut buf,
+                                )
+                                .unwrap();
 
-    #[task(binds = PIN_INT2, resources = [target_rts_int])]
-    fn pinint2(context: pinint2::Context) {
-        context.resources.target_rts_int.handle_interrupt();
-    }
+                            Ok(())
+                        }
+                        HostToAssistant::StopUsartStream => {
+                            COUNTING.store(false, Ordering::Relaxed);
+                            let mut report = usart_stream.take()
+                                .map(|usart_stream| usart_stream.report)
+                                .unwrap_or_default();
+                            report.errors = RX_ERRORS.take();
 
-    #[task(binds = PIN_INT3, resources = [pwm_int])]
-    fn pinint3(context: pinint3::Context) {
-        context.resources.pwm_int.handle_interrupt();
-    }
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::UsartStreamed(report),
+                                    &mut buf,
+                                )
+                                .unwrap();
 
-    #[task(binds = I2C0, resources = [i2c])]
-    fn i2c0(context: i2c0::Context) {
-        static mut DATA: Option<u8> = None;
+                            Ok(())
+                        }
+                        HostToAssistant::DriveSharedLine(level) => {
+                            match level {
+                                pin::Level::Low => {
+                                    trigger.switch_to_output(gpio::Level::Low);
+                                }
+                                pin::Level::High => {
+                                    trigger.switch_to_input();
+                                }
+                            }
 
-        rprintln!("I2C: Handling I2C0 interrupt...");
+                            let level = match trigger.is_high() {
+                                true  => pin::Level::High,
+                                false => pin::Level::Low,
+                            };
 
-        match context.resources.i2c.wait() {
-            Ok(i2c::slave::State::AddressMatched(i2c)) => {
-                rprintln!("I2C: Address matched.");
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::SharedLineLevel(level),
+                                    &mut buf,
+                                )
+                                .unwrap();
 
-                i2c.ack().unwrap();
+                            Ok(())
+                        }
+                        HostToAssistant::SimulateTouch(touched) => {
+                            match touched {
+                                true  => {
+                                    touch.switch_to_output(gpio::Level::Low);
+                                }
+                                false => {
+                                    touch.switch_to_input();
+                                }
+                            }
 
-                rprintln!("I2C: Ack'ed address.");
-            }
-            Ok(i2c::slave::State::RxReady(i2c)) => {
-                rprintln!("I2C: Ready to receive.");
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::TouchSimulated,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ResetReference => {
+                            reference.lock(|reference| reference.reset());
+                            Ok(())
+                        }
+                        HostToAssistant::ReadReference => {
+                            let timestamps = reference
+                                .lock(|reference| reference.timestamps());
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::ReferenceTimestamps(
+                                        timestamps,
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::MonitorUsart {
+                            pin: MonitorPin::TargetTx,
+                            baud,
+                        } => {
+                            monitor.lock(|monitor| monitor.start(baud));
+
+                            // Anything still queued was received before.
+                            while monitor_queue.dequeue().is_some() {}
+                            pattern = Pattern::default();
+
+                            Ok(())
+                        }
+                        HostToAssistant::TriggerOnPattern(data) => {
+                            pattern = Pattern::new(data);
+                            Ok(())
+                        }
+                        HostToAssistant::StopMonitor => {
+                            monitor.lock(|monitor| monitor.stop());
+                            Ok(())
+                        }
+                        HostToAssistant::StartPinMonitoring(pin) => {
+                            pin_monitors
+                                .insert(pin as usize, PinMonitor::new(pin))
+                                .unwrap();
+                            Ok(())
+                        }
+                        HostToAssistant::StopPinMonitoring(pin) => {
+                            let signal = pin_monitors.remove(&(pin as usize))
+                                .map(|monitor| monitor.signal);
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::PinMonitoringResult(
+                                        signal,
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ReadPin(
+                            pin::ReadLevel { pin }
+                        ) => {
+                            let result = pins.get(&(pin as usize))
+                                .map(|&(level, period)| {
+                                    pin::ReadLevelResult {
+                                        pin,
+                                        level,
+                                        period,
+                                    }
+                                });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::ReadPinResult(result),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::NegotiateCompression(compression) => {
+                            // The reply must still use the previous scheme, so
+                            // only switch after sending it.
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::CompressionNegotiated(
+                                        compression,
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+                            host_tx.compression = compression;
+                            panic::set_framing(compression, host_tx.checksum);
+
+                            Ok(())
+                        }
+                        HostToAssistant::NegotiateChecksum(checksum) => {
+                            // The reply must still use the previous checksum,
+                            // so only switch after sending it.
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::ChecksumNegotiated(
+                                        checksum,
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+                            host_tx.checksum = checksum;
+                            panic::set_framing(host_tx.compression, checksum);
+                            negotiated_checksum = Some(checksum);
+
+                            Ok(())
+                        }
+                        HostToAssistant::ConfigureUsart { mode, config } => {
+                            // The HAL can only configure a USART when enabling
+                            // it. This is sound, as nothing else accesses the
+                            // configuration of these USARTs after `init`.
+                            let usart = match mode {
+                                UsartMode::Regular | UsartMode::FlowControl => {
+                                    unsafe { &*USART1::ptr() }
+                                }
+                                UsartMode::Dma => {
+                                    unsafe { &*USART2::ptr() }
+                                }
+                                UsartMode::Sync => {
+                                    unsafe { &*USART3::ptr() }
+                                }
+                            };
+                            firmware_lib::usart::configure(
+                                usart,
+                                USART_CLOCK_HZ,
+                                &config,
+                            );
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::UsartConfigured,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::PlayWaveform(waveform) => {
+                            play_waveform(
+                                &waveform,
+                                || reference.lock(|reference| reference.now()),
+                                pin_5,
+                                red,
+                                cts,
+                            );
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::WaveformPlayed,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ConfigureI2cSlave(config) => {
+                            i2c_slave.lock(|slave| slave.configure(config));
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::I2cSlaveConfigured,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::WriteI2cRegisters {
+                            register,
+                            data,
+                        } => {
+                            i2c_slave.lock(|slave| {
+                                slave.write_registers(register, data)
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::I2cRegistersWritten,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ReadI2cRegisters { register, len } => {
+                            let mut data = [0; i2c_slave::TRANSFER_CAP];
+                            let data = &mut data[..len as usize];
+                            i2c_slave.lock(|slave| {
+                                slave.read_registers(register, data)
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::I2cRegisters(data),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::GetI2cTransactionLog => {
+                            let log = i2c_slave.lock(|slave| slave.take_log());
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::I2cTransactionLog(log),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::LoadSpiResponse(response) => {
+                            spi_slave.lock(|slave| {
+                                slave.load_response(response)
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::SpiResponseLoaded,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::GetSpiReceived => {
+                            let mut data = [0; spi_slave::BUF_CAP];
+                            let data = spi_slave.lock(|slave| {
+                                let received = slave.received();
+                                let data = &mut data[..received.len()];
+                                data.copy_from_slice(received);
+                                data
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::SpiReceived(data),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::SpiMasterTransfer(data) => {
+                            let mut received = [0; spi_slave::BUF_CAP];
+                            let received = &mut received[..data.len()];
+                            spi_slave.lock(|slave| {
+                                slave.transfer_as_master(data, received)
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::SpiMasterTransferred(
+                                        received,
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::SetPinGroup { mask, value } => {
+                            set_output_group(mask, value, pin_5, red, cts);
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::PinGroupSet,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::SendPulse { pin, width } => {
+                            send_pulse(pin, width, pin_5, red, cts);
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::PulseSent,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ReadPinGroup { mask } => {
+                            let levels = read_input_group(mask);
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::PinGroup(levels),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::Chunk { .. } => {
+                            // Chunks are collected by the receiver. This one
+                            // was reassembled from other chunks, which the host
+                            // never sends. Drop it.
+                            rprintln!("Dropping nested chunk.");
+                            Ok(())
+                        }
+                        HostToAssistant::GrantCredits(grant) => {
+                            host_tx.credits.grant(grant);
+                            Ok(())
+                        }
+                    }
+                });
+            match result {
+                Err(ProcessError::LinkSync) => {
+                    // A host is attaching, and starts over.
+                    host_tx.resync()
+                        .void_unwrap();
+                    panic::set_framing(host_tx.compression, host_tx.checksum);
+                }
+                Err(ProcessError::Postcard(_)) => {
+                    // Whatever was received before the host attached, or
+                    // before it resynchronized the link. Drop it.
+                    rprintln!("Dropping undecodable host request.");
+                }
+                Err(ProcessError::ChecksumMismatch) => {
+                    // The request was corrupted in transit. Drop it. The host
+                    // won't receive a reply, and can retransmit the request.
+                    rprintln!("Dropping corrupted host request.");
+                }
+                Err(ProcessError::Chunk(err)) => {
+                    // A chunk of a large request got lost. The host won't
+                    // receive a reply either, and can retransmit the request.
+                    rprintln!("Dropping incomplete host request: {:?}", err);
+                }
+                Err(ProcessError::BufferFull) => {
+                    // The request didn't fit into the receive buffer, and the
+                    // rest of it is being skipped. Drop it.
+                    rprintln!("Dropping oversized host request.");
+                }
+                Err(ProcessError::Other(err)) => {
+                    // The host won't receive a reply, and will time out.
+                    rprintln!("Error processing host request: {:?}", err);
+                }
+                Ok(()) => {}
+            }
+            host_rx.clear_buf();
+            if let Some(checksum) = negotiated_checksum {
+                host_rx.checksum = checksum;
+            }
+
+            let monitors = &mut pin_monitors;
+            handle_pin_interrupt(
+                green_idle, InputPin::Green, &mut pins, monitors,
+            );
+            handle_pin_interrupt(blue, InputPin::Blue, &mut pins, monitors);
+            handle_pin_interrupt(rts,  InputPin::Rts,  &mut pins, monitors);
+            handle_pin_interrupt(pwm,  InputPin::Pwm,  &mut pins, monitors);
+
+            // We need this critical section to protect against a race
+            // conditions with the interrupt handlers. Otherwise, the following
+            // sequence of events could occur:
+            // 1. We check the queues here, they're empty.
+            // 2. New data is received, an interrupt handler adds it to a queue.
+            // 3. The interrupt handler is done, we're back here and going to
+            //    sleep.
+            //
+            // This might not be observable, if something else happens to wake
+            // us up before the test suite times out. But it could also lead to
+            // spurious test failures.
+            interrupt::free(|_| {
+                let should_sleep =
+                    !host_rx.can_process()
+                    && !target_rx.can_process()
+                    && !TARGET_BREAK.load(Ordering::Relaxed)
+                    && !monitor_queue.ready()
+                    && !green_idle.is_ready();
+
+                if should_sleep {
+                    // On LPC84x MCUs, debug mode is not supported when
+                    // sleeping. This interferes with RTT communication. Only
+                    // sleep, if the user enables this through a compile-time
+                    // flag.
+                    #[cfg(feature = "sleep")]
+                    asm::wfi();
+                }
+            });
+        }
+    }
+
+    #[task(binds = USART0, resources = [host_rx_int])]
+    fn usart0(cx: usart0::Context) {
+        cx.resources.host_rx_int.receive()
+            .expect("Error receiving from USART0");
+    }
+
+    #[task(binds = USART1, resources = [target_rx_int])]
+    fn usart1(cx: usart1::Context) {
+        let result = cx.resources.target_rx_int.receive();
+
+        // The flag changes at the start and at the end of a break. Only the
+        // start is reported.
+        let usart = &cx.resources.target_rx_int.usart;
+        if usart.is_flag_set(usart::Flag::DELTARXBRK)
+            && usart.is_flag_set(usart::Flag::RXBRK)
+        {
+            TARGET_BREAK.store(true, Ordering::Relaxed);
+        }
+
+        if result.is_err() && COUNTING.load(Ordering::Relaxed) {
+            RX_ERRORS.add(1);
+            return;
+        }
+
+        match result {
+            // A break looks like a `0` without a stop bit, until it has lasted
+            // long enough to be detected. It's reported as a break then.
+            Err(ReceiveError::Usart(usart::Error::Framing(0))) => {}
+            result => {
+                result.expect("Error receiving from USART1");
+            }
+        }
+    }
+
+    #[task(binds = PIN_INT6_USART3, resources = [target_sync_rx_int])]
+    fn usart3(cx: usart3::Context) {
+        cx.resources.target_sync_rx_int.receive()
+            .expect("Error receiving from USART3");
+    }
+
+    #[task(binds = PIN_INT0, resources = [green_int, reference])]
+    fn pinint0(context: pinint0::Context) {
+        let timestamp = context.resources.reference.now();
+        context.resources.green_int.handle_interrupt(timestamp);
+    }
+
+    #[task(binds = PIN_INT1, resources = [blue_int, reference])]
+    fn pinint1(context: pinint1::Context) {
+        let timestamp = context.resources.reference.now();
+        context.resources.blue_int.handle_interrupt(timestamp);
+    }
+
+    #[task(binds = PIN_INT2, resources = [target_rts_int, reference])]
+    fn pinint2(context: pinint2::Context) {
+        let timestamp = context.resources.reference.now();
+        context.resources.target_rts_int.handle_interrupt(timestamp);
+    }
 
-                *DATA = Some(i2c.read().unwrap());
-                i2c.ack().unwrap();
+    #[task(binds = PIN_INT3, resources = [pwm_int, reference])]
+    fn pinint3(context: pinint3::Context) {
+        let timestamp = context.resources.reference.now();
+        context.resources.pwm_int.handle_interrupt(timestamp);
+    }
+
+    #[task(binds = CTIMER0, resources = [reference])]
+    fn ctimer0(context: ctimer0::Context) {
+        context.resources.reference.handle_interrupt();
+    }
+
+    #[task(binds = PIN_INT7_USART4, resources = [monitor, reference])]
+    fn usart4(context: usart4::Context) {
+        let timestamp = context.resources.reference.now();
+        context.resources.monitor.handle_interrupt(timestamp);
+    }
+
+    #[task(binds = I2C0, resources = [i2c, i2c_slave])]
+    fn i2c0(context: i2c0::Context) {
+        let slave = context.resources.i2c_slave;
+
+        rprintln!("I2C: Handling I2C0 interrupt...");
+
+        // The HAL doesn't support the deselect flag, which is set by a stop
+        // condition.
+        //
+        // Sound, as we're only doing an atomic read and write to a flag that
+        // the HAL doesn't access.
+        let i2c0 = unsafe { &*I2C0::ptr() };
+        if i2c0.stat.read().slvdesel().is_deselected() {
+            i2c0.stat.write(|w| w.slvdesel().deselected());
+            slave.deselected();
+            rprintln!("I2C: Deselected.");
+        }
+
+        // The peripheral stretches the clock, until the current state has
+        // been handled.
+        match context.resources.i2c.wait() {
+            Ok(i2c::slave::State::AddressMatched(i2c)) => {
+                rprintln!("I2C: Address matched.");
+
+                slave.stretch();
+                if slave.address_matched() {
+                    i2c.ack().unwrap();
+                    rprintln!("I2C: Ack'ed address.");
+                }
+                else {
+                    i2c.nack().unwrap();
+                    rprintln!("I2C: Nack'ed address.");
+                }
+            }
+            Ok(i2c::slave::State::RxReady(i2c)) => {
+                rprintln!("I2C: Ready to receive.");
 
-                rprintln!("I2C: Received and ack'ed.");
+                slave.stretch();
+                let data = i2c.read().unwrap();
+                if slave.receive(data) {
+                    i2c.ack().unwrap();
+                    rprintln!("I2C: Received and ack'ed.");
+                }
+                else {
+                    i2c.nack().unwrap();
+                    rprintln!("I2C: Received and nack'ed.");
+                }
             }
             Ok(i2c::slave::State::TxReady(i2c)) => {
                 rprintln!("I2C: Ready to transmit.");
 
-                if let Some(data) = *DATA {
-                    i2c.transmit(data << 1).unwrap();
-                    rprintln!("I2C: Transmitted.");
-                }
+                slave.stretch();
+                i2c.transmit(slave.transmit()).unwrap();
+
+                rprintln!("I2C: Transmitted.");
             }
             Err(nb::Error::WouldBlock) => {
                 // I2C not ready; nothing to do
@@ -732,44 +1751,840 @@ const APP: () = {
         }
     }
 
-    #[task(binds = SPI0, resources = [spi])]
+    #[task(binds = SPI0, resources = [spi_slave])]
     fn spi0(context: spi0::Context) {
-        static mut ACTIVE: bool = false;
-
-        let spi = context.resources.spi;
-
-        if spi.is_slave_select_asserted() {
-            *ACTIVE = true;
-        }
-        if *ACTIVE {
-            if spi.is_ready_to_receive() {
-                let data = spi.receive().unwrap();
-                block!(spi.transmit(data << 1))
-                    .unwrap();
-            }
-        }
-        if spi.is_slave_select_deasserted() {
-            *ACTIVE = false;
-        }
+        context.resources.spi_slave.handle_interrupt();
     }
 };
 
 
 fn handle_pin_interrupt(
-    int:  &mut pin_interrupt::Idle,
-    pin:  InputPin,
-    pins: &mut FnvIndexMap<usize, (pin::Level, Option<u32>), 8>,
+    int:      &mut pin_interrupt::Idle,
+    pin:      InputPin,
+    pins:     &mut FnvIndexMap<
+        usize,
+        (pin::Level, Option<Microseconds>),
+        8,
+    >,
+    monitors: &mut FnvIndexMap<usize, PinMonitor, 8>,
 ) {
     while let Some(event) = int.next() {
         match event {
-            pin_interrupt::Event { level, period } => {
+            pin_interrupt::Event { level, period, timestamp } => {
                 let level = match level {
                     gpio::Level::High => pin::Level::High,
                     gpio::Level::Low  => pin::Level::Low,
                 };
 
-                let period_ms = period.map(|value| value / 12_000);
-                pins.insert(pin as usize, (level, period_ms)).unwrap();
+                // The MRT runs from the system clock, at 12 MHz.
+                let period = period.map(|ticks| Microseconds(ticks / 12));
+                pins.insert(pin as usize, (level, period)).unwrap();
+
+                if let Some(monitor) = monitors.get_mut(&(pin as usize)) {
+                    monitor.edge(level, timestamp);
+                }
+            }
+        }
+    }
+}
+
+/// Send data to the target, using a temporarily modified configuration
+///
+/// The HAL can only configure a USART when enabling it, so `modify` gets
+/// access to the registers of USART1. The previous configuration is restored,
+/// once the data has been sent.
+fn send_misconfigured(
+    target_tx: &mut Tx<USART1, AsyncMode>,
+    data:      &[u8],
+    modify:    impl FnOnce(&RegisterBlock),
+)
+    -> Result<(), Void>
+{
+    // This is sound, as nothing else accesses the configuration of USART1
+    // after `init`.
+    let usart = unsafe { &*USART1::ptr() };
+
+    let cfg = usart.cfg.read().bits();
+    let brg = usart.brg.read().bits();
+
+    firmware_lib::usart::reconfigure(usart, modify);
+    target_tx.send_raw(data)?;
+    firmware_lib::usart::reconfigure(usart, |usart| {
+        usart.brg.write(|w| unsafe { w.bits(brg) });
+        usart.cfg.write(|w| unsafe { w.bits(cfg) });
+    });
+
+    Ok(())
+}
+
+/// Fire the trigger, performing the armed action, if any
+fn fire_trigger(
+    trigger: &mut GpioPin<PIO0_21, Dynamic>,
+    action:  Option<trigger::Action<OutputPin>>,
+    pin_5:   &mut GpioPin<PIO0_20, Output>,
+    red:     &mut GpioPin<PIO1_2, Output>,
+    cts:     &mut GpioPin<PIO0_8, Output>,
+) {
+    // The line might have been released, if it was shared as an open-drain
+    // line before. Firing the trigger takes it back.
+    trigger.switch_to_output(gpio::Level::High);
+    match action {
+        Some(trigger::Action::SetLevel(set_level)) => {
+            set_output(set_level, pin_5, red, cts);
+        }
+        None => {}
+    }
+    asm::delay(TRIGGER_PULSE_CYCLES);
+    trigger.set_low();
+}
+
+/// Play back a waveform on the pins that the host can control
+///
+/// `now` returns the current value of the timer of `Reference`, which is
+/// polled until each step is due. Delays count from the time the previous step
+/// was due, not from when it was applied, so an interrupt that delays one step
+/// doesn't shift the ones after it.
+fn play_waveform(
+    waveform: &waveform::Waveform,
+    mut now:  impl FnMut() -> u32,
+    pin_5:    &mut GpioPin<PIO0_20, Output>,
+    red:      &mut GpioPin<PIO1_2, Output>,
+    cts:      &mut GpioPin<PIO0_8, Output>,
+) {
+    let ticks_per_us = REFERENCE_TIMER_HZ / 1_000_000;
+
+    let mut due = now();
+    for step in waveform.steps() {
+        let delay = step.delay.0.wrapping_mul(ticks_per_us);
+        while now().wrapping_sub(due) < delay {}
+        due = due.wrapping_add(delay);
+
+        for &pin in &[OutputPin::Pin5, OutputPin::Cts, OutputPin::Red] {
+            if let Some(level) = step.level(pin as u8) {
+                set_output(pin::SetLevel { pin, level }, pin_5, red, cts);
+            }
+        }
+    }
+}
+
+/// Set the levels of a group of the pins that the host can control
+///
+/// The HAL can only set one pin at a time, so this uses the masked port
+/// registers of the GPIO peripheral. Bit `n` of `mask` and `value` stands for
+/// the `OutputPin` with the value `n`. Takes the pins, to make sure they're not
+/// used at the same time.
+fn set_output_group(
+    mask:   u32,
+    value:  u32,
+    _pin_5: &mut GpioPin<PIO0_20, Output>,
+    _red:   &mut GpioPin<PIO1_2, Output>,
+    _cts:   &mut GpioPin<PIO0_8, Output>,
+) {
+    let mut port_masks  = [0; 2];
+    let mut port_values = [0; 2];
+    for (i, &(port, pin)) in OUTPUT_PINS.iter().enumerate() {
+        if mask & 1 << i == 0 {
+            continue;
+        }
+
+        port_masks[port] |= 1 << pin;
+        if value & 1 << i != 0 {
+            port_values[port] |= 1 << pin;
+        }
+    }
+
+    // Sound, as the HAL doesn't use the masked port registers, and the mask
+    // only lets through the pins we own.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    // Both ports are written right after each other, without an interrupt in
+    // between.
+    interrupt::free(|_| {
+        let ports = gpio.mask.iter().zip(gpio.mpin.iter());
+        for (i, (mask, mpin)) in ports.enumerate() {
+            if port_masks[i] == 0 {
+                continue;
+            }
+
+            // Pins whose bit is set in MASK are not affected by MPIN.
+            mask.write(|w| unsafe { w.maskp().bits(!port_masks[i]) });
+            mpin.write(|w| unsafe { w.mportp().bits(port_values[i]) });
+        }
+    });
+}
+
+/// Invert one of the pins that the host can control, for a short time
+///
+/// The pulse lasts `width`, rounded down to whole cycles of the system clock,
+/// plus the few cycles it takes to write to the GPIO peripheral. Interrupts are
+/// disabled in the meantime, so they can't stretch it. Takes the pins, to make
+/// sure they're not used at the same time.
+///
+/// # Panics
+///
+/// Panics, if `width` is longer than `PULSE_MAX_NS`.
+fn send_pulse(
+    pin:    OutputPin,
+    width:  Nanoseconds,
+    _pin_5: &mut GpioPin<PIO0_20, Output>,
+    _red:   &mut GpioPin<PIO1_2, Output>,
+    _cts:   &mut GpioPin<PIO0_8, Output>,
+) {
+    assert!(width.0 <= PULSE_MAX_NS, "Pulse too long");
+
+    let (port, number) = OUTPUT_PINS[pin as usize];
+    let cycles =
+        u64::from(width.0) * u64::from(SYSTEM_CLOCK_HZ) / 1_000_000_000;
+
+    // Sound, as we have exclusive access to the pins, and writing to the NOT
+    // register only affects the pin whose bit is set.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    interrupt::free(|_| {
+        gpio.not[port].write(|w| unsafe { w.notp().bits(1 << number) });
+        if cycles > 0 {
+            asm::delay(cycles as u32);
+        }
+        gpio.not[port].write(|w| unsafe { w.notp().bits(1 << number) });
+    });
+}
+
+/// Read the levels of a group of the pins that the host can read
+///
+/// Bit `n` of `mask` stands for the `InputPin` with the value `n`. Returns the
+/// pins in `mask` that are high.
+fn read_input_group(mask: u32) -> u32 {
+    // Sound, as we're only reading the pin registers.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    let ports = interrupt::free(|_| {
+        [gpio.pin[0].read().port().bits(), gpio.pin[1].read().port().bits()]
+    });
+
+    let mut levels = 0;
+    for (i, &(port, pin)) in INPUT_PINS.iter().enumerate() {
+        if mask & 1 << i != 0 && ports[port] & 1 << pin != 0 {
+            levels |= 1 << i;
+        }
+    }
+
+    levels
+}
+
+/// Set one of the pins that the host can control
+fn set_output(
+    set_level: pin::SetLevel<OutputPin>,
+    pin_5:     &mut GpioPin<PIO0_20, Output>,
+    red:       &mut GpioPin<PIO1_2, Output>,
+    cts:       &mut GpioPin<PIO0_8, Output>,
+) {
+    match (set_level.pin, set_level.level) {
+        (OutputPin::Pin5, pin::Level::High) => pin_5.set_high(),
+        (OutputPin::Pin5, pin::Level::Low)  => pin_5.set_low(),
+        (OutputPin::Red,  pin::Level::High) => red.set_high(),
+        (OutputPin::Red,  pin::Level::Low)  => red.set_low(),
+        (OutputPin::Cts,  pin::Level::High) => cts.set_high(),
+        (OutputPin::Cts,  pin::Level::Low)  => cts.set_low(),
+    }
+}
+
+
+/// Timestamps the rising edges of the reference clock input
+///
+/// Uses the capture function of CTIMER0, so the timestamps are taken by the
+/// hardware, and don't depend on interrupt latency.
+pub struct Reference {
+    timer: CTIMER0,
+    edges: u32,
+    first: Option<u32>,
+    last:  Option<u32>,
+}
+
+impl Reference {
+    fn new(timer: CTIMER0) -> Self {
+        timer.ccr.write(|w| w.cap0re().set_bit().cap0i().set_bit());
+
+        Self {
+            timer,
+            edges: 0,
+            first: None,
+            last:  None,
+        }
+    }
+
+    /// Record the timestamp of an edge
+    ///
+    /// This should be called directly from the interrupt handler.
+    fn handle_interrupt(&mut self) {
+        let timestamp = self.timer.cr[0].read().cap().bits();
+        self.timer.ir.write(|w| w.cr0int().set_bit());
+
+        self.edges = self.edges.wrapping_add(1);
+        self.first.get_or_insert(timestamp);
+        self.last = Some(timestamp);
+    }
+
+    fn reset(&mut self) {
+        self.edges = 0;
+        self.first = None;
+        self.last  = None;
+    }
+
+    /// The current value of the timer
+    fn now(&self) -> u32 {
+        self.timer.tc.read().tcval().bits()
+    }
+
+    fn timestamps(&self) -> reference::Timestamps {
+        reference::Timestamps {
+            edges:    self.edges,
+            first:    self.first,
+            last:     self.last,
+            now:      self.now(),
+            timer_hz: REFERENCE_TIMER_HZ,
+        }
+    }
+}
+
+
+/// Monitors the target's TX line
+///
+/// Uses USART4, which only receives. Bytes are timestamped in the interrupt
+/// handler, using the timer of `Reference`, and queued for the idle loop.
+pub struct Monitor {
+    usart: USART4,
+    queue: spsc::Producer<'static, monitor::Byte, MONITOR_QUEUE_CAP>,
+}
+
+impl Monitor {
+    fn new(
+        usart: USART4,
+        queue: spsc::Producer<'static, monitor::Byte, MONITOR_QUEUE_CAP>,
+    )
+        -> Self
+    {
+        let mut monitor = Self {
+            usart,
+            queue,
+        };
+        monitor.stop();
+
+        monitor
+    }
+
+    /// Start monitoring, using the provided baud rate
+    fn start(&mut self, baud: u32) {
+        self.stop();
+
+        let brgval = firmware_lib::usart::brgval(
+            USART_CLOCK_HZ,
+            USART_OVERSAMPLING,
+            baud,
+        );
+
+        self.usart.brg.write(|w| unsafe { w.brgval().bits(brgval) });
+        self.usart.cfg.modify(|_, w| w.enable().enabled());
+        self.usart.intenset.write(|w| w.rxrdyen().set_bit());
+    }
+
+    fn stop(&mut self) {
+        self.usart.intenclr.write(|w| w.rxrdyclr().set_bit());
+        self.usart.cfg.modify(|_, w| w.enable().disabled());
+    }
+
+    /// Queue the received byte
+    ///
+    /// This should be called directly from the interrupt handler.
+    fn handle_interrupt(&mut self, timestamp: u32) {
+        while self.usart.stat.read().rxrdy().bit_is_set() {
+            let value = self.usart.rxdat.read().rxdat().bits() as u8;
+
+            self.queue.enqueue(monitor::Byte { value, timestamp })
+                .expect("Monitor queue is full");
+        }
+    }
+}
+
+
+/// Watches the monitored data for a pattern
+#[derive(Default)]
+struct Pattern {
+    pattern: Vec<u8, { monitor::PATTERN_CAP }>,
+    recent:  [u8; monitor::PATTERN_CAP],
+    seen:    usize,
+}
+
+impl Pattern {
+    /// Create a pattern that watches for `data`
+    ///
+    /// If `data` is empty or too long, the pattern never matches.
+    fn new(data: &[u8]) -> Self {
+        Self {
+            pattern: Vec::from_slice(data).unwrap_or_default(),
+            .. Self::default()
+        }
+    }
+
+    /// Add a byte to the recent data
+    ///
+    /// Returns `true`, if the recent data ends with the pattern.
+    fn push(&mut self, value: u8) -> bool {
+        self.recent.copy_within(1.., 0);
+        self.recent[monitor::PATTERN_CAP - 1] = value;
+        self.seen = (self.seen + 1).min(monitor::PATTERN_CAP);
+
+        let len = self.pattern.len();
+        len > 0
+            && self.seen >= len
+            && self.recent[monitor::PATTERN_CAP - len..] == self.pattern[..]
+    }
+}
+
+
+/// Pauses the target's USART transfer, by deasserting CTS for a while
+///
+/// Works with the timestamps of the timer of `Reference`.
+struct CtsThrottle {
+    /// The number of bytes to receive, before CTS is deasserted
+    after: u32,
+
+    /// How long CTS stays deasserted, in ticks of the timer
+    duration: u32,
+
+    throttle: UsartThrottle,
+}
+
+impl CtsThrottle {
+    fn new(after: u32, duration: Microseconds) -> Self {
+        Self {
+            after,
+            duration: duration.0 * (REFERENCE_TIMER_HZ / 1_000_000),
+            throttle: UsartThrottle {
+                timer_hz: REFERENCE_TIMER_HZ,
+                .. UsartThrottle::default()
+            },
+        }
+    }
+
+    /// Record data that was received at `now`
+    fn receive(&mut self, bytes: u32, now: u32) {
+        let throttle = &mut self.throttle;
+        throttle.bytes += bytes;
+
+        match (throttle.deasserted, throttle.asserted) {
+            (None, _) => {
+                throttle.last_before = Some(now);
+            }
+            (Some(_), None) => {
+                throttle.bytes_paused += bytes;
+            }
+            (Some(_), Some(_)) => {
+                throttle.first_after.get_or_insert(now);
+            }
+        }
+    }
+
+    /// Deassert or assert CTS, once it's time to
+    fn update(&mut self, now: u32, cts: &mut GpioPin<PIO0_8, Output>) {
+        let throttle = &mut self.throttle;
+
+        match (throttle.deasserted, throttle.asserted) {
+            (None, _) if throttle.bytes >= self.after => {
+                cts.set_high();
+                throttle.deasserted = Some(now);
+            }
+            (Some(deasserted), None)
+                if now.wrapping_sub(deasserted) >= self.duration =>
+            {
+                cts.set_low();
+                throttle.asserted = Some(now);
+            }
+            _ => {}
+        }
+    }
+}
+
+
+/// Streams a pattern to the target, and checks what the target echoes back
+struct UsartStream {
+    pattern: Vec<u8, STREAM_PATTERN_CAP>,
+
+    /// The number of bytes that are still to be sent
+    remaining: u32,
+
+    report: UsartStreamReport,
+}
+
+impl UsartStream {
+    /// Create a stream of `bytes` bytes, repeating `pattern`
+    ///
+    /// If `pattern` is empty or too long, nothing is sent.
+    fn new(pattern: &[u8], bytes: u32) -> Self {
+        let pattern: Vec<_, STREAM_PATTERN_CAP> =
+            Vec::from_slice(pattern).unwrap_or_default();
+        let remaining = if pattern.is_empty() { 0 } else { bytes };
+
+        Self {
+            pattern,
+            remaining,
+            report: UsartStreamReport::default(),
+        }
+    }
+
+    /// Take the next bytes to send
+    ///
+    /// Returns no more than `STREAM_CHUNK` bytes, and nothing, once the whole
+    /// stream has been sent.
+    fn next_chunk(&mut self) -> &[u8] {
+        if self.remaining == 0 {
+            return &[];
+        }
+
+        let start = self.report.sent as usize % self.pattern.len();
+        let len   = (self.pattern.len() - start)
+            .min(STREAM_CHUNK)
+            .min(self.remaining as usize);
+
+        self.report.sent += len as u32;
+        self.remaining   -= len as u32;
+
+        &self.pattern[start..start + len]
+    }
+
+    /// Compare received data to the stream
+    fn receive(&mut self, data: &[u8]) {
+        let len = self.pattern.len();
+        for &value in data {
+            let position = self.report.received as usize;
+            if len == 0 || self.pattern[position % len] != value {
+                self.report.mismatches += 1;
+            }
+            self.report.received += 1;
+        }
+    }
+}
+
+
+/// The I2C slave that the target's I2C master talks to
+///
+/// Emulates a device with a register map, as described in the documentation
+/// of the `i2c` module of the protocol.
+pub struct I2cSlave {
+    config:    SlaveConfig,
+    registers: [u8; i2c_slave::REGISTERS],
+    pointer:   u8,
+
+    /// The index of the next byte to receive in the current write
+    index: usize,
+
+    /// Whether a transaction with the slave is in progress
+    selected: bool,
+
+    log: TransactionLog,
+}
+
+impl I2cSlave {
+    fn new() -> Self {
+        Self {
+            config: SlaveConfig {
+                address: I2C_ADDRESS,
+                nack:    Nack::None,
+                stretch: Microseconds(0),
+            },
+            registers: [0; i2c_slave::REGISTERS],
+            pointer:   0,
+            index:     0,
+            selected:  false,
+            log:       TransactionLog::default(),
+        }
+    }
+
+    /// Apply a new configuration, and reset the register map
+    fn configure(&mut self, config: SlaveConfig) {
+        // Sound, as the HAL only writes the address while enabling slave
+        // mode. All 7-bit addresses are valid values for this field.
+        let i2c = unsafe { &*I2C0::ptr() };
+        i2c.slvadr[0].write(|w| {
+            w.sadisable().enabled();
+            unsafe { w.slvadr().bits(config.address & 0x7f) }
+        });
+
+        *self = Self {
+            config,
+            .. Self::new()
+        };
+    }
+
+    fn write_registers(&mut self, register: u8, data: &[u8]) {
+        for (i, &value) in data.iter().enumerate() {
+            let register = register.wrapping_add(i as u8);
+            self.registers[register as usize] = value;
+        }
+    }
+
+    fn read_registers(&self, register: u8, data: &mut [u8]) {
+        for (i, value) in data.iter_mut().enumerate() {
+            let register = register.wrapping_add(i as u8);
+            *value = self.registers[register as usize];
+        }
+    }
+
+    /// Take the transaction log, leaving an empty one in its place
+    fn take_log(&mut self) -> TransactionLog {
+        core::mem::take(&mut self.log)
+    }
+
+    /// Start a new transaction
+    ///
+    /// Returns whether to acknowledge the address.
+    fn address_matched(&mut self) -> bool {
+        self.log.starts += 1;
+        if self.selected {
+            self.log.restarts += 1;
+        }
+
+        // A slave that doesn't acknowledge its address isn't selected, and
+        // won't see the end of the transaction.
+        let ack = self.config.nack != Nack::Address;
+
+        self.selected = ack;
+        self.index    = 0;
+        ack
+    }
+
+    /// End the current transaction, after a stop condition
+    fn deselected(&mut self) {
+        if self.selected {
+            self.log.stops += 1;
+        }
+
+        self.selected = false;
+    }
+
+    /// Handle a byte written by the master
+    ///
+    /// Returns whether to acknowledge the byte.
+    fn receive(&mut self, data: u8) -> bool {
+        let index = self.index;
+        self.index += 1;
+
+        if let Nack::Data(nack) = self.config.nack {
+            if index == nack as usize {
+                return false;
+            }
+        }
+
+        if index == 0 {
+            self.pointer = data;
+        }
+        else {
+            self.registers[self.pointer as usize] = data;
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+
+        true
+    }
+
+    /// Provide the next byte read by the master
+    fn transmit(&mut self) -> u8 {
+        let data = self.registers[self.pointer as usize];
+        self.pointer = self.pointer.wrapping_add(1);
+        data
+    }
+
+    /// Stretch the clock for the configured time
+    fn stretch(&self) {
+        // The system clock runs at the same frequency as the reference timer.
+        let cycles_per_us = REFERENCE_TIMER_HZ / 1_000_000;
+        asm::delay(self.config.stretch.0 * cycles_per_us);
+    }
+}
+
+
+/// The SPI slave that the target's SPI master talks to
+///
+/// Sends the response that the host has loaded, and records what it receives,
+/// as described in the documentation of the `spi` module of the protocol.
+pub struct SpiSlave {
+    spi:      SPI<SPI0, Enabled<spi::Slave>>,
+    response: Vec<u8, { spi_slave::BUF_CAP }>,
+    received: Vec<u8, { spi_slave::BUF_CAP }>,
+
+    /// The index of the next byte of the response to load into the transmitter
+    index: usize,
+}
+
+impl SpiSlave {
+    fn new(spi: SPI<SPI0, Enabled<spi::Slave>>) -> Self {
+        // The HAL has already loaded `0xff` into the transmitter, which is
+        // what an empty response starts with.
+        Self {
+            spi,
+            response: Vec::new(),
+            received: Vec::new(),
+            index:    1,
+        }
+    }
+
+    /// Replace the response, starting with the next transaction
+    fn load_response(&mut self, response: &[u8]) {
+        self.response.clear();
+        self.response.extend_from_slice(response)
+            .expect("SPI response too long");
+
+        self.reset();
+    }
+
+    /// The data received in the most recent transaction
+    fn received(&self) -> &[u8] {
+        &self.received
+    }
+
+    fn handle_interrupt(&mut self) {
+        if self.spi.is_slave_select_asserted() {
+            self.received.clear();
+        }
+        if self.spi.is_ready_to_receive() {
+            let data = self.spi.receive().unwrap();
+            self.received.push(data).ok();
+
+            // The byte in the transmitter has just been sent. What we load now
+            // is sent while the master clocks in the next byte.
+            let next = self.next();
+            block!(self.spi.transmit(next))
+                .unwrap();
+        }
+        if self.spi.is_slave_select_deasserted() {
+            self.reset();
+        }
+    }
+
+    fn next(&mut self) -> u8 {
+        let data = self.response.get(self.index).copied().unwrap_or(0xff);
+        self.index += 1;
+        data
+    }
+
+    /// Transfer data as the master, then return to slave mode
+    ///
+    /// The HAL can't switch an enabled SPI peripheral between master and slave
+    /// mode, so this is done through the registers. The SPI interrupt must not
+    /// be handled while this runs, which holding the lock ensures.
+    fn transfer_as_master(&mut self, data: &[u8], received: &mut [u8]) {
+        // Sound, as we own the peripheral, and switch it back to slave mode
+        // before returning.
+        let spi = unsafe { &*SPI0::ptr() };
+
+        // 100 kHz, from the 12 MHz IOSC. Slow enough for a target that polls
+        // the peripheral.
+        spi.div.write(|w| unsafe { w.divval().bits(119) });
+        spi.cfg.modify(|_, w| w.enable().disabled());
+        spi.cfg.modify(|_, w| w.master().master_mode());
+        spi.cfg.modify(|_, w| w.enable().enabled());
+
+        for (i, (&tx, rx)) in data.iter().zip(received).enumerate() {
+            let last = i == data.len() - 1;
+
+            while spi.stat.read().txrdy().bit_is_clear() {}
+            spi.txdatctl.write(|w| {
+                w.txssel0_n().txssel0_n_0();
+                w.eot().bit(last);
+                w.len().bits(7);
+                unsafe { w.txdat().bits(tx.into()) }
+            });
+
+            while spi.stat.read().rxrdy().bit_is_clear() {}
+            *rx = spi.rxdat.read().rxdat().bits() as u8;
+        }
+
+        while spi.stat.read().mstidle().bit_is_clear() {}
+
+        // Restore the slave configuration. Without this, the next write to
+        // TXDAT would still end the transfer.
+        spi.txctl.write(|w| unsafe { w.len().bits(7) });
+        spi.cfg.modify(|_, w| w.enable().disabled());
+        spi.cfg.modify(|_, w| w.master().slave_mode());
+        spi.stat.write(|w| w.ssa().set_bit().ssd().set_bit());
+
+        // Re-enables the peripheral.
+        self.reset();
+    }
+
+    /// Load the first byte of the response, for the next transaction
+    ///
+    /// At the end of a transaction, the transmitter still holds the byte that
+    /// would have come after the last one. Disabling the peripheral resets it,
+    /// which discards that byte.
+    fn reset(&mut self) {
+        // Sound, as we own the peripheral, and only toggle the enable bit.
+        // Its configuration stays as it was.
+        let spi = unsafe { &*SPI0::ptr() };
+        spi.cfg.modify(|_, w| w.enable().disabled());
+        spi.cfg.modify(|_, w| w.enable().enabled());
+
+        self.index = 0;
+        let first = self.next();
+        block!(self.spi.transmit(first))
+            .unwrap();
+    }
+}
+
+
+/// Measures the period and duty cycle of the signal on a pin
+///
+/// Works with the timestamps that the pin interrupt handlers take, using the
+/// timer of `Reference`.
+#[derive(Debug)]
+struct PinMonitor {
+    signal:  pin::Signal<InputPin>,
+    rising:  Option<u32>,
+    falling: Option<u32>,
+}
+
+impl PinMonitor {
+    fn new(pin: InputPin) -> Self {
+        Self {
+            signal: pin::Signal {
+                pin,
+                periods:      0,
+                period_min:   u32::MAX,
+                period_max:   0,
+                period_total: 0,
+                high_total:   0,
+                timer_hz:     REFERENCE_TIMER_HZ,
+            },
+            rising:  None,
+            falling: None,
+        }
+    }
+
+    /// Process an edge, given the level of the pin after it
+    fn edge(&mut self, level: pin::Level, timestamp: u32) {
+        match level {
+            pin::Level::High => {
+                // A period is only complete, if we've seen the falling edge
+                // since the previous rising edge.
+                if let (Some(rising), Some(falling)) =
+                    (self.rising, self.falling)
+                {
+                    let period = timestamp.wrapping_sub(rising);
+                    let high   = falling.wrapping_sub(rising);
+
+                    let signal = &mut self.signal;
+                    signal.periods      += 1;
+                    signal.period_min    = signal.period_min.min(period);
+                    signal.period_max    = signal.period_max.max(period);
+                    signal.period_total += period as u64;
+                    signal.high_total   += high as u64;
+                }
+
+                self.rising  = Some(timestamp);
+                self.falling = None;
+            }
+            pin::Level::Low => {
+                if self.rising.is_some() {
+                    self.falling = Some(timestamp);
+                }
             }
         }
     }
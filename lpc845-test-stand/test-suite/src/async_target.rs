@@ -0,0 +1,1363 @@
+//! Async variant of `Target`, based on tokio
+
+
+use std::{
+    convert::TryFrom,
+    time::Duration,
+};
+
+use lpc845_messages::{
+    Capabilities,
+    DmaMode,
+    FLASH_BUF_CAP,
+    FlashInfo,
+    HostToTarget,
+    I2C_BUF_CAP,
+    IrqLatencies,
+    IrqState,
+    SPI_BUF_CAP,
+    SleepMode,
+    TargetToHost,
+    UsartError,
+    UsartMode,
+    UsartWakeup,
+    WakeupSource,
+    checksum::Checksum,
+    compress::Compression,
+    event,
+    pin,
+    trigger,
+    units::Millivolts,
+    usart,
+};
+
+use host_lib::{
+    config::Timeouts,
+    conn::{
+        self,
+        AsyncConn,
+    },
+    log,
+    measurement::Capture,
+    pin::ReadLevelError,
+    target::{
+        HasTimeouts,
+        WithTimeouts,
+        buffer_overrun,
+        defmt_frames,
+        heartbeat,
+        log_record,
+        panic_report,
+    },
+};
+use tokio::time::{
+    self,
+    Instant,
+};
+
+use crate::target::{
+    ADC_REFERENCE,
+    AddressMatch,
+    HEARTBEAT_TIMEOUT,
+    Lpc845,
+    TargetCapabilitiesError,
+    TargetConfigureUsartError,
+    TargetDumpEventsError,
+    TargetFlashError,
+    TargetI2cError,
+    TargetIrqStateError,
+    TargetNegotiateChecksumError,
+    TargetNegotiateCompressionError,
+    TargetPinInterruptError,
+    TargetPinReadError,
+    TargetPingError,
+    TargetRequestError,
+    TargetRunIrqWorkloadError,
+    TargetSetIrqPriorityError,
+    TargetSetPinHighError,
+    TargetSetPinLowError,
+    TargetSleepError,
+    TargetSpiError,
+    TargetSpiSlaveError,
+    TargetUsartSendError,
+    TargetUsartWaitError,
+    TargetUsartWakeupError,
+    TargetVersionError,
+    TargetWaitForAddressError,
+    Version,
+    Wakeup,
+};
+
+
+/// The async connection to the test target
+///
+/// Provides `async` versions of the methods of [`Target`], for use in async
+/// test harnesses. Methods that return a guard (`start_timer_interrupt`,
+/// `start_pwm_signal`), or that [`TestStand`] uses to check the target
+/// (`expect_fault`, `quiesce`), are only available on [`Target`].
+///
+/// [`Target`]: crate::target::Target
+/// [`TestStand`]: crate::TestStand
+pub struct AsyncTarget {
+    conn:     AsyncConn,
+    timeouts: Timeouts,
+}
+
+impl AsyncTarget {
+    /// Create an instance of `AsyncTarget`
+    ///
+    /// Open `conn` using the `target` path from the test stand configuration.
+    pub fn new(mut conn: AsyncConn) -> Self {
+        conn.set_log_filter(log_record::<Lpc845>);
+        conn.set_defmt_filter(defmt_frames::<Lpc845>);
+        conn.set_overrun_filter(buffer_overrun::<Lpc845>);
+        conn.set_panic_filter(panic_report::<Lpc845>);
+        conn.set_heartbeat_filter(heartbeat::<Lpc845>, HEARTBEAT_TIMEOUT);
+
+        Self {
+            conn,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// The timeouts of the methods that don't take a timeout
+    ///
+    /// See `Target::timeouts`.
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Replace the timeouts of the methods that don't take a timeout
+    ///
+    /// See `Target::set_timeouts`. Use the `timeouts` section of the test
+    /// stand configuration.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Replace the timeouts for a single call
+    ///
+    /// See `Target::with_timeouts`.
+    pub fn with_timeouts(&mut self, f: impl FnOnce(Timeouts) -> Timeouts)
+        -> WithTimeouts<'_, Self>
+    {
+        WithTimeouts::new(self, f)
+    }
+
+    /// Take all log records that the target has sent so far
+    ///
+    /// See `Target::take_log`.
+    pub fn take_log(&mut self) -> Vec<log::Record> {
+        self.conn.take_log()
+    }
+
+    /// Negotiate the compression scheme for frames sent by the target
+    ///
+    /// See `Target::negotiate_compression`.
+    pub async fn negotiate_compression(&mut self,
+        compression: Compression,
+        timeout:     Duration,
+    )
+        -> Result<(), TargetNegotiateCompressionError>
+    {
+        self.conn
+            .send(&HostToTarget::NegotiateCompression(compression)).await
+            .map_err(|err| TargetNegotiateCompressionError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetNegotiateCompressionError::Receive(err))?;
+
+        match message {
+            TargetToHost::CompressionNegotiated(negotiated) => {
+                self.conn.set_compression(negotiated);
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetNegotiateCompressionError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Negotiate the checksum for frames in both directions
+    ///
+    /// See `Target::negotiate_checksum`.
+    pub async fn negotiate_checksum(&mut self,
+        checksum: Checksum,
+        timeout:  Duration,
+    )
+        -> Result<(), TargetNegotiateChecksumError>
+    {
+        self.conn
+            .send(&HostToTarget::NegotiateChecksum(checksum)).await
+            .map_err(|err| TargetNegotiateChecksumError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetNegotiateChecksumError::Receive(err))?;
+
+        match message {
+            TargetToHost::ChecksumNegotiated(negotiated) => {
+                self.conn.set_checksum(negotiated);
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetNegotiateChecksumError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which functionality it supports
+    pub async fn capabilities(&mut self, timeout: Duration)
+        -> Result<Capabilities, TargetCapabilitiesError>
+    {
+        self.conn
+            .send(&HostToTarget::GetCapabilities).await
+            .map_err(|err| TargetCapabilitiesError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetCapabilitiesError::Receive(err))?;
+
+        match message {
+            TargetToHost::Capabilities(capabilities) => {
+                Ok(capabilities)
+            }
+            message => {
+                Err(
+                    TargetCapabilitiesError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which version of the firmware it runs
+    pub async fn version(&mut self, timeout: Duration)
+        -> Result<Version, TargetVersionError>
+    {
+        self.conn
+            .send(&HostToTarget::GetVersion).await
+            .map_err(|err| TargetVersionError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetVersionError::Receive(err))?;
+
+        match message {
+            TargetToHost::Version {
+                protocol,
+                firmware_hash,
+                hal_version,
+                features,
+            } => {
+                Ok(
+                    Version {
+                        protocol,
+                        firmware_hash: firmware_hash.to_owned(),
+                        hal_version:   hal_version.to_owned(),
+                        features:      features
+                            .split(',')
+                            .filter(|feature| !feature.is_empty())
+                            .map(|feature| feature.to_owned())
+                            .collect(),
+                    }
+                )
+            }
+            message => {
+                Err(
+                    TargetVersionError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which interrupts are enabled and pending
+    pub async fn irq_state(&mut self, timeout: Duration)
+        -> Result<IrqState, TargetIrqStateError>
+    {
+        self.conn
+            .send(&HostToTarget::GetIrqState).await
+            .map_err(|err| TargetIrqStateError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetIrqStateError::Receive(err))?;
+
+        match message {
+            TargetToHost::IrqState(state) => {
+                Ok(state)
+            }
+            message => {
+                Err(
+                    TargetIrqStateError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target for the events it has recorded recently
+    ///
+    /// See [`Target::dump_events`](crate::target::Target::dump_events).
+    pub async fn dump_events(&mut self, timeout: Duration)
+        -> Result<Vec<event::Event>, TargetDumpEventsError>
+    {
+        self.conn
+            .send(&HostToTarget::DumpEvents).await
+            .map_err(|err| TargetDumpEventsError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetDumpEventsError::Receive(err))?;
+
+        match message {
+            TargetToHost::Events(events) => {
+                Ok(events.as_slice().to_vec())
+            }
+            message => {
+                Err(
+                    TargetDumpEventsError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Change the priority of an interrupt on the target
+    ///
+    /// See `Target::set_irq_priority`.
+    pub async fn set_irq_priority(&mut self,
+        irq:      u8,
+        priority: u8,
+        timeout:  Duration,
+    )
+        -> Result<(), TargetSetIrqPriorityError>
+    {
+        self.conn
+            .send(&HostToTarget::SetIrqPriority { irq, priority }).await
+            .map_err(|err| TargetSetIrqPriorityError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetSetIrqPriorityError::Receive(err))?;
+
+        match message {
+            TargetToHost::IrqPrioritySet => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetSetIrqPriorityError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Run a workload on the target that measures interrupt latency
+    ///
+    /// See `Target::run_irq_workload`.
+    pub async fn run_irq_workload(&mut self,
+        irqs:    &[u8],
+        rounds:  u16,
+        timeout: Duration,
+    )
+        -> Result<IrqLatencies, TargetRunIrqWorkloadError>
+    {
+        let irqs = irqs.iter().fold(0, |mask, irq| mask | 1 << irq);
+
+        self.conn
+            .send(&HostToTarget::RunIrqWorkload { irqs, rounds }).await
+            .map_err(|err| TargetRunIrqWorkloadError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetRunIrqWorkloadError::Receive(err))?;
+
+        match message {
+            TargetToHost::IrqLatencies(latencies) => {
+                Ok(latencies)
+            }
+            message => {
+                Err(
+                    TargetRunIrqWorkloadError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Check that the target is still responsive
+    pub async fn ping(&mut self, timeout: Duration)
+        -> Result<(), TargetPingError>
+    {
+        self.conn
+            .send(&HostToTarget::Ping).await
+            .map_err(|err| TargetPingError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetPingError::Receive(err))?;
+
+        match message {
+            TargetToHost::Pong => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetPingError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target about its flash memory
+    ///
+    /// See `Target::flash_info`.
+    pub async fn flash_info(&mut self, timeout: Duration)
+        -> Result<FlashInfo, TargetRequestError>
+    {
+        self.conn
+            .send(&HostToTarget::GetFlashInfo).await
+            .map_err(|err| TargetRequestError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetRequestError::Receive(err))?;
+
+        match message {
+            TargetToHost::FlashInfo(flash_info) => {
+                Ok(flash_info)
+            }
+            message => {
+                Err(
+                    TargetRequestError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to read from the ADC
+    ///
+    /// See `Target::read_adc`.
+    pub async fn read_adc(&mut self, channel: u8, timeout: Duration)
+        -> Result<Millivolts, TargetRequestError>
+    {
+        self.conn
+            .send(&HostToTarget::ReadAdc { channel }).await
+            .map_err(|err| TargetRequestError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetRequestError::Receive(err))?;
+
+        match message {
+            TargetToHost::AdcValue(value) => {
+                Ok(Millivolts::from_adc(value, 12, ADC_REFERENCE))
+            }
+            message => {
+                Err(
+                    TargetRequestError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to set a GPIO pin high
+    pub async fn set_pin_high(&mut self, pin: pin::PortPin)
+        -> Result<(), TargetSetPinHighError>
+    {
+        let level = pin::Level::High;
+        self.conn
+            .send(&HostToTarget::SetPin(pin::SetLevel { pin, level })).await
+            .map_err(|err| TargetSetPinHighError(err))
+    }
+
+    /// Instruct the target to set a GPIO pin low
+    pub async fn set_pin_low(&mut self, pin: pin::PortPin)
+        -> Result<(), TargetSetPinLowError>
+    {
+        let level = pin::Level::Low;
+        self.conn
+            .send(&HostToTarget::SetPin(pin::SetLevel { pin, level })).await
+            .map_err(|err| TargetSetPinLowError(err))
+    }
+
+    /// Enable or disable the reporting of pin interrupts on the target
+    ///
+    /// See `Target::report_pin_interrupts`.
+    pub async fn report_pin_interrupts(&mut self,
+        enable:  bool,
+        timeout: Duration,
+    )
+        -> Result<(), TargetRequestError>
+    {
+        self.conn
+            .send(&HostToTarget::ReportPinInterrupts(enable)).await
+            .map_err(|err| TargetRequestError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetRequestError::Receive(err))?;
+
+        match message {
+            TargetToHost::PinInterruptsReported => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetRequestError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the target to report an interrupt of its input pin
+    ///
+    /// See `Target::wait_for_pin_interrupt`.
+    pub async fn wait_for_pin_interrupt(&mut self,
+        edge:    pin::Edge,
+        timeout: Duration,
+    )
+        -> Result<u32, TargetPinInterruptError>
+    {
+        self.wait_for_pin_capture(edge, timeout).await
+            .map(|capture| capture.handled)
+    }
+
+    /// Wait for the target to report an interrupt of its input pin
+    ///
+    /// See `Target::wait_for_pin_capture`.
+    pub async fn wait_for_pin_capture(&mut self,
+        edge:    pin::Edge,
+        timeout: Duration,
+    )
+        -> Result<Capture, TargetPinInterruptError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::PinInterrupt { .. })
+                )
+            })
+            .await
+            .map_err(|err| TargetPinInterruptError::Receive(err))?;
+
+        match message {
+            TargetToHost::PinInterrupt {
+                edge: actual,
+                timestamp,
+                captured,
+                ..
+            }
+                if actual == edge =>
+            {
+                Ok(Capture { captured, handled: timestamp })
+            }
+            message => {
+                Err(
+                    TargetPinInterruptError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to perform an action on the trigger's next edge
+    ///
+    /// See `Target::arm_on_trigger`.
+    pub async fn arm_on_trigger(&mut self,
+        action:  trigger::Action<pin::PortPin>,
+        timeout: Duration,
+    )
+        -> Result<(), TargetRequestError>
+    {
+        self.conn
+            .send(&HostToTarget::ArmOnTrigger(action)).await
+            .map_err(|err| TargetRequestError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetRequestError::Receive(err))?;
+
+        match message {
+            TargetToHost::TriggerArmed => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetRequestError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Indicates whether a GPIO pin is set high
+    pub async fn pin_is_high(&mut self, pin: pin::PortPin)
+        -> Result<bool, TargetPinReadError>
+    {
+        let level = self.read_pin(pin, self.timeouts.read()).await?;
+        Ok(level == pin::Level::High)
+    }
+
+    /// Indicates whether a GPIO pin is set low
+    pub async fn pin_is_low(&mut self, pin: pin::PortPin)
+        -> Result<bool, TargetPinReadError>
+    {
+        let level = self.read_pin(pin, self.timeouts.read()).await?;
+        Ok(level == pin::Level::Low)
+    }
+
+    /// Read the level of a pin, like `host_lib::pin::Pin::read_level` does
+    async fn read_pin(&mut self, pin: pin::PortPin, timeout: Duration)
+        -> Result<pin::Level, ReadLevelError>
+    {
+        // Wait for a bit, to give whatever event is expected to change the
+        // level some time to happen.
+        time::sleep(timeout).await;
+
+        self.conn
+            .send(&HostToTarget::ReadPin(pin::ReadLevel { pin })).await
+            .map_err(|err| ReadLevelError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| ReadLevelError::Receive(err))?;
+
+        match message {
+            TargetToHost::ReadPinResult(Some(result)) if result.pin == pin => {
+                Ok(result.level)
+            }
+            message => {
+                Err(
+                    ReadLevelError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Reconfigure the target's USART
+    ///
+    /// See `Target::configure_usart`.
+    pub async fn configure_usart(&mut self,
+        mode:    UsartMode,
+        config:  &usart::Config,
+        timeout: Duration,
+    )
+        -> Result<(), TargetConfigureUsartError>
+    {
+        self.conn
+            .send(&HostToTarget::ConfigureUsart { mode, config: *config })
+            .await
+            .map_err(|err| TargetConfigureUsartError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetConfigureUsartError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartConfigured => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetConfigureUsartError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to send this message via USART
+    pub async fn send_usart(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Regular).await
+    }
+
+    /// Instruct the target to send this message via USART using DMA
+    pub async fn send_usart_dma(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Dma).await
+    }
+
+    /// Instruct the target to send this message via synchronous USART
+    pub async fn send_usart_sync(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Sync).await
+    }
+
+    /// Instruct the target to send this message via USART with flow control
+    pub async fn send_usart_with_flow_control(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::FlowControl).await
+    }
+
+    async fn send_usart_inner(&mut self, data: &[u8], mode: UsartMode)
+        -> Result<(), TargetUsartSendError>
+    {
+        self.conn
+            .send(&HostToTarget::SendUsart { mode, data }).await
+            .map_err(|err| TargetUsartSendError(err))
+    }
+
+    /// Wait to receive the provided data via USART
+    ///
+    /// See `Target::wait_for_usart_rx`.
+    pub async fn wait_for_usart_rx(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Regular).await
+    }
+
+    /// Wait to receive the provided data via USART/DMA
+    pub async fn wait_for_usart_rx_dma(&mut self,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Dma).await
+    }
+
+    /// Wait to receive the provided data via synchronous USART
+    pub async fn wait_for_usart_rx_sync(&mut self,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Sync).await
+    }
+
+    /// Wait for the target to report an error, while receiving via USART
+    ///
+    /// See `Target::wait_for_usart_error`.
+    pub async fn wait_for_usart_error(&mut self, timeout: Duration)
+        -> Result<UsartError, TargetUsartWaitError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::UsartError { mode, .. })
+                        if mode == UsartMode::Regular
+                )
+            })
+            .await
+            .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartError { error, .. } => {
+                Ok(error)
+            }
+            message => {
+                Err(
+                    TargetUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    async fn wait_for_usart_rx_inner(&mut self,
+        data:          &[u8],
+        timeout:       Duration,
+        expected_mode: UsartMode,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        loop {
+            if buf.windows(data.len()).any(|window| window == data) {
+                return Ok(buf);
+            }
+            if start.elapsed() > timeout {
+                return Err(TargetUsartWaitError::Timeout);
+            }
+
+            // Other messages might arrive in the meantime. Leave them for
+            // whoever expects them.
+            let mut tmp = Vec::new();
+            let message = self.conn
+                .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                    matches!(
+                        conn::peek(frame),
+                        Some(TargetToHost::UsartReceive { mode, .. })
+                            if mode == expected_mode
+                    )
+                })
+                .await
+                .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+            match message {
+                TargetToHost::UsartReceive { mode, data, .. }
+                    if mode == expected_mode =>
+                {
+                    buf.extend(data)
+                }
+                message => {
+                    return Err(
+                        TargetUsartWaitError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    /// Instruct the target to sleep, until it receives data via USART
+    ///
+    /// See `Target::sleep_until_usart_rx`.
+    pub async fn sleep_until_usart_rx(&mut self, timeout: Duration)
+        -> Result<(), TargetUsartWakeupError>
+    {
+        self.conn
+            .send(&HostToTarget::SleepUntilUsartRx).await
+            .map_err(|err| TargetUsartWakeupError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetUsartWakeupError::Receive(err))?;
+
+        match message {
+            TargetToHost::Sleeping => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetUsartWakeupError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the target to wake up from USART activity
+    ///
+    /// See `Target::wait_for_usart_wakeup`.
+    pub async fn wait_for_usart_wakeup(&mut self, timeout: Duration)
+        -> Result<UsartWakeup, TargetUsartWakeupError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetUsartWakeupError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartWakeup(wakeup) => {
+                Ok(wakeup)
+            }
+            message => {
+                Err(
+                    TargetUsartWakeupError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to enter a low-power mode, until it is woken up
+    ///
+    /// See `Target::enter_sleep`.
+    pub async fn enter_sleep(&mut self,
+        mode:          SleepMode,
+        source:        WakeupSource,
+        sleep_timeout: Duration,
+        timeout:       Duration,
+    )
+        -> Result<(), TargetSleepError>
+    {
+        let timeout_ms = u32::try_from(sleep_timeout.as_millis())
+            .unwrap_or(u32::MAX);
+
+        self.conn
+            .send(&HostToTarget::EnterSleep {
+                mode,
+                wakeup_source: source,
+                timeout_ms,
+            })
+            .await
+            .map_err(|err| TargetSleepError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetSleepError::Receive(err))?;
+
+        match message {
+            TargetToHost::Sleeping => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetSleepError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the target to wake up from `enter_sleep`
+    ///
+    /// See `Target::wait_for_wakeup`.
+    pub async fn wait_for_wakeup(&mut self, timeout: Duration)
+        -> Result<Wakeup, TargetSleepError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::WokeUp { .. })
+                )
+            })
+            .await
+            .map_err(|err| TargetSleepError::Receive(err))?;
+
+        match message {
+            TargetToHost::WokeUp { cause, after_ms } => {
+                Ok(
+                    Wakeup {
+                        cause,
+                        after: Duration::from_millis(after_ms.into()),
+                    }
+                )
+            }
+            message => {
+                Err(
+                    TargetSleepError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Enable address matching
+    pub async fn wait_for_address(&mut self, address: u8)
+        -> Result<(), TargetWaitForAddressError>
+    {
+        self.conn
+            .send(&HostToTarget::WaitForAddress(address)).await
+            .map_err(|err| TargetWaitForAddressError(err))
+    }
+
+    /// Wait for the target to report that it has matched an address
+    ///
+    /// See `Target::wait_for_address_match`.
+    pub async fn wait_for_address_match(&mut self, timeout: Duration)
+        -> Result<AddressMatch, TargetUsartWaitError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::AddressMatched { .. })
+                )
+            })
+            .await
+            .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+        match message {
+            TargetToHost::AddressMatched { address, discarded } => {
+                Ok(AddressMatch { address, discarded })
+            }
+            message => {
+                Err(
+                    TargetUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Program `data` into the flash memory of the target, at `address`
+    ///
+    /// See `Target::flash_write`.
+    pub async fn flash_write(&mut self,
+        address: u32,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<(), TargetFlashError>
+    {
+        if data.len() > FLASH_BUF_CAP {
+            return Err(TargetFlashError::TooLong);
+        }
+
+        self.conn
+            .send(&HostToTarget::WriteFlash { address, data })
+            .await
+            .map_err(|err| TargetFlashError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .await
+            .map_err(|err| TargetFlashError::Receive(err))?;
+
+        match message {
+            TargetToHost::FlashWritten => {
+                Ok(())
+            }
+            TargetToHost::FlashError(err) => {
+                Err(TargetFlashError::Flash(err))
+            }
+            message => {
+                Err(
+                    TargetFlashError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Read `len` bytes from the flash memory of the target, at `address`
+    ///
+    /// See `Target::flash_read`.
+    pub async fn flash_read(&mut self,
+        address: u32,
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetFlashError>
+    {
+        if len > FLASH_BUF_CAP {
+            return Err(TargetFlashError::TooLong);
+        }
+
+        self.conn
+            .send(&HostToTarget::ReadFlash { address, len: len as u32 })
+            .await
+            .map_err(|err| TargetFlashError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .await
+            .map_err(|err| TargetFlashError::Receive(err))?;
+
+        match message {
+            TargetToHost::FlashRead(data) => {
+                Ok(data.to_vec())
+            }
+            TargetToHost::FlashError(err) => {
+                Err(TargetFlashError::Flash(err))
+            }
+            message => {
+                Err(
+                    TargetFlashError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Start an I2C transaction
+    ///
+    /// Writes `write` to the slave at the 7-bit `address`, then reads
+    /// `read_len` bytes from it, and returns those. Either part is skipped, if
+    /// it is empty. Both are limited to `I2C_BUF_CAP` bytes.
+    ///
+    /// Returns `TargetI2cError::Nack`, if the slave doesn't acknowledge, and
+    /// `TargetI2cError::Bus`, if the transaction fails for another reason.
+    pub async fn start_i2c_transaction(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(
+            address,
+            write,
+            read_len,
+            timeout,
+            DmaMode::Regular,
+        )
+            .await
+    }
+
+    /// Start an I2C/DMA transaction
+    ///
+    /// See `start_i2c_transaction`.
+    pub async fn start_i2c_transaction_dma(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(
+            address,
+            write,
+            read_len,
+            timeout,
+            DmaMode::Dma,
+        )
+            .await
+    }
+
+    async fn start_i2c_transaction_inner(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+        mode:     DmaMode,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        if address > 0x7f {
+            return Err(TargetI2cError::InvalidAddress);
+        }
+        if write.len() > I2C_BUF_CAP || read_len > I2C_BUF_CAP {
+            return Err(TargetI2cError::TooLong);
+        }
+
+        self.conn
+            .send(
+                &HostToTarget::StartI2cTransaction {
+                    mode,
+                    address,
+                    write,
+                    read_len: read_len as u16,
+                }
+            )
+            .await
+            .map_err(|err| TargetI2cError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetI2cError::Receive(err))?;
+
+        match message {
+            TargetToHost::I2cReply(reply) => {
+                Ok(reply.to_vec())
+            }
+            TargetToHost::I2cNack => {
+                Err(TargetI2cError::Nack)
+            }
+            TargetToHost::I2cError => {
+                Err(TargetI2cError::Bus)
+            }
+            message => {
+                Err(
+                    TargetI2cError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Start an SPI transaction
+    ///
+    /// Writes `write` to the slave, then reads `read_len` bytes from it, and
+    /// returns those. Either part is skipped, if it is empty. Both combined are
+    /// limited to `SPI_BUF_CAP` bytes.
+    pub async fn start_spi_transaction(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        self.start_spi_transaction_inner(
+            write,
+            read_len,
+            timeout,
+            DmaMode::Regular,
+        )
+            .await
+    }
+
+    /// Start an SPI/DMA transaction
+    ///
+    /// See `start_spi_transaction`.
+    pub async fn start_spi_transaction_dma(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        self.start_spi_transaction_inner(write, read_len, timeout, DmaMode::Dma)
+            .await
+    }
+
+    async fn start_spi_transaction_inner(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+        mode:     DmaMode,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        if write.len() + read_len > SPI_BUF_CAP {
+            return Err(TargetSpiError::TooLong);
+        }
+
+        self.conn
+            .send(
+                &HostToTarget::StartSpiTransaction {
+                    mode,
+                    write,
+                    read_len: read_len as u16,
+                }
+            )
+            .await
+            .map_err(|err| TargetSpiError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiReplyBuffer(reply) => {
+                Ok(reply.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to act as an SPI slave for one transaction
+    ///
+    /// See `Target::expect_spi_slave_transfer`.
+    pub async fn expect_spi_slave_transfer(&mut self,
+        response: &[u8],
+        timeout:  Duration,
+    )
+        -> Result<(), TargetSpiSlaveError>
+    {
+        if response.len() > SPI_BUF_CAP {
+            return Err(TargetSpiSlaveError::TooLong);
+        }
+
+        self.conn
+            .send(&HostToTarget::ExpectSpiSlaveTransfer { response }).await
+            .map_err(|err| TargetSpiSlaveError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetSpiSlaveError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiSlaveReady => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetSpiSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the target's SPI slave transaction to end
+    ///
+    /// See `Target::wait_for_spi_slave_transfer`.
+    pub async fn wait_for_spi_slave_transfer(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, TargetSpiSlaveError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetSpiSlaveError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiSlaveReceived(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+impl HasTimeouts for AsyncTarget {
+    fn timeouts_mut(&mut self) -> &mut Timeouts {
+        &mut self.timeouts
+    }
+}
@@ -0,0 +1,32 @@
+//! Interactive session with the test target
+//!
+//! Connects to the test stand, as configured in `test-stand.toml`, and starts
+//! an interactive session with the test target (see the `interactive` module).
+//! Run it from the test suite directory:
+//!
+//! ``` bash
+//! cargo run --bin interactive
+//! ```
+
+
+use std::io::{
+    self,
+    BufReader,
+};
+
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+fn main() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let stdout = io::stdout();
+    test_stand.target
+        .interactive()
+        .run(BufReader::new(io::stdin()), stdout.lock())?;
+
+    Ok(())
+}
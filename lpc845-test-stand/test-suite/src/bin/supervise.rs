@@ -0,0 +1,170 @@
+//! Run test cases under a supervisor
+//!
+//! Runs every test case in a process of its own, kills test cases that hang,
+//! and brings the test stand back into a known state after every test case
+//! that didn't pass (see `host_lib::supervisor`). The connections to the test
+//! nodes stay open for the whole run, and are lent to the test cases (see
+//! `host_lib::lease`). Pass the test binaries that
+//! `cargo test --no-run` prints, and run it from the test suite directory:
+//!
+//! ``` bash
+//! cargo test --no-run
+//! cargo run --bin supervise -- target/debug/deps/gpio-0123456789abcdef
+//! ```
+//!
+//! Writes a report for CI, if `report` is set in `test-stand.toml` (see
+//! `host_lib::report`).
+//!
+//! Exits with 1, if any test case didn't pass, and with 2, if the test stand
+//! couldn't be recovered.
+
+
+use std::{
+    env,
+    io,
+    path::PathBuf,
+    process,
+    thread,
+};
+
+use host_lib::{
+    Config,
+    lease::{
+        self,
+        LeaseError,
+    },
+    report,
+    supervisor::{
+        Report,
+        Supervisor,
+        SupervisorError,
+    },
+};
+
+use lpc845_test_suite::{
+    Error,
+    Result,
+    TestStand,
+};
+
+
+fn main() {
+    match run() {
+        Ok(report) => {
+            println!("\n{}", report);
+            if !report.passed() {
+                process::exit(1);
+            }
+        }
+        Err(SuperviseError::Setup(err)) => {
+            eprintln!("Error setting up test stand: {:?}", err);
+            process::exit(2);
+        }
+        Err(SuperviseError::Lease(err)) => {
+            eprintln!("Error serving connections: {:?}", err);
+            process::exit(2);
+        }
+        Err(SuperviseError::Run(err)) => {
+            eprintln!("Error: {:?}", err);
+            process::exit(2);
+        }
+        Err(SuperviseError::Report(err)) => {
+            eprintln!("Error writing report: {:?}", err);
+            process::exit(2);
+        }
+    }
+}
+
+fn run() -> std::result::Result<Report, SuperviseError> {
+    let binaries = env::args_os().skip(1).map(PathBuf::from);
+
+    // Connecting once up front downloads the firmware, if configured, and
+    // makes sure the test stand is usable at all.
+    recover()
+        .map_err(|err| SuperviseError::Setup(err))?;
+
+    let config = Config::read()
+        .map_err(|err| SuperviseError::Setup(err.into()))?;
+    let supervisor = Supervisor::new(config.timeouts.test());
+
+    // From here on, `recover` connects through the leases too, like the test
+    // cases do.
+    lease::serve(&config)
+        .map_err(|err| SuperviseError::Lease(err))?;
+
+    let mut report = Report::new();
+    for binary in binaries {
+        let first    = report.results.len();
+        let finished = supervisor.run(&binary, &mut report, recover);
+
+        for result in &report.results[first..] {
+            println!("{} {}", result.outcome, result.name);
+        }
+        finished
+            .map_err(|err| SuperviseError::Run(err))?;
+    }
+
+    // The configuration makes sure that `artifacts` is set, if `report` is.
+    if let (Some(format), Some(artifacts)) = (config.report, &config.artifacts)
+    {
+        let path = report::write(&report, format, artifacts)
+            .map_err(|err| SuperviseError::Report(err))?;
+        println!("Report written to {}", path.display());
+    }
+
+    Ok(report)
+}
+
+/// Bring the test stand back into a known state
+///
+/// Connecting to the test stand checks that the targets respond, and dropping
+/// the connection quiesces them. If they don't respond, they are reset through
+/// the debug probes from the `[flash]` table of `test-stand.toml`.
+fn recover() -> Result {
+    let err = match TestStand::new() {
+        Ok(_) => return Ok(()),
+        Err(err) => err,
+    };
+    println!("Resetting test stand: {:?}", err);
+
+    let config = Config::read()?;
+    reset(&config)?;
+    thread::sleep(config.timeouts.restart());
+
+    TestStand::new()?;
+
+    Ok(())
+}
+
+/// Reset the test nodes through the debug probes
+#[cfg(feature = "flash")]
+fn reset(config: &Config) -> Result {
+    for (node, settings) in &config.flash {
+        host_lib::flash::reset(node, settings)?;
+    }
+
+    Ok(())
+}
+
+/// Reset the test nodes through the debug probes
+///
+/// Without the `flash` feature, there's no way to do that. The test nodes only
+/// get some time to recover on their own.
+#[cfg(not(feature = "flash"))]
+fn reset(_: &Config) -> Result {
+    Ok(())
+}
+
+
+enum SuperviseError {
+    /// The test stand couldn't be set up, before running any test cases
+    Setup(Error),
+
+    /// The connections to the test nodes couldn't be served
+    Lease(LeaseError),
+
+    Run(SupervisorError<Error>),
+
+    /// The report couldn't be written, after running all test cases
+    Report(io::Error),
+}
@@ -1,20 +1,64 @@
 /// Test-suite specific error module
 
 
-use host_lib::assistant::AssistantError;
+use host_lib::{
+    assistant::AssistantError,
+    config::ConfigReadError,
+    payload::Mismatch,
+    report::MeasureError,
+};
+#[cfg(feature = "flash")]
+use host_lib::flash::DownloadError;
+use lpc845_messages::StoreError;
+use test_cases::gpio::GpioConformanceError;
 use super::{
+    interactive::InteractiveError,
     target::{
+        TargetCapabilitiesError,
+        TargetCaptError,
+        TargetConfigureUsartError,
+        TargetDriveSharedLineError,
+        TargetDumpEventsError,
+        TargetExpectFaultError,
+        TargetFlashError,
+        TargetGpioError,
         TargetI2cError,
+        TargetIrqStateError,
+        TargetMrtError,
+        TargetNegotiateChecksumError,
+        TargetNegotiateCompressionError,
+        TargetPinInterruptError,
         TargetPinReadError,
+        TargetPingError,
+        TargetQuiesceError,
+        TargetRequestError,
+        TargetResetError,
+        TargetResyncLinkError,
+        TargetRunIrqWorkloadError,
+        TargetSampleAdcError,
+        TargetSendPartialRequestError,
+        TargetSetDacValueError,
+        TargetSetIrqPriorityError,
         TargetSetPinHighError,
         TargetSetPinLowError,
+        TargetSleepError,
         TargetSpiError,
+        TargetSpiSlaveError,
+        TargetStartDacWaveformError,
+        TargetStartPwmSignalError,
         TargetStartTimerInterruptError,
+        TargetUsartEchoError,
         TargetUsartSendError,
+        TargetUsartThroughputError,
         TargetUsartWaitError,
+        TargetUsartWakeupError,
+        TargetVersionError,
         TargetWaitForAddressError,
     },
-    test_stand::TestStandInitError,
+    test_stand::{
+        TestStandFinishError,
+        TestStandInitError,
+    },
 };
 
 
@@ -26,15 +70,54 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Assistant(AssistantError),
+    Config(ConfigReadError),
+    #[cfg(feature = "flash")]
+    Download(DownloadError),
+    GpioConformance(GpioConformanceError<TargetGpioError>),
+    Interactive(InteractiveError),
+    Measure(MeasureError),
+    Payload(Mismatch),
+    Store(StoreError),
+    TargetCapabilities(TargetCapabilitiesError),
+    TargetCapt(TargetCaptError),
+    TargetConfigureUsart(TargetConfigureUsartError),
+    TargetDriveSharedLine(TargetDriveSharedLineError),
+    TargetDumpEvents(TargetDumpEventsError),
+    TargetExpectFault(TargetExpectFaultError),
+    TargetFlash(TargetFlashError),
     TargetI2c(TargetI2cError),
+    TargetIrqState(TargetIrqStateError),
+    TargetMrt(TargetMrtError),
+    TargetNegotiateChecksum(TargetNegotiateChecksumError),
+    TargetNegotiateCompression(TargetNegotiateCompressionError),
+    TargetPinInterrupt(TargetPinInterruptError),
     TargetPinRead(TargetPinReadError),
+    TargetPing(TargetPingError),
+    TargetQuiesce(TargetQuiesceError),
+    TargetRequest(TargetRequestError),
+    TargetReset(TargetResetError),
+    TargetResyncLink(TargetResyncLinkError),
+    TargetRunIrqWorkload(TargetRunIrqWorkloadError),
+    TargetSampleAdc(TargetSampleAdcError),
+    TargetSendPartialRequest(TargetSendPartialRequestError),
+    TargetSetDacValue(TargetSetDacValueError),
+    TargetSetIrqPriority(TargetSetIrqPriorityError),
     TargetSetPinHigh(TargetSetPinHighError),
     TargetSetPinLow(TargetSetPinLowError),
+    TargetSleep(TargetSleepError),
     TargetSpi(TargetSpiError),
+    TargetSpiSlave(TargetSpiSlaveError),
+    TargetStartDacWaveform(TargetStartDacWaveformError),
+    TargetStartPwmSignal(TargetStartPwmSignalError),
     TargetStartTimerInterrupt(TargetStartTimerInterruptError),
+    TargetUsartEcho(TargetUsartEchoError),
     TargetUsartSend(TargetUsartSendError),
+    TargetUsartThroughput(TargetUsartThroughputError),
     TargetUsartWait(TargetUsartWaitError),
+    TargetUsartWakeup(TargetUsartWakeupError),
+    TargetVersion(TargetVersionError),
     TargetWaitForAddress(TargetWaitForAddressError),
+    TestStandFinish(TestStandFinishError),
     TestStandInit(TestStandInitError),
 }
 
@@ -44,36 +127,241 @@ impl From<AssistantError> for Error {
     }
 }
 
+impl From<ConfigReadError> for Error {
+    fn from(err: ConfigReadError) -> Self {
+        Self::Config(err)
+    }
+}
+
+#[cfg(feature = "flash")]
+impl From<DownloadError> for Error {
+    fn from(err: DownloadError) -> Self {
+        Self::Download(err)
+    }
+}
+
+impl From<GpioConformanceError<TargetGpioError>> for Error {
+    fn from(err: GpioConformanceError<TargetGpioError>) -> Self {
+        Self::GpioConformance(err)
+    }
+}
+
+impl From<InteractiveError> for Error {
+    fn from(err: InteractiveError) -> Self {
+        Self::Interactive(err)
+    }
+}
+
+impl From<MeasureError> for Error {
+    fn from(err: MeasureError) -> Self {
+        Self::Measure(err)
+    }
+}
+
+impl From<Mismatch> for Error {
+    fn from(err: Mismatch) -> Self {
+        Self::Payload(err)
+    }
+}
+
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Self {
+        Self::Store(err)
+    }
+}
+
 impl From<TargetI2cError> for Error {
     fn from(err: TargetI2cError) -> Self {
         Self::TargetI2c(err)
     }
 }
 
+impl From<TargetCapabilitiesError> for Error {
+    fn from(err: TargetCapabilitiesError) -> Self {
+        Self::TargetCapabilities(err)
+    }
+}
+
+impl From<TargetCaptError> for Error {
+    fn from(err: TargetCaptError) -> Self {
+        Self::TargetCapt(err)
+    }
+}
+
+impl From<TargetConfigureUsartError> for Error {
+    fn from(err: TargetConfigureUsartError) -> Self {
+        Self::TargetConfigureUsart(err)
+    }
+}
+
+impl From<TargetExpectFaultError> for Error {
+    fn from(err: TargetExpectFaultError) -> Self {
+        Self::TargetExpectFault(err)
+    }
+}
+
+impl From<TargetFlashError> for Error {
+    fn from(err: TargetFlashError) -> Self {
+        Self::TargetFlash(err)
+    }
+}
+
+impl From<TargetMrtError> for Error {
+    fn from(err: TargetMrtError) -> Self {
+        Self::TargetMrt(err)
+    }
+}
+
+impl From<TargetNegotiateChecksumError> for Error {
+    fn from(err: TargetNegotiateChecksumError) -> Self {
+        Self::TargetNegotiateChecksum(err)
+    }
+}
+
+impl From<TargetNegotiateCompressionError> for Error {
+    fn from(err: TargetNegotiateCompressionError) -> Self {
+        Self::TargetNegotiateCompression(err)
+    }
+}
+
+impl From<TargetPinInterruptError> for Error {
+    fn from(err: TargetPinInterruptError) -> Self {
+        Self::TargetPinInterrupt(err)
+    }
+}
+
 impl From<TargetPinReadError> for Error {
     fn from(err: TargetPinReadError) -> Self {
         Self::TargetPinRead(err)
     }
 }
 
+impl From<TargetSleepError> for Error {
+    fn from(err: TargetSleepError) -> Self {
+        Self::TargetSleep(err)
+    }
+}
+
 impl From<TargetSpiError> for Error {
     fn from(err: TargetSpiError) -> Self {
         Self::TargetSpi(err)
     }
 }
 
+impl From<TargetSpiSlaveError> for Error {
+    fn from(err: TargetSpiSlaveError) -> Self {
+        Self::TargetSpiSlave(err)
+    }
+}
+
+impl From<TargetSampleAdcError> for Error {
+    fn from(err: TargetSampleAdcError) -> Self {
+        Self::TargetSampleAdc(err)
+    }
+}
+
+impl From<TargetSetDacValueError> for Error {
+    fn from(err: TargetSetDacValueError) -> Self {
+        Self::TargetSetDacValue(err)
+    }
+}
+
+impl From<TargetStartDacWaveformError> for Error {
+    fn from(err: TargetStartDacWaveformError) -> Self {
+        Self::TargetStartDacWaveform(err)
+    }
+}
+
+impl From<TargetStartPwmSignalError> for Error {
+    fn from(err: TargetStartPwmSignalError) -> Self {
+        Self::TargetStartPwmSignal(err)
+    }
+}
+
 impl From<TargetStartTimerInterruptError> for Error {
     fn from(err: TargetStartTimerInterruptError) -> Self {
         Self::TargetStartTimerInterrupt(err)
     }
 }
 
+impl From<TargetUsartEchoError> for Error {
+    fn from(err: TargetUsartEchoError) -> Self {
+        Self::TargetUsartEcho(err)
+    }
+}
+
 impl From<TargetUsartSendError> for Error {
     fn from(err: TargetUsartSendError) -> Self {
         Self::TargetUsartSend(err)
     }
 }
 
+impl From<TargetDumpEventsError> for Error {
+    fn from(err: TargetDumpEventsError) -> Self {
+        Self::TargetDumpEvents(err)
+    }
+}
+
+impl From<TargetIrqStateError> for Error {
+    fn from(err: TargetIrqStateError) -> Self {
+        Self::TargetIrqState(err)
+    }
+}
+
+impl From<TargetDriveSharedLineError> for Error {
+    fn from(err: TargetDriveSharedLineError) -> Self {
+        Self::TargetDriveSharedLine(err)
+    }
+}
+
+impl From<TargetResetError> for Error {
+    fn from(err: TargetResetError) -> Self {
+        Self::TargetReset(err)
+    }
+}
+
+impl From<TargetResyncLinkError> for Error {
+    fn from(err: TargetResyncLinkError) -> Self {
+        Self::TargetResyncLink(err)
+    }
+}
+
+impl From<TargetPingError> for Error {
+    fn from(err: TargetPingError) -> Self {
+        Self::TargetPing(err)
+    }
+}
+
+impl From<TargetQuiesceError> for Error {
+    fn from(err: TargetQuiesceError) -> Self {
+        Self::TargetQuiesce(err)
+    }
+}
+
+impl From<TargetRequestError> for Error {
+    fn from(err: TargetRequestError) -> Self {
+        Self::TargetRequest(err)
+    }
+}
+
+impl From<TargetSendPartialRequestError> for Error {
+    fn from(err: TargetSendPartialRequestError) -> Self {
+        Self::TargetSendPartialRequest(err)
+    }
+}
+
+impl From<TargetRunIrqWorkloadError> for Error {
+    fn from(err: TargetRunIrqWorkloadError) -> Self {
+        Self::TargetRunIrqWorkload(err)
+    }
+}
+
+impl From<TargetSetIrqPriorityError> for Error {
+    fn from(err: TargetSetIrqPriorityError) -> Self {
+        Self::TargetSetIrqPriority(err)
+    }
+}
+
 impl From<TargetSetPinHighError> for Error {
     fn from(err: TargetSetPinHighError) -> Self {
         Self::TargetSetPinHigh(err)
@@ -86,6 +374,12 @@ impl From<TargetSetPinLowError> for Error {
     }
 }
 
+impl From<TargetUsartThroughputError> for Error {
+    fn from(err: TargetUsartThroughputError) -> Self {
+        Self::TargetUsartThroughput(err)
+    }
+}
+
 impl From<TargetUsartWaitError> for Error {
     fn from(err: TargetUsartWaitError) -> Self {
         Self::TargetUsartWait(err)
@@ -98,6 +392,24 @@ impl From<TargetWaitForAddressError> for Error {
     }
 }
 
+impl From<TargetUsartWakeupError> for Error {
+    fn from(err: TargetUsartWakeupError) -> Self {
+        Self::TargetUsartWakeup(err)
+    }
+}
+
+impl From<TargetVersionError> for Error {
+    fn from(err: TargetVersionError) -> Self {
+        Self::TargetVersion(err)
+    }
+}
+
+impl From<TestStandFinishError> for Error {
+    fn from(err: TestStandFinishError) -> Self {
+        Self::TestStandFinish(err)
+    }
+}
+
 impl From<TestStandInitError> for Error {
     fn from(err: TestStandInitError) -> Self {
         Self::TestStandInit(err)
@@ -0,0 +1,278 @@
+//! Interactive sessions with the test target
+//!
+//! Test cases fail as soon as the target doesn't reply in time, which is what
+//! they should do, but it gets in the way of exploring a misbehaving driver by
+//! hand. An interactive session has no timeouts. It prints every message the
+//! target sends, as it arrives, and sends requests that are typed as commands.
+//! See [`Target::interactive`], and the `interactive` binary of this crate.
+
+
+use std::{
+    io::{
+        self,
+        prelude::*,
+    },
+    sync::mpsc::{
+        self,
+        TryRecvError,
+    },
+    thread,
+    time::Duration,
+};
+
+use host_lib::conn::{
+    ConnReceiveError,
+    ConnSendError,
+};
+use lpc845_messages::{
+    HostToTarget,
+    TargetToHost,
+    UsartMode,
+    pin,
+};
+
+use super::target::{
+    Target,
+    TargetResetError,
+};
+
+
+/// How long to wait for messages from the target, before checking for commands
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to wait for the target to restart, after a `reset` command
+///
+/// The only operation in a session that can time out, as there'd be no way to
+/// continue otherwise.
+const RESET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Lists the commands, in response to `help`
+const HELP: &str = "\
+Commands:
+    ping                       Check that the target is responsive
+    version                    Ask for the firmware version
+    capabilities               Ask which peripherals the firmware supports
+    flash                      Ask about the flash memory
+    irq                        Ask for the interrupt state
+    events                     Dump the recent firmware events
+    usart <text>               Send text via USART
+    pin <port> <pin> high|low  Set the level of a pin
+    read-pin <port> <pin>      Read the level of a pin
+    pin-interrupts on|off      Report pin interrupts, or stop doing so
+    adc <channel>              Read from the ADC
+    quiesce                    Stop whatever the target is doing
+    reset                      Reset the target, and wait for it to restart
+    help                       Show this list
+    quit                       End the session";
+
+
+/// An interactive session with the test target
+///
+/// Returned by [`Target::interactive`].
+pub struct Interactive<'r> {
+    target: &'r mut Target,
+}
+
+impl<'r> Interactive<'r> {
+    pub(crate) fn new(target: &'r mut Target) -> Self {
+        Self {
+            target,
+        }
+    }
+
+    /// Run the session
+    ///
+    /// Reads commands from `input`, one per line, until it reads `quit`, or
+    /// reaches the end of `input`. Writes every message received from the
+    /// target to `output`, as well as the responses to invalid commands.
+    /// Returns an error, if the connection to the target fails.
+    pub fn run<I, O>(&mut self, input: I, mut output: O)
+        -> Result<(), InteractiveError>
+        where
+            I: BufRead + Send + 'static,
+            O: Write,
+    {
+        // Reading the input blocks, so it needs its own thread. The thread
+        // ends along with the session, once it can't send any more lines.
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in input.lines() {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        writeln!(output, "Type `help` for a list of commands.")
+            .map_err(|err| InteractiveError::Io(err))?;
+
+        loop {
+            loop {
+                let line = match rx.try_recv() {
+                    Ok(line)                        => line,
+                    Err(TryRecvError::Empty)        => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                };
+                let line = line
+                    .map_err(|err| InteractiveError::Io(err))?;
+
+                match line.trim() {
+                    ""     => {}
+                    "quit" => return Ok(()),
+                    "help" => {
+                        writeln!(output, "{}", HELP)
+                            .map_err(|err| InteractiveError::Io(err))?;
+                    }
+                    "reset" => {
+                        self.target.reset(RESET_TIMEOUT)
+                            .map_err(|err| InteractiveError::Reset(err))?;
+                        writeln!(output, "Target has restarted.")
+                            .map_err(|err| InteractiveError::Io(err))?;
+                    }
+                    command => match parse(command) {
+                        Ok(request) => {
+                            self.target.conn().send(&request)
+                                .map_err(|err| InteractiveError::Send(err))?;
+                        }
+                        Err(err) => {
+                            writeln!(output, "{}", err)
+                                .map_err(|err| InteractiveError::Io(err))?;
+                        }
+                    },
+                }
+            }
+
+            self.receive(&mut output)?;
+        }
+    }
+
+    /// Print whatever the target has sent, waiting up to `POLL_INTERVAL`
+    fn receive(&mut self, output: &mut impl Write)
+        -> Result<(), InteractiveError>
+    {
+        let mut buf = Vec::new();
+        let result = self.target.conn()
+            .receive::<TargetToHost>(POLL_INTERVAL, &mut buf);
+
+        // Log records are taken out of the stream of messages. Print them
+        // along with everything else.
+        for record in self.target.take_log() {
+            writeln!(output, "{}", record)
+                .map_err(|err| InteractiveError::Io(err))?;
+        }
+
+        match result {
+            Ok(message) => {
+                writeln!(output, "{:?}", message)
+                    .map_err(|err| InteractiveError::Io(err))?;
+            }
+            Err(err) if err.is_timeout() => {}
+            Err(err)
+                if err.is_overrun()
+                    || err.is_checksum_mismatch()
+                    || err.is_panic() =>
+            {
+                writeln!(output, "{:?}", err)
+                    .map_err(|err| InteractiveError::Io(err))?;
+            }
+            Err(err) => {
+                return Err(InteractiveError::Receive(err));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Parse a command into the request it stands for
+///
+/// Data that the request carries is borrowed from `command`.
+fn parse(command: &str) -> Result<HostToTarget<'_>, String> {
+    let (name, args) = match command.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None               => (command, ""),
+    };
+    let mut words = args.split_whitespace();
+
+    let request = match name {
+        "ping"         => HostToTarget::Ping,
+        "version"      => HostToTarget::GetVersion,
+        "capabilities" => HostToTarget::GetCapabilities,
+        "flash"        => HostToTarget::GetFlashInfo,
+        "irq"          => HostToTarget::GetIrqState,
+        "events"       => HostToTarget::DumpEvents,
+        "quiesce"      => HostToTarget::Quiesce,
+        "usart" => {
+            HostToTarget::SendUsart {
+                mode: UsartMode::Regular,
+                data: args.as_bytes(),
+            }
+        }
+        "pin" => {
+            let pin   = parse_pin(&mut words)?;
+            let level = match words.next() {
+                Some("high") => pin::Level::High,
+                Some("low")  => pin::Level::Low,
+                _            => return Err(usage("pin <port> <pin> high|low")),
+            };
+
+            HostToTarget::SetPin(pin::SetLevel { pin, level })
+        }
+        "read-pin" => {
+            let pin = parse_pin(&mut words)?;
+            HostToTarget::ReadPin(pin::ReadLevel { pin })
+        }
+        "pin-interrupts" => {
+            let report = match words.next() {
+                Some("on")  => true,
+                Some("off") => false,
+                _           => return Err(usage("pin-interrupts on|off")),
+            };
+
+            HostToTarget::ReportPinInterrupts(report)
+        }
+        "adc" => {
+            let channel = words.next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| usage("adc <channel>"))?;
+
+            HostToTarget::ReadAdc { channel }
+        }
+        name => {
+            return Err(
+                format!("Unknown command `{}`. Type `help` for a list.", name)
+            );
+        }
+    };
+
+    Ok(request)
+}
+
+fn parse_pin<'a>(words: &mut impl Iterator<Item = &'a str>)
+    -> Result<pin::PortPin, String>
+{
+    let mut number = || {
+        words.next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| usage("<port> <pin>, like `1 0` for PIO1_0"))
+    };
+
+    let port   = number()?;
+    let number = number()?;
+
+    Ok(pin::PortPin::new(port, number))
+}
+
+fn usage(usage: &str) -> String {
+    format!("Usage: {}", usage)
+}
+
+
+#[derive(Debug)]
+pub enum InteractiveError {
+    Io(io::Error),
+    Receive(ConnReceiveError),
+    Reset(TargetResetError),
+    Send(ConnSendError),
+}
@@ -1,14 +1,19 @@
 //! The library code that supports this test suite
 //!
-//! For now, this is just all custom code used by this test suite (except for
-//! code shared with the firmware). Eventually, a lot of it will be moved into
-//! a generally usable library that can be shared with other test suites.
+//! The parts of the target API that are shared with other test suites are
+//! provided by `host_lib::target`. This is the code that is specific to this
+//! test suite (except for code shared with the firmware).
 
 
 pub mod error;
+pub mod interactive;
+pub mod prelude;
 pub mod target;
 pub mod test_stand;
 
+#[cfg(feature = "tokio")]
+pub mod async_target;
+
 
 pub use self::{
     error::{
@@ -17,3 +22,6 @@ pub use self::{
     },
     test_stand::TestStand,
 };
+
+#[cfg(feature = "tokio")]
+pub use self::async_target::AsyncTarget;
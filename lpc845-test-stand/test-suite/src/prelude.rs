@@ -0,0 +1,32 @@
+//! Convenient imports for test cases
+//!
+//! Includes everything from `host_lib::prelude`, as well as the types of this
+//! test suite, and `TargetRequests`, whose methods are available on `Target`.
+//! Import them all at once:
+//!
+//! ```
+//! use lpc845_test_suite::prelude::*;
+//! ```
+
+
+pub use host_lib::prelude::*;
+pub use lpc845_messages::TargetRequests as _;
+
+pub use crate::{
+    Result,
+    target::{
+        ADC_IN,
+        ADC_REFERENCE,
+        DAC_LOOPBACK,
+        DAC_OUT,
+        GPIO_GROUP_IN,
+        GPIO_GROUP_OUT,
+        GPIO_IN,
+        GPIO_OUT,
+        Target,
+    },
+    test_stand::{
+        TestStand,
+        TestStandBuilder,
+    },
+};
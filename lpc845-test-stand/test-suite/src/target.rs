@@ -1,257 +1,544 @@
-use std::time::{
-    Duration,
-    Instant,
+use std::{
+    convert::TryFrom,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
 use lpc845_messages::{
+    ADC_SAMPLES_CAP,
+    CaptEvent,
+    Capabilities,
+    DAC_WAVEFORM_CAP,
+    DAC_WAVEFORM_MAX_RATE,
     DmaMode,
+    FLASH_BUF_CAP,
+    FaultKind,
+    FlashError,
+    HEARTBEAT_INTERVAL_MS,
     HostToTarget,
+    I2C_BUF_CAP,
+    IrqLatencies,
+    IrqState,
+    SPI_BUF_CAP,
+    SleepMode,
+    TargetRequests,
     TargetToHost,
+    TimerMode,
+    UsartEcho,
+    UsartError,
     UsartMode,
+    UsartWakeup,
+    WakeupCause,
+    WakeupSource,
+    decode_samples,
+    encode_samples,
+    event,
     pin,
+    units::{
+        Microseconds,
+        Millivolts,
+    },
+    usart,
 };
 
 use host_lib::{
+    config::Timeouts,
     conn::{
+        self,
         Conn,
         ConnReceiveError,
+        ConnResyncError,
         ConnSendError,
     },
-    pin::{
-        Pin,
-        ReadLevelError,
+    latency::Clock,
+    measurement::Capture,
+    target::{
+        self,
+        Board,
+        HasTimeouts,
+        Reply,
+        Request,
+        TargetRenegotiateError,
+        WithTimeouts,
     },
 };
+use test_cases::gpio::GpioTarget;
 
+use crate::interactive::Interactive;
 
-/// The connection to the test target
-pub struct Target {
-    conn: Conn,
-    pin: Pin<()>,
-}
+pub use host_lib::target::{
+    TargetCapabilitiesError,
+    TargetIrqStateError,
+    TargetNegotiateChecksumError,
+    TargetNegotiateCompressionError,
+    TargetPinReadError,
+    TargetPingError,
+    TargetQuiesceOnInterruptError,
+    TargetRequestError,
+    TargetSetPinHighError,
+    TargetSetPinLowError,
+    TargetStartTimerInterruptError,
+    TargetUsartSendError,
+    TargetUsartThroughputError,
+    TargetUsartWaitError,
+    TargetVersionError,
+    Version,
+};
 
-impl Target {
-    pub(crate) fn new(conn: Conn) -> Self {
-        Self {
-            conn,
-            pin: Pin::new(()),
-        }
-    }
 
-    /// Instruct the target to set a GPIO pin high
-    pub fn set_pin_high(&mut self) -> Result<(), TargetSetPinHighError> {
-        self.pin
-            .set_level::<HostToTarget>(
-                pin::Level::High,
-                &mut self.conn,
-            )
-            .map_err(|err| TargetSetPinHighError(err))
-    }
+/// Returned by [`host_lib::target::Target::quiesce`]
+pub type TargetQuiesceError = target::TargetQuiesceError<FaultKind>;
 
-    /// Instruct the target to set a GPIO pin low
-    pub fn set_pin_low(&mut self) -> Result<(), TargetSetPinLowError> {
-        self.pin
-            .set_level::<HostToTarget>(
-                pin::Level::Low,
-                &mut self.conn,
-            )
-            .map_err(|err| TargetSetPinLowError(err))
-    }
 
-    /// Indicates whether the input pin is set high
-    ///
-    /// Uses `pin_state` internally.
-    pub fn pin_is_high(&mut self) -> Result<bool, TargetPinReadError> {
-        let pin_state = self.pin.read_level::<HostToTarget, TargetToHost>(
-            Duration::from_millis(10),
-            &mut self.conn,
-        )?;
-        Ok(pin_state.0 == pin::Level::High)
-    }
+/// The target pin that is wired to an input of the assistant (PIO1_0)
+pub const GPIO_OUT: pin::PortPin = pin::PortPin::new(1, 0);
 
-    /// Indicates whether the input pin is set low
-    ///
-    /// Uses `pin_state` internally.
-    pub fn pin_is_low(&mut self) -> Result<bool, TargetPinReadError> {
-        let pin_state = self.pin.read_level::<HostToTarget, TargetToHost>(
-            Duration::from_millis(10),
-            &mut self.conn,
-        )?;
-        Ok(pin_state.0 == pin::Level::Low)
-    }
+/// The target pin that is wired to an output of the assistant (PIO1_2)
+pub const GPIO_IN: pin::PortPin = pin::PortPin::new(1, 2);
 
-    /// Instruct the target to send this message via USART
-    pub fn send_usart(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
-    {
-        self.conn
-            .send(&HostToTarget::SendUsart { mode: UsartMode::Regular, data })
-            .map_err(|err| TargetUsartSendError(err))
+/// The target pins that are wired to inputs of the assistant, as a group
+///
+/// Bit `n` stands for PIO1_n. PIO1_0 is [`GPIO_OUT`], which the assistant
+/// reads as `InputPin::Green`, and PIO1_1 is the blue LED, which it reads as
+/// `InputPin::Blue`. See [`TargetRequests::set_pin_group`].
+pub const GPIO_GROUP_OUT: u32 = 0b011;
+
+/// The target pins that are wired to outputs of the assistant, as a group
+///
+/// Bit `n` stands for PIO1_n. PIO1_2 is [`GPIO_IN`], which the assistant sets
+/// as `OutputPin::Red`. See [`TargetRequests::read_pin_group`].
+pub const GPIO_GROUP_IN: u32 = 0b100;
+
+/// The target pin that the USART's TX function is assigned to (PIO0_27)
+///
+/// Wired to the assistant's USART RX. See [`TargetRequests::move_usart_tx`].
+/// While the function is moved to [`GPIO_OUT`], which the assistant monitors
+/// as `InputPin::Green`, the assistant doesn't receive anything the target
+/// sends via USART, and setting the level of `GPIO_OUT` has no effect.
+pub const USART_TX: pin::PortPin = pin::PortPin::new(0, 27);
+
+/// The target's ADC channel that is wired to an output of the assistant
+///
+/// ADC_6, on PIO0_20. The assistant sets the level using `set_pin_5_high` and
+/// `set_pin_5_low`.
+pub const ADC_IN: u8 = 6;
+
+/// The reference voltage of the target's ADC
+///
+/// The LPC845-BRK connects VREFP to its 3.3 V supply.
+pub const ADC_REFERENCE: Millivolts = Millivolts(3300);
+
+/// The target's DAC channel whose output pin is used
+///
+/// DACOUT1, on PIO0_29. Wired to [`DAC_LOOPBACK`] on the target itself.
+pub const DAC_OUT: u8 = 1;
+
+/// The target's ADC channel that reads back the output of [`DAC_OUT`]
+///
+/// ADC_1, on PIO0_6.
+pub const DAC_LOOPBACK: u8 = 1;
+
+/// The longest interval that `Target::start_mrt` accepts
+///
+/// The MRT counts down 2^31 cycles at most, with a 12 MHz clock.
+pub const MRT_MAX_INTERVAL: Duration = Duration::from_micros(178_956_970);
+
+/// The X pin of the target's CAPT peripheral that the touch sensor is on
+///
+/// CAPT_X0, on PIO0_31. The assistant grounds it, to simulate a touch.
+pub const CAPT_X: u8 = 0;
+
+/// How long the target may stay silent, before it's considered unresponsive
+///
+/// The firmware sends a heartbeat every `HEARTBEAT_INTERVAL_MS`, but requests
+/// that keep it busy for a while can delay them. See
+/// [`Conn::set_heartbeat_filter`].
+pub const HEARTBEAT_TIMEOUT: Duration =
+    Duration::from_millis(8 * HEARTBEAT_INTERVAL_MS as u64);
+
+
+/// The LPC845-BRK, as used in this test stand
+///
+/// Connects the API shared between test stands to the messages of this one.
+/// See [`host_lib::target`].
+#[derive(Debug)]
+pub struct Lpc845;
+
+impl Board for Lpc845 {
+    type Request<'r> = HostToTarget<'r>;
+    type Reply<'r>   = TargetToHost<'r>;
+
+    type Capabilities = Capabilities;
+    type IrqState     = IrqState;
+    type Fault        = FaultKind;
+
+    fn request<'r>(request: Request<'r>) -> Self::Request<'r> {
+        match request {
+            Request::NegotiateCompression(compression) => {
+                HostToTarget::NegotiateCompression(compression)
+            }
+            Request::NegotiateChecksum(checksum) => {
+                HostToTarget::NegotiateChecksum(checksum)
+            }
+            Request::GetCapabilities => HostToTarget::GetCapabilities,
+            Request::GetVersion      => HostToTarget::GetVersion,
+            Request::GetIrqState     => HostToTarget::GetIrqState,
+            Request::Ping            => HostToTarget::Ping,
+            Request::Quiesce         => HostToTarget::Quiesce,
+            Request::SendUsart { mode, data } => {
+                HostToTarget::SendUsart { mode, data }
+            }
+            Request::StartTimerInterrupt { period } => {
+                HostToTarget::StartTimerInterrupt { period }
+            }
+            Request::StopTimerInterrupt => HostToTarget::StopTimerInterrupt,
+            Request::StartUsartThroughputTest { bytes, chunk_size } => {
+                HostToTarget::StartUsartThroughputTest { bytes, chunk_size }
+            }
+        }
     }
 
-    /// Instruct the target to send this message via USART using DMA
-    pub fn send_usart_dma(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
+    fn reply<'r>(reply: Self::Reply<'r>)
+        -> Result<Reply<'r, Self>, Self::Reply<'r>>
     {
-        self.conn
-            .send(&HostToTarget::SendUsart { mode: UsartMode::Dma, data })
-            .map_err(|err| TargetUsartSendError(err))
+        let reply = match reply {
+            TargetToHost::CompressionNegotiated(compression) => {
+                Reply::CompressionNegotiated(compression)
+            }
+            TargetToHost::ChecksumNegotiated(checksum) => {
+                Reply::ChecksumNegotiated(checksum)
+            }
+            TargetToHost::Capabilities(capabilities) => {
+                Reply::Capabilities(capabilities)
+            }
+            TargetToHost::Version {
+                protocol,
+                firmware_hash,
+                hal_version,
+                features,
+            } => {
+                Reply::Version {
+                    protocol,
+                    firmware_hash,
+                    hal_version,
+                    features,
+                }
+            }
+            TargetToHost::IrqState(state) => Reply::IrqState(state),
+            TargetToHost::Pong            => Reply::Pong,
+            TargetToHost::Quiesced        => Reply::Quiesced,
+            TargetToHost::Fault(kind)     => Reply::Fault(kind),
+            TargetToHost::UsartReceive { mode, data, .. } => {
+                Reply::UsartReceive { mode, data }
+            }
+            TargetToHost::Log { level, message } => {
+                Reply::Log { level, message }
+            }
+            TargetToHost::BufferOverrun { dropped } => {
+                Reply::BufferOverrun { dropped }
+            }
+            TargetToHost::Heartbeat      => Reply::Heartbeat,
+            TargetToHost::Panic(report) => Reply::Panic(report),
+            TargetToHost::Defmt(frames) => Reply::Defmt(frames),
+            TargetToHost::UsartThroughputSent => Reply::UsartThroughputSent,
+            reply => {
+                return Err(reply);
+            }
+        };
+
+        Ok(reply)
     }
+}
 
-    /// Instruct the target to send this message via USART using DMA
-    pub fn send_usart_sync(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
-    {
-        self.conn
-            .send(&HostToTarget::SendUsart { mode: UsartMode::Sync, data })
-            .map_err(|err| TargetUsartSendError(err))
+
+/// The connection to the test target
+///
+/// Dereferences to [`host_lib::target::Target`], which provides the API that
+/// is shared with other test stands. The methods of this type are specific to
+/// this test stand.
+pub struct Target(target::Target<Lpc845>);
+
+impl Target {
+    pub(crate) fn new(mut conn: Conn) -> Self {
+        conn.set_heartbeat_filter(
+            target::heartbeat::<Lpc845>,
+            HEARTBEAT_TIMEOUT,
+        );
+        Self(target::Target::new(conn))
     }
 
-    /// Instruct the target to send this message via USART using DMA
-    pub fn send_usart_with_flow_control(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
-    {
-        self.conn
-            .send(&HostToTarget::SendUsart {
-                mode: UsartMode::FlowControl,
-                data,
-            })
-            .map_err(|err| TargetUsartSendError(err))
+    /// Start an interactive session with the target
+    ///
+    /// For exploring the target by hand, without any timeouts. See the
+    /// [`interactive`] module.
+    ///
+    /// [`interactive`]: crate::interactive
+    pub fn interactive(&mut self) -> Interactive<'_> {
+        Interactive::new(self)
     }
 
-    /// Wait to receive the provided data via USART
+    /// Replace the timeouts for a single call
     ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn wait_for_usart_rx(&mut self, data: &[u8], timeout: Duration)
-        -> Result<Vec<u8>, TargetUsartWaitError>
+    /// Like `host_lib::target::Target::with_timeouts`, but the returned guard
+    /// dereferences to this type.
+    pub fn with_timeouts(&mut self, f: impl FnOnce(Timeouts) -> Timeouts)
+        -> WithTimeouts<'_, Self>
     {
-        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Regular)
+        WithTimeouts::new(self, f)
     }
 
-    /// Wait to receive the provided data via USART/DMA
+    /// Expect the target to be reset by a fault of the given kind
+    ///
+    /// Use this in test cases that intentionally provoke a fault. Waits up to
+    /// `timeout` for the target to restart and report the fault. Anything the
+    /// target sent before restarting is discarded.
     ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn wait_for_usart_rx_dma(&mut self, data: &[u8], timeout: Duration)
-        -> Result<Vec<u8>, TargetUsartWaitError>
+    /// Returns an error, if the target reports anything else. Once the fault
+    /// has been reported, the connection is restored to its previous state, so
+    /// the test case can continue to use the target.
+    pub fn expect_fault(&mut self, kind: FaultKind, timeout: Duration)
+        -> Result<(), TargetExpectFaultError>
     {
-        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Dma)
+        self.conn().resync(timeout)
+            .map_err(|err| TargetExpectFaultError::Resync(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetExpectFaultError::Receive(err))?;
+
+        match message {
+            TargetToHost::Fault(fault) if fault == kind => {}
+            message => {
+                return Err(
+                    TargetExpectFaultError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                );
+            }
+        }
+
+        self.renegotiate(timeout)
+            .map_err(|err| TargetExpectFaultError::Recover(err))
     }
 
-    /// Wait to receive the provided data via synchronous USART
+    /// Ask the target for the events it has recorded recently
     ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn wait_for_usart_rx_sync(&mut self, data: &[u8], timeout: Duration)
-        -> Result<Vec<u8>, TargetUsartWaitError>
+    /// The target keeps the last few requests it has received, the errors it
+    /// has run into, and the interrupt handlers it has entered. If a test case
+    /// times out, use this to find out what the target actually did. Returns
+    /// the events oldest first.
+    pub fn dump_events(&mut self, timeout: Duration)
+        -> Result<Vec<event::Event>, TargetDumpEventsError>
     {
-        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Sync)
+        self.conn()
+            .send(&HostToTarget::DumpEvents)
+            .map_err(|err| TargetDumpEventsError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetDumpEventsError::Receive(err))?;
+
+        match message {
+            TargetToHost::Events(events) => {
+                Ok(events.as_slice().to_vec())
+            }
+            message => {
+                Err(
+                    TargetDumpEventsError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
     }
 
-    fn wait_for_usart_rx_inner(&mut self,
-        data:          &[u8],
-        timeout:       Duration,
-        expected_mode: UsartMode,
+    /// Change the priority of an interrupt on the target
+    ///
+    /// As in the NVIC, `0` is the highest priority. The target starts with all
+    /// of its interrupts at priority `3`, the lowest. Changed priorities stay
+    /// in effect until the target is reset, so tests should restore them.
+    pub fn set_irq_priority(&mut self,
+        irq:      u8,
+        priority: u8,
+        timeout:  Duration,
     )
-        -> Result<Vec<u8>, TargetUsartWaitError>
+        -> Result<(), TargetSetIrqPriorityError>
     {
-        let mut buf   = Vec::new();
-        let     start = Instant::now();
+        self.conn()
+            .send(&HostToTarget::SetIrqPriority { irq, priority })
+            .map_err(|err| TargetSetIrqPriorityError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetSetIrqPriorityError::Receive(err))?;
 
-        loop {
-            if buf.windows(data.len()).any(|window| window == data) {
-                return Ok(buf);
+        match message {
+            TargetToHost::IrqPrioritySet => {
+                Ok(())
             }
-            if start.elapsed() > timeout {
-                return Err(TargetUsartWaitError::Timeout);
+            message => {
+                Err(
+                    TargetSetIrqPriorityError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
             }
+        }
+    }
+
+    /// Run a workload on the target that measures interrupt latency
+    ///
+    /// Pends all interrupts in `irqs` at the same time, `rounds` times, and
+    /// returns the maximum latency of each. Use this together with
+    /// `set_irq_priority`, to check that interrupts are served in order of
+    /// their priorities.
+    pub fn run_irq_workload(&mut self,
+        irqs:    &[u8],
+        rounds:  u16,
+        timeout: Duration,
+    )
+        -> Result<IrqLatencies, TargetRunIrqWorkloadError>
+    {
+        let irqs = irqs.iter().fold(0, |mask, irq| mask | 1 << irq);
 
-            let mut tmp = Vec::new();
-            let message = self.conn
-                .receive::<TargetToHost>(timeout, &mut tmp)
-                .map_err(|err| TargetUsartWaitError::Receive(err))?;
+        self.conn()
+            .send(&HostToTarget::RunIrqWorkload { irqs, rounds })
+            .map_err(|err| TargetRunIrqWorkloadError::Send(err))?;
 
-            match message {
-                TargetToHost::UsartReceive { mode, data }
-                    if mode == expected_mode =>
-                {
-                    buf.extend(data)
-                }
-                message => {
-                    return Err(
-                        TargetUsartWaitError::UnexpectedMessage(
-                            format!("{:?}", message)
-                        )
-                    );
-                }
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetRunIrqWorkloadError::Receive(err))?;
+
+        match message {
+            TargetToHost::IrqLatencies(latencies) => {
+                Ok(latencies)
+            }
+            message => {
+                Err(
+                    TargetRunIrqWorkloadError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
             }
         }
     }
 
-    /// Enable address matching
-    pub fn wait_for_address(&mut self, address: u8)
-        -> Result<(), TargetWaitForAddressError>
+    /// Instruct the target to reset itself
+    ///
+    /// Waits up to `timeout` for the target to restart. Afterwards, the
+    /// connection is restored to its previous state, so the test case can
+    /// continue to use the target.
+    pub fn reset(&mut self, timeout: Duration)
+        -> Result<(), TargetResetError>
     {
-        self.conn
-            .send(&HostToTarget::WaitForAddress(address))
-            .map_err(|err| TargetWaitForAddressError(err))
+        self.conn()
+            .send(&HostToTarget::Reset)
+            .map_err(|err| TargetResetError::Send(err))?;
+        self.conn().resync(timeout)
+            .map_err(|err| TargetResetError::Resync(err))?;
+
+        self.renegotiate(timeout)
+            .map_err(|err| TargetResetError::Recover(err))
     }
 
-    /// Start a timer interrupt with the given period in milliseconds
-    pub fn start_timer_interrupt(&mut self, period_ms: u32)
-        -> Result<TimerInterrupt, TargetStartTimerInterruptError>
+    /// Resynchronize the link with the target, without resetting it
+    ///
+    /// The target discards any partial request. Waits up to `timeout` for it to
+    /// confirm. Afterwards, the connection is restored to its previous state,
+    /// like after [`Target::reset`].
+    pub fn resync_link(&mut self, timeout: Duration)
+        -> Result<(), TargetResyncLinkError>
     {
-        self.conn
-            .send(&HostToTarget::StartTimerInterrupt { period_ms })
-            .map_err(|err| TargetStartTimerInterruptError(err))?;
+        self.conn().resync_link(timeout)
+            .map_err(|err| TargetResyncLinkError::Resync(err))?;
 
-        Ok(TimerInterrupt(self))
+        self.renegotiate(timeout)
+            .map_err(|err| TargetResyncLinkError::Recover(err))
     }
 
-    /// Start an I2C transaction
+    /// Instruct the target to read from the ADC
     ///
-    /// Sends the provided `data` and returns the reply.
-    pub fn start_i2c_transaction(&mut self, data: u8, timeout: Duration)
-        -> Result<u8, TargetI2cError>
+    /// Returns the voltage on the ADC input, converted from the 12-bit result
+    /// using `ADC_REFERENCE`. The target only supports `ADC_IN`.
+    pub fn read_adc(&mut self, channel: u8, timeout: Duration)
+        -> Result<Millivolts, TargetRequestError>
     {
-        self.start_i2c_transaction_inner(data, timeout, DmaMode::Regular)
+        let value = self.read_adc_value(channel, timeout)?;
+        Ok(Millivolts::from_adc(value, 12, ADC_REFERENCE))
     }
 
-    /// Start an I2C/DMA transaction
+    /// Send the beginning of a request, then go silent
     ///
-    /// Sends the provided `data` and returns the reply.
-    pub fn start_i2c_transaction_dma(&mut self, data: u8, timeout: Duration)
-        -> Result<u8, TargetI2cError>
+    /// Sends at most `len` bytes of a `Ping` request, but never completes it.
+    /// The target should discard the partial request after a while, and then
+    /// answer the next request normally.
+    pub fn send_partial_request(&mut self, len: usize)
+        -> Result<(), TargetSendPartialRequestError>
     {
-        self.start_i2c_transaction_inner(data, timeout, DmaMode::Dma)
+        self.conn().send_partial(&HostToTarget::Ping, len)
+            .map_err(|err| TargetSendPartialRequestError(err))
     }
 
-    fn start_i2c_transaction_inner(&mut self,
-        data:    u8,
+    /// Wait for the target to report an interrupt of its input pin
+    ///
+    /// Returns the target's timestamp of the interrupt. Returns an error, if
+    /// the interrupt was triggered by a different edge. Other messages are left
+    /// for later. Requires `report_pin_interrupts` to be enabled.
+    pub fn wait_for_pin_interrupt(&mut self,
+        edge:    pin::Edge,
         timeout: Duration,
-        mode:    DmaMode,
     )
-        -> Result<u8, TargetI2cError>
+        -> Result<u32, TargetPinInterruptError>
     {
-        let address = 0x48;
-
-        self.conn
-            .send(&HostToTarget::StartI2cTransaction { mode, address, data })
-            .map_err(|err| TargetI2cError::Send(err))?;
+        self.wait_for_pin_capture(edge, timeout)
+            .map(|capture| capture.handled)
+    }
 
+    /// Wait for the target to report an interrupt of its input pin
+    ///
+    /// Like `wait_for_pin_interrupt`, but also returns when the edge reached
+    /// the pin, as captured by the target's timer. Use this to measure the
+    /// latency of the interrupt (see [`host_lib::measurement`]).
+    pub fn wait_for_pin_capture(&mut self,
+        edge:    pin::Edge,
+        timeout: Duration,
+    )
+        -> Result<Capture, TargetPinInterruptError>
+    {
         let mut tmp = Vec::new();
-        let message = self.conn
-            .receive::<TargetToHost>(timeout, &mut tmp)
-            .map_err(|err| TargetI2cError::Receive(err))?;
+        let message = self.conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::PinInterrupt { .. })
+                )
+            })
+            .map_err(|err| TargetPinInterruptError::Receive(err))?;
 
         match message {
-            TargetToHost::I2cReply(reply) => {
-                Ok(reply)
+            TargetToHost::PinInterrupt {
+                edge: actual,
+                timestamp,
+                captured,
+                ..
+            }
+                if actual == edge =>
+            {
+                Ok(Capture { captured, handled: timestamp })
             }
             message => {
                 Err(
-                    TargetI2cError::UnexpectedMessage(
+                    TargetPinInterruptError::UnexpectedMessage(
                         format!("{:?}", message)
                     )
                 )
@@ -259,109 +546,1289 @@ impl Target {
         }
     }
 
-    /// Start an SPI transaction
+    /// Relate the target's timestamps to the host's clock
     ///
-    /// Sends the provided `data` and returns the reply.
-    pub fn start_spi_transaction(&mut self, data: u8, timeout: Duration)
-        -> Result<u8, TargetSpiError>
+    /// Asks the target for the value of its timer `samples` times, and keeps
+    /// the most accurate sample. See [`Clock`].
+    pub fn sync_clock(&mut self, samples: u32, timeout: Duration)
+        -> Result<Clock, TargetRequestError>
     {
-        self.start_spi_transaction_inner(data, timeout, DmaMode::Regular)
-    }
+        let sent      = Instant::now();
+        let timestamp = self.time(timeout)?;
+        let mut clock = Clock::new(sent, timestamp, Instant::now());
 
-    /// Start an SPI/DMA transaction
-    ///
-    /// Sends the provided `data` and returns the reply.
-    pub fn start_spi_transaction_dma(&mut self, data: u8, timeout: Duration)
-        -> Result<u8, TargetSpiError>
-    {
-        self.start_spi_transaction_inner(data, timeout, DmaMode::Dma)
+        for _ in 1 .. samples {
+            let sent      = Instant::now();
+            let timestamp = self.time(timeout)?;
+            clock.update(sent, timestamp, Instant::now());
+        }
+
+        Ok(clock)
     }
 
-    fn start_spi_transaction_inner(&mut self,
-        data:    u8,
+    /// Reconfigure the target's USART
+    ///
+    /// `mode` selects the USART that is used for that mode. Waits until the
+    /// target confirms the new configuration. Use
+    /// `Assistant::configure_usart` to reconfigure the other end the same way.
+    pub fn configure_usart(&mut self,
+        mode:    UsartMode,
+        config:  &usart::Config,
         timeout: Duration,
-        mode:    DmaMode,
     )
-        -> Result<u8, TargetSpiError>
+        -> Result<(), TargetConfigureUsartError>
     {
-        self.conn.send(&HostToTarget::StartSpiTransaction { mode, data })
-            .map_err(|err| TargetSpiError::Send(err))?;
+        self.conn()
+            .send(&HostToTarget::ConfigureUsart { mode, config: *config })
+            .map_err(|err| TargetConfigureUsartError::Send(err))?;
 
         let mut tmp = Vec::new();
-        let message = self.conn.receive::<TargetToHost>(timeout, &mut tmp)
-            .map_err(|err| TargetSpiError::Receive(err))?;
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetConfigureUsartError::Receive(err))?;
 
         match message {
-            TargetToHost::SpiReply(reply) => {
-                Ok(reply)
+            TargetToHost::UsartConfigured => {
+                Ok(())
             }
             message => {
                 Err(
-                    TargetSpiError::UnexpectedMessage(
+                    TargetConfigureUsartError::UnexpectedMessage(
                         format!("{:?}", message)
                     )
                 )
             }
         }
     }
-}
 
+    /// Drive the trigger line like an open-drain output
+    ///
+    /// The trigger line can be shared by the test nodes as a wired-AND line,
+    /// like an active-low interrupt line. Passing `Level::Low` pulls the line
+    /// low, passing `Level::High` releases it. Waits until the target reports
+    /// the level the line has settled at. Release the line again, before using
+    /// it as a trigger.
+    pub fn drive_shared_line(&mut self, level: pin::Level, timeout: Duration)
+        -> Result<SharedLine, TargetDriveSharedLineError>
+    {
+        self.conn()
+            .send(&HostToTarget::DriveSharedLine(level))
+            .map_err(|err| TargetDriveSharedLineError::Send(err))?;
 
-/// Represent a timer interrupt that's currently configured on the target
-///
-/// This timer interrupt will be stopped when this struct is dropped.
-pub struct TimerInterrupt<'r>(&'r mut Target);
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetDriveSharedLineError::Receive(err))?;
 
-impl Drop for TimerInterrupt<'_> {
-    fn drop(&mut self) {
-        (self.0).conn.send(&HostToTarget::StopTimerInterrupt)
-            .unwrap()
+        match message {
+            TargetToHost::SharedLine { level, settle_us } => {
+                Ok(SharedLine {
+                    level,
+                    settle_time: settle_us.map(Microseconds),
+                })
+            }
+            message => {
+                Err(
+                    TargetDriveSharedLineError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
     }
-}
-
 
-#[derive(Debug)]
-pub struct TargetSetPinHighError(ConnSendError);
-
-#[derive(Debug)]
-pub struct TargetSetPinLowError(ConnSendError);
+    /// Wait for the target to report an error, while receiving via USART
+    ///
+    /// Data that the target received is left for `wait_for_usart_rx`. Returns
+    /// an error, if it times out before an error has been reported.
+    pub fn wait_for_usart_error(&mut self, timeout: Duration)
+        -> Result<UsartError, TargetUsartWaitError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::UsartError { mode, .. })
+                        if mode == UsartMode::Regular
+                )
+            })
+            .map_err(|err| TargetUsartWaitError::Receive(err))?;
 
-#[derive(Debug)]
-pub struct TargetPinReadError(ReadLevelError);
+        match message {
+            TargetToHost::UsartError { error, .. } => {
+                Ok(error)
+            }
+            message => {
+                Err(
+                    TargetUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
 
-impl From<ReadLevelError> for TargetPinReadError {
-    fn from(err: ReadLevelError) -> Self {
-        Self(err)
+    /// Instruct the target to send a break via USART
+    ///
+    /// The target holds its regular TX line low for 2 ms. The assistant
+    /// reports the break (see `Assistant::wait_for_break_from_target_usart`).
+    pub fn send_break(&mut self) -> Result<(), TargetUsartSendError> {
+        self.conn()
+            .send(&HostToTarget::SendUsartBreak)
+            .map_err(|err| TargetUsartSendError(err))
     }
-}
 
+    /// Wait for the target to report a break, while receiving via USART
+    ///
+    /// The target reports the framing error that the break looks like at
+    /// first, before it detects the break. That error is left for
+    /// `wait_for_usart_error`. Returns an error, if it times out before a break
+    /// has been reported.
+    pub fn wait_for_break(&mut self, timeout: Duration)
+        -> Result<(), TargetUsartWaitError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::UsartBreakDetected)
+                )
+            })
+            .map_err(|err| TargetUsartWaitError::Receive(err))?;
 
-#[derive(Debug)]
-pub struct TargetUsartSendError(ConnSendError);
+        match message {
+            TargetToHost::UsartBreakDetected => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
 
-#[derive(Debug)]
-pub struct TargetStartTimerInterruptError(ConnSendError);
+    /// Instruct the target to sleep, until it receives data via USART
+    ///
+    /// Returns once the target is about to enter sleep mode. The target wakes
+    /// up on the start bit of the next byte that it receives via its regular
+    /// USART. Use `wait_for_usart_wakeup` afterwards.
+    pub fn sleep_until_usart_rx(&mut self, timeout: Duration)
+        -> Result<(), TargetUsartWakeupError>
+    {
+        self.conn()
+            .send(&HostToTarget::SleepUntilUsartRx)
+            .map_err(|err| TargetUsartWakeupError::Send(err))?;
 
-#[derive(Debug)]
-pub enum TargetUsartWaitError {
-    Receive(ConnReceiveError),
-    Timeout,
-    UnexpectedMessage(String),
-}
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetUsartWakeupError::Receive(err))?;
 
-#[derive(Debug)]
-pub struct TargetWaitForAddressError(ConnSendError);
+        match message {
+            TargetToHost::Sleeping => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetUsartWakeupError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
 
-#[derive(Debug)]
-pub enum TargetI2cError {
-    Send(ConnSendError),
-    Receive(ConnReceiveError),
-    UnexpectedMessage(String),
-}
+    /// Wait for the target to wake up from USART activity
+    ///
+    /// Returns how long it took the target to receive the byte that woke it
+    /// up. The received data itself can be waited for using
+    /// `wait_for_usart_rx`, as usual.
+    pub fn wait_for_usart_wakeup(&mut self, timeout: Duration)
+        -> Result<UsartWakeup, TargetUsartWakeupError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetUsartWakeupError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartWakeup(wakeup) => {
+                Ok(wakeup)
+            }
+            message => {
+                Err(
+                    TargetUsartWakeupError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to echo everything it receives via USART
+    ///
+    /// The target sends every byte back as soon as it has received it, while
+    /// it keeps receiving, for `duration`. Use `wait_for_usart_echo` to get
+    /// the target's report afterwards.
+    pub fn start_usart_echo(&mut self, duration: Duration)
+        -> Result<(), TargetUsartEchoError>
+    {
+        let duration = Microseconds::try_from(duration)
+            .expect("USART echo duration too long");
+
+        self.conn()
+            .send(&HostToTarget::StartUsartEcho { duration })
+            .map_err(|err| TargetUsartEchoError::Send(err))?;
+
+        Ok(())
+    }
+
+    /// Wait for the target to report the end of a USART echo
+    pub fn wait_for_usart_echo(&mut self, timeout: Duration)
+        -> Result<UsartEcho, TargetUsartEchoError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::UsartEchoed(_))
+                )
+            })
+            .map_err(|err| TargetUsartEchoError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartEchoed(echo) => {
+                Ok(echo)
+            }
+            message => {
+                Err(
+                    TargetUsartEchoError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to enter a low-power mode, until it is woken up
+    ///
+    /// Returns once the target is about to enter the mode. The target wakes up
+    /// on `source`, or once `sleep_timeout` has passed, whichever comes first.
+    /// Use `wait_for_wakeup` afterwards.
+    pub fn enter_sleep(&mut self,
+        mode:          SleepMode,
+        source:        WakeupSource,
+        sleep_timeout: Duration,
+        timeout:       Duration,
+    )
+        -> Result<(), TargetSleepError>
+    {
+        let timeout_ms = u32::try_from(sleep_timeout.as_millis())
+            .unwrap_or(u32::MAX);
+
+        self.conn()
+            .send(&HostToTarget::EnterSleep {
+                mode,
+                wakeup_source: source,
+                timeout_ms,
+            })
+            .map_err(|err| TargetSleepError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetSleepError::Receive(err))?;
+
+        match message {
+            TargetToHost::Sleeping => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetSleepError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the target to wake up from `enter_sleep`
+    ///
+    /// Data that the target received in the meantime is left for
+    /// `wait_for_usart_rx`, as usual.
+    pub fn wait_for_wakeup(&mut self, timeout: Duration)
+        -> Result<Wakeup, TargetSleepError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::WokeUp { .. })
+                )
+            })
+            .map_err(|err| TargetSleepError::Receive(err))?;
+
+        match message {
+            TargetToHost::WokeUp { cause, after_ms } => {
+                Ok(
+                    Wakeup {
+                        cause,
+                        after: Duration::from_millis(after_ms.into()),
+                    }
+                )
+            }
+            message => {
+                Err(
+                    TargetSleepError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Enable address matching
+    ///
+    /// The target ignores received data, until it receives `address`. Use
+    /// `wait_for_address_match` afterwards.
+    pub fn wait_for_address(&mut self, address: u8)
+        -> Result<(), TargetWaitForAddressError>
+    {
+        self.conn()
+            .send(&HostToTarget::WaitForAddress(address))
+            .map_err(|err| TargetWaitForAddressError(err))
+    }
+
+    /// Wait for the target to report that it has matched an address
+    ///
+    /// Data that the target received after the address is left for
+    /// `wait_for_usart_rx`. Returns an error, if it times out before the
+    /// address has been matched.
+    pub fn wait_for_address_match(&mut self, timeout: Duration)
+        -> Result<AddressMatch, TargetUsartWaitError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::AddressMatched { .. })
+                )
+            })
+            .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+        match message {
+            TargetToHost::AddressMatched { address, discarded } => {
+                Ok(AddressMatch { address, discarded })
+            }
+            message => {
+                Err(
+                    TargetUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Program `data` into the flash memory of the target, at `address`
+    ///
+    /// Only the flash memory after the firmware image can be written, in whole
+    /// pages (see `FlashInfo`). `data` is limited to `FLASH_BUF_CAP` bytes.
+    ///
+    /// Returns `TargetFlashError::Flash`, if the target reports an error.
+    pub fn flash_write(&mut self, address: u32, data: &[u8], timeout: Duration)
+        -> Result<(), TargetFlashError>
+    {
+        if data.len() > FLASH_BUF_CAP {
+            return Err(TargetFlashError::TooLong);
+        }
+
+        self.conn()
+            .send(&HostToTarget::WriteFlash { address, data })
+            .map_err(|err| TargetFlashError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetFlashError::Receive(err))?;
+
+        match message {
+            TargetToHost::FlashWritten => {
+                Ok(())
+            }
+            TargetToHost::FlashError(err) => {
+                Err(TargetFlashError::Flash(err))
+            }
+            message => {
+                Err(
+                    TargetFlashError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Read `len` bytes from the flash memory of the target, at `address`
+    ///
+    /// Like `flash_write`, this is limited to the flash memory after the
+    /// firmware image, and to `FLASH_BUF_CAP` bytes.
+    pub fn flash_read(&mut self, address: u32, len: usize, timeout: Duration)
+        -> Result<Vec<u8>, TargetFlashError>
+    {
+        if len > FLASH_BUF_CAP {
+            return Err(TargetFlashError::TooLong);
+        }
+
+        self.conn()
+            .send(&HostToTarget::ReadFlash { address, len: len as u32 })
+            .map_err(|err| TargetFlashError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetFlashError::Receive(err))?;
+
+        match message {
+            TargetToHost::FlashRead(data) => {
+                Ok(data.to_vec())
+            }
+            TargetToHost::FlashError(err) => {
+                Err(TargetFlashError::Flash(err))
+            }
+            message => {
+                Err(
+                    TargetFlashError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Take `len` readings of an ADC channel, one every `interval`
+    ///
+    /// `interval` counts from the start of one reading to the start of the
+    /// next. The target doesn't respond to anything else while sampling, so
+    /// `len` times `interval` should be well below [`HEARTBEAT_TIMEOUT`].
+    /// `timeout` applies after the sampling is done.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `interval` doesn't fit into `Microseconds`.
+    pub fn sample_adc(&mut self,
+        channel:  u8,
+        len:      usize,
+        interval: Duration,
+        timeout:  Duration,
+    )
+        -> Result<Vec<Millivolts>, TargetSampleAdcError>
+    {
+        if len > ADC_SAMPLES_CAP {
+            return Err(TargetSampleAdcError::TooLong);
+        }
+
+        let sampling = interval * len as u32;
+        let interval = Microseconds::try_from(interval)
+            .expect("ADC sample interval too long");
+
+        self.conn()
+            .send(&HostToTarget::SampleAdc {
+                channel,
+                len: len as u16,
+                interval,
+            })
+            .map_err(|err| TargetSampleAdcError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(sampling + timeout, &mut tmp)
+            .map_err(|err| TargetSampleAdcError::Receive(err))?;
+
+        match message {
+            TargetToHost::AdcSamples(samples) => {
+                let samples = decode_samples(samples)
+                    .map(|value| Millivolts::from_adc(value, 12, ADC_REFERENCE))
+                    .collect();
+                Ok(samples)
+            }
+            message => {
+                Err(
+                    TargetSampleAdcError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Set the output of a DAC channel
+    ///
+    /// `value` is a raw 12-bit value. The target's DAC only has 10 bits, so it
+    /// ignores the lowest two. Stops a waveform that is being output. The
+    /// target only supports `DAC_OUT`.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `value` doesn't fit into 12 bits.
+    pub fn set_dac_value(&mut self, channel: u8, value: u16)
+        -> Result<(), TargetSetDacValueError>
+    {
+        assert!(value < 1 << 12);
+
+        self.conn()
+            .send(&HostToTarget::SetDacValue { channel, value })
+            .map_err(|err| TargetSetDacValueError(err))
+    }
+
+    /// Output a waveform on a DAC channel
+    ///
+    /// `samples` are raw 12-bit values, like the one passed to
+    /// `set_dac_value`. The target outputs them one after the other, `rate`
+    /// times per second, and starts over after the last one, until the
+    /// returned `DacWaveform` is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `samples` is empty or longer than `DAC_WAVEFORM_CAP`, if any
+    /// of them doesn't fit into 12 bits, or if `rate` is zero or larger than
+    /// `DAC_WAVEFORM_MAX_RATE`.
+    pub fn start_dac_waveform(&mut self,
+        channel: u8,
+        samples: &[u16],
+        rate:    u32,
+    )
+        -> Result<DacWaveform<'_>, TargetStartDacWaveformError>
+    {
+        assert!(!samples.is_empty() && samples.len() <= DAC_WAVEFORM_CAP);
+        assert!(samples.iter().all(|&sample| sample < 1 << 12));
+        assert!(rate > 0 && rate <= DAC_WAVEFORM_MAX_RATE);
+
+        let mut buf = [0; 2 * DAC_WAVEFORM_CAP];
+        let samples = encode_samples(samples.iter().copied(), &mut buf);
+
+        self.conn()
+            .send(&HostToTarget::StartDacWaveform { channel, samples, rate })
+            .map_err(|err| TargetStartDacWaveformError(err))?;
+
+        Ok(DacWaveform(self))
+    }
+
+    /// Start a PWM signal
+    ///
+    /// `duty_permille` is the share of each period that the signal is high, in
+    /// thousandths.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `duty_permille` is larger than 1000, or if `period` doesn't
+    /// fit into `Microseconds`.
+    pub fn start_pwm_signal(&mut self, period: Duration, duty_permille: u16)
+        -> Result<PwmSignal, TargetStartPwmSignalError>
+    {
+        assert!(duty_permille <= 1000);
+        let period = Microseconds::try_from(period)
+            .expect("PWM period too long");
+
+        self.conn()
+            .send(&HostToTarget::StartPwmSignal { period, duty_permille })
+            .map_err(|err| TargetStartPwmSignalError(err))?;
+
+        Ok(PwmSignal(self))
+    }
+
+    /// Start the MRT with the given interval
+    ///
+    /// The target toggles the blue LED and reports each expiration, until the
+    /// returned `Mrt` is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `interval` is not shorter than `MRT_MAX_INTERVAL`.
+    pub fn start_mrt(&mut self, mode: TimerMode, interval: Duration)
+        -> Result<Mrt<'_>, TargetMrtError>
+    {
+        assert!(interval < MRT_MAX_INTERVAL, "MRT interval too long");
+        let interval = Microseconds::try_from(interval)
+            .expect("MRT interval too long");
+
+        self.conn()
+            .send(&HostToTarget::StartMrt { mode, interval })
+            .map_err(|err| TargetMrtError::Send(err))?;
+
+        Ok(Mrt(self))
+    }
+
+    /// Start scanning the capacitive touch sensors on the X pins in `pins`
+    ///
+    /// Bit `n` of `pins` selects X pin `n`. Measurements that count more than
+    /// `threshold` cycles are touches. The target reports the first measurement
+    /// of each pin, and every time a pin crosses the threshold after that,
+    /// until the returned `Capt` is dropped.
+    pub fn start_capt(&mut self, pins: u16, threshold: u16)
+        -> Result<Capt<'_>, TargetCaptError>
+    {
+        self.conn()
+            .send(&HostToTarget::StartCapt { pins, threshold })
+            .map_err(|err| TargetCaptError::Send(err))?;
+
+        Ok(Capt(self))
+    }
+
+    /// Start an I2C transaction
+    ///
+    /// Writes `write` to the slave at the 7-bit `address`, then reads
+    /// `read_len` bytes from it, and returns those. Either part is skipped, if
+    /// it is empty. Both are limited to `I2C_BUF_CAP` bytes.
+    ///
+    /// Returns `TargetI2cError::Nack`, if the slave doesn't acknowledge, and
+    /// `TargetI2cError::Bus`, if the transaction fails for another reason.
+    pub fn start_i2c_transaction(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(
+            address,
+            write,
+            read_len,
+            timeout,
+            DmaMode::Regular,
+        )
+    }
+
+    /// Start an I2C/DMA transaction
+    ///
+    /// See `start_i2c_transaction`.
+    pub fn start_i2c_transaction_dma(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(
+            address,
+            write,
+            read_len,
+            timeout,
+            DmaMode::Dma,
+        )
+    }
+
+    fn start_i2c_transaction_inner(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+        mode:     DmaMode,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        if address > 0x7f {
+            return Err(TargetI2cError::InvalidAddress);
+        }
+        if write.len() > I2C_BUF_CAP || read_len > I2C_BUF_CAP {
+            return Err(TargetI2cError::TooLong);
+        }
+
+        self.conn()
+            .send(
+                &HostToTarget::StartI2cTransaction {
+                    mode,
+                    address,
+                    write,
+                    read_len: read_len as u16,
+                }
+            )
+            .map_err(|err| TargetI2cError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetI2cError::Receive(err))?;
+
+        match message {
+            TargetToHost::I2cReply(reply) => {
+                Ok(reply.to_vec())
+            }
+            TargetToHost::I2cNack => {
+                Err(TargetI2cError::Nack)
+            }
+            TargetToHost::I2cError => {
+                Err(TargetI2cError::Bus)
+            }
+            message => {
+                Err(
+                    TargetI2cError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Start an SPI transaction
+    ///
+    /// Writes `write` to the slave, then reads `read_len` bytes from it, and
+    /// returns those. Either part is skipped, if it is empty. Both combined are
+    /// limited to `SPI_BUF_CAP` bytes.
+    pub fn start_spi_transaction(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        self.start_spi_transaction_inner(
+            write,
+            read_len,
+            timeout,
+            DmaMode::Regular,
+        )
+    }
+
+    /// Start an SPI/DMA transaction
+    ///
+    /// See `start_spi_transaction`.
+    pub fn start_spi_transaction_dma(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        self.start_spi_transaction_inner(write, read_len, timeout, DmaMode::Dma)
+    }
+
+    fn start_spi_transaction_inner(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+        mode:     DmaMode,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        if write.len() + read_len > SPI_BUF_CAP {
+            return Err(TargetSpiError::TooLong);
+        }
+
+        self.conn()
+            .send(
+                &HostToTarget::StartSpiTransaction {
+                    mode,
+                    write,
+                    read_len: read_len as u16,
+                }
+            )
+            .map_err(|err| TargetSpiError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn().receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiReplyBuffer(reply) => {
+                Ok(reply.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to act as an SPI slave for one transaction
+    ///
+    /// Returns once the target is ready. It sends `response` in the following
+    /// transaction, then `0xff`. The response is limited to `SPI_BUF_CAP`
+    /// bytes. Use `wait_for_spi_slave_transfer` once the master has ended the
+    /// transaction.
+    pub fn expect_spi_slave_transfer(&mut self,
+        response: &[u8],
+        timeout:  Duration,
+    )
+        -> Result<(), TargetSpiSlaveError>
+    {
+        if response.len() > SPI_BUF_CAP {
+            return Err(TargetSpiSlaveError::TooLong);
+        }
+
+        self.conn()
+            .send(&HostToTarget::ExpectSpiSlaveTransfer { response })
+            .map_err(|err| TargetSpiSlaveError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetSpiSlaveError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiSlaveReady => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetSpiSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the target's SPI slave transaction to end
+    ///
+    /// Returns the data the target received from the master, up to
+    /// `SPI_BUF_CAP` bytes. The target is back in master mode afterwards.
+    pub fn wait_for_spi_slave_transfer(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, TargetSpiSlaveError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn()
+            .receive::<TargetToHost>(timeout, &mut tmp)
+            .map_err(|err| TargetSpiSlaveError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiSlaveReceived(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+impl Deref for Target {
+    type Target = target::Target<Lpc845>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Target {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl TargetRequests for Target {
+    fn conn(&mut self) -> &mut Conn {
+        self.0.conn()
+    }
+}
+
+impl HasTimeouts for Target {
+    fn timeouts_mut(&mut self) -> &mut Timeouts {
+        self.0.timeouts_mut()
+    }
+}
+
+impl GpioTarget for Target {
+    type Error = TargetGpioError;
+
+    fn set_output(&mut self, level: pin::Level) -> Result<(), Self::Error> {
+        match level {
+            pin::Level::High => {
+                self.set_pin_high(GPIO_OUT)
+                    .map_err(|err| TargetGpioError::SetPinHigh(err))
+            }
+            pin::Level::Low => {
+                self.set_pin_low(GPIO_OUT)
+                    .map_err(|err| TargetGpioError::SetPinLow(err))
+            }
+        }
+    }
+
+    fn read_input(&mut self) -> Result<pin::Level, Self::Error> {
+        let is_high = self.pin_is_high(GPIO_IN)
+            .map_err(|err| TargetGpioError::PinRead(err))?;
+
+        if is_high {
+            Ok(pin::Level::High)
+        }
+        else {
+            Ok(pin::Level::Low)
+        }
+    }
+
+    fn host_link_uses_pins(&mut self) -> Result<bool, Self::Error> {
+        let timeout = self.timeouts().reply();
+        let host_link = self.capabilities(timeout)
+            .map_err(|err| TargetGpioError::Capabilities(err))?
+            .host_link;
+
+        Ok(host_link.uses_pin(GPIO_OUT) || host_link.uses_pin(GPIO_IN))
+    }
+}
+
+
+/// The state of the shared line, as seen by the target
+///
+/// Returned by [`Target::drive_shared_line`].
+#[derive(Debug)]
+pub struct SharedLine {
+    /// The level of the line, once it has settled
+    pub level: pin::Level,
+
+    /// How long the line took to reach the level the target drove it to
+    ///
+    /// `None`, if it didn't reach it within 1 ms, because another test node
+    /// pulls the line low.
+    pub settle_time: Option<Microseconds>,
+}
+
+
+/// An address that the target has matched
+///
+/// Returned by [`Target::wait_for_address_match`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AddressMatch {
+    /// The address, as received
+    ///
+    /// Includes the address bit, unless 9 data bits are configured.
+    pub address: u8,
+
+    /// The number of words the target discarded before the address
+    pub discarded: u32,
+}
+
+
+/// How the target woke up from a low-power mode
+///
+/// Returned by [`Target::wait_for_wakeup`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Wakeup {
+    pub cause: WakeupCause,
+
+    /// How long the target was in the low-power mode
+    ///
+    /// Measured by the target, with a resolution of 1 ms.
+    pub after: Duration,
+}
+
+
+/// The MRT on the target, while it is running
+///
+/// The MRT will be stopped when this struct is dropped.
+pub struct Mrt<'r>(&'r mut Target);
+
+impl Mrt<'_> {
+    /// Wait for the MRT to expire
+    ///
+    /// Returns the timestamp of the expiration, as measured by the target.
+    /// Returns an error, if it times out before the MRT expires.
+    pub fn wait_for_expiration(&mut self, timeout: Duration)
+        -> Result<u32, TargetMrtError>
+    {
+        let mut tmp = Vec::new();
+        let message = (self.0).conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::MrtExpired { .. })
+                )
+            })
+            .map_err(|err| TargetMrtError::Receive(err))?;
+
+        match message {
+            TargetToHost::MrtExpired { timestamp } => {
+                Ok(timestamp)
+            }
+            message => {
+                Err(
+                    TargetMrtError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+impl Drop for Mrt<'_> {
+    fn drop(&mut self) {
+        (self.0).conn().send(&HostToTarget::StopMrt)
+            .unwrap()
+    }
+}
+
+
+/// The target's capacitive touch sensors, while they are being scanned
+///
+/// Scanning will be stopped when this struct is dropped.
+pub struct Capt<'r>(&'r mut Target);
+
+impl Capt<'_> {
+    /// Wait for the target to report a measurement
+    ///
+    /// Returns an error, if it times out before the target does.
+    pub fn wait_for_event(&mut self, timeout: Duration)
+        -> Result<CaptEvent, TargetCaptError>
+    {
+        let mut tmp = Vec::new();
+        let message = (self.0).conn()
+            .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(TargetToHost::CaptEvent(_))
+                )
+            })
+            .map_err(|err| TargetCaptError::Receive(err))?;
+
+        match message {
+            TargetToHost::CaptEvent(event) => {
+                Ok(event)
+            }
+            message => {
+                Err(
+                    TargetCaptError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+impl Drop for Capt<'_> {
+    fn drop(&mut self) {
+        (self.0).conn().send(&HostToTarget::StopCapt)
+            .unwrap()
+    }
+}
+
+
+/// A waveform that the target's DAC is outputting
+///
+/// The waveform will be stopped when this struct is dropped.
+pub struct DacWaveform<'r>(&'r mut Target);
+
+impl DacWaveform<'_> {
+    /// Sample an ADC channel, while the waveform is being output
+    ///
+    /// See `Target::sample_adc`.
+    pub fn sample_adc(&mut self,
+        channel:  u8,
+        len:      usize,
+        interval: Duration,
+        timeout:  Duration,
+    )
+        -> Result<Vec<Millivolts>, TargetSampleAdcError>
+    {
+        self.0.sample_adc(channel, len, interval, timeout)
+    }
+}
+
+impl Drop for DacWaveform<'_> {
+    fn drop(&mut self) {
+        (self.0).conn().send(&HostToTarget::StopDacWaveform)
+            .unwrap()
+    }
+}
+
+
+/// Represent an ongoing PWM signal that's currently configured on the target
+///
+/// This PWM signal will be stopped when this struct is dropped.
+pub struct PwmSignal<'r>(&'r mut Target);
+
+impl Drop for PwmSignal<'_> {
+    fn drop(&mut self) {
+        (self.0).conn().send(&HostToTarget::StopPwmSignal)
+            .unwrap()
+    }
+}
+
+
+#[derive(Debug)]
+pub enum TargetExpectFaultError {
+    Resync(ConnResyncError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+
+    /// The fault has been reported, but restoring the connection failed
+    Recover(TargetRenegotiateError),
+}
+
+#[derive(Debug)]
+pub enum TargetDumpEventsError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetSetIrqPriorityError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetRunIrqWorkloadError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetResetError {
+    Send(ConnSendError),
+    Resync(ConnResyncError),
+
+    /// The target has been reset, but restoring the connection failed
+    Recover(TargetRenegotiateError),
+}
+
+#[derive(Debug)]
+pub enum TargetResyncLinkError {
+    Resync(ConnResyncError),
+
+    /// The link has been resynchronized, but restoring the connection failed
+    Recover(TargetRenegotiateError),
+}
+
+#[derive(Debug)]
+pub struct TargetSendPartialRequestError(ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetGpioError {
+    SetPinHigh(TargetSetPinHighError),
+    SetPinLow(TargetSetPinLowError),
+    PinRead(TargetPinReadError),
+    Capabilities(TargetCapabilitiesError),
+}
+
+#[derive(Debug)]
+pub enum TargetConfigureUsartError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetDriveSharedLineError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub struct TargetStartPwmSignalError(ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetSetDacValueError(ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetStartDacWaveformError(ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetSampleAdcError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    TooLong,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetPinInterruptError {
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetUsartWakeupError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetUsartEchoError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetSleepError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub struct TargetWaitForAddressError(pub(crate) ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetFlashError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    Flash(FlashError),
+    TooLong,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetCaptError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetMrtError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetI2cError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    InvalidAddress,
+    Nack,
+    Bus,
+    TooLong,
+    UnexpectedMessage(String),
+}
 
 #[derive(Debug)]
 pub enum TargetSpiError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+    TooLong,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetSpiSlaveError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    TooLong,
     UnexpectedMessage(String),
 }
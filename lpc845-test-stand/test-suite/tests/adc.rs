@@ -0,0 +1,35 @@
+//! Test Suite for the ADC API in LPC8xx HAL
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::thread::sleep;
+
+use lpc845_test_suite::prelude::*;
+
+
+#[test]
+fn it_should_read_adc_values() -> Result {
+    let mut test_stand = TestStand::new()?;
+    let tolerances = test_stand.tolerances().clone();
+
+    let timeout = Duration::from_millis(50);
+
+    // The assistant and the target are separate connections, so give the
+    // level some time to change, before reading it.
+    test_stand.assistant.set_pin_5_low()?;
+    sleep(Duration::from_millis(10));
+    let voltage = test_stand.target.read_adc(ADC_IN, timeout)?;
+    let error_mv = f64::from(voltage.0);
+    assert!(error_mv <= tolerances.adc_offset_mv(), "{:?}", voltage);
+
+    test_stand.assistant.set_pin_5_high()?;
+    sleep(Duration::from_millis(10));
+    let voltage = test_stand.target.read_adc(ADC_IN, timeout)?;
+    let error_mv = f64::from(ADC_REFERENCE.0 - voltage.0);
+    assert!(error_mv <= tolerances.adc_full_scale_error_mv(),
+        "{:?}", voltage);
+
+    Ok(())
+}
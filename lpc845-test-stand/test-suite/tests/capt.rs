@@ -0,0 +1,76 @@
+//! Test Suite for the capacitive touch (CAPT) peripheral of the target hardware
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use lpc845_messages::CAPT_MAX_COUNT;
+use lpc845_test_suite::{
+    prelude::*,
+    target::{
+        CAPT_X,
+        TargetCaptError,
+    },
+};
+
+
+/// Only a measurement that times out is above this threshold
+///
+/// That's what a grounded sensor looks like, regardless of how much
+/// capacitance the wiring adds.
+const THRESHOLD: u16 = CAPT_MAX_COUNT - 1;
+
+
+#[test]
+fn it_should_report_the_initial_state_of_a_sensor() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(100);
+
+    test_stand.assistant.simulate_touch(false, timeout)?;
+
+    // When `capt` is dropped, scanning will be stopped.
+    let mut capt = test_stand.target.start_capt(1 << CAPT_X, THRESHOLD)?;
+
+    let event = capt.wait_for_event(timeout)?;
+    assert_eq!(event.pin, CAPT_X);
+    assert!(!event.touched, "{:?}", event);
+    assert!(event.count <= THRESHOLD, "{:?}", event);
+
+    // Nothing has changed, so there's nothing more to report.
+    let result = capt.wait_for_event(timeout);
+    assert!(matches!(
+        result,
+        Err(TargetCaptError::Receive(err)) if err.is_timeout()
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_simulated_touches() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(100);
+
+    test_stand.assistant.simulate_touch(false, timeout)?;
+
+    let mut capt = test_stand.target.start_capt(1 << CAPT_X, THRESHOLD)?;
+    let released = capt.wait_for_event(timeout)?;
+    assert!(!released.touched, "{:?}", released);
+
+    test_stand.assistant.simulate_touch(true, timeout)?;
+
+    let touched = capt.wait_for_event(timeout)?;
+    assert_eq!(touched.pin, CAPT_X);
+    assert!(touched.touched, "{:?}", touched);
+    assert_eq!(touched.count, CAPT_MAX_COUNT);
+
+    test_stand.assistant.simulate_touch(false, timeout)?;
+
+    let released = capt.wait_for_event(timeout)?;
+    assert_eq!(released.pin, CAPT_X);
+    assert!(!released.touched, "{:?}", released);
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! Test Suite for the DAC of the target hardware
+//!
+//! The HAL doesn't support the DAC yet, so the target firmware uses it through
+//! its registers. The target reads back its own output through the ADC.
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::thread::sleep;
+
+use host_lib::report;
+use lpc845_messages::units::Millivolts;
+use lpc845_test_suite::prelude::*;
+
+
+#[test]
+fn it_should_output_dac_values_that_the_adc_reads_back() -> Result {
+    let mut test_stand = TestStand::new()?;
+    let tolerances = test_stand.tolerances().clone();
+
+    let timeout = Duration::from_millis(50);
+
+    for &value in &[0, 1024, 2048, 3072, 4095] {
+        test_stand.target.set_dac_value(DAC_OUT, value)?;
+
+        // The request to set the value has no reply, so give the target some
+        // time to handle it.
+        sleep(Duration::from_millis(10));
+        let voltage = test_stand.target.read_adc(DAC_LOOPBACK, timeout)?;
+
+        // The DAC only has 10 bits, so the lowest two are dropped.
+        let expected = Millivolts::from_adc(value >> 2, 10, ADC_REFERENCE);
+        let error_mv = f64::from(
+            Ord::max(voltage, expected).0 - Ord::min(voltage, expected).0
+        );
+        assert!(error_mv <= tolerances.adc_full_scale_error_mv(),
+            "{:?} != {:?}", voltage, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_output_a_waveform_with_the_requested_amplitude_and_period()
+    -> Result
+{
+    let mut test_stand = TestStand::new()?;
+    let tolerances = test_stand.tolerances().clone();
+
+    // A square wave with a period of 16 samples, or 2 ms.
+    let rate    = 8000;
+    let samples = [[4095; 8], [0; 8]].concat();
+    let period  = Duration::from_secs(1) * samples.len() as u32 / rate;
+
+    // Sample a few periods, at a 20th of the period.
+    let interval = period / 20;
+    let len      = 64;
+    let timeout  = Duration::from_millis(50);
+
+    // When `waveform` is dropped, the waveform will be stopped.
+    let mut waveform = test_stand.target
+        .start_dac_waveform(DAC_OUT, &samples, rate)?;
+    let voltages =
+        waveform.sample_adc(DAC_LOOPBACK, len, interval, timeout)?;
+
+    let min = voltages.iter().min().unwrap();
+    let max = voltages.iter().max().unwrap();
+    assert!(f64::from(min.0) <= tolerances.adc_offset_mv(), "{:?}", voltages);
+    assert!(
+        f64::from(ADC_REFERENCE.0 - max.0)
+            <= tolerances.adc_full_scale_error_mv(),
+        "{:?}", voltages,
+    );
+
+    // Find the rising edges, where the signal crosses half the reference.
+    let threshold = ADC_REFERENCE.0 / 2;
+    let rising: Vec<_> = voltages
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0].0 < threshold && pair[1].0 >= threshold)
+        .map(|(i, _)| i)
+        .collect();
+    assert!(rising.len() >= 2, "{:?}", voltages);
+
+    let first    = rising[0];
+    let last     = rising[rising.len() - 1];
+    let periods  = (rising.len() - 1) as u32;
+    let measured = interval * (last - first) as u32 / periods;
+    report::measure(
+        "dac_waveform_period",
+        measured.as_secs_f64() * 1_000_000.0,
+        "us",
+    )?;
+
+    // Each edge is only known to within one sample interval.
+    assert!(measured >= period * 9/10, "{:?}", measured);
+    assert!(measured <= period * 11/10, "{:?}", measured);
+
+    Ok(())
+}
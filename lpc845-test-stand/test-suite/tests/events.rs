@@ -0,0 +1,52 @@
+//! Test Suite for the event history of the target firmware
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use host_lib::schema::Schema;
+use lpc845_messages::{
+    SCHEMA,
+    event::Kind,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+#[test]
+fn it_should_record_the_requests_it_has_received() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.ping(timeout)?;
+
+    let events = test_stand.target.dump_events(timeout)?;
+
+    let requests: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event.kind {
+            Kind::Request(variant) => Some(request_name(variant)),
+            _                      => None,
+        })
+        .collect();
+    assert!(requests.ends_with(&["Ping".to_owned(), "DumpEvents".to_owned()]));
+
+    // Events are returned oldest first.
+    assert!(events.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+
+    Ok(())
+}
+
+
+/// Look up the name of a request, by the index of its variant
+fn request_name(variant: u8) -> String {
+    let schema = Schema::parse(SCHEMA).unwrap();
+    schema.type_def("", "HostToTarget")
+        .and_then(|ty| ty.variant(variant.into()))
+        .map(|variant| variant.name.clone())
+        .unwrap_or_else(|| format!("<unknown request {}>", variant))
+}
@@ -0,0 +1,121 @@
+//! Test Suite for programming the flash memory of the target, using IAP
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use host_lib::payload::{
+    Pattern,
+    Payload,
+};
+use lpc845_messages::{
+    FLASH_BUF_CAP,
+    FlashError,
+    FlashInfo,
+    TargetRequests as _,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+    target::TargetFlashError,
+};
+
+
+#[test]
+fn it_should_write_and_read_back_flash() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(200);
+    let flash   = test_stand.target.flash_info(timeout)?;
+    let address = scratch_address(&flash);
+
+    // Write twice, to make sure the pages are erased before they're programmed
+    // again.
+    for seed in 0 .. 2 {
+        let payload = Payload::new(Pattern::Lfsr, seed);
+        let data    = payload.generate(FLASH_BUF_CAP);
+
+        test_stand.target.flash_write(address, &data, timeout)?;
+        let read = test_stand.target.flash_read(address, data.len(), timeout)?;
+
+        payload.verify(&read, data.len())?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_unaligned_flash_writes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(200);
+    let flash   = test_stand.target.flash_info(timeout)?;
+    let address = scratch_address(&flash);
+    let data    = vec![0x55; flash.page_size as usize];
+
+    let result = test_stand.target.flash_write(address + 4, &data, timeout);
+    assert!(matches!(
+        result,
+        Err(TargetFlashError::Flash(FlashError::Unaligned))
+    ));
+
+    let result = test_stand.target.flash_write(address, &data[1..], timeout);
+    assert!(matches!(
+        result,
+        Err(TargetFlashError::Flash(FlashError::Unaligned))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_flash_access_outside_of_the_scratch_area() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(200);
+    let flash   = test_stand.target.flash_info(timeout)?;
+    let data    = vec![0x55; flash.page_size as usize];
+    let end     = flash.start + flash.size - flash.reserved;
+
+    // The target must not overwrite its own firmware, or its key-value store.
+    let result = test_stand.target.flash_write(flash.start, &data, timeout);
+    assert!(matches!(
+        result,
+        Err(TargetFlashError::Flash(FlashError::OutOfBounds))
+    ));
+
+    let result = test_stand.target.flash_write(end, &data, timeout);
+    assert!(matches!(
+        result,
+        Err(TargetFlashError::Flash(FlashError::OutOfBounds))
+    ));
+
+    let result = test_stand.target.flash_read(flash.start, 4, timeout);
+    assert!(matches!(
+        result,
+        Err(TargetFlashError::Flash(FlashError::OutOfBounds))
+    ));
+
+    let address = end - flash.page_size;
+    let len     = 2 * flash.page_size as usize;
+    let result  = test_stand.target.flash_read(address, len, timeout);
+    assert!(matches!(
+        result,
+        Err(TargetFlashError::Flash(FlashError::OutOfBounds))
+    ));
+
+    Ok(())
+}
+
+
+/// The address of the last `FLASH_BUF_CAP` bytes before the reserved area
+///
+/// That's as far away from the firmware image as it gets.
+fn scratch_address(flash: &FlashInfo) -> u32 {
+    let end     = flash.start + flash.size - flash.reserved;
+    let address = end - FLASH_BUF_CAP as u32;
+    assert!(address >= flash.start + flash.used, "{:?}", flash);
+    address
+}
@@ -4,9 +4,38 @@
 //! wiring instructions.
 
 
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use host_lib::{
+    latency::DeadlineMonitor,
+    measurement::InterruptLatency,
+    report,
+    timestamp,
+};
+use lpc845_messages::{
+    InputPin,
+    OutputPin,
+    TargetRequests as _,
+    pin,
+    trigger,
+};
 use lpc845_test_suite::{
     Result,
     TestStand,
+    target::{
+        GPIO_GROUP_IN,
+        GPIO_GROUP_OUT,
+        GPIO_IN,
+        GPIO_OUT,
+        TargetPinInterruptError,
+    },
+};
+use test_cases::gpio::{
+    self,
+    Bounds,
 };
 
 
@@ -14,10 +43,126 @@ use lpc845_test_suite::{
 fn it_should_set_pin_level() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    test_stand.target.set_pin_low()?;
+    test_stand.target.set_pin_low(GPIO_OUT)?;
     assert!(test_stand.assistant.pin_is_low()?);
 
-    test_stand.target.set_pin_high()?;
+    test_stand.target.set_pin_high(GPIO_OUT)?;
+    assert!(test_stand.assistant.pin_is_high()?);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_set_other_output_pin_level() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // PIO1_1 of the target is wired to this assistant input.
+    let blue_pin = pin::PortPin::new(1, 1);
+    let blue     = 1 << InputPin::Blue as u32;
+
+    test_stand.target.set_pin_low(blue_pin)?;
+    assert_eq!(test_stand.assistant.read_pin_group(blue, timeout)?, 0);
+
+    test_stand.target.set_pin_high(blue_pin)?;
+    assert_eq!(test_stand.assistant.read_pin_group(blue, timeout)?, blue);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_unsupported_pin() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // PIO0_0 isn't wired to anything the host may access. The target must
+    // refuse, instead of resetting.
+    let unsupported = pin::PortPin::new(0, 0);
+    assert!(test_stand.target.pin_is_high(unsupported).is_err());
+
+    test_stand.target.ping(Duration::from_millis(50))?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_set_pin_group_levels() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // PIO1_0 and PIO1_1 of the target are wired to these assistant inputs.
+    let green = 1 << InputPin::Green as u32;
+    let blue  = 1 << InputPin::Blue as u32;
+
+    let cases = [
+        (0b00, 0),
+        (0b01, green),
+        (0b10, blue),
+        (0b11, green | blue),
+    ];
+    for &(value, expected) in &cases {
+        test_stand.target.set_pin_group(GPIO_GROUP_OUT, value, timeout)?;
+        let levels =
+            test_stand.assistant.read_pin_group(green | blue, timeout)?;
+        assert_eq!(levels, expected, "value: {:#b}", value);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_pin_group_levels() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The target's PIO1_2 is wired to this assistant output.
+    let red = 1 << OutputPin::Red as u32;
+
+    for &(value, expected) in &[(0, 0), (red, GPIO_GROUP_IN)] {
+        test_stand.assistant.set_pin_group(red, value, timeout)?;
+        let levels = test_stand.target.read_pin_group(GPIO_GROUP_IN, timeout)?;
+        assert_eq!(levels, expected, "value: {:#b}", value);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_apply_pull_resistors_to_reconfigured_pin() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // While the target pin is an input, only its pull resistor determines the
+    // level the assistant sees.
+    test_stand.target.configure_pin(
+        GPIO_OUT, pin::Direction::Input, pin::Pull::Down, false, timeout)?;
+    assert!(test_stand.assistant.pin_is_low()?);
+
+    test_stand.target.configure_pin(
+        GPIO_OUT, pin::Direction::Input, pin::Pull::Up, false, timeout)?;
+    assert!(test_stand.assistant.pin_is_high()?);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_drive_reconfigured_pin_as_open_drain() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // An open-drain output only pulls the pin low. When set high, it releases
+    // the pin to the pull-up.
+    test_stand.target.configure_pin(
+        GPIO_OUT, pin::Direction::Output, pin::Pull::Up, true, timeout)?;
+
+    test_stand.target.set_pin_low(GPIO_OUT)?;
+    assert!(test_stand.assistant.pin_is_low()?);
+
+    test_stand.target.set_pin_high(GPIO_OUT)?;
     assert!(test_stand.assistant.pin_is_high()?);
 
     Ok(())
@@ -28,10 +173,24 @@ fn it_should_read_input_level() -> Result {
     let mut test_stand = TestStand::new()?;
 
     test_stand.assistant.set_pin_low()?;
-    assert!(test_stand.target.pin_is_low()?);
+    assert!(test_stand.target.pin_is_low(GPIO_IN)?);
 
     test_stand.assistant.set_pin_high()?;
-    assert!(test_stand.target.pin_is_high()?);
+    assert!(test_stand.target.pin_is_high(GPIO_IN)?);
+
+    // A slower read gives the same result. Afterwards, the configured
+    // timeouts apply again.
+    let read = test_stand.timeouts().read() * 5;
+    test_stand.assistant.set_pin_low()?;
+    assert!(
+        test_stand.target
+            .with_timeouts(|timeouts| timeouts.with_read(read))
+            .pin_is_low(GPIO_IN)?
+    );
+    assert_eq!(
+        test_stand.target.timeouts().read(),
+        test_stand.timeouts().read(),
+    );
 
     Ok(())
 }
@@ -42,3 +201,217 @@ fn it_should_read_input_level_without_level_change() -> Result {
     assert!(test_stand.assistant.pin_is_high()?);
     Ok(())
 }
+
+#[test]
+fn it_should_conform_to_gpio_latency_bounds() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Latencies are measured from the host, and every read of a pin level
+    // takes at least 10 ms, so the default bounds are generous.
+    let bounds = Bounds::from_tolerances(test_stand.tolerances());
+
+    let report = gpio::run(
+        &mut test_stand.target,
+        &mut test_stand.assistant,
+        &bounds,
+        10,
+    )?;
+
+    // `run` only skips the measurements, if the host link uses the pins under
+    // test. Then there's nothing to check.
+    if let Some(report) = report {
+        for latency in &[report.set, report.clear] {
+            assert!(latency.max <= bounds.output, "{:#?}", report);
+        }
+        assert!(report.input.max <= bounds.input, "{:#?}", report);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_act_on_trigger() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target.set_pin_low(GPIO_OUT)?;
+    test_stand.assistant.set_pin_high()?;
+
+    test_stand.target.arm_on_trigger(
+        trigger::Action::SetLevel(
+            pin::SetLevel {
+                pin:   GPIO_OUT,
+                level: pin::Level::High,
+            }
+        ),
+        Duration::from_millis(50),
+    )?;
+    test_stand.assistant.arm_on_trigger(
+        trigger::Action::SetLevel(
+            pin::SetLevel {
+                pin:   OutputPin::Red,
+                level: pin::Level::Low,
+            }
+        ),
+    )?;
+    test_stand.assistant.fire_trigger()?;
+
+    assert!(test_stand.assistant.pin_is_high()?);
+    assert!(test_stand.target.pin_is_low(GPIO_IN)?);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_pin_interrupts() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    test_stand.assistant.set_pin_high()?;
+    test_stand.target.report_pin_interrupts(true, timeout)?;
+
+    let start = Instant::now();
+
+    test_stand.assistant.set_pin_low()?;
+    let falling = test_stand.target
+        .wait_for_pin_interrupt(pin::Edge::Falling, timeout)?;
+
+    test_stand.assistant.set_pin_high()?;
+    let rising = test_stand.target
+        .wait_for_pin_interrupt(pin::Edge::Rising, timeout)?;
+
+    let elapsed = start.elapsed();
+
+    test_stand.target.report_pin_interrupts(false, timeout)?;
+
+    // Both edges happened while the host was waiting for them, so the target
+    // can't have measured more time between them than the host did.
+    let interval = timestamp::interval(falling, rising);
+    assert!(interval > Duration::ZERO);
+    assert!(interval < elapsed, "{:?} >= {:?}", interval, elapsed);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_invalid_pin_filter() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The filter can't sample more than 3 times. The target must refuse,
+    // instead of resetting.
+    assert!(test_stand.target.set_pin_filter(GPIO_IN, 4, 12, timeout).is_err());
+
+    test_stand.target.ping(timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_filter_short_pulses() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The filter clock runs at 1 MHz, so pulses shorter than 3 µs are
+    // rejected.
+    test_stand.assistant.set_pin_high()?;
+    test_stand.target.set_pin_filter(GPIO_IN, 3, 12, timeout)?;
+    test_stand.target.report_pin_interrupts(true, timeout)?;
+
+    test_stand.assistant
+        .send_pulse(OutputPin::Red, Duration::from_nanos(50), timeout)?;
+    let result = test_stand.target
+        .wait_for_pin_interrupt(pin::Edge::Falling, timeout);
+    assert!(matches!(
+        result,
+        Err(TargetPinInterruptError::Receive(err)) if err.is_timeout()
+    ));
+
+    test_stand.assistant
+        .send_pulse(OutputPin::Red, Duration::from_micros(10), timeout)?;
+    test_stand.target.wait_for_pin_interrupt(pin::Edge::Falling, timeout)?;
+    test_stand.target.wait_for_pin_interrupt(pin::Edge::Rising, timeout)?;
+
+    test_stand.target.report_pin_interrupts(false, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_deliver_pin_interrupts_within_the_deadline() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout  = Duration::from_millis(50);
+    let deadline = Duration::from_millis(
+        test_stand.tolerances().notification_latency_ms()
+    );
+
+    test_stand.assistant.set_pin_high()?;
+    test_stand.target.report_pin_interrupts(true, timeout)?;
+
+    let clock = test_stand.target.sync_clock(10, timeout)?;
+    let mut monitor = DeadlineMonitor::new(clock, deadline);
+
+    for _ in 0 .. 50 {
+        test_stand.assistant.set_pin_low()?;
+        let timestamp = test_stand.target
+            .wait_for_pin_interrupt(pin::Edge::Falling, timeout)?;
+        monitor.record(timestamp, Instant::now());
+
+        test_stand.assistant.set_pin_high()?;
+        let timestamp = test_stand.target
+            .wait_for_pin_interrupt(pin::Edge::Rising, timeout)?;
+        monitor.record(timestamp, Instant::now());
+    }
+
+    test_stand.target.report_pin_interrupts(false, timeout)?;
+
+    // The deadline is soft. Tolerate the odd notification that is delayed,
+    // for example by the host's scheduler.
+    let tolerated = monitor.histogram().len() / 100;
+    assert!(monitor.missed() <= tolerated, "{}", monitor);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_handle_pin_interrupts_with_low_latency() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    test_stand.assistant.set_pin_high()?;
+    test_stand.target.report_pin_interrupts(true, timeout)?;
+
+    let mut latency = InterruptLatency::new();
+
+    for _ in 0 .. 50 {
+        test_stand.assistant.set_pin_low()?;
+        let capture = test_stand.target
+            .wait_for_pin_capture(pin::Edge::Falling, timeout)?;
+        latency.record(capture);
+
+        test_stand.assistant.set_pin_high()?;
+        let capture = test_stand.target
+            .wait_for_pin_capture(pin::Edge::Rising, timeout)?;
+        latency.record(capture);
+    }
+
+    test_stand.target.report_pin_interrupts(false, timeout)?;
+
+    let micros = |latency: Duration| latency.as_secs_f64() * 1_000_000.0;
+    let mean   = micros(latency.mean().unwrap());
+    let max    = micros(latency.max().unwrap());
+    report::measure("interrupt_latency_mean", mean, "us")?;
+    report::measure("interrupt_latency_max",  max,  "us")?;
+
+    // The handler is entered within a few microseconds, unless it is held up
+    // by a critical section in the firmware. None of those should take
+    // anywhere near this long.
+    let max = latency.max().unwrap();
+    assert!(max < Duration::from_micros(500), "{}", latency);
+
+    Ok(())
+}
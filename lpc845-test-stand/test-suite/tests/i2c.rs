@@ -4,23 +4,43 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::time::{
+    Duration,
+    Instant,
+};
 
+use lpc845_messages::{
+    i2c::{
+        Nack,
+        SlaveConfig,
+        TransactionLog,
+    },
+    units::Microseconds,
+};
 use lpc845_test_suite::{
     Result,
     TestStand,
+    target::TargetI2cError,
 };
 
 
+/// The address of the test assistant's I2C slave
+const ADDRESS: u8 = 0x48;
+
+
 #[test]
 fn it_should_start_a_transaction() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    let data = 0x22;
     let timeout = Duration::from_millis(50);
-    let reply = test_stand.target.start_i2c_transaction(data, timeout)?;
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+    test_stand.assistant
+        .write_i2c_registers(0x10, &[0x22, 0x44, 0x66], timeout)?;
+
+    let reply = test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x10], 3, timeout)?;
 
-    assert_eq!(reply, data << 1);
+    assert_eq!(reply, [0x22, 0x44, 0x66]);
 
     Ok(())
 }
@@ -29,11 +49,207 @@ fn it_should_start_a_transaction() -> Result {
 fn it_should_start_a_transaction_using_dma() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    let data = 0x22;
     let timeout = Duration::from_millis(50);
-    let reply = test_stand.target.start_i2c_transaction_dma(data, timeout)?;
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+    test_stand.assistant
+        .write_i2c_registers(0x10, &[0x22, 0x44, 0x66], timeout)?;
+
+    let reply = test_stand.target
+        .start_i2c_transaction_dma(ADDRESS, &[0x10], 3, timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44, 0x66]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_to_the_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+
+    // The first byte sets the register pointer.
+    let data = [0x10, 0x11, 0x22, 0x33];
+    test_stand.target
+        .start_i2c_transaction(ADDRESS, &data, 0, timeout)?;
+
+    let registers = test_stand.assistant
+        .read_i2c_registers(0x10, 3, timeout)?;
+    assert_eq!(registers, [0x11, 0x22, 0x33]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nack_from_a_missing_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+
+    let data = [0x00];
+    let result = test_stand.target
+        .start_i2c_transaction(ADDRESS + 1, &data, 1, timeout);
+
+    assert!(matches!(result, Err(TargetI2cError::Nack)));
+
+    // The target must have recovered from the NACK.
+    test_stand.target
+        .start_i2c_transaction(ADDRESS, &data, 1, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_respond_at_the_configured_address() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let address = 0x30;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(
+        &SlaveConfig {
+            address,
+            .. config()
+        },
+        timeout,
+    )?;
+    test_stand.assistant.write_i2c_registers(0x00, &[0x55], timeout)?;
+
+    let reply = test_stand.target
+        .start_i2c_transaction(address, &[0x00], 1, timeout)?;
+    assert_eq!(reply, [0x55]);
+
+    let result = test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x00], 1, timeout);
+    assert!(matches!(result, Err(TargetI2cError::Nack)));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nack_of_the_address() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(
+        &SlaveConfig {
+            nack: Nack::Address,
+            .. config()
+        },
+        timeout,
+    )?;
+
+    let result = test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x00], 1, timeout);
+    assert!(matches!(result, Err(TargetI2cError::Nack)));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nack_of_written_data() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(
+        &SlaveConfig {
+            nack: Nack::Data(2),
+            .. config()
+        },
+        timeout,
+    )?;
+
+    let data = [0x10, 0x11, 0x22, 0x33];
+    let result = test_stand.target
+        .start_i2c_transaction(ADDRESS, &data, 0, timeout);
+    assert!(matches!(result, Err(TargetI2cError::Nack)));
+
+    // Only the data before the refused byte has been written.
+    let registers = test_stand.assistant
+        .read_i2c_registers(0x10, 3, timeout)?;
+    assert_eq!(registers, [0x11, 0x00, 0x00]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_wait_for_a_slave_that_stretches_the_clock() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let stretch = Duration::from_millis(2);
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(
+        &SlaveConfig {
+            stretch: Microseconds(stretch.as_micros() as u32),
+            .. config()
+        },
+        timeout,
+    )?;
+    test_stand.assistant
+        .write_i2c_registers(0x10, &[0x22, 0x44, 0x66], timeout)?;
+
+    let start = Instant::now();
+    let reply = test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x10], 3, timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44, 0x66]);
 
-    assert_eq!(reply, data << 1);
+    // The slave stretches the clock before the address of the write, the
+    // register pointer, the address of the read, and every byte it sends.
+    assert!(start.elapsed() >= stretch * 6);
 
     Ok(())
 }
+
+#[test]
+fn it_should_stop_after_each_part_of_a_transaction() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+
+    // The target writes the register pointer and reads the registers in two
+    // separate transfers, each ended by a stop, rather than using a repeated
+    // start.
+    test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x10], 3, timeout)?;
+    let log = test_stand.assistant.i2c_transaction_log(timeout)?;
+
+    assert_eq!(
+        log,
+        TransactionLog {
+            starts:   2,
+            restarts: 0,
+            stops:    2,
+        },
+    );
+
+    // Reading the log has cleared it.
+    test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x10, 0x11], 0, timeout)?;
+    let log = test_stand.assistant.i2c_transaction_log(timeout)?;
+
+    assert_eq!(
+        log,
+        TransactionLog {
+            starts:   1,
+            restarts: 0,
+            stops:    1,
+        },
+    );
+
+    Ok(())
+}
+
+
+/// The default configuration of the assistant's I2C slave
+fn config() -> SlaveConfig {
+    SlaveConfig {
+        address: ADDRESS,
+        nack:    Nack::None,
+        stretch: Microseconds(0),
+    }
+}
@@ -0,0 +1,71 @@
+//! Test Suite for interrupt priorities on the target
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+/// The interrupt number of USART1
+const USART1: u8 = 4;
+
+/// The interrupt number of pin interrupt 0
+const PIN_INT0: u8 = 24;
+
+/// The priority that the target firmware starts its interrupts with
+const DEFAULT_PRIORITY: u8 = 3;
+
+
+#[test]
+fn it_should_serve_pending_interrupts_in_order_of_priority() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    let rounds  = 100;
+
+    // With equal priorities, the NVIC serves the lower interrupt number first.
+    // Raising the priority of the pin interrupt should reverse that.
+    test_stand.target.set_irq_priority(PIN_INT0, 2, timeout)?;
+    let latencies = test_stand.target
+        .run_irq_workload(&[USART1, PIN_INT0], rounds, timeout)?;
+    assert!(
+        latencies.max[PIN_INT0 as usize] < latencies.max[USART1 as usize],
+        "{:?}",
+        latencies,
+    );
+
+    test_stand.target.set_irq_priority(PIN_INT0, DEFAULT_PRIORITY, timeout)?;
+    test_stand.target.set_irq_priority(USART1, 2, timeout)?;
+    let latencies = test_stand.target
+        .run_irq_workload(&[USART1, PIN_INT0], rounds, timeout)?;
+    assert!(
+        latencies.max[USART1 as usize] < latencies.max[PIN_INT0 as usize],
+        "{:?}",
+        latencies,
+    );
+
+    test_stand.target.set_irq_priority(USART1, DEFAULT_PRIORITY, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_unsupported_priority() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The LPC845 only has four priorities. The target must refuse, instead of
+    // resetting.
+    assert!(test_stand.target.set_irq_priority(USART1, 4, timeout).is_err());
+
+    test_stand.target.ping(timeout)?;
+
+    Ok(())
+}
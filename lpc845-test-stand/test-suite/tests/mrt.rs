@@ -0,0 +1,81 @@
+//! Test Suite for the multi-rate timer (MRT) of the target hardware
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use host_lib::timestamp;
+use lpc845_messages::TimerMode;
+use lpc845_test_suite::{
+    prelude::*,
+    target::TargetMrtError,
+};
+
+
+#[test]
+fn it_should_report_repeated_expirations_at_the_requested_interval()
+    -> Result
+{
+    let mut test_stand = TestStand::new()?;
+
+    let interval = Duration::from_millis(10);
+    let timeout  = interval * 2;
+
+    // When `mrt` is dropped, the MRT will be stopped.
+    let mut mrt = test_stand.target.start_mrt(TimerMode::Repeat, interval)?;
+
+    let mut previous = mrt.wait_for_expiration(timeout)?;
+    for _ in 0 .. 5 {
+        let timestamp = mrt.wait_for_expiration(timeout)?;
+        let measured  = timestamp::interval(previous, timestamp);
+
+        // The timestamps are taken in the interrupt handler, so they jitter a
+        // bit with the interrupt latency.
+        assert!(measured >= interval * 99/100, "{:?}", measured);
+        assert!(measured <= interval * 101/100, "{:?}", measured);
+
+        previous = timestamp;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_toggle_the_led_at_the_requested_interval() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let interval = Duration::from_millis(10);
+
+    // When `_mrt` is dropped, the MRT will be stopped.
+    let _mrt = test_stand.target.start_mrt(TimerMode::Repeat, interval)?;
+
+    let timeout = interval * 2;
+    let measurement = test_stand.assistant.measure_timer_interrupt(5, timeout)?;
+
+    let min_acceptable = interval *  9/10;
+    let max_acceptable = interval * 11/10;
+
+    assert!(measurement.min >= min_acceptable, "{:?}", measurement);
+    assert!(measurement.max <= max_acceptable, "{:?}", measurement);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_expire_only_once_in_one_shot_mode() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let interval = Duration::from_millis(10);
+    let timeout  = interval * 5;
+
+    let mut mrt = test_stand.target.start_mrt(TimerMode::OneShot, interval)?;
+    mrt.wait_for_expiration(timeout)?;
+
+    let result = mrt.wait_for_expiration(timeout);
+    assert!(matches!(
+        result,
+        Err(TargetMrtError::Receive(err)) if err.is_timeout()
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+//! Test Suite for test stands with more than one test target
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions. The test cases in here only do anything, if additional
+//! targets are configured in `test-stand.toml`.
+
+
+use lpc845_test_suite::prelude::*;
+
+
+#[test]
+fn it_should_reach_all_targets() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target.ping(Duration::from_millis(50))?;
+    for target in test_stand.targets.values_mut() {
+        target.ping(Duration::from_millis(50))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Test Suite for the PWM functionality of the target hardware
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::thread::sleep;
+
+use host_lib::report;
+use lpc845_messages::InputPin;
+use lpc845_test_suite::prelude::*;
+
+
+#[test]
+fn it_should_create_a_pwm_signal_with_the_requested_period_and_duty_cycle()
+    -> Result
+{
+    let mut test_stand = TestStand::new()?;
+
+    for &(period_us, duty_permille) in &[(1000, 500), (2000, 250), (500, 800)] {
+        let period = Duration::from_micros(period_us);
+
+        // When `_signal` is dropped, the PWM signal will be stopped.
+        let _signal = test_stand.target
+            .start_pwm_signal(period, duty_permille)?;
+
+        // Monitor the signal for a few dozen periods.
+        test_stand.assistant.start_pin_monitoring(InputPin::Pwm)?;
+        sleep(period * 40);
+        let signal = test_stand.assistant
+            .stop_pin_monitoring(InputPin::Pwm, Duration::from_millis(50))?;
+
+        assert!(signal.periods >= 30, "{:?}", signal);
+
+        let expected = period.as_secs_f64();
+        let (min, max) = signal.period_range_secs().unwrap();
+        let name     = format!("pwm_period_{}us", period_us);
+        let measured = signal.period_secs().unwrap() * 1_000_000.0;
+        report::measure(&name, measured, "us")?;
+        assert!(min >= expected * 0.99, "{:?}", signal);
+        assert!(max <= expected * 1.01, "{:?}", signal);
+
+        let expected = f64::from(duty_permille) / 1000.0;
+        let duty_cycle = signal.duty_cycle().unwrap();
+        assert!((duty_cycle - expected).abs() <= 0.02, "{:?}", signal);
+    }
+
+    test_stand.finish()?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Test Suite for the receive timeout and link sync of the target firmware
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::{
+    thread,
+    time::Duration,
+};
+
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+#[test]
+fn it_should_discard_a_partial_request_after_a_timeout() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target.send_partial_request(2)?;
+
+    // The target discards partial requests after 100 ms.
+    thread::sleep(Duration::from_millis(200));
+
+    // If the partial request hadn't been discarded, it would corrupt this one.
+    test_stand.target.ping(Duration::from_millis(50))?;
+
+    let log = test_stand.target.take_log();
+    assert!(
+        log.iter()
+            .any(|record| record.message.contains("partial host request"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_should_discard_a_partial_request_on_link_sync() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target.send_partial_request(2)?;
+
+    // Well before the target would discard the partial request on its own.
+    test_stand.target.resync_link(Duration::from_millis(50))?;
+
+    test_stand.target.ping(Duration::from_millis(50))?;
+
+    Ok(())
+}
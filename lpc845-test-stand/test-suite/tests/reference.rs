@@ -0,0 +1,41 @@
+//! Test Suite for the reference clock input of the test assistant
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions. The test cases in here only run, if a reference clock
+//! is configured in `test-stand.toml`.
+
+
+use std::{
+    thread::sleep,
+    time::Duration,
+};
+
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+#[test]
+fn it_should_timestamp_the_reference_clock() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let reference_hz = match test_stand.reference_hz {
+        Some(reference_hz) => reference_hz,
+        None               => return Ok(()),
+    };
+
+    // Wait long enough to capture at least three edges.
+    test_stand.assistant.reset_reference()?;
+    sleep(Duration::from_secs_f64(3.5 / reference_hz));
+
+    let timestamps = test_stand.assistant
+        .read_reference(Duration::from_millis(50))?;
+
+    assert!(timestamps.edges >= 3);
+
+    let error_ppm = timestamps.timer_error_ppm(reference_hz).unwrap();
+    assert!(error_ppm.abs() <= test_stand.tolerances().clock_ppm());
+
+    Ok(())
+}
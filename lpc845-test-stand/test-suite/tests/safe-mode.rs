@@ -0,0 +1,52 @@
+//! Test Suite for the requests that the safe mode firmware supports
+//!
+//! The regular firmware supports these requests too, so these test cases run
+//! against either. Against the safe mode firmware, they are the first check
+//! when bringing up a new board.
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_messages::{
+    TargetRequests as _,
+    reset,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+#[test]
+fn it_should_report_its_flash_memory() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let flash = test_stand.target.flash_info(Duration::from_millis(50))?;
+
+    assert_eq!(flash.size % flash.sector_size, 0, "{:?}", flash);
+    assert_eq!(flash.sector_size % flash.page_size, 0, "{:?}", flash);
+    assert!(0 < flash.used && flash.used <= flash.size, "{:?}", flash);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reset_on_request() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    test_stand.target.reset(Duration::from_millis(500))?;
+
+    // A requested reset is no fault. If the target reported one, this would
+    // receive the report instead of the reply.
+    test_stand.target.ping(timeout)?;
+
+    let cause = test_stand.target.reset_cause(timeout)?;
+    assert_eq!(cause, reset::Cause::Software);
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! Test Suite for a line that the test nodes share, like an interrupt line
+//!
+//! The trigger line is driven like an open-drain line by both the target and
+//! the assistant, which results in a wired-AND: The line is only high, if
+//! neither of them pulls it low.
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_messages::{
+    pin::Level,
+    units::Microseconds,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+/// How long the line may take to rise, once the last test node releases it
+///
+/// The line is pulled up by the internal pull-ups of both test nodes, which
+/// are weak, but strong enough for a short wire.
+const MAX_RISE_TIME: Microseconds = Microseconds(100);
+
+
+#[test]
+fn it_should_act_as_a_wired_and() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    let target    = &mut test_stand.target;
+    let assistant = &mut test_stand.assistant;
+
+    // Start with both test nodes releasing the line.
+    target.drive_shared_line(Level::High, timeout)?;
+    let released = assistant.drive_shared_line(Level::High, timeout)?;
+
+    // Then pull it low from either side, and from both.
+    let target_low = target.drive_shared_line(Level::Low, timeout)?;
+    let seen_by_assistant = assistant.drive_shared_line(Level::High, timeout)?;
+
+    target.drive_shared_line(Level::High, timeout)?;
+    assistant.drive_shared_line(Level::Low, timeout)?;
+    let seen_by_target = target.drive_shared_line(Level::High, timeout)?;
+
+    target.drive_shared_line(Level::Low, timeout)?;
+    let both_low = target.drive_shared_line(Level::High, timeout)?;
+
+    // The target has released the line, but the assistant still holds it low.
+    // Releasing it from the assistant's side, too, lets it rise.
+    let rising = assistant.drive_shared_line(Level::High, timeout)?;
+    let risen  = target.drive_shared_line(Level::High, timeout)?;
+
+    // Restore the idle state of the trigger line, so other test cases aren't
+    // affected, even if an assertion fails.
+    assistant.drive_shared_line(Level::Low, timeout)?;
+
+    assert_eq!(released, Level::High);
+
+    assert_eq!(target_low.level, Level::Low);
+    assert_eq!(seen_by_assistant, Level::Low);
+
+    assert_eq!(seen_by_target.level, Level::Low);
+    assert!(seen_by_target.settle_time.is_none(), "{:?}", seen_by_target);
+
+    assert_eq!(both_low.level, Level::Low);
+    assert!(both_low.settle_time.is_none(), "{:?}", both_low);
+
+    assert_eq!(rising, Level::High);
+    assert_eq!(risen.level, Level::High);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_release_the_shared_line_in_time() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The assistant releases the line first, so the target is the last test
+    // node holding it low, and measures how long it takes to rise.
+    test_stand.target.drive_shared_line(Level::Low, timeout)?;
+    test_stand.assistant.drive_shared_line(Level::High, timeout)?;
+    let released = test_stand.target.drive_shared_line(Level::High, timeout)?;
+
+    test_stand.assistant.drive_shared_line(Level::Low, timeout)?;
+
+    assert_eq!(released.level, Level::High);
+    match released.settle_time {
+        Some(time) => assert!(time.0 <= MAX_RISE_TIME.0, "{:?}", released),
+        None       => panic!("Line didn't rise: {:?}", released),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! Test Suite for waking up the target from sleep and deep-sleep mode
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_messages::{
+    SleepMode,
+    WakeupCause,
+    WakeupSource,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+    target::GPIO_IN,
+};
+
+
+#[test]
+fn it_should_wake_up_from_sleep_on_usart_rx() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout       = Duration::from_millis(50);
+    let sleep_timeout = Duration::from_secs(1);
+
+    test_stand.target.enter_sleep(
+        SleepMode::Sleep,
+        WakeupSource::UsartRx,
+        sleep_timeout,
+        timeout,
+    )?;
+
+    let message = b"W";
+    test_stand.assistant.send_to_target_usart(message)?;
+
+    let wakeup = test_stand.target.wait_for_wakeup(timeout)?;
+    assert_eq!(wakeup.cause, WakeupCause::UsartRx);
+    assert!(wakeup.after < sleep_timeout);
+
+    // In sleep mode, the USART keeps running, so it shouldn't lose the byte
+    // that woke up the target.
+    let received = test_stand.target.wait_for_usart_rx(message, timeout)?;
+    assert_eq!(received, message);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_wake_up_from_deep_sleep_on_usart_rx() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout       = Duration::from_millis(50);
+    let sleep_timeout = Duration::from_secs(1);
+
+    test_stand.target.enter_sleep(
+        SleepMode::DeepSleep,
+        WakeupSource::UsartRx,
+        sleep_timeout,
+        timeout,
+    )?;
+
+    // The byte itself is likely to be lost, as the USART has no clock, while
+    // the target is in deep-sleep mode.
+    test_stand.assistant.send_to_target_usart(b"W")?;
+
+    let wakeup = test_stand.target.wait_for_wakeup(timeout)?;
+    assert_eq!(wakeup.cause, WakeupCause::UsartRx);
+    assert!(wakeup.after < sleep_timeout);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_wake_up_from_sleep_on_a_pin_interrupt() -> Result {
+    wake_up_on_pin_interrupt(SleepMode::Sleep)
+}
+
+#[test]
+fn it_should_wake_up_from_deep_sleep_on_a_pin_interrupt() -> Result {
+    wake_up_on_pin_interrupt(SleepMode::DeepSleep)
+}
+
+#[test]
+fn it_should_wake_up_from_deep_sleep_after_the_timeout() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout       = Duration::from_millis(100);
+    let sleep_timeout = Duration::from_millis(20);
+
+    test_stand.target.enter_sleep(
+        SleepMode::DeepSleep,
+        WakeupSource::PinInterrupt,
+        sleep_timeout,
+        timeout,
+    )?;
+
+    let wakeup = test_stand.target.wait_for_wakeup(timeout)?;
+    assert_eq!(wakeup.cause, WakeupCause::Timeout);
+    assert_eq!(wakeup.after, sleep_timeout);
+
+    Ok(())
+}
+
+
+fn wake_up_on_pin_interrupt(mode: SleepMode) -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout       = Duration::from_millis(50);
+    let sleep_timeout = Duration::from_secs(1);
+
+    // Make sure the pin is high, before the target goes to sleep, so the
+    // falling edge is the one that wakes it up.
+    test_stand.assistant.set_pin_high()?;
+    assert!(test_stand.target.pin_is_high(GPIO_IN)?);
+
+    test_stand.target.enter_sleep(
+        mode,
+        WakeupSource::PinInterrupt,
+        sleep_timeout,
+        timeout,
+    )?;
+
+    test_stand.assistant.set_pin_low()?;
+
+    let wakeup = test_stand.target.wait_for_wakeup(timeout)?;
+    assert_eq!(wakeup.cause, WakeupCause::PinInterrupt);
+    assert!(wakeup.after < sleep_timeout);
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Test Suite for the key-value store of the target
+//!
+//! The store survives a reset, which allows test cases to carry state from
+//! before a reset to after it.
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_messages::{
+    STORE_CAP,
+    StoreError,
+    TargetRequests as _,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+#[test]
+fn it_should_keep_store_entries_across_a_reset() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(200);
+
+    test_stand.target.clear_store(timeout)??;
+    test_stand.target.set_store_entry(1, 0xdead_beef, timeout)??;
+    test_stand.target.set_store_entry(2, 5, timeout)??;
+    test_stand.target.set_store_entry(2, 6, timeout)??;
+
+    test_stand.target.reset(Duration::from_millis(500))?;
+
+    assert_eq!(test_stand.target.store_entry(1, timeout)?, Some(0xdead_beef));
+    assert_eq!(test_stand.target.store_entry(2, timeout)?, Some(6));
+    assert_eq!(test_stand.target.store_entry(3, timeout)?, None);
+
+    test_stand.target.clear_store(timeout)??;
+    assert_eq!(test_stand.target.store_entry(1, timeout)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_store_entries_that_dont_fit() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(200);
+
+    test_stand.target.clear_store(timeout)??;
+
+    let result = test_stand.target.set_store_entry(u32::MAX, 0, timeout)?;
+    assert_eq!(result, Err(StoreError::InvalidKey));
+
+    for key in 0 .. STORE_CAP as u32 {
+        test_stand.target.set_store_entry(key, key, timeout)??;
+    }
+    let result = test_stand.target
+        .set_store_entry(STORE_CAP as u32, 0, timeout)?;
+    assert_eq!(result, Err(StoreError::Full));
+
+    // Existing entries can still be replaced.
+    test_stand.target.set_store_entry(0, 1, timeout)??;
+
+    test_stand.target.clear_store(timeout)??;
+
+    Ok(())
+}
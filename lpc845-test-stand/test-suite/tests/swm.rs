@@ -0,0 +1,73 @@
+//! Test Suite for the switch matrix (SWM) API in LPC8xx HAL
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_messages::{
+    InputPin,
+    TargetRequests as _,
+    UsartMode,
+    usart,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+    target::{
+        GPIO_OUT,
+        USART_TX,
+    },
+};
+
+
+#[test]
+fn it_should_move_usart_tx_to_another_pin() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The assistant sees the output on the other pin as a signal, and can't
+    // keep up with its edges at the default baud rate. Its own USART keeps the
+    // default, as it shouldn't receive anything anyway.
+    let config = usart::Config {
+        baud: 9600,
+        .. usart::Config::default()
+    };
+    test_stand.target
+        .configure_usart(UsartMode::Regular, &config, timeout)?;
+
+    test_stand.target.move_usart_tx(GPIO_OUT, timeout)?;
+    test_stand.assistant.start_pin_monitoring(InputPin::Green)?;
+
+    test_stand.target.send_usart(b"UUUU")?;
+    test_stand.assistant.expect_nothing_from_target(timeout)?;
+
+    let moved = test_stand.assistant
+        .stop_pin_monitoring(InputPin::Green, timeout)?;
+
+    // Restore the default pin and configuration, so other test cases aren't
+    // affected, even if the assertion fails.
+    let config = usart::Config::default();
+    test_stand.target.move_usart_tx(USART_TX, timeout)?;
+    test_stand.target
+        .configure_usart(UsartMode::Regular, &config, timeout)?;
+
+    assert!(moved.periods > 0, "{:?}", moved);
+
+    test_stand.assistant.start_pin_monitoring(InputPin::Green)?;
+
+    let message = b"Hello, world!";
+    test_stand.target.send_usart(message)?;
+    let received = test_stand.assistant
+        .receive_from_target_usart(message, timeout)?;
+
+    let restored = test_stand.assistant
+        .stop_pin_monitoring(InputPin::Green, timeout)?;
+
+    assert_eq!(received, message);
+    assert_eq!(restored.periods, 0, "{:?}", restored);
+
+    Ok(())
+}
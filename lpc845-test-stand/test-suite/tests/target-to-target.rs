@@ -0,0 +1,104 @@
+//! Test Suite for test targets that are wired to each other
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions. Each test case only does anything, if a link between
+//! two targets that uses its bus is configured in `test-stand.toml`.
+
+
+use std::time::Duration;
+
+use host_lib::topology::Bus;
+use lpc845_messages::{
+    i2c::{
+        Nack,
+        SlaveConfig,
+    },
+    units::Microseconds,
+};
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+/// The address of the test assistant's I2C slave
+const ADDRESS: u8 = 0x48;
+
+
+#[test]
+fn it_should_send_messages_to_the_other_target() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let (a, b) = match test_stand.linked_targets(Bus::Usart) {
+        Some(targets) => targets,
+        None          => return Ok(()),
+    };
+
+    let timeout = Duration::from_millis(50);
+
+    let message = b"Hello, world!";
+    a.send_usart(message)?;
+    let received = b.wait_for_usart_rx(message, timeout)?;
+    assert_eq!(received, message);
+
+    let message = b"Hello back!";
+    b.send_usart(message)?;
+    let received = a.wait_for_usart_rx(message, timeout)?;
+    assert_eq!(received, message);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_share_an_i2c_bus_with_the_other_target() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Neither target can act as a slave, so both talk to the assistant's
+    // slave, which must be on the same bus.
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(
+        &SlaveConfig {
+            address: ADDRESS,
+            nack:    Nack::None,
+            stretch: Microseconds(0),
+        },
+        timeout,
+    )?;
+
+    let (a, b) = match test_stand.linked_targets(Bus::I2c) {
+        Some(targets) => targets,
+        None          => return Ok(()),
+    };
+
+    // The first byte sets the register pointer.
+    a.start_i2c_transaction(ADDRESS, &[0x10, 0x11, 0x22, 0x33], 0, timeout)?;
+    let reply = b.start_i2c_transaction(ADDRESS, &[0x10], 3, timeout)?;
+    assert_eq!(reply, [0x11, 0x22, 0x33]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_data_to_the_other_target_via_spi() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let (master, slave) = match test_stand.linked_targets(Bus::Spi) {
+        Some(targets) => targets,
+        None          => return Ok(()),
+    };
+
+    let timeout = Duration::from_millis(50);
+
+    slave.expect_spi_slave_transfer(&[], timeout)?;
+    let data = [0x11, 0x22, 0x33];
+    master.start_spi_transaction(&data, 0, timeout)?;
+    let received = slave.wait_for_spi_slave_transfer(timeout)?;
+    assert_eq!(received, data);
+
+    slave.expect_spi_slave_transfer(&[0x22, 0x44], timeout)?;
+    let reply = master.start_spi_transaction(&[], 2, timeout)?;
+    slave.wait_for_spi_slave_transfer(timeout)?;
+    assert_eq!(reply, [0x22, 0x44]);
+
+    Ok(())
+}
@@ -4,31 +4,58 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::thread::sleep;
 
-use lpc845_test_suite::{
-    Result,
-    TestStand,
-};
+use lpc845_messages::InputPin;
+use lpc845_test_suite::prelude::*;
 
 
 #[test]
 fn it_should_fire_regular_timer_interrupts() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    let period_ms = 10;
+    let period = Duration::from_millis(10);
 
     // When `_interrupt` is dropped, the timer interrupt will be stopped.
-    let _interrupt = test_stand.target.start_timer_interrupt(period_ms)?;
+    let _interrupt = test_stand.target.start_timer_interrupt(period)?;
 
-    let timeout = Duration::from_millis((period_ms * 2).into());
+    let timeout = period * 2;
     let measurement = test_stand.assistant.measure_timer_interrupt(5, timeout)?;
 
-    let min_acceptable = Duration::from_millis((period_ms *  9/10).into());
-    let max_acceptable = Duration::from_millis((period_ms * 11/10).into());
+    let min_acceptable = period *  9/10;
+    let max_acceptable = period * 11/10;
 
     assert!(measurement.min >= min_acceptable);
     assert!(measurement.max <= max_acceptable);
 
     Ok(())
 }
+
+#[test]
+fn it_should_toggle_the_led_with_a_stable_duty_cycle() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let period = Duration::from_millis(10);
+
+    // When `_interrupt` is dropped, the timer interrupt will be stopped.
+    let _interrupt = test_stand.target.start_timer_interrupt(period)?;
+
+    // The LED is toggled on every interrupt, so one period of the signal
+    // spans two interrupts.
+    test_stand.assistant.start_pin_monitoring(InputPin::Blue)?;
+    sleep(period * 2 * 6);
+    let signal = test_stand.assistant
+        .stop_pin_monitoring(InputPin::Blue, Duration::from_millis(50))?;
+
+    assert!(signal.periods >= 5, "{:?}", signal);
+
+    let expected = (period * 2).as_secs_f64();
+    let (min, max) = signal.period_range_secs().unwrap();
+    assert!(min >= expected *  9.0/10.0, "{:?}", signal);
+    assert!(max <= expected * 11.0/10.0, "{:?}", signal);
+
+    let duty_cycle = signal.duty_cycle().unwrap();
+    assert!((duty_cycle - 0.5).abs() <= 0.05, "{:?}", signal);
+
+    Ok(())
+}
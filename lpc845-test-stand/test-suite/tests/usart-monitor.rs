@@ -0,0 +1,73 @@
+//! Test Suite for the USART monitor of the test assistant
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_messages::MonitorPin;
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+/// The baud rate the target's USART is configured with
+const BAUD: u32 = 115_200;
+
+
+#[test]
+fn it_should_timestamp_monitored_bytes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The monitor uses the same timer as the reference clock input.
+    let timer_hz = test_stand.assistant.read_reference(timeout)?.timer_hz;
+
+    test_stand.assistant.monitor_usart(MonitorPin::TargetTx, BAUD)?;
+
+    let message = b"Hello, world!";
+    test_stand.target.send_usart(message)?;
+
+    let bytes = test_stand.assistant.wait_for_monitored(message, timeout)?;
+    test_stand.assistant.stop_monitor()?;
+
+    let values: Vec<_> = bytes.iter().map(|byte| byte.value).collect();
+    assert_eq!(values, message);
+
+    // A byte takes 10 bit periods on the wire. The target might leave gaps
+    // between bytes, but it can't send them any faster than that. Allow for
+    // some interrupt latency on the assistant.
+    let byte_ticks = 10.0 * timer_hz as f64 / BAUD as f64;
+    let min_span   = 0.9 * byte_ticks * (bytes.len() - 1) as f64;
+
+    let first = bytes.first().unwrap().timestamp;
+    let last  = bytes.last().unwrap().timestamp;
+    assert!(last.wrapping_sub(first) as f64 >= min_span);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_trigger_on_pattern() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    test_stand.assistant.monitor_usart(MonitorPin::TargetTx, BAUD)?;
+    test_stand.assistant.trigger_on_pattern(b"world")?;
+
+    let message = b"Hello, world!";
+    test_stand.target.send_usart(message)?;
+
+    let timestamp = test_stand.assistant.wait_for_pattern(timeout)?;
+    let bytes     = test_stand.assistant.wait_for_monitored(message, timeout)?;
+    test_stand.assistant.stop_monitor()?;
+
+    // The pattern ends with the `d` in "world".
+    assert_eq!(timestamp, bytes[11].timestamp);
+
+    Ok(())
+}
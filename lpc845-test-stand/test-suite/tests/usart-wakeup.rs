@@ -0,0 +1,51 @@
+//! Test Suite for waking up the target from sleep mode via USART
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+/// The baud rate of the target's USART
+const BAUD: f64 = 115200.0;
+
+
+#[test]
+fn it_should_wake_up_and_receive_the_first_byte() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.sleep_until_usart_rx(timeout)?;
+
+    let message = b"W";
+    test_stand.assistant.send_to_target_usart(message)?;
+
+    let wakeup = test_stand.target.wait_for_usart_wakeup(timeout)?;
+
+    // In sleep mode, the USART keeps running, so it shouldn't lose the byte
+    // that woke up the target.
+    let received_after = wakeup.received_after
+        .expect("First byte was lost");
+    let received = test_stand.target.wait_for_usart_rx(message, timeout)?;
+    assert_eq!(received, message);
+
+    // The byte is ready in the middle of its stop bit, 9.5 bit times after its
+    // start bit. Whatever is missing from that was spent waking up.
+    let bit_time       = 1.0 / BAUD;
+    let received_after = received_after as f64 / wakeup.timer_hz as f64;
+    let latency        = 9.5 * bit_time - received_after;
+
+    assert!(
+        latency < bit_time,
+        "Wakeup latency of {} us is too high",
+        latency * 1_000_000.0,
+    );
+
+    Ok(())
+}
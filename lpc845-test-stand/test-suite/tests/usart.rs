@@ -4,11 +4,35 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::{
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    thread,
+    time::Duration,
+};
 
+use host_lib::{
+    payload::{
+        Pattern,
+        Payload,
+    },
+    report,
+};
+use lpc845_messages::{
+    UsartError,
+    UsartMode,
+    inject::{
+        Corruption,
+        Corruptions,
+    },
+    usart,
+};
 use lpc845_test_suite::{
     Result,
     TestStand,
+    target::AddressMatch,
 };
 
 
@@ -27,6 +51,51 @@ fn it_should_send_messages() -> Result {
     Ok(())
 }
 
+#[test]
+fn it_should_forward_a_long_stream_without_dropping_data() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Enough data to keep the assistant forwarding for a while, so it relies
+    // on the credits granted by the host.
+    let payload = Payload::new(Pattern::Prbs15, 1);
+    let message = payload.generate(400);
+    test_stand.target.send_usart(&message)?;
+
+    let timeout  = Duration::from_millis(200);
+    let received = test_stand.assistant
+        .receive_from_target_usart(&message, timeout)?;
+
+    payload.verify(&received, message.len())?;
+    Ok(())
+}
+
+#[test]
+fn it_should_send_at_the_full_baud_rate() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let bytes   = 4096;
+    let timeout = Duration::from_secs(1);
+    let count   = test_stand.target.measure_usart_throughput(
+        &mut test_stand.assistant,
+        bytes,
+        32,
+        timeout,
+    )?;
+
+    assert_eq!(count.bytes,   bytes, "{:?}", count);
+    assert_eq!(count.errors,  0,     "{:?}", count);
+    assert_eq!(count.dropped, 0,     "{:?}", count);
+
+    // With 8 data bits, no parity, and 1 stop bit, each byte takes 10 bits on
+    // the wire. A driver that keeps the USART busy comes close to that.
+    let line_rate  = usart::Config::default().baud as f64 / 10.0;
+    let throughput = count.bytes_per_sec().unwrap();
+    report::measure("usart_throughput", throughput, "B/s")?;
+    assert!(throughput > line_rate * 0.95, "{} B/s, {:?}", throughput, count);
+
+    Ok(())
+}
+
 #[test]
 fn it_should_receive_messages() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -41,6 +110,56 @@ fn it_should_receive_messages() -> Result {
     Ok(())
 }
 
+#[test]
+fn it_should_send_and_receive_at_the_same_time() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(200);
+
+    // The assistant starts sending, once the first byte from the target
+    // arrives. The target sends twice as much, so it is still sending, when
+    // the assistant is done.
+    let assistant_payload = Payload::new(Pattern::Counter, 0);
+    let target_payload    = Payload::new(Pattern::Lfsr, 1);
+    let from_assistant    = assistant_payload.generate(usart::DUPLEX_CAP);
+    let from_target       = target_payload.generate(2 * usart::DUPLEX_CAP);
+
+    test_stand.assistant
+        .send_to_target_usart_duplex(&from_assistant, timeout)?;
+    test_stand.target.send_usart(&from_target)?;
+
+    let received_by_assistant = test_stand.assistant
+        .receive_from_target_usart(&from_target, timeout)?;
+    let received_by_target = test_stand.target
+        .wait_for_usart_rx(&from_assistant, timeout)?;
+
+    target_payload.verify(&received_by_assistant, from_target.len())?;
+    assistant_payload.verify(&received_by_target, from_assistant.len())?;
+    Ok(())
+}
+
+#[test]
+fn it_should_receive_corrupted_messages() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Turn the `H` into an `h`, and drop the `,`.
+    let mut corruptions = Corruptions::new();
+    corruptions.push(Corruption::FlipBits { position: 0, mask: 0x20 })
+        .unwrap();
+    corruptions.push(Corruption::Drop { position: 5 })
+        .unwrap();
+
+    test_stand.assistant
+        .send_to_target_usart_corrupted(b"Hello, world!", corruptions)?;
+
+    let expected = b"hello world!";
+    let timeout  = Duration::from_millis(50);
+    let received = test_stand.target.wait_for_usart_rx(expected, timeout)?;
+
+    assert_eq!(received, expected);
+    Ok(())
+}
+
 #[test]
 fn it_should_send_messages_using_dma() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -70,6 +189,24 @@ fn it_should_receive_messages_via_dma() -> Result {
     Ok(())
 }
 
+#[test]
+fn it_should_receive_long_messages_via_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Longer than the target's DMA ring buffer, and not a multiple of its
+    // halves, so the data wraps around, and the last part is only available
+    // while a half is still being filled.
+    let payload = Payload::new(Pattern::Prbs15, 1);
+    let message = payload.generate(300);
+    test_stand.assistant.send_to_target_usart_dma(&message)?;
+
+    let timeout  = Duration::from_millis(100);
+    let received = test_stand.target.wait_for_usart_rx_dma(&message, timeout)?;
+
+    payload.verify(&received, message.len())?;
+    Ok(())
+}
+
 #[test]
 fn it_should_send_using_flow_control() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -94,6 +231,70 @@ fn it_should_send_using_flow_control() -> Result {
     Ok(())
 }
 
+#[test]
+fn it_should_pause_while_cts_is_deasserted() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    let pause   = Duration::from_millis(10);
+
+    test_stand.assistant.throttle_cts(4, pause, timeout)?;
+
+    let message = b"Hello, world! Hello, world!";
+    test_stand.target.send_usart_with_flow_control(message)?;
+
+    // Leave enough time for the pause and the rest of the message.
+    thread::sleep(pause * 3);
+    let throttle = test_stand.assistant.stop_cts_throttle(timeout)?;
+
+    assert_eq!(throttle.bytes, message.len() as u32, "{:?}", throttle);
+
+    // The target may finish the byte it was sending when CTS was deasserted,
+    // but must not start another one.
+    assert!(throttle.bytes_paused <= 1, "{:?}", throttle);
+
+    // A byte takes less than 0.1 ms at 115200 baud, so the gap in the data can
+    // only be that much shorter than the pause.
+    let paused = throttle.paused_secs().expect("CTS not asserted again");
+    let gap    = throttle.gap_secs().expect("No data around the pause");
+    assert!(paused >= pause.as_secs_f64(), "{:?}", throttle);
+    assert!(gap >= paused - 0.0001, "{:?}", throttle);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_echo_while_receiving() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // 1024 bytes take less than 100 ms at 115200 baud. The echo must still be
+    // running after the last byte has come back.
+    let bytes    = 1024;
+    let duration = Duration::from_millis(200);
+
+    test_stand.target.start_usart_echo(duration)?;
+    test_stand.assistant
+        .start_usart_stream(b"0123456789abcdef", bytes, timeout)?;
+
+    thread::sleep(duration);
+    let stream = test_stand.assistant.stop_usart_stream(timeout)?;
+    let echo   = test_stand.target.wait_for_usart_echo(timeout)?;
+
+    assert_eq!(stream.sent, bytes, "{:?}", stream);
+    assert_eq!(stream.received, stream.sent, "{:?}", stream);
+    assert_eq!(stream.mismatches, 0, "{:?}", stream);
+    assert_eq!(stream.errors, 0, "{:?}", stream);
+    assert_eq!(stream.dropped, 0, "{:?}", stream);
+
+    assert_eq!(echo.bytes, bytes, "{:?}", echo);
+    assert_eq!(echo.errors, 0, "{:?}", echo);
+    assert_eq!(echo.dropped, 0, "{:?}", echo);
+
+    Ok(())
+}
+
 #[test]
 fn it_should_send_in_sync_mode() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -145,8 +346,230 @@ fn it_should_ignore_received_data_until_an_address_is_matched() -> Result {
     test_stand.assistant.send_to_target_usart(message)?;
 
     let timeout = Duration::from_millis(50);
+    let matched = test_stand.target.wait_for_address_match(timeout)?;
     let received = test_stand.target.wait_for_usart_rx(message, timeout)?;
 
+    let expected = AddressMatch {
+        address:   address | 0x80,
+        discarded: 11,
+    };
+    assert_eq!(matched, expected);
     assert_eq!(received, message);
     Ok(())
 }
+
+#[test]
+fn it_should_match_an_address_using_9_data_bits() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = usart::Config {
+        word_length: usart::WordLength::Nine,
+        .. usart::Config::default()
+    };
+
+    let timeout        = Duration::from_millis(50);
+    let mut test_stand = UsartConfig::apply(&mut test_stand, &config, timeout)?;
+
+    // With 9 data bits, data that has all 8 bits set is still data.
+    let address = b'X';
+    let message = b"\xffHello, world!";
+
+    test_stand.target.wait_for_address(address)?;
+
+    // With 9 data bits, the most significant bit of the data doesn't make an
+    // address.
+    test_stand.assistant.send_to_target_usart(&[address | 0x80])?;
+    test_stand.assistant.send_address_to_target_usart(b'Y')?;
+
+    test_stand.assistant.send_address_to_target_usart(address)?;
+    test_stand.assistant.send_to_target_usart(message)?;
+
+    let matched = test_stand.target.wait_for_address_match(timeout)?;
+    let received = test_stand.target.wait_for_usart_rx(message, timeout)?;
+
+    let expected = AddressMatch {
+        address,
+        discarded: 2,
+    };
+    assert_eq!(matched, expected);
+    assert_eq!(received, message);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_communicate_using_a_different_configuration() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = usart::Config {
+        baud:        9600,
+        parity:      usart::Parity::Even,
+        stop_bits:   usart::StopBits::Two,
+        word_length: usart::WordLength::Seven,
+    };
+
+    let timeout        = Duration::from_millis(50);
+    let mut test_stand = UsartConfig::apply(&mut test_stand, &config, timeout)?;
+
+    // Only 7 bits per byte are transmitted, so stick to ASCII.
+    let message = b"Hello, world!";
+    let timeout = Duration::from_millis(100);
+
+    test_stand.target.send_usart(message)?;
+    let received = test_stand.assistant
+        .receive_from_target_usart(message, timeout)?;
+    assert_eq!(received, message);
+
+    test_stand.assistant.send_to_target_usart(message)?;
+    let received = test_stand.target.wait_for_usart_rx(message, timeout)?;
+    assert_eq!(received, message);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_parity_errors() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = usart::Config {
+        parity: usart::Parity::Even,
+        .. usart::Config::default()
+    };
+
+    let timeout        = Duration::from_millis(50);
+    let mut test_stand = UsartConfig::apply(&mut test_stand, &config, timeout)?;
+
+    test_stand.assistant.send_to_target_usart_with_wrong_parity(b"X")?;
+    let error = test_stand.target.wait_for_usart_error(timeout)?;
+    assert_eq!(error, UsartError::Parity(b'X'));
+
+    // The target should still receive correct data after the error.
+    let message = b"Hello, world!";
+    test_stand.assistant.send_to_target_usart(message)?;
+    let received = test_stand.target.wait_for_usart_rx(message, timeout)?;
+    assert!(received.ends_with(message));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_break_as_a_framing_error() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.send_break_to_target_usart()?;
+
+    let timeout = Duration::from_millis(50);
+    let error   = test_stand.target.wait_for_usart_error(timeout)?;
+    assert_eq!(error, UsartError::Framing(0));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_detect_a_break() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.send_break_to_target_usart()?;
+
+    test_stand.target.wait_for_break(Duration::from_millis(50))?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_send_a_break() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target.send_break()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.wait_for_break_from_target_usart(timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_data_at_the_wrong_baud_rate() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // At half the baud rate, the target sees its stop bit in the middle of the
+    // slowed-down data bits, which are all zero.
+    test_stand.assistant.send_to_target_usart_at_wrong_baud(&[0], 57600)?;
+
+    let timeout = Duration::from_millis(50);
+    let error   = test_stand.target.wait_for_usart_error(timeout)?;
+    assert!(matches!(error, UsartError::Framing(_)));
+
+    Ok(())
+}
+
+
+/// The test stand, with a USART configuration other than the default
+///
+/// The default configuration is restored when this struct is dropped, even if
+/// the test case fails, so other test cases aren't affected.
+struct UsartConfig<'r> {
+    test_stand: &'r mut TestStand,
+    timeout:    Duration,
+}
+
+impl<'r> UsartConfig<'r> {
+    /// Configure the USARTs of the target and assistant
+    fn apply(
+        test_stand: &'r mut TestStand,
+        config:     &usart::Config,
+        timeout:    Duration,
+    )
+        -> Result<Self>
+    {
+        test_stand.target
+            .configure_usart(UsartMode::Regular, config, timeout)?;
+
+        // From here on, the target's configuration needs to be restored, even
+        // if configuring the assistant fails.
+        let mut usart = Self {
+            test_stand,
+            timeout,
+        };
+        usart.test_stand.assistant
+            .configure_usart(UsartMode::Regular, config, timeout)?;
+
+        Ok(usart)
+    }
+
+    fn restore(&mut self) -> Result {
+        let config = usart::Config::default();
+        self.test_stand.target
+            .configure_usart(UsartMode::Regular, &config, self.timeout)?;
+        self.test_stand.assistant
+            .configure_usart(UsartMode::Regular, &config, self.timeout)?;
+
+        Ok(())
+    }
+}
+
+impl Deref for UsartConfig<'_> {
+    type Target = TestStand;
+
+    fn deref(&self) -> &Self::Target {
+        self.test_stand
+    }
+}
+
+impl DerefMut for UsartConfig<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.test_stand
+    }
+}
+
+impl Drop for UsartConfig<'_> {
+    fn drop(&mut self) {
+        let result = self.restore();
+
+        // Panicking again would abort the test run. The test case has already
+        // failed anyway.
+        if !thread::panicking() {
+            result.unwrap();
+        }
+    }
+}
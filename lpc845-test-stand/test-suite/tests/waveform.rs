@@ -0,0 +1,87 @@
+//! Test Suite for waveform playback by the test assistant
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use host_lib::timestamp;
+use lpc845_messages::{
+    OutputPin,
+    pin,
+    units::Microseconds,
+    waveform::{
+        Step,
+        Waveform,
+    },
+};
+use lpc845_test_suite::prelude::*;
+
+
+#[test]
+fn it_should_play_back_waveforms_with_precise_timing() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+
+    // The assistant's red pin is connected to the target's input pin. The
+    // waveform starts from a known level, and ends on it.
+    let red = 1 << OutputPin::Red as u8;
+    let steps = [
+        (0,    pin::Level::Low),
+        (2000, pin::Level::High),
+        (1000, pin::Level::Low),
+        (3000, pin::Level::High),
+    ];
+
+    let mut waveform = Waveform::new();
+    for &(delay_us, level) in &steps {
+        let levels = match level {
+            pin::Level::High => red,
+            pin::Level::Low  => 0,
+        };
+
+        waveform
+            .push(Step {
+                delay: Microseconds(delay_us),
+                mask:  red,
+                levels,
+            })
+            .unwrap();
+    }
+
+    test_stand.assistant.set_pin_high()?;
+    test_stand.target.report_pin_interrupts(true, timeout)?;
+    test_stand.assistant.play_waveform(&waveform, timeout)?;
+
+    let mut timestamps = Vec::new();
+    for &(_, level) in &steps {
+        let edge = match level {
+            pin::Level::High => pin::Edge::Rising,
+            pin::Level::Low  => pin::Edge::Falling,
+        };
+
+        let timestamp = test_stand.target
+            .wait_for_pin_interrupt(edge, timeout)?;
+        timestamps.push(timestamp);
+    }
+
+    test_stand.target.report_pin_interrupts(false, timeout)?;
+
+    // The target timestamps the edges, so the intervals between them don't
+    // depend on the latency of the serial connections.
+    for (window, &(delay_us, _)) in timestamps.windows(2).zip(&steps[1..]) {
+        let interval = timestamp::interval(window[0], window[1]);
+        let expected = Duration::from_micros(delay_us.into());
+
+        let error = if interval > expected {
+            interval - expected
+        }
+        else {
+            expected - interval
+        };
+        assert!(error <= Duration::from_micros(20),
+            "{:?} != {:?}", interval, expected);
+    }
+
+    Ok(())
+}
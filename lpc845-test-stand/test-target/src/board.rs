@@ -0,0 +1,44 @@
+//! Board support
+//!
+//! All choices of which pin is used for what are made in a board file, one per
+//! supported board layout. The board is selected using a Cargo feature. To
+//! support a carrier board with different wiring, add a board file here and a
+//! feature that selects it.
+//!
+//! Each board file needs to provide the same items:
+//! - A type alias for each pin, named after its role.
+//! - A `Pins` struct, containing all pins that the firmware uses.
+//! - A `Pins::take` function, which creates the `Pins` struct from the HAL's
+//!   pins and GPIO tokens.
+//! - The `GREEN` and `RED` pin identifiers, which the host uses to refer to
+//!   the GPIO pins, and the `USART_TX` identifier of the USART's TX pin.
+//! - The `OUTPUT_PINS` that the host can set, and the `INPUT_PINS` that it can
+//!   read in addition to those.
+//! - The `HostUsart` that is connected to the host, the `HostRxFunction` and
+//!   `HostTxFunction` of that USART, and a `take_host_usart` function, which
+//!   picks them from the HAL's peripherals.
+//! - The `HOST_LINK` description of the USART and pins connected to the host,
+//!   which is reported to the host as part of the firmware's capabilities.
+//! - The `CAPT_X_PINS` that are connected to capacitive touch sensors.
+//! - The `ADC_CHANNEL`, `DAC_CHANNEL`, and `DAC_LOOPBACK_CHANNEL` that the
+//!   analog pins belong to.
+//! - The `PIN_GROUP_PORT` whose pins the host can access as a group, and the
+//!   `PIN_GROUP_OUTPUTS` of that port that the host can set.
+//! - The `CONFIGURABLE_PINS` that the host can reconfigure, which must be
+//!   outputs with the pull-up enabled initially.
+//! - The `FILTER_PINS` whose input filter the host can enable.
+//!
+//! The host link can use any pins, and USART0 or USART4, so a board file can
+//! move it out of the way of the pins that need to be tested. The host skips
+//! test cases that need any of them.
+
+
+#[cfg(feature = "board-lpc845-brk")]
+mod lpc845_brk;
+
+#[cfg(feature = "board-lpc845-brk")]
+pub use self::lpc845_brk::*;
+
+
+#[cfg(not(any(feature = "board-lpc845-brk")))]
+compile_error!("No board selected. Please enable one of the board features.");
@@ -0,0 +1,266 @@
+//! Pin assignments for the LPC845-BRK, wired as documented in the README
+
+
+use lpc8xx_hal::{
+    gpio::{
+        GpioPin,
+        Level,
+        direction::{
+            Input,
+            Output,
+        },
+    },
+    init_state::{
+        Disabled,
+        Enabled,
+    },
+    pac,
+    pins::{
+        self,
+        Pin,
+        state::Unused,
+    },
+    swm::{
+        self,
+        state::Unassigned,
+    },
+    usart::USART,
+};
+
+use lpc845_messages::{
+    HostLink,
+    pin::PortPin,
+};
+
+
+// Connected to the programmer, and bridged to the host via USB. Careful, the
+// LCP845-BRK documentation uses the opposite designations (i.e. from the
+// perspective of the on-board programmer, not the microcontroller).
+pub type HostRx = pins::PIO0_24;
+pub type HostTx = pins::PIO0_25;
+
+// USART1 to USART3 are under test, so the host link can use USART0 or USART4.
+// To change it, change these, `take_host_usart`, and `HOST_LINK` together.
+pub type HostUsart      = pac::USART0;
+pub type HostRxFunction = swm::U0_RXD;
+pub type HostTxFunction = swm::U0_TXD;
+
+pub type UsartRx  = pins::PIO0_26;
+pub type UsartTx  = pins::PIO0_27;
+pub type UsartCts = pins::PIO0_8;
+pub type UsartRts = pins::PIO0_9;
+
+pub type UsartSyncRx   = pins::PIO0_13;
+pub type UsartSyncTx   = pins::PIO0_14;
+pub type UsartSyncSclk = pins::PIO0_15;
+
+pub type UsartDmaRx = pins::PIO0_28;
+pub type UsartDmaTx = pins::PIO0_30;
+
+// I2C0 is a fixed function, so these can't be changed.
+pub type I2cSda = pins::PIO0_11;
+pub type I2cScl = pins::PIO0_10;
+
+pub type SpiSck  = pins::PIO0_16;
+pub type SpiMosi = pins::PIO0_17;
+pub type SpiMiso = pins::PIO0_18;
+pub type SpiSsel = pins::PIO0_19;
+
+// ADC_6 is a fixed function, so this can't be changed.
+pub type Adc = pins::PIO0_20;
+
+// DACOUT1 and ADC_1 are fixed functions, so these can't be changed. They are
+// connected to each other, so the ADC can read back the DAC's output.
+pub type Dac         = pins::PIO0_29;
+pub type DacLoopback = pins::PIO0_6;
+
+pub type Green = pins::PIO1_0;
+pub type Blue  = pins::PIO1_1;
+pub type Red   = pins::PIO1_2;
+
+pub type Trigger = pins::PIO0_21;
+
+pub type Pwm = pins::PIO0_23;
+
+
+/// The output pin that the host can set (PIO1_0, the green LED)
+pub const GREEN: PortPin = PortPin::new(1, 0);
+
+/// The input pin that the host can read (PIO1_2, the red LED)
+pub const RED: PortPin = PortPin::new(1, 2);
+
+/// The GPIO pins that the host can set
+///
+/// These are the green and blue LEDs (PIO1_0 and PIO1_1), which are wired to
+/// inputs of the assistant.
+pub const OUTPUT_PINS: &[PortPin] = &[
+    GREEN,
+    PortPin::new(1, 1),
+];
+
+/// The GPIO pins that the host can read, in addition to `OUTPUT_PINS`
+///
+/// These are the red LED (PIO1_2) and the trigger input (PIO0_21), which are
+/// wired to outputs of the assistant.
+pub const INPUT_PINS: &[PortPin] = &[
+    RED,
+    PortPin::new(0, 21),
+];
+
+/// The pin that the USART's TX function is assigned to at startup (PIO0_27)
+pub const USART_TX: PortPin = PortPin::new(0, 27);
+
+/// The ADC channel that the analog input pin is connected to (PIO0_20)
+pub const ADC_CHANNEL: u8 = 6;
+
+/// The DAC channel whose output pin is used (DACOUT1, PIO0_29)
+pub const DAC_CHANNEL: u8 = 1;
+
+/// The ADC channel that the DAC output is connected to (ADC_1, PIO0_6)
+pub const DAC_LOOPBACK_CHANNEL: u8 = 1;
+
+/// The GPIO port whose pins the host can read and write as a group
+pub const PIN_GROUP_PORT: usize = 1;
+
+/// The pins of the group that are outputs (PIO1_0 and PIO1_1)
+///
+/// Bit `n` stands for pin `n` of `PIN_GROUP_PORT`. These are the green and
+/// blue LEDs. The red LED (PIO1_2) is an input.
+pub const PIN_GROUP_OUTPUTS: u32 = 0b011;
+
+/// The pins that the host can reconfigure, with their IOCON register index
+///
+/// The IOCON registers are not in the order of the pins. See table 196 in the
+/// user manual. These are the green and blue LEDs (PIO1_0 and PIO1_1), which
+/// are wired to inputs of the assistant, so it can observe the effects of the
+/// pull resistors while they are inputs.
+pub const CONFIGURABLE_PINS: &[(PortPin, usize)] = &[
+    (PortPin::new(1, 0), 36),
+    (PortPin::new(1, 1), 37),
+];
+
+/// The pins whose input filter the host can enable, with their IOCON index
+///
+/// This is the red LED (PIO1_2), which is wired to an output of the assistant.
+pub const FILTER_PINS: &[(PortPin, usize)] = &[
+    (RED, 38),
+];
+
+/// The X pins of the CAPT peripheral that are connected to a sensor
+///
+/// Bit `n` stands for X pin `n`. Only X0 (PIO0_31) is used. The CAPT pins are
+/// fixed functions, which `capt::init` enables, along with YL (PIO1_8) and YH
+/// (PIO1_9).
+pub const CAPT_X_PINS: u16 = 0b1;
+
+/// The USART and pins connected to the host (USART0, PIO0_24 and PIO0_25)
+pub const HOST_LINK: HostLink = HostLink::Usart {
+    usart: 0,
+    rx:    PortPin::new(0, 24),
+    tx:    PortPin::new(0, 25),
+};
+
+
+/// Pick the `HostUsart` and its functions from both candidates
+///
+/// The candidate that isn't picked stays disabled.
+pub fn take_host_usart(
+    usart0:  USART<pac::USART0, Disabled>,
+    u0_rxd:  swm::Function<swm::U0_RXD, Unassigned>,
+    u0_txd:  swm::Function<swm::U0_TXD, Unassigned>,
+    _usart4: USART<pac::USART4, Disabled>,
+    _u4_rxd: swm::Function<swm::U4_RXD, Unassigned>,
+    _u4_txd: swm::Function<swm::U4_TXD, Unassigned>,
+)
+    -> (
+        USART<HostUsart, Disabled>,
+        swm::Function<HostRxFunction, Unassigned>,
+        swm::Function<HostTxFunction, Unassigned>,
+    )
+{
+    (usart0, u0_rxd, u0_txd)
+}
+
+
+/// All pins used by the firmware
+pub struct Pins {
+    pub host_rx: Pin<HostRx, Unused>,
+    pub host_tx: Pin<HostTx, Unused>,
+
+    pub usart_rx:  Pin<UsartRx, Unused>,
+    pub usart_tx:  Pin<UsartTx, Unused>,
+    pub usart_cts: Pin<UsartCts, Unused>,
+    pub usart_rts: Pin<UsartRts, Unused>,
+
+    pub usart_sync_rx:   Pin<UsartSyncRx, Unused>,
+    pub usart_sync_tx:   Pin<UsartSyncTx, Unused>,
+    pub usart_sync_sclk: Pin<UsartSyncSclk, Unused>,
+
+    pub usart_dma_rx: Pin<UsartDmaRx, Unused>,
+    pub usart_dma_tx: Pin<UsartDmaTx, Unused>,
+
+    pub i2c_sda: Pin<I2cSda, Unused>,
+    pub i2c_scl: Pin<I2cScl, Unused>,
+
+    pub spi_sck:  Pin<SpiSck, Unused>,
+    pub spi_mosi: Pin<SpiMosi, Unused>,
+    pub spi_miso: Pin<SpiMiso, Unused>,
+    pub spi_ssel: GpioPin<SpiSsel, Output>,
+
+    pub adc: Pin<Adc, Unused>,
+
+    pub dac:          Pin<Dac, Unused>,
+    pub dac_loopback: Pin<DacLoopback, Unused>,
+
+    pub green: GpioPin<Green, Output>,
+    pub blue:  GpioPin<Blue, Output>,
+    pub red:   GpioPin<Red, Input>,
+
+    pub trigger: GpioPin<Trigger, Input>,
+
+    pub pwm: Pin<Pwm, Unused>,
+}
+
+impl Pins {
+    /// Take the pins used by the firmware, and configure the GPIO pins
+    pub fn take(pins: pins::Pins, tokens: pins::Tokens<Enabled>) -> Self {
+        Self {
+            host_rx: pins.pio0_24,
+            host_tx: pins.pio0_25,
+
+            usart_rx:  pins.pio0_26,
+            usart_tx:  pins.pio0_27,
+            usart_cts: pins.pio0_8,
+            usart_rts: pins.pio0_9,
+
+            usart_sync_rx:   pins.pio0_13,
+            usart_sync_tx:   pins.pio0_14,
+            usart_sync_sclk: pins.pio0_15,
+
+            usart_dma_rx: pins.pio0_28,
+            usart_dma_tx: pins.pio0_30,
+
+            i2c_sda: pins.pio0_11,
+            i2c_scl: pins.pio0_10,
+
+            spi_sck:  pins.pio0_16,
+            spi_mosi: pins.pio0_17,
+            spi_miso: pins.pio0_18,
+            spi_ssel: pins.pio0_19
+                .into_output_pin(tokens.pio0_19, Level::High),
+
+            adc: pins.pio0_20,
+
+            dac:          pins.pio0_29,
+            dac_loopback: pins.pio0_6,
+
+            green: pins.pio1_0.into_output_pin(tokens.pio1_0, Level::High),
+            blue:  pins.pio1_1.into_output_pin(tokens.pio1_1, Level::High),
+            red:   pins.pio1_2.into_input_pin(tokens.pio1_2),
+
+            trigger: pins.pio0_21.into_input_pin(tokens.pio0_21),
+
+            pwm: pins.pio0_23,
+        }
+    }
+}
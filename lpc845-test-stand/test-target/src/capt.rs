@@ -0,0 +1,199 @@
+//! Capacitive touch sensing, using the CAPT peripheral
+//!
+//! The HAL doesn't support the CAPT peripheral, so this module sets it up
+//! through its registers. The peripheral polls the selected X pins, one after
+//! the other. For each of them, it counts the cycles of its clock, until enough
+//! charge has been transferred from the sensor on the X pin to the capacitor on
+//! YL and YH, to trigger the digital input of YH. A finger on the sensor draws
+//! off charge, so a touch takes more cycles than no touch.
+//!
+//! A sensor that is grounded, which is how the assistant simulates a touch,
+//! never triggers YH. That ends the measurement with a timeout, which counts as
+//! a touch, too.
+
+
+use lpc8xx_hal::pac::{
+    SWM0,
+    SYSCON,
+    capt::RegisterBlock,
+};
+
+use lpc845_messages::{
+    CAPT_MAX_COUNT,
+    CaptEvent,
+};
+
+
+/// The divider of the CAPT clock, minus one
+///
+/// The CAPT runs from the 12 MHz FRO, which is divided down to 4 MHz.
+const FDIV: u8 = 2;
+
+/// The time-out of a measurement, as the log2 of the count
+///
+/// A measurement times out after 4096 cycles, so it never counts more than
+/// `CAPT_MAX_COUNT`.
+const TOUT: u8 = 12;
+
+/// The delay between two rounds of polling
+///
+/// In wrap-arounds of a 12-bit counter, which is about 1 ms each, with the
+/// clock divided down to 4 MHz.
+const POLL: u8 = 10;
+
+
+/// Enable the CAPT peripheral, and connect it to its pins
+///
+/// Bit `n` of `x_pins` stands for X pin `n`. YL and YH are always connected.
+pub fn init(x_pins: u16) {
+    // Sound, as the HAL doesn't use the clock, reset, or fixed functions of
+    // the CAPT peripheral, and we only change their fields.
+    let syscon = unsafe { &*SYSCON::ptr() };
+    let swm    = unsafe { &*SWM0::ptr() };
+
+    syscon.captclksel.write(|w| w.sel().fro());
+    syscon.sysahbclkctrl1.modify(|_, w| w.capt().enable());
+    syscon.presetctrl1.modify(|_, w| w.capt_rst_n().clear());
+
+    // Fixed functions are enabled by clearing their bits. X0 to X3 are the top
+    // bits of PINENABLE0, X4 to X8 the bottom bits of PINENABLE1, followed by
+    // YL and YH.
+    let enable0 = u32::from(x_pins & 0xf) << 28;
+    let enable1 = u32::from(x_pins >> 4 & 0x1f) | 0b11 << 5;
+    swm.pinenable0.modify(|r, w| unsafe { w.bits(r.bits() & !enable0) });
+    swm.pinenable1.modify(|r, w| unsafe { w.bits(r.bits() & !enable1) });
+}
+
+
+/// Capacitive touch sensors that are being scanned
+pub struct Scan {
+    /// The X pins that have been measured at least once
+    measured: u16,
+
+    /// The X pins whose last measurement was a touch
+    touched: u16,
+}
+
+impl Scan {
+    /// Start polling the X pins in `pins`
+    ///
+    /// Measurements that count more than `threshold` cycles are touches.
+    ///
+    /// Returns `Err`, without changing anything, if no pins are selected, or if
+    /// `threshold` is larger than `CAPT_MAX_COUNT`.
+    pub fn start(capt: &RegisterBlock, pins: u16, threshold: u16)
+        -> Result<Self, InvalidScan>
+    {
+        if pins == 0 || threshold > CAPT_MAX_COUNT {
+            return Err(InvalidScan);
+        }
+
+        // The configuration can only be changed while polling is stopped.
+        stop(capt);
+
+        capt.poll_tcnt.write(|w| unsafe {
+            w
+                .tcnt().bits(threshold)
+                .tout().bits(TOUT)
+                .poll().bits(POLL)
+                .mdelay().bits(1)
+                .rdelay().bits(1)
+        });
+        capt.intenset.write(|w| {
+            w
+                .yestouch().set_bit()
+                .notouch().set_bit()
+                .timeout().set_bit()
+        });
+        capt.ctrl.write(|w| {
+            w
+                .pollmode().normal()
+                .type_().type_0()
+                .trigger().uses_yh_gpio()
+                .xpinuse().normal_mdoe();
+            unsafe {
+                w
+                    .fdiv().bits(FDIV)
+                    .xpinsel().bits(pins)
+            }
+        });
+
+        Ok(Self {
+            measured: 0,
+            touched:  0,
+        })
+    }
+
+    /// Handle a finished measurement
+    ///
+    /// Needs to be called from the CMP_CAPT interrupt handler. Returns an
+    /// event, if this is the first measurement of its X pin, or if the pin has
+    /// crossed the threshold since the last one.
+    pub fn handle_interrupt(&mut self, capt: &RegisterBlock, timestamp: u32)
+        -> Option<CaptEvent>
+    {
+        let touch = capt.touch.read();
+        clear_status(capt);
+
+        // The register is being updated, and will interrupt again.
+        if touch.change().bit_is_set() {
+            return None;
+        }
+
+        let pin     = touch.xval().bits();
+        let mask    = 1 << pin;
+        let touched = touch.istouch().bit_is_set() || touch.isto().bit_is_set();
+
+        let first   = self.measured & mask == 0;
+        let crossed = (self.touched & mask != 0) != touched;
+
+        self.measured |= mask;
+        if touched {
+            self.touched |= mask;
+        }
+        else {
+            self.touched &= !mask;
+        }
+
+        if !first && !crossed {
+            return None;
+        }
+
+        Some(CaptEvent {
+            pin,
+            touched,
+            count: touch.count().bits(),
+            timestamp,
+        })
+    }
+}
+
+
+/// The requested scan isn't supported
+#[derive(Debug)]
+pub struct InvalidScan;
+
+/// Stop polling, and discard a measurement that hasn't been handled yet
+pub fn stop(capt: &RegisterBlock) {
+    capt.intenclr.write(|w| {
+        w
+            .yestouch().set_bit()
+            .notouch().set_bit()
+            .timeout().set_bit()
+    });
+    capt.ctrl.modify(|_, w| w.pollmode().none());
+    while capt.ctrl.read().inchange().bit_is_set() {}
+
+    clear_status(capt);
+}
+
+fn clear_status(capt: &RegisterBlock) {
+    capt.status.write(|w| {
+        w
+            .yestouch().set_bit()
+            .notouch().set_bit()
+            .polldone().set_bit()
+            .timeout().set_bit()
+            .overun().set_bit()
+    });
+}
@@ -0,0 +1,155 @@
+//! Output of the digital-to-analog converter (DAC)
+//!
+//! The HAL doesn't support the DAC, so this module sets it up through its
+//! registers. Only DAC1 is used, as the output pin of DAC0 is taken by SPI. The
+//! output pin itself is a fixed function, which is enabled through the HAL.
+//!
+//! Waveforms are timed by channel 1 of the multi-rate timer (MRT). Its
+//! interrupt handler writes one sample to the DAC every time it expires.
+
+
+use core::convert::TryFrom;
+
+use lpc8xx_hal::{
+    mrt,
+    pac::{
+        MRT0,
+        SYSCON,
+        dac0::RegisterBlock,
+    },
+};
+
+use lpc845_messages::{
+    DAC_WAVEFORM_CAP,
+    DAC_WAVEFORM_MAX_RATE,
+    decode_samples,
+};
+
+
+/// The frequency of the MRT's clock, in Hz
+const MRT_HZ: u32 = 12_000_000;
+
+
+/// Power up DAC1, and enable its clock
+pub fn init() {
+    // Sound, as the HAL doesn't use the power, clock, or reset of the DAC, and
+    // we only change their fields.
+    let syscon = unsafe { &*SYSCON::ptr() };
+
+    // The PAC names the power bit after the wake-up configuration. Clearing it
+    // powers the DAC up.
+    syscon.pdruncfg.modify(|_, w| w.dac1().clear_bit());
+    syscon.sysahbclkctrl1.modify(|_, w| w.dac1().enable());
+    syscon.presetctrl1.modify(|_, w| w.dac1_rst_n().clear());
+}
+
+/// Write a raw 12-bit value to the DAC
+///
+/// The DAC only has 10 bits, so the lowest two bits of `value` are ignored.
+pub fn set(dac: &RegisterBlock, value: u16) {
+    dac.cr.write(|w| unsafe { w.value().bits(value >> 2 & 0x3ff) });
+}
+
+
+/// A waveform that is being output by the DAC
+pub struct Waveform {
+    samples: [u16; DAC_WAVEFORM_CAP],
+    len:     usize,
+
+    /// The index of the sample that is output next
+    next: usize,
+}
+
+impl Waveform {
+    /// Start outputting the encoded `samples`, `rate` times per second
+    ///
+    /// Outputs the first sample right away.
+    ///
+    /// Returns `Err`, without changing the output, if there are no samples or
+    /// too many, or if `rate` is zero or larger than `DAC_WAVEFORM_MAX_RATE`.
+    pub fn start(
+        dac:     &RegisterBlock,
+        channel: &mut mrt::Channel<mrt::MRT1>,
+        samples: &[u8],
+        rate:    u32,
+    )
+        -> Result<Self, InvalidWaveform>
+    {
+        if rate == 0 || rate > DAC_WAVEFORM_MAX_RATE {
+            return Err(InvalidWaveform);
+        }
+
+        let mut buf = [0; DAC_WAVEFORM_CAP];
+        let mut len = 0;
+        for sample in decode_samples(samples) {
+            if len == DAC_WAVEFORM_CAP {
+                return Err(InvalidWaveform);
+            }
+            buf[len] = sample;
+            len += 1;
+        }
+        if len == 0 {
+            return Err(InvalidWaveform);
+        }
+
+        stop(channel);
+
+        let mut waveform = Self {
+            samples: buf,
+            len,
+            next:    0,
+        };
+        waveform.handle_interrupt(dac);
+
+        // Can't fail, as the rate is limited well below the MRT's clock.
+        let ticks = mrt::Ticks::try_from(MRT_HZ / rate)
+            .unwrap_or_else(|_| unreachable!());
+
+        // Sound, as the HAL doesn't touch the control register, and the
+        // interrupt handler only touches the status register.
+        let mrt = unsafe { &*MRT0::ptr() };
+        mrt.channel[1].ctrl.write(|w| {
+            w
+                .inten().enabled()
+                .mode().repeat_interrupt_mode()
+        });
+
+        channel.start(ticks);
+
+        Ok(waveform)
+    }
+
+    /// Output the next sample
+    ///
+    /// Needs to be called from the MRT0 interrupt handler, whenever channel 1
+    /// has expired.
+    pub fn handle_interrupt(&mut self, dac: &RegisterBlock) {
+        set(dac, self.samples[self.next]);
+        self.next = (self.next + 1) % self.len;
+    }
+}
+
+
+/// The requested waveform can't be output
+#[derive(Debug)]
+pub struct InvalidWaveform;
+
+
+/// Stop the timer that outputs a waveform
+///
+/// The DAC keeps outputting the last sample. Takes the channel, to make sure
+/// it's not used at the same time.
+pub fn stop(_: &mut mrt::Channel<mrt::MRT1>) {
+    // Sound, as the HAL doesn't touch the control register, and the interrupt
+    // can't fire, once it has been disabled.
+    let mrt = unsafe { &*MRT0::ptr() };
+    mrt.channel[1].ctrl.write(|w| w.inten().disabled());
+
+    // Loading `0` stops the timer immediately, and clearing the flag discards
+    // an expiration that hasn't been handled yet.
+    mrt.channel[1].intval.write(|w| {
+        w.load().set_bit();
+        unsafe { w.ivalue().bits(0) }
+    });
+    mrt.channel[1].stat.write(|w| w.intflag().set_bit());
+}
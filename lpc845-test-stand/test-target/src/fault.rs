@@ -0,0 +1,127 @@
+//! Fault handling
+//!
+//! If the firmware panics or runs into a HardFault, it records what happened
+//! and resets itself. After the reset, the fault is reported to the host, which
+//! allows test cases to expect faults. Watchdog resets are detected using the
+//! reset status register and reported the same way. The host can ask for the
+//! cause of any reset, as recorded by that register.
+//!
+//! Panics are also reported right before the reset, with their message and
+//! location (see `firmware_lib::panic`).
+
+
+use core::{
+    mem::MaybeUninit,
+    panic::PanicInfo,
+    ptr,
+};
+
+use cortex_m_rt::{
+    ExceptionFrame,
+    exception,
+};
+use lpc8xx_hal::{
+    cortex_m::{
+        interrupt,
+        peripheral::SCB,
+    },
+    pac::SYSCON,
+};
+use rtt_target::rprintln;
+
+use firmware_lib::panic;
+use lpc845_messages::{
+    FaultKind,
+    TargetToHost,
+    reset,
+};
+
+
+/// Marks a valid fault record in `FAULT`
+///
+/// `FAULT` isn't initialized at startup, so it might contain anything after a
+/// power-on reset.
+const MAGIC: u32 = 0xfa17_0000;
+
+
+// Not initialized at startup, so it survives the reset.
+#[link_section = ".uninit.FAULT"]
+static mut FAULT: MaybeUninit<u32> = MaybeUninit::uninit();
+
+
+/// Returns the cause of the last reset, and the fault that caused it, if any
+///
+/// Clears the record and the reset status register, so both are only returned
+/// once. Needs to be called once at startup.
+pub fn take_last() -> (reset::Cause, Option<FaultKind>) {
+    // Sound, as this is only accessed here and before a reset, and those can't
+    // happen at the same time.
+    let record = unsafe {
+        let fault  = ptr::addr_of_mut!(FAULT).cast::<u32>();
+        let record = ptr::read_volatile(fault);
+        ptr::write_volatile(fault, 0);
+        record
+    };
+
+    // Sound, as nothing else accesses the reset status register.
+    let syscon = unsafe { &*SYSCON::ptr() };
+    let status = syscon.sysrststat.read();
+    // Status bits are cleared by writing `1`.
+    syscon.sysrststat.write(|w| unsafe { w.bits(status.bits()) });
+
+    // More than one bit might be set. Report the most specific one.
+    let cause = if status.wdt().is_detected() {
+        reset::Cause::Watchdog
+    }
+    else if status.sysrst().is_detected() {
+        reset::Cause::Software
+    }
+    else if status.bod().is_detected() {
+        reset::Cause::BrownOut
+    }
+    else if status.por().is_detected() {
+        reset::Cause::PowerOn
+    }
+    else if status.extrst().is_detected() {
+        reset::Cause::Pin
+    }
+    else {
+        reset::Cause::Unknown
+    };
+
+    if cause == reset::Cause::Watchdog {
+        return (cause, Some(FaultKind::Watchdog));
+    }
+
+    let fault = [FaultKind::Panic, FaultKind::HardFault]
+        .iter()
+        .copied()
+        .find(|&kind| record == MAGIC | kind as u32);
+
+    (cause, fault)
+}
+
+fn record_and_reset(kind: FaultKind) -> ! {
+    // Sound, as we never return from here.
+    unsafe {
+        let fault = ptr::addr_of_mut!(FAULT).cast::<u32>();
+        ptr::write_volatile(fault, MAGIC | kind as u32);
+    }
+    SCB::sys_reset()
+}
+
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+
+    rprintln!("{}", info);
+    panic::report(info, TargetToHost::Panic);
+    record_and_reset(FaultKind::Panic)
+}
+
+#[exception]
+fn HardFault(frame: &ExceptionFrame) -> ! {
+    rprintln!("HardFault: {:?}", frame);
+    record_and_reset(FaultKind::HardFault)
+}
@@ -0,0 +1,207 @@
+//! Information about, and access to, the flash memory
+//!
+//! The layout is fixed by the LPC845 (see user manual, chapter 5). How much of
+//! the flash memory is in use is determined from the symbols that the linker
+//! script of `cortex-m-rt` defines.
+//!
+//! The flash memory after the firmware image can be erased and programmed,
+//! using the IAP commands in ROM (see user manual, chapter 4). The HAL doesn't
+//! wrap those, so they're called directly. The last pages are reserved for the
+//! key-value store (see `store`), and the host can access everything between
+//! the firmware image and those.
+
+
+use core::{
+    mem,
+    slice,
+};
+
+use lpc8xx_hal::cortex_m::interrupt;
+
+use lpc845_messages::{
+    FlashError,
+    FlashInfo,
+    STORE_CAP,
+};
+
+
+/// The address at which the flash memory starts
+const START: u32 = 0x0000_0000;
+
+/// The size of the flash memory, in bytes
+const SIZE: u32 = 64 * 1024;
+
+/// The size of a sector, the smallest area that can be erased, in bytes
+const SECTOR_SIZE: u32 = 1024;
+
+/// The size of a page, the smallest area that can be programmed, in bytes
+const PAGE_SIZE: u32 = 64;
+
+/// The size of the key-value store, in bytes
+///
+/// Each entry of the store takes up 8 bytes (see `store`).
+pub const STORE_SIZE: u32 = STORE_CAP as u32 * 8;
+
+/// The address of the key-value store, in the last pages of flash memory
+pub const STORE_START: u32 = START + SIZE - STORE_SIZE;
+
+/// The entry point of the IAP commands in ROM, with the Thumb bit set
+const IAP_ENTRY: usize = 0x0f00_1ff1;
+
+/// The frequency of the system clock in kHz, as needed by some IAP commands
+const SYSTEM_CLOCK_KHZ: u32 = 12_000;
+
+// IAP command codes
+const PREPARE_SECTORS:   u32 = 50;
+const COPY_RAM_TO_FLASH: u32 = 51;
+const ERASE_PAGES:       u32 = 59;
+
+// IAP status codes
+const CMD_SUCCESS:         u32 = 0;
+const SRC_ADDR_ERROR:      u32 = 2;
+const DST_ADDR_ERROR:      u32 = 3;
+const SRC_ADDR_NOT_MAPPED: u32 = 4;
+const DST_ADDR_NOT_MAPPED: u32 = 5;
+const COUNT_ERROR:         u32 = 6;
+const INVALID_SECTOR:      u32 = 7;
+
+
+extern "C" {
+    // The load address of the initial values of `.data`, which are the last
+    // part of the firmware image.
+    static __sidata: u32;
+
+    // The start and end of `.data` in RAM.
+    static __sdata: u32;
+    static __edata: u32;
+}
+
+
+/// Returns the layout of the flash memory, and how much of it is in use
+pub fn info() -> FlashInfo {
+    // Sound, as we only take the addresses of the symbols, without reading
+    // from them.
+    let (sidata, sdata, edata) = unsafe {
+        (
+            &__sidata as *const u32 as u32,
+            &__sdata as *const u32 as u32,
+            &__edata as *const u32 as u32,
+        )
+    };
+
+    FlashInfo {
+        start:       START,
+        size:        SIZE,
+        sector_size: SECTOR_SIZE,
+        page_size:   PAGE_SIZE,
+        used:        sidata + (edata - sdata) - START,
+        reserved:    STORE_SIZE,
+    }
+}
+
+/// Erase the pages starting at `address`, and program `data` into them
+///
+/// Refuses to touch the firmware image and the key-value store. Apart from
+/// that, see `program`.
+pub fn write(address: u32, data: &[u8]) -> Result<(), FlashError> {
+    let end = address.checked_add(data.len() as u32)
+        .ok_or(FlashError::OutOfBounds)?;
+    if address < scratch_start() || end > STORE_START {
+        return Err(FlashError::OutOfBounds);
+    }
+
+    program(address, data)
+}
+
+/// Erase the pages starting at `address`, and program `data` into them
+///
+/// Doesn't check what's there. The IAP commands check `address` and the
+/// length of `data` themselves, and their errors are passed on. Pages before
+/// the one that failed might have been programmed.
+pub fn program(address: u32, data: &[u8]) -> Result<(), FlashError> {
+    // The source of `COPY_RAM_TO_FLASH` must be word-aligned.
+    #[repr(align(4))]
+    struct Page([u8; PAGE_SIZE as usize]);
+
+    // The interrupt handlers and the vector table are in flash, which can't be
+    // read while it's being erased or programmed.
+    interrupt::free(|_| {
+        for (i, chunk) in data.chunks(PAGE_SIZE as usize).enumerate() {
+            let address = address + i as u32 * PAGE_SIZE;
+            let page    = address / PAGE_SIZE;
+            let sector  = address / SECTOR_SIZE;
+
+            let mut buf = Page([0xff; PAGE_SIZE as usize]);
+            buf.0[..chunk.len()].copy_from_slice(chunk);
+
+            // Each erase or program command needs its own preparation.
+            iap(&[PREPARE_SECTORS, sector, sector])?;
+            iap(&[ERASE_PAGES, page, page, SYSTEM_CLOCK_KHZ])?;
+            iap(&[PREPARE_SECTORS, sector, sector])?;
+            iap(&[
+                COPY_RAM_TO_FLASH,
+                address,
+                buf.0.as_ptr() as u32,
+                chunk.len() as u32,
+                SYSTEM_CLOCK_KHZ,
+            ])?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Returns `len` bytes of flash memory, starting at `address`
+///
+/// Only the flash memory that `write` can write can be read, as reading the
+/// rest isn't useful, and reading outside of the flash memory would fault.
+pub fn read(address: u32, len: u32) -> Result<&'static [u8], FlashError> {
+    let end = address.checked_add(len).ok_or(FlashError::OutOfBounds)?;
+    if address < scratch_start() || end > STORE_START {
+        return Err(FlashError::OutOfBounds);
+    }
+
+    // Sound, as we just made sure that the whole area is flash memory, which
+    // is only modified by `write`, and never while the slice is in use.
+    let data = unsafe { slice::from_raw_parts(address as *const u8, len as _) };
+    Ok(data)
+}
+
+/// Returns the start of the first page after the firmware image
+fn scratch_start() -> u32 {
+    let end = START + info().used;
+    (end + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+/// Run an IAP command
+///
+/// `command` consists of the command code, followed by its parameters.
+fn iap(command: &[u32]) -> Result<(), FlashError> {
+    let mut command_buf = [0u32; 5];
+    command_buf[..command.len()].copy_from_slice(command);
+    let mut result = [0u32; 4];
+
+    // Sound, as `IAP_ENTRY` is the documented entry point, and both buffers
+    // are as large as the largest command and result. The IAP commands use the
+    // top 32 bytes of RAM, which the stack stays clear of (see `build.rs`).
+    unsafe {
+        let iap_entry: extern "C" fn(*const u32, *mut u32) =
+            mem::transmute(IAP_ENTRY);
+        iap_entry(command_buf.as_ptr(), result.as_mut_ptr());
+    }
+
+    match result[0] {
+        CMD_SUCCESS => {
+            Ok(())
+        }
+        SRC_ADDR_ERROR | DST_ADDR_ERROR | COUNT_ERROR => {
+            Err(FlashError::Unaligned)
+        }
+        SRC_ADDR_NOT_MAPPED | DST_ADDR_NOT_MAPPED | INVALID_SECTOR => {
+            Err(FlashError::OutOfBounds)
+        }
+        status => {
+            Err(FlashError::Iap(status))
+        }
+    }
+}
@@ -8,151 +8,485 @@
 #![no_std]
 
 
-extern crate panic_rtt_target;
-
-
-use core::marker::PhantomData;
+// A board file describes all of the board's pins. Builds that leave out some
+// of the peripherals, or safe mode, don't use all of them.
+#[allow(dead_code)]
+mod board;
+mod fault;
+// Safe mode only reports the layout of the flash memory.
+#[cfg_attr(feature = "safe-mode", allow(dead_code))]
+mod flash;
+
+#[cfg(not(feature = "safe-mode"))]
+mod capt;
+#[cfg(not(feature = "safe-mode"))]
+mod pin_config;
+#[cfg(not(feature = "safe-mode"))]
+mod pin_group;
+#[cfg(not(feature = "safe-mode"))]
+mod pin_level;
+#[cfg(not(feature = "safe-mode"))]
+mod pwm;
+#[cfg(not(feature = "safe-mode"))]
+mod store;
+
+#[cfg(feature = "dac")]
+mod dac;
+#[cfg(feature = "safe-mode")]
+mod safe_mode;
+
+
+// Everything below is only used by the regular firmware.
+
+#[cfg(not(feature = "safe-mode"))]
+use core::{
+    convert::TryFrom,
+    sync::atomic::{
+        AtomicU32,
+        Ordering,
+    },
+};
 
+#[cfg(not(feature = "safe-mode"))]
 use heapless::spsc;
+#[cfg(not(feature = "safe-mode"))]
 use lpc8xx_hal::{
     prelude::*,
     Peripherals,
     cortex_m::{
-        interrupt,
-        peripheral::SYST,
-    },
-    dma::{
-        self,
-        transfer::state::Started,
+        interrupt::{
+            self,
+            Nr,
+        },
+        peripheral::{
+            NVIC,
+            SCB,
+            SYST,
+        },
     },
     gpio::{
+        self,
         GpioPin,
-        Level,
         direction::{
-            Input,
+            Dynamic,
             Output,
         },
     },
-    i2c,
     init_state::Enabled,
-    nb::{
-        self,
-        block,
-    },
+    mrt,
     pac::{
-        I2C0,
-        SPI0,
-        USART0,
+        CAPT,
+        CTIMER0,
+        Interrupt,
+        MRT0,
+        PINT,
+        PMU,
+        SYSCON,
         USART1,
-        USART2,
-        USART3,
+        WKT,
     },
     pinint::{
         self,
         PININT0,
+        PININT1,
     },
-    pins::{
+    pins::DynamicPinDirection,
+    pmu::LowPowerClock,
+    swm,
+    syscon::frg,
+    usart::{
         self,
-        Pin,
-        PIO0_8,
-        PIO0_9,
-        PIO0_19,
-        PIO1_0,
-        PIO1_1,
-        PIO1_2,
+        state::AsyncMode,
     },
-    spi::{
+};
+#[cfg(not(feature = "safe-mode"))]
+use rtt_target::rprintln;
+#[cfg(not(feature = "safe-mode"))]
+use void::{
+    ResultVoidExt,
+    Void,
+};
+
+#[cfg(not(feature = "safe-mode"))]
+use lpc8xx_hal::cortex_m::asm;
+
+#[cfg(not(feature = "safe-mode"))]
+use firmware_lib::{
+    overrun,
+    panic,
+    usart::{
+        RxIdle,
+        Sender,
+        Usart,
+        lpc8xx::{
+            self,
+            QueuedTx,
+            RxInt,
+            SenderInt,
+        },
+        rx::ProcessError,
+    },
+};
+#[cfg(not(feature = "safe-mode"))]
+use lpc845_messages::{
+    CaptEvent,
+    Capabilities,
+    HEARTBEAT_INTERVAL_MS,
+    HostToTarget,
+    IrqLatencies,
+    IrqState,
+    PROTOCOL_VERSION,
+    SleepMode,
+    TargetToHost,
+    TimerMode,
+    WakeupCause,
+    WakeupSource,
+    chunk::Reassembly,
+    event::{
         self,
-        SPI,
+        Kind,
+    },
+    log,
+    pin,
+    trigger,
+    units::Microseconds,
+};
+
+#[cfg(not(feature = "safe-mode"))]
+use self::{
+    board::{
+        GREEN,
+        HostUsart,
+        RED,
     },
+    pin_config::FilterError,
+};
+
+// What's only needed for some of the peripherals.
+
+#[cfg(feature = "usart")]
+use core::sync::atomic::AtomicBool;
+#[cfg(feature = "usart")]
+use lpc8xx_hal::{
+    pins::Pin,
     swm::{
-        self,
         U1_CTS,
         U1_RTS,
-        state::{
-            Assigned,
-            Unassigned,
-        },
+        state::Unassigned,
     },
-    syscon::{
-        IOSC,
-        frg,
-    },
-    usart::{
+};
+#[cfg(feature = "usart")]
+use firmware_lib::usart::rx::ReceiveError;
+#[cfg(feature = "usart")]
+use lpc845_messages::{
+    USART_CHUNK_CAP,
+    UsartEcho,
+    UsartError,
+    UsartWakeup,
+};
+
+#[cfg(feature = "usart-dma")]
+use lpc8xx_hal::pac::USART2;
+#[cfg(feature = "usart-dma")]
+use firmware_lib::usart::{
+    DmaRx,
+    DmaRxIdle,
+    DmaRxInt,
+};
+
+#[cfg(feature = "usart-sync")]
+use lpc8xx_hal::{
+    pac::USART3,
+    usart::state::SyncMode,
+};
+
+#[cfg(any(feature = "usart", feature = "usart-sync"))]
+use firmware_lib::usart::lpc8xx::Tx;
+#[cfg(any(feature = "usart", feature = "usart-sync"))]
+use lpc845_messages::UsartMode;
+
+#[cfg(feature = "i2c")]
+use core::{
+    marker::PhantomData,
+    mem,
+    ptr,
+};
+#[cfg(feature = "i2c")]
+use lpc8xx_hal::{
+    i2c,
+    pac::I2C0,
+    syscon::IOSC,
+};
+#[cfg(feature = "i2c")]
+use lpc845_messages::I2C_BUF_CAP;
+
+#[cfg(feature = "spi")]
+use lpc8xx_hal::{
+    pac::SPI0,
+    spi::{
         self,
-        state::{
-            AsyncMode,
-            SyncMode,
-        },
+        SPI,
     },
 };
-use rtt_target::rprintln;
+#[cfg(feature = "spi")]
+use lpc845_messages::SPI_BUF_CAP;
 
-#[cfg(feature = "sleep")]
-use lpc8xx_hal::cortex_m::asm;
+#[cfg(any(feature = "i2c", feature = "spi"))]
+use lpc845_messages::DmaMode;
 
-use firmware_lib::usart::{
-    RxIdle,
-    RxInt,
-    Tx,
-    Usart,
+#[cfg(any(feature = "usart-dma", feature = "i2c", feature = "spi"))]
+use lpc8xx_hal::dma;
+
+#[cfg(any(feature = "usart", feature = "spi"))]
+use lpc8xx_hal::{
+    nb,
+    pins,
+};
+
+#[cfg(feature = "adc")]
+use lpc8xx_hal::{
+    ADC,
+    syscon::clock_source::AdcClock,
 };
+#[cfg(feature = "adc")]
 use lpc845_messages::{
-    DmaMode,
-    HostToTarget,
-    TargetToHost,
-    UsartMode,
-    pin,
+    ADC_SAMPLES_CAP,
+    encode_samples,
 };
 
+#[cfg(any(feature = "spi", feature = "adc"))]
+use lpc8xx_hal::nb::block;
+
+#[cfg(any(feature = "usart", feature = "adc"))]
+use lpc8xx_hal::swm::state::Assigned;
+
+#[cfg(feature = "dac")]
+use lpc8xx_hal::pac::DAC1;
+
+
+/// The functionality this firmware was built with
+///
+/// Which peripherals are supported depends on the enabled Cargo features. See
+/// `Cargo.toml`.
+#[cfg(not(feature = "safe-mode"))]
+const CAPABILITIES: Capabilities = Capabilities {
+    usart:              cfg!(feature = "usart"),
+    usart_dma:          cfg!(feature = "usart-dma"),
+    usart_flow_control: cfg!(feature = "usart"),
+    usart_sync:         cfg!(feature = "usart-sync"),
+    i2c:                cfg!(feature = "i2c"),
+    i2c_dma:            cfg!(feature = "i2c"),
+    spi:                cfg!(feature = "spi"),
+    spi_dma:            cfg!(feature = "spi"),
+    adc:                cfg!(feature = "adc"),
+    dac:                cfg!(feature = "dac"),
+    pwm:                true,
+    timer_interrupt:    true,
+    trigger:            true,
+    host_link:          board::HOST_LINK,
+};
 
+/// The frequency of the free-running timer, in Hz
+///
+/// The timer times out partial host requests, and timestamps events. Those
+/// timestamps are reported to the host in microseconds, so this can't be
+/// changed.
+#[cfg(not(feature = "safe-mode"))]
+const TIMER_HZ: u32 = 1_000_000;
+
+/// The time after which a partial host request is discarded, in timer ticks
+#[cfg(not(feature = "safe-mode"))]
+const HOST_RX_TIMEOUT: u32 = TIMER_HZ / 10;
+
+/// The time between heartbeats, in timer ticks
+#[cfg(not(feature = "safe-mode"))]
+const HEARTBEAT_INTERVAL: u32 = TIMER_HZ / 1000 * HEARTBEAT_INTERVAL_MS;
+
+/// How long to wait for a byte to be received, after waking up from it
+///
+/// In timer ticks. Long enough for a byte at 1200 baud.
+#[cfg(feature = "usart")]
+const WAKEUP_RX_TIMEOUT: u32 = TIMER_HZ / 100;
+
+/// The frequency of the self-wake-up timer, in Hz
+///
+/// This is the frequency of the low-power clock, which the timer runs from.
+#[cfg(not(feature = "safe-mode"))]
+const WKT_HZ: u32 = 10_000;
+
+/// How long to wait for the shared line to reach the driven level
+///
+/// In timer ticks. See `drive_shared_line`.
+#[cfg(not(feature = "safe-mode"))]
+const SHARED_LINE_TIMEOUT: u32 = TIMER_HZ / 1000;
+
+/// How long a break is held on the TX line, in system clock cycles
+///
+/// This is 2 ms at 12 MHz.
+#[cfg(feature = "usart")]
+const BREAK_CYCLES: u32 = 24_000;
+
+/// Frequency of the clock that the USARTs run from, in Hz
+///
+/// This is the output of FRG0, as configured in `init`: the 12 MHz FRO, divided
+/// by `1 + 22/256`.
+#[cfg(feature = "usart")]
+const USART_CLOCK_HZ: u32 = 11_050_359;
+
+/// The interrupts that the latency workload can pend
+///
+/// Their handlers can run without a cause, and record when they were entered.
+#[cfg(not(feature = "safe-mode"))]
+const WORKLOAD_IRQS: u32 =
+    1 << 3       // USART0
+    | 1 << 4     // USART1
+    | 1 << 24    // PIN_INT0
+    | 1 << 25    // PIN_INT1
+    | 1 << 30;   // PIN_INT6_USART3
+
+/// When the handler of each interrupt was last entered, in timer ticks
+///
+/// Indexed by interrupt number. Used by the latency workload, and to timestamp
+/// received data. Atomics are used, as the handlers can preempt each other,
+/// once their priorities have been changed.
+#[cfg(not(feature = "safe-mode"))]
+static IRQ_ENTERED: [AtomicU32; 32] = [const { AtomicU32::new(0) }; 32];
+
+/// Counts what the interrupt handlers dropped, because a queue was full
+///
+/// Covers the queues that are set up in `init`. The ones in the `Usart`
+/// wrappers count for themselves. `idle` reports the sum to the host.
+#[cfg(not(feature = "safe-mode"))]
+static OVERRUN: overrun::Counter = overrun::Counter::new();
+
+/// Set by the USART1 handler, when the USART has detected a break
+///
+/// `idle` reports the break to the host, and resets this.
+#[cfg(feature = "usart")]
+static USART_BREAK: AtomicBool = AtomicBool::new(false);
+
+
+// In safe mode, the firmware in `safe_mode` is built instead.
+#[cfg(not(feature = "safe-mode"))]
 #[rtic::app(device = lpc8xx_hal::pac)]
 const APP: () = {
     struct Resources {
         swm: Option<swm::Handle>,
 
-        host_rx_int:  RxInt<'static, USART0, AsyncMode>,
+        host_rx_int:  RxInt<'static, HostUsart, AsyncMode>,
         host_rx_idle: RxIdle<'static>,
-        host_tx:      Tx<USART0, AsyncMode>,
+        host_tx_int:  SenderInt<'static, HostUsart, AsyncMode>,
+        host_tx:      QueuedTx<'static, HostUsart>,
 
+        #[cfg(feature = "usart")]
         usart_rx_int:  RxInt<'static, USART1, AsyncMode>,
+        #[cfg(feature = "usart")]
         usart_rx_idle: RxIdle<'static>,
+        #[cfg(feature = "usart")]
         usart_tx:      Option<Tx<USART1, AsyncMode>>,
+        #[cfg(feature = "usart")]
         usart_rts:     Option<swm::Function<U1_RTS, Unassigned>>,
-        usart_rts_pin: Option<Pin<PIO0_9, pins::state::Swm<(), ()>>>,
-        usart_cts:     Option<swm::Function<U1_CTS, Assigned<PIO0_8>>>,
-
+        #[cfg(feature = "usart")]
+        usart_rts_pin: Option<Pin<board::UsartRts, pins::state::Swm<(), ()>>>,
+        #[cfg(feature = "usart")]
+        usart_cts:     Option<swm::Function<U1_CTS, Assigned<board::UsartCts>>>,
+        #[cfg(feature = "usart")]
+        usart_err_prod: spsc::Producer<'static, UsartError, 8>,
+        #[cfg(feature = "usart")]
+        usart_err_cons: spsc::Consumer<'static, UsartError, 8>,
+
+        #[cfg(feature = "usart-sync")]
         usart_sync_rx_int:  RxInt<'static, USART3, SyncMode>,
+        #[cfg(feature = "usart-sync")]
         usart_sync_rx_idle: RxIdle<'static>,
+        #[cfg(feature = "usart-sync")]
         usart_sync_tx:      Tx<USART3, SyncMode>,
 
-        green: GpioPin<PIO1_0, Output>,
-        blue:  GpioPin<PIO1_1, Output>,
-        red:   GpioPin<PIO1_2, Input>,
+        green: GpioPin<board::Green, Output>,
+        blue:  GpioPin<board::Blue, Output>,
+
+        red_int: pinint::Interrupt<PININT0, board::Red, Enabled>,
+
+        pin_int_prod: spsc::Producer<'static, (pin::Edge, u32, u32), 8>,
+        pin_int_cons: spsc::Consumer<'static, (pin::Edge, u32, u32), 8>,
+
+        trigger_int: pinint::Interrupt<PININT1, board::Trigger, Enabled>,
 
-        red_int: pinint::Interrupt<PININT0, PIO1_2, Enabled>,
+        /// The trigger line, which can be driven like an open-drain pin
+        shared_line: GpioPin<board::Trigger, Dynamic>,
+
+        /// The level that the output pin is set to on the trigger's next edge
+        #[init(None)]
+        trigger_action: Option<pin::Level>,
 
         systick: SYST,
+
+        /// The PWM signal that is being generated, if any
+        #[init(None)]
+        pwm: Option<pwm::Signal>,
+
+        /// Free-running timer, used to time out partial host requests and to
+        /// timestamp events
+        timer: CTIMER0,
+
+        /// Self-wake-up timer, used to time out `EnterSleep`
+        wkt: WKT,
+
+        /// Channel 0 of the multi-rate timer, which the host can start
+        mrt: mrt::Channel<mrt::MRT0>,
+
+        mrt_prod: spsc::Producer<'static, u32, 8>,
+        mrt_cons: spsc::Consumer<'static, u32, 8>,
+
+        capt: CAPT,
+
+        /// The capacitive touch sensors that are being scanned, if any
+        #[init(None)]
+        capt_scan: Option<capt::Scan>,
+
+        capt_prod: spsc::Producer<'static, CaptEvent, 8>,
+        capt_cons: spsc::Consumer<'static, CaptEvent, 8>,
+
+        #[cfg(feature = "i2c")]
         i2c:     Option<i2c::Master<I2C0, Enabled<PhantomData<IOSC>>, Enabled>>,
+        #[cfg(feature = "i2c")]
         i2c_dma: Option<dma::Channel<dma::Channel15, Enabled>>,
 
+        #[cfg(feature = "spi")]
         spi:        Option<SPI<SPI0, Enabled<spi::Master>>>,
-        ssel:       GpioPin<PIO0_19, Output>,
+        #[cfg(feature = "spi")]
+        ssel:       GpioPin<board::SpiSsel, Output>,
+        #[cfg(feature = "spi")]
         spi_rx_dma: Option<dma::Channel<dma::Channel10, Enabled>>,
+        #[cfg(feature = "spi")]
         spi_tx_dma: Option<dma::Channel<dma::Channel11, Enabled>>,
 
+        #[cfg(feature = "adc")]
+        adc:     ADC,
+        #[cfg(feature = "adc")]
+        adc_pin: swm::Function<swm::ADC_6, Assigned<board::Adc>>,
+
+        #[cfg(feature = "dac")]
+        dac:              DAC1,
+        #[cfg(feature = "dac")]
+        dac_loopback_pin:
+            swm::Function<swm::ADC_1, Assigned<board::DacLoopback>>,
+
+        /// Channel 1 of the multi-rate timer, which times DAC waveforms
+        #[cfg(feature = "dac")]
+        dac_mrt: mrt::Channel<mrt::MRT1>,
+
+        /// The waveform that the DAC outputs, if any
+        #[cfg(feature = "dac")]
+        #[init(None)]
+        dac_waveform: Option<dac::Waveform>,
+
+        #[cfg(feature = "usart-dma")]
         usart_dma_tx_channel: Option<dma::Channel<dma::Channel3, Enabled>>,
-        usart_dma_rx_transfer: Option<
-            dma::Transfer<
-                Started,
-                dma::Channel4,
-                usart::Rx<USART2, usart::state::Enabled<u8, AsyncMode>>,
-                &'static mut [u8],
-            >
-        >,
-
-        dma_rx_prod: spsc::Producer<'static, u8, 32>,
-        dma_rx_cons: spsc::Consumer<'static, u8, 32>,
+        #[cfg(feature = "usart-dma")]
+        usart_dma_rx_int: DmaRxInt<USART2, AsyncMode, dma::Channel4>,
+        #[cfg(feature = "usart-dma")]
+        usart_dma_rx_idle: DmaRxIdle<dma::Channel4>,
     }
 
     #[init]
@@ -163,14 +497,29 @@ const APP: () = {
         // at the beginning of the method, we're opting into some RTFM magic
         // that gives us safe access to them.
         static mut HOST:       Usart = Usart::new();
+        static mut HOST_SENDER: Sender = Sender::new();
+        static mut REASSEMBLY: Reassembly = Reassembly::new();
+        #[cfg(feature = "usart")]
         static mut USART:      Usart = Usart::new();
+        #[cfg(feature = "usart-sync")]
         static mut USART_SYNC: Usart = Usart::new();
 
-        static mut DMA_QUEUE: spsc::Queue<u8, 32> = spsc::Queue::new();
-        static mut DMA_BUFFER: [u8; 13] = [0; 13];
+        #[cfg(feature = "usart")]
+        static mut USART_ERRORS: spsc::Queue<UsartError, 8> =
+            spsc::Queue::new();
+
+        static mut PIN_INTERRUPTS: spsc::Queue<(pin::Edge, u32, u32), 8> =
+            spsc::Queue::new();
+        static mut MRT_EXPIRATIONS: spsc::Queue<u32, 8> = spsc::Queue::new();
+        static mut CAPT_EVENTS: spsc::Queue<CaptEvent, 8> = spsc::Queue::new();
+
+        #[cfg(feature = "usart-dma")]
+        static mut USART_DMA: DmaRx = DmaRx::new();
 
         rtt_target::rtt_init_print!();
         rprintln!("Starting target.");
+        #[cfg(feature = "defmt")]
+        defmt::info!("Starting target.");
 
         // Get access to the device's peripherals. This can't panic, since this
         // is the only place in this program where we call this method.
@@ -185,13 +534,10 @@ const APP: () = {
 
         let mut swm_handle = swm.handle.enable(&mut syscon.handle);
 
-        // Configure GPIO pins
-        let green = p.pins.pio1_0
-            .into_output_pin(gpio.tokens.pio1_0, Level::High);
-        let blue = p.pins.pio1_1
-            .into_output_pin(gpio.tokens.pio1_1, Level::High);
-        let red = p.pins.pio1_2
-            .into_input_pin(gpio.tokens.pio1_2);
+        let pins  = board::Pins::take(p.pins, gpio.tokens);
+        let green = pins.green;
+        let blue  = pins.blue;
+        let red   = pins.red;
 
         // Set up interrupt for input pin
         let mut red_int = pinint
@@ -201,7 +547,26 @@ const APP: () = {
         red_int.enable_rising_edge();
         red_int.enable_falling_edge();
 
-        // Configure the clock for USART0, using the Fractional Rate Generator
+        // Set up interrupt for the trigger line
+        let mut trigger_int = pinint
+            .interrupts
+            .pinint1
+            .select(pins.trigger.inner(), &mut syscon.handle);
+        trigger_int.enable_rising_edge();
+
+        // The trigger line is also shared by the test nodes as an open-drain
+        // line. It's released, until the host says otherwise.
+        let shared_line = pins.trigger
+            .into_dynamic(gpio::Level::Low, DynamicPinDirection::Input);
+
+        // The PWM signal is generated by CTIMER0, which is set up below. The
+        // output only needs to be routed to the pin.
+        swm.movable_functions.t0_mat0.assign(
+            pins.pwm.into_swm_pin(),
+            &mut swm_handle,
+        );
+
+        // Configure the USART clock, using the Fractional Rate Generator
         // (FRG) and the USART's own baud rate divider value (BRG). See user
         // manual, section 17.7.1.
         //
@@ -215,28 +580,32 @@ const APP: () = {
             usart::Clock::new(&syscon.frg0, 5, 16)
         };
 
-        // Assign pins to USART0 for RX/TX functions. On the LPC845-BRK, those
-        // are the pins connected to the programmer, and bridged to the host via
-        // USB.
-        //
-        // Careful, the LCP845-BRK documentation uses the opposite designations
-        // (i.e. from the perspective of the on-board programmer, not the
-        // microcontroller).
-        let (u0_rxd, _) = swm.movable_functions.u0_rxd.assign(
-            p.pins.pio0_24.into_swm_pin(),
+        // Assign pins to the host USART for RX/TX functions. Which USART and
+        // pins those are depends on the board, but they need to be connected to
+        // the host.
+        let (host_usart, host_rxd, host_txd) = board::take_host_usart(
+            p.USART0,
+            swm.movable_functions.u0_rxd,
+            swm.movable_functions.u0_txd,
+            p.USART4,
+            swm.movable_functions.u4_rxd,
+            swm.movable_functions.u4_txd,
+        );
+        let (host_rxd, _) = host_rxd.assign(
+            pins.host_rx.into_swm_pin(),
             &mut swm_handle,
         );
-        let (u0_txd, _) = swm.movable_functions.u0_txd.assign(
-            p.pins.pio0_25.into_swm_pin(),
+        let (host_txd, _) = host_txd.assign(
+            pins.host_tx.into_swm_pin(),
             &mut swm_handle,
         );
 
-        // Use USART0 to communicate with the test suite
-        let mut host = p.USART0.enable_async(
+        // Use the host USART to communicate with the test suite
+        let mut host = host_usart.enable_async(
             &clock_config,
             &mut syscon.handle,
-            u0_rxd,
-            u0_txd,
+            host_rxd,
+            host_txd,
             usart::Settings::default(),
         );
         host.enable_interrupts(usart::Interrupts {
@@ -244,180 +613,321 @@ const APP: () = {
             .. usart::Interrupts::default()
         });
 
-        // Assign pins to USART1.
-        let (u1_rxd, _) = swm.movable_functions.u1_rxd.assign(
-            p.pins.pio0_26.into_swm_pin(),
-            &mut swm_handle,
-        );
-        let (u1_txd, _) = swm.movable_functions.u1_txd.assign(
-            p.pins.pio0_27.into_swm_pin(),
-            &mut swm_handle,
-        );
-        let (u1_cts, _) = swm.movable_functions.u1_cts.assign(
-            p.pins.pio0_8.into_swm_pin(),
-            &mut swm_handle,
-        );
-
         // Use USART1 as the test subject.
-        let mut usart = p.USART1.enable_async(
-            &clock_config,
-            &mut syscon.handle,
-            u1_rxd,
-            u1_txd,
-            usart::Settings::default(),
-        );
-        usart.enable_interrupts(usart::Interrupts {
-            RXRDY: true,
-            .. usart::Interrupts::default()
-        });
+        #[cfg(feature = "usart")]
+        let (usart_rx_int, usart_rx_idle, usart_tx, u1_cts) = {
+            let (u1_rxd, _) = swm.movable_functions.u1_rxd.assign(
+                pins.usart_rx.into_swm_pin(),
+                &mut swm_handle,
+            );
+            let (u1_txd, _) = swm.movable_functions.u1_txd.assign(
+                pins.usart_tx.into_swm_pin(),
+                &mut swm_handle,
+            );
+            let (u1_cts, _) = swm.movable_functions.u1_cts.assign(
+                pins.usart_cts.into_swm_pin(),
+                &mut swm_handle,
+            );
 
-        // Assign pins to USART3.
-        let (u3_rxd, _) = swm.movable_functions.u3_rxd.assign(
-            p.pins.pio0_13.into_swm_pin(),
-            &mut swm_handle,
-        );
-        let (u3_txd, _) = swm.movable_functions.u3_txd.assign(
-            p.pins.pio0_14.into_swm_pin(),
-            &mut swm_handle,
-        );
-        let (u3_sclk, _) = swm.movable_functions.u3_sclk.assign(
-            p.pins.pio0_15.into_swm_pin(),
-            &mut swm_handle,
-        );
+            let mut usart = p.USART1.enable_async(
+                &clock_config,
+                &mut syscon.handle,
+                u1_rxd,
+                u1_txd,
+                usart::Settings::default(),
+            );
+            usart.enable_interrupts(usart::Interrupts {
+                RXRDY:      true,
+                DELTARXBRK: true,
+                .. usart::Interrupts::default()
+            });
+
+            let (rx_int, rx_idle, tx) = USART.init(usart);
+            (rx_int, rx_idle, tx, u1_cts)
+        };
 
         // Use USART3 as secondary test subject for sync mode.
-        let mut usart_sync = p.USART3.enable_sync_as_master(
-            &usart::Clock::new(&syscon.iosc, 0x03ff, 16),
-            &mut syscon.handle,
-            u3_rxd,
-            u3_txd,
-            u3_sclk,
-            usart::Settings::default(),
-        );
-        usart_sync.enable_interrupts(usart::Interrupts {
-            RXRDY: true,
-            .. usart::Interrupts::default()
-        });
+        #[cfg(feature = "usart-sync")]
+        let (usart_sync_rx_int, usart_sync_rx_idle, usart_sync_tx) = {
+            let (u3_rxd, _) = swm.movable_functions.u3_rxd.assign(
+                pins.usart_sync_rx.into_swm_pin(),
+                &mut swm_handle,
+            );
+            let (u3_txd, _) = swm.movable_functions.u3_txd.assign(
+                pins.usart_sync_tx.into_swm_pin(),
+                &mut swm_handle,
+            );
+            let (u3_sclk, _) = swm.movable_functions.u3_sclk.assign(
+                pins.usart_sync_sclk.into_swm_pin(),
+                &mut swm_handle,
+            );
 
-        // Assign pins to USART2
-        let (u2_rxd, _) = swm.movable_functions.u2_rxd.assign(
-            p.pins.pio0_28.into_swm_pin(),
-            &mut swm_handle,
-        );
-        let (u2_txd, _) = swm.movable_functions.u2_txd.assign(
-            p.pins.pio0_29.into_swm_pin(),
-            &mut swm_handle,
-        );
+            let mut usart_sync = p.USART3.enable_sync_as_master(
+                &usart::Clock::new(&syscon.iosc, 0x03ff, 16),
+                &mut syscon.handle,
+                u3_rxd,
+                u3_txd,
+                u3_sclk,
+                usart::Settings::default(),
+            );
+            usart_sync.enable_interrupts(usart::Interrupts {
+                RXRDY: true,
+                .. usart::Interrupts::default()
+            });
 
-        // Use USART2 as tertiary test subject, for receiving via DMA.
-        let usart2 = p.USART2.enable_async(
-            &clock_config,
-            &mut syscon.handle,
-            u2_rxd,
-            u2_txd,
-            usart::Settings::default(),
-        );
+            USART_SYNC.init(usart_sync)
+        };
 
-        let (host_rx_int,  host_rx_idle,  host_tx)  = HOST.init(host);
-        let (usart_rx_int, usart_rx_idle, usart_tx) = USART.init(usart);
-        let (usart_sync_rx_int, usart_sync_rx_idle, usart_sync_tx) =
-            USART_SYNC.init(usart_sync);
-
-        let (i2c0_sda, _) = swm
-            .fixed_functions
-            .i2c0_sda
-            .assign(p.pins.pio0_11.into_swm_pin(), &mut swm_handle);
-        let (i2c0_scl, _) = swm
-            .fixed_functions
-            .i2c0_scl
-            .assign(p.pins.pio0_10.into_swm_pin(), &mut swm_handle);
-
-        let i2c = p.I2C0
-            .enable(
-                &syscon.iosc,
-                i2c0_scl,
-                i2c0_sda,
+        // Sending to the host is interrupt-driven, so the idle loop doesn't
+        // stall, while large replies go out.
+        let host_sender: *const Sender = HOST_SENDER;
+        let (host_tx_int, host_queued) =
+            HOST_SENDER.split(host.tx, lpc8xx::Interrupt::<HostUsart>::new());
+        let (host_rx_int, mut host_rx_idle, host_tx) =
+            HOST.split(host.rx, host_queued);
+        panic::init::<HostUsart>();
+        // Sound, as `HOST_SENDER` is a `static`.
+        unsafe { panic::set_sender(host_sender) };
+
+        // CTIMER0 runs from the 12 MHz system clock, and is divided down to
+        // count microseconds. If the host stops in the middle of a request, the
+        // partial request is discarded after a while, so it can't corrupt the
+        // next one.
+        let prescaler = 12_000_000 / TIMER_HZ - 1;
+        let timer     = p.CTIMER0
+            .enable(u32::MAX, prescaler, &mut syscon.handle)
+            .free();
+        capture_edges(&timer, RED);
+        host_rx_idle.timeout    = Some(HOST_RX_TIMEOUT);
+
+        // The self-wake-up timer times out `EnterSleep`. It runs from the
+        // low-power clock, which keeps running in deep-sleep mode.
+        let mut pmu = p.PMU.split();
+        pmu.low_power_clock.enable(&mut pmu.handle);
+        let mut wkt = p.WKT.enable(&mut syscon.handle);
+        wkt.select_clock::<LowPowerClock>();
+        let wkt = wkt.free();
+        host_rx_idle.reassembly = Some(REASSEMBLY);
+
+        // The multi-rate timer runs from the 12 MHz system clock. The HAL only
+        // starts it in repeat mode, without interrupts, so `start_mrt` and
+        // `dac::Waveform` take care of those.
+        let mrt_channels = p.MRT0.split(&mut syscon.handle);
+        let mrt          = mrt_channels.mrt0;
+
+        // The HAL doesn't support the capacitive touch peripheral, so it is
+        // only enabled here. `capt::Scan` takes care of the rest.
+        capt::init(board::CAPT_X_PINS);
+
+        // The HAL doesn't touch the IOCON peripheral, whose settings the host
+        // can change using `pin_config`.
+        pin_config::init();
+
+        #[cfg(feature = "i2c")]
+        let i2c = {
+            let (i2c0_sda, _) = swm
+                .fixed_functions
+                .i2c0_sda
+                .assign(pins.i2c_sda.into_swm_pin(), &mut swm_handle);
+            let (i2c0_scl, _) = swm
+                .fixed_functions
+                .i2c0_scl
+                .assign(pins.i2c_scl.into_swm_pin(), &mut swm_handle);
+
+            p.I2C0
+                .enable(
+                    &syscon.iosc,
+                    i2c0_scl,
+                    i2c0_sda,
+                    &mut syscon.handle,
+                )
+                .enable_master_mode(
+                    &i2c::Clock::new_400khz(),
+                )
+        };
+
+        #[cfg(feature = "spi")]
+        let (spi, ssel) = {
+            let (spi0_sck, _) = swm
+                .movable_functions
+                .spi0_sck
+                .assign(pins.spi_sck.into_swm_pin(), &mut swm_handle);
+            let (spi0_mosi, _) = swm
+                .movable_functions
+                .spi0_mosi
+                .assign(pins.spi_mosi.into_swm_pin(), &mut swm_handle);
+            let (spi0_miso, _) = swm
+                .movable_functions
+                .spi0_miso
+                .assign(pins.spi_miso.into_swm_pin(), &mut swm_handle);
+            let ssel = pins.spi_ssel;
+
+            let spi = p.SPI0.enable_as_master(
+                &spi::Clock::new(&syscon.iosc, 0x0fff),
                 &mut syscon.handle,
-            )
-            .enable_master_mode(
-                &i2c::Clock::new_400khz(),
+                spi::MODE_0,
+                spi0_sck,
+                spi0_mosi,
+                spi0_miso,
             );
 
-        let (spi0_sck, _) = swm
-            .movable_functions
-            .spi0_sck
-            .assign(p.pins.pio0_16.into_swm_pin(), &mut swm_handle);
-        let (spi0_mosi, _) = swm
-            .movable_functions
-            .spi0_mosi
-            .assign(p.pins.pio0_17.into_swm_pin(), &mut swm_handle);
-        let (spi0_miso, _) = swm
-            .movable_functions
-            .spi0_miso
-            .assign(p.pins.pio0_18.into_swm_pin(), &mut swm_handle);
-        let ssel = p.pins.pio0_19.into_output_pin(
-            gpio.tokens.pio0_19,
-            Level::High,
-        );
+            (spi, ssel)
+        };
 
-        let spi = p.SPI0.enable_as_master(
-            &spi::Clock::new(&syscon.iosc, 0x0fff),
-            &mut syscon.handle,
-            spi::MODE_0,
-            spi0_sck,
-            spi0_mosi,
-            spi0_miso,
-        );
+        #[cfg(feature = "adc")]
+        let (adc, adc_pin) = {
+            let (adc_pin, _) = swm
+                .fixed_functions
+                .adc_6
+                .assign(pins.adc.into_swm_pin(), &mut swm_handle);
+
+            let adc = p.ADC.enable(
+                &AdcClock::new_default(),
+                &mut syscon.handle,
+            );
+
+            (adc, adc_pin)
+        };
+
+        // The HAL doesn't support the DAC, so it is only powered up here.
+        // `dac::set` and `dac::Waveform` take care of the rest.
+        #[cfg(feature = "dac")]
+        let dac_loopback_pin = {
+            swm
+                .fixed_functions
+                .dacout1
+                .assign(pins.dac.into_swm_pin(), &mut swm_handle);
+            let (dac_loopback_pin, _) = swm
+                .fixed_functions
+                .adc_1
+                .assign(pins.dac_loopback.into_swm_pin(), &mut swm_handle);
+
+            dac::init();
+
+            dac_loopback_pin
+        };
 
+        #[cfg(any(feature = "usart-dma", feature = "i2c", feature = "spi"))]
         let dma = p.DMA.enable(&mut syscon.handle);
 
-        let mut dma_rx_channel = dma.channels.channel4;
-        dma_rx_channel.enable_interrupts();
-        let mut usart_dma_rx_transfer = usart2.rx
-            .read_all(&mut DMA_BUFFER[..], dma_rx_channel);
-        usart_dma_rx_transfer.set_a_when_complete();
-        let usart_dma_rx_transfer =  usart_dma_rx_transfer.start();
+        // Use USART2 as tertiary test subject, for receiving via DMA.
+        #[cfg(feature = "usart-dma")]
+        let (usart_dma_rx_int, usart_dma_rx_idle) = {
+            let (u2_rxd, _) = swm.movable_functions.u2_rxd.assign(
+                pins.usart_dma_rx.into_swm_pin(),
+                &mut swm_handle,
+            );
+            let (u2_txd, _) = swm.movable_functions.u2_txd.assign(
+                pins.usart_dma_tx.into_swm_pin(),
+                &mut swm_handle,
+            );
+
+            let usart2 = p.USART2.enable_async(
+                &clock_config,
+                &mut syscon.handle,
+                u2_rxd,
+                u2_txd,
+                usart::Settings::default(),
+            );
+
+            USART_DMA.init(usart2.rx, dma.channels.channel4)
+        };
+
+        #[cfg(feature = "usart")]
+        let (usart_err_prod, usart_err_cons) = USART_ERRORS.split();
 
-        let (dma_rx_prod, dma_rx_cons) = DMA_QUEUE.split();
+        let (pin_int_prod, pin_int_cons) = PIN_INTERRUPTS.split();
+        let (mrt_prod, mrt_cons) = MRT_EXPIRATIONS.split();
+        let (capt_prod, capt_cons) = CAPT_EVENTS.split();
 
         init::LateResources {
             swm: Some(swm_handle),
 
             host_rx_int,
             host_rx_idle,
+            host_tx_int,
             host_tx,
 
+            #[cfg(feature = "usart")]
             usart_rx_int,
+            #[cfg(feature = "usart")]
             usart_rx_idle,
+            #[cfg(feature = "usart")]
             usart_tx:  Some(usart_tx),
+            #[cfg(feature = "usart")]
             usart_rts: Some(swm.movable_functions.u1_rts),
-            usart_rts_pin: Some(p.pins.pio0_9.into_swm_pin()),
+            #[cfg(feature = "usart")]
+            usart_rts_pin: Some(pins.usart_rts.into_swm_pin()),
+            #[cfg(feature = "usart")]
             usart_cts: Some(u1_cts),
+            #[cfg(feature = "usart")]
+            usart_err_prod,
+            #[cfg(feature = "usart")]
+            usart_err_cons,
 
+            #[cfg(feature = "usart-sync")]
             usart_sync_rx_int,
+            #[cfg(feature = "usart-sync")]
             usart_sync_rx_idle,
+            #[cfg(feature = "usart-sync")]
             usart_sync_tx,
 
             green,
             blue,
-            red,
 
             red_int,
 
+            pin_int_prod,
+            pin_int_cons,
+
+            trigger_int,
+            shared_line,
+
             systick,
+
+            timer,
+            wkt,
+
+            mrt,
+            mrt_prod,
+            mrt_cons,
+
+            capt: p.CAPT,
+            capt_prod,
+            capt_cons,
+
+            #[cfg(feature = "i2c")]
             i2c:     Some(i2c.master),
+            #[cfg(feature = "i2c")]
             i2c_dma: Some(dma.channels.channel15),
 
+            #[cfg(feature = "spi")]
             spi: Some(spi),
+            #[cfg(feature = "spi")]
             ssel,
+            #[cfg(feature = "spi")]
             spi_rx_dma: Some(dma.channels.channel10),
+            #[cfg(feature = "spi")]
             spi_tx_dma: Some(dma.channels.channel11),
 
-            usart_dma_tx_channel:  Some(dma.channels.channel3),
-            usart_dma_rx_transfer: Some(usart_dma_rx_transfer),
+            #[cfg(feature = "adc")]
+            adc,
+            #[cfg(feature = "adc")]
+            adc_pin,
+
+            #[cfg(feature = "dac")]
+            dac: p.DAC1,
+            #[cfg(feature = "dac")]
+            dac_loopback_pin,
+            #[cfg(feature = "dac")]
+            dac_mrt: mrt_channels.mrt1,
 
-            dma_rx_prod,
-            dma_rx_cons,
+            #[cfg(feature = "usart-dma")]
+            usart_dma_tx_channel:  Some(dma.channels.channel3),
+            #[cfg(feature = "usart-dma")]
+            usart_dma_rx_int,
+            #[cfg(feature = "usart-dma")]
+            usart_dma_rx_idle,
         }
     }
 
@@ -426,83 +936,344 @@ const APP: () = {
         host_rx_idle, host_tx,
         usart_rx_int, usart_rx_idle, usart_tx,
         usart_rts, usart_rts_pin, usart_cts,
+        usart_err_cons,
         usart_sync_rx_idle, usart_sync_tx,
-        green,
-        red,
+        pin_int_cons,
+        trigger_action,
+        shared_line,
         systick,
+        pwm,
+        timer,
+        wkt,
+        mrt,
+        mrt_cons,
+        capt,
+        capt_scan,
+        capt_cons,
         i2c,
         i2c_dma,
         spi,
         ssel,
         spi_rx_dma,
         spi_tx_dma,
+        adc,
+        adc_pin,
+        dac,
+        dac_loopback_pin,
+        dac_mrt,
+        dac_waveform,
         usart_dma_tx_channel,
-        dma_rx_cons,
+        usart_dma_rx_idle,
     ])]
     fn idle(cx: idle::Context) -> ! {
+        let host_rx        = cx.resources.host_rx_idle;
+        let host_tx        = cx.resources.host_tx;
+        let pin_interrupts = cx.resources.pin_int_cons;
+        let mut trigger    = cx.resources.trigger_action;
+        let shared_line    = cx.resources.shared_line;
+        let systick        = cx.resources.systick;
+        let mut pwm        = cx.resources.pwm;
+        let timer          = cx.resources.timer;
+        let wkt            = cx.resources.wkt;
+        let mrt            = cx.resources.mrt;
+        let mrt_expired    = cx.resources.mrt_cons;
+        let capt           = cx.resources.capt;
+        let mut capt_scan  = cx.resources.capt_scan;
+        let capt_events    = cx.resources.capt_cons;
+
+        #[cfg(feature = "usart")]
         let swm            = cx.resources.swm;
+        #[cfg(feature = "usart")]
         let usart_rx       = cx.resources.usart_rx_idle;
+        #[cfg(feature = "usart")]
         let usart_tx       = cx.resources.usart_tx;
+        #[cfg(feature = "usart")]
         let usart_rts      = cx.resources.usart_rts;
+        #[cfg(feature = "usart")]
         let usart_rts_pin  = cx.resources.usart_rts_pin;
+        #[cfg(feature = "usart")]
         let usart_cts      = cx.resources.usart_cts;
+        #[cfg(feature = "usart")]
+        let usart_errors   = cx.resources.usart_err_cons;
+        #[cfg(feature = "usart-sync")]
         let usart_sync_rx  = cx.resources.usart_sync_rx_idle;
+        #[cfg(feature = "usart-sync")]
         let usart_sync_tx  = cx.resources.usart_sync_tx;
-        let host_rx        = cx.resources.host_rx_idle;
-        let host_tx        = cx.resources.host_tx;
-        let green          = cx.resources.green;
-        let red            = cx.resources.red;
-        let systick        = cx.resources.systick;
+        #[cfg(feature = "i2c")]
         let i2c            = cx.resources.i2c;
+        #[cfg(feature = "i2c")]
         let i2c_dma        = cx.resources.i2c_dma;
+        #[cfg(feature = "spi")]
         let spi            = cx.resources.spi;
+        #[cfg(feature = "spi")]
         let ssel           = cx.resources.ssel;
+        #[cfg(feature = "spi")]
         let spi_rx_dma     = cx.resources.spi_rx_dma;
+        #[cfg(feature = "spi")]
         let spi_tx_dma     = cx.resources.spi_tx_dma;
+        #[cfg(feature = "adc")]
+        let adc            = cx.resources.adc;
+        #[cfg(feature = "adc")]
+        let adc_pin        = cx.resources.adc_pin;
+        #[cfg(feature = "dac")]
+        let dac            = cx.resources.dac;
+        #[cfg(feature = "dac")]
+        let dac_loop_pin   = cx.resources.dac_loopback_pin;
+        #[cfg(feature = "dac")]
+        let dac_mrt        = cx.resources.dac_mrt;
+        #[cfg(feature = "dac")]
+        let mut dac_wave   = cx.resources.dac_waveform;
+        #[cfg(feature = "usart-dma")]
         let usart_dma_chan = cx.resources.usart_dma_tx_channel;
-        let usart_dma_cons = cx.resources.dma_rx_cons;
+        #[cfg(feature = "usart-dma")]
+        let usart_dma_rx   = cx.resources.usart_dma_rx_idle;
 
+        #[cfg(feature = "usart")]
         let mut usart_rx_int = cx.resources.usart_rx_int;
 
         let mut buf = [0; 256];
 
+        // Pin interrupts are only reported, if the host asks for them.
+        let mut report_pin_interrupts = false;
+
+        // When echoing started, for how long, and what has been echoed so far
+        #[cfg(feature = "usart")]
+        let mut usart_echo: Option<(u32, u32, UsartEcho)> = None;
+
+        // Let the host know that we're ready, and why we were reset, if that
+        // was caused by a fault.
+        host_tx.send_sync()
+            .void_unwrap();
+        let (reset_cause, fault) = fault::take_last();
+        if let Some(fault) = fault {
+            host_tx
+                .send_message(&TargetToHost::Fault(fault), &mut buf)
+                .unwrap();
+        }
+
+        let mut last_heartbeat = timer.tc.read().tcval().bits();
+
         loop {
+            // While echoing, received data goes right back to the assistant,
+            // instead of to the host. This needs to happen before the data is
+            // forwarded below, which takes it out of the queue.
+            #[cfg(feature = "usart")]
+            if let Some((started, duration, echo)) = &mut usart_echo {
+                let tx = usart_tx.as_mut().unwrap();
+                usart_rx
+                    .process_raw(|data| {
+                        echo.bytes += data.len() as u32;
+                        tx.send_raw(data)
+                    })
+                    .expect("Error echoing USART data");
+                while usart_errors.dequeue().is_some() {
+                    echo.errors += 1;
+                }
+                echo.dropped += usart_rx.take_dropped();
+
+                let now = timer.tc.read().tcval().bits();
+                if now.wrapping_sub(*started) >= *duration {
+                    host_tx
+                        .send_message(
+                            &TargetToHost::UsartEchoed(*echo),
+                            &mut buf,
+                        )
+                        .unwrap();
+                    usart_echo = None;
+                }
+            }
+
+            #[cfg(feature = "usart")]
             usart_rx
                 .process_raw(|data| {
                     host_tx.send_message(
                         &TargetToHost::UsartReceive {
-                            mode: UsartMode::Regular,
+                            mode:      UsartMode::Regular,
                             data,
+                            timestamp: Some(irq_entered(Interrupt::USART1)),
                         },
                         &mut buf,
                     )
                 })
                 .expect("Error processing USART data");
+            #[cfg(feature = "usart")]
+            while let Some(error) = usart_errors.dequeue() {
+                host_tx
+                    .send_message(
+                        &TargetToHost::UsartError {
+                            mode: UsartMode::Regular,
+                            error,
+                        },
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+            // No atomic swap on this MCU. The handler could set the flag again,
+            // in between loading and storing it.
+            #[cfg(feature = "usart")]
+            let usart_break = interrupt::free(|_| {
+                let detected = USART_BREAK.load(Ordering::Relaxed);
+                USART_BREAK.store(false, Ordering::Relaxed);
+                detected
+            });
+            #[cfg(feature = "usart")]
+            if usart_break {
+                host_tx
+                    .send_message(&TargetToHost::UsartBreakDetected, &mut buf)
+                    .unwrap();
+            }
+            #[cfg(feature = "usart-sync")]
             usart_sync_rx
                 .process_raw(|data| {
                     host_tx.send_message(
                         &TargetToHost::UsartReceive {
-                            mode: UsartMode::Sync,
+                            mode:      UsartMode::Sync,
                             data,
+                            timestamp: Some(
+                                irq_entered(Interrupt::PIN_INT6_USART3)
+                            ),
                         },
                         &mut buf,
                     )
                 })
                 .expect("Error processing USART data (sync)");
 
-            while let Some(b) = usart_dma_cons.dequeue() {
+            // The DMA controller doesn't interrupt per byte, so there's
+            // nothing to timestamp received data with.
+            #[cfg(feature = "usart-dma")]
+            usart_dma_rx
+                .process_raw(|data| {
+                    host_tx.send_message(
+                        &TargetToHost::UsartReceive {
+                            mode:      UsartMode::Dma,
+                            data,
+                            timestamp: None,
+                        },
+                        &mut buf,
+                    )
+                })
+                .expect("Error processing USART data (DMA)");
+
+            while let Some((edge, timestamp, captured)) =
+                pin_interrupts.dequeue()
+            {
+                if !report_pin_interrupts {
+                    continue;
+                }
+
                 host_tx
                     .send_message(
-                        &TargetToHost::UsartReceive {
-                            mode: UsartMode::Dma,
-                            data: &[b],
+                        &TargetToHost::PinInterrupt {
+                            pin: RED,
+                            edge,
+                            timestamp,
+                            captured,
                         },
                         &mut buf,
                     )
                     .unwrap();
             }
 
-            host_rx
+            while let Some(timestamp) = mrt_expired.dequeue() {
+                host_tx
+                    .send_message(
+                        &TargetToHost::MrtExpired { timestamp },
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+
+            while let Some(event) = capt_events.dequeue() {
+                host_tx
+                    .send_message(&TargetToHost::CaptEvent(event), &mut buf)
+                    .unwrap();
+            }
+
+            // The queues have been drained. Report anything the interrupt
+            // handlers had to drop in the meantime.
+            #[cfg_attr(
+                not(any(
+                    feature = "usart",
+                    feature = "usart-sync",
+                    feature = "usart-dma",
+                )),
+                allow(unused_mut)
+            )]
+            let mut dropped = OVERRUN.take()
+                .saturating_add(host_rx.take_dropped());
+            #[cfg(feature = "usart")]
+            {
+                dropped = dropped.saturating_add(usart_rx.take_dropped());
+            }
+            #[cfg(feature = "usart-sync")]
+            {
+                dropped = dropped.saturating_add(usart_sync_rx.take_dropped());
+            }
+            #[cfg(feature = "usart-dma")]
+            {
+                dropped = dropped.saturating_add(usart_dma_rx.take_dropped());
+            }
+            if dropped > 0 {
+                host_tx
+                    .send_message(
+                        &TargetToHost::BufferOverrun { dropped },
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+
+            // Send anything that has been logged using defmt. Dropped log
+            // frames don't affect the test case, so they're only logged.
+            #[cfg(feature = "defmt")]
+            {
+                let mut frames = [0; 64];
+                loop {
+                    let data = firmware_lib::defmt::take(&mut frames);
+                    if data.is_empty() {
+                        break;
+                    }
+                    host_tx
+                        .send_message(&TargetToHost::Defmt(data), &mut buf)
+                        .unwrap();
+                }
+
+                let dropped = firmware_lib::defmt::take_dropped();
+                if dropped > 0 {
+                    firmware_lib::log!(
+                        host_tx,
+                        &mut buf,
+                        TargetToHost::Log,
+                        log::Level::Warn,
+                        "Dropped {} defmt frames.",
+                        dropped,
+                    )
+                    .unwrap();
+                }
+            }
+
+            let now = timer.tc.read().tcval().bits();
+            if now.wrapping_sub(last_heartbeat) >= HEARTBEAT_INTERVAL {
+                host_tx
+                    .send_message(&TargetToHost::Heartbeat, &mut buf)
+                    .unwrap();
+                last_heartbeat = now;
+            }
+            if host_rx.discard_stale_frame(now) {
+                firmware_lib::log!(
+                    host_tx,
+                    &mut buf,
+                    TargetToHost::Log,
+                    log::Level::Warn,
+                    "Discarding partial host request.",
+                )
+                .unwrap();
+            }
+
+            // The receiver is borrowed while the message is processed, so a
+            // negotiated checksum can only be applied to it afterwards.
+            let mut negotiated_checksum = None;
+
+            let result = host_rx
                 .process_message(|message| {
                     // We're working around two problems here:
                     // 1. We only have a mutable reference to resources we need
@@ -515,26 +1286,39 @@ const APP: () = {
                     //    problem. The closure prevents that understanding, thus
                     //    necessitating this little dance with the local
                     //    variables.
+                    #[cfg(feature = "usart")]
                     let mut swm_local = swm.take().unwrap();
+                    #[cfg(feature = "usart")]
                     let mut usart_tx_local = usart_tx.take().unwrap();
+                    #[cfg(feature = "usart")]
                     let mut usart_rts_local = usart_rts.take().unwrap();
+                    #[cfg(feature = "usart")]
                     let mut usart_rts_pin_local = usart_rts_pin.take().unwrap();
+                    #[cfg(feature = "usart")]
                     let mut usart_cts_local = usart_cts.take().unwrap();
+                    #[cfg(feature = "usart-dma")]
                     let mut usart_dma_chan_local =
                         usart_dma_chan.take().unwrap();
+                    #[cfg(feature = "i2c")]
                     let mut i2c_local = i2c.take().unwrap();
+                    #[cfg(feature = "i2c")]
                     let mut i2c_dma_local = i2c_dma.take().unwrap();
+                    #[cfg(feature = "spi")]
                     let mut spi_local = spi.take().unwrap();
+                    #[cfg(feature = "spi")]
                     let mut spi_rx_dma_local = spi_rx_dma.take().unwrap();
+                    #[cfg(feature = "spi")]
                     let mut spi_tx_dma_local = spi_tx_dma.take().unwrap();
 
-                    let result = match message {
+                    let result: Result<(), Void> = match message {
+                        #[cfg(feature = "usart")]
                         HostToTarget::SendUsart {
                             mode: UsartMode::Regular,
                             data,
                         } => {
                             usart_tx_local.send_raw(data)
                         }
+                        #[cfg(feature = "usart-dma")]
                         HostToTarget::SendUsart {
                             mode: UsartMode::Dma,
                             data,
@@ -576,6 +1360,7 @@ const APP: () = {
 
                             Ok(())
                         }
+                        #[cfg(feature = "usart")]
                         HostToTarget::SendUsart {
                             mode: UsartMode::FlowControl,
                             data,
@@ -612,61 +1397,99 @@ const APP: () = {
 
                             Ok(())
                         }
+                        #[cfg(feature = "usart-sync")]
                         HostToTarget::SendUsart {
                             mode: UsartMode::Sync,
                             data,
                         } => {
                             usart_sync_tx.send_raw(data)
                         }
+                        #[cfg(feature = "usart")]
                         HostToTarget::WaitForAddress(address) => {
-                            usart_rx_int.lock(|rx| {
+                            let (address, starts) = usart_rx_int.lock(|rx| {
+                                // The receiver ignores the words before the
+                                // address, but still detects their start bits.
+                                // Reading the flag clears it, so earlier words
+                                // aren't counted.
+                                rx.usart.is_flag_set(usart::Flag::START);
                                 rx.usart.start_address_detection(address);
-                                block!(rx.usart.read())
-                                    .unwrap();
+
+                                let mut starts: u32 = 0;
+                                let address = loop {
+                                    if rx.usart.is_flag_set(usart::Flag::START)
+                                    {
+                                        starts += 1;
+                                    }
+                                    match rx.usart.read() {
+                                        Err(nb::Error::WouldBlock) => {}
+                                        result => break result.unwrap(),
+                                    }
+                                };
+
                                 rx.usart.stop_address_detection();
+                                (address, starts)
                             });
+
+                            // The address has a start bit too.
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::AddressMatched {
+                                        address,
+                                        discarded: starts.saturating_sub(1),
+                                    },
+                                    &mut buf,
+                                )
+                                .unwrap();
+
                             Ok(())
                         }
-                        HostToTarget::SetPin(
-                            pin::SetLevel { level: pin::Level::High, .. }
-                        ) => {
-                            Ok(green.set_high())
-                        }
-                        HostToTarget::SetPin(
-                            pin::SetLevel { level: pin::Level::Low, .. }
-                        ) => {
-                            Ok(green.set_low())
-                        }
-                        HostToTarget::ReadPin(pin::ReadLevel { pin: () }) => {
-                            let level = match red.is_high() {
-                                true  => pin::Level::High,
-                                false => pin::Level::Low,
-                            };
+                        HostToTarget::SetPin(pin::SetLevel { pin, level }) => {
+                            // Setting a pin has no reply, unless it fails.
+                            if let Err(pin_level::UnsupportedPin) =
+                                pin_level::set(pin, level)
+                            {
+                                host_tx
+                                    .send_message(
+                                        &TargetToHost::UnsupportedPin(pin),
+                                        &mut buf,
+                                    )
+                                    .unwrap();
+                            }
 
-                            let result = pin::ReadLevelResult {
-                                pin: (),
-                                level,
-                                period_ms: None,
+                            Ok(())
+                        }
+                        HostToTarget::ReadPin(pin::ReadLevel { pin }) => {
+                            let message = match pin_level::read(pin) {
+                                Ok(level) => {
+                                    let result = pin::ReadLevelResult {
+                                        pin,
+                                        level,
+                                        period: None,
+                                    };
+                                    TargetToHost::ReadPinResult(Some(result))
+                                }
+                                Err(pin_level::UnsupportedPin) => {
+                                    TargetToHost::UnsupportedPin(pin)
+                                }
                             };
 
                             host_tx
-                                .send_message(
-                                    &TargetToHost::ReadPinResult(Some(result)),
-                                    &mut buf,
-                                )
+                                .send_message(&message, &mut buf)
                                 .unwrap();
 
                             Ok(())
                         }
-                        HostToTarget::StartTimerInterrupt { period_ms } => {
+                        HostToTarget::StartTimerInterrupt {
+                            period: Microseconds(period),
+                        } => {
                             // By default (and we haven't changed that setting)
                             // the SysTick timer runs at half the system
                             // frequency. The system frequency runs at 12 MHz by
                             // default (again, we haven't changed it), meaning
                             // the SysTick timer runs at 6 MHz.
                             //
-                            // At 6 MHz, 1 ms are 6000 timer ticks.
-                            let reload = period_ms * 6000;
+                            // At 6 MHz, 1 us are 6 timer ticks.
+                            let reload = period * 6;
                             systick.set_reload(reload);
 
                             systick.clear_current();
@@ -681,187 +1504,1125 @@ const APP: () = {
 
                             Ok(())
                         }
+                        HostToTarget::StartMrt {
+                            mode,
+                            interval: Microseconds(interval),
+                        } => {
+                            // Starting the timer has no reply, unless it
+                            // fails.
+                            if let Err(InvalidRequest) =
+                                start_mrt(mrt, mode, interval)
+                            {
+                                host_tx
+                                    .send_message(
+                                        &TargetToHost::InvalidRequest,
+                                        &mut buf,
+                                    )
+                                    .unwrap();
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::StopMrt => {
+                            stop_mrt(mrt);
+                            Ok(())
+                        }
+                        HostToTarget::StartCapt { pins, threshold } => {
+                            // Only the X pins that the board connects to a
+                            // sensor can be selected.
+                            let result = if pins & !board::CAPT_X_PINS == 0 {
+                                capt_scan.lock(|scan| {
+                                    capt::Scan::start(capt, pins, threshold)
+                                        .map(|started| *scan = Some(started))
+                                })
+                            }
+                            else {
+                                Err(capt::InvalidScan)
+                            };
+
+                            if let Err(capt::InvalidScan) = result {
+                                host_tx
+                                    .send_message(
+                                        &TargetToHost::InvalidRequest,
+                                        &mut buf,
+                                    )
+                                    .unwrap();
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::StopCapt => {
+                            capt_scan.lock(|scan| {
+                                capt::stop(capt);
+                                *scan = None;
+                            });
+
+                            Ok(())
+                        }
+                        HostToTarget::StartPwmSignal {
+                            period: Microseconds(period),
+                            duty_permille,
+                        } => {
+                            // The timer counts microseconds, so the period
+                            // doesn't need to be converted.
+                            let result = pwm.lock(|pwm| {
+                                pwm::Signal::start(timer, period, duty_permille)
+                                    .map(|signal| *pwm = Some(signal))
+                            });
+
+                            if let Err(pwm::InvalidSignal) = result {
+                                host_tx
+                                    .send_message(
+                                        &TargetToHost::InvalidRequest,
+                                        &mut buf,
+                                    )
+                                    .unwrap();
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::StopPwmSignal => {
+                            pwm.lock(|pwm| {
+                                pwm::stop(timer);
+                                *pwm = None;
+                            });
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "i2c")]
                         HostToTarget::StartI2cTransaction {
                             mode: DmaMode::Regular,
                             address,
-                            data,
+                            write,
+                            read_len,
+                        } => {
+                            let mut rx_buf = [0u8; I2C_BUF_CAP];
+
+                            let message = if write.len() > I2C_BUF_CAP
+                                || read_len as usize > I2C_BUF_CAP
+                            {
+                                TargetToHost::InvalidRequest
+                            }
+                            else {
+                                let rx_buf = &mut rx_buf[..read_len as usize];
+
+                                let mut result = Ok(());
+
+                                if !write.is_empty() {
+                                    rprintln!("I2C: Write");
+                                    result = i2c_local.write(address, write);
+                                }
+
+                                if result.is_ok() && !rx_buf.is_empty() {
+                                    rprintln!("I2C: Read");
+                                    result = i2c_local.read(address, rx_buf);
+                                }
+
+                                match result {
+                                    Ok(()) => {
+                                        rprintln!("I2C: Done");
+                                        TargetToHost::I2cReply(rx_buf)
+                                    }
+                                    Err(err) if is_nack(&err) => {
+                                        rprintln!("I2C: NACK");
+                                        stop_after_nack();
+                                        TargetToHost::I2cNack
+                                    }
+                                    Err(err) => {
+                                        rprintln!("I2C error: {:?}", err);
+                                        firmware_lib::event::record(
+                                            Kind::Error(event::Error::I2c),
+                                        );
+                                        TargetToHost::I2cError
+                                    }
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "i2c")]
+                        HostToTarget::StartI2cTransaction {
+                            mode: DmaMode::Dma,
+                            address,
+                            write,
+                            read_len,
+                        } => {
+                            let message = if write.len() > I2C_BUF_CAP
+                                || read_len as usize > I2C_BUF_CAP
+                            {
+                                TargetToHost::InvalidRequest
+                            }
+                            else {
+                                let (master, channel, message) =
+                                    i2c_transaction_dma(
+                                        i2c_local,
+                                        i2c_dma_local,
+                                        address,
+                                        write,
+                                        read_len as usize,
+                                    );
+                                i2c_local     = master;
+                                i2c_dma_local = channel;
+
+                                message
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "spi")]
+                        HostToTarget::StartSpiTransaction {
+                            mode: DmaMode::Regular,
+                            write,
+                            read_len,
+                        } => {
+                            let mut rx_buf = [0u8; SPI_BUF_CAP];
+
+                            let len = write.len() + read_len as usize;
+                            let message = if len > SPI_BUF_CAP {
+                                TargetToHost::InvalidRequest
+                            }
+                            else {
+                                let rx_buf = &mut rx_buf[..read_len as usize];
+
+                                rprintln!("SPI: Start transaction");
+                                ssel.set_low();
+
+                                // Clear receive buffer. Otherwise the following
+                                // series of operations won't work as intended.
+                                loop {
+                                    if let Err(nb::Error::WouldBlock) =
+                                        spi_local.read()
+                                    {
+                                        break;
+                                    }
+                                }
+
+                                rprintln!("SPI: Write");
+                                for &b in write {
+                                    block!(spi_local.send(b))
+                                        .unwrap();
+                                    let _ = block!(spi_local.read())
+                                        .unwrap();
+                                }
+
+                                rprintln!("SPI: Read");
+                                for b in rx_buf.iter_mut() {
+                                    block!(spi_local.send(0xff))
+                                        .unwrap();
+                                    *b = block!(spi_local.read())
+                                        .unwrap();
+                                }
+
+                                ssel.set_high();
+                                rprintln!("SPI: Done");
+
+                                TargetToHost::SpiReplyBuffer(rx_buf)
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "spi")]
+                        HostToTarget::StartSpiTransaction {
+                            mode: DmaMode::Dma,
+                            write,
+                            read_len,
                         } => {
-                            rprintln!("I2C: Write");
-                            i2c_local.write(address, &[data])
+                            static mut SPI_BUF: [u8; SPI_BUF_CAP] =
+                                [0; SPI_BUF_CAP];
+
+                            let len = write.len() + read_len as usize;
+                            let message = if len > SPI_BUF_CAP {
+                                TargetToHost::InvalidRequest
+                            }
+                            else {
+                                // Sound, as we have exclusive access to the
+                                // static here.
+                                let spi_buf = unsafe { &mut SPI_BUF[..] };
+
+                                // Writing and reading happen in a single
+                                // transfer. Whatever is received while writing
+                                // is discarded below, and `0xff` is sent while
+                                // reading.
+                                let mut spi_buf = &mut spi_buf[..len];
+                                spi_buf[..write.len()].copy_from_slice(write);
+                                for b in &mut spi_buf[write.len()..] {
+                                    *b = 0xff;
+                                }
+
+                                rprintln!("SPI/DMA: Start transaction");
+                                ssel.set_low();
+
+                                let payload = spi_local
+                                    .transfer_all(
+                                        spi_buf,
+                                        spi_rx_dma_local,
+                                        spi_tx_dma_local,
+                                    )
+                                    .start()
+                                    .wait();
+
+                                ssel.set_high();
+
+                                spi_local        = payload.0;
+                                spi_buf          = payload.1;
+                                spi_rx_dma_local = payload.2;
+                                spi_tx_dma_local = payload.3;
+
+                                rprintln!("SPI/DMA: Transaction ended");
+
+                                TargetToHost::SpiReplyBuffer(
+                                    &spi_buf[write.len()..],
+                                )
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "spi")]
+                        HostToTarget::ExpectSpiSlaveTransfer { response } => {
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SpiSlaveReady,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            rprintln!("SPI: Wait for transaction as slave");
+                            let mut received = [0u8; SPI_BUF_CAP];
+                            let len = spi_slave_transfer(
+                                ssel,
+                                response,
+                                &mut received,
+                            );
+                            rprintln!("SPI: Slave transaction ended");
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SpiSlaveReceived(
+                                        &received[..len],
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::NegotiateCompression(compression) => {
+                            // The reply must still use the previous scheme, so
+                            // only switch after sending it.
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::CompressionNegotiated(
+                                        compression,
+                                    ),
+                                    &mut buf,
+                                )
                                 .unwrap();
+                            host_tx.compression = compression;
+                            panic::set_framing(compression, host_tx.checksum);
 
-                            rprintln!("I2C: Read");
-                            let mut rx_buf = [0u8; 1];
-                            i2c_local.read(address, &mut rx_buf)
+                            Ok(())
+                        }
+                        HostToTarget::NegotiateChecksum(checksum) => {
+                            // The reply must still use the previous checksum,
+                            // so only switch after sending it.
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::ChecksumNegotiated(checksum),
+                                    &mut buf,
+                                )
                                 .unwrap();
+                            host_tx.checksum = checksum;
+                            panic::set_framing(host_tx.compression, checksum);
+                            negotiated_checksum = Some(checksum);
 
-                            rprintln!("I2C: Done");
+                            Ok(())
+                        }
+                        HostToTarget::GetCapabilities => {
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::Capabilities(CAPABILITIES),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::GetVersion => {
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::Version {
+                                        protocol:      PROTOCOL_VERSION,
+                                        firmware_hash: env!("FIRMWARE_HASH"),
+                                        hal_version:   env!("HAL_VERSION"),
+                                        features:
+                                            env!("FIRMWARE_FEATURES"),
+                                    },
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::Ping => {
+                            host_tx
+                                .send_message(&TargetToHost::Pong, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::Reset => {
+                            // Make sure the host has received everything we
+                            // sent so far.
+                            host_tx.flush()
+                                .void_unwrap();
+                            SCB::sys_reset()
+                        }
+                        #[cfg(feature = "adc")]
+                        HostToTarget::ReadAdc {
+                            channel: board::ADC_CHANNEL,
+                        } => {
+                            // Can't fail. The HAL returns the 12-bit result
+                            // in the upper bits of the value.
+                            let value = block!(adc.read(adc_pin))
+                                .unwrap_or_else(|()| unreachable!());
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::AdcValue(value >> 4),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "dac")]
+                        HostToTarget::ReadAdc {
+                            channel: board::DAC_LOOPBACK_CHANNEL,
+                        } => {
+                            // Can't fail. See above.
+                            let value = block!(adc.read(dac_loop_pin))
+                                .unwrap_or_else(|()| unreachable!());
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::AdcValue(value >> 4),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "adc")]
+                        HostToTarget::SampleAdc {
+                            channel,
+                            len,
+                            interval: Microseconds(interval),
+                        } => {
+                            // Only the channels that `ReadAdc` supports can
+                            // be sampled.
+                            let supported = channel == board::ADC_CHANNEL
+                                || (cfg!(feature = "dac")
+                                    && channel == board::DAC_LOOPBACK_CHANNEL);
+
+                            let mut sample_buf = [0; 2 * ADC_SAMPLES_CAP];
+                            let message = if supported
+                                && len as usize <= ADC_SAMPLES_CAP
+                            {
+                                // Can't fail. The HAL returns the 12-bit
+                                // results in the upper bits of the values.
+                                let mut samples = [0; ADC_SAMPLES_CAP];
+                                let samples = &mut samples[..len as usize];
+                                sample_adc(timer, interval, samples, || {
+                                    let value = match channel {
+                                        board::ADC_CHANNEL => {
+                                            block!(adc.read(adc_pin))
+                                        }
+                                        #[cfg(feature = "dac")]
+                                        board::DAC_LOOPBACK_CHANNEL => {
+                                            block!(adc.read(dac_loop_pin))
+                                        }
+                                        // The channel has been checked above.
+                                        _ => unreachable!(),
+                                    };
+                                    value.unwrap_or_else(|()| unreachable!())
+                                        >> 4
+                                });
+
+                                let samples = encode_samples(
+                                    samples.iter().copied(),
+                                    &mut sample_buf,
+                                );
+                                TargetToHost::AdcSamples(samples)
+                            }
+                            else {
+                                TargetToHost::InvalidRequest
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "dac")]
+                        HostToTarget::SetDacValue {
+                            channel: board::DAC_CHANNEL,
+                            value,
+                        } => {
+                            // A waveform would overwrite the value right away.
+                            dac_wave.lock(|waveform| {
+                                dac::stop(dac_mrt);
+                                *waveform = None;
+                            });
+                            dac::set(dac, value);
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "dac")]
+                        HostToTarget::StartDacWaveform {
+                            channel: board::DAC_CHANNEL,
+                            samples,
+                            rate,
+                        } => {
+                            let result = dac_wave.lock(|waveform| {
+                                dac::Waveform::start(
+                                    dac,
+                                    dac_mrt,
+                                    samples,
+                                    rate,
+                                )
+                                .map(|started| *waveform = Some(started))
+                            });
+
+                            if let Err(dac::InvalidWaveform) = result {
+                                host_tx
+                                    .send_message(
+                                        &TargetToHost::InvalidRequest,
+                                        &mut buf,
+                                    )
+                                    .unwrap();
+                            }
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "dac")]
+                        HostToTarget::StopDacWaveform => {
+                            dac_wave.lock(|waveform| {
+                                dac::stop(dac_mrt);
+                                *waveform = None;
+                            });
+
+                            Ok(())
+                        }
+                        HostToTarget::GetFlashInfo => {
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::FlashInfo(flash::info()),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::WriteFlash { address, data } => {
+                            let reply = match flash::write(address, data) {
+                                Ok(())   => TargetToHost::FlashWritten,
+                                Err(err) => TargetToHost::FlashError(err),
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::ReadFlash { address, len } => {
+                            let reply = match flash::read(address, len) {
+                                Ok(data) => TargetToHost::FlashRead(data),
+                                Err(err) => TargetToHost::FlashError(err),
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::GetStoreEntry(key) => {
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::StoreEntry(store::get(key)),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::SetStoreEntry { key, value } => {
+                            let result = store::set(key, value);
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::StoreUpdated(result),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::ClearStore => {
+                            let result = store::clear();
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::StoreUpdated(result),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::GetIrqState => {
+                            // Sound, as we're only reading the registers.
+                            let nvic = unsafe { &*NVIC::ptr() };
+
+                            // The Cortex-M0+ supports no more than 32
+                            // interrupts, so the first word is all there is.
+                            let mut state = IrqState {
+                                enabled: [0; 4],
+                                pending: [0; 4],
+                            };
+                            state.enabled[0] = nvic.iser[0].read();
+                            state.pending[0] = nvic.ispr[0].read();
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::IrqState(state),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::DumpEvents => {
+                            let events = firmware_lib::event::dump();
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::Events(events),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::SetIrqPriority { irq, priority } => {
+                            let result = set_irq_priority(irq, priority);
+                            let message = match result {
+                                Ok(()) => {
+                                    TargetToHost::IrqPrioritySet
+                                }
+                                Err(InvalidRequest) => {
+                                    TargetToHost::InvalidRequest
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::RunIrqWorkload { irqs, rounds } => {
+                            let result = run_irq_workload(timer, irqs, rounds);
+                            let message = match result {
+                                Ok(latencies) => {
+                                    TargetToHost::IrqLatencies(latencies)
+                                }
+                                Err(InvalidRequest) => {
+                                    TargetToHost::InvalidRequest
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::ReportPinInterrupts(enable) => {
+                            report_pin_interrupts = enable;
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::PinInterruptsReported,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::ArmOnTrigger(
+                            trigger::Action::SetLevel(
+                                pin::SetLevel {
+                                    pin: GREEN,
+                                    level,
+                                }
+                            )
+                        ) => {
+                            trigger.lock(|action| *action = Some(level));
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::TriggerArmed,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::Quiesce => {
+                            systick.disable_interrupt();
+                            systick.disable_counter();
+                            stop_mrt(mrt);
+
+                            pwm.lock(|pwm| {
+                                pwm::stop(timer);
+                                *pwm = None;
+                            });
+                            capt_scan.lock(|scan| {
+                                capt::stop(capt);
+                                *scan = None;
+                            });
+                            #[cfg(feature = "dac")]
+                            dac_wave.lock(|waveform| {
+                                dac::stop(dac_mrt);
+                                *waveform = None;
+                            });
+
+                            report_pin_interrupts = false;
+                            trigger.lock(|action| *action = None);
+                            pin_config::reset();
+                            #[cfg(feature = "usart")]
+                            {
+                                usart_echo = None;
+                            }
+
+                            host_tx
+                                .send_message(&TargetToHost::Quiesced, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "usart")]
+                        HostToTarget::SleepUntilUsartRx => {
+                            host_tx
+                                .send_message(&TargetToHost::Sleeping, &mut buf)
+                                .unwrap();
+
+                            // The handler that sends queued data can't run
+                            // while sleeping.
+                            host_tx.flush()
+                                .void_unwrap();
+
+                            let wakeup = sleep_until_usart_rx(timer);
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::UsartWakeup(wakeup),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::EnterSleep {
+                            mode,
+                            wakeup_source,
+                            timeout_ms,
+                        }
+                            if cfg!(feature = "usart")
+                                || wakeup_source != WakeupSource::UsartRx =>
+                        {
+                            host_tx
+                                .send_message(&TargetToHost::Sleeping, &mut buf)
+                                .unwrap();
+
+                            // The handler that sends queued data can't run
+                            // while sleeping.
+                            host_tx.flush()
+                                .void_unwrap();
+
+                            let ticks_per_ms = WKT_HZ / 1000;
+                            let (cause, ticks) = enter_sleep(
+                                wkt,
+                                mode,
+                                wakeup_source,
+                                timeout_ms.saturating_mul(ticks_per_ms),
+                            );
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::WokeUp {
+                                        cause,
+                                        after_ms: ticks / ticks_per_ms,
+                                    },
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "usart")]
+                        HostToTarget::SendUsartBreak => {
+                            // Sound, as the HAL doesn't use the break function
+                            // of USART1. We're only reading from the other
+                            // registers.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            while usart.stat.read().txidle().bit_is_clear() {}
+                            usart.ctl.modify(|_, w| w.txbrken().continous());
+                            asm::delay(BREAK_CYCLES);
+                            usart.ctl.modify(|_, w| w.txbrken().normal());
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "usart")]
+                        HostToTarget::ConfigureUsart {
+                            mode: UsartMode::Regular | UsartMode::FlowControl,
+                            config,
+                        } => {
+                            // The HAL can only configure a USART when enabling
+                            // it. This is sound, as nothing else accesses the
+                            // configuration of USART1 after `init`.
+                            firmware_lib::usart::configure(
+                                unsafe { &*USART1::ptr() },
+                                USART_CLOCK_HZ,
+                                &config,
+                            );
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::UsartConfigured,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        #[cfg(feature = "usart")]
+                        HostToTarget::MoveUsartTx(pin)
+                            if pin == board::USART_TX || pin == GREEN =>
+                        {
+                            move_usart_tx(pin);
 
                             host_tx
                                 .send_message(
-                                    &TargetToHost::I2cReply(rx_buf[0]),
+                                    &TargetToHost::UsartTxMoved,
                                     &mut buf,
                                 )
                                 .unwrap();
 
                             Ok(())
                         }
-                        HostToTarget::StartI2cTransaction {
-                            mode: DmaMode::Dma,
-                            address,
-                            data,
+                        #[cfg(feature = "usart")]
+                        HostToTarget::StartUsartThroughputTest {
+                            bytes,
+                            chunk_size,
                         } => {
-                            static mut TX_BUF: [u8; 1] = [0; 1];
-                            static mut RX_BUF: [u8; 1] = [0; 1];
+                            let mut chunk = [0; USART_CHUNK_CAP];
+                            for (i, b) in chunk.iter_mut().enumerate() {
+                                *b = i as u8;
+                            }
+                            let chunk_size = usize::from(chunk_size)
+                                .clamp(1, USART_CHUNK_CAP);
+
+                            let mut remaining = bytes as usize;
+                            while remaining > 0 {
+                                let n = remaining.min(chunk_size);
+                                usart_tx_local.send_raw(&chunk[..n])?;
+                                remaining -= n;
+                            }
 
-                            // Sound, as we have exclusive access to these
-                            // statics here.
-                            let tx_buf = unsafe { &mut TX_BUF };
-                            let mut rx_buf = unsafe { &mut RX_BUF[..] };
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::UsartThroughputSent,
+                                    &mut buf,
+                                )
+                                .unwrap();
 
+                            Ok(())
+                        }
+                        #[cfg(feature = "usart")]
+                        HostToTarget::StartUsartEcho { duration } => {
+                            let now = timer.tc.read().tcval().bits();
+                            usart_echo =
+                                Some((now, duration.0, UsartEcho::default()));
 
-                            tx_buf[0] = data;
+                            Ok(())
+                        }
+                        HostToTarget::DriveSharedLine(level) => {
+                            let (level, settle_us) =
+                                drive_shared_line(shared_line, timer, level);
 
-                            // Write data to slave
-                            let payload = i2c_local
-                                .write_all(address, tx_buf, i2c_dma_local)
-                                .unwrap()
-                                .start()
-                                .wait()
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SharedLine {
+                                        level,
+                                        settle_us,
+                                    },
+                                    &mut buf,
+                                )
                                 .unwrap();
 
-                            i2c_dma_local = payload.channel;
-                            i2c_local = payload.dest;
-
-                            rx_buf[0] = 0;
+                            Ok(())
+                        }
+                        HostToTarget::GetTime => {
+                            let now = timer.tc.read().tcval().bits();
 
-                            // Read data from slave
-                            let payload = i2c_local
-                                .read_all(address, rx_buf, i2c_dma_local)
-                                .unwrap()
-                                .start()
-                                .wait()
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::Time(now),
+                                    &mut buf,
+                                )
                                 .unwrap();
 
-                            i2c_dma_local = payload.channel;
-                            i2c_local = payload.source;
-                            rx_buf = payload.dest;
-
+                            Ok(())
+                        }
+                        HostToTarget::GetResetCause => {
                             host_tx
                                 .send_message(
-                                    &TargetToHost::I2cReply(rx_buf[0]),
+                                    &TargetToHost::ResetCause(reset_cause),
                                     &mut buf,
                                 )
                                 .unwrap();
 
                             Ok(())
                         }
-                        HostToTarget::StartSpiTransaction {
-                            mode: DmaMode::Regular,
-                            data,
+                        #[cfg(feature = "usart-dma")]
+                        HostToTarget::ConfigureUsart {
+                            mode: UsartMode::Dma,
+                            config,
                         } => {
-                            rprintln!("SPI: Start transaction");
-                            ssel.set_low();
-
-                            // Clear receive buffer. Otherwise the following
-                            // series of operations won't work as intended.
-                            loop {
-                                if let Err(nb::Error::WouldBlock) =
-                                    spi_local.read()
-                                {
-                                    break;
-                                }
-                            }
+                            // Sound, for the same reason as above.
+                            firmware_lib::usart::configure(
+                                unsafe { &*USART2::ptr() },
+                                USART_CLOCK_HZ,
+                                &config,
+                            );
 
-                            rprintln!("SPI: Write");
-                            block!(spi_local.send(data))
-                                .unwrap();
-                            let _ = block!(spi_local.read())
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::UsartConfigured,
+                                    &mut buf,
+                                )
                                 .unwrap();
 
-                            rprintln!("SPI: Read");
-                            block!(spi_local.send(0xff))
-                                .unwrap();
-                            let reply = block!(spi_local.read())
+                            Ok(())
+                        }
+                        HostToTarget::SetPinGroup { mask, value } => {
+                            let message = match pin_group::set(mask, value) {
+                                Ok(()) => {
+                                    TargetToHost::PinGroupSet
+                                }
+                                Err(pin_group::NotOutputs) => {
+                                    TargetToHost::InvalidRequest
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&message, &mut buf)
                                 .unwrap();
 
-                            ssel.set_high();
-                            rprintln!("SPI: Done");
+                            Ok(())
+                        }
+                        HostToTarget::ReadPinGroup { mask } => {
+                            let levels = pin_group::read(mask);
 
                             host_tx
                                 .send_message(
-                                    &TargetToHost::SpiReply(reply),
+                                    &TargetToHost::PinGroup(levels),
                                     &mut buf,
                                 )
                                 .unwrap();
 
                             Ok(())
                         }
-                        HostToTarget::StartSpiTransaction {
-                            mode: DmaMode::Dma,
-                            data,
+                        HostToTarget::ConfigurePin {
+                            pin,
+                            direction,
+                            pull,
+                            open_drain,
                         } => {
-                            static mut SPI_BUF: [u8; 2] = [0; 2];
-
-                            // Sound, as we have exclusive access to the static
-                            // here.
-                            let mut spi_buf = unsafe { &mut SPI_BUF[..] };
-
-                            rprintln!("SPI/DMA: Start transaction");
-                            ssel.set_low();
-
-                            spi_buf[0] = data;
-                            let payload = spi_local
-                                .transfer_all(
-                                    spi_buf,
-                                    spi_rx_dma_local,
-                                    spi_tx_dma_local,
-                                )
-                                .start()
-                                .wait();
-
-                            ssel.set_high();
+                            let result = pin_config::configure(
+                                pin,
+                                direction,
+                                pull,
+                                open_drain,
+                            );
+                            let message = match result {
+                                Ok(()) => {
+                                    TargetToHost::PinConfigured
+                                }
+                                Err(pin_level::UnsupportedPin) => {
+                                    TargetToHost::UnsupportedPin(pin)
+                                }
+                            };
 
-                            spi_local        = payload.0;
-                            spi_buf          = payload.1;
-                            spi_rx_dma_local = payload.2;
-                            spi_tx_dma_local = payload.3;
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
 
-                            rprintln!(
-                                "SPI/DMA: Transaction ended ({})",
-                                spi_buf[1],
+                            Ok(())
+                        }
+                        HostToTarget::SetPinFilter {
+                            pin,
+                            samples,
+                            clock_divider,
+                        } => {
+                            let result = pin_config::set_filter(
+                                pin,
+                                samples,
+                                clock_divider,
                             );
+                            let message = match result {
+                                Ok(()) => {
+                                    TargetToHost::PinFilterSet
+                                }
+                                Err(FilterError::UnsupportedPin) => {
+                                    TargetToHost::UnsupportedPin(pin)
+                                }
+                                Err(FilterError::InvalidSettings) => {
+                                    TargetToHost::InvalidRequest
+                                }
+                            };
 
+                            host_tx
+                                .send_message(&message, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        // Requests for peripherals or channels this firmware
+                        // doesn't support.
+                        _ => {
                             host_tx
                                 .send_message(
-                                    &TargetToHost::SpiReply(spi_buf[1]),
+                                    &TargetToHost::InvalidRequest,
                                     &mut buf,
                                 )
                                 .unwrap();
 
                             Ok(())
                         }
-                        message => {
-                            panic!("Unsupported message: {:?}", message)
-                        }
                     };
 
-                    *swm = Some(swm_local);
-                    *usart_tx = Some(usart_tx_local);
-                    *usart_rts = Some(usart_rts_local);
-                    *usart_rts_pin = Some(usart_rts_pin_local);
-                    *usart_cts = Some(usart_cts_local);
-                    *usart_dma_chan = Some(usart_dma_chan_local);
-                    *i2c = Some(i2c_local);
-                    *i2c_dma = Some(i2c_dma_local);
-                    *spi = Some(spi_local);
-                    *spi_rx_dma = Some(spi_rx_dma_local);
-                    *spi_tx_dma = Some(spi_tx_dma_local);
+                    #[cfg(feature = "usart")]
+                    {
+                        *swm = Some(swm_local);
+                        *usart_tx = Some(usart_tx_local);
+                        *usart_rts = Some(usart_rts_local);
+                        *usart_rts_pin = Some(usart_rts_pin_local);
+                        *usart_cts = Some(usart_cts_local);
+                    }
+                    #[cfg(feature = "usart-dma")]
+                    {
+                        *usart_dma_chan = Some(usart_dma_chan_local);
+                    }
+                    #[cfg(feature = "i2c")]
+                    {
+                        *i2c = Some(i2c_local);
+                        *i2c_dma = Some(i2c_dma_local);
+                    }
+                    #[cfg(feature = "spi")]
+                    {
+                        *spi = Some(spi_local);
+                        *spi_rx_dma = Some(spi_rx_dma_local);
+                        *spi_tx_dma = Some(spi_tx_dma_local);
+                    }
 
                     result
-                })
-                .expect("Error processing host request");
+                });
+            match result {
+                Err(ProcessError::LinkSync) => {
+                    // A host is attaching, and starts over.
+                    host_tx.resync()
+                        .void_unwrap();
+                    panic::set_framing(host_tx.compression, host_tx.checksum);
+                }
+                Err(ProcessError::Postcard(_)) => {
+                    // Whatever was received before the host attached, or
+                    // before it resynchronized the link. Drop it.
+                    firmware_lib::log!(
+                        host_tx,
+                        &mut buf,
+                        TargetToHost::Log,
+                        log::Level::Warn,
+                        "Dropping undecodable host request.",
+                    )
+                    .unwrap();
+                }
+                Err(ProcessError::ChecksumMismatch) => {
+                    // The request was corrupted in transit. Drop it. The host
+                    // won't receive a reply, and can retransmit the request.
+                    firmware_lib::log!(
+                        host_tx,
+                        &mut buf,
+                        TargetToHost::Log,
+                        log::Level::Warn,
+                        "Dropping corrupted host request.",
+                    )
+                    .unwrap();
+                }
+                Err(ProcessError::Chunk(_)) => {
+                    // A chunk of a large request got lost. The host won't
+                    // receive a reply either, and can retransmit the request.
+                    firmware_lib::log!(
+                        host_tx,
+                        &mut buf,
+                        TargetToHost::Log,
+                        log::Level::Warn,
+                        "Dropping incomplete host request.",
+                    )
+                    .unwrap();
+                }
+                Err(ProcessError::BufferFull) => {
+                    // The request didn't fit into the receive buffer, and the
+                    // rest of it is being skipped. Drop it.
+                    firmware_lib::log!(
+                        host_tx,
+                        &mut buf,
+                        TargetToHost::Log,
+                        log::Level::Warn,
+                        "Dropping oversized host request.",
+                    )
+                    .unwrap();
+                }
+                Err(ProcessError::Other(err)) => {
+                    match err {}
+                }
+                Ok(()) => {}
+            }
             host_rx.clear_buf();
+            if let Some(checksum) = negotiated_checksum {
+                host_rx.checksum = checksum;
+            }
 
             // We need this critical section to protect against a race
             // conditions with the interrupt handlers. Otherwise, the following
@@ -875,7 +2636,17 @@ const APP: () = {
             // us up before the test suite times out. But it could also lead to
             // spurious test failures.
             interrupt::free(|_| {
-                if !host_rx.can_process() && !usart_rx.can_process() {
+                #[cfg(feature = "usart")]
+                let usart_idle =
+                    !usart_rx.can_process()
+                    && !usart_errors.ready()
+                    && !USART_BREAK.load(Ordering::Relaxed);
+                #[cfg(not(feature = "usart"))]
+                let usart_idle = true;
+
+                let pin_idle = !pin_interrupts.ready();
+
+                if !host_rx.can_process() && usart_idle && pin_idle {
                     // On LPC84x MCUs, debug mode is not supported when
                     // sleeping. This interferes with RTT communication. Only
                     // sleep, if the user enables this through a compile-time
@@ -887,20 +2658,68 @@ const APP: () = {
         }
     }
 
-    #[task(binds = USART0, resources = [host_rx_int])]
+    // The host link uses one of these USARTs, depending on the board. The
+    // interrupt of the other one never fires.
+
+    #[task(binds = USART0, resources = [host_rx_int, host_tx_int])]
     fn usart0(cx: usart0::Context) {
-        cx.resources.host_rx_int.receive()
-            .expect("Error receiving from USART0");
+        record_irq_entry(Interrupt::USART0);
+
+        handle_host(cx.resources.host_rx_int, cx.resources.host_tx_int);
+    }
+
+    #[task(binds = PIN_INT7_USART4, resources = [host_rx_int, host_tx_int])]
+    fn usart4(cx: usart4::Context) {
+        record_irq_entry(Interrupt::PIN_INT7_USART4);
+
+        handle_host(cx.resources.host_rx_int, cx.resources.host_tx_int);
     }
 
-    #[task(binds = USART1, resources = [usart_rx_int])]
+    // Hardware tasks can't be removed using `#[cfg]`. If their peripheral is
+    // not compiled in, they stay in place but do nothing. Their interrupts
+    // never fire in that case.
+
+    #[task(binds = USART1, resources = [usart_rx_int, usart_err_prod])]
+    #[cfg_attr(not(feature = "usart"), allow(unused_variables))]
     fn usart1(cx: usart1::Context) {
-        cx.resources.usart_rx_int.receive()
-            .expect("Error receiving from USART1");
+        record_irq_entry(Interrupt::USART1);
+
+        #[cfg(feature = "usart")]
+        match cx.resources.usart_rx_int.receive() {
+            // Receive errors are expected, when testing the error handling of
+            // the HAL. Report them to the host, instead of panicking.
+            Err(ReceiveError::Usart(err)) => {
+                firmware_lib::event::record(Kind::Error(event::Error::Usart));
+                if cx.resources.usart_err_prod.enqueue(usart_error(err))
+                    .is_err()
+                {
+                    OVERRUN.add(1);
+                }
+            }
+            result => {
+                result.expect("Error receiving from USART1");
+            }
+        }
+
+        // The flag changes at the start and at the end of a break. Only the
+        // start is reported.
+        #[cfg(feature = "usart")]
+        {
+            let usart = &cx.resources.usart_rx_int.usart;
+            if usart.is_flag_set(usart::Flag::DELTARXBRK)
+                && usart.is_flag_set(usart::Flag::RXBRK)
+            {
+                USART_BREAK.store(true, Ordering::Relaxed);
+            }
+        }
     }
 
     #[task(binds = PIN_INT6_USART3, resources = [usart_sync_rx_int])]
+    #[cfg_attr(not(feature = "usart-sync"), allow(unused_variables))]
     fn usart3(cx: usart3::Context) {
+        record_irq_entry(Interrupt::PIN_INT6_USART3);
+
+        #[cfg(feature = "usart-sync")]
         cx.resources.usart_sync_rx_int.receive()
             .expect("Error receiving from USART3");
     }
@@ -910,44 +2729,849 @@ const APP: () = {
         cx.resources.blue.toggle();
     }
 
-    #[task(binds = PIN_INT0, resources = [red_int])]
+    #[task(binds = MRT0, resources = [blue, mrt_prod, dac_waveform])]
+    fn mrt0(cx: mrt0::Context) {
+        record_irq_entry(Interrupt::MRT0);
+
+        let now = timer_now();
+
+        // Sound, as `idle` only accesses the channels while their interrupts
+        // are disabled, and we only read and clear the interrupt flags.
+        let mrt   = unsafe { &*MRT0::ptr() };
+        let flags = mrt.irq_flag.read();
+
+        // All channels share this interrupt. Channel 0 is the one the host
+        // starts, channel 1 times the DAC waveform.
+        if flags.gflag0().bit_is_set() {
+            mrt.channel[0].stat.write(|w| w.intflag().set_bit());
+
+            cx.resources.blue.toggle();
+            if cx.resources.mrt_prod.enqueue(now).is_err() {
+                OVERRUN.add(1);
+            }
+        }
+
+        #[cfg(feature = "dac")]
+        if flags.gflag1().bit_is_set() {
+            mrt.channel[1].stat.write(|w| w.intflag().set_bit());
+
+            // Sound, as `idle` only accesses the DAC while holding the lock on
+            // `dac_waveform`.
+            let dac = unsafe { &*DAC1::ptr() };
+
+            if let Some(waveform) = cx.resources.dac_waveform {
+                waveform.handle_interrupt(dac);
+            }
+        }
+    }
+
+    #[task(binds = CMP_CAPT, resources = [capt_scan, capt_prod])]
+    fn cmp_capt(cx: cmp_capt::Context) {
+        record_irq_entry(Interrupt::CMP_CAPT);
+
+        let now = timer_now();
+
+        // Sound, as `idle` only accesses the CAPT peripheral while holding the
+        // lock on `capt_scan`.
+        let capt = unsafe { &*CAPT::ptr() };
+
+        let event = match cx.resources.capt_scan {
+            Some(scan) => scan.handle_interrupt(capt, now),
+            None       => {
+                capt::stop(capt);
+                None
+            }
+        };
+
+        if let Some(event) = event {
+            if cx.resources.capt_prod.enqueue(event).is_err() {
+                OVERRUN.add(1);
+            }
+        }
+    }
+
+    #[task(binds = CTIMER0, resources = [pwm])]
+    fn ctimer0(cx: ctimer0::Context) {
+        // Sound, as `idle` only accesses the registers used for the PWM signal
+        // while holding the lock on `pwm`.
+        let timer = unsafe { &*CTIMER0::ptr() };
+
+        match cx.resources.pwm {
+            Some(signal) => signal.handle_interrupt(timer),
+            None         => pwm::stop(timer),
+        }
+    }
+
+    #[task(binds = PIN_INT0, resources = [red_int, pin_int_prod])]
     fn pinint0(context: pinint0::Context) {
+        record_irq_entry(Interrupt::PIN_INT0);
+
         let red_int = context.resources.red_int;
+        let queue   = context.resources.pin_int_prod;
+
+        let now = timer_now();
 
-        red_int.clear_rising_edge_flag();
-        red_int.clear_falling_edge_flag();
+        // Sound, as we're only reading the capture register.
+        let timer    = unsafe { &*CTIMER0::ptr() };
+        let captured = timer.cr[0].read().cap().bits();
+
+        // The interrupt can also be pended without a cause, by the latency
+        // workload. Only report edges that actually happened.
+        if red_int.clear_rising_edge_flag() {
+            if queue.enqueue((pin::Edge::Rising, now, captured)).is_err() {
+                OVERRUN.add(1);
+            }
+        }
+        if red_int.clear_falling_edge_flag() {
+            if queue.enqueue((pin::Edge::Falling, now, captured)).is_err() {
+                OVERRUN.add(1);
+            }
+        }
     }
 
     #[task(
-        binds = DMA0,
+        binds = PIN_INT1,
         resources = [
-            usart_dma_rx_transfer,
-            dma_rx_prod,
+            trigger_int,
+            trigger_action,
+            green,
         ]
     )]
-    fn dma0(context: dma0::Context) {
-        let transfer = context.resources.usart_dma_rx_transfer;
-        let queue    = context.resources.dma_rx_prod;
-
-        // Process completed transfer.
-        let payload = transfer
-            .take()
-            .unwrap()
-            .wait()
-            .unwrap();
-        let channel = payload.channel;
-        let usart   = payload.source;
-        let buffer  = payload.dest;
-
-        // Send received data to idle loop.
-        for &b in buffer.iter() {
-            queue.enqueue(b)
-                .unwrap();
+    fn pinint1(context: pinint1::Context) {
+        record_irq_entry(Interrupt::PIN_INT1);
+
+        let trigger_int = context.resources.trigger_int;
+        let action      = context.resources.trigger_action;
+        let green       = context.resources.green;
+
+        trigger_int.clear_rising_edge_flag();
+
+        // The action is only performed once, until the host arms it again.
+        match action.take() {
+            Some(pin::Level::High) => green.set_high(),
+            Some(pin::Level::Low)  => green.set_low(),
+            None                   => {}
         }
+    }
+
+    #[task(
+        binds = DMA0,
+        resources = [usart_dma_rx_int]
+    )]
+    #[cfg_attr(not(feature = "usart-dma"), allow(unused_variables))]
+    fn dma0(context: dma0::Context) {
+        record_irq_entry(Interrupt::DMA0);
 
-        // Restart transfer.
-        let mut transfer_ready = usart.read_all(buffer, channel);
-        transfer_ready.set_a_when_complete();
-        *transfer = Some(transfer_ready.start());
+        #[cfg(feature = "usart-dma")]
+        context.resources.usart_dma_rx_int.handle_interrupt();
     }
 };
+
+
+/// Receive from the host, and send whatever is queued for it
+///
+/// Needs to be called from the interrupt handler of `board::HostUsart`.
+#[cfg(not(feature = "safe-mode"))]
+fn handle_host(
+    rx: &mut RxInt<'static, HostUsart, AsyncMode>,
+    tx: &mut SenderInt<'static, HostUsart, AsyncMode>,
+) {
+    rx.receive()
+        .expect("Error receiving from host");
+    tx.send()
+        .void_unwrap();
+}
+
+/// Convert an error from the HAL's USART API into its message representation
+#[cfg(feature = "usart")]
+fn usart_error(err: usart::Error<u8>) -> UsartError {
+    match err {
+        usart::Error::Framing(word) => UsartError::Framing(word),
+        usart::Error::Noise(word)   => UsartError::Noise(word),
+        usart::Error::Overrun(word) => UsartError::Overrun(word),
+        usart::Error::Parity(word)  => UsartError::Parity(word),
+    }
+}
+
+/// Sleep, until USART1 receives the start bit of a byte
+///
+/// Then waits for the rest of the byte, without reading it, so it is processed
+/// like any other received data. On LPC84x MCUs, debug mode is not supported
+/// when sleeping, so RTT output might get lost while this is running.
+#[cfg(feature = "usart")]
+fn sleep_until_usart_rx(timer: &CTIMER0) -> UsartWakeup {
+    // This is sound, as the HAL doesn't use the start detection of USART1.
+    // We're only reading from the other registers.
+    let usart = unsafe { &*USART1::ptr() };
+
+    // WFI also wakes up on interrupts that are masked by the critical section.
+    // This allows us to handle the start detection right here, before the
+    // interrupt handler could see it.
+    interrupt::free(|_| {
+        usart.stat.write(|w| w.start().set_bit());
+        usart.intenset.write(|w| w.starten().set_bit());
+
+        // Other interrupts could wake us up too. Go back to sleep, until it's
+        // USART activity that woke us.
+        while usart.stat.read().start().bit_is_clear() {
+            asm::dsb();
+            asm::wfi();
+        }
+        let woken = timer.tc.read().tcval().bits();
+
+        usart.intenclr.write(|w| w.startclr().set_bit());
+        usart.stat.write(|w| w.start().set_bit());
+
+        // If the byte has been lost, it's never going to be ready.
+        let mut received_after = None;
+        loop {
+            let elapsed = timer.tc.read().tcval().bits().wrapping_sub(woken);
+
+            if usart.stat.read().rxrdy().bit_is_set() {
+                received_after = Some(elapsed);
+                break;
+            }
+            if elapsed >= WAKEUP_RX_TIMEOUT {
+                break;
+            }
+        }
+
+        UsartWakeup {
+            received_after,
+            timer_hz: TIMER_HZ,
+        }
+    })
+}
+
+/// Start channel 0 of the multi-rate timer, with its interrupt enabled
+///
+/// `interval` is in microseconds. Returns `Err`, if it is too long for the
+/// timer.
+#[cfg(not(feature = "safe-mode"))]
+fn start_mrt(
+    channel:  &mut mrt::Channel<mrt::MRT0>,
+    mode:     TimerMode,
+    interval: u32,
+)
+    -> Result<(), InvalidRequest>
+{
+    let ticks = interval
+        .checked_mul(12)
+        .and_then(|ticks| mrt::Ticks::try_from(ticks).ok())
+        .ok_or(InvalidRequest)?;
+
+    // Sound, as the HAL doesn't touch the control register, and the interrupt
+    // handler only touches the status register.
+    let mrt = unsafe { &*MRT0::ptr() };
+    mrt.channel[0].ctrl.write(|w| {
+        w.inten().enabled();
+        match mode {
+            TimerMode::OneShot => w.mode().one_shot_interrupt_mode(),
+            TimerMode::Repeat  => w.mode().repeat_interrupt_mode(),
+        }
+    });
+
+    channel.start(ticks);
+
+    Ok(())
+}
+
+/// Stop channel 0 of the multi-rate timer
+///
+/// Takes the channel, to make sure it's not used at the same time.
+#[cfg(not(feature = "safe-mode"))]
+fn stop_mrt(_: &mut mrt::Channel<mrt::MRT0>) {
+    // Sound, as the HAL doesn't touch the control register, and the interrupt
+    // can't fire, once it has been disabled.
+    let mrt = unsafe { &*MRT0::ptr() };
+    mrt.channel[0].ctrl.write(|w| w.inten().disabled());
+
+    // Loading `0` stops the timer immediately, and clearing the flag discards
+    // an expiration that hasn't been handled yet.
+    mrt.channel[0].intval.write(|w| {
+        w.load().set_bit();
+        unsafe { w.ivalue().bits(0) }
+    });
+    mrt.channel[0].stat.write(|w| w.intflag().set_bit());
+}
+
+/// Fill `samples` with readings from `read`, one every `interval`
+///
+/// `interval` is in microseconds, and counts from the start of one reading to
+/// the start of the next. Nothing else is handled while this is going on.
+#[cfg(feature = "adc")]
+fn sample_adc(
+    timer:    &CTIMER0,
+    interval: u32,
+    samples:  &mut [u16],
+    mut read: impl FnMut() -> u16,
+) {
+    let start = timer.tc.read().tcval().bits();
+
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let due = interval.wrapping_mul(i as u32);
+        while timer.tc.read().tcval().bits().wrapping_sub(start) < due {}
+
+        *sample = read();
+    }
+}
+
+/// Enter a low-power mode, until `source` wakes us up, or the timeout expires
+///
+/// `timeout` is in ticks of the self-wake-up timer. Returns what woke us up,
+/// and the number of ticks until then. Whatever woke us up is left for the
+/// interrupt handlers, so it is processed like anything else.
+#[cfg(not(feature = "safe-mode"))]
+fn enter_sleep(
+    wkt:     &WKT,
+    mode:    SleepMode,
+    source:  WakeupSource,
+    timeout: u32,
+)
+    -> (WakeupCause, u32)
+{
+    const SCR_SLEEPDEEP: u32 = 1 << 2;
+
+    // This is sound, as the HAL doesn't use the PMU, the start logic, or the
+    // SLEEPDEEP bit. Of the other peripherals, we're only reading the status,
+    // and using the start detection of USART1, which the HAL doesn't use
+    // either. The WKT interrupt has no handler, so it is only enabled while
+    // nothing else can run.
+    let host   = unsafe { &*HostUsart::ptr() };
+    let nvic   = unsafe { &*NVIC::ptr() };
+    let pint   = unsafe { &*PINT::ptr() };
+    let pmu    = unsafe { &*PMU::ptr() };
+    let scb    = unsafe { &*SCB::ptr() };
+    let syscon = unsafe { &*SYSCON::ptr() };
+    let usart  = unsafe { &*USART1::ptr() };
+
+    let wkt_irq = 1 << Interrupt::WKT.nr();
+
+    // The USARTs lose their clock in deep-sleep mode. Make sure the host gets
+    // all of `Sleeping`.
+    while host.stat.read().txidle().bit_is_clear() {}
+
+    // As in `sleep_until_usart_rx`, WFI also wakes up on interrupts that are
+    // masked by the critical section. Their handlers only run after we've
+    // seen what woke us up.
+    interrupt::free(|_| {
+        wkt.ctrl.modify(|_, w| {
+            w.alarmflag().time_out();
+            w.clearctr().clear_the_counter()
+        });
+        wkt.count.write(|w| unsafe { w.value().bits(timeout) });
+        unsafe { nvic.iser[0].write(wkt_irq) };
+        syscon.starterp1.modify(|_, w| w.wkt().enabled());
+
+        match source {
+            WakeupSource::UsartRx => {
+                usart.stat.write(|w| w.start().set_bit());
+                usart.intenset.write(|w| w.starten().set_bit());
+                syscon.starterp1.modify(|_, w| w.usart1().enabled());
+            }
+            WakeupSource::PinInterrupt => {
+                syscon.starterp0.modify(|_, w| w.pint0().enabled());
+            }
+        }
+
+        if mode == SleepMode::DeepSleep {
+            // Power up the same analog blocks after waking up, as before. See
+            // user manual, section 6.7.5.2.
+            syscon.pdawakecfg.write(|w| unsafe {
+                w.bits(syscon.pdruncfg.read().bits())
+            });
+            pmu.pcon.modify(|_, w| w.pm().deep_sleep_mode());
+            unsafe { scb.scr.modify(|scr| scr | SCR_SLEEPDEEP) };
+        }
+
+        // Other interrupts could wake us up too. Go back to sleep, until it's
+        // the wakeup source or the timeout.
+        let cause = loop {
+            asm::dsb();
+            asm::wfi();
+
+            let woken = match source {
+                WakeupSource::UsartRx => {
+                    usart.stat.read().start().bit_is_set()
+                }
+                WakeupSource::PinInterrupt => {
+                    // Channel 0 is the input pin. See `init`.
+                    pint.ist.read().pstat().bits() & 0x1 != 0
+                }
+            };
+            if woken {
+                break match source {
+                    WakeupSource::UsartRx      => WakeupCause::UsartRx,
+                    WakeupSource::PinInterrupt => WakeupCause::PinInterrupt,
+                };
+            }
+            if wkt.ctrl.read().alarmflag().is_time_out() {
+                break WakeupCause::Timeout;
+            }
+        };
+        let elapsed = timeout - wkt.count.read().value().bits();
+
+        pmu.pcon.modify(|_, w| w.pm().default());
+        unsafe { scb.scr.modify(|scr| scr & !SCR_SLEEPDEEP) };
+
+        match source {
+            WakeupSource::UsartRx => {
+                syscon.starterp1.modify(|_, w| w.usart1().disabled());
+                usart.intenclr.write(|w| w.startclr().set_bit());
+                usart.stat.write(|w| w.start().set_bit());
+            }
+            WakeupSource::PinInterrupt => {
+                syscon.starterp0.modify(|_, w| w.pint0().disabled());
+            }
+        }
+
+        syscon.starterp1.modify(|_, w| w.wkt().disabled());
+        wkt.ctrl.modify(|_, w| {
+            w.alarmflag().time_out();
+            w.clearctr().clear_the_counter()
+        });
+        unsafe {
+            nvic.icer[0].write(wkt_irq);
+            nvic.icpr[0].write(wkt_irq);
+        }
+
+        (cause, elapsed)
+    })
+}
+
+/// Act as an SPI slave for one transaction
+///
+/// The HAL can't switch an enabled SPI peripheral between master and slave
+/// mode, so this function does it through the registers, and switches back to
+/// master mode before returning. The slave select pin is turned into an input
+/// for the duration, and routed to the peripheral.
+///
+/// Sends `response`, then `0xff`. Returns the number of bytes written to
+/// `received`. Bytes that don't fit are discarded.
+#[cfg(feature = "spi")]
+fn spi_slave_transfer(
+    ssel:     &GpioPin<board::SpiSsel, Output>,
+    response: &[u8],
+    received: &mut [u8],
+)
+    -> usize
+{
+    use lpc8xx_hal::{
+        pac::{
+            GPIO,
+            SWM0,
+        },
+        pins::Trait as _,
+    };
+
+    // This is sound, as the HAL's SPI instance is not used while this function
+    // runs, and it's back in master mode afterwards. The slave select pin is
+    // only driven through the GPIO instance we've been passed, and SPI0_SSEL0
+    // is not assigned to any pin otherwise.
+    let spi  = unsafe { &*SPI0::ptr() };
+    let gpio = unsafe { &*GPIO::ptr() };
+    let swm  = unsafe { &*SWM0::ptr() };
+
+    let port = ssel.inner().port() as usize;
+    let mask = ssel.inner().mask();
+
+    gpio.dirclr[port].write(|w| unsafe { w.dirclrp().bits(mask) });
+    swm.pinassign4.modify(|_, w| unsafe {
+        w.spi0_ssel0_io().bits(ssel.inner().id() + port as u8 * 32)
+    });
+    set_spi_master(spi, false);
+
+    let mut response = response.iter().copied();
+    spi.txdat.write(|w| unsafe {
+        w.data().bits(response.next().unwrap_or(0xff).into())
+    });
+
+    spi.stat.write(|w| w.ssa().set_bit().ssd().set_bit());
+
+    let mut len = 0;
+    loop {
+        let stat = spi.stat.read();
+
+        if stat.rxrdy().bit_is_set() {
+            let data = spi.rxdat.read().rxdat().bits() as u8;
+            if let Some(b) = received.get_mut(len) {
+                *b = data;
+                len += 1;
+            }
+
+            // The byte in the transmitter has just been sent. What we load now
+            // is sent while the master clocks in the next byte.
+            while spi.stat.read().txrdy().bit_is_clear() {}
+            spi.txdat.write(|w| unsafe {
+                w.data().bits(response.next().unwrap_or(0xff).into())
+            });
+
+            continue;
+        }
+        // Can't read the SSD flag through the API, same as in the HAL.
+        if stat.bits() & (0x1 << 5) != 0 {
+            break;
+        }
+    }
+
+    // Disabling the peripheral, which switching modes does, discards the byte
+    // that is still in the transmitter.
+    set_spi_master(spi, true);
+    swm.pinassign4.modify(|_, w| unsafe { w.spi0_ssel0_io().bits(0xff) });
+    gpio.dirset[port].write(|w| unsafe { w.dirsetp().bits(mask) });
+
+    len
+}
+
+/// Assign the TX function of USART1 to `pin`
+///
+/// The HAL only assigns movable functions at startup, as the USART owns its TX
+/// function from then on. Moving it later requires writing to the switch
+/// matrix directly. Waits until the USART has finished sending, so no frame is
+/// cut in half.
+#[cfg(feature = "usart")]
+fn move_usart_tx(pin: pin::PortPin) {
+    use lpc8xx_hal::pac::SWM0;
+
+    // This is sound, as the USART doesn't depend on which pin its TX function
+    // is assigned to, and only the field of that function is changed. The
+    // other functions in the register are only reassigned from `idle`, too.
+    let usart = unsafe { &*USART1::ptr() };
+    let swm   = unsafe { &*SWM0::ptr() };
+
+    while usart.stat.read().txidle().bit_is_clear() {}
+    swm.pinassign1.modify(|_, w| unsafe {
+        w.u1_txd_o().bits(pin.number + pin.port * 32)
+    });
+}
+
+/// Drive the shared line like an open-drain pin
+///
+/// Pulls the line low for `Level::Low`, and releases it to the pull-ups for
+/// `Level::High`. Then polls the line, until it has reached `level`, or until
+/// `SHARED_LINE_TIMEOUT` has passed. Returns the level of the line, and how
+/// long it took to reach `level`, in microseconds, if it did.
+#[cfg(not(feature = "safe-mode"))]
+fn drive_shared_line(
+    line:  &mut GpioPin<board::Trigger, Dynamic>,
+    timer: &CTIMER0,
+    level: pin::Level,
+)
+    -> (pin::Level, Option<u32>)
+{
+    let start = timer.tc.read().tcval().bits();
+
+    match level {
+        pin::Level::Low  => line.switch_to_output(gpio::Level::Low),
+        pin::Level::High => line.switch_to_input(),
+    }
+
+    let mut settled_after = None;
+    loop {
+        let elapsed = timer.tc.read().tcval().bits().wrapping_sub(start);
+
+        if line.is_high() == (level == pin::Level::High) {
+            settled_after = Some(elapsed);
+            break;
+        }
+        if elapsed >= SHARED_LINE_TIMEOUT {
+            break;
+        }
+    }
+
+    let level = match line.is_high() {
+        true  => pin::Level::High,
+        false => pin::Level::Low,
+    };
+
+    // The timer counts microseconds, as `TIMER_HZ` is 1 MHz.
+    (level, settled_after)
+}
+
+/// Switch SPI0 between master and slave mode
+///
+/// The mode can only be changed while the peripheral is disabled.
+#[cfg(feature = "spi")]
+fn set_spi_master(spi: &lpc8xx_hal::pac::spi0::RegisterBlock, master: bool) {
+    spi.cfg.modify(|_, w| w.enable().disabled());
+    spi.cfg.modify(|_, w| w.master().bit(master));
+    spi.cfg.modify(|_, w| w.enable().enabled());
+}
+
+/// Indicates whether an I2C error was caused by a NACK from the slave
+#[cfg(feature = "i2c")]
+fn is_nack(err: &i2c::Error) -> bool {
+    use i2c::master::State;
+
+    matches!(
+        err,
+        i2c::Error::UnexpectedState {
+            actual: Ok(State::NackAddress) | Ok(State::NackData),
+            ..
+        }
+    )
+}
+
+/// Record a NACK from the I2C slave, and stop the master
+///
+/// The HAL leaves the master in the NACK state. It needs to be stopped, before
+/// it can start another transaction.
+#[cfg(feature = "i2c")]
+fn stop_after_nack() {
+    firmware_lib::event::record(Kind::Error(event::Error::I2c));
+
+    // Sound, as the caller has exclusive access to the I2C master, and we only
+    // write to a register the HAL won't touch again before the next
+    // transaction.
+    let i2c = unsafe { &*I2C0::ptr() };
+    i2c.mstctl.write(|w| w.mststop().stop());
+}
+
+/// Carry out an I2C transaction using DMA
+///
+/// Returns the master and the DMA channel, together with the reply to the
+/// host. `write` and `read_len` must not be larger than `I2C_BUF_CAP`.
+#[cfg(feature = "i2c")]
+fn i2c_transaction_dma(
+    mut master:  i2c::Master<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
+    mut channel: dma::Channel<dma::Channel15, Enabled>,
+    address:     u8,
+    write:       &[u8],
+    read_len:    usize,
+)
+    -> (
+        i2c::Master<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
+        dma::Channel<dma::Channel15, Enabled>,
+        TargetToHost<'static>,
+    )
+{
+    static mut TX_BUF: [u8; I2C_BUF_CAP] = [0; I2C_BUF_CAP];
+    static mut RX_BUF: [u8; I2C_BUF_CAP] = [0; I2C_BUF_CAP];
+
+    // Sound, as this is only called from `idle`, and the reply that borrows
+    // `RX_BUF` has been sent before the next call.
+    let tx_buf = unsafe { &mut TX_BUF[..write.len()] };
+    let mut rx_buf = unsafe { &mut RX_BUF[..read_len] };
+
+    tx_buf.copy_from_slice(write);
+
+    let mut failed = false;
+
+    // The HAL drops the master and the channel, if it can't start a transfer,
+    // which is what happens if the slave doesn't acknowledge its address. The
+    // copies made below stand in for them in that case.
+    //
+    // Sound, as neither of them implements `Drop`, and only one of each pair
+    // is used afterwards. The other is dropped or forgotten.
+
+    // Write data to slave
+    if !tx_buf.is_empty() {
+        let spare = unsafe { (ptr::read(&master), ptr::read(&channel)) };
+
+        match master.write_all(address, tx_buf, channel) {
+            Ok(transfer) => {
+                mem::forget(spare);
+
+                let payload = match transfer.start().wait() {
+                    Ok(payload) => payload,
+                    Err((_, payload)) => {
+                        failed = true;
+                        payload
+                    }
+                };
+
+                channel = payload.channel;
+                master  = payload.dest;
+            }
+            Err(err) => {
+                rprintln!("I2C/DMA: Error starting write: {:?}", err);
+                failed = true;
+
+                master  = spare.0;
+                channel = spare.1;
+            }
+        }
+    }
+
+    // Read data from slave
+    if !failed && !rx_buf.is_empty() {
+        rx_buf.fill(0);
+
+        let spare = unsafe { (ptr::read(&master), ptr::read(&channel)) };
+
+        match master.read_all(address, rx_buf, channel) {
+            Ok(transfer) => {
+                mem::forget(spare);
+
+                let payload = match transfer.start().wait() {
+                    Ok(payload) => payload,
+                    Err((_, payload)) => {
+                        failed = true;
+                        payload
+                    }
+                };
+
+                channel = payload.channel;
+                master  = payload.source;
+                rx_buf  = payload.dest;
+            }
+            Err(err) => {
+                rprintln!("I2C/DMA: Error starting read: {:?}", err);
+                failed = true;
+
+                master  = spare.0;
+                channel = spare.1;
+            }
+        }
+    }
+
+    if !failed {
+        return (master, channel, TargetToHost::I2cReply(rx_buf));
+    }
+
+    // The HAL doesn't return the error of a failed DMA transfer in a form that
+    // `is_nack` understands, but the master state tells us what happened.
+    //
+    // Sound, as we have exclusive access to the I2C master, and only read a
+    // register.
+    let i2c = unsafe { &*I2C0::ptr() };
+    let state = i2c.stat.read().mststate();
+    if !state.is_nack_address() && !state.is_nack_data() {
+        rprintln!("I2C/DMA: Error. Master state: {}", state.bits());
+        firmware_lib::event::record(Kind::Error(event::Error::I2c));
+        return (master, channel, TargetToHost::I2cError);
+    }
+
+    rprintln!("I2C/DMA: NACK");
+    stop_after_nack();
+
+    (master, channel, TargetToHost::I2cNack)
+}
+
+
+/// Change the priority of interrupt `irq` in the NVIC
+///
+/// Returns `Err`, if the LPC845 doesn't have that interrupt or priority.
+#[cfg(not(feature = "safe-mode"))]
+fn set_irq_priority(irq: u8, priority: u8) -> Result<(), InvalidRequest> {
+    // The LPC845 only implements the upper two bits of each priority.
+    if irq >= 32 || priority >= 4 {
+        return Err(InvalidRequest);
+    }
+
+    // RTIC assumes the priorities that it has configured itself. This is
+    // sound regardless, as none of our tasks share resources with each other.
+    // They only share them with `idle`, which locks them using a critical
+    // section on the Cortex-M0+.
+    let nvic  = unsafe { &*NVIC::ptr() };
+    let shift = irq as u32 % 4 * 8;
+
+    // The register is shared with three other interrupts, so we need to make
+    // sure nothing else modifies it in the meantime.
+    interrupt::free(|_| unsafe {
+        nvic.ipr[irq as usize / 4].modify(|value| {
+            value & !(0xff << shift) | (priority as u32) << 6 << shift
+        });
+    });
+
+    Ok(())
+}
+
+/// Record when the handler of interrupt `irq` was entered
+///
+/// Needs to be called at the beginning of every handler that the latency
+/// workload can pend, or whose entry is used as a timestamp. Also adds an event
+/// for the host to retrieve.
+#[cfg(not(feature = "safe-mode"))]
+fn record_irq_entry(irq: Interrupt) {
+    IRQ_ENTERED[irq.nr() as usize].store(timer_now(), Ordering::Relaxed);
+    firmware_lib::event::record(Kind::Interrupt(irq.nr()));
+}
+
+/// Read when the handler of interrupt `irq` was last entered
+#[cfg(any(feature = "usart", feature = "usart-sync"))]
+fn irq_entered(irq: Interrupt) -> u32 {
+    IRQ_ENTERED[irq.nr() as usize].load(Ordering::Relaxed)
+}
+
+/// Read the free-running timer from an interrupt handler
+///
+/// `idle` owns the timer, so the handlers can't access it as a resource.
+#[cfg(not(feature = "safe-mode"))]
+fn timer_now() -> u32 {
+    // Sound, as we're only reading the timer.
+    let timer = unsafe { &*CTIMER0::ptr() };
+    timer.tc.read().tcval().bits()
+}
+
+/// Capture the timer's value on both edges of `pin`, using capture channel 0
+///
+/// Lets the pin interrupt report when an edge actually reached the pin, in
+/// addition to when its handler ran. T0_CAP0 is an input function, so the pin
+/// can stay a GPIO input. The HAL can't express that, so this writes to the
+/// switch matrix directly.
+#[cfg(not(feature = "safe-mode"))]
+fn capture_edges(timer: &CTIMER0, pin: pin::PortPin) {
+    use lpc8xx_hal::pac::SWM0;
+
+    // This is sound, as only the field of T0_CAP0 is changed, which isn't
+    // assigned anywhere else.
+    let swm = unsafe { &*SWM0::ptr() };
+
+    swm.pinassign14.modify(|_, w| unsafe {
+        w.t0_cap0().bits(pin.number + pin.port * 32)
+    });
+    timer.ccr.modify(|_, w| w.cap0re().set_bit().cap0fe().set_bit());
+}
+
+/// Pend all interrupts in `irqs` at the same time, `rounds` times
+///
+/// Returns the maximum time between pending each interrupt and its handler
+/// being entered, or `Err`, if `irqs` includes interrupts that are not in
+/// `WORKLOAD_IRQS`.
+#[cfg(not(feature = "safe-mode"))]
+fn run_irq_workload(timer: &CTIMER0, irqs: u32, rounds: u16)
+    -> Result<IrqLatencies, InvalidRequest>
+{
+    if irqs & !WORKLOAD_IRQS != 0 {
+        return Err(InvalidRequest);
+    }
+
+    // Sound, as only interrupts whose handlers can deal with it are pended.
+    let nvic = unsafe { &*NVIC::ptr() };
+
+    let mut max = [0; 32];
+
+    for _ in 0..rounds {
+        // No handler can run before the critical section ends, so all of them
+        // are measured from the same timestamp.
+        let pended = interrupt::free(|_| {
+            unsafe { nvic.ispr[0].write(irqs) };
+            timer.tc.read().tcval().bits()
+        });
+
+        // All handlers have a higher priority than `idle`, so they've all run,
+        // in order of priority, by the time we get here.
+        for (irq, max) in max.iter_mut().enumerate() {
+            if irqs & 1 << irq == 0 {
+                continue;
+            }
+
+            let entered = IRQ_ENTERED[irq].load(Ordering::Relaxed);
+            *max = (*max).max(entered.wrapping_sub(pended));
+        }
+    }
+
+    Ok(IrqLatencies {
+        max,
+        timer_hz: TIMER_HZ,
+    })
+}
+
+
+/// A request with fields that the target can't carry out
+///
+/// Handled by replying with `TargetToHost::InvalidRequest`.
+#[cfg(not(feature = "safe-mode"))]
+#[derive(Debug)]
+struct InvalidRequest;
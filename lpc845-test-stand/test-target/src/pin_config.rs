@@ -0,0 +1,157 @@
+//! Reconfiguring GPIO pins at runtime
+//!
+//! The HAL fixes the direction of a pin in its type, and doesn't support the
+//! IOCON settings at all, so this module uses the registers of the GPIO and
+//! IOCON peripherals. Only the pins in `board::CONFIGURABLE_PINS` can be
+//! reconfigured. They stay owned by the HAL, which keeps setting their levels
+//! as usual. That only has an effect while they are outputs.
+//!
+//! The input filter of the pins in `board::FILTER_PINS` can be enabled
+//! separately. It runs from the IOCONCLKDIV0 clock divider.
+
+
+use lpc8xx_hal::pac::{
+    GPIO,
+    IOCON,
+    SYSCON,
+};
+
+use lpc845_messages::pin::{
+    Direction,
+    PortPin,
+    Pull,
+};
+
+use crate::{
+    board::{
+        CONFIGURABLE_PINS,
+        FILTER_PINS,
+    },
+    pin_level::UnsupportedPin,
+};
+
+
+/// The S_MODE and CLK_DIV fields of an IOCON register
+const FILTER_FIELDS: u32 = 0b11111 << 11;
+
+
+/// Enable the clock of the IOCON peripheral
+pub fn init() {
+    // Sound, as the HAL doesn't use the clock of the IOCON peripheral, and we
+    // only change its field.
+    let syscon = unsafe { &*SYSCON::ptr() };
+
+    syscon.sysahbclkctrl0.modify(|_, w| w.iocon().enable());
+}
+
+/// Change the configuration of a pin
+///
+/// Returns `Err`, if `pin` is not in `CONFIGURABLE_PINS`.
+pub fn configure(
+    pin:        PortPin,
+    direction:  Direction,
+    pull:       Pull,
+    open_drain: bool,
+)
+    -> Result<(), UnsupportedPin>
+{
+    let index = CONFIGURABLE_PINS
+        .iter()
+        .find(|&&(configurable, _)| configurable == pin)
+        .map(|&(_, index)| index)
+        .ok_or(UnsupportedPin)?;
+
+    // Sound, as the HAL doesn't use the IOCON registers, and all writes to the
+    // direction registers go through the atomic DIRSET and DIRCLR registers.
+    let gpio  = unsafe { &*GPIO::ptr() };
+    let iocon = unsafe { &*IOCON::ptr() };
+
+    iocon.pio[index].modify(|_, w| {
+        let w = match pull {
+            Pull::None     => w.mode().inactive(),
+            Pull::Down     => w.mode().pull_down(),
+            Pull::Up       => w.mode().pull_up(),
+            Pull::Repeater => w.mode().repeater(),
+        };
+        w.od().bit(open_drain)
+    });
+
+    let port = pin.port as usize;
+    let bit  = 1 << pin.number;
+    match direction {
+        Direction::Input => {
+            gpio.dirclr[port].write(|w| unsafe { w.dirclrp().bits(bit) });
+        }
+        Direction::Output => {
+            gpio.dirset[port].write(|w| unsafe { w.dirsetp().bits(bit) });
+        }
+    }
+
+    Ok(())
+}
+
+/// Enable or disable the input filter of a pin
+///
+/// The filter rejects pulses that are shorter than `samples` periods of the
+/// system clock, divided by `clock_divider`. A `samples` value of `0` disables
+/// the filter.
+///
+/// Returns `Err`, without changing anything, if `pin` is not in
+/// `FILTER_PINS`, if `samples` is larger than `3`, or if the filter is enabled
+/// with a `clock_divider` of `0`.
+pub fn set_filter(pin: PortPin, samples: u8, clock_divider: u8)
+    -> Result<(), FilterError>
+{
+    let index = FILTER_PINS
+        .iter()
+        .find(|&&(filterable, _)| filterable == pin)
+        .map(|&(_, index)| index)
+        .ok_or(FilterError::UnsupportedPin)?;
+    if samples > 3 || (samples != 0 && clock_divider == 0) {
+        return Err(FilterError::InvalidSettings);
+    }
+
+    // Sound, as the HAL doesn't use the IOCON registers or the IOCON clock
+    // dividers.
+    let syscon = unsafe { &*SYSCON::ptr() };
+    let iocon  = unsafe { &*IOCON::ptr() };
+
+    if samples != 0 {
+        syscon.ioconclkdiv0.write(|w| unsafe { w.div().bits(clock_divider) });
+    }
+
+    // CLK_DIV stays at `0`, which selects IOCONCLKDIV0.
+    let filter = u32::from(samples) << 11;
+    iocon.pio[index].modify(|r, w| unsafe {
+        w.bits(r.bits() & !FILTER_FIELDS | filter)
+    });
+
+    Ok(())
+}
+
+/// Restore the initial configuration of all configurable pins
+///
+/// Also disables the input filters.
+pub fn reset() {
+    // Can't fail, as only pins from the tables are passed, and disabling a
+    // filter doesn't need a clock divider.
+    for &(pin, _) in CONFIGURABLE_PINS {
+        configure(pin, Direction::Output, Pull::Up, false)
+            .unwrap_or_else(|_| unreachable!());
+    }
+    for &(pin, _) in FILTER_PINS {
+        set_filter(pin, 0, 0)
+            .unwrap_or_else(|_| unreachable!());
+    }
+}
+
+
+/// Error changing the input filter of a pin
+#[derive(Debug)]
+pub enum FilterError {
+    /// The pin has no filter that can be changed
+    UnsupportedPin,
+
+    /// The number of samples or the clock divider are out of range
+    InvalidSettings,
+}
@@ -0,0 +1,51 @@
+//! Reading and writing a group of GPIO pins at once
+//!
+//! The HAL can only access one pin at a time, so this module uses the port
+//! registers of the GPIO peripheral. The group is the GPIO port selected by
+//! `board::PIN_GROUP_PORT`, and bit `n` of a mask stands for pin `n` of that
+//! port.
+
+
+use lpc8xx_hal::pac::GPIO;
+
+use crate::board::{
+    PIN_GROUP_OUTPUTS,
+    PIN_GROUP_PORT,
+};
+
+
+/// Set the pins in `mask` to the levels in `value`, all at the same time
+///
+/// Returns `Err`, without setting any pins, if `mask` includes pins that are
+/// not in `PIN_GROUP_OUTPUTS`.
+pub fn set(mask: u32, value: u32) -> Result<(), NotOutputs> {
+    if mask & !PIN_GROUP_OUTPUTS != 0 {
+        return Err(NotOutputs);
+    }
+
+    // Sound, as the HAL doesn't use the masked port registers, and the mask
+    // only lets through output pins. Everything else that writes to those pins
+    // uses the atomic SET, CLR, and NOT registers.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    // Pins whose bit is set in MASK are not affected by MPIN.
+    gpio.mask[PIN_GROUP_PORT].write(|w| unsafe { w.maskp().bits(!mask) });
+    gpio.mpin[PIN_GROUP_PORT].write(|w| unsafe { w.mportp().bits(value) });
+
+    Ok(())
+}
+
+/// Read the levels of the pins in `mask`, all at the same time
+///
+/// Returns the pins in `mask` that are high.
+pub fn read(mask: u32) -> u32 {
+    // Sound, as we're only reading.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    gpio.pin[PIN_GROUP_PORT].read().port().bits() & mask
+}
+
+
+/// The mask includes pins that are not outputs
+#[derive(Debug)]
+pub struct NotOutputs;
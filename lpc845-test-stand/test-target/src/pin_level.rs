@@ -0,0 +1,73 @@
+//! Setting and reading the GPIO pins that the host controls
+//!
+//! The HAL fixes the pin in the type of each GPIO pin, so the pin that a request
+//! refers to can't be looked up at runtime. This module uses the port registers
+//! of the GPIO peripheral instead. The host can set the pins in
+//! `board::OUTPUT_PINS`, and read those and the pins in `board::INPUT_PINS`.
+//! Other pins are rejected, as they might be in use by a peripheral.
+
+
+use lpc8xx_hal::pac::GPIO;
+
+use lpc845_messages::pin::{
+    Level,
+    PortPin,
+};
+
+use crate::board::{
+    INPUT_PINS,
+    OUTPUT_PINS,
+};
+
+
+/// Set the level of a pin
+///
+/// Returns `Err`, if `pin` is not in `OUTPUT_PINS`.
+pub fn set(pin: PortPin, level: Level) -> Result<(), UnsupportedPin> {
+    if !OUTPUT_PINS.contains(&pin) {
+        return Err(UnsupportedPin);
+    }
+
+    // Sound, as we only write to the atomic SET and CLR registers, which is
+    // what the HAL uses too.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    let port = pin.port as usize;
+    let bit  = 1 << pin.number;
+    match level {
+        Level::High => {
+            gpio.set[port].write(|w| unsafe { w.setp().bits(bit) });
+        }
+        Level::Low => {
+            gpio.clr[port].write(|w| unsafe { w.clrp().bits(bit) });
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the level of a pin
+///
+/// Returns `Err`, if `pin` is neither in `OUTPUT_PINS` nor in `INPUT_PINS`.
+pub fn read(pin: PortPin) -> Result<Level, UnsupportedPin> {
+    if !OUTPUT_PINS.contains(&pin) && !INPUT_PINS.contains(&pin) {
+        return Err(UnsupportedPin);
+    }
+
+    // Sound, as we're only reading.
+    let gpio = unsafe { &*GPIO::ptr() };
+
+    let port = pin.port as usize;
+    let bit  = 1 << pin.number;
+    let level = match gpio.pin[port].read().port().bits() & bit != 0 {
+        true  => Level::High,
+        false => Level::Low,
+    };
+
+    Ok(level)
+}
+
+
+/// The pin is not one that the host may access
+#[derive(Debug)]
+pub struct UnsupportedPin;
@@ -0,0 +1,116 @@
+//! PWM signal generation, using CTIMER0
+//!
+//! CTIMER0 is also the free-running timer that times out partial host requests
+//! and timestamps events, so it can't be reset at the end of every period, as
+//! the CTIMER's own PWM mode would require. Instead, match register 0 toggles
+//! the output (T0_MAT0) whenever the timer reaches it, and the interrupt
+//! handler moves the match register to the next edge.
+//!
+//! The edges are placed by the hardware, relative to the previous edge, so the
+//! interrupt latency doesn't show up in the signal. It only needs to be shorter
+//! than the time the signal stays high or low.
+
+
+use lpc8xx_hal::pac::ctimer0::RegisterBlock;
+
+
+/// The shortest time the signal can stay high or low, in timer ticks
+///
+/// The interrupt handler needs to move the match register, before the timer
+/// reaches the next edge.
+const MIN_PULSE: u32 = 50;
+
+
+/// A PWM signal that is being generated
+pub struct Signal {
+    /// How long the signal stays high, in timer ticks
+    high: u32,
+
+    /// How long the signal stays low, in timer ticks
+    low: u32,
+}
+
+impl Signal {
+    /// Start generating a signal on T0_MAT0
+    ///
+    /// `period` is in timer ticks. `duty_permille` is the share of each period
+    /// that the signal is high, in thousandths. A duty cycle of 0 or 1000
+    /// results in a constant level, without any interrupts.
+    ///
+    /// Returns `Err`, without changing the signal, if `duty_permille` is larger
+    /// than 1000, or if the signal would stay high or low for less than
+    /// `MIN_PULSE` ticks.
+    pub fn start(timer: &RegisterBlock, period: u32, duty_permille: u16)
+        -> Result<Self, InvalidSignal>
+    {
+        if duty_permille > 1000 {
+            return Err(InvalidSignal);
+        }
+
+        let high =
+            (u64::from(period) * u64::from(duty_permille) / 1000) as u32;
+        let low = period - high;
+
+        let constant = high == 0 || low == 0;
+        if !constant && (high < MIN_PULSE || low < MIN_PULSE) {
+            return Err(InvalidSignal);
+        }
+
+        // The HAL sets up the CTIMER for PWM, which would take the output out
+        // of our hands, and reload the match register when the timer wraps.
+        timer.pwmc.modify(|_, w| w.pwmen0().clear_bit());
+        timer.mcr.modify(|_, w| w.mr0rl().clear_bit());
+
+        if constant {
+            stop(timer);
+            timer.emr.modify(|_, w| w.em0().bit(high != 0));
+
+            return Ok(Self { high, low });
+        }
+
+        // Start with the signal low, and the first rising edge right away.
+        let now = timer.tc.read().tcval().bits();
+        timer.mr[0].write(|w| unsafe {
+            w.match_().bits(now.wrapping_add(MIN_PULSE))
+        });
+        timer.emr.modify(|_, w| w.em0().clear_bit().emc0().toggle());
+        timer.ir.write(|w| w.mr0int().set_bit());
+        timer.mcr.modify(|_, w| w.mr0i().set_bit());
+
+        Ok(Self { high, low })
+    }
+
+    /// Schedule the next edge
+    ///
+    /// Needs to be called from the CTIMER0 interrupt handler.
+    pub fn handle_interrupt(&self, timer: &RegisterBlock) {
+        timer.ir.write(|w| w.mr0int().set_bit());
+
+        // The output has just been toggled. How long it stays at its new
+        // level decides when the next edge is due.
+        let next = if timer.emr.read().em0().bit_is_set() {
+            self.high
+        }
+        else {
+            self.low
+        };
+
+        let last = timer.mr[0].read().match_().bits();
+        timer.mr[0].write(|w| unsafe {
+            w.match_().bits(last.wrapping_add(next))
+        });
+    }
+}
+
+
+/// Stop generating the signal, and leave the output low
+pub fn stop(timer: &RegisterBlock) {
+    timer.mcr.modify(|_, w| w.mr0i().clear_bit());
+    timer.emr.modify(|_, w| w.em0().clear_bit().emc0().do_nothing());
+    timer.ir.write(|w| w.mr0int().set_bit());
+}
+
+
+/// The requested signal can't be generated
+#[derive(Debug)]
+pub struct InvalidSignal;
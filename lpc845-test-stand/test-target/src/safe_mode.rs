@@ -0,0 +1,322 @@
+//! Safe mode firmware
+//!
+//! A minimal firmware that is built instead of the regular one, if the
+//! `safe-mode` feature is enabled. It only sets up the link to the host, and
+//! answers the requests needed to identify the target, check that it is alive,
+//! reset it, find out why it was reset, and read information about its flash
+//! memory. It doesn't use any interrupts, and doesn't touch any other
+//! peripherals.
+//!
+//! Use it as a recovery image, if the regular firmware is broken, or as the
+//! first step of bringing up a new board, before all of its pins are wired.
+//! The host can detect it from the `safe-mode` feature in the version
+//! handshake.
+
+
+use cortex_m_rt::entry;
+use lpc8xx_hal::{
+    Peripherals,
+    cortex_m::{
+        self,
+        peripheral::SCB,
+    },
+    syscon::frg,
+    usart,
+};
+use rtt_target::rprintln;
+use void::{
+    ResultVoidExt,
+    Void,
+};
+
+use firmware_lib::{
+    panic,
+    usart::{
+        Usart,
+        rx::ProcessError,
+    },
+};
+use lpc845_messages::{
+    Capabilities,
+    HEARTBEAT_INTERVAL_MS,
+    HostToTarget,
+    PROTOCOL_VERSION,
+    TargetToHost,
+};
+
+use crate::{
+    board::{
+        self,
+        HostUsart,
+    },
+    fault,
+    flash,
+};
+
+
+/// The functionality of the safe mode firmware
+///
+/// None of the peripherals under test are supported.
+const CAPABILITIES: Capabilities = Capabilities {
+    usart:              false,
+    usart_dma:          false,
+    usart_flow_control: false,
+    usart_sync:         false,
+    i2c:                false,
+    i2c_dma:            false,
+    spi:                false,
+    spi_dma:            false,
+    adc:                false,
+    dac:                false,
+    pwm:                false,
+    timer_interrupt:    false,
+    trigger:            false,
+    host_link:          board::HOST_LINK,
+};
+
+
+#[entry]
+fn main() -> ! {
+    // `cortex-m-rt` gives us safe access to this `static mut`, as this
+    // function is only called once.
+    static mut HOST: Usart = Usart::new();
+
+    rtt_target::rtt_init_print!();
+    rprintln!("Starting target in safe mode.");
+
+    // Get access to the device's peripherals. This can't panic, since this is
+    // the only place in this program where we call this method.
+    let p = Peripherals::take().unwrap_or_else(|| unreachable!());
+    let mut systick = cortex_m::Peripherals::take()
+        .unwrap_or_else(|| unreachable!())
+        .SYST;
+
+    let mut syscon = p.SYSCON.split();
+    let     swm    = p.SWM.split();
+    let     gpio   = p.GPIO.enable(&mut syscon.handle);
+
+    let mut swm_handle = swm.handle.enable(&mut syscon.handle);
+
+    let pins = board::Pins::take(p.pins, gpio.tokens);
+
+    // Configure the clock for the host USART the same way as the regular
+    // firmware does. The resulting rate is roughly 115200 baud.
+    let clock_config = {
+        syscon.frg0.select_clock(frg::Clock::FRO);
+        syscon.frg0.set_mult(22);
+        syscon.frg0.set_div(0xFF);
+        usart::Clock::new(&syscon.frg0, 5, 16)
+    };
+
+    let (host_usart, host_rxd, host_txd) = board::take_host_usart(
+        p.USART0,
+        swm.movable_functions.u0_rxd,
+        swm.movable_functions.u0_txd,
+        p.USART4,
+        swm.movable_functions.u4_rxd,
+        swm.movable_functions.u4_txd,
+    );
+    let (host_rxd, _) = host_rxd.assign(
+        pins.host_rx.into_swm_pin(),
+        &mut swm_handle,
+    );
+    let (host_txd, _) = host_txd.assign(
+        pins.host_tx.into_swm_pin(),
+        &mut swm_handle,
+    );
+
+    // No interrupts are enabled. Received data is polled in the loop below.
+    let host = host_usart.enable_async(
+        &clock_config,
+        &mut syscon.handle,
+        host_rxd,
+        host_txd,
+        usart::Settings::default(),
+    );
+
+    let (mut host_rx_int, mut host_rx, mut host_tx) = HOST.init(host);
+    panic::init::<HostUsart>();
+
+    // SysTick is polled below, to time the heartbeats. It runs at half the
+    // 12 MHz system clock, so 6000 ticks are 1 ms.
+    systick.set_reload(HEARTBEAT_INTERVAL_MS * 6000);
+    systick.clear_current();
+    systick.enable_counter();
+
+    let mut buf = [0; 256];
+
+    host_tx.send_sync()
+        .void_unwrap();
+    let (reset_cause, fault) = fault::take_last();
+    if let Some(fault) = fault {
+        host_tx
+            .send_message(&TargetToHost::Fault(fault), &mut buf)
+            .unwrap();
+    }
+
+    loop {
+        if systick.has_wrapped() {
+            host_tx
+                .send_message(&TargetToHost::Heartbeat, &mut buf)
+                .unwrap();
+        }
+
+        host_rx_int.receive()
+            .expect("Error receiving from host");
+
+        // The receiver is borrowed while the message is processed, so a
+        // negotiated checksum can only be applied to it afterwards.
+        let mut negotiated_checksum = None;
+
+        let result = host_rx
+            .process_message(|message| {
+                let result: Result<(), Void> = match message {
+                    HostToTarget::NegotiateCompression(compression) => {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::CompressionNegotiated(
+                                    compression,
+                                ),
+                                &mut buf,
+                            )
+                            .unwrap();
+                        host_tx.compression = compression;
+
+                        Ok(())
+                    }
+                    HostToTarget::NegotiateChecksum(checksum) => {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::ChecksumNegotiated(checksum),
+                                &mut buf,
+                            )
+                            .unwrap();
+                        host_tx.checksum = checksum;
+                        negotiated_checksum = Some(checksum);
+
+                        Ok(())
+                    }
+                    HostToTarget::GetCapabilities => {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::Capabilities(CAPABILITIES),
+                                &mut buf,
+                            )
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    HostToTarget::GetVersion => {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::Version {
+                                    protocol:      PROTOCOL_VERSION,
+                                    firmware_hash: env!("FIRMWARE_HASH"),
+                                    hal_version:   env!("HAL_VERSION"),
+                                    features:      env!("FIRMWARE_FEATURES"),
+                                },
+                                &mut buf,
+                            )
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    HostToTarget::Ping => {
+                        host_tx
+                            .send_message(&TargetToHost::Pong, &mut buf)
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    HostToTarget::GetResetCause => {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::ResetCause(reset_cause),
+                                &mut buf,
+                            )
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    HostToTarget::Reset => {
+                        host_tx.flush()
+                            .void_unwrap();
+                        SCB::sys_reset()
+                    }
+                    HostToTarget::Quiesce => {
+                        // Safe mode doesn't start anything that would need
+                        // to be stopped.
+                        host_tx
+                            .send_message(&TargetToHost::Quiesced, &mut buf)
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    HostToTarget::GetFlashInfo => {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::FlashInfo(flash::info()),
+                                &mut buf,
+                            )
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    HostToTarget::DumpEvents => {
+                        let events = firmware_lib::event::dump();
+
+                        host_tx
+                            .send_message(
+                                &TargetToHost::Events(events),
+                                &mut buf,
+                            )
+                            .unwrap();
+
+                        Ok(())
+                    }
+                    message => {
+                        // Unlike the regular firmware, don't panic. Staying
+                        // responsive is the whole point of safe mode.
+                        rprintln!("Unsupported in safe mode: {:?}", message);
+                        Ok(())
+                    }
+                };
+
+                result
+            });
+        match result {
+            Err(ProcessError::LinkSync) => {
+                // A host is attaching, and starts over.
+                host_tx.resync()
+                    .void_unwrap();
+            }
+            Err(ProcessError::Postcard(_)) => {
+                // Whatever was received before the host attached. Drop it.
+                rprintln!("Dropping undecodable host request.");
+            }
+            Err(ProcessError::ChecksumMismatch) => {
+                // The request was corrupted in transit. Drop it. The host
+                // won't receive a reply, and can retransmit the request.
+                rprintln!("Dropping corrupted host request.");
+            }
+            Err(ProcessError::BufferFull) => {
+                // The request didn't fit into the receive buffer, and the rest
+                // of it is being skipped. Drop it.
+                rprintln!("Dropping oversized host request.");
+            }
+            Err(ProcessError::Chunk(err)) => {
+                // Chunks aren't reassembled in safe mode, so this doesn't
+                // happen. If it did, the host could retransmit the request.
+                rprintln!("Dropping incomplete host request: {:?}", err);
+            }
+            Err(ProcessError::Other(err)) => {
+                match err {}
+            }
+            Ok(()) => {}
+        }
+        host_rx.clear_buf();
+        if let Some(checksum) = negotiated_checksum {
+            host_rx.checksum = checksum;
+        }
+    }
+}
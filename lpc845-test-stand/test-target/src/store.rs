@@ -0,0 +1,93 @@
+//! Key-value store, for state that survives a reset
+//!
+//! The store is kept in the last pages of flash memory (see
+//! `flash::STORE_START`). Each entry is a key and a value, both `u32`, stored
+//! as little-endian. Erased flash memory reads as `0xff`, so a key of
+//! `u32::MAX` marks an unused entry, and an erased store is empty.
+//!
+//! Flash memory needs to be erased, before it can be programmed again. On every
+//! change, the whole store is copied into RAM, modified there, and programmed
+//! back. That's slow, but test cases don't change the store often.
+
+
+use core::slice;
+
+use lpc845_messages::{
+    STORE_CAP,
+    StoreError,
+};
+
+use crate::flash;
+
+
+/// The size of an entry, in bytes
+const ENTRY_SIZE: usize = 8;
+
+/// The key of unused entries
+const UNUSED: u32 = u32::MAX;
+
+
+/// Returns the value of the entry with `key`, if there is one
+pub fn get(key: u32) -> Option<u32> {
+    entries(stored())
+        .find(|&(k, _)| k == key)
+        .map(|(_, value)| value)
+}
+
+/// Add an entry with `key`, or replace its value, if there already is one
+pub fn set(key: u32, value: u32) -> Result<(), StoreError> {
+    if key == UNUSED {
+        return Err(StoreError::InvalidKey);
+    }
+    // Don't wear out the flash memory for nothing.
+    if get(key) == Some(value) {
+        return Ok(());
+    }
+
+    let mut store = [0; STORE_CAP * ENTRY_SIZE];
+    store.copy_from_slice(stored());
+
+    let index = entries(&store)
+        .position(|(k, _)| k == key)
+        .or_else(|| entries(&store).position(|(k, _)| k == UNUSED))
+        .ok_or(StoreError::Full)?;
+
+    let entry = &mut store[index * ENTRY_SIZE..][..ENTRY_SIZE];
+    entry[..4].copy_from_slice(&key.to_le_bytes());
+    entry[4..].copy_from_slice(&value.to_le_bytes());
+
+    flash::program(flash::STORE_START, &store)
+        .map_err(|err| StoreError::Flash(err))
+}
+
+/// Remove all entries
+pub fn clear() -> Result<(), StoreError> {
+    flash::program(flash::STORE_START, &[0xff; STORE_CAP * ENTRY_SIZE])
+        .map_err(|err| StoreError::Flash(err))
+}
+
+/// Returns the store, as it is in flash memory
+fn stored() -> &'static [u8] {
+    // Sound, as the store is in flash memory, which is only modified by
+    // `flash::program`, and never while the slice is in use.
+    unsafe {
+        slice::from_raw_parts(
+            flash::STORE_START as *const u8,
+            STORE_CAP * ENTRY_SIZE,
+        )
+    }
+}
+
+/// Returns the key and value of each entry, including unused ones
+fn entries(store: &[u8]) -> impl Iterator<Item = (u32, u32)> + '_ {
+    store
+        .chunks(ENTRY_SIZE)
+        .map(|entry| {
+            let (key, value) = entry.split_at(4);
+            (from_le_bytes(key), from_le_bytes(value))
+        })
+}
+
+fn from_le_bytes(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
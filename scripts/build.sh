@@ -11,9 +11,34 @@ export RUSTFLAGS="-D warnings"
 (
     cd test-stand-infra/firmware-lib
     cargo test --verbose)
+(
+    # Firmwares for other microcontrollers use firmware-lib without lpc8xx-hal.
+    cd test-stand-infra/firmware-lib
+    cargo build --no-default-features --verbose)
+(
+    cd test-stand-infra/derive
+    cargo test --verbose)
+(
+    cd test-stand-infra/decoder
+    cargo test --verbose)
 (
     cd test-stand-infra/host-lib
     cargo test --verbose)
+(
+    cd test-stand-infra/host-lib
+    cargo test --features tokio --verbose)
+(
+    cd test-stand-infra/host-lib
+    cargo test --features flash --verbose)
+(
+    cd test-stand-infra/host-lib
+    cargo test --features defmt --verbose)
+(
+    cd test-stand-infra/test-cases
+    cargo test --verbose)
+(
+    cd test-stand-infra/extcap
+    cargo test --verbose)
 
 # LPC845 test stand
 (
@@ -22,20 +47,61 @@ export RUSTFLAGS="-D warnings"
 (
     cd lpc845-test-stand/test-target
     cargo build --verbose)
+(
+    # Builds that leave out some of the peripherals, or all of them.
+    cd lpc845-test-stand/test-target
+    for features in "" usart usart-dma usart-sync i2c spi adc dac; do
+        cargo build \
+            --no-default-features \
+            --features "board-lpc845-brk $features" \
+            --verbose
+    done)
+(
+    cd lpc845-test-stand/test-target
+    cargo build \
+        --no-default-features \
+        --features board-lpc845-brk,safe-mode \
+        --verbose)
 (
     cd lpc845-test-stand/test-assistant
     cargo build --verbose)
 (
     cd lpc845-test-stand/test-suite
     cargo build --tests --verbose)
+(
+    cd lpc845-test-stand/test-suite
+    cargo build --tests --features flash --verbose)
+(
+    cd lpc845-test-stand/test-suite
+    cargo build --tests --features defmt --verbose)
 
 # STM32L4 test stand
 (
     cd stm32l4-test-stand/test-target
     cargo build --verbose)
+(
+    cd stm32l4-test-stand/test-target
+    cargo build --features usb-host-link --verbose)
 (
     cd stm32l4-test-stand/test-assistant
     cargo build --verbose)
 (
     cd stm32l4-test-stand/test-suite
     cargo build --tests --verbose)
+(
+    cd stm32l4-test-stand/test-suite
+    cargo build --tests --features flash --verbose)
+
+# Example test stand for an SPI EEPROM driver
+(
+    cd examples/spi-eeprom-test-stand/driver
+    cargo test --verbose)
+(
+    cd examples/spi-eeprom-test-stand/messages
+    cargo test --verbose)
+(
+    cd examples/spi-eeprom-test-stand/test-target
+    cargo build --verbose)
+(
+    cd examples/spi-eeprom-test-stand/test-suite
+    cargo build --tests --verbose)
@@ -0,0 +1,622 @@
+//! Async variant of `Target`, based on tokio
+
+
+use std::time::Duration;
+
+use lpc845_messages::{
+    Capabilities,
+    DmaMode,
+    HostToTarget,
+    I2C_BUF_CAP,
+    IrqState,
+    SPI_BUF_CAP,
+    TargetToHost,
+    UsartMode,
+    checksum::Checksum,
+    compress::Compression,
+    pin,
+    units::Millivolts,
+};
+
+use host_lib::{
+    config::Timeouts,
+    conn::{
+        self,
+        AsyncConn,
+    },
+    pin::ReadLevelError,
+    target::{
+        HasTimeouts,
+        WithTimeouts,
+    },
+};
+use tokio::time::{
+    self,
+    Instant,
+};
+
+use crate::target::{
+    ADC_REFERENCE,
+    TargetCapabilitiesError,
+    TargetI2cError,
+    TargetIrqStateError,
+    TargetNegotiateChecksumError,
+    TargetNegotiateCompressionError,
+    TargetPinReadError,
+    TargetPingError,
+    TargetRequestError,
+    TargetSetDacValueError,
+    TargetSetPinHighError,
+    TargetSetPinLowError,
+    TargetSpiError,
+    TargetUsartSendError,
+    TargetUsartWaitError,
+    TargetVersionError,
+    Version,
+};
+
+
+/// The async connection to the test target
+///
+/// Provides `async` versions of the methods of [`Target`], for use in async
+/// test harnesses. Methods that return a guard (`start_timer_interrupt`,
+/// `start_pwm_signal`), or that [`TestStand`] uses to check the target
+/// (`quiesce`), are only available on [`Target`].
+///
+/// [`Target`]: crate::target::Target
+/// [`TestStand`]: crate::TestStand
+pub struct AsyncTarget {
+    conn:     AsyncConn,
+    timeouts: Timeouts,
+}
+
+impl AsyncTarget {
+    /// Create an instance of `AsyncTarget`
+    ///
+    /// Open `conn` using the `target` path from the test stand configuration.
+    pub fn new(conn: AsyncConn) -> Self {
+        Self {
+            conn,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// The timeouts of the methods that don't take a timeout
+    ///
+    /// See `Target::timeouts`.
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Replace the timeouts of the methods that don't take a timeout
+    ///
+    /// See `Target::set_timeouts`. Use the `timeouts` section of the test
+    /// stand configuration.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Replace the timeouts for a single call
+    ///
+    /// See `Target::with_timeouts`.
+    pub fn with_timeouts(&mut self, f: impl FnOnce(Timeouts) -> Timeouts)
+        -> WithTimeouts<'_, Self>
+    {
+        WithTimeouts::new(self, f)
+    }
+
+    /// Negotiate the compression scheme for frames sent by the target
+    ///
+    /// See `Target::negotiate_compression`.
+    pub async fn negotiate_compression(&mut self,
+        compression: Compression,
+        timeout:     Duration,
+    )
+        -> Result<(), TargetNegotiateCompressionError>
+    {
+        self.conn
+            .send(&HostToTarget::NegotiateCompression(compression)).await
+            .map_err(|err| TargetNegotiateCompressionError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetNegotiateCompressionError::Receive(err))?;
+
+        match message {
+            TargetToHost::CompressionNegotiated(negotiated) => {
+                self.conn.set_compression(negotiated);
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetNegotiateCompressionError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Negotiate the checksum for frames in both directions
+    ///
+    /// See `Target::negotiate_checksum`.
+    pub async fn negotiate_checksum(&mut self,
+        checksum: Checksum,
+        timeout:  Duration,
+    )
+        -> Result<(), TargetNegotiateChecksumError>
+    {
+        self.conn
+            .send(&HostToTarget::NegotiateChecksum(checksum)).await
+            .map_err(|err| TargetNegotiateChecksumError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetNegotiateChecksumError::Receive(err))?;
+
+        match message {
+            TargetToHost::ChecksumNegotiated(negotiated) => {
+                self.conn.set_checksum(negotiated);
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetNegotiateChecksumError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which functionality it supports
+    pub async fn capabilities(&mut self, timeout: Duration)
+        -> Result<Capabilities, TargetCapabilitiesError>
+    {
+        self.conn
+            .send(&HostToTarget::GetCapabilities).await
+            .map_err(|err| TargetCapabilitiesError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetCapabilitiesError::Receive(err))?;
+
+        match message {
+            TargetToHost::Capabilities(capabilities) => {
+                Ok(capabilities)
+            }
+            message => {
+                Err(
+                    TargetCapabilitiesError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which version of the firmware it runs
+    pub async fn version(&mut self, timeout: Duration)
+        -> Result<Version, TargetVersionError>
+    {
+        self.conn
+            .send(&HostToTarget::GetVersion).await
+            .map_err(|err| TargetVersionError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetVersionError::Receive(err))?;
+
+        match message {
+            TargetToHost::Version {
+                protocol,
+                firmware_hash,
+                hal_version,
+                features,
+            } => {
+                Ok(
+                    Version {
+                        protocol,
+                        firmware_hash: firmware_hash.to_owned(),
+                        hal_version:   hal_version.to_owned(),
+                        features:      features
+                            .split(',')
+                            .filter(|feature| !feature.is_empty())
+                            .map(|feature| feature.to_owned())
+                            .collect(),
+                    }
+                )
+            }
+            message => {
+                Err(
+                    TargetVersionError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which interrupts are enabled and pending
+    pub async fn irq_state(&mut self, timeout: Duration)
+        -> Result<IrqState, TargetIrqStateError>
+    {
+        self.conn
+            .send(&HostToTarget::GetIrqState).await
+            .map_err(|err| TargetIrqStateError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetIrqStateError::Receive(err))?;
+
+        match message {
+            TargetToHost::IrqState(state) => {
+                Ok(state)
+            }
+            message => {
+                Err(
+                    TargetIrqStateError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Check that the target is still responsive
+    pub async fn ping(&mut self, timeout: Duration)
+        -> Result<(), TargetPingError>
+    {
+        self.conn
+            .send(&HostToTarget::Ping).await
+            .map_err(|err| TargetPingError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetPingError::Receive(err))?;
+
+        match message {
+            TargetToHost::Pong => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetPingError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to set a GPIO pin high
+    pub async fn set_pin_high(&mut self, pin: pin::PortPin)
+        -> Result<(), TargetSetPinHighError>
+    {
+        let level = pin::Level::High;
+        self.conn
+            .send(&HostToTarget::SetPin(pin::SetLevel { pin, level })).await
+            .map_err(|err| TargetSetPinHighError(err))
+    }
+
+    /// Set the output of a DAC channel
+    ///
+    /// See `Target::set_dac_value`.
+    pub async fn set_dac_value(&mut self, channel: u8, value: u16)
+        -> Result<(), TargetSetDacValueError>
+    {
+        assert!(value < 1 << 12);
+
+        self.conn
+            .send(&HostToTarget::SetDacValue { channel, value }).await
+            .map_err(|err| TargetSetDacValueError(err))
+    }
+
+    /// Instruct the target to set a GPIO pin low
+    pub async fn set_pin_low(&mut self, pin: pin::PortPin)
+        -> Result<(), TargetSetPinLowError>
+    {
+        let level = pin::Level::Low;
+        self.conn
+            .send(&HostToTarget::SetPin(pin::SetLevel { pin, level })).await
+            .map_err(|err| TargetSetPinLowError(err))
+    }
+
+    /// Indicates whether a GPIO pin is set high
+    pub async fn pin_is_high(&mut self, pin: pin::PortPin)
+        -> Result<bool, TargetPinReadError>
+    {
+        let level = self.read_pin(pin, self.timeouts.read()).await?;
+        Ok(level == pin::Level::High)
+    }
+
+    /// Indicates whether a GPIO pin is set low
+    pub async fn pin_is_low(&mut self, pin: pin::PortPin)
+        -> Result<bool, TargetPinReadError>
+    {
+        let level = self.read_pin(pin, self.timeouts.read()).await?;
+        Ok(level == pin::Level::Low)
+    }
+
+    /// Read the level of a pin, like `host_lib::pin::Pin::read_level` does
+    async fn read_pin(&mut self, pin: pin::PortPin, timeout: Duration)
+        -> Result<pin::Level, ReadLevelError>
+    {
+        // Wait for a bit, to give whatever event is expected to change the
+        // level some time to happen.
+        time::sleep(timeout).await;
+
+        self.conn
+            .send(&HostToTarget::ReadPin(pin::ReadLevel { pin })).await
+            .map_err(|err| ReadLevelError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| ReadLevelError::Receive(err))?;
+
+        match message {
+            TargetToHost::ReadPinResult(Some(result)) if result.pin == pin => {
+                Ok(result.level)
+            }
+            message => {
+                Err(
+                    ReadLevelError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to send this message via USART
+    pub async fn send_usart(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Regular).await
+    }
+
+    /// Instruct the target to send this message via USART using DMA
+    pub async fn send_usart_dma(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Dma).await
+    }
+
+    /// Instruct the target to send this message via USART with flow control
+    pub async fn send_usart_with_flow_control(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::FlowControl).await
+    }
+
+    async fn send_usart_inner(&mut self, data: &[u8], mode: UsartMode)
+        -> Result<(), TargetUsartSendError>
+    {
+        self.conn
+            .send(&HostToTarget::SendUsart { mode, data }).await
+            .map_err(|err| TargetUsartSendError(err))
+    }
+
+    /// Wait to receive the provided data via USART
+    ///
+    /// See `Target::wait_for_usart_rx`.
+    pub async fn wait_for_usart_rx(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Regular).await
+    }
+
+    /// Wait to receive the provided data via USART/DMA
+    pub async fn wait_for_usart_rx_dma(&mut self,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Dma).await
+    }
+
+    async fn wait_for_usart_rx_inner(&mut self,
+        data:          &[u8],
+        timeout:       Duration,
+        expected_mode: UsartMode,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        loop {
+            if buf.windows(data.len()).any(|window| window == data) {
+                return Ok(buf);
+            }
+            if start.elapsed() > timeout {
+                return Err(TargetUsartWaitError::Timeout);
+            }
+
+            // Other messages might arrive in the meantime. Leave them for
+            // whoever expects them.
+            let mut tmp = Vec::new();
+            let message = self.conn
+                .receive_matching::<TargetToHost>(timeout, &mut tmp, |frame| {
+                    matches!(
+                        conn::peek(frame),
+                        Some(TargetToHost::UsartReceive { mode, .. })
+                            if mode == expected_mode
+                    )
+                })
+                .await
+                .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+            match message {
+                TargetToHost::UsartReceive { mode, data, .. }
+                    if mode == expected_mode =>
+                {
+                    buf.extend(data)
+                }
+                message => {
+                    return Err(
+                        TargetUsartWaitError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    /// Read the voltage on an ADC channel
+    ///
+    /// See `Target::read_adc`.
+    pub async fn read_adc(&mut self, channel: u8)
+        -> Result<Millivolts, TargetRequestError>
+    {
+        let timeout = self.timeouts.read();
+
+        // Wait for a bit, to give whatever event is expected to change the
+        // level some time to happen.
+        time::sleep(timeout).await;
+
+        self.conn
+            .send(&HostToTarget::ReadAdc { channel }).await
+            .map_err(|err| TargetRequestError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let reply = self.conn.receive::<TargetToHost>(timeout, &mut buf).await
+            .map_err(|err| TargetRequestError::Receive(err))?;
+
+        match reply {
+            TargetToHost::AdcValue(value) => {
+                Ok(Millivolts::from_adc(value, 12, ADC_REFERENCE))
+            }
+            message => {
+                Err(
+                    TargetRequestError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Start an I2C transaction
+    ///
+    /// Writes `write` to the slave at the 7-bit `address`, then reads
+    /// `read_len` bytes from it, and returns those. Either part is skipped, if
+    /// it is empty. Both are limited to `I2C_BUF_CAP` bytes.
+    ///
+    /// Returns `TargetI2cError::Nack`, if the slave doesn't acknowledge, and
+    /// `TargetI2cError::Bus`, if the transaction fails for another reason.
+    pub async fn start_i2c_transaction(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        if address > 0x7f {
+            return Err(TargetI2cError::InvalidAddress);
+        }
+        if write.len() > I2C_BUF_CAP || read_len > I2C_BUF_CAP {
+            return Err(TargetI2cError::TooLong);
+        }
+
+        self.conn
+            .send(
+                &HostToTarget::StartI2cTransaction {
+                    mode: DmaMode::Regular,
+                    address,
+                    write,
+                    read_len: read_len as u16,
+                }
+            )
+            .await
+            .map_err(|err| TargetI2cError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetI2cError::Receive(err))?;
+
+        match message {
+            TargetToHost::I2cReply(reply) => {
+                Ok(reply.to_vec())
+            }
+            TargetToHost::I2cNack => {
+                Err(TargetI2cError::Nack)
+            }
+            TargetToHost::I2cError => {
+                Err(TargetI2cError::Bus)
+            }
+            message => {
+                Err(
+                    TargetI2cError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Start an SPI transaction
+    ///
+    /// Writes `write` to the slave, then reads `read_len` bytes from it, and
+    /// returns those. Either part is skipped, if it is empty. Both combined are
+    /// limited to `SPI_BUF_CAP` bytes.
+    pub async fn start_spi_transaction(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        if write.len() + read_len > SPI_BUF_CAP {
+            return Err(TargetSpiError::TooLong);
+        }
+
+        let mode = DmaMode::Regular;
+
+        self.conn
+            .send(
+                &HostToTarget::StartSpiTransaction {
+                    mode,
+                    write,
+                    read_len: read_len as u16,
+                }
+            )
+            .await
+            .map_err(|err| TargetSpiError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<TargetToHost>(timeout, &mut tmp).await
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiReplyBuffer(reply) => {
+                Ok(reply.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+impl HasTimeouts for AsyncTarget {
+    fn timeouts_mut(&mut self) -> &mut Timeouts {
+        &mut self.timeouts
+    }
+}
@@ -1,22 +1,39 @@
 /// Test-suite specific error module
 
 
-use host_lib::assistant::AssistantError;
+use host_lib::{
+    assistant::AssistantError,
+    report::MeasureError,
+};
+use test_cases::gpio::GpioConformanceError;
 
 use crate::{
     target::{
-        ReadAdcError,
+        TargetCapabilitiesError,
+        TargetGpioError,
         TargetI2cError,
+        TargetIrqStateError,
+        TargetNegotiateChecksumError,
+        TargetNegotiateCompressionError,
         TargetPinReadError,
+        TargetPingError,
+        TargetQuiesceError,
+        TargetRequestError,
+        TargetSetDacValueError,
         TargetSetPinHighError,
         TargetSetPinLowError,
         TargetSpiError,
         TargetStartPwmSignalError,
         TargetStartTimerInterruptError,
         TargetUsartSendError,
+        TargetUsartThroughputError,
         TargetUsartWaitError,
+        TargetVersionError,
+    },
+    test_stand::{
+        TestStandFinishError,
+        TestStandInitError,
     },
-    test_stand::TestStandInitError,
 };
 
 
@@ -28,16 +45,28 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Assistant(AssistantError),
-    ReadAdc(ReadAdcError),
+    GpioConformance(GpioConformanceError<TargetGpioError>),
+    Measure(MeasureError),
+    TargetCapabilities(TargetCapabilitiesError),
     TargetI2c(TargetI2cError),
+    TargetIrqState(TargetIrqStateError),
+    TargetNegotiateChecksum(TargetNegotiateChecksumError),
+    TargetNegotiateCompression(TargetNegotiateCompressionError),
     TargetPinRead(TargetPinReadError),
+    TargetPing(TargetPingError),
+    TargetQuiesce(TargetQuiesceError),
+    TargetRequest(TargetRequestError),
+    TargetSetDacValue(TargetSetDacValueError),
     TargetSetPinHigh(TargetSetPinHighError),
     TargetSetPinLow(TargetSetPinLowError),
     TargetSpi(TargetSpiError),
     TargetStartPwmSignal(TargetStartPwmSignalError),
     TargetStartTimerInterrupt(TargetStartTimerInterruptError),
     TargetUsartSend(TargetUsartSendError),
+    TargetUsartThroughput(TargetUsartThroughputError),
     TargetUsartWait(TargetUsartWaitError),
+    TargetVersion(TargetVersionError),
+    TestStandFinish(TestStandFinishError),
     TestStandInit(TestStandInitError),
 }
 
@@ -47,9 +76,15 @@ impl From<AssistantError> for Error {
     }
 }
 
-impl From<ReadAdcError> for Error {
-    fn from(err: ReadAdcError) -> Self {
-        Self::ReadAdc(err)
+impl From<GpioConformanceError<TargetGpioError>> for Error {
+    fn from(err: GpioConformanceError<TargetGpioError>) -> Self {
+        Self::GpioConformance(err)
+    }
+}
+
+impl From<MeasureError> for Error {
+    fn from(err: MeasureError) -> Self {
+        Self::Measure(err)
     }
 }
 
@@ -59,12 +94,60 @@ impl From<TargetI2cError> for Error {
     }
 }
 
+impl From<TargetCapabilitiesError> for Error {
+    fn from(err: TargetCapabilitiesError) -> Self {
+        Self::TargetCapabilities(err)
+    }
+}
+
+impl From<TargetNegotiateChecksumError> for Error {
+    fn from(err: TargetNegotiateChecksumError) -> Self {
+        Self::TargetNegotiateChecksum(err)
+    }
+}
+
+impl From<TargetNegotiateCompressionError> for Error {
+    fn from(err: TargetNegotiateCompressionError) -> Self {
+        Self::TargetNegotiateCompression(err)
+    }
+}
+
 impl From<TargetPinReadError> for Error {
     fn from(err: TargetPinReadError) -> Self {
         Self::TargetPinRead(err)
     }
 }
 
+impl From<TargetIrqStateError> for Error {
+    fn from(err: TargetIrqStateError) -> Self {
+        Self::TargetIrqState(err)
+    }
+}
+
+impl From<TargetPingError> for Error {
+    fn from(err: TargetPingError) -> Self {
+        Self::TargetPing(err)
+    }
+}
+
+impl From<TargetQuiesceError> for Error {
+    fn from(err: TargetQuiesceError) -> Self {
+        Self::TargetQuiesce(err)
+    }
+}
+
+impl From<TargetRequestError> for Error {
+    fn from(err: TargetRequestError) -> Self {
+        Self::TargetRequest(err)
+    }
+}
+
+impl From<TargetSetDacValueError> for Error {
+    fn from(err: TargetSetDacValueError) -> Self {
+        Self::TargetSetDacValue(err)
+    }
+}
+
 impl From<TargetSetPinHighError> for Error {
     fn from(err: TargetSetPinHighError) -> Self {
         Self::TargetSetPinHigh(err)
@@ -101,12 +184,30 @@ impl From<TargetStartTimerInterruptError> for Error {
     }
 }
 
+impl From<TargetUsartThroughputError> for Error {
+    fn from(err: TargetUsartThroughputError) -> Self {
+        Self::TargetUsartThroughput(err)
+    }
+}
+
 impl From<TargetUsartWaitError> for Error {
     fn from(err: TargetUsartWaitError) -> Self {
         Self::TargetUsartWait(err)
     }
 }
 
+impl From<TargetVersionError> for Error {
+    fn from(err: TargetVersionError) -> Self {
+        Self::TargetVersion(err)
+    }
+}
+
+impl From<TestStandFinishError> for Error {
+    fn from(err: TestStandFinishError) -> Self {
+        Self::TestStandFinish(err)
+    }
+}
+
 impl From<TestStandInitError> for Error {
     fn from(err: TestStandInitError) -> Self {
         Self::TestStandInit(err)
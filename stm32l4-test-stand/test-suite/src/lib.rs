@@ -2,9 +2,13 @@
 
 
 pub mod error;
+pub mod prelude;
 pub mod target;
 pub mod test_stand;
 
+#[cfg(feature = "tokio")]
+pub mod async_target;
+
 
 pub use self::{
     error::{
@@ -13,3 +17,6 @@ pub use self::{
     },
     test_stand::TestStand,
 };
+
+#[cfg(feature = "tokio")]
+pub use self::async_target::AsyncTarget;
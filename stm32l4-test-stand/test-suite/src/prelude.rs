@@ -0,0 +1,28 @@
+//! Convenient imports for test cases
+//!
+//! Includes everything from `host_lib::prelude`, as well as the types of this
+//! test suite. Import them all at once:
+//!
+//! ```
+//! use stm32l4_test_suite::prelude::*;
+//! ```
+
+
+pub use host_lib::prelude::*;
+
+pub use crate::{
+    Result,
+    target::{
+        ADC_IN,
+        ADC_REFERENCE,
+        DAC_LOOPBACK,
+        DAC_OUT,
+        GPIO_IN,
+        GPIO_OUT,
+        Target,
+    },
+    test_stand::{
+        TestStand,
+        TestStandBuilder,
+    },
+};
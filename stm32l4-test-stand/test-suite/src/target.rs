@@ -1,232 +1,294 @@
 use std::{
-    thread::sleep,
-    time::{
-        Duration,
-        Instant,
+    ops::{
+        Deref,
+        DerefMut,
     },
+    thread::sleep,
+    time::Duration,
 };
 
 use host_lib::{
+    config::Timeouts,
     conn::{
         Conn,
         ConnReceiveError,
         ConnSendError,
     },
-    pin::{
-        Pin,
-        ReadLevelError,
+    target::{
+        self,
+        Board,
+        HasTimeouts,
+        Reply,
+        Request,
+        WithTimeouts,
     },
 };
+use test_cases::gpio::GpioTarget;
 use lpc845_messages::{
+    Capabilities,
     DmaMode,
+    FaultKind,
     HostToTarget,
+    I2C_BUF_CAP,
+    IrqState,
+    SPI_BUF_CAP,
+    TargetRequests,
     TargetToHost,
-    UsartMode,
     pin,
+    units::{
+        Microseconds,
+        Millivolts,
+    },
 };
 
+pub use host_lib::target::{
+    TargetCapabilitiesError,
+    TargetIrqStateError,
+    TargetNegotiateChecksumError,
+    TargetNegotiateCompressionError,
+    TargetPinReadError,
+    TargetPingError,
+    TargetQuiesceOnInterruptError,
+    TargetRequestError,
+    TargetSetPinHighError,
+    TargetSetPinLowError,
+    TargetStartTimerInterruptError,
+    TargetUsartSendError,
+    TargetUsartThroughputError,
+    TargetUsartWaitError,
+    TargetVersionError,
+    Version,
+};
 
-/// The connection to the test target
-pub struct Target {
-    conn: Conn,
-    pin: Pin<()>,
-}
 
-impl Target {
-    pub(crate) fn new(conn: Conn) -> Self {
-        Self {
-            conn,
-            pin: Pin::new(()),
-        }
-    }
+/// Returned by [`host_lib::target::Target::quiesce`]
+pub type TargetQuiesceError = target::TargetQuiesceError<FaultKind>;
 
-    /// Instruct the target to set a GPIO pin high
-    pub fn set_pin_high(&mut self) -> Result<(), TargetSetPinHighError> {
-        self.pin
-            .set_level::<HostToTarget>(
-                pin::Level::High,
-                &mut self.conn,
-            )
-            .map_err(|err| TargetSetPinHighError(err))
-    }
 
-    /// Instruct the target to set a GPIO pin low
-    pub fn set_pin_low(&mut self) -> Result<(), TargetSetPinLowError> {
-        self.pin
-            .set_level::<HostToTarget>(
-                pin::Level::Low,
-                &mut self.conn,
-            )
-            .map_err(|err| TargetSetPinLowError(err))
-    }
+/// The target pin that is wired to an input of the assistant (PC1)
+pub const GPIO_OUT: pin::PortPin = pin::PortPin::new(2, 1);
 
-    /// Indicates whether the input pin is set high
-    ///
-    /// Uses `pin_state` internally.
-    pub fn pin_is_high(&mut self) -> Result<bool, TargetPinReadError> {
-        let pin_state = self.pin.read_level::<HostToTarget, TargetToHost>(
-            Duration::from_millis(10),
-            &mut self.conn,
-        )?;
-        Ok(pin_state.0 == pin::Level::High)
-    }
+/// The target pin that is wired to an output of the assistant (PC2)
+pub const GPIO_IN: pin::PortPin = pin::PortPin::new(2, 2);
 
-    /// Indicates whether the input pin is set low
-    ///
-    /// Uses `pin_state` internally.
-    pub fn pin_is_low(&mut self) -> Result<bool, TargetPinReadError> {
-        let pin_state = self.pin.read_level::<HostToTarget, TargetToHost>(
-            Duration::from_millis(10),
-            &mut self.conn,
-        )?;
-        Ok(pin_state.0 == pin::Level::Low)
-    }
+/// The target's ADC channel that is wired to an output of the assistant
+///
+/// ADC1_IN1, on PC0.
+pub const ADC_IN: u8 = 1;
 
-    /// Instruct the target to send this message via USART
-    pub fn send_usart(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
-    {
-        self.conn
-            .send(&HostToTarget::SendUsart { mode: UsartMode::Regular, data })
-            .map_err(|err| TargetUsartSendError(err))
-    }
+/// The target's ADC channel that is wired to its own DAC output
+///
+/// ADC1_IN4, on PC3. It is connected to `DAC_OUT`.
+pub const DAC_LOOPBACK: u8 = 4;
 
-    /// Instruct the target to send this message via USART using DMA
-    pub fn send_usart_dma(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
-    {
-        self.conn
-            .send(&HostToTarget::SendUsart { mode: UsartMode::Dma, data })
-            .map_err(|err| TargetUsartSendError(err))
-    }
+/// The target's DAC channel
+///
+/// DAC1_OUT1, on PA4.
+pub const DAC_OUT: u8 = 1;
 
-    /// Instruct the target to send this message via USART using DMA
-    pub fn send_usart_with_flow_control(&mut self, data: &[u8])
-        -> Result<(), TargetUsartSendError>
-    {
-        self.conn
-            .send(&HostToTarget::SendUsart {
-                mode: UsartMode::FlowControl,
-                data,
-            })
-            .map_err(|err| TargetUsartSendError(err))
-    }
+/// The reference voltage of the target's ADC
+///
+/// The Nucleo board connects VREF+ to its 3.3 V supply.
+pub const ADC_REFERENCE: Millivolts = Millivolts(3300);
 
-    /// Wait to receive the provided data via USART
-    ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn wait_for_usart_rx(&mut self, data: &[u8], timeout: Duration)
-        -> Result<Vec<u8>, TargetUsartWaitError>
-    {
-        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Regular)
-    }
 
-    /// Wait to receive the provided data via USART/DMA
-    ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn wait_for_usart_rx_dma(&mut self, data: &[u8], timeout: Duration)
-        -> Result<Vec<u8>, TargetUsartWaitError>
-    {
-        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Dma)
-    }
+/// The STM32L433 Nucleo board, as used in this test stand
+///
+/// Connects the API shared between test stands to the messages of this one.
+/// See [`host_lib::target`].
+#[derive(Debug)]
+pub struct Stm32l4;
 
-    fn wait_for_usart_rx_inner(&mut self,
-        data:          &[u8],
-        timeout:       Duration,
-        expected_mode: UsartMode,
-    )
-        -> Result<Vec<u8>, TargetUsartWaitError>
-    {
-        let mut buf   = Vec::new();
-        let     start = Instant::now();
+impl Board for Stm32l4 {
+    type Request<'r> = HostToTarget<'r>;
+    type Reply<'r>   = TargetToHost<'r>;
 
-        loop {
-            if buf.windows(data.len()).any(|window| window == data) {
-                return Ok(buf);
+    type Capabilities = Capabilities;
+    type IrqState     = IrqState;
+    type Fault        = FaultKind;
+
+    fn request<'r>(request: Request<'r>) -> Self::Request<'r> {
+        match request {
+            Request::NegotiateCompression(compression) => {
+                HostToTarget::NegotiateCompression(compression)
             }
-            if start.elapsed() > timeout {
-                return Err(TargetUsartWaitError::Timeout);
+            Request::NegotiateChecksum(checksum) => {
+                HostToTarget::NegotiateChecksum(checksum)
             }
+            Request::GetCapabilities => HostToTarget::GetCapabilities,
+            Request::GetVersion      => HostToTarget::GetVersion,
+            Request::GetIrqState     => HostToTarget::GetIrqState,
+            Request::Ping            => HostToTarget::Ping,
+            Request::Quiesce         => HostToTarget::Quiesce,
+            Request::SendUsart { mode, data } => {
+                HostToTarget::SendUsart { mode, data }
+            }
+            Request::StartTimerInterrupt { period } => {
+                HostToTarget::StartTimerInterrupt { period }
+            }
+            Request::StopTimerInterrupt => HostToTarget::StopTimerInterrupt,
+            Request::StartUsartThroughputTest { bytes, chunk_size } => {
+                HostToTarget::StartUsartThroughputTest { bytes, chunk_size }
+            }
+        }
+    }
 
-            let mut tmp = Vec::new();
-            let message = self.conn
-                .receive::<TargetToHost>(timeout, &mut tmp)
-                .map_err(|err| TargetUsartWaitError::Receive(err))?;
-
-            match message {
-                TargetToHost::UsartReceive { mode, data }
-                    if mode == expected_mode =>
-                {
-                    buf.extend(data)
-                }
-                message => {
-                    return Err(
-                        TargetUsartWaitError::UnexpectedMessage(
-                            format!("{:?}", message)
-                        )
-                    );
+    fn reply<'r>(reply: Self::Reply<'r>)
+        -> Result<Reply<'r, Self>, Self::Reply<'r>>
+    {
+        let reply = match reply {
+            TargetToHost::CompressionNegotiated(compression) => {
+                Reply::CompressionNegotiated(compression)
+            }
+            TargetToHost::ChecksumNegotiated(checksum) => {
+                Reply::ChecksumNegotiated(checksum)
+            }
+            TargetToHost::Capabilities(capabilities) => {
+                Reply::Capabilities(capabilities)
+            }
+            TargetToHost::Version {
+                protocol,
+                firmware_hash,
+                hal_version,
+                features,
+            } => {
+                Reply::Version {
+                    protocol,
+                    firmware_hash,
+                    hal_version,
+                    features,
                 }
             }
-        }
+            TargetToHost::IrqState(state) => Reply::IrqState(state),
+            TargetToHost::Pong            => Reply::Pong,
+            TargetToHost::Quiesced        => Reply::Quiesced,
+            TargetToHost::Fault(kind)     => Reply::Fault(kind),
+            TargetToHost::UsartReceive { mode, data, .. } => {
+                Reply::UsartReceive { mode, data }
+            }
+            TargetToHost::Log { level, message } => {
+                Reply::Log { level, message }
+            }
+            TargetToHost::BufferOverrun { dropped } => {
+                Reply::BufferOverrun { dropped }
+            }
+            TargetToHost::UsartThroughputSent => Reply::UsartThroughputSent,
+            reply => {
+                return Err(reply);
+            }
+        };
+
+        Ok(reply)
     }
+}
+
 
-    pub fn read_adc(&mut self) -> Result<u16, ReadAdcError> {
-        let timeout = Duration::from_millis(10);
+/// The connection to the test target
+///
+/// Dereferences to [`host_lib::target::Target`], which provides the API that
+/// is shared with other test stands. The methods of this type are specific to
+/// this test stand.
+pub struct Target(target::Target<Stm32l4>);
+
+impl Target {
+    pub(crate) fn new(conn: Conn) -> Self {
+        Self(target::Target::new(conn))
+    }
+
+    /// Replace the timeouts for a single call
+    ///
+    /// Like `host_lib::target::Target::with_timeouts`, but the returned guard
+    /// dereferences to this type, so it also applies to `read_adc`.
+    pub fn with_timeouts(&mut self, f: impl FnOnce(Timeouts) -> Timeouts)
+        -> WithTimeouts<'_, Self>
+    {
+        WithTimeouts::new(self, f)
+    }
+
+    /// Read the voltage on an ADC channel
+    ///
+    /// Converts the 12-bit result of the conversion using `ADC_REFERENCE`. The
+    /// target only supports `ADC_IN` and `DAC_LOOPBACK`. Uses the read timeout
+    /// (see `Timeouts::read`).
+    pub fn read_adc(&mut self, channel: u8)
+        -> Result<Millivolts, TargetRequestError>
+    {
+        let timeout = self.timeouts().read();
 
         // Wait for a bit, to give whatever event is expected to change the
         // level some time to happen.
         sleep(timeout);
 
-        self.conn
-            .send(&HostToTarget::ReadAdc)
-            .map_err(|err| ReadAdcError::Send(err))?;
+        let value = self.read_adc_value(channel, timeout)?;
+        Ok(Millivolts::from_adc(value, 12, ADC_REFERENCE))
+    }
 
-        let mut buf = Vec::new();
-        let reply = self.conn.receive::<TargetToHost>(timeout, &mut buf)
-            .map_err(|err| ReadAdcError::Receive(err))?;
+    /// Set the output of a DAC channel
+    ///
+    /// `value` is the raw 12-bit value that is written to the DAC. The target
+    /// only supports `DAC_OUT`.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `value` doesn't fit into 12 bits.
+    pub fn set_dac_value(&mut self, channel: u8, value: u16)
+        -> Result<(), TargetSetDacValueError>
+    {
+        assert!(value < 1 << 12);
 
-        match reply {
-            TargetToHost::AdcValue(value) => {
-                Ok(value)
-            }
-            message => {
-                Err(
-                    ReadAdcError::UnexpectedMessage(
-                        format!("{:?}", message)
-                    )
-                )
-            }
-        }
+        self.conn()
+            .send(&HostToTarget::SetDacValue { channel, value })
+            .map_err(|err| TargetSetDacValueError(err))
     }
 
     /// Start an I2C transaction
     ///
-    /// Sends the provided `data` and returns the reply.
-    pub fn start_i2c_transaction(&mut self, data: u8, timeout: Duration)
-        -> Result<u8, TargetI2cError>
+    /// Writes `write` to the slave at the 7-bit `address`, then reads
+    /// `read_len` bytes from it, and returns those. Either part is skipped, if
+    /// it is empty. Both are limited to `I2C_BUF_CAP` bytes.
+    ///
+    /// Returns `TargetI2cError::Nack`, if the slave doesn't acknowledge, and
+    /// `TargetI2cError::Bus`, if the transaction fails for another reason.
+    pub fn start_i2c_transaction(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
     {
-        let address = 0x48;
+        if address > 0x7f {
+            return Err(TargetI2cError::InvalidAddress);
+        }
+        if write.len() > I2C_BUF_CAP || read_len > I2C_BUF_CAP {
+            return Err(TargetI2cError::TooLong);
+        }
 
-        self.conn
+        self.conn()
             .send(
                 &HostToTarget::StartI2cTransaction {
                     mode: DmaMode::Regular,
                     address,
-                    data,
+                    write,
+                    read_len: read_len as u16,
                 }
             )
             .map_err(|err| TargetI2cError::Send(err))?;
 
         let mut tmp = Vec::new();
-        let message = self.conn
+        let message = self.conn()
             .receive::<TargetToHost>(timeout, &mut tmp)
             .map_err(|err| TargetI2cError::Receive(err))?;
 
         match message {
             TargetToHost::I2cReply(reply) => {
-                Ok(reply)
+                Ok(reply.to_vec())
+            }
+            TargetToHost::I2cNack => {
+                Err(TargetI2cError::Nack)
+            }
+            TargetToHost::I2cError => {
+                Err(TargetI2cError::Bus)
             }
             message => {
                 Err(
@@ -240,26 +302,37 @@ impl Target {
 
     /// Start an SPI transaction
     ///
-    /// Sends the provided `data` and returns the reply.
-    pub fn start_spi_transaction(&mut self, data: u8, timeout: Duration)
-        -> Result<u8, TargetSpiError>
+    /// Writes `write` to the slave, then reads `read_len` bytes from it, and
+    /// returns those. Either part is skipped, if it is empty. Both combined are
+    /// limited to `SPI_BUF_CAP` bytes.
+    pub fn start_spi_transaction(&mut self,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
     {
-        self.conn
+        if write.len() + read_len > SPI_BUF_CAP {
+            return Err(TargetSpiError::TooLong);
+        }
+
+        self.conn()
             .send(
                 &HostToTarget::StartSpiTransaction {
                     mode: DmaMode::Regular,
-                    data,
+                    write,
+                    read_len: read_len as u16,
                 }
             )
             .map_err(|err| TargetSpiError::Send(err))?;
 
         let mut tmp = Vec::new();
-        let message = self.conn.receive::<TargetToHost>(timeout, &mut tmp)
+        let message = self.conn().receive::<TargetToHost>(timeout, &mut tmp)
             .map_err(|err| TargetSpiError::Receive(err))?;
 
         match message {
-            TargetToHost::SpiReply(reply) => {
-                Ok(reply)
+            TargetToHost::SpiReplyBuffer(reply) => {
+                Ok(reply.to_vec())
             }
             message => {
                 Err(
@@ -271,92 +344,117 @@ impl Target {
         }
     }
 
-    /// Start a timer interrupt with the given period in milliseconds
-    pub fn start_timer_interrupt(&mut self, period_ms: u32)
-        -> Result<TimerInterrupt, TargetStartTimerInterruptError>
-    {
-        self.conn
-            .send(&HostToTarget::StartTimerInterrupt { period_ms })
-            .map_err(|err| TargetStartTimerInterruptError(err))?;
-
-        Ok(TimerInterrupt(self))
-    }
-
-    /// Start a PWM signal with the given period in milliseconds
+    /// Start a 50 Hz PWM signal with a duty cycle of 50%
+    ///
+    /// The target can't change the frequency of the signal.
     pub fn start_pwm_signal(&mut self)
         -> Result<PwmSignal, TargetStartPwmSignalError>
     {
-        self.conn
-            .send(&HostToTarget::StartPwmSignal)
+        self.conn()
+            .send(&HostToTarget::StartPwmSignal {
+                period:        Microseconds(20_000),
+                duty_permille: 500,
+            })
             .map_err(|err| TargetStartPwmSignalError(err))?;
 
         Ok(PwmSignal(self))
     }
 }
 
+impl Deref for Target {
+    type Target = target::Target<Stm32l4>;
 
-/// Represent a timer interrupt that's currently configured on the target
-///
-/// This timer interrupt will be stopped when this struct is dropped.
-pub struct TimerInterrupt<'r>(&'r mut Target);
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
 
-impl Drop for TimerInterrupt<'_> {
-    fn drop(&mut self) {
-        (self.0).conn.send(&HostToTarget::StopTimerInterrupt)
-            .unwrap()
+impl DerefMut for Target {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
     }
 }
 
-/// Represent an ongoing PWM signal that's currently configured on the target
-///
-/// This PWM signal will be stopped when this struct is dropped.
-pub struct PwmSignal<'r>(&'r mut Target);
+impl HasTimeouts for Target {
+    fn timeouts_mut(&mut self) -> &mut Timeouts {
+        self.0.timeouts_mut()
+    }
+}
 
-impl Drop for PwmSignal<'_> {
-    fn drop(&mut self) {
-        (self.0).conn.send(&HostToTarget::StopPwmSignal)
-            .unwrap()
+impl TargetRequests for Target {
+    fn conn(&mut self) -> &mut Conn {
+        self.0.conn()
     }
 }
 
+impl GpioTarget for Target {
+    type Error = TargetGpioError;
 
-#[derive(Debug)]
-pub struct TargetSetPinHighError(ConnSendError);
+    fn set_output(&mut self, level: pin::Level) -> Result<(), Self::Error> {
+        match level {
+            pin::Level::High => {
+                self.set_pin_high(GPIO_OUT)
+                    .map_err(|err| TargetGpioError::SetPinHigh(err))
+            }
+            pin::Level::Low => {
+                self.set_pin_low(GPIO_OUT)
+                    .map_err(|err| TargetGpioError::SetPinLow(err))
+            }
+        }
+    }
 
-#[derive(Debug)]
-pub struct TargetSetPinLowError(ConnSendError);
+    fn read_input(&mut self) -> Result<pin::Level, Self::Error> {
+        let is_high = self.pin_is_high(GPIO_IN)
+            .map_err(|err| TargetGpioError::PinRead(err))?;
 
-#[derive(Debug)]
-pub struct TargetPinReadError(ReadLevelError);
+        if is_high {
+            Ok(pin::Level::High)
+        }
+        else {
+            Ok(pin::Level::Low)
+        }
+    }
+
+    fn host_link_uses_pins(&mut self) -> Result<bool, Self::Error> {
+        let timeout = self.timeouts().reply();
+        let host_link = self.capabilities(timeout)
+            .map_err(|err| TargetGpioError::Capabilities(err))?
+            .host_link;
 
-impl From<ReadLevelError> for TargetPinReadError {
-    fn from(err: ReadLevelError) -> Self {
-        Self(err)
+        Ok(host_link.uses_pin(GPIO_OUT) || host_link.uses_pin(GPIO_IN))
     }
 }
 
 
-#[derive(Debug)]
-pub struct TargetUsartSendError(ConnSendError);
+/// Represent an ongoing PWM signal that's currently configured on the target
+///
+/// This PWM signal will be stopped when this struct is dropped.
+pub struct PwmSignal<'r>(&'r mut Target);
 
-#[derive(Debug)]
-pub enum TargetUsartWaitError {
-    Receive(ConnReceiveError),
-    Timeout,
-    UnexpectedMessage(String),
+impl Drop for PwmSignal<'_> {
+    fn drop(&mut self) {
+        (self.0).conn().send(&HostToTarget::StopPwmSignal)
+            .unwrap()
+    }
 }
 
+
 #[derive(Debug)]
-pub enum ReadAdcError {
-    Send(ConnSendError),
-    Receive(ConnReceiveError),
-    UnexpectedMessage(String)
+pub enum TargetGpioError {
+    SetPinHigh(TargetSetPinHighError),
+    SetPinLow(TargetSetPinLowError),
+    PinRead(TargetPinReadError),
+    Capabilities(TargetCapabilitiesError),
 }
 
 #[derive(Debug)]
 pub enum TargetI2cError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+    InvalidAddress,
+    Nack,
+    Bus,
+    TooLong,
     UnexpectedMessage(String),
 }
 
@@ -364,11 +462,12 @@ pub enum TargetI2cError {
 pub enum TargetSpiError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+    TooLong,
     UnexpectedMessage(String),
 }
 
 #[derive(Debug)]
-pub struct TargetStartTimerInterruptError(ConnSendError);
+pub struct TargetStartPwmSignalError(ConnSendError);
 
 #[derive(Debug)]
-pub struct TargetStartPwmSignalError(ConnSendError);
+pub struct TargetSetDacValueError(pub(crate) ConnSendError);
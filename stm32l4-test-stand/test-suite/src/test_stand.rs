@@ -1,24 +1,79 @@
-use std::sync::{
-    LockResult,
-    MutexGuard,
+use std::{
+    collections::BTreeMap,
+    fmt,
+    iter,
+    path::PathBuf,
+    sync::{
+        LockResult,
+        MutexGuard,
+    },
+    thread,
 };
 
 use host_lib::{
     Assistant,
+    Conn,
+    config::{
+        Features,
+        Timeouts,
+    },
+    lock::DeviceLock,
+    signal::Registration,
     test_stand::NotConfiguredError,
+    tolerances::Tolerances,
+    trace,
+};
+use lpc845_messages::{
+    PROTOCOL_VERSION,
+    checksum::Checksum,
+    compress::Compression,
 };
 
-use crate::target::Target;
+use crate::target::{
+    Target,
+    TargetNegotiateChecksumError,
+    TargetNegotiateCompressionError,
+    TargetQuiesceError,
+    TargetQuiesceOnInterruptError,
+    TargetVersionError,
+    Version,
+};
 
 
 /// An instance of the test stand
 ///
 /// Used to access all resources that a test case requires.
 pub struct TestStand {
-    _guard: LockResult<MutexGuard<'static, ()>>,
+    // Declared before the guard, so the registrations are dropped first. They
+    // keep the connections to the targets open.
+    _interrupt: Vec<Registration>,
+    _guard:     LockResult<MutexGuard<'static, ()>>,
+    _trace:     trace::Guard,
 
     pub target:    Target,
     pub assistant: Assistant,
+
+    /// The additional test targets, by name
+    ///
+    /// Configured in the `[targets]` table of `test-stand.toml`. Each of them
+    /// has been checked and set up like `target`.
+    pub targets: BTreeMap<String, Target>,
+
+    /// The additional test assistants, by name
+    ///
+    /// Configured in the `[assistants]` table of `test-stand.toml`.
+    pub assistants: BTreeMap<String, Assistant>,
+
+    tolerances: Tolerances,
+    timeouts:   Timeouts,
+    features:   Features,
+
+    // Set by `finish`, so `drop` doesn't quiesce the targets a second time.
+    finished: bool,
+
+    // Declared last, so the locks are released after all connections have
+    // been closed.
+    _locks: Vec<DeviceLock>,
 }
 
 impl TestStand {
@@ -27,24 +82,234 @@ impl TestStand {
     /// Reads the `test-stand.toml` configuration file and initializes test
     /// stand resources, as configured in there.
     pub fn new() -> Result<Self, TestStandInitError> {
-        let test_stand = host_lib::TestStand::new()
+        Self::builder().build()
+    }
+
+    /// Start building a `TestStand` instance
+    pub fn builder() -> TestStandBuilder {
+        TestStandBuilder {
+            inner: host_lib::TestStand::builder().require_assistant(),
+        }
+    }
+
+    /// The tolerances that test cases should apply on this test stand
+    ///
+    /// Configured in the `tolerances` section of `test-stand.toml`.
+    pub fn tolerances(&self) -> &Tolerances {
+        &self.tolerances
+    }
+
+    /// The timeouts that test cases should use on this test stand
+    ///
+    /// Configured in the `timeouts` section of `test-stand.toml`.
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// What the hardware of this test stand provides
+    ///
+    /// Configured in the `features` section of `test-stand.toml`. Test cases
+    /// that need a feature should be skipped, if it is missing.
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
+
+    /// Stop whatever the test case started, and report whether that worked
+    ///
+    /// Quiesces all targets. Call this at the end of a test case, to make it
+    /// fail, if a target wasn't left idle. Returns the error of the first
+    /// target that failed, after trying all of them.
+    ///
+    /// If this isn't called, the targets are still quiesced when the
+    /// `TestStand` is dropped, but failures are only printed there.
+    pub fn finish(mut self) -> Result<(), TestStandFinishError> {
+        self.finished = true;
+        self.quiesce()
+    }
+
+    fn quiesce(&mut self) -> Result<(), TestStandFinishError> {
+        let timeout = self.timeouts.reply();
+
+        let mut result = self.target.quiesce(timeout)
+            .map_err(|err| TestStandFinishError { target: None, err });
+        for (name, target) in &mut self.targets {
+            if let Err(err) = target.quiesce(timeout) {
+                if result.is_ok() {
+                    result = Err(
+                        TestStandFinishError {
+                            target: Some(name.clone()),
+                            err,
+                        }
+                    );
+                }
+            }
+        }
+
+        result
+    }
+}
+
+
+/// Builds a [`TestStand`] instance
+///
+/// Returned by [`TestStand::builder`]. The test assistant is always required.
+pub struct TestStandBuilder {
+    inner: host_lib::test_stand::TestStandBuilder,
+}
+
+impl TestStandBuilder {
+    /// Read the configuration from `path`, instead of `test-stand.toml`
+    pub fn config_path(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: self.inner.config_path(path),
+        }
+    }
+
+    /// Download firmware to its test node, before connecting to it
+    ///
+    /// `path` is the directory of the firmware crate, for example
+    /// `../test-target`.
+    pub fn flash_firmware(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: self.inner.flash_firmware(path),
+        }
+    }
+
+    /// Initialize the test stand
+    pub fn build(self) -> Result<TestStand, TestStandInitError> {
+        let test_stand = self.inner.build()
             .map_err(|err| TestStandInitError::Inner(err))?;
 
+        let compression = test_stand.compression;
+        let checksum    = test_stand.checksum;
+
+        let timeouts    = &test_stand.timeouts;
+
+        let target = init_target(
+            test_stand.target?,
+            compression,
+            checksum,
+            timeouts,
+        )?;
+
+        let mut targets = BTreeMap::new();
+        for (name, conn) in test_stand.targets {
+            let target = init_target(conn, compression, checksum, timeouts)?;
+            targets.insert(name, target);
+        }
+
+        let mut interrupt = Vec::new();
+        for target in iter::once(&target).chain(targets.values()) {
+            let registration = target.quiesce_on_interrupt()
+                .map_err(|err| TestStandInitError::QuiesceOnInterrupt(err))?;
+            interrupt.push(registration);
+        }
+
         Ok(
-            Self {
-                _guard:    test_stand.guard,
-                target:    Target::new(test_stand.target?),
-                assistant: test_stand.assistant?,
+            TestStand {
+                _interrupt: interrupt,
+                _guard:     test_stand.guard,
+                _trace:     test_stand.trace,
+                target,
+                assistant:  test_stand.assistant?,
+                targets,
+                assistants: test_stand.assistants,
+                tolerances: test_stand.tolerances,
+                timeouts:   test_stand.timeouts,
+                features:   test_stand.features,
+                finished:   false,
+                _locks:     test_stand.locks,
             }
         )
     }
 }
 
 
+impl Drop for TestStand {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        // Stop whatever the test case started, so the next test case doesn't
+        // have to deal with it. This runs before the guard is released, so the
+        // next test case can't interfere.
+        //
+        // Panicking here would obscure the original failure, if the test case
+        // already failed, and abort the test process on top of that. If it
+        // didn't fail, a failure is still recorded in its output. Test cases
+        // that want to fail because of it can call `finish`.
+        if let Err(err) = self.quiesce() {
+            if !thread::panicking() {
+                eprintln!("{}", err);
+            }
+        }
+    }
+}
+
+
+/// Check the target's firmware and negotiate the connection parameters
+fn init_target(
+    conn:        Conn,
+    compression: Compression,
+    checksum:    Checksum,
+    timeouts:    &Timeouts,
+)
+    -> Result<Target, TestStandInitError>
+{
+    let mut target = Target::new(conn);
+    target.set_timeouts(timeouts.clone());
+
+    // Make sure the firmware speaks the same protocol as this test suite,
+    // before sending anything else. Otherwise, a mismatch would show up as
+    // confusing errors later on.
+    let version = target.version(timeouts.negotiate())
+        .map_err(|err| TestStandInitError::Version(err))?;
+    if version.protocol != PROTOCOL_VERSION {
+        return Err(
+            TestStandInitError::ProtocolMismatch {
+                host:   PROTOCOL_VERSION,
+                target: version,
+            }
+        );
+    }
+
+    // Record which firmware the test case ran against. Cargo shows this next
+    // to the result of a failed test case, and in the output of passed ones,
+    // if asked to.
+    println!("Target firmware: {}", version);
+
+    if compression != Compression::None {
+        target
+            .negotiate_compression(compression, timeouts.negotiate())
+            .map_err(|err| TestStandInitError::Compression(err))?;
+    }
+    if checksum != Checksum::None {
+        target
+            .negotiate_checksum(checksum, timeouts.negotiate())
+            .map_err(|err| TestStandInitError::Checksum(err))?;
+    }
+
+    Ok(target)
+}
+
+
 #[derive(Debug)]
 pub enum TestStandInitError {
+    Checksum(TargetNegotiateChecksumError),
+    Compression(TargetNegotiateCompressionError),
     Inner(host_lib::test_stand::TestStandInitError),
     NotConfigured(NotConfiguredError),
+    QuiesceOnInterrupt(TargetQuiesceOnInterruptError),
+    Version(TargetVersionError),
+
+    /// The target firmware was built from a different version of the messages
+    ///
+    /// Re-upload the firmware, to make sure it matches the test suite.
+    ProtocolMismatch {
+        host:   u32,
+        target: Version,
+    },
 }
 
 impl From<NotConfiguredError> for TestStandInitError {
@@ -52,3 +317,25 @@ impl From<NotConfiguredError> for TestStandInitError {
         Self::NotConfigured(err)
     }
 }
+
+
+/// A target was not idle at the end of a test case
+///
+/// Returned by [`TestStand::finish`].
+#[derive(Debug)]
+pub struct TestStandFinishError {
+    /// The name of the target, or `None` for the main target
+    pub target: Option<String>,
+
+    pub err: TargetQuiesceError,
+}
+
+impl fmt::Display for TestStandFinishError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.target {
+            Some(name) => write!(f, "Target `{}`", name)?,
+            None       => write!(f, "Target")?,
+        }
+        write!(f, " not idle at end of test case: {:?}", self.err)
+    }
+}
@@ -1,25 +1,25 @@
 //! Test Suite for the ADC API in STM32L4xx HAL
 
 
-use stm32l4_test_suite::{
-    Result,
-    TestStand,
-};
+use stm32l4_test_suite::prelude::*;
 
 
 #[test]
 fn it_should_read_adc_values() -> Result {
     let mut test_stand = TestStand::new()?;
+    let tolerances = test_stand.tolerances().clone();
 
     test_stand.assistant.set_pin_5_low()?;
-    let value = test_stand.target.read_adc()?;
-    println!("value (low): {}", value);
-    assert!(value < 16);
+    let voltage = test_stand.target.read_adc(ADC_IN)?;
+    println!("voltage (low): {:?}", voltage);
+    let error_mv = f64::from(voltage.0);
+    assert!(error_mv <= tolerances.adc_offset_mv());
 
     test_stand.assistant.set_pin_5_high()?;
-    let value = test_stand.target.read_adc()?;
-    println!("value (high): {}", value);
-    assert!(value > 2u16.pow(12) - 128);
+    let voltage = test_stand.target.read_adc(ADC_IN)?;
+    println!("voltage (high): {:?}", voltage);
+    let error_mv = f64::from(ADC_REFERENCE.0 - voltage.0);
+    assert!(error_mv <= tolerances.adc_full_scale_error_mv());
 
     Ok(())
 }
@@ -0,0 +1,29 @@
+//! Test Suite for the DAC API in STM32L4xx HAL
+//!
+//! This test suite communicates with hardware. See README.md for wiring
+//! instructions.
+
+
+use lpc845_messages::units::Millivolts;
+use stm32l4_test_suite::prelude::*;
+
+
+#[test]
+fn it_should_output_dac_values_that_the_adc_reads_back() -> Result {
+    let mut test_stand = TestStand::new()?;
+    let tolerances = test_stand.tolerances().clone();
+
+    for &value in &[0, 1024, 2048, 3072, 4095] {
+        test_stand.target.set_dac_value(DAC_OUT, value)?;
+        let voltage = test_stand.target.read_adc(DAC_LOOPBACK)?;
+
+        let expected = Millivolts::from_adc(value, 12, ADC_REFERENCE);
+        let error_mv = f64::from(
+            Ord::max(voltage, expected).0 - Ord::min(voltage, expected).0
+        );
+        assert!(error_mv <= tolerances.adc_full_scale_error_mv(),
+            "{:?} != {:?}", voltage, expected);
+    }
+
+    Ok(())
+}
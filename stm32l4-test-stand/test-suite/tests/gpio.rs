@@ -7,6 +7,14 @@
 use stm32l4_test_suite::{
     Result,
     TestStand,
+    target::{
+        GPIO_IN,
+        GPIO_OUT,
+    },
+};
+use test_cases::gpio::{
+    self,
+    Bounds,
 };
 
 
@@ -14,10 +22,10 @@ use stm32l4_test_suite::{
 fn it_should_set_pin_level() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    test_stand.target.set_pin_low()?;
+    test_stand.target.set_pin_low(GPIO_OUT)?;
     assert!(test_stand.assistant.pin_is_low()?);
 
-    test_stand.target.set_pin_high()?;
+    test_stand.target.set_pin_high(GPIO_OUT)?;
     assert!(test_stand.assistant.pin_is_high()?);
 
     Ok(())
@@ -28,10 +36,37 @@ fn it_should_read_input_level() -> Result {
     let mut test_stand = TestStand::new()?;
 
     test_stand.assistant.set_pin_low()?;
-    assert!(test_stand.target.pin_is_low()?);
+    assert!(test_stand.target.pin_is_low(GPIO_IN)?);
 
     test_stand.assistant.set_pin_high()?;
-    assert!(test_stand.target.pin_is_high()?);
+    assert!(test_stand.target.pin_is_high(GPIO_IN)?);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_conform_to_gpio_latency_bounds() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Latencies are measured from the host, and every read of a pin level
+    // takes at least 10 ms, so the default bounds are generous.
+    let bounds = Bounds::from_tolerances(test_stand.tolerances());
+
+    let report = gpio::run(
+        &mut test_stand.target,
+        &mut test_stand.assistant,
+        &bounds,
+        10,
+    )?;
+
+    // `run` only skips the measurements, if the host link uses the pins under
+    // test. Then there's nothing to check.
+    if let Some(report) = report {
+        for latency in &[report.set, report.clear] {
+            assert!(latency.max <= bounds.output, "{:#?}", report);
+        }
+        assert!(report.input.max <= bounds.input, "{:#?}", report);
+    }
 
     Ok(())
 }
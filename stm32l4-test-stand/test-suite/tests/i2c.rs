@@ -6,21 +6,88 @@
 
 use std::time::Duration;
 
+use lpc845_messages::{
+    i2c::{
+        Nack,
+        SlaveConfig,
+    },
+    units::Microseconds,
+};
 use stm32l4_test_suite::{
     Result,
     TestStand,
+    target::TargetI2cError,
 };
 
 
+/// The address of the test assistant's I2C slave
+const ADDRESS: u8 = 0x48;
+
+
 #[test]
 fn it_should_start_a_transaction() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    let data = 0x22;
     let timeout = Duration::from_millis(50);
-    let reply = test_stand.target.start_i2c_transaction(data, timeout)?;
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+    test_stand.assistant
+        .write_i2c_registers(0x10, &[0x22, 0x44, 0x66], timeout)?;
+
+    let reply = test_stand.target
+        .start_i2c_transaction(ADDRESS, &[0x10], 3, timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44, 0x66]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_to_the_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+
+    // The first byte sets the register pointer.
+    let data = [0x10, 0x11, 0x22, 0x33];
+    test_stand.target
+        .start_i2c_transaction(ADDRESS, &data, 0, timeout)?;
+
+    let registers = test_stand.assistant
+        .read_i2c_registers(0x10, 3, timeout)?;
+    assert_eq!(registers, [0x11, 0x22, 0x33]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nack_from_a_missing_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.configure_i2c_slave(&config(), timeout)?;
+    test_stand.assistant.write_i2c_registers(0x00, &[0x22], timeout)?;
+
+    let data = [0x00];
+    let result = test_stand.target
+        .start_i2c_transaction(ADDRESS + 1, &data, 1, timeout);
+
+    assert!(matches!(result, Err(TargetI2cError::Nack)));
 
-    assert_eq!(reply, data << 1);
+    // The target must have recovered from the NACK.
+    let reply = test_stand.target
+        .start_i2c_transaction(ADDRESS, &data, 1, timeout)?;
+    assert_eq!(reply, [0x22]);
 
     Ok(())
 }
+
+
+/// The default configuration of the assistant's I2C slave
+fn config() -> SlaveConfig {
+    SlaveConfig {
+        address: ADDRESS,
+        nack:    Nack::None,
+        stretch: Microseconds(0),
+    }
+}
@@ -4,8 +4,12 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::{
+    thread::sleep,
+    time::Duration,
+};
 
+use lpc845_messages::InputPin;
 use stm32l4_test_suite::{
     Result,
     TestStand,
@@ -32,3 +36,27 @@ fn it_should_create_a_pwm_signal() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_create_a_pwm_signal_with_half_duty_cycle() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // When `_interrupt` is dropped, the PWM signal will be stopped.
+    let _interrupt = test_stand.target.start_pwm_signal()?;
+
+    // The target generates a 50 Hz signal. Monitor it for a few periods.
+    test_stand.assistant.start_pin_monitoring(InputPin::Pwm)?;
+    sleep(Duration::from_millis(120));
+    let signal = test_stand.assistant
+        .stop_pin_monitoring(InputPin::Pwm, Duration::from_millis(50))?;
+
+    assert!(signal.periods >= 4, "{:?}", signal);
+
+    let period = signal.period_secs().unwrap();
+    assert!((period - 0.02).abs() <= 0.002, "{:?}", signal);
+
+    let duty_cycle = signal.duty_cycle().unwrap();
+    assert!((duty_cycle - 0.5).abs() <= 0.05, "{:?}", signal);
+
+    Ok(())
+}
@@ -16,11 +16,68 @@ use stm32l4_test_suite::{
 fn it_should_start_a_transaction() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    let data = 0x22;
+    // The slave sends one byte of the response for every byte it receives,
+    // including those the target writes.
+    let response = [0x00, 0x00, 0x00, 0x22, 0x44, 0x66];
     let timeout = Duration::from_millis(50);
-    let reply = test_stand.target.start_spi_transaction(data, timeout)?;
+    test_stand.assistant.load_spi_response(&response, timeout)?;
 
-    assert_eq!(reply, data << 1);
+    let data = [0x11, 0x22, 0x33];
+    let reply = test_stand.target
+        .start_spi_transaction(&data, data.len(), timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44, 0x66]);
+
+    // The target sends `0xff` while reading.
+    let received = test_stand.assistant.spi_received(timeout)?;
+    assert_eq!(received, [0x11, 0x22, 0x33, 0xff, 0xff, 0xff]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_every_bit() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.load_spi_response(&[], timeout)?;
+
+    let data = [0x00, 0x01, 0x80, 0x55, 0xaa, 0xfe, 0x7f];
+    test_stand.target.start_spi_transaction(&data, 0, timeout)?;
+
+    let received = test_stand.assistant.spi_received(timeout)?;
+    assert_eq!(received, data);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_more_than_the_response() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Once the response is exhausted, the slave sends `0xff`.
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.load_spi_response(&[0x12, 0x34], timeout)?;
+
+    let reply = test_stand.target.start_spi_transaction(&[], 4, timeout)?;
+
+    assert_eq!(reply, [0x12, 0x34, 0xff, 0xff]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_start_every_transaction_at_the_start_of_the_response() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let response = [0x12, 0x34, 0x56];
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.load_spi_response(&response, timeout)?;
+
+    for _ in 0 .. 2 {
+        let reply = test_stand.target.start_spi_transaction(&[], 2, timeout)?;
+        assert_eq!(reply, [0x12, 0x34]);
+    }
 
     Ok(())
 }
@@ -16,16 +16,16 @@ use stm32l4_test_suite::{
 fn it_should_fire_regular_timer_interrupts() -> Result {
     let mut test_stand = TestStand::new()?;
 
-    let period_ms = 10;
+    let period = Duration::from_millis(10);
 
     // When `_interrupt` is dropped, the timer interrupt will be stopped.
-    let _interrupt = test_stand.target.start_timer_interrupt(period_ms)?;
+    let _interrupt = test_stand.target.start_timer_interrupt(period)?;
 
-    let timeout = Duration::from_millis((period_ms * 2).into());
+    let timeout = period * 2;
     let measurement = test_stand.assistant.measure_timer_interrupt(5, timeout)?;
 
-    let min_acceptable = Duration::from_millis((period_ms *  9/10).into());
-    let max_acceptable = Duration::from_millis((period_ms * 11/10).into());
+    let min_acceptable = period *  9/10;
+    let max_acceptable = period * 11/10;
 
     assert!(measurement.min >= min_acceptable);
     assert!(measurement.max <= max_acceptable);
@@ -3,6 +3,8 @@
 
 use std::time::Duration;
 
+use host_lib::report;
+use lpc845_messages::usart;
 use stm32l4_test_suite::{
     Result,
     TestStand,
@@ -24,6 +26,32 @@ fn it_should_send_messages() -> Result {
     Ok(())
 }
 
+#[test]
+fn it_should_send_at_the_full_baud_rate() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let bytes   = 4096;
+    let timeout = Duration::from_secs(1);
+    let count   = test_stand.target.measure_usart_throughput(
+        &mut test_stand.assistant,
+        bytes,
+        32,
+        timeout,
+    )?;
+
+    assert_eq!(count.bytes,   bytes, "{:?}", count);
+    assert_eq!(count.errors,  0,     "{:?}", count);
+    assert_eq!(count.dropped, 0,     "{:?}", count);
+
+    // Each byte takes 10 bits on the wire, with the default configuration.
+    let line_rate  = usart::Config::default().baud as f64 / 10.0;
+    let throughput = count.bytes_per_sec().unwrap();
+    report::measure("usart_throughput", throughput, "B/s")?;
+    assert!(throughput > line_rate * 0.95, "{} B/s, {:?}", throughput, count);
+
+    Ok(())
+}
+
 #[test]
 fn it_should_receive_messages() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -0,0 +1,84 @@
+//! Makes build metadata available to the firmware
+//!
+//! Sets the following environment variables:
+//! - `FIRMWARE_HASH`: The revision of the firmware source code
+//! - `HAL_VERSION`: The version of the HAL, as locked in `Cargo.lock`
+//! - `FIRMWARE_FEATURES`: The enabled Cargo features, separated by commas
+//!
+//! The firmware reports them to the host, as part of the version handshake.
+
+
+use std::{
+    env,
+    fs,
+    process::Command,
+};
+
+
+/// The name of the HAL crate, as listed in `Cargo.lock`
+const HAL: &str = "stm32l4xx-hal";
+
+
+fn main() {
+    let hash = Command::new("git")
+        .args(&["describe", "--always", "--dirty"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .unwrap_or_else(|| String::from("unknown"));
+
+    let hal_version = fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| locked_version(&lock, HAL))
+        .unwrap_or_else(|| String::from("unknown"));
+
+    // Cargo tells us about enabled features through environment variables,
+    // with the names converted to upper case and `-` replaced by `_`.
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=FIRMWARE_HASH={}", hash);
+    println!("cargo:rustc-env=HAL_VERSION={}", hal_version);
+    println!("cargo:rustc-env=FIRMWARE_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/index");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+}
+
+
+/// Find the version of package `name` in the contents of `Cargo.lock`
+///
+/// If the package comes from a Git repository, the version is followed by the
+/// abbreviated commit hash.
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|package| {
+            package.lines().any(|line| line == format!("name = \"{}\"", name))
+        })?;
+
+    let field = |key: &str| {
+        package.lines()
+            .find_map(|line| line.strip_prefix(key))
+            .map(|value| value.trim_matches('"'))
+    };
+
+    let version = field("version = ")?;
+
+    match field("source = ").and_then(|source| source.strip_prefix("git+")) {
+        Some(source) => {
+            let commit = source.rsplit('#').next()?;
+            Some(format!("{} ({})", version, &commit[..commit.len().min(7)]))
+        }
+        None => {
+            Some(version.to_owned())
+        }
+    }
+}
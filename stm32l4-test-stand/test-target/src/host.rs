@@ -0,0 +1,193 @@
+//! The link to the host
+//!
+//! By default, the host is connected to USART2, which the on-board programmer
+//! bridges to USB. If the `usb-host-link` feature is enabled, the target's own
+//! USB peripheral is used instead, as a CDC-ACM serial port. The rest of the
+//! firmware doesn't need to know which one it is.
+
+
+#[cfg(not(feature = "usb-host-link"))]
+use heapless::spsc;
+#[cfg(not(feature = "usb-host-link"))]
+use stm32l4xx_hal::{
+    prelude::*,
+    pac::USART2,
+    serial,
+};
+
+#[cfg(feature = "usb-host-link")]
+use stm32l4xx_hal::{
+    pac,
+    signature::Uid,
+    usb::UsbBusType,
+};
+#[cfg(feature = "usb-host-link")]
+use usb_device::{
+    UsbError,
+    bus::UsbBusAllocator,
+    prelude::*,
+};
+#[cfg(feature = "usb-host-link")]
+use usbd_serial::{
+    SerialPort,
+    USB_CLASS_CDC,
+};
+
+#[cfg(feature = "usb-host-link")]
+use lpc845_messages::{
+    USB_PID,
+    USB_VID,
+};
+
+
+/// The link to the host, over USART2
+#[cfg(not(feature = "usb-host-link"))]
+pub struct Host {
+    tx: serial::Tx<USART2>,
+    rx: spsc::Consumer<'static, u8, 256>,
+}
+
+#[cfg(not(feature = "usb-host-link"))]
+impl Host {
+    /// Create the host link
+    ///
+    /// `rx` receives the bytes that the USART2 interrupt handler reads.
+    pub fn new(
+        tx: serial::Tx<USART2>,
+        rx: spsc::Consumer<'static, u8, 256>,
+    )
+        -> Self
+    {
+        Self { tx, rx }
+    }
+
+    /// Return the next byte received from the host, if one is available
+    pub fn read(&mut self) -> Option<u8> {
+        self.rx.dequeue()
+    }
+
+    /// Send `buf` to the host, blocking until all of it has been sent
+    pub fn bwrite_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.tx.bwrite_all(buf)
+            .map_err(|err| Error::Usart(err))
+    }
+}
+
+
+/// The link to the host, over USB
+#[cfg(feature = "usb-host-link")]
+pub struct Host {
+    device: UsbDevice<'static, UsbBusType>,
+    serial: SerialPort<'static, UsbBusType>,
+}
+
+#[cfg(feature = "usb-host-link")]
+impl Host {
+    /// Create the host link
+    ///
+    /// `serial_number` identifies the target to the host. See
+    /// [`serial_number`].
+    pub fn new(
+        bus:           &'static UsbBusAllocator<UsbBusType>,
+        serial_number: &'static str,
+    )
+        -> Self
+    {
+        let serial = SerialPort::new(bus);
+        let device = UsbDeviceBuilder::new(bus, UsbVidPid(USB_VID, USB_PID))
+            .manufacturer("Braun Embedded")
+            .product("STM32L4 Test Target")
+            .serial_number(serial_number)
+            .device_class(USB_CLASS_CDC)
+            .build();
+
+        Self { device, serial }
+    }
+
+    /// Return the next byte received from the host, if one is available
+    ///
+    /// Also keeps the USB device going, so this needs to be called regularly.
+    pub fn read(&mut self) -> Option<u8> {
+        self.device.poll(&mut [&mut self.serial]);
+
+        let mut b = [0];
+        match self.serial.read(&mut b) {
+            Ok(1) => Some(b[0]),
+            _     => None,
+        }
+    }
+
+    /// Send `buf` to the host, blocking until all of it has been sent
+    ///
+    /// Blocks until the host has opened the serial port, if it hasn't yet.
+    pub fn bwrite_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            self.device.poll(&mut [&mut self.serial]);
+
+            match self.serial.write(buf) {
+                Ok(n)                     => buf = &buf[n..],
+                Err(UsbError::WouldBlock) => continue,
+                Err(err)                  => return Err(Error::Usb(err)),
+            }
+        }
+
+        loop {
+            self.device.poll(&mut [&mut self.serial]);
+
+            match self.serial.flush() {
+                Ok(())                    => return Ok(()),
+                Err(UsbError::WouldBlock) => continue,
+                Err(err)                  => return Err(Error::Usb(err)),
+            }
+        }
+    }
+}
+
+
+/// Error sending to the host
+#[derive(Debug)]
+pub enum Error {
+    #[cfg(not(feature = "usb-host-link"))]
+    Usart(serial::Error),
+
+    #[cfg(feature = "usb-host-link")]
+    Usb(UsbError),
+}
+
+
+/// Prepare the USB peripheral for use
+///
+/// Needs to be called after the clocks have been frozen with HSI48 enabled.
+/// Enables the clock recovery system, which trims HSI48 to the USB start of
+/// frame packets, and removes the power isolation of the USB transceiver.
+#[cfg(feature = "usb-host-link")]
+pub fn enable_usb() {
+    // Sound, as we're only modifying registers that the HAL doesn't touch after
+    // the clocks have been frozen, and nothing else is running yet.
+    let rcc = unsafe { &*pac::RCC::ptr() };
+    let crs = unsafe { &*pac::CRS::ptr() };
+    let pwr = unsafe { &*pac::PWR::ptr() };
+
+    rcc.apb1enr1.modify(|_, w| w.crsen().set_bit());
+    crs.cr.modify(|_, w| w.autotrimen().set_bit().cen().set_bit());
+
+    rcc.apb1enr1.modify(|_, w| w.pwren().set_bit());
+    pwr.cr2.modify(|_, w| w.usv().set_bit());
+}
+
+/// Derive the USB serial number from the unique ID of the chip
+///
+/// Formats the ID as hexadecimal digits into `buf`, so every target can be
+/// told apart by the host, using an address like `usb://1209:0001/<serial>`.
+#[cfg(feature = "usb-host-link")]
+pub fn serial_number(buf: &'static mut [u8; 24]) -> &'static str {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    for (i, &b) in Uid::as_bytes().iter().enumerate() {
+        buf[i * 2]     = DIGITS[usize::from(b >> 4)];
+        buf[i * 2 + 1] = DIGITS[usize::from(b & 0xf)];
+    }
+
+    // Can't fail, as only ASCII digits have been written.
+    core::str::from_utf8(buf).unwrap()
+}
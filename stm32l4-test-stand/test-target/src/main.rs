@@ -8,7 +8,11 @@
 extern crate panic_rtt_target;
 
 
+mod host;
+
+
 use cortex_m::peripheral::{
+    NVIC,
     SYST,
     syst::SystClkSource,
 };
@@ -29,6 +33,12 @@ use rtt_target::{
 use stm32l4xx_hal::{
     prelude::*,
     adc::ADC,
+    dac::{
+        self,
+        DacExt as _,
+        DacOut as _,
+        DacPin as _,
+    },
     delay::Delay,
     dma::{
         DMAFrame,
@@ -54,23 +64,21 @@ use stm32l4xx_hal::{
         PC0,
         PC1,
         PC2,
+        PC3,
         PC7,
         PushPull,
     },
-    i2c::I2c,
+    i2c::{
+        self,
+        I2c,
+    },
     pac::{
         self,
         I2C1,
         SPI2,
-        TIM1,
         USART1,
-        USART2,
         USART3,
     },
-    pwm::{
-        self,
-        Pwm,
-    },
     rcc::Clocks,
     serial::{
         self,
@@ -78,15 +86,53 @@ use stm32l4xx_hal::{
     },
     spi::Spi,
 };
+#[cfg(not(feature = "usb-host-link"))]
+use stm32l4xx_hal::{
+    pac::{
+        TIM1,
+        USART2,
+    },
+    pwm::{
+        self,
+        Pwm,
+    },
+};
+#[cfg(feature = "usb-host-link")]
+use stm32l4xx_hal::usb::{
+    Peripheral,
+    UsbBus,
+    UsbBusType,
+};
+#[cfg(feature = "usb-host-link")]
+use usb_device::bus::UsbBusAllocator;
 
 use lpc845_messages::{
+    Capabilities,
     DmaMode,
+    HostLink,
     HostToTarget,
+    I2C_BUF_CAP,
+    IrqState,
+    PROTOCOL_VERSION,
+    SPI_BUF_CAP,
     TargetToHost,
+    SYNC_PREAMBLE,
+    USART_CHUNK_CAP,
     UsartMode,
+    checksum::Checksum,
+    chunk::{
+        Chunk,
+        Reassembly,
+    },
+    compress::Compression,
+    is_link_sync,
     pin,
+    reset,
+    units::Microseconds,
 };
 
+use self::host::Host;
+
 
 pool!(
     #[allow(non_upper_case_globals)]
@@ -94,28 +140,71 @@ pool!(
 );
 
 
+/// The output pin that the host can set (PC1)
+const GPIO_OUT: pin::PortPin = pin::PortPin::new(2, 1);
+
+/// The input pin that the host can read (PC2)
+const GPIO_IN: pin::PortPin = pin::PortPin::new(2, 2);
+
+/// The pins connected to the host (USART2 on PA3 and PA2)
+#[cfg(not(feature = "usb-host-link"))]
+const HOST_LINK: HostLink = HostLink::Usart {
+    usart: 2,
+    rx:    pin::PortPin::new(0, 3),
+    tx:    pin::PortPin::new(0, 2),
+};
+
+/// The pins connected to the host (USB on PA11 and PA12)
+#[cfg(feature = "usb-host-link")]
+const HOST_LINK: HostLink = HostLink::Usb {
+    dm: pin::PortPin::new(0, 11),
+    dp: pin::PortPin::new(0, 12),
+};
+
+/// The ADC channel of the analog input pin (ADC1_IN1, on PC0)
+const ADC_CHANNEL: u8 = 1;
+
+/// The ADC channel that is wired to the DAC output (ADC1_IN4, on PC3)
+const DAC_LOOPBACK_CHANNEL: u8 = 4;
+
+/// The DAC channel of the analog output pin (DAC1_OUT1, on PA4)
+const DAC_CHANNEL: u8 = 1;
+
+/// The period of the PWM signal
+///
+/// TIM1 is set up for 50 Hz in `init`, and can't change its frequency after
+/// that.
+#[cfg(not(feature = "usb-host-link"))]
+const PWM_PERIOD: Microseconds = Microseconds(20_000);
+
+
 #[rtic::app(device = stm32l4xx_hal::pac)]
 const APP: () = {
     struct Resources {
         rx_main: serial::Rx<USART1>,
         tx_main: serial::Tx<USART1>,
+        #[cfg(not(feature = "usb-host-link"))]
         rx_host: serial::Rx<USART2>,
-        tx_host: serial::Tx<USART2>,
         rx_dma: serial::Rx<USART3>,
         tx_dma: serial::Tx<USART3>,
 
         rx_prod_main: spsc::Producer<'static, u8, 256>,
         rx_cons_main: spsc::Consumer<'static, u8, 256>,
+        #[cfg(not(feature = "usb-host-link"))]
         rx_prod_host: spsc::Producer<'static, u8, 256>,
-        rx_cons_host: spsc::Consumer<'static, u8, 256>,
         rx_prod_dma: spsc::Producer<'static, u8, 256>,
         rx_cons_dma: spsc::Consumer<'static, u8, 256>,
 
+        host: Host,
+
         dma_tx_main: FrameSender<Box<DmaPool>, dma1::C4, 256>,
         dma_rx_dma: FrameReader<Box<DmaPool>, dma1::C3, 256>,
 
         adc: ADC,
         analog: PC0<Analog>,
+        dac_loopback: PC3<Analog>,
+
+        dac: dac::C1<dac::Enabled>,
 
         gpio_out: PC1<Output<PushPull>>,
         gpio_in: PC2<Input<Floating>>,
@@ -142,15 +231,25 @@ const APP: () = {
         timer_signal: PC7<Output<PushPull>>,
         clocks: Clocks,
 
+        #[cfg(not(feature = "usb-host-link"))]
         pwm_signal: Pwm<TIM1, pwm::C4>,
+
+        reset_cause: reset::Cause,
     }
 
     #[init]
     fn init(cx: init::Context) -> init::LateResources {
+        #[cfg(not(feature = "usb-host-link"))]
         static mut RX_QUEUE_HOST: spsc::Queue<u8, 256> = spsc::Queue::new();
         static mut RX_QUEUE_MAIN: spsc::Queue<u8, 256> = spsc::Queue::new();
         static mut RX_QUEUE_DMA: spsc::Queue<u8, 256> = spsc::Queue::new();
 
+        // The USB device and its serial number need to live forever.
+        #[cfg(feature = "usb-host-link")]
+        static mut USB_BUS: Option<UsbBusAllocator<UsbBusType>> = None;
+        #[cfg(feature = "usb-host-link")]
+        static mut USB_SERIAL: [u8; 24] = [0; 24];
+
         // Allocate memory for DMA transfers.
         static mut MEMORY: [u8; 1024] = [0; 1024];
         DmaPool::grow(MEMORY);
@@ -161,13 +260,27 @@ const APP: () = {
         let cp = cx.core;
         let p = pac::Peripherals::take().unwrap();
 
+        // The HAL doesn't provide access to the reset flags, so they have to
+        // be read before it takes over RCC.
+        let reset_cause = take_reset_cause(&p.RCC);
+
         let mut rcc = p.RCC.constrain();
         let mut flash = p.FLASH.constrain();
         let mut pwr = p.PWR.constrain(&mut rcc.apb1r1);
 
-        let clocks = rcc.cfgr
-            .pclk1(2.mhz()) // needed to slow down SPI2 clock rate
-            .freeze(&mut flash.acr, &mut pwr);
+        let cfgr = rcc.cfgr
+            .pclk1(2.mhz()); // needed to slow down SPI2 clock rate
+
+        // USB needs its 48 MHz clock, and a system clock of at least 14.2 MHz.
+        #[cfg(feature = "usb-host-link")]
+        let cfgr = cfgr
+            .hsi48(true)
+            .sysclk(16.mhz());
+
+        let clocks = cfgr.freeze(&mut flash.acr, &mut pwr);
+
+        #[cfg(feature = "usb-host-link")]
+        host::enable_usb();
 
         let mut delay = Delay::new(cp.SYST, clocks);
         let adc = ADC::new(
@@ -194,12 +307,25 @@ const APP: () = {
         let rx_pin_main = gpiob.pb7.into_af7(&mut gpiob.moder, &mut gpiob.afrl);
         let rts_main = gpiob.pb3.into_af7(&mut gpiob.moder, &mut gpiob.afrl);
         let cts_main = gpiob.pb4.into_af7(&mut gpiob.moder, &mut gpiob.afrl);
+        #[cfg(not(feature = "usb-host-link"))]
         let tx_pin_host = gpioa.pa2.into_af7(&mut gpioa.moder, &mut gpioa.afrl);
+        #[cfg(not(feature = "usb-host-link"))]
         let rx_pin_host = gpioa.pa3.into_af7(&mut gpioa.moder, &mut gpioa.afrl);
         let tx_pin_dma = gpiob.pb10.into_af7(&mut gpiob.moder, &mut gpiob.afrh);
         let rx_pin_dma = gpiob.pb11.into_af7(&mut gpiob.moder, &mut gpiob.afrh);
 
         let analog = gpioc.pc0.into_analog(&mut gpioc.moder, &mut gpioc.pupdr);
+        let dac_loopback =
+            gpioc.pc3.into_analog(&mut gpioc.moder, &mut gpioc.pupdr);
+
+        // The DAC output is wired to `dac_loopback`, so the target can sample
+        // it with its own ADC.
+        let dac = p.DAC1
+            .constrain(
+                gpioa.pa4.into_analog(&mut gpioa.moder, &mut gpioa.pupdr),
+                &mut rcc.apb1r1,
+            )
+            .enable();
 
         let gpio_out = gpioc.pc1
             .into_push_pull_output(&mut gpioc.moder, &mut gpioc.otyper);
@@ -209,11 +335,29 @@ const APP: () = {
         let timer_signal = gpioc.pc7
             .into_push_pull_output(&mut gpioc.moder, &mut gpioc.otyper);
 
-        let pwm_signal = gpioa.pa11
-            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)
-            .into_af1(&mut gpioa.moder, &mut gpioa.afrh);
-        let pwm_signal = p.TIM1
-            .pwm(pwm_signal, 50.hz(), clocks, &mut rcc.apb2);
+        // PA11 is also USB D-, so there's no PWM with the USB host link.
+        #[cfg(not(feature = "usb-host-link"))]
+        let pwm_signal = {
+            let pin = gpioa.pa11
+                .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)
+                .into_af1(&mut gpioa.moder, &mut gpioa.afrh);
+            p.TIM1.pwm(pin, 50.hz(), clocks, &mut rcc.apb2)
+        };
+
+        #[cfg(feature = "usb-host-link")]
+        let host = {
+            let usb = Peripheral {
+                usb:    p.USB,
+                pin_dm: gpioa.pa11.into_af10(&mut gpioa.moder, &mut gpioa.afrh),
+                pin_dp: gpioa.pa12.into_af10(&mut gpioa.moder, &mut gpioa.afrh),
+            };
+            *USB_BUS = Some(UsbBus::new(usb));
+
+            Host::new(
+                USB_BUS.as_ref().unwrap(),
+                host::serial_number(USB_SERIAL),
+            )
+        };
 
         let mut scl = gpioa.pa9
             .into_open_drain_output(&mut gpioa.moder, &mut gpioa.otyper);
@@ -238,6 +382,7 @@ const APP: () = {
             clocks,
             &mut rcc.apb2,
         );
+        #[cfg(not(feature = "usb-host-link"))]
         let mut usart_host = Serial::usart2(
             p.USART2,
             (tx_pin_host, rx_pin_host),
@@ -256,6 +401,7 @@ const APP: () = {
         );
 
         usart_main.listen(serial::Event::Rxne);
+        #[cfg(not(feature = "usb-host-link"))]
         usart_host.listen(serial::Event::Rxne);
         usart_dma.listen(serial::Event::CharacterMatch);
 
@@ -277,12 +423,17 @@ const APP: () = {
         );
 
         let (tx_main, rx_main) = usart_main.split();
-        let (tx_host, rx_host) = usart_host.split();
         let (tx_dma, rx_dma) = usart_dma.split();
         let (rx_prod_main, rx_cons_main) = RX_QUEUE_MAIN.split();
-        let (rx_prod_host, rx_cons_host) = RX_QUEUE_HOST.split();
         let (rx_prod_dma, rx_cons_dma) = RX_QUEUE_DMA.split();
 
+        #[cfg(not(feature = "usb-host-link"))]
+        let (host, rx_host, rx_prod_host) = {
+            let (tx_host, rx_host) = usart_host.split();
+            let (rx_prod_host, rx_cons_host) = RX_QUEUE_HOST.split();
+            (Host::new(tx_host, rx_cons_host), rx_host, rx_prod_host)
+        };
+
         let dma1 = p.DMA1.split(&mut rcc.ahb1);
         let dma_tx_main = tx_main.frame_sender(dma1.4);
         let dma_rx_dma = {
@@ -297,23 +448,28 @@ const APP: () = {
         init::LateResources {
             rx_main,
             tx_main,
+            #[cfg(not(feature = "usb-host-link"))]
             rx_host,
-            tx_host,
             rx_dma,
             tx_dma,
 
             rx_prod_main,
             rx_cons_main,
+            #[cfg(not(feature = "usb-host-link"))]
             rx_prod_host,
-            rx_cons_host,
             rx_prod_dma,
             rx_cons_dma,
 
+            host,
+
             dma_tx_main,
             dma_rx_dma,
 
             adc,
             analog,
+            dac_loopback,
+
+            dac,
 
             gpio_out,
             gpio_in,
@@ -327,19 +483,23 @@ const APP: () = {
             timer_signal,
             clocks,
 
+            #[cfg(not(feature = "usb-host-link"))]
             pwm_signal,
+
+            reset_cause,
         }
     }
 
     #[idle(resources = [
         rx_cons_main,
-        rx_cons_host,
         rx_cons_dma,
         tx_main,
-        tx_host,
+        host,
         dma_tx_main,
         adc,
         analog,
+        dac_loopback,
+        dac,
         gpio_out,
         gpio_in,
         i2c,
@@ -348,16 +508,21 @@ const APP: () = {
         systick,
         clocks,
         pwm_signal,
+        reset_cause,
     ])]
     fn idle(cx: idle::Context) -> ! {
+        // Too large for the stack.
+        static mut REASSEMBLY: Reassembly = Reassembly::new();
+
         let rx_main = cx.resources.rx_cons_main;
-        let rx_host = cx.resources.rx_cons_host;
         let rx_dma  = cx.resources.rx_cons_dma;
         let tx_main = cx.resources.tx_main;
-        let tx_host = cx.resources.tx_host;
+        let host    = cx.resources.host;
         let dma_tx_main = cx.resources.dma_tx_main;
         let adc = cx.resources.adc;
         let analog = cx.resources.analog;
+        let dac_loopback = cx.resources.dac_loopback;
+        let dac = cx.resources.dac;
         let gpio_out = cx.resources.gpio_out;
         let gpio_in = cx.resources.gpio_in;
         let i2c = cx.resources.i2c;
@@ -365,35 +530,116 @@ const APP: () = {
         let spi = cx.resources.spi;
         let systick = cx.resources.systick;
         let clocks = cx.resources.clocks;
+        #[cfg(not(feature = "usb-host-link"))]
         let pwm_signal = cx.resources.pwm_signal;
+        let reset_cause = *cx.resources.reset_cause;
 
         let mut buf_main_rx: Vec<_, 256> = Vec::new();
         let mut buf_host_rx: Vec<_, 256> = Vec::new();
+        let reassembly = REASSEMBLY;
+
+        // Set, while the rest of a request that didn't fit into the receive
+        // buffer is skipped.
+        let mut discarding = false;
+
+        // Let the host know that we're ready.
+        host.bwrite_all(SYNC_PREAMBLE)
+            .expect("Error sending sync preamble to host");
 
         loop {
             handle_usart_rx(
                 rx_main,
-                tx_host,
+                host,
                 UsartMode::Regular,
                 &mut buf_main_rx,
             );
             handle_usart_rx(
                 rx_dma,
-                tx_host,
+                host,
                 UsartMode::Dma,
                 &mut buf_main_rx,
             );
 
-            if let Some(b) = rx_host.dequeue() {
+            if let Some(b) = host.read() {
+                // Skip the rest of a request that didn't fit into the buffer.
+                // Its delimiter is where the next request starts.
+                if discarding {
+                    discarding = b != 0;
+                    continue;
+                }
+
                 // Requests are COBS-encoded, so we know that `0` means we
                 // received a full frame.
                 if b != 0 {
-                    buf_host_rx.push(b).expect("Receive buffer full");
+                    if buf_host_rx.push(b).is_err() {
+                        rprintln!("Dropping oversized host request.");
+                        buf_host_rx.clear();
+                        discarding = true;
+                    }
                     continue;
                 }
 
-                let message = postcard::from_bytes_cobs(&mut buf_host_rx)
-                    .expect("Error decoding message");
+                // Empty frames, like the ones at the start of the link sync
+                // sequence, only terminate whatever was received before them.
+                if buf_host_rx.is_empty() {
+                    continue;
+                }
+                if is_link_sync(&buf_host_rx) {
+                    // A host is attaching, and starts over.
+                    buf_host_rx.clear();
+                    reassembly.reset();
+                    host.bwrite_all(SYNC_PREAMBLE)
+                        .expect("Error sending sync preamble to host");
+                    continue;
+                }
+
+                let message = match postcard::from_bytes_cobs(
+                    &mut buf_host_rx,
+                ) {
+                    Ok(message) => message,
+                    Err(_) => {
+                        // Whatever was received before the host attached.
+                        rprintln!("Dropping undecodable host request.");
+                        buf_host_rx.clear();
+                        continue;
+                    }
+                };
+
+                // Large requests arrive in chunks. Only handle them, once the
+                // last chunk has been received.
+                let message = match message {
+                    HostToTarget::Chunk { id, index, total, data } => {
+                        let chunk = Chunk { id, index, total, data };
+                        match reassembly.add(chunk) {
+                            Ok(Some(message)) => {
+                                match postcard::from_bytes(message) {
+                                    Ok(message) => message,
+                                    Err(_) => {
+                                        rprintln!(
+                                            "Dropping undecodable chunks.",
+                                        );
+                                        buf_host_rx.clear();
+                                        continue;
+                                    }
+                                }
+                            }
+                            Ok(None) => {
+                                buf_host_rx.clear();
+                                continue;
+                            }
+                            Err(err) => {
+                                rprintln!(
+                                    "Dropping incomplete host request: {:?}",
+                                    err,
+                                );
+                                buf_host_rx.clear();
+                                continue;
+                            }
+                        }
+                    }
+                    message => message,
+                };
+
                 match message {
                     HostToTarget::SendUsart {
                         mode: UsartMode::Regular,
@@ -440,7 +686,34 @@ const APP: () = {
 
                         rprintln!("Sent data using flow control: {:?}", data);
                     }
-                    HostToTarget::ReadAdc => {
+                    HostToTarget::StartUsartThroughputTest {
+                        bytes,
+                        chunk_size,
+                    } => {
+                        let mut chunk = [0; USART_CHUNK_CAP];
+                        for (i, b) in chunk.iter_mut().enumerate() {
+                            *b = i as u8;
+                        }
+                        let chunk_size = usize::from(chunk_size)
+                            .clamp(1, USART_CHUNK_CAP);
+
+                        let mut remaining = bytes as usize;
+                        while remaining > 0 {
+                            let n = remaining.min(chunk_size);
+                            tx_main.bwrite_all(&chunk[..n])
+                                .expect("Error writing to USART");
+                            remaining -= n;
+                        }
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(
+                                &TargetToHost::UsartThroughputSent,
+                            )
+                            .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::ReadAdc { channel: ADC_CHANNEL } => {
                         let value = adc.read(analog).unwrap();
 
                         let message = TargetToHost::AdcValue(value);
@@ -448,11 +721,28 @@ const APP: () = {
                         let buf_host_tx: Vec<_, 256> =
                             postcard::to_vec_cobs(&message)
                                 .expect("Error encoding message to host");
-                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                        host.bwrite_all(buf_host_tx.as_ref())
                             .expect("Error sending message to host");
                     }
+                    HostToTarget::ReadAdc { channel: DAC_LOOPBACK_CHANNEL } => {
+                        let value = adc.read(dac_loopback).unwrap();
+
+                        let message = TargetToHost::AdcValue(value);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::SetDacValue {
+                        channel: DAC_CHANNEL,
+                        value,
+                    } => {
+                        dac.set_value(value);
+                    }
                     HostToTarget::SetPin(
-                        pin::SetLevel { level, pin: () }
+                        pin::SetLevel { level, pin: GPIO_OUT }
                     ) => {
                         match level {
                             pin::Level::High => {
@@ -463,7 +753,7 @@ const APP: () = {
                             }
                         }
                     }
-                    HostToTarget::ReadPin(pin::ReadLevel { pin: () }) => {
+                    HostToTarget::ReadPin(pin::ReadLevel { pin: GPIO_IN }) => {
                         let level = match gpio_in.is_high().unwrap() {
                             true  => pin::Level::High,
                             false => pin::Level::Low,
@@ -472,9 +762,9 @@ const APP: () = {
                         let message = TargetToHost::ReadPinResult(
                             Some(
                                 pin::ReadLevelResult {
-                                    pin: (),
+                                    pin: GPIO_IN,
                                     level,
-                                    period_ms: None,
+                                    period: None,
                                 }
                             )
                         );
@@ -482,55 +772,106 @@ const APP: () = {
                         let buf_host_tx: Vec<_, 256> =
                             postcard::to_vec_cobs(&message)
                                 .expect("Error encoding message to host");
-                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::SetPin(pin::SetLevel { pin, .. }) |
+                    HostToTarget::ReadPin(pin::ReadLevel { pin }) => {
+                        // Only `GPIO_OUT` and `GPIO_IN` are wired to the
+                        // assistant. Other pins might be in use.
+                        let message = TargetToHost::UnsupportedPin(pin);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
                             .expect("Error sending message to host");
                     }
                     HostToTarget::StartI2cTransaction {
                         mode: DmaMode::Regular,
                         address,
-                        data,
+                        write,
+                        read_len,
                     } => {
-                        i2c.write(address, &[data])
-                            .unwrap();
+                        let mut rx_buf = [0u8; I2C_BUF_CAP];
+
+                        let message = if write.len() > I2C_BUF_CAP
+                            || read_len as usize > I2C_BUF_CAP
+                        {
+                            TargetToHost::InvalidRequest
+                        }
+                        else {
+                            let rx_buf = &mut rx_buf[..read_len as usize];
 
-                        let mut rx_buf = [0u8; 1];
-                        i2c.read(address, &mut rx_buf)
-                            .unwrap();
+                            let mut result = Ok(());
 
-                        let message = TargetToHost::I2cReply(rx_buf[0]);
+                            if !write.is_empty() {
+                                result = i2c.write(address, write);
+                            }
+                            if result.is_ok() && !rx_buf.is_empty() {
+                                result = i2c.read(address, rx_buf);
+                            }
+
+                            match result {
+                                Ok(()) => {
+                                    TargetToHost::I2cReply(rx_buf)
+                                }
+                                Err(i2c::Error::Nack) => {
+                                    TargetToHost::I2cNack
+                                }
+                                Err(err) => {
+                                    rprintln!("I2C error: {:?}", err);
+                                    TargetToHost::I2cError
+                                }
+                            }
+                        };
 
                         let buf_host_tx: Vec<_, 256> =
                             postcard::to_vec_cobs(&message)
                                 .expect("Error encoding message to host");
-                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                        host.bwrite_all(buf_host_tx.as_ref())
                             .expect("Error sending message to host");
                     }
                     HostToTarget::StartSpiTransaction {
                         mode: DmaMode::Regular,
-                        data,
+                        write,
+                        read_len,
                     } => {
-                        rprintln!("SPI: Set SSEL LOW");
-                        ssel.set_low().unwrap();
+                        // Writing and reading happen in a single transfer,
+                        // sending `0xFF` while reading.
+                        let mut data = [0xFFu8; SPI_BUF_CAP];
+                        let len = write.len() + read_len as usize;
+
+                        let message = if len > SPI_BUF_CAP {
+                            TargetToHost::InvalidRequest
+                        }
+                        else {
+                            let data = &mut data[..len];
+                            data[..write.len()].copy_from_slice(write);
+
+                            rprintln!("SPI: Set SSEL LOW");
+                            ssel.set_low().unwrap();
 
-                        let mut data = [data, 0xFF];
-                        spi.transfer(&mut data).unwrap();
-                        let reply = data[1];
+                            spi.transfer(data).unwrap();
 
-                        rprintln!("SPI: Set SSEL HIGH");
-                        ssel.set_high().unwrap();
+                            rprintln!("SPI: Set SSEL HIGH");
+                            ssel.set_high().unwrap();
 
-                        let message = TargetToHost::SpiReply(reply);
+                            TargetToHost::SpiReplyBuffer(&data[write.len()..])
+                        };
 
                         let buf_host_tx: Vec<_, 256> =
                             postcard::to_vec_cobs(&message)
                                 .expect("Error encoding message to host");
-                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                        host.bwrite_all(buf_host_tx.as_ref())
                             .expect("Error sending message to host");
 
                         rprintln!(" done.");
                     }
-                    HostToTarget::StartTimerInterrupt { period_ms } => {
-                        let reload = clocks.hclk().0 / 1000 * period_ms;
+                    HostToTarget::StartTimerInterrupt {
+                        period: Microseconds(period),
+                    } => {
+                        let reload = clocks.hclk().0 / 1_000_000 * period;
                         systick.set_clock_source(SystClkSource::Core);
                         systick.set_reload(reload);
 
@@ -542,15 +883,159 @@ const APP: () = {
                         systick.disable_interrupt();
                         systick.disable_counter();
                     }
-                    HostToTarget::StartPwmSignal => {
-                        pwm_signal.set_duty(pwm_signal.get_max_duty() / 2);
-                        pwm_signal.enable();
+                    #[cfg(not(feature = "usb-host-link"))]
+                    HostToTarget::StartPwmSignal {
+                        period,
+                        duty_permille,
+                    } => {
+                        // The period is fixed when the timer is set up.
+                        if period != PWM_PERIOD || duty_permille > 1000 {
+                            let buf_host_tx: Vec<_, 256> =
+                                postcard::to_vec_cobs(
+                                    &TargetToHost::InvalidRequest
+                                )
+                                .expect("Error encoding message to host");
+                            host.bwrite_all(buf_host_tx.as_ref())
+                                .expect("Error sending message to host");
+                        }
+                        else {
+                            let max  = u32::from(pwm_signal.get_max_duty());
+                            let duty = max * u32::from(duty_permille) / 1000;
+                            pwm_signal.set_duty(duty as u16);
+                            pwm_signal.enable();
+                        }
                     }
+                    #[cfg(not(feature = "usb-host-link"))]
                     HostToTarget::StopPwmSignal => {
                         pwm_signal.disable();
                     }
+                    HostToTarget::Quiesce => {
+                        systick.disable_interrupt();
+                        systick.disable_counter();
+                        #[cfg(not(feature = "usb-host-link"))]
+                        pwm_signal.disable();
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&TargetToHost::Quiesced)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::NegotiateCompression(_) => {
+                        // This firmware doesn't support compression. Let the
+                        // host know that frames will stay uncompressed.
+                        let message = TargetToHost::CompressionNegotiated(
+                            Compression::None,
+                        );
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::NegotiateChecksum(_) => {
+                        // This firmware doesn't support checksums either. Let
+                        // the host know that frames will stay as they are.
+                        let message = TargetToHost::ChecksumNegotiated(
+                            Checksum::None,
+                        );
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::GetCapabilities => {
+                        let message = TargetToHost::Capabilities(
+                            Capabilities {
+                                usart:              true,
+                                usart_dma:          true,
+                                usart_flow_control: true,
+                                usart_sync:         false,
+                                i2c:                true,
+                                i2c_dma:            false,
+                                spi:                true,
+                                spi_dma:            false,
+                                adc:                true,
+                                dac:                true,
+                                pwm:                !cfg!(
+                                    feature = "usb-host-link"
+                                ),
+                                timer_interrupt:    true,
+                                trigger:            false,
+                                host_link:          HOST_LINK,
+                            }
+                        );
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::GetVersion => {
+                        let message = TargetToHost::Version {
+                            protocol:      PROTOCOL_VERSION,
+                            firmware_hash: env!("FIRMWARE_HASH"),
+                            hal_version:   env!("HAL_VERSION"),
+                            features:      env!("FIRMWARE_FEATURES"),
+                        };
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::Ping => {
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&TargetToHost::Pong)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::GetResetCause => {
+                        let message = TargetToHost::ResetCause(reset_cause);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::GetIrqState => {
+                        // Sound, as we're only reading the registers.
+                        let nvic = unsafe { &*NVIC::ptr() };
+
+                        // The STM32L4 has fewer than 96 interrupts, so the
+                        // fourth word is always zero.
+                        let mut state = IrqState {
+                            enabled: [0; 4],
+                            pending: [0; 4],
+                        };
+                        for i in 0 .. 3 {
+                            state.enabled[i] = nvic.iser[i].read();
+                            state.pending[i] = nvic.ispr[i].read();
+                        }
+
+                        let message = TargetToHost::IrqState(state);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
                     message => {
-                        panic!("Unsupported message: {:?}", message)
+                        rprintln!("Unsupported message: {:?}", message);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&TargetToHost::InvalidRequest)
+                                .expect("Error encoding message to host");
+                        host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
                     }
                 }
 
@@ -580,23 +1065,32 @@ const APP: () = {
         }
     }
 
+    // With the USB host link, USART2 isn't set up, and this interrupt never
+    // fires.
     #[task(binds = USART2, resources = [rx_host, rx_prod_host])]
+    #[cfg_attr(feature = "usb-host-link", allow(unused_variables))]
     fn usart2(cx: usart2::Context) {
-        let rx = cx.resources.rx_host;
-        let queue = cx.resources.rx_prod_host;
-
-        let b = match rx.read() {
-            Ok(b) => b,
-            Err(err) => {
-                rprintln!("Error reading from USART2: {:?}", err);
-                return;
-            }
-        };
-        match queue.enqueue(b) {
-            Ok(()) => (),
-            Err(err) => {
-                rprintln!("Error adding received byte to queue: {:?}", err);
-                return;
+        #[cfg(not(feature = "usb-host-link"))]
+        {
+            let rx = cx.resources.rx_host;
+            let queue = cx.resources.rx_prod_host;
+
+            let b = match rx.read() {
+                Ok(b) => b,
+                Err(err) => {
+                    rprintln!("Error reading from USART2: {:?}", err);
+                    return;
+                }
+            };
+            match queue.enqueue(b) {
+                Ok(()) => (),
+                Err(err) => {
+                    rprintln!(
+                        "Error adding received byte to queue: {:?}",
+                        err,
+                    );
+                    return;
+                }
             }
         }
     }
@@ -614,7 +1108,10 @@ const APP: () = {
             let buf = dma_rx_dma.character_match_interrupt(buf);
 
             for &b in buf.read() {
-                rx_prod_dma.enqueue(b).unwrap();
+                if let Err(err) = rx_prod_dma.enqueue(b) {
+                    rprintln!("Error adding received byte to queue: {:?}", err);
+                    break;
+                }
             }
         }
     }
@@ -639,7 +1136,7 @@ const APP: () = {
 
 fn handle_usart_rx(
     queue: &mut spsc::Consumer<'static, u8, 256>,
-    tx_host: &mut serial::Tx<USART2>,
+    host: &mut Host,
     mode: UsartMode,
     buf: &mut Vec<u8, 256>,
 ) {
@@ -651,14 +1148,44 @@ fn handle_usart_rx(
     if buf.len() > 0 {
         let message = TargetToHost::UsartReceive {
             mode,
-            data: buf.as_ref(),
+            data:      buf.as_ref(),
+            timestamp: None,
         };
 
         let buf_host_tx: Vec<_, 256> = postcard::to_vec_cobs(&message)
             .expect("Error encoding message to host");
-        tx_host.bwrite_all(buf_host_tx.as_ref())
+        host.bwrite_all(buf_host_tx.as_ref())
             .expect("Error sending message to host");
 
         buf.clear();
     }
 }
+
+/// Returns the cause of the last reset, and clears the reset flags
+///
+/// PINRSTF is set on every reset, as the reset is also driven onto the reset
+/// pin, and BORRSTF on power-on. Both are only reported, if no more specific
+/// flag is set.
+fn take_reset_cause(rcc: &pac::RCC) -> reset::Cause {
+    let csr = rcc.csr.read();
+    rcc.csr.modify(|_, w| w.rmvf().set_bit());
+
+    if csr.lpwrstf().bit_is_set() {
+        reset::Cause::LowPower
+    }
+    else if csr.iwdgrstf().bit_is_set() || csr.wwdgrstf().bit_is_set() {
+        reset::Cause::Watchdog
+    }
+    else if csr.sftrstf().bit_is_set() {
+        reset::Cause::Software
+    }
+    else if csr.borrstf().bit_is_set() {
+        reset::Cause::BrownOut
+    }
+    else if csr.pinrstf().bit_is_set() {
+        reset::Cause::Pin
+    }
+    else {
+        reset::Cause::Unknown
+    }
+}
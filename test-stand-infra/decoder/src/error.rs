@@ -0,0 +1,67 @@
+//! Defines the error type for this library
+
+
+use std::io;
+
+use protocol::compress;
+
+
+/// The result type for this library
+///
+/// This is just a convenient short-hand.
+pub type Result<T = ()> = core::result::Result<T, Error>;
+
+
+/// The error type for this library
+#[derive(Debug)]
+pub enum Error {
+    /// A received frame's checksum didn't match
+    ///
+    /// The frame has been corrupted in transit. This is not a protocol error,
+    /// so it can make sense to request the message again.
+    ChecksumMismatch,
+
+    /// A received frame could not be COBS-decoded
+    Cobs,
+
+    /// Error occurred while decompressing a received frame
+    Compress(compress::Error),
+
+    /// A received frame had an unknown compression flag
+    FrameFlag(u8),
+
+    /// An I/O error occurred
+    Io(io::Error),
+
+    /// Error occurred while deserializing JSON
+    Json(serde_json::Error),
+
+    /// An error originated from Postcard
+    ///
+    /// The `postcard` crate is used for (de-)serialization.
+    Postcard(postcard::Error),
+}
+
+impl From<compress::Error> for Error {
+    fn from(err: compress::Error) -> Self {
+        Self::Compress(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<postcard::Error> for Error {
+    fn from(err: postcard::Error) -> Self {
+        Self::Postcard(err)
+    }
+}
@@ -0,0 +1,120 @@
+//! Encoding and decoding of frames
+//!
+//! Messages are serialized using Postcard. A checksum is appended, if one has
+//! been negotiated, and the result is COBS-encoded and terminated with `0`.
+//! Frames sent by test nodes can additionally be compressed.
+
+
+use protocol::{
+    SYNC_PREAMBLE,
+    checksum::Checksum,
+    compress::{
+        self,
+        Compression,
+    },
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::Error;
+
+
+/// Track how much of the sync preamble has been received
+///
+/// Takes the number of preamble bytes matched so far, and the next received
+/// byte. Returns the new number of matched bytes.
+pub fn match_sync(matched: usize, b: u8) -> usize {
+    if b == SYNC_PREAMBLE[matched] {
+        matched + 1
+    }
+    else if b == SYNC_PREAMBLE[0] {
+        // The preamble only contains `0` at its start and end, so a mismatched
+        // `0` can only be the start of a new preamble.
+        1
+    }
+    else {
+        0
+    }
+}
+
+/// Serialize `message` into a frame that is ready to be sent
+///
+/// Appends the checksum, then COBS-encodes the frame into `buf`. Returns the
+/// length of the frame, including the terminating `0`.
+pub fn encode_frame<T>(message: &T, checksum: Checksum, buf: &mut [u8])
+    -> Result<usize, Error>
+    where T: Serialize
+{
+    let mut frame = [0; 256];
+
+    let len = postcard::to_slice(message, &mut frame)?.len();
+    let len = checksum.append(&mut frame, len)
+        .map_err(|_| postcard::Error::SerializeBufferFull)?;
+
+    // We need space for the COBS-encoded frame, plus the terminating `0`.
+    let frame = &frame[..len];
+    if postcard_cobs::max_encoding_length(frame.len()) + 1 > buf.len() {
+        return Err(postcard::Error::SerializeBufferFull.into());
+    }
+
+    let len = postcard_cobs::encode(frame, buf);
+    buf[len] = 0;
+
+    Ok(len + 1)
+}
+
+/// Decode a received frame into `buf`
+///
+/// `frame` is COBS-decoded in place. The checksum is verified and stripped,
+/// then the frame is decompressed into `buf`, if necessary.
+pub fn decode_frame(
+    frame:       &mut [u8],
+    checksum:    Checksum,
+    compression: Compression,
+    buf:         &mut Vec<u8>,
+)
+    -> Result<(), Error>
+{
+    let len = postcard_cobs::decode_in_place(frame)
+        .map_err(|()| Error::Cobs)?;
+    let frame = checksum.verify(&frame[..len])
+        .map_err(|_| Error::ChecksumMismatch)?;
+
+    buf.clear();
+    match compression {
+        Compression::None => {
+            buf.extend_from_slice(frame);
+        }
+        Compression::Rle => {
+            let (&flag, data) = frame.split_first()
+                .ok_or(Error::Cobs)?;
+
+            match flag {
+                compress::FLAG_RAW => {
+                    buf.extend_from_slice(data);
+                }
+                compress::FLAG_RLE => {
+                    buf.resize(compress::decompressed_len(data)?, 0);
+                    compress::decompress(data, buf)?;
+                }
+                flag => {
+                    return Err(Error::FrameFlag(flag));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Deserialize a decoded frame, for inspecting it
+///
+/// Returns `None`, if the frame can't be deserialized as `T`.
+pub fn peek<'de, T>(frame: &'de [u8]) -> Option<T>
+    where T: Deserialize<'de>
+{
+    postcard::from_bytes(frame).ok()
+}
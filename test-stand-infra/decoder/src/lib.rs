@@ -0,0 +1,22 @@
+//! Host-side decoding of the test stand protocol
+//!
+//! Covers everything that's needed to make sense of the traffic between the
+//! host and the test nodes, without linking a messages crate: Frame encoding
+//! and decoding, the message schema, pretty-printing messages based on the
+//! schema, and trace files.
+
+
+pub mod error;
+pub mod frame;
+pub mod pretty;
+pub mod schema;
+pub mod trace;
+
+
+pub use self::{
+    error::{
+        Error,
+        Result,
+    },
+    schema::Schema,
+};
@@ -0,0 +1,386 @@
+//! Pretty-printing of messages, based on a message schema
+//!
+//! Decodes the Postcard-serialized contents of a frame, using only the field
+//! types recorded in the schema, and formats the message much like `Debug`
+//! would.
+//!
+//! Postcard encodes integers as fixed-size little-endian values. Only enum
+//! variant indexes and lengths are encoded as varints.
+
+
+use std::{
+    convert::TryInto as _,
+    fmt::Write as _,
+};
+
+use crate::schema::{
+    Field,
+    Schema,
+    TypeDef,
+    TypeKind,
+};
+
+
+/// Format a decoded frame for display
+///
+/// `root` is the name of the top-level type that the frame contains, like
+/// `TargetToHost`. Trailing bytes after the message are ignored, as they are
+/// by Postcard.
+pub fn format(schema: &Schema, root: &str, frame: &[u8])
+    -> Result<String, FormatError>
+{
+    let mut decoder = Decoder {
+        schema,
+        frame,
+        pos: 0,
+        out: String::new(),
+    };
+
+    decoder.out.push_str(root);
+    decoder.out.push_str("::");
+    decoder.decode(root, &Context::default())?;
+
+    Ok(decoder.out)
+}
+
+
+/// Error formatting a message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FormatError {
+    /// The frame ended in the middle of the message
+    Truncated,
+
+    /// A string in the message is not valid UTF-8
+    Utf8,
+
+    /// A type is not in the schema, or can't be decoded
+    ///
+    /// This includes arrays whose length is given by a constant, as the
+    /// schema doesn't record the values of constants.
+    UnknownType(String),
+
+    /// An enum variant index is not in the schema
+    UnknownVariant {
+        ty:    String,
+        index: u32,
+    },
+}
+
+
+/// Where a type is referenced from
+///
+/// Field types are written relative to the module of the type that contains
+/// them, and might refer to the generic parameters of that type.
+#[derive(Clone, Default)]
+struct Context {
+    module: String,
+
+    /// Generic parameters, with the type they stand for
+    ///
+    /// The context is where the type argument has been written.
+    subst: Vec<(String, String, Context)>,
+}
+
+
+struct Decoder<'r> {
+    schema: &'r Schema,
+    frame:  &'r [u8],
+    pos:    usize,
+    out:    String,
+}
+
+impl<'r> Decoder<'r> {
+    fn decode(&mut self, ty: &str, ctx: &Context) -> Result<(), FormatError> {
+        let generic = ctx.subst.iter().find(|(param, _, _)| param == ty);
+        if let Some((_, ty, ctx)) = generic {
+            return self.decode(ty, ctx);
+        }
+
+        let ty = strip_lifetime(ty);
+        let ty = ty.as_str();
+
+        match ty {
+            "()" => {
+                self.out.push_str("()");
+            }
+            "u8" => {
+                let value = self.take(1)?[0];
+                write!(self.out, "{}", value).unwrap();
+            }
+            "i8" => {
+                let value = self.take(1)?[0] as i8;
+                write!(self.out, "{}", value).unwrap();
+            }
+            "bool" => {
+                let value = self.take(1)?[0] != 0;
+                write!(self.out, "{}", value).unwrap();
+            }
+            "u16" => {
+                let bytes = self.take(2)?.try_into().unwrap();
+                write!(self.out, "{}", u16::from_le_bytes(bytes)).unwrap();
+            }
+            "i16" => {
+                let bytes = self.take(2)?.try_into().unwrap();
+                write!(self.out, "{}", i16::from_le_bytes(bytes)).unwrap();
+            }
+            "u32" => {
+                let bytes = self.take(4)?.try_into().unwrap();
+                write!(self.out, "{}", u32::from_le_bytes(bytes)).unwrap();
+            }
+            "i32" => {
+                let bytes = self.take(4)?.try_into().unwrap();
+                write!(self.out, "{}", i32::from_le_bytes(bytes)).unwrap();
+            }
+            // `usize` and `isize` are serialized as 64-bit values.
+            "u64" | "usize" => {
+                let bytes = self.take(8)?.try_into().unwrap();
+                write!(self.out, "{}", u64::from_le_bytes(bytes)).unwrap();
+            }
+            "i64" | "isize" => {
+                let bytes = self.take(8)?.try_into().unwrap();
+                write!(self.out, "{}", i64::from_le_bytes(bytes)).unwrap();
+            }
+            "f32" => {
+                let bytes = self.take(4)?.try_into().unwrap();
+                write!(self.out, "{}", f32::from_le_bytes(bytes)).unwrap();
+            }
+            "f64" => {
+                let bytes = self.take(8)?.try_into().unwrap();
+                write!(self.out, "{}", f64::from_le_bytes(bytes)).unwrap();
+            }
+            "&str" => {
+                let len   = self.len()?;
+                let value = std::str::from_utf8(self.take(len)?)
+                    .map_err(|_| FormatError::Utf8)?;
+                write!(self.out, "{:?}", value).unwrap();
+            }
+            _ => {
+                return self.decode_compound(ty, ctx);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_compound(&mut self, ty: &str, ctx: &Context)
+        -> Result<(), FormatError>
+    {
+        if let Some(array) = ty.strip_prefix('[').and_then(|ty| {
+            ty.strip_suffix(']')
+        }) {
+            let (elem, len) = array.rsplit_once(';')
+                .ok_or_else(|| FormatError::UnknownType(ty.to_owned()))?;
+            let len = len.parse()
+                .map_err(|_| FormatError::UnknownType(ty.to_owned()))?;
+            return self.decode_sequence(elem, len, ctx);
+        }
+        if let Some(elem) = ty.strip_prefix("&[").and_then(|ty| {
+            ty.strip_suffix(']')
+        }) {
+            let len = self.len()?;
+            return self.decode_sequence(elem, len, ctx);
+        }
+
+        let (base, args) = parse_type(ty);
+
+        match base {
+            "Option" if args.len() == 1 => {
+                if self.take(1)?[0] == 0 {
+                    self.out.push_str("None");
+                    return Ok(());
+                }
+
+                self.out.push_str("Some(");
+                self.decode(args[0], ctx)?;
+                self.out.push(')');
+                return Ok(());
+            }
+            // Covers `Vec<T>`, as well as `heapless::Vec<T, N>`.
+            "Vec" | "heapless::Vec" if !args.is_empty() => {
+                let len = self.len()?;
+                return self.decode_sequence(args[0], len, ctx);
+            }
+            _ => {}
+        }
+
+        let def = lookup(self.schema, &ctx.module, base)
+            .ok_or_else(|| FormatError::UnknownType(ty.to_owned()))?;
+
+        // Lifetimes are not passed as arguments in field types, so only map
+        // type parameters.
+        let params = def.generics
+            .iter()
+            .filter(|param| !param.starts_with('\''));
+        let inner = Context {
+            module: def.module.clone(),
+            subst:  params
+                .zip(args)
+                .map(|(param, arg)| {
+                    (param.clone(), arg.to_owned(), ctx.clone())
+                })
+                .collect(),
+        };
+
+        match &def.kind {
+            TypeKind::Enum { variants } => {
+                let index = self.varint()? as u32;
+                let variant = variants
+                    .iter()
+                    .find(|variant| variant.index == index)
+                    .ok_or_else(|| FormatError::UnknownVariant {
+                        ty: ty.to_owned(),
+                        index,
+                    })?;
+
+                self.out.push_str(&variant.name);
+                self.decode_fields(&variant.fields, &inner)
+            }
+            TypeKind::Struct { fields } => {
+                self.out.push_str(&def.name);
+                self.decode_fields(fields, &inner)
+            }
+        }
+    }
+
+    fn decode_fields(&mut self, fields: &[Field], ctx: &Context)
+        -> Result<(), FormatError>
+    {
+        let named = match fields.first() {
+            Some(field) => field.name.is_some(),
+            None        => return Ok(()),
+        };
+
+        self.out.push_str(if named { " { " } else { "(" });
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            if let Some(name) = &field.name {
+                self.out.push_str(name);
+                self.out.push_str(": ");
+            }
+
+            self.decode(&field.ty, ctx)?;
+        }
+        self.out.push_str(if named { " }" } else { ")" });
+
+        Ok(())
+    }
+
+    fn decode_sequence(&mut self, elem: &str, len: usize, ctx: &Context)
+        -> Result<(), FormatError>
+    {
+        self.out.push('[');
+        for i in 0 .. len {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.decode(elem, ctx)?;
+        }
+        self.out.push(']');
+
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'r [u8], FormatError> {
+        let frame = self.frame;
+        let bytes = frame.get(self.pos .. self.pos + len)
+            .ok_or(FormatError::Truncated)?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, FormatError> {
+        let mut value = 0;
+
+        for shift in (0 .. 64).step_by(7) {
+            let b = self.take(1)?[0];
+            value |= u64::from(b & 0x7f) << shift;
+
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(FormatError::Truncated)
+    }
+
+    /// Read the length of a slice, string, or `Vec`
+    fn len(&mut self) -> Result<usize, FormatError> {
+        let len = self.varint()? as usize;
+
+        // Every element takes at least one byte, except for zero-sized ones,
+        // which messages don't contain.
+        if len > self.frame.len() - self.pos {
+            return Err(FormatError::Truncated);
+        }
+
+        Ok(len)
+    }
+}
+
+
+/// Remove the lifetime from a reference type, like in `&'r[u8]`
+///
+/// The schema doesn't contain whitespace, so `&'r str` shows up as `&'rstr`.
+fn strip_lifetime(ty: &str) -> String {
+    let rest = match ty.strip_prefix("&'") {
+        Some(rest) => rest,
+        None       => return ty.to_owned(),
+    };
+
+    let end = rest
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(rest.len());
+    if end == rest.len() && rest.ends_with("str") {
+        return String::from("&str");
+    }
+
+    format!("&{}", &rest[end..])
+}
+
+/// Split a type like `Option<pin::ReadLevelResult<()>>` into its base name and
+/// its generic arguments
+fn parse_type(ty: &str) -> (&str, Vec<&str>) {
+    let (base, rest) = match ty.find('<') {
+        Some(i) if ty.ends_with('>') => (&ty[..i], &ty[i + 1 .. ty.len() - 1]),
+        _                            => return (ty, Vec::new()),
+    };
+
+    let mut args  = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(&rest[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(&rest[start..]);
+
+    (base, args)
+}
+
+/// Find a type, relative to the module of the type that references it
+fn lookup<'r>(schema: &'r Schema, module: &str, path: &str)
+    -> Option<&'r TypeDef>
+{
+    let find = |path: &str| {
+        let (module, name) = path.rsplit_once("::").unwrap_or(("", path));
+        schema.type_def(module, name)
+    };
+
+    if !module.is_empty() {
+        if let Some(def) = find(&format!("{}::{}", module, path)) {
+            return Some(def);
+        }
+    }
+
+    find(path)
+}
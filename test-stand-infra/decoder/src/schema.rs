@@ -0,0 +1,141 @@
+//! Message schema, as exported by a messages crate
+//!
+//! Describes the names, variant indexes, and field types of all messages, so
+//! tools can decode traffic without linking the messages crate itself.
+
+
+use std::{
+    fs::File,
+    io::prelude::*,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::Error;
+
+
+/// A message schema
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Schema {
+    /// Name of the crate that exported the schema
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+
+    /// Version of the crate that exported the schema
+    pub version: String,
+
+    /// All serializable types, in the order they are defined in
+    pub types: Vec<TypeDef>,
+}
+
+impl Schema {
+    /// Parse a schema from its JSON representation
+    pub fn parse(json: &str) -> Result<Self, SchemaLoadError> {
+        serde_json::from_str(json)
+            .map_err(|err| SchemaLoadError(Error::Json(err)))
+    }
+
+    /// Load a schema from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SchemaLoadError> {
+        Self::load_inner(path.as_ref())
+            .map_err(|err| SchemaLoadError(err))
+    }
+
+    fn load_inner(path: &Path) -> Result<Self, Error> {
+        let mut json = String::new();
+        File::open(path)?
+            .read_to_string(&mut json)?;
+
+        let schema = serde_json::from_str(&json)?;
+        Ok(schema)
+    }
+
+    /// Look up a type by its module and name
+    ///
+    /// `module` is relative to the crate root, so it's `""` for top-level
+    /// types.
+    pub fn type_def(&self, module: &str, name: &str) -> Option<&TypeDef> {
+        self.types
+            .iter()
+            .find(|ty| ty.module == module && ty.name == name)
+    }
+}
+
+
+/// Description of a single type
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct TypeDef {
+    /// Name of the type
+    pub name: String,
+
+    /// Module the type is defined in, relative to the crate root
+    ///
+    /// Field types are written relative to this module.
+    pub module: String,
+
+    /// Generic parameters, including lifetimes
+    pub generics: Vec<String>,
+
+    /// Whether this is an enum or a struct
+    #[serde(flatten)]
+    pub kind: TypeKind,
+}
+
+impl TypeDef {
+    /// Look up an enum variant by the index used on the wire
+    ///
+    /// Returns `None`, if this type is not an enum, or has no such variant.
+    pub fn variant(&self, index: u32) -> Option<&Variant> {
+        match &self.kind {
+            TypeKind::Enum { variants } => {
+                variants.iter().find(|variant| variant.index == index)
+            }
+            TypeKind::Struct { .. } => {
+                None
+            }
+        }
+    }
+}
+
+
+/// The kind of a type, with its contents
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TypeKind {
+    Enum {
+        variants: Vec<Variant>,
+    },
+    Struct {
+        fields: Vec<Field>,
+    },
+}
+
+
+/// An enum variant
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Variant {
+    pub name: String,
+
+    /// The index that identifies this variant on the wire
+    pub index: u32,
+
+    pub fields: Vec<Field>,
+}
+
+
+/// A field of a struct or enum variant
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Field {
+    /// The name of the field, or `None` for tuple fields
+    pub name: Option<String>,
+
+    /// The type of the field, as written in the source code
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+
+/// Error loading a schema
+#[derive(Debug)]
+pub struct SchemaLoadError(pub Error);
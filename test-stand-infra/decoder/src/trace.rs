@@ -0,0 +1,167 @@
+//! Trace files, in the classic pcap file format
+//!
+//! A trace contains one packet per decoded frame, as captured by the Wireshark
+//! bridge. Saving a capture from Wireshark in the pcap format results in such
+//! a file, as does writing one directly using [`Writer`].
+
+
+use std::{
+    convert::TryInto as _,
+    io::{
+        self,
+        prelude::*,
+    },
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+
+/// The link-layer type for private use, which our dissector registers for
+pub const LINKTYPE_USER0: u32 = 147;
+
+/// The magic number at the start of a pcap file with microsecond timestamps
+const MAGIC: u32 = 0xa1b2c3d4;
+
+
+/// Writes packets in pcap format
+pub struct Writer<W> {
+    writer: W,
+}
+
+impl<W: Write> Writer<W> {
+    /// Create a new writer and write the pcap header
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&MAGIC.to_le_bytes())?;         // magic number
+        writer.write_all(&2u16.to_le_bytes())?;          // major version
+        writer.write_all(&4u16.to_le_bytes())?;          // minor version
+        writer.write_all(&0i32.to_le_bytes())?;          // time zone offset
+        writer.write_all(&0u32.to_le_bytes())?;          // timestamp accuracy
+        writer.write_all(&65535u32.to_le_bytes())?;      // snapshot length
+        writer.write_all(&LINKTYPE_USER0.to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(Self { writer })
+    }
+
+    /// Write a packet, timestamped with the current time
+    ///
+    /// Flushes the writer, so the packet shows up in Wireshark right away.
+    pub fn write_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let len = data.len() as u32;
+
+        self.writer.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&now.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?; // captured length
+        self.writer.write_all(&len.to_le_bytes())?; // original length
+        self.writer.write_all(data)?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+
+/// Reads packets from a pcap file
+///
+/// Supports files in either byte order, as long as they use microsecond
+/// timestamps and the link-layer type of our dissector.
+pub struct Reader<R> {
+    reader:     R,
+    big_endian: bool,
+}
+
+impl<R: Read> Reader<R> {
+    /// Create a new reader and read the pcap header
+    ///
+    /// Returns an error of kind `InvalidData`, if the file is not in a
+    /// supported format.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header)?;
+
+        let magic = field(&header, 0);
+        let big_endian = if magic == MAGIC.to_le_bytes() {
+            false
+        }
+        else if magic == MAGIC.to_be_bytes() {
+            true
+        }
+        else {
+            return Err(io::ErrorKind::InvalidData.into());
+        };
+
+        let reader = Self { reader, big_endian };
+        if reader.u32(field(&header, 20)) != LINKTYPE_USER0 {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        Ok(reader)
+    }
+
+    /// Read the next packet
+    ///
+    /// Returns `None`, if the end of the file has been reached.
+    pub fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut header = [0; 16];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        }
+
+        let secs   = self.u32(field(&header, 0));
+        let micros = self.u32(field(&header, 4));
+        let len    = self.u32(field(&header, 8));
+
+        let mut data = vec![0; len as usize];
+        self.reader.read_exact(&mut data)?;
+
+        let timestamp = Duration::from_secs(secs.into())
+            + Duration::from_micros(micros.into());
+
+        Ok(Some(Packet { timestamp, data }))
+    }
+
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        }
+        else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_packet().transpose()
+    }
+}
+
+
+/// A packet from a trace file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Packet {
+    /// When the packet was captured, relative to the Unix epoch
+    pub timestamp: Duration,
+
+    /// The decoded frame
+    pub data: Vec<u8>,
+}
+
+
+fn field(header: &[u8], offset: usize) -> [u8; 4] {
+    header[offset..offset + 4].try_into().unwrap()
+}
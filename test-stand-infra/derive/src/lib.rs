@@ -0,0 +1,351 @@
+//! Derive macros for the messages crates of the test stands
+//!
+//! See [`Requests`](derive@Requests).
+
+
+use proc_macro::TokenStream;
+use proc_macro2::{
+    Span,
+    TokenStream as TokenStream2,
+};
+use quote::{
+    format_ident,
+    quote,
+};
+use syn::{
+    Attribute,
+    Data,
+    DeriveInput,
+    Fields,
+    Ident,
+    LitInt,
+    Type,
+    Variant,
+    parenthesized,
+    parse_macro_input,
+    token,
+};
+
+
+/// Generate methods that send requests, and wait for the reply
+///
+/// Derived for the enum of requests that a test node understands. Generates a
+/// trait with one method for every variant that has a `#[request(...)]`
+/// attribute. The method sends the request, receives the next message, and
+/// returns the data of the expected reply. Test suites implement the trait for
+/// the type that represents the test node, by providing the connection to it.
+///
+/// ``` ignore
+/// #[derive(Debug, Deserialize, Requests, Serialize)]
+/// #[requests(name = TargetRequests, reply = TargetToHost)]
+/// pub enum HostToTarget<'r> {
+///     /// Ask the target about its flash memory
+///     #[request(reply = FlashInfo(FlashInfo))]
+///     GetFlashInfo,
+///
+///     /// Instruct the target to move the TX function of its USART
+///     #[request(args(pin), reply = UsartTxMoved)]
+///     MoveUsartTx(pin::PortPin),
+/// }
+/// ```
+///
+/// The enum takes these arguments, which are both required:
+///
+/// - `name`: The name of the generated trait.
+/// - `reply`: The enum of the messages that the test node sends back.
+///
+/// Each variant takes these:
+///
+/// - `reply`: The variant of the reply enum that is expected. Required. If
+///   the variant has a field, its type goes into parentheses, and the method
+///   returns it. It must not borrow from the message. Otherwise, the method
+///   returns `()`.
+/// - `name`: The name of the method. Defaults to the name of the variant, in
+///   snake case.
+/// - `args`: The names of the fields of a tuple variant, which the method
+///   takes as arguments. The fields of a struct variant are taken as arguments
+///   of the same name.
+/// - `timeout_ms`: A fixed timeout for the reply, in milliseconds. Without it,
+///   the method takes a `timeout` argument after the fields.
+///
+/// The generated trait uses `host-lib`, which a messages crate can't depend
+/// on unconditionally, as it is shared with the firmware. It is only compiled
+/// with the `host` feature of that crate, which needs to enable `std` and the
+/// optional dependency on `host-lib`. The methods return
+/// `host_lib::target::TargetRequestError`.
+#[proc_macro_derive(Requests, attributes(requests, request))]
+pub fn derive_requests(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    requests(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+
+fn requests(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`Requests` can only be derived for enums",
+            ));
+        }
+    };
+    if let Some(param) = input.generics.type_params().next() {
+        return Err(syn::Error::new_spanned(
+            param,
+            "`Requests` doesn't support type parameters",
+        ));
+    }
+
+    let (name, reply) = parse_requests(&input.attrs)?;
+    let lifetimes: Vec<_> = input.generics.lifetimes().collect();
+
+    let mut methods = Vec::new();
+    for variant in &data.variants {
+        let attr = variant.attrs.iter()
+            .find(|attr| attr.path().is_ident("request"));
+        if let Some(attr) = attr {
+            let request = parse_request(attr)?;
+            methods.push(
+                method(&input.ident, &reply, &lifetimes, variant, request)?
+            );
+        }
+    }
+
+    let doc = format!(
+        " Methods that send `{}` requests, and wait for the reply",
+        input.ident,
+    );
+
+    Ok(quote! {
+        #[cfg(feature = "host")]
+        #[doc = #doc]
+        #[doc = ""]
+        #[doc = " Generated by `#[derive(Requests)]`."]
+        pub trait #name {
+            /// The connection that requests are sent over
+            fn conn(&mut self) -> &mut ::host_lib::conn::Conn;
+
+            #(#methods)*
+        }
+    })
+}
+
+fn method(
+    request:   &Ident,
+    reply:     &Ident,
+    lifetimes: &[&syn::LifetimeParam],
+    variant:   &Variant,
+    attr:      Request,
+)
+    -> syn::Result<TokenStream2>
+{
+    let ident = &variant.ident;
+    let name  = attr.name.unwrap_or_else(|| {
+        format_ident!("{}", snake_case(&ident.to_string()))
+    });
+
+    let (reply_variant, reply_field) = attr.reply.ok_or_else(|| {
+        syn::Error::new_spanned(ident, "missing `reply` in `#[request(...)]`")
+    })?;
+
+    if !attr.args.is_empty() && !matches!(variant.fields, Fields::Unnamed(_)) {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "`args` is only supported for tuple variants",
+        ));
+    }
+
+    let (params, message) = match &variant.fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            let types = fields.named.iter().map(|field| &field.ty);
+            let names2 = names.clone();
+
+            (
+                quote!(#(#names: #types,)*),
+                quote!(#request::#ident { #(#names2),* }),
+            )
+        }
+        Fields::Unnamed(fields) => {
+            if attr.args.len() != fields.unnamed.len() {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "`args` needs a name for every field of the variant",
+                ));
+            }
+
+            let names = &attr.args;
+            let types = fields.unnamed.iter().map(|field| &field.ty);
+
+            (
+                quote!(#(#names: #types,)*),
+                quote!(#request::#ident(#(#names),*)),
+            )
+        }
+        Fields::Unit => {
+            (quote!(), quote!(#request::#ident))
+        }
+    };
+
+    let (timeout_param, timeout) = match attr.timeout_ms {
+        Some(ms) => {
+            (quote!(), quote!(::std::time::Duration::from_millis(#ms)))
+        }
+        None => {
+            (quote!(timeout: ::std::time::Duration,), quote!(timeout))
+        }
+    };
+
+    let (ty, pattern, value) = match reply_field {
+        Some(ty) => {
+            (quote!(#ty), quote!(#reply::#reply_variant(value)), quote!(value))
+        }
+        None => {
+            (quote!(()), quote!(#reply::#reply_variant), quote!(()))
+        }
+    };
+
+    let docs = variant.attrs.iter()
+        .filter(|attr| attr.path().is_ident("doc"));
+
+    Ok(quote! {
+        #(#docs)*
+        fn #name<#(#lifetimes),*>(&mut self, #params #timeout_param)
+            -> ::core::result::Result<
+                #ty,
+                ::host_lib::target::TargetRequestError,
+            >
+        {
+            use ::host_lib::target::TargetRequestError;
+
+            let conn = self.conn();
+            conn.send(&#message)
+                .map_err(|err| TargetRequestError::Send(err))?;
+
+            let mut tmp = ::std::vec::Vec::new();
+            let message = conn.receive::<#reply>(#timeout, &mut tmp)
+                .map_err(|err| TargetRequestError::Receive(err))?;
+
+            match message {
+                #pattern => {
+                    ::core::result::Result::Ok(#value)
+                }
+                message => {
+                    ::core::result::Result::Err(
+                        TargetRequestError::UnexpectedMessage(
+                            ::std::format!("{:?}", message)
+                        )
+                    )
+                }
+            }
+        }
+    })
+}
+
+
+/// The arguments of `#[requests(...)]`: the name of the trait, and the reply
+fn parse_requests(attrs: &[Attribute]) -> syn::Result<(Ident, Ident)> {
+    let mut name  = None;
+    let mut reply = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("requests")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            }
+            else if meta.path.is_ident("reply") {
+                reply = Some(meta.value()?.parse()?);
+            }
+            else {
+                return Err(meta.error("expected `name` or `reply`"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    let missing = |arg| {
+        syn::Error::new(
+            Span::call_site(),
+            format!("missing `{}` in `#[requests(...)]`", arg),
+        )
+    };
+
+    Ok((
+        name.ok_or_else(|| missing("name"))?,
+        reply.ok_or_else(|| missing("reply"))?,
+    ))
+}
+
+/// The arguments of `#[request(...)]`
+struct Request {
+    name:       Option<Ident>,
+    args:       Vec<Ident>,
+    reply:      Option<(Ident, Option<Type>)>,
+    timeout_ms: Option<LitInt>,
+}
+
+fn parse_request(attr: &Attribute) -> syn::Result<Request> {
+    let mut request = Request {
+        name:       None,
+        args:       Vec::new(),
+        reply:      None,
+        timeout_ms: None,
+    };
+
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("name") {
+            request.name = Some(meta.value()?.parse()?);
+        }
+        else if meta.path.is_ident("args") {
+            meta.parse_nested_meta(|arg| {
+                request.args.push(arg.path.require_ident()?.clone());
+                Ok(())
+            })?;
+        }
+        else if meta.path.is_ident("reply") {
+            let input   = meta.value()?;
+            let variant = input.parse()?;
+
+            let field = if input.peek(token::Paren) {
+                let content;
+                parenthesized!(content in input);
+                Some(content.parse()?)
+            }
+            else {
+                None
+            };
+
+            request.reply = Some((variant, field));
+        }
+        else if meta.path.is_ident("timeout_ms") {
+            request.timeout_ms = Some(meta.value()?.parse()?);
+        }
+        else {
+            return Err(
+                meta.error("expected `name`, `args`, `reply`, or `timeout_ms`")
+            );
+        }
+
+        Ok(())
+    })?;
+
+    Ok(request)
+}
+
+
+/// Converts a variant name, like `GetFlashInfo`, into `get_flash_info`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
@@ -0,0 +1,273 @@
+-- Decodes test stand frames, according to the `types` table above.
+--
+-- The frames are serialized using Postcard, so enum variants and integers are
+-- encoded as varints, and slices are prefixed with their length.
+
+local proto = Proto("test_stand", "Embedded Test Stand")
+
+local f_message = ProtoField.string("test_stand.message", "Message")
+proto.fields = { f_message }
+
+local root_choices = {}
+local root_default = nil
+for i, name in ipairs(roots) do
+    root_choices[i] = { i, name, i }
+    if root_default == nil and name:sub(-6) == "ToHost" then
+        root_default = i
+    end
+end
+
+proto.prefs.root = Pref.enum(
+    "Message type",
+    root_default or 1,
+    "The type of the messages sent by the captured test node",
+    root_choices,
+    false
+)
+
+
+-- Splits a type like `Option<pin::ReadLevelResult<()>>` into its base name and
+-- its generic arguments.
+local function parse_type(ty)
+    local base, rest = ty:match("^([^<]*)<(.*)>$")
+    if base == nil then
+        return ty, {}
+    end
+
+    local args  = {}
+    local depth = 0
+    local start = 1
+    for i = 1, #rest do
+        local c = rest:sub(i, i)
+        if c == "<" or c == "(" or c == "[" then
+            depth = depth + 1
+        elseif c == ">" or c == ")" or c == "]" then
+            depth = depth - 1
+        elseif c == "," and depth == 0 then
+            table.insert(args, rest:sub(start, i - 1))
+            start = i + 1
+        end
+    end
+    table.insert(args, rest:sub(start))
+
+    return base, args
+end
+
+-- Finds a type, relative to the module of the type that references it.
+local function lookup(module, name)
+    if module ~= "" and types[module .. "::" .. name] ~= nil then
+        return module .. "::" .. name
+    end
+    if types[name] ~= nil then
+        return name
+    end
+    return nil
+end
+
+local function read_varint(tvb, offset)
+    local value = 0
+    local mult  = 1
+    local pos   = offset
+
+    while pos < tvb:len() do
+        local b = tvb(pos, 1):uint()
+        pos = pos + 1
+
+        value = value + (b % 128) * mult
+        if b < 128 then
+            return value, pos - offset
+        end
+
+        mult = mult * 128
+    end
+
+    error("truncated varint")
+end
+
+-- Adds a text item, even if it doesn't cover any bytes.
+local function add(tree, tvb, offset, len, label)
+    if len > 0 then
+        return tree:add(tvb(offset, len), label)
+    else
+        return tree:add(proto, label)
+    end
+end
+
+local unsigned = { u16 = true, u32 = true, u64 = true, usize = true }
+local signed   = { i16 = true, i32 = true, i64 = true, isize = true }
+
+local decode
+
+local function decode_fields(tvb, offset, tree, fields, ctx)
+    for i, field in ipairs(fields) do
+        local label = field.name or tostring(i - 1)
+        offset = decode(tvb, offset, tree, label, field.type, ctx)
+    end
+    return offset
+end
+
+local function decode_sequence(tvb, offset, tree, label, n, ty, ctx, header)
+    local subtree = add(tree, tvb, offset, math.min(1, tvb:len() - offset),
+        label .. ": " .. n .. " elements")
+    local pos = offset + header
+    for i = 0, n - 1 do
+        pos = decode(tvb, pos, subtree, tostring(i), ty, ctx)
+    end
+    if pos > offset then
+        subtree:set_len(pos - offset)
+    end
+    return pos
+end
+
+decode = function(tvb, offset, tree, label, ty, ctx)
+    local generic = ctx.subst[ty]
+    if generic ~= nil then
+        return decode(tvb, offset, tree, label, generic.ty, generic.ctx)
+    end
+
+    -- Lifetimes don't matter for decoding, like in `&'r[u8]`.
+    ty = ty:gsub("^&'[%w_]+", "&")
+
+    if ty == "()" then
+        return offset
+    elseif ty == "u8" then
+        tree:add(tvb(offset, 1), label .. ": " .. tvb(offset, 1):uint())
+        return offset + 1
+    elseif ty == "i8" then
+        tree:add(tvb(offset, 1), label .. ": " .. tvb(offset, 1):int())
+        return offset + 1
+    elseif ty == "bool" then
+        tree:add(tvb(offset, 1),
+            label .. ": " .. tostring(tvb(offset, 1):uint() ~= 0))
+        return offset + 1
+    elseif unsigned[ty] then
+        local value, len = read_varint(tvb, offset)
+        tree:add(tvb(offset, len), label .. ": " .. string.format("%d", value))
+        return offset + len
+    elseif signed[ty] then
+        local value, len = read_varint(tvb, offset)
+        if value % 2 == 0 then
+            value = math.floor(value / 2)
+        else
+            value = -math.floor((value + 1) / 2)
+        end
+        tree:add(tvb(offset, len), label .. ": " .. string.format("%d", value))
+        return offset + len
+    elseif ty == "f32" then
+        tree:add(tvb(offset, 4), label .. ": " .. tvb(offset, 4):le_float())
+        return offset + 4
+    elseif ty == "f64" then
+        tree:add(tvb(offset, 8), label .. ": " .. tvb(offset, 8):le_float())
+        return offset + 8
+    elseif ty == "&[u8]" or ty == "&str" then
+        local n, len = read_varint(tvb, offset)
+        local value = ""
+        if n > 0 then
+            if ty == "&str" then
+                value = tvb(offset + len, n):string()
+            else
+                value = tostring(tvb(offset + len, n):bytes())
+            end
+        end
+        add(tree, tvb, offset, len + n, label .. ": " .. value)
+        return offset + len + n
+    end
+
+    local elem, count = ty:match("^%[(.*);(%d+)%]$")
+    if elem ~= nil then
+        return decode_sequence(tvb, offset, tree, label, tonumber(count), elem,
+            ctx, 0)
+    end
+    elem = ty:match("^&%[(.*)%]$")
+    if elem ~= nil then
+        local n, len = read_varint(tvb, offset)
+        return decode_sequence(tvb, offset, tree, label, n, elem, ctx, len)
+    end
+
+    local base, args = parse_type(ty)
+
+    if base == "Option" then
+        if tvb(offset, 1):uint() == 0 then
+            tree:add(tvb(offset, 1), label .. ": None")
+            return offset + 1
+        end
+        return decode(tvb, offset + 1, tree, label, args[1], ctx)
+    elseif base == "Vec" then
+        -- Covers `Vec<T>`, as well as `heapless::Vec<T, N>`.
+        local n, len = read_varint(tvb, offset)
+        return decode_sequence(tvb, offset, tree, label, n, args[1], ctx, len)
+    end
+
+    local name = lookup(ctx.module, base)
+    if name == nil then
+        add(tree, tvb, offset, tvb:len() - offset,
+            label .. ": <unknown type " .. ty .. ">")
+        return tvb:len()
+    end
+    local def = types[name]
+
+    -- Lifetimes are not passed as arguments in field types, so only map type
+    -- parameters.
+    local subst  = {}
+    local params = {}
+    for _, param in ipairs(def.generics) do
+        if param:sub(1, 1) ~= "'" then
+            table.insert(params, param)
+        end
+    end
+    for i, param in ipairs(params) do
+        if args[i] ~= nil then
+            subst[param] = { ty = args[i], ctx = ctx }
+        end
+    end
+    local inner = { module = def.module, subst = subst }
+
+    if def.variants ~= nil then
+        local index, len = read_varint(tvb, offset)
+        local variant = def.variants[index]
+        if variant == nil then
+            tree:add(tvb(offset, len),
+                label .. ": <unknown variant " .. index .. ">")
+            return tvb:len()
+        end
+
+        local subtree = tree:add(tvb(offset, len), label .. ": " .. variant.name)
+        local pos = decode_fields(tvb, offset + len, subtree, variant.fields,
+            inner)
+        subtree:set_len(pos - offset)
+        return pos
+    else
+        local subtree = add(tree, tvb, offset,
+            math.min(1, tvb:len() - offset), label .. ": " .. base)
+        local pos = decode_fields(tvb, offset, subtree, def.fields, inner)
+        if pos > offset then
+            subtree:set_len(pos - offset)
+        end
+        return pos
+    end
+end
+
+
+function proto.dissector(tvb, pinfo, tree)
+    pinfo.cols.protocol = "TEST-STAND"
+
+    local root    = roots[proto.prefs.root]
+    local subtree = tree:add(proto, tvb(), "Embedded Test Stand, " .. root)
+
+    local ok, err = pcall(function()
+        local index   = read_varint(tvb, 0)
+        local variant = types[root].variants[index]
+        if variant ~= nil then
+            pinfo.cols.info = root .. "::" .. variant.name
+            subtree:add(f_message, variant.name)
+        end
+
+        decode(tvb, 0, subtree, root, root, { module = "", subst = {} })
+    end)
+    if not ok then
+        subtree:add_expert_info(PI_MALFORMED, PI_ERROR, tostring(err))
+    end
+end
+
+local encaps = wtap_encaps or wtap
+DissectorTable.get("wtap_encap"):add(encaps.USER0, proto)
@@ -0,0 +1,128 @@
+//! Generates a Wireshark Lua dissector from a message schema
+
+
+use std::io::{
+    self,
+    prelude::*,
+};
+
+use test_stand_decoder::schema::{
+    Field,
+    Schema,
+    TypeKind,
+};
+
+
+/// The part of the dissector that doesn't depend on the schema
+const DECODER: &str = include_str!("dissector.lua");
+
+
+/// Write a Lua dissector for the messages described by `schema`
+pub fn generate(schema: &Schema, w: &mut impl Write) -> io::Result<()> {
+    writeln!(w,
+        "-- Wireshark dissector for the messages of {} {}",
+        schema.crate_name,
+        schema.version,
+    )?;
+    writeln!(w, "--")?;
+    writeln!(w, "-- Generated by test-stand-extcap. Do not edit.")?;
+    writeln!(w)?;
+
+    writeln!(w, "local types = {{")?;
+    for ty in &schema.types {
+        let name = if ty.module.is_empty() {
+            ty.name.clone()
+        }
+        else {
+            format!("{}::{}", ty.module, ty.name)
+        };
+
+        writeln!(w, "    [{}] = {{", lua_str(&name))?;
+        writeln!(w, "        module = {},", lua_str(&ty.module))?;
+        writeln!(w, "        generics = {},",
+            lua_table(ty.generics.iter().map(|generic| lua_str(generic))),
+        )?;
+
+        match &ty.kind {
+            TypeKind::Enum { variants } => {
+                writeln!(w, "        variants = {{")?;
+                for variant in variants {
+                    writeln!(w,
+                        "            [{}] = {{ name = {}, fields = {} }},",
+                        variant.index,
+                        lua_str(&variant.name),
+                        fields(&variant.fields),
+                    )?;
+                }
+                writeln!(w, "        }},")?;
+            }
+            TypeKind::Struct { fields: f } => {
+                writeln!(w, "        fields = {},", fields(f))?;
+            }
+        }
+
+        writeln!(w, "    }},")?;
+    }
+    writeln!(w, "}}")?;
+    writeln!(w)?;
+
+    // Top-level enums are the candidates for the message type that's being
+    // captured.
+    writeln!(w, "local roots = {{")?;
+    for ty in &schema.types {
+        if ty.module.is_empty() {
+            if let TypeKind::Enum { .. } = ty.kind {
+                writeln!(w, "    {},", lua_str(&ty.name))?;
+            }
+        }
+    }
+    writeln!(w, "}}")?;
+    writeln!(w)?;
+
+    w.write_all(DECODER.as_bytes())?;
+
+    Ok(())
+}
+
+
+fn fields(fields: &[Field]) -> String {
+    let fields = fields.iter().map(|field| {
+        match &field.name {
+            Some(name) => {
+                format!(
+                    "{{ name = {}, type = {} }}",
+                    lua_str(name),
+                    lua_str(&field.ty),
+                )
+            }
+            None => {
+                format!("{{ type = {} }}", lua_str(&field.ty))
+            }
+        }
+    });
+
+    lua_table(fields)
+}
+
+fn lua_table(items: impl Iterator<Item=String>) -> String {
+    let items = items.collect::<Vec<_>>();
+
+    if items.is_empty() {
+        return String::from("{}");
+    }
+
+    format!("{{ {} }}", items.join(", "))
+}
+
+fn lua_str(s: &str) -> String {
+    let mut lua = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => lua.push_str("\\\""),
+            '\\' => lua.push_str("\\\\"),
+            c    => lua.push(c),
+        }
+    }
+    lua.push('"');
+    lua
+}
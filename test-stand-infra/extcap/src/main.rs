@@ -0,0 +1,335 @@
+//! Wireshark extcap bridge for the test stand control link
+//!
+//! Exposes the serial connection to a test node as a live capture in
+//! Wireshark. Every frame received from the test node becomes one packet. The
+//! packets can be decoded by a Lua dissector that this tool generates from a
+//! message schema.
+//!
+//! To use it, copy (or link) the binary into Wireshark's extcap directory, and
+//! the generated dissector into its plugin directory:
+//!
+//! ``` bash
+//! test-stand-extcap --generate-dissector schema.json > test_stand.lua
+//! ```
+//!
+//! Captures saved in the pcap format can also be printed without Wireshark:
+//!
+//! ``` bash
+//! test-stand-extcap --print-trace capture.pcap --schema schema.json
+//! ```
+
+
+mod dissector;
+
+
+use std::{
+    env,
+    fs::File,
+    io::{
+        self,
+        prelude::*,
+    },
+    process,
+    time::Duration,
+};
+
+use host_lib::conn::{
+    Conn,
+    ConnInitError,
+    ConnReceiveError,
+};
+use protocol::{
+    checksum::Checksum,
+    compress::Compression,
+};
+use test_stand_decoder::{
+    pretty,
+    schema::{
+        Schema,
+        SchemaLoadError,
+        TypeKind,
+    },
+    trace,
+};
+
+
+/// The name of the only interface this tool provides
+const INTERFACE: &str = "test-stand";
+
+
+fn main() {
+    let args = Args::parse(env::args().skip(1));
+
+    if let Err(err) = run(args) {
+        eprintln!("Error: {:?}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    if let Some(path) = &args.generate_dissector {
+        let schema = Schema::load(path)?;
+        dissector::generate(&schema, &mut stdout)?;
+        return Ok(());
+    }
+
+    if let Some(path) = &args.print_trace {
+        let schema = args.schema
+            .ok_or(Error::MissingArgument("--schema"))?;
+        let schema = Schema::load(schema)?;
+
+        return print_trace(&schema, args.root.as_deref(), path, &mut stdout);
+    }
+
+    if args.extcap_interfaces {
+        writeln!(stdout,
+            "extcap {{version={}}}\
+            {{help=https://github.com/braun-embedded/embedded-test-stand}}",
+            env!("CARGO_PKG_VERSION"),
+        )?;
+        writeln!(stdout,
+            "interface {{value={}}}\
+            {{display=Embedded Test Stand control link}}",
+            INTERFACE,
+        )?;
+        return Ok(());
+    }
+
+    if args.extcap_interface.as_deref() != Some(INTERFACE) {
+        return Err(Error::UnknownInterface(args.extcap_interface));
+    }
+
+    if args.extcap_dlts {
+        writeln!(stdout,
+            "dlt {{number={}}}{{name=USER0}}{{display=Test stand frames}}",
+            trace::LINKTYPE_USER0,
+        )?;
+        return Ok(());
+    }
+
+    if args.extcap_config {
+        writeln!(stdout,
+            "arg {{number=0}}{{call=--port}}{{display=Serial port}}\
+            {{tooltip=Serial device connected to the test node}}\
+            {{type=string}}{{required=true}}",
+        )?;
+        writeln!(stdout,
+            "arg {{number=1}}{{call=--compression}}{{display=Compression}}\
+            {{tooltip=Compression scheme negotiated by the test suite}}\
+            {{type=selector}}",
+        )?;
+        writeln!(stdout,
+            "value {{arg=1}}{{value=None}}{{display=None}}{{default=true}}",
+        )?;
+        writeln!(stdout, "value {{arg=1}}{{value=Rle}}{{display=RLE}}")?;
+        writeln!(stdout,
+            "arg {{number=2}}{{call=--checksum}}{{display=Checksum}}\
+            {{tooltip=Checksum negotiated by the test suite}}\
+            {{type=selector}}",
+        )?;
+        writeln!(stdout,
+            "value {{arg=2}}{{value=None}}{{display=None}}{{default=true}}",
+        )?;
+        writeln!(stdout, "value {{arg=2}}{{value=Crc16}}{{display=CRC-16}}")?;
+        writeln!(stdout, "value {{arg=2}}{{value=Crc32}}{{display=CRC-32}}")?;
+        return Ok(());
+    }
+
+    if args.capture {
+        let port = args.port
+            .ok_or(Error::MissingArgument("--port"))?;
+        let fifo = args.fifo
+            .ok_or(Error::MissingArgument("--fifo"))?;
+
+        return capture(&port, &fifo, args.compression, args.checksum);
+    }
+
+    Ok(())
+}
+
+fn capture(
+    port:        &str,
+    fifo:        &str,
+    compression: Compression,
+    checksum:    Checksum,
+)
+    -> Result<(), Error>
+{
+    let mut conn = Conn::new(port)?;
+    conn.set_compression(compression);
+    conn.set_checksum(checksum);
+
+    let mut trace = trace::Writer::new(File::create(fifo)?)?;
+    let mut buf   = Vec::new();
+
+    loop {
+        match conn.receive_frame(Duration::from_secs(1), &mut buf) {
+            Ok(()) => {
+                trace.write_packet(&buf)?;
+            }
+            Err(err) if err.is_timeout() => {
+                continue;
+            }
+            Err(err) => {
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+
+fn print_trace(
+    schema: &Schema,
+    root:   Option<&str>,
+    path:   &str,
+    w:      &mut impl Write,
+)
+    -> Result<(), Error>
+{
+    // Like the dissector, default to the messages sent by a test node.
+    let root = root
+        .or_else(|| {
+            schema.types
+                .iter()
+                .find(|ty| {
+                    ty.module.is_empty()
+                        && ty.name.ends_with("ToHost")
+                        && matches!(ty.kind, TypeKind::Enum { .. })
+                })
+                .map(|ty| ty.name.as_str())
+        })
+        .ok_or(Error::MissingArgument("--root"))?;
+
+    for packet in trace::Reader::new(File::open(path)?)? {
+        let packet = packet?;
+
+        write!(w, "{:17.6} ", packet.timestamp.as_secs_f64())?;
+        match pretty::format(schema, root, &packet.data) {
+            Ok(message) => writeln!(w, "{}", message)?,
+            Err(err)    => writeln!(w, "<{:?}: {:02x?}>", err, packet.data)?,
+        }
+    }
+
+    Ok(())
+}
+
+
+/// The command-line arguments
+///
+/// Wireshark passes these, as specified by the extcap interface. Unknown
+/// arguments are ignored, as Wireshark might pass more than we need.
+#[derive(Default)]
+struct Args {
+    extcap_interfaces:  bool,
+    extcap_interface:   Option<String>,
+    extcap_dlts:        bool,
+    extcap_config:      bool,
+    capture:            bool,
+    fifo:               Option<String>,
+    port:               Option<String>,
+    compression:        Compression,
+    checksum:           Checksum,
+    generate_dissector: Option<String>,
+    print_trace:        Option<String>,
+    schema:             Option<String>,
+    root:               Option<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item=String>) -> Self {
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--extcap-interfaces" => {
+                    parsed.extcap_interfaces = true;
+                }
+                "--extcap-interface" => {
+                    parsed.extcap_interface = args.next();
+                }
+                "--extcap-dlts" => {
+                    parsed.extcap_dlts = true;
+                }
+                "--extcap-config" => {
+                    parsed.extcap_config = true;
+                }
+                "--capture" => {
+                    parsed.capture = true;
+                }
+                "--fifo" => {
+                    parsed.fifo = args.next();
+                }
+                "--port" => {
+                    parsed.port = args.next();
+                }
+                "--compression" => {
+                    if let Some("Rle") = args.next().as_deref() {
+                        parsed.compression = Compression::Rle;
+                    }
+                }
+                "--checksum" => {
+                    match args.next().as_deref() {
+                        Some("Crc16") => parsed.checksum = Checksum::Crc16,
+                        Some("Crc32") => parsed.checksum = Checksum::Crc32,
+                        _             => {}
+                    }
+                }
+                "--generate-dissector" => {
+                    parsed.generate_dissector = args.next();
+                }
+                "--print-trace" => {
+                    parsed.print_trace = args.next();
+                }
+                "--schema" => {
+                    parsed.schema = args.next();
+                }
+                "--root" => {
+                    parsed.root = args.next();
+                }
+                _ => {}
+            }
+        }
+
+        parsed
+    }
+}
+
+
+// The fields are only read through `Debug`, when the error is printed.
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Error {
+    ConnInit(ConnInitError),
+    ConnReceive(ConnReceiveError),
+    Io(io::Error),
+    MissingArgument(&'static str),
+    SchemaLoad(SchemaLoadError),
+    UnknownInterface(Option<String>),
+}
+
+impl From<ConnInitError> for Error {
+    fn from(err: ConnInitError) -> Self {
+        Self::ConnInit(err)
+    }
+}
+
+impl From<ConnReceiveError> for Error {
+    fn from(err: ConnReceiveError) -> Self {
+        Self::ConnReceive(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<SchemaLoadError> for Error {
+    fn from(err: SchemaLoadError) -> Self {
+        Self::SchemaLoad(err)
+    }
+}
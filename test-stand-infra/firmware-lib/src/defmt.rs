@@ -0,0 +1,169 @@
+//! Logging using defmt, over the host USART
+//!
+//! defmt-rtt needs a debug probe to be attached, which isn't always the case,
+//! for example on CI runners. With the `defmt` feature enabled, this module
+//! provides the global defmt logger instead. It queues the encoded frames, and
+//! the idle loop passes them to [`take`], to send them to the host along with
+//! all other messages. The test suite decodes them, using the ELF file of the
+//! firmware, and prints them like the log records sent using [`log!`].
+//!
+//! Firmware that enables the feature needs to link with `defmt.x`, in addition
+//! to `link.x`.
+//!
+//! [`log!`]: crate::log!
+
+
+use core::{
+    cell::RefCell,
+    ptr,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use ::defmt::Encoder;
+use cortex_m::{
+    interrupt::{
+        self,
+        Mutex,
+    },
+    register::primask,
+};
+use heapless::{
+    Vec,
+    spsc::Queue,
+};
+
+use crate::overrun;
+
+
+/// The maximum size of an encoded frame, in bytes
+///
+/// Frames that are larger are dropped.
+pub const FRAME_CAP: usize = 128;
+
+/// The size of the queue that frames wait in for the idle loop, in bytes
+///
+/// Frames that don't fit are dropped.
+pub const QUEUE_CAP: usize = 512;
+
+
+static QUEUE: Mutex<RefCell<Queue<u8, QUEUE_CAP>>> =
+    Mutex::new(RefCell::new(Queue::new()));
+
+/// Counts the frames that have been dropped
+static DROPPED: overrun::Counter = overrun::Counter::new();
+
+/// Set while a frame is being logged
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+// Only accessed while the logger is acquired, with interrupts disabled.
+static mut RESTORE: bool = false;
+static mut ENCODER: Encoder = Encoder::new();
+static mut FRAME: Vec<u8, FRAME_CAP> = Vec::new();
+static mut TRUNCATED: bool = false;
+
+
+/// Take queued frames, to send them to the host
+///
+/// Moves as many bytes as fit out of the queue into `buf`, and returns them.
+/// The result can end in the middle of a frame. The host decodes the rest of
+/// it, once it has been received. Returns an empty slice, if nothing has been
+/// logged.
+pub fn take(buf: &mut [u8]) -> &[u8] {
+    let n = interrupt::free(|cs| {
+        let mut queue = QUEUE.borrow(cs).borrow_mut();
+
+        let mut n = 0;
+        for b in buf.iter_mut() {
+            match queue.dequeue() {
+                Some(byte) => *b = byte,
+                None       => break,
+            }
+            n += 1;
+        }
+
+        n
+    });
+
+    &buf[..n]
+}
+
+/// Return the number of frames dropped since the last call
+///
+/// Frames are dropped, if they are larger than [`FRAME_CAP`], or if they
+/// don't fit into the queue. Like for the firmware's own queues, the idle loop
+/// should report this to the host.
+pub fn take_dropped() -> u32 {
+    DROPPED.take()
+}
+
+
+#[::defmt::global_logger]
+struct Logger;
+
+unsafe impl ::defmt::Logger for Logger {
+    fn acquire() {
+        let restore = primask::read().is_active();
+        interrupt::disable();
+
+        if TAKEN.load(Ordering::Relaxed) {
+            panic!("defmt logger taken reentrantly");
+        }
+        TAKEN.store(true, Ordering::Relaxed);
+
+        // Sound, as interrupts are disabled, and `TAKEN` makes sure that no
+        // other frame is being logged.
+        unsafe {
+            RESTORE = restore;
+            TRUNCATED = false;
+            (*ptr::addr_of_mut!(FRAME)).clear();
+            (*ptr::addr_of_mut!(ENCODER)).start_frame(write);
+        }
+    }
+
+    unsafe fn flush() {
+        // Frames are sent by the idle loop. There's nothing to wait for here.
+    }
+
+    unsafe fn release() {
+        (*ptr::addr_of_mut!(ENCODER)).end_frame(write);
+
+        let frame = &*ptr::addr_of!(FRAME);
+        let queued = !TRUNCATED && interrupt::free(|cs| {
+            let mut queue = QUEUE.borrow(cs).borrow_mut();
+            if queue.capacity() - queue.len() < frame.len() {
+                return false;
+            }
+            for &b in frame.iter() {
+                // Can't fail, as we checked there's enough room.
+                let _ = queue.enqueue(b);
+            }
+            true
+        });
+        if !queued {
+            DROPPED.add(1);
+        }
+
+        TAKEN.store(false, Ordering::Relaxed);
+        if RESTORE {
+            interrupt::enable();
+        }
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        (*ptr::addr_of_mut!(ENCODER)).write(bytes, write);
+    }
+}
+
+
+/// Write encoded bytes into the frame that is being logged
+fn write(bytes: &[u8]) {
+    // Sound, as this is only called while the logger is acquired.
+    unsafe {
+        if (*ptr::addr_of_mut!(FRAME)).extend_from_slice(bytes).is_err() {
+            TRUNCATED = true;
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Recording of significant events, for the host to retrieve later
+//!
+//! Events are recorded into a global ring buffer using [`record`], which can be
+//! called from any context, including interrupt handlers. Once the buffer is
+//! full, the oldest events are overwritten. [`dump`] returns what's left, so
+//! the firmware can send it to the host on request. See `protocol::event`.
+
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{
+    self,
+    Mutex,
+};
+use protocol::event::{
+    CAP,
+    Event,
+    Events,
+    Kind,
+};
+
+
+static RING: Mutex<RefCell<Ring>> = Mutex::new(RefCell::new(Ring::new()));
+
+
+/// Record an event
+///
+/// If the most recent event is of the same kind, its count is incremented
+/// instead of recording a new event.
+pub fn record(kind: Kind) {
+    interrupt::free(|cs| RING.borrow(cs).borrow_mut().record(kind))
+}
+
+/// Return the recorded events, oldest first
+pub fn dump() -> Events {
+    interrupt::free(|cs| RING.borrow(cs).borrow().dump())
+}
+
+
+struct Ring {
+    events: [Event; CAP],
+
+    /// The index of the oldest event
+    start: usize,
+    len:   usize,
+
+    next_seq: u32,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        const EMPTY: Event = Event {
+            seq:   0,
+            kind:  Kind::Request(0),
+            count: 0,
+        };
+
+        Self {
+            events:   [EMPTY; CAP],
+            start:    0,
+            len:      0,
+            next_seq: 0,
+        }
+    }
+
+    fn record(&mut self, kind: Kind) {
+        if self.len > 0 {
+            let last = &mut self.events[(self.start + self.len - 1) % CAP];
+            if last.kind == kind {
+                last.count = last.count.saturating_add(1);
+                return;
+            }
+        }
+
+        let event = Event {
+            seq:   self.next_seq,
+            kind,
+            count: 1,
+        };
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        if self.len < CAP {
+            self.events[(self.start + self.len) % CAP] = event;
+            self.len += 1;
+        }
+        else {
+            self.events[self.start] = event;
+            self.start = (self.start + 1) % CAP;
+        }
+    }
+
+    fn dump(&self) -> Events {
+        let mut events = Events::new();
+
+        for i in 0 .. self.len {
+            // Can't fail, as `Events` has room for `CAP` events.
+            let _ = events.push(self.events[(self.start + i) % CAP]);
+        }
+
+        events
+    }
+}
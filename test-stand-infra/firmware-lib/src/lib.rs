@@ -4,5 +4,13 @@
 #![no_std]
 
 
+#[cfg(feature = "defmt")]
+pub mod defmt;
+pub mod event;
+pub mod log;
+pub mod overrun;
+#[cfg(feature = "lpc8xx")]
+pub mod panic;
+#[cfg(feature = "lpc8xx")]
 pub mod pin_interrupt;
 pub mod usart;
@@ -0,0 +1,97 @@
+//! Logging to the host, over the host USART
+//!
+//! `rprintln!` needs a debug probe to be attached, which isn't always the case,
+//! for example on CI runners. Log records sent using the [`log!`] macro arrive
+//! at the host together with all other messages, and are printed by the test
+//! suite.
+//!
+//! [`log!`]: crate::log!
+
+
+use core::fmt::{
+    self,
+    Write as _,
+};
+
+use heapless::String;
+
+
+/// The maximum length of a log message, in bytes
+///
+/// Longer messages are truncated. Together with the rest of the message, this
+/// needs to fit into the buffer that is passed to [`log!`].
+///
+/// [`log!`]: crate::log!
+pub const MESSAGE_CAP: usize = 128;
+
+
+/// Format a log message
+///
+/// Used by [`log!`]. Truncates the message, if it doesn't fit into
+/// [`MESSAGE_CAP`] bytes.
+///
+/// [`log!`]: crate::log!
+pub fn format(args: fmt::Arguments) -> String<MESSAGE_CAP> {
+    let mut message = String::new();
+
+    // `Truncate` never returns an error.
+    let _ = Truncate(&mut message).write_fmt(args);
+
+    message
+}
+
+
+/// Send a log record to the host
+///
+/// Accepts the following arguments:
+/// - The [`Tx`] that is connected to the host.
+/// - The buffer that is used to serialize the message.
+/// - The path of the message variant that carries log records. It must have
+///   the fields `level` and `message`.
+/// - The level of the log record, as a `protocol::log::Level`.
+/// - The format string and its arguments, like for `format_args!`.
+///
+/// Evaluates to the result of [`Tx::send_message`].
+///
+/// [`Tx`]: crate::usart::Tx
+/// [`Tx::send_message`]: crate::usart::Tx::send_message
+#[macro_export]
+macro_rules! log {
+    (
+        $tx:expr,
+        $buf:expr,
+        $($message:ident)::+,
+        $level:expr,
+        $($arg:tt)*
+    ) => {
+        $tx.send_message(
+            &$($message)::+ {
+                level:   $level,
+                message: &$crate::log::format(format_args!($($arg)*)),
+            },
+            $buf,
+        )
+    };
+}
+
+
+/// Writes into a string, dropping anything that doesn't fit
+pub(crate) struct Truncate<'r>(pub &'r mut String<MESSAGE_CAP>);
+
+impl fmt::Write for Truncate<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = self.0.capacity() - self.0.len();
+
+        let mut end = s.len().min(space);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        // Can't fail, as we made sure it fits. Slicing with `get` instead of
+        // indexing keeps the code for reporting slicing errors out of the
+        // firmware.
+        let _ = self.0.push_str(s.get(.. end).unwrap_or(""));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! Counting of data that was dropped, because a queue was full
+//!
+//! Interrupt handlers pass received data to the idle loop through queues. If
+//! the idle loop can't keep up, a queue fills up, and the data that doesn't fit
+//! is dropped and counted using [`Counter`]. The idle loop takes the count once
+//! it has drained the queue, and reports it to the host, which can then fail
+//! the test case for that reason, instead of the firmware panicking.
+
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::{
+    self,
+    Mutex,
+};
+
+
+/// Counts dropped data
+///
+/// Can be used from any context, including interrupt handlers, and can be
+/// allocated in a `static`.
+pub struct Counter {
+    dropped: Mutex<Cell<u32>>,
+}
+
+impl Counter {
+    /// Create a new instance of `Counter`
+    pub const fn new() -> Self {
+        Self {
+            dropped: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Count `n` dropped items
+    ///
+    /// Saturates at `u32::MAX`.
+    pub fn add(&self, n: u32) {
+        interrupt::free(|cs| {
+            let dropped = self.dropped.borrow(cs);
+            dropped.set(dropped.get().saturating_add(n));
+        })
+    }
+
+    /// Return the number of items dropped since the last call
+    pub fn take(&self) -> u32 {
+        interrupt::free(|cs| self.dropped.borrow(cs).replace(0))
+    }
+}
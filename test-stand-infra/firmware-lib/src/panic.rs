@@ -0,0 +1,263 @@
+//! Reporting panics to the host, over the host USART
+//!
+//! `panic_rtt_target` prints the panic over RTT, which needs a debug probe to
+//! be attached. All the host sees is that the firmware no longer replies, so
+//! the test case fails with a timeout. [`report`] sends the panic message and
+//! location to the host instead, so the test suite can print what actually
+//! went wrong.
+//!
+//! This is best effort. The [`Tx`] of the host USART belongs to whatever code
+//! panicked, or was interrupted by the panic, so the report is written to the
+//! USART's registers directly. If the firmware sends through a [`Sender`],
+//! whatever is still queued goes out first (see [`set_sender`]). If a message
+//! was being sent at the time, the host receives it cut off, but the report
+//! still arrives in a frame of its own.
+//!
+//! [`Tx`]: crate::usart::Tx
+//! [`Sender`]: crate::usart::Sender
+
+
+use core::{
+    cell::Cell,
+    fmt::Write as _,
+    panic::PanicInfo,
+    ptr,
+    sync::atomic::{
+        self,
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use heapless::String;
+use lpc8xx_hal::{
+    cortex_m::interrupt::{
+        self,
+        Mutex,
+    },
+    pac::usart0::RegisterBlock,
+    usart,
+};
+use protocol::{
+    checksum::Checksum,
+    compress::Compression,
+    panic::Report,
+};
+use serde::Serialize;
+
+use crate::{
+    log::{
+        MESSAGE_CAP,
+        Truncate,
+    },
+    usart::{
+        Sender,
+        tx,
+    },
+};
+
+
+/// The size of the buffer that reports are serialized into
+const BUF_CAP: usize = 256;
+
+
+static HOST: Mutex<Cell<Option<Host>>> = Mutex::new(Cell::new(None));
+
+/// Set while a panic is reported, to catch panics from within [`report`]
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+// Reserved, as the stack might not have much room left when panicking. Only
+// accessed from `report`, and `REPORTING` makes sure that isn't re-entered.
+static mut MESSAGE: String<MESSAGE_CAP> = String::new();
+static mut BUF: [u8; BUF_CAP] = [0; BUF_CAP];
+
+
+/// Report panics over the USART instance `I`
+///
+/// Call this once the host USART has been enabled. Until then, panics aren't
+/// reported.
+pub fn init<I>()
+    where I: usart::Instance
+{
+    interrupt::free(|cs| {
+        HOST.borrow(cs).set(Some(Host {
+            usart:       I::REGISTERS,
+            sender:      None,
+            compression: Compression::None,
+            checksum:    Checksum::None,
+        }));
+    })
+}
+
+/// Send the data queued in `sender`, before reporting a panic
+///
+/// Call this after [`init`], if the firmware sends to the host through a
+/// [`Sender`]. A reply might still be queued when a panic happens. Otherwise,
+/// it would be lost, along with the start of the report.
+///
+/// # Safety
+///
+/// `sender` must stay valid for the rest of the program's run time.
+///
+/// [`Sender`]: crate::usart::Sender
+pub unsafe fn set_sender(sender: *const Sender) {
+    interrupt::free(|cs| {
+        let host = HOST.borrow(cs);
+        if let Some(mut h) = host.get() {
+            h.sender = Some(sender);
+            host.set(Some(h));
+        }
+    })
+}
+
+/// Frame reports with the given compression scheme and checksum
+///
+/// Call this whenever the compression scheme or checksum of the host USART's
+/// [`Tx`] changes, so the host can decode the report.
+///
+/// [`Tx`]: crate::usart::Tx
+pub fn set_framing(compression: Compression, checksum: Checksum) {
+    interrupt::free(|cs| {
+        let host = HOST.borrow(cs);
+        if let Some(mut h) = host.get() {
+            h.compression = compression;
+            h.checksum    = checksum;
+            host.set(Some(h));
+        }
+    })
+}
+
+/// Report a panic to the host
+///
+/// `message` wraps the report into the message that carries it, for example
+/// `TargetToHost::Panic`. Blocks until the message has been sent. Does nothing,
+/// if [`init`] hasn't been called, or if a panic happens while reporting one.
+///
+/// Disables interrupts, so nothing interferes with sending the report.
+pub fn report<'r, T>(info: &'r PanicInfo, message: impl FnOnce(Report<'r>) -> T)
+    where T: Serialize
+{
+    interrupt::disable();
+
+    if REPORTING.load(Ordering::Relaxed) {
+        return;
+    }
+    REPORTING.store(true, Ordering::Relaxed);
+
+    let host = match interrupt::free(|cs| HOST.borrow(cs).get()) {
+        Some(host) => host,
+        None       => return,
+    };
+
+    // Sound, as interrupts are disabled, and `REPORTING` prevents this from
+    // being re-entered.
+    let (text, buf) = unsafe {
+        (
+            &mut *ptr::addr_of_mut!(MESSAGE),
+            &mut *ptr::addr_of_mut!(BUF),
+        )
+    };
+
+    text.clear();
+    // `Truncate` never returns an error.
+    let _ = write!(Truncate(text), "{}", info.message());
+
+    let (file, line, column) = match info.location() {
+        Some(location) => {
+            (location.file(), location.line(), location.column())
+        }
+        None => {
+            ("<unknown>", 0, 0)
+        }
+    };
+
+    let report = Report {
+        message: text.as_str(),
+        file,
+        line,
+        column,
+    };
+
+    let data = match tx::serialize(
+        &message(report),
+        host.compression,
+        host.checksum,
+        buf,
+    ) {
+        Ok(data) => data,
+        Err(_)   => return,
+    };
+
+    // Sound, as the pointer comes from the USART instance, and the registers
+    // live for the whole program.
+    let usart = unsafe { &*host.usart };
+
+    if let Some(sender) = host.sender {
+        // Sound, as `set_sender` requires the pointer to stay valid, and
+        // interrupts are disabled for good, so the queue isn't accessed
+        // anywhere else anymore.
+        for b in unsafe { Sender::queued(sender) } {
+            send_byte(usart, b);
+        }
+    }
+
+    // Terminates the frame that was being sent when the panic happened, if
+    // any, so the host doesn't merge it with the report. Otherwise, the host
+    // receives an empty frame, which it ignores.
+    send_byte(usart, 0);
+
+    for &b in data.iter() {
+        send_byte(usart, b);
+    }
+    while usart.stat.read().txidle().bit_is_clear() {}
+}
+
+/// Stop execution, after a panic has been reported
+pub fn halt() -> ! {
+    interrupt::disable();
+
+    loop {
+        atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+
+/// Define a panic handler that reports panics to the host
+///
+/// Accepts the path of the message variant that carries the report, for
+/// example `AssistantToHost::Panic`. The panic handler calls [`report`], then
+/// [`halt`]s.
+///
+/// [`report`]: crate::panic::report
+/// [`halt`]: crate::panic::halt
+#[macro_export]
+macro_rules! panic_handler {
+    ($($message:ident)::+) => {
+        #[panic_handler]
+        fn panic(info: &core::panic::PanicInfo) -> ! {
+            $crate::panic::report(info, $($message)::+);
+            $crate::panic::halt()
+        }
+    };
+}
+
+
+fn send_byte(usart: &RegisterBlock, b: u8) {
+    while usart.stat.read().txrdy().bit_is_clear() {}
+    usart.txdat.write(|w| unsafe { w.txdat().bits(b.into()) });
+}
+
+
+#[derive(Clone, Copy)]
+struct Host {
+    usart:       *const RegisterBlock,
+    sender:      Option<*const Sender>,
+    compression: Compression,
+    checksum:    Checksum,
+}
+
+// Sound, as the pointers refer to the registers of a USART instance, which are
+// valid in any context, and to a `Sender`, which is only accessed from
+// `report`.
+unsafe impl Send for Host {}
+
@@ -15,10 +15,13 @@ use lpc8xx_hal::{
     pins,
 };
 
+use crate::overrun;
+
 
 /// Represents a pin interrupt
 pub struct PinInterrupt {
-    queue: Queue<Event, QUEUE_CAP>,
+    queue:   Queue<Event, QUEUE_CAP>,
+    dropped: overrun::Counter,
 }
 
 impl PinInterrupt {
@@ -28,7 +31,8 @@ impl PinInterrupt {
     /// initialize a `static`.
     pub const fn new() -> Self {
         Self {
-            queue: Queue::new(),
+            queue:   Queue::new(),
+            dropped: overrun::Counter::new(),
         }
     }
 
@@ -54,8 +58,14 @@ impl PinInterrupt {
     {
         let (prod, cons) = self.queue.split();
 
-        let int  = Int { int: interrupt, queue: prod, timer, measuring: false };
-        let idle = Idle { queue: cons };
+        let int = Int {
+            int:       interrupt,
+            queue:     prod,
+            dropped:   &self.dropped,
+            timer,
+            measuring: false,
+        };
+        let idle = Idle { queue: cons, dropped: &self.dropped };
 
         (int, idle)
     }
@@ -71,6 +81,7 @@ impl PinInterrupt {
 pub struct Int<'r, I, P, T: mrt::Trait> {
     int:       pinint::Interrupt<I, P, Enabled>,
     queue:     Producer<'r, Event, QUEUE_CAP>,
+    dropped:   &'r overrun::Counter,
     timer:     mrt::Channel<T>,
     measuring: bool,
 }
@@ -86,9 +97,16 @@ impl<I, P, T> Int<'_, I, P, T>
     /// This should be called directly from the interrupt handler. Will check
     /// whether this interrupt was triggered by a rising or falling edge, and
     /// will send the respective event to the corresponding [`Idle`] instance.
+    /// If its queue is full, the event is dropped, and counted instead (see
+    /// [`Idle::take_dropped`]).
+    ///
+    /// `timestamp` is the current value of a free-running timer. It is passed
+    /// on with the event, which allows for measuring the time between events
+    /// that are further apart than the MRT channel can measure.
     ///
     /// [`Idle`]: struct.Idle.html
-    pub fn handle_interrupt(&mut self) {
+    /// [`Idle::take_dropped`]: struct.Idle.html#method.take_dropped
+    pub fn handle_interrupt(&mut self, timestamp: u32) {
         let mut period = None;
 
         if self.measuring {
@@ -102,12 +120,16 @@ impl<I, P, T> Int<'_, I, P, T>
         self.measuring = true;
 
         if self.int.clear_rising_edge_flag() {
-            let event = Event { level: gpio::Level::High, period };
-            self.queue.enqueue(event).unwrap();
+            let event = Event { level: gpio::Level::High, period, timestamp };
+            if self.queue.enqueue(event).is_err() {
+                self.dropped.add(1);
+            }
         }
         if self.int.clear_falling_edge_flag() {
-            let event = Event { level: gpio::Level::Low, period };
-            self.queue.enqueue(event).unwrap();
+            let event = Event { level: gpio::Level::Low, period, timestamp };
+            if self.queue.enqueue(event).is_err() {
+                self.dropped.add(1);
+            }
         }
     }
 }
@@ -123,7 +145,8 @@ impl<I, P, T> Int<'_, I, P, T>
 /// [`PinInterrupt::init`]: struct.PinInterrupt.html#method.init
 /// [`Int`]: struct.Int.html
 pub struct Idle<'r> {
-    queue: Consumer<'r, Event, QUEUE_CAP>,
+    queue:   Consumer<'r, Event, QUEUE_CAP>,
+    dropped: &'r overrun::Counter,
 }
 
 impl Idle<'_> {
@@ -136,6 +159,15 @@ impl Idle<'_> {
     pub fn is_ready(&self) -> bool {
         self.queue.ready()
     }
+
+    /// Return the number of events dropped since the last call
+    ///
+    /// Events are dropped by [`Int::handle_interrupt`], if the queue is full.
+    ///
+    /// [`Int::handle_interrupt`]: struct.Int.html#method.handle_interrupt
+    pub fn take_dropped(&mut self) -> u32 {
+        self.dropped.take()
+    }
 }
 
 
@@ -147,6 +179,11 @@ pub struct Event {
 
     /// The period measured since the last event, if available
     pub period: Option<u32>,
+
+    /// The timestamp passed to [`Int::handle_interrupt`]
+    ///
+    /// [`Int::handle_interrupt`]: struct.Int.html#method.handle_interrupt
+    pub timestamp: u32,
 }
 
 
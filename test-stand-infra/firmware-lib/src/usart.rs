@@ -1,7 +1,12 @@
 //! Interrupt-enabled USART API
 
 
+#[cfg(feature = "lpc8xx")]
+pub mod dma;
+#[cfg(feature = "lpc8xx")]
+pub mod lpc8xx;
 pub mod rx;
+pub mod sender;
 pub mod tx;
 
 
@@ -10,24 +15,49 @@ pub use self::{
         RxIdle,
         RxInt,
     },
+    sender::{
+        Queued,
+        Sender,
+        SenderInt,
+    },
     tx::Tx,
 };
+#[cfg(feature = "lpc8xx")]
+pub use self::dma::{
+    DmaRx,
+    DmaRxIdle,
+    DmaRxInt,
+};
 
 
+use embedded_hal::{
+    blocking::serial::Write,
+    serial::Read,
+};
 use heapless::{
     Vec,
     spsc,
 };
-use lpc8xx_hal::{
-    USART,
-    usart::state::Enabled,
+#[cfg(feature = "lpc8xx")]
+use lpc8xx_hal::pac::usart0::RegisterBlock;
+use protocol::checksum::Checksum;
+#[cfg(feature = "lpc8xx")]
+use protocol::usart::{
+    Config,
+    Parity,
+    StopBits,
+    WordLength,
 };
 
+use crate::overrun;
+
 
 /// Interrupt-enabled USART wrapper
 ///
 /// Can be allocated in a `static` or another memory location with an
-/// appropriate lifetime. Once initialized, it is split into two parts:
+/// appropriate lifetime. Works with the USART of any HAL that implements the
+/// serial traits from `embedded-hal`. Once initialized, it is split into three
+/// parts:
 ///
 /// - [`RxInt`], which handles the timing-critical parts of receiving, and is
 ///   intended to be moved into the interrupt handler.
@@ -39,46 +69,122 @@ use lpc8xx_hal::{
 /// [`Tx`]: tx/struct.Tx.html
 pub struct Usart {
     queue: spsc::Queue<u8, QUEUE_CAP>,
+
+    /// Counts the bytes that were dropped, because the queue was full
+    dropped: overrun::Counter,
 }
 
 impl Usart {
     /// Creates a new instance of `Usart`
     pub const fn new() -> Self {
         Self {
-            queue: spsc::Queue::new(),
+            queue:   spsc::Queue::new(),
+            dropped: overrun::Counter::new(),
         }
     }
 
-    /// Initialize the USART
+    /// Split a USART receiver and transmitter into the three parts
     ///
     /// Returns the three parts - [`RxInt`], [`RxIdle`], and [`Tx`] - which can
-    /// then be moved into different contexts.
+    /// then be moved into different contexts. For the USARTs of lpc8xx-hal,
+    /// [`init`] does the splitting, too.
     ///
     /// [`RxInt`]: rx/struct.RxInt.html
     /// [`RxIdle`]: rx/struct.RxIdle.html
     /// [`Tx`]: tx/struct.Tx.html
-    pub fn init<I, Mode>(&mut self, usart: USART<I, Enabled<u8, Mode>>)
-        -> (RxInt<I, Mode>, RxIdle, Tx<I, Mode>)
+    /// [`init`]: #method.init
+    pub fn split<R, W>(&mut self, rx: R, tx: W)
+        -> (RxInt<R>, RxIdle, Tx<W>)
+        where
+            R: Read<u8>,
+            W: Write<u8>,
     {
         let (prod, cons) = self.queue.split();
 
         let rx_int = RxInt {
-            usart: usart.rx,
-            queue: prod,
+            usart:   rx,
+            queue:   prod,
+            dropped: &self.dropped,
         };
         let rx_idle = RxIdle {
-            queue: cons,
-            buf:   Vec::new(),
-        };
-        let tx = Tx {
-            usart: usart.tx,
+            queue:        cons,
+            buf:          Vec::new(),
+            checksum:     Checksum::None,
+            timeout:      None,
+            reassembly:   None,
+            complete:     false,
+            discarding:   false,
+            last_data:    0,
+            dropped:      &self.dropped,
         };
+        let tx = Tx::new(tx);
 
         (rx_int, rx_idle, tx)
     }
 }
 
 
+/// Reconfigure an enabled USART at runtime
+///
+/// The HAL only supports configuring a USART when enabling it, so this writes
+/// to the registers directly. `clock_hz` is the frequency of the USART's clock,
+/// before oversampling.
+#[cfg(feature = "lpc8xx")]
+pub fn configure(usart: &RegisterBlock, clock_hz: u32, config: &Config) {
+    let oversampling = usart.osr.read().osrval().bits() as u32 + 1;
+    let brgval       = brgval(clock_hz, oversampling, config.baud);
+
+    reconfigure(usart, |usart| {
+        usart.brg.write(|w| unsafe { w.brgval().bits(brgval) });
+        usart.cfg.modify(|_, w| {
+            match config.word_length {
+                WordLength::Seven => w.datalen().bit_7(),
+                WordLength::Eight => w.datalen().bit_8(),
+                WordLength::Nine  => w.datalen().bit_9(),
+            };
+            match config.parity {
+                Parity::None => w.paritysel().no_parity(),
+                Parity::Even => w.paritysel().even_parity(),
+                Parity::Odd  => w.paritysel().odd_parity(),
+            };
+            match config.stop_bits {
+                StopBits::One => w.stoplen().bit_1(),
+                StopBits::Two => w.stoplen().bits_2(),
+            }
+        });
+    });
+}
+
+/// Modify the registers of an enabled USART, while it is disabled
+///
+/// Some registers, like `CFG` and `BRG`, must only be changed while the USART
+/// is disabled. Waits until the USART has finished sending, disables it, passes
+/// it to `f`, then enables it again.
+#[cfg(feature = "lpc8xx")]
+pub fn reconfigure(usart: &RegisterBlock, f: impl FnOnce(&RegisterBlock)) {
+    while usart.stat.read().txidle().bit_is_clear() {}
+    usart.cfg.modify(|_, w| w.enable().disabled());
+
+    f(usart);
+
+    usart.cfg.modify(|_, w| w.enable().enabled());
+}
+
+/// Compute the baud rate divider value (BRG) for a baud rate
+///
+/// Rounds to the nearest divider. A baud rate of zero makes no sense, and is
+/// treated like the highest possible one.
+pub fn brgval(clock_hz: u32, oversampling: u32, baud: u32) -> u16 {
+    let divider = oversampling * baud;
+    let brgval  = (clock_hz + divider / 2)
+        .checked_div(divider)
+        .unwrap_or(1)
+        .saturating_sub(1);
+
+    brgval.min(u16::MAX as u32) as u16
+}
+
+
 // It would be nice to make the queue capacity configurable, but that would
 // require a generic with trait bound on all the structs. As of this writing,
 // `const fn`s with trait bounds are unstable, so we can't do it yet.
@@ -0,0 +1,345 @@
+//! Receiving part of the USART API, using DMA
+//!
+//! The HAL only supports one-shot DMA transfers into a fixed buffer. Those
+//! make the data available only once the buffer is full, and need to be
+//! restarted from the interrupt handler, which can lose data. This module
+//! programs the DMA channel directly instead, using two linked descriptors.
+//! Each fills one half of a ring buffer, then reloads the other, so the channel
+//! never stops. Received data can be read at any time, even while a half is
+//! still being filled.
+
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    mem,
+    ptr,
+    slice,
+    sync::atomic::{
+        AtomicU32,
+        Ordering,
+    },
+};
+
+use lpc8xx_hal::{
+    cortex_m::interrupt,
+    dma,
+    init_state,
+    pac::DMA0,
+    usart::{
+        self,
+        state::Enabled,
+    },
+};
+
+
+/// DMA ring buffer for receiving from a USART
+///
+/// Must be allocated in a `static`, as the DMA controller keeps writing to it
+/// for the rest of the program's run time. Once initialized, it is split into
+/// two parts:
+///
+/// - [`DmaRxInt`], which keeps track of the DMA channel, and is intended to be
+///   moved into the DMA interrupt handler.
+/// - [`DmaRxIdle`], which can be used to process the received data somewhere
+///   else.
+///
+/// [`DmaRxInt`]: struct.DmaRxInt.html
+/// [`DmaRxIdle`]: struct.DmaRxIdle.html
+pub struct DmaRx {
+    buffer:      UnsafeCell<[u8; RING_CAP]>,
+    descriptors: UnsafeCell<[Descriptor; 2]>,
+
+    /// Counts the halves of the buffer that the DMA controller has filled
+    ///
+    /// Only written by the interrupt handler. There are no atomic
+    /// read-modify-write operations on this MCU.
+    filled: AtomicU32,
+}
+
+impl DmaRx {
+    /// Creates a new instance of `DmaRx`
+    pub const fn new() -> Self {
+        Self {
+            buffer:      UnsafeCell::new([0; RING_CAP]),
+            descriptors: UnsafeCell::new([Descriptor::EMPTY; 2]),
+            filled:      AtomicU32::new(0),
+        }
+    }
+
+    /// Start receiving from the USART, using the DMA channel
+    ///
+    /// Both are kept in the returned [`DmaRxInt`], so they can't be used for
+    /// anything else. Interrupts of the DMA channel are enabled, and the
+    /// handler must call [`DmaRxInt::handle_interrupt`].
+    ///
+    /// [`DmaRxInt`]: struct.DmaRxInt.html
+    /// [`DmaRxInt::handle_interrupt`]:
+    ///     struct.DmaRxInt.html#method.handle_interrupt
+    pub fn init<I, Mode, C>(&'static mut self,
+        usart:   usart::Rx<I, Enabled<u8, Mode>>,
+        channel: dma::Channel<C, init_state::Enabled>,
+    )
+        -> (DmaRxInt<I, Mode, C>, DmaRxIdle<C>)
+        where
+            I: usart::Instance,
+            C: dma::channels::Instance,
+    {
+        // Sound, as the HAL only accesses the registers of a channel through
+        // the channel's API, and we own the channel. The shared registers
+        // are only written with our channel's flag.
+        let dma = unsafe { &*DMA0::ptr() };
+
+        // Sound, as we only take the address of the register.
+        let rxdat = unsafe { &(*I::REGISTERS).rxdat } as *const _ as u32;
+
+        let buffer      = self.buffer.get() as *mut u8 as u32;
+        let descriptors = self.descriptors.get() as *mut Descriptor;
+
+        for half in 0 .. 2 {
+            let descriptor = Descriptor {
+                config:     XFERCFG,
+                source_end: rxdat,
+                dest_end:   buffer + ((half + 1) * HALF - 1) as u32,
+                next:       descriptors.wrapping_add((half + 1) % 2) as u32,
+            };
+
+            // Sound, as `descriptors` points to the array in `self`, which we
+            // have exclusive access to.
+            unsafe { ptr::write_volatile(descriptors.add(half), descriptor) };
+        }
+
+        // The channel starts with the first half. Its descriptor in the table
+        // is a copy of the linked one.
+        let table = dma.srambase.read().bits() as *mut Descriptor;
+        // Sound, as the HAL has set up the table for all channels, and only
+        // writes the descriptor of a channel when starting a transfer on it.
+        unsafe {
+            let first = ptr::read_volatile(descriptors);
+            ptr::write_volatile(table.add(C::INDEX), first);
+        }
+
+        let registers = &dma.channel[C::INDEX];
+        registers.cfg.write(|w| unsafe { w.bits(CFG_PERIPHREQEN) });
+        dma.enableset0.write(|w| unsafe { w.bits(C::FLAG) });
+        dma.intenset0.write(|w| unsafe { w.bits(C::FLAG) });
+        registers.xfercfg.write(|w| unsafe { w.bits(XFERCFG) });
+
+        let ring: &'static DmaRx = self;
+
+        let rx_int = DmaRxInt {
+            _usart:   usart,
+            _channel: channel,
+            ring,
+        };
+        let rx_idle = DmaRxIdle {
+            ring,
+            read:     0,
+            dropped:  0,
+            _channel: PhantomData,
+        };
+
+        (rx_int, rx_idle)
+    }
+}
+
+
+/// API for tracking the DMA ring buffer in an interrupt handler
+///
+/// You can get an instance of this struct by calling [`DmaRx::init`].
+///
+/// [`DmaRx::init`]: struct.DmaRx.html#method.init
+pub struct DmaRxInt<I, Mode, C> {
+    _usart:   usart::Rx<I, Enabled<u8, Mode>>,
+    _channel: dma::Channel<C, init_state::Enabled>,
+
+    ring: &'static DmaRx,
+}
+
+impl<I, Mode, C> DmaRxInt<I, Mode, C>
+    where
+        C: dma::channels::Instance,
+{
+    /// Handle the interrupt of the DMA channel
+    ///
+    /// This method should be called directly from the interrupt handler. It
+    /// needs to run before the next half of the buffer has been filled.
+    /// Otherwise, [`DmaRxIdle`] loses track of the received data.
+    ///
+    /// [`DmaRxIdle`]: struct.DmaRxIdle.html
+    pub fn handle_interrupt(&mut self) {
+        // Sound, as we only access the flags of our own channel, using a
+        // write-1-to-clear register.
+        let dma = unsafe { &*DMA0::ptr() };
+
+        if dma.inta0.read().bits() & C::FLAG == 0 {
+            return;
+        }
+        dma.inta0.write(|w| unsafe { w.bits(C::FLAG) });
+
+        let filled = self.ring.filled.load(Ordering::Relaxed);
+        self.ring.filled.store(filled.wrapping_add(1), Ordering::Relaxed);
+    }
+}
+
+
+/// API for processing data received into the DMA ring buffer
+///
+/// You can get an instance of this struct by calling [`DmaRx::init`].
+///
+/// [`DmaRx::init`]: struct.DmaRx.html#method.init
+pub struct DmaRxIdle<C> {
+    ring: &'static DmaRx,
+
+    /// The position of the next byte to be processed
+    ///
+    /// Counts all bytes since the channel was started, and wraps around. This
+    /// works, because `RING_CAP` is a power of two.
+    read: u32,
+
+    /// Counts the bytes that were dropped, because they weren't processed in
+    /// time
+    dropped: u32,
+
+    _channel: PhantomData<C>,
+}
+
+impl<C> DmaRxIdle<C>
+    where
+        C: dma::channels::Instance,
+{
+    /// Process received data
+    ///
+    /// Calls the closure with any data that has been received since the last
+    /// call. As the data is passed straight out of the ring buffer, the
+    /// closure is called twice, if the data wraps around the end of it.
+    ///
+    /// Data that is older than half the ring buffer could be overwritten while
+    /// it is being processed. It is dropped instead. [`take_dropped`] returns
+    /// how much.
+    ///
+    /// [`take_dropped`]: #method.take_dropped
+    pub fn process_raw<E>(&mut self,
+        mut f: impl FnMut(&[u8]) -> Result<(), E>,
+    )
+        -> Result<(), E>
+    {
+        let written = self.written();
+
+        let mut available = written.wrapping_sub(self.read);
+        if available > HALF as u32 {
+            self.dropped = self.dropped
+                .saturating_add(available - HALF as u32);
+            self.read = written.wrapping_sub(HALF as u32);
+            available = HALF as u32;
+        }
+
+        while available > 0 {
+            let start = self.read as usize % RING_CAP;
+            let len   = (RING_CAP - start).min(available as usize);
+
+            // Sound, as the DMA controller is writing to another part of the
+            // buffer, and won't get to this part before we're done.
+            let data = unsafe {
+                let buffer = self.ring.buffer.get() as *const u8;
+                slice::from_raw_parts(buffer.add(start), len)
+            };
+            f(data)?;
+
+            self.read = self.read.wrapping_add(len as u32);
+            available -= len as u32;
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of bytes dropped since the last call
+    pub fn take_dropped(&mut self) -> u32 {
+        mem::take(&mut self.dropped)
+    }
+
+    /// The position that the DMA controller writes the next byte to
+    ///
+    /// Counts the same way as `read`.
+    fn written(&self) -> u32 {
+        // Sound, as we only read registers.
+        let dma = unsafe { &*DMA0::ptr() };
+
+        // The interrupt handler can't update `filled` in here, but the DMA
+        // controller can move on to the next half at any time. If it does,
+        // the remaining count goes up, and we try again.
+        interrupt::free(|_| loop {
+            let before  = remaining::<C>(dma);
+            let pending = dma.inta0.read().bits() & C::FLAG != 0;
+            let after   = remaining::<C>(dma);
+
+            if before > HALF as u32 || after > before {
+                continue;
+            }
+
+            let filled = self.ring.filled.load(Ordering::Relaxed)
+                .wrapping_add(pending as u32);
+            return filled
+                .wrapping_mul(HALF as u32)
+                .wrapping_add(HALF as u32 - after);
+        })
+    }
+}
+
+
+/// A DMA transfer descriptor, as the DMA controller expects it in memory
+#[repr(C, align(16))]
+struct Descriptor {
+    /// The XFERCFG value that is loaded, when the descriptor is reloaded
+    config: u32,
+
+    source_end: u32,
+    dest_end:   u32,
+    next:       u32,
+}
+
+impl Descriptor {
+    const EMPTY: Self = Self {
+        config:     0,
+        source_end: 0,
+        dest_end:   0,
+        next:       0,
+    };
+}
+
+
+/// The number of bytes the current descriptor has yet to transfer
+///
+/// Reads more than `HALF`, while the DMA controller is between descriptors.
+fn remaining<C>(dma: &lpc8xx_hal::pac::dma0::RegisterBlock) -> u32
+    where
+        C: dma::channels::Instance,
+{
+    let xfercfg = dma.channel[C::INDEX].xfercfg.read().bits();
+    (xfercfg >> XFERCOUNT_SHIFT & XFERCOUNT_MASK) + 1
+}
+
+
+// Must be a power of two, for the positions to wrap around correctly. Each
+// half must not be larger than the maximum transfer count of 1024.
+const RING_CAP: usize = 256;
+const HALF:     usize = RING_CAP / 2;
+
+/// CFG value: Transfers are paced by the peripheral's DMA request
+const CFG_PERIPHREQEN: u32 = 1 << 0;
+
+/// XFERCFG value for each half of the buffer
+///
+/// Valid, reload the linked descriptor when done, triggered by software and
+/// staying triggered, set interrupt flag A when done, byte-wide transfers into
+/// an incrementing destination.
+const XFERCFG: u32 =
+    1 << 0
+    | 1 << 1
+    | 1 << 2
+    | 1 << 4
+    | 1 << 14
+    | (HALF as u32 - 1) << XFERCOUNT_SHIFT;
+
+const XFERCOUNT_SHIFT: u32 = 16;
+const XFERCOUNT_MASK:  u32 = 0x3ff;
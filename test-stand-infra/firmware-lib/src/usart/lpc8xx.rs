@@ -0,0 +1,121 @@
+//! Adapter for the USARTs of lpc8xx-hal
+//!
+//! The HAL's receivers and transmitters implement the serial traits from
+//! `embedded-hal`, so they work with [`Usart::split`] and [`Sender::split`] as
+//! they are. This module adds the type names and the initialization that the
+//! LPC8xx firmwares use, and the interrupt control that [`Sender`] needs.
+//!
+//! [`Usart::split`]: ../struct.Usart.html#method.split
+//! [`Sender::split`]: ../sender/struct.Sender.html#method.split
+//! [`Sender`]: ../sender/struct.Sender.html
+
+
+use core::marker::PhantomData;
+
+use lpc8xx_hal::{
+    USART,
+    usart::{
+        self,
+        state::{
+            Enabled,
+            NoThrottle,
+        },
+    },
+};
+
+use super::{
+    Usart,
+    sender::{
+        Queued,
+        TxInterrupt,
+    },
+};
+
+
+/// [`RxInt`] for a USART instance of lpc8xx-hal
+///
+/// [`RxInt`]: ../rx/struct.RxInt.html
+pub type RxInt<'r, I, Mode> =
+    super::RxInt<'r, usart::Rx<I, Enabled<u8, Mode>>>;
+
+/// [`Tx`] for a USART instance of lpc8xx-hal
+///
+/// [`Tx`]: ../tx/struct.Tx.html
+pub type Tx<I, Mode> = super::Tx<usart::Tx<I, Enabled<u8, Mode>, NoThrottle>>;
+
+/// [`SenderInt`] for a USART instance of lpc8xx-hal
+///
+/// [`SenderInt`]: ../sender/struct.SenderInt.html
+pub type SenderInt<'r, I, Mode> = super::SenderInt<
+    'r,
+    usart::Tx<I, Enabled<u8, Mode>, NoThrottle>,
+    Interrupt<I>,
+>;
+
+/// [`Tx`] that sends through a [`SenderInt`]
+///
+/// [`Tx`]: ../tx/struct.Tx.html
+/// [`SenderInt`]: type.SenderInt.html
+pub type QueuedTx<'r, I> = super::Tx<Queued<'r, Interrupt<I>>>;
+
+
+impl Usart {
+    /// Initialize the USART
+    ///
+    /// Returns the three parts - [`RxInt`], [`RxIdle`], and [`Tx`] - which can
+    /// then be moved into different contexts.
+    ///
+    /// [`RxInt`]: type.RxInt.html
+    /// [`RxIdle`]: ../rx/struct.RxIdle.html
+    /// [`Tx`]: type.Tx.html
+    pub fn init<I, Mode>(&mut self, usart: USART<I, Enabled<u8, Mode>>)
+        -> (RxInt<I, Mode>, super::RxIdle, Tx<I, Mode>)
+        where I: usart::Instance
+    {
+        self.split(usart.rx, usart.tx)
+    }
+}
+
+
+/// The TXRDY interrupt of a USART instance of lpc8xx-hal
+///
+/// The HAL can only enable interrupts before the USART is split, so this writes
+/// to the registers directly.
+pub struct Interrupt<I>(PhantomData<I>);
+
+impl<I> Interrupt<I> {
+    /// Create an instance for the USART instance `I`
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<I> Clone for Interrupt<I> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<I> TxInterrupt for Interrupt<I>
+    where I: usart::Instance
+{
+    fn enable(&self) {
+        // Sound, as INTENSET is a write-1-to-set register, and we only write
+        // to the TXRDY bit.
+        let usart = unsafe { &*I::REGISTERS };
+        usart.intenset.write(|w| w.txrdyen().set_bit());
+    }
+
+    fn disable(&self) {
+        // Sound, as INTENCLR is a write-1-to-clear register, and we only write
+        // to the TXRDY bit.
+        let usart = unsafe { &*I::REGISTERS };
+        usart.intenclr.write(|w| w.txrdyclr().set_bit());
+    }
+
+    fn is_idle(&self) -> bool {
+        // Sound, as we're only reading.
+        let usart = unsafe { &*I::REGISTERS };
+        usart.stat.read().txidle().bit_is_set()
+    }
+}
@@ -5,31 +5,50 @@ use heapless::{
     Vec,
     spsc,
 };
-use lpc8xx_hal::{
-    prelude::*,
-    usart::{
+use embedded_hal::serial::Read;
+use protocol::{
+    checksum::Checksum,
+    chunk::{
         self,
-        state::Enabled,
+        Chunked,
+        Reassembly,
+    },
+    event::{
+        self,
+        Kind,
     },
 };
 use serde::Deserialize;
 
+use crate::{
+    event::record,
+    overrun,
+};
+
 use super::QUEUE_CAP;
 
 
 /// API for receiving data from a USART instance in an interrupt handler
 ///
-/// You can get an instance of this struct by calling [`Usart::init`].
+/// The receiver can be from any HAL that implements the serial trait from
+/// `embedded-hal`. See [`lpc8xx::RxInt`] for the one from lpc8xx-hal.
+///
+/// You can get an instance of this struct by calling [`Usart::init`] or
+/// [`Usart::split`].
 ///
+/// [`lpc8xx::RxInt`]: ../lpc8xx/type.RxInt.html
 /// [`Usart::init`]: ../struct.Usart.html#method.init
-pub struct RxInt<'r, I, Mode> {
-    pub usart: usart::Rx<I, Enabled<u8, Mode>>,
+/// [`Usart::split`]: ../struct.Usart.html#method.split
+pub struct RxInt<'r, R> {
+    pub usart: R,
     pub queue: spsc::Producer<'r, u8, QUEUE_CAP>,
+
+    pub(super) dropped: &'r overrun::Counter,
 }
 
-impl<I, Mode> RxInt<'_, I, Mode>
+impl<R> RxInt<'_, R>
     where
-        I: usart::Instance,
+        R: Read<u8>,
 {
     /// Receive available data
     ///
@@ -38,13 +57,18 @@ impl<I, Mode> RxInt<'_, I, Mode>
     /// can be processed by the corresponding [`RxIdle`] instance without any
     /// time pressure.
     ///
+    /// If the queue is full, because the data isn't processed fast enough, the
+    /// received data is dropped. [`RxIdle::take_dropped`] returns how much.
+    ///
     /// [`RxIdle`]: struct.RxIdle.html
-    pub fn receive(&mut self) -> Result<(), ReceiveError> {
+    /// [`RxIdle::take_dropped`]: struct.RxIdle.html#method.take_dropped
+    pub fn receive(&mut self) -> Result<(), ReceiveError<R::Error>> {
         loop {
             match self.usart.read() {
                 Ok(b) => {
-                    self.queue.enqueue(b)
-                        .map_err(|_| ReceiveError::QueueFull)?;
+                    if self.queue.enqueue(b).is_err() {
+                        self.dropped.add(1);
+                    }
                 }
                 Err(nb::Error::WouldBlock) => {
                     return Ok(());
@@ -63,12 +87,53 @@ impl<I, Mode> RxInt<'_, I, Mode>
 /// This processing can be done in a lower-priority context, for example an idle
 /// loop.
 ///
-/// You can get an instance of this struct by calling [`Usart::init`].
+/// You can get an instance of this struct by calling [`Usart::init`] or
+/// [`Usart::split`].
 ///
 /// [`Usart::init`]: ../struct.Usart.html#method.init
+/// [`Usart::split`]: ../struct.Usart.html#method.split
 pub struct RxIdle<'r> {
     pub queue: spsc::Consumer<'r, u8, QUEUE_CAP>,
     pub buf:   Vec<u8, QUEUE_CAP>,
+
+    /// The checksum verified by [`process_message`]
+    ///
+    /// This is [`Checksum::None`] by default. It should only be changed after
+    /// the host has agreed to the new checksum.
+    ///
+    /// [`process_message`]: #method.process_message
+    pub checksum: Checksum,
+
+    /// The time after which a partial frame is discarded
+    ///
+    /// In ticks of the timer passed to [`discard_stale_frame`]. This is `None`
+    /// by default, meaning partial frames are never discarded.
+    ///
+    /// [`discard_stale_frame`]: #method.discard_stale_frame
+    pub timeout: Option<u32>,
+
+    /// Reassembles requests that the host split into chunks
+    ///
+    /// This is `None` by default, meaning that chunks are passed to the
+    /// closure of [`process_message`] like any other message. Set it for the
+    /// USART connected to the host, to support requests that are too large for
+    /// a single frame.
+    ///
+    /// [`process_message`]: #method.process_message
+    pub reassembly: Option<&'r mut Reassembly>,
+
+    /// Indicates whether `buf` holds a complete frame
+    pub(super) complete: bool,
+
+    /// Indicates whether the rest of a frame that didn't fit into `buf` is
+    /// being skipped
+    pub(super) discarding: bool,
+
+    /// The time of the last check for a stale frame, that found new data
+    pub(super) last_data: u32,
+
+    /// The number of dropped bytes, as counted by [`RxInt`]
+    pub(super) dropped: &'r overrun::Counter,
 }
 
 impl RxIdle<'_> {
@@ -77,18 +142,31 @@ impl RxIdle<'_> {
         self.queue.ready()
     }
 
+    /// Return the number of bytes dropped since the last call
+    ///
+    /// Bytes are dropped by [`RxInt::receive`], if the queue is full.
+    ///
+    /// [`RxInt::receive`]: struct.RxInt.html#method.receive
+    pub fn take_dropped(&mut self) -> u32 {
+        self.dropped.take()
+    }
+
     /// Process received data
     ///
     /// Copies any available data to the internal buffer. If the buffer is not
     /// empty, the closure is called, with the buffer data as an argument.
     ///
-    /// The internal buffer is cleared, once the closure returns.
+    /// The internal buffer is cleared, once the closure returns. If the data
+    /// doesn't fit into the buffer, it is discarded, and
+    /// [`ProcessError::BufferFull`] is returned.
     pub fn process_raw<E>(&mut self, f: impl FnOnce(&[u8]) -> Result<(), E>)
         -> Result<(), ProcessError<E>>
     {
         while let Some(b) = self.queue.dequeue() {
-            self.buf.push(b)
-                .map_err(|_| ProcessError::BufferFull)?;
+            if self.buf.push(b).is_err() {
+                self.buf.clear();
+                return Err(ProcessError::BufferFull);
+            }
         }
 
         if self.buf.len() > 0 {
@@ -106,26 +184,111 @@ impl RxIdle<'_> {
     /// available, or a full message has been received. If a message has been
     /// received, that message is deserialized and the closure is called.
     ///
+    /// If a checksum has been set, it is verified before deserializing the
+    /// message. A corrupted message is not passed to the closure. Instead,
+    /// [`ProcessError::ChecksumMismatch`] is returned, and the caller can drop
+    /// the message (by calling [`clear_buf`]) and wait for the host to
+    /// retransmit it.
+    ///
+    /// If [`reassembly`] has been set, chunks of a larger message are collected
+    /// there, instead of being passed to the closure. Once the last chunk has
+    /// been received, the reassembled message is passed to the closure.
+    ///
+    /// A frame that doesn't fit into the internal buffer is discarded, and
+    /// [`ProcessError::BufferFull`] is returned. The rest of that frame is
+    /// skipped, up to its delimiter, and the frame after it is received as
+    /// usual.
+    ///
+    /// Empty frames are skipped. If the host has sent the link sync sequence
+    /// ([`protocol::LINK_SYNC`]), any partially reassembled message is
+    /// discarded, the checksum is reset, and [`ProcessError::LinkSync`] is
+    /// returned.
+    ///
     /// After calling this method, you must clear the internal buffer by calling
     /// [`clear_buf`]. Otherwise, the same message will be processed again on
-    /// the next call.
+    /// the next call. A partial frame stays in the buffer, until the rest of it
+    /// has been received, or [`discard_stale_frame`] discards it.
     ///
     /// [`clear_buf`]: #method.clear_buf
+    /// [`discard_stale_frame`]: #method.discard_stale_frame
+    /// [`reassembly`]: #structfield.reassembly
     pub fn process_message<'de, M, E>(&'de mut self,
         f: impl FnOnce(M) -> Result<(), E>,
     )
         -> Result<(), ProcessError<E>>
-        where M: Deserialize<'de>
+        where M: Deserialize<'de> + Chunked
     {
-        while let Some(b) = self.queue.dequeue() {
-            self.buf.push(b)
-                .map_err(|_| ProcessError::BufferFull)?;
+        // Borrow the fields separately, so the reassembled message can borrow
+        // from `reassembly`, while `buf` is still borrowed.
+        let Self {
+            queue, buf, checksum, reassembly, complete, discarding, ..
+        } = self;
+
+        while let Some(b) = queue.dequeue() {
+            // Skip the rest of a frame that didn't fit into the buffer. Its
+            // delimiter is where the next frame starts.
+            if *discarding {
+                *discarding = b != 0;
+                continue;
+            }
+
+            if buf.push(b).is_err() {
+                // Whatever was received so far is useless. If the byte that
+                // didn't fit was the delimiter, the frame is already over.
+                buf.clear();
+                *discarding = b != 0;
+                return Err(rejected(ProcessError::BufferFull));
+            }
 
             // Requests are COBS-encoded, so we know that `0` means we
             // received a full frame.
             if b == 0 {
-                let message = postcard::from_bytes_cobs(&mut self.buf)
-                    .map_err(|err| ProcessError::Postcard(err))?;
+                *complete = true;
+
+                // Empty frames, like the ones at the start of the link sync
+                // sequence, only terminate whatever was received before them.
+                if buf.len() == 1 {
+                    return Ok(());
+                }
+                if protocol::is_link_sync(&buf[..buf.len() - 1]) {
+                    *checksum = Checksum::None;
+                    if let Some(reassembly) = reassembly {
+                        reassembly.reset();
+                    }
+                    return Err(ProcessError::LinkSync);
+                }
+
+                let len = postcard_cobs::decode_in_place(buf)
+                    .map_err(|()| rejected(ProcessError::Postcard(
+                        postcard::Error::DeserializeBadEncoding
+                    )))?;
+                let frame = checksum.verify(&buf[..len])
+                    .map_err(|_| rejected(ProcessError::ChecksumMismatch))?;
+                let message: M = postcard::from_bytes(frame)
+                    .map_err(|err| rejected(ProcessError::Postcard(err)))?;
+
+                let chunk = message.chunk();
+                if let (Some(chunk), Some(reassembly)) =
+                    (chunk, reassembly.as_deref_mut())
+                {
+                    let message = reassembly.add(chunk)
+                        .map_err(|err| rejected(ProcessError::Chunk(err)))?;
+
+                    // Nothing to do, until the last chunk has been received.
+                    if let Some(message) = message {
+                        record_request(message);
+                        let message = postcard::from_bytes(message)
+                            .map_err(|err| {
+                                rejected(ProcessError::Postcard(err))
+                            })?;
+                        f(message)
+                            .map_err(|err| ProcessError::Other(err))?;
+                    }
+
+                    return Ok(());
+                }
+
+                record_request(frame);
                 f(message)
                     .map_err(|err| ProcessError::Other(err))?;
                 return Ok(());
@@ -138,7 +301,9 @@ impl RxIdle<'_> {
     /// Clear the internal buffer
     ///
     /// This method _must_ be called after every call to [`process_message`], or
-    /// on the next call, the same message will be processed again.
+    /// on the next call, the same message will be processed again. Only clears
+    /// the buffer, if it holds a complete frame, whether that frame could be
+    /// processed or not.
     ///
     /// It would be much nice, if this functionality could be included in
     /// [`process_message`], but unfortunately there's no straight-forward way
@@ -147,32 +312,109 @@ impl RxIdle<'_> {
     ///
     /// [`process_message`]: #method.process_message
     pub fn clear_buf(&mut self) {
-        self.buf.clear();
+        if self.complete {
+            self.buf.clear();
+            self.complete = false;
+        }
+    }
+
+    /// Discard a partial frame, if nothing has been added to it for too long
+    ///
+    /// Without this, a sender that stops in the middle of a frame would corrupt
+    /// the next frame, as that would be appended to the partial one. Returns
+    /// `true`, if a partial frame has been discarded. Never discards anything,
+    /// while new data is waiting to be processed.
+    ///
+    /// `now` is the current value of a timer that counts up, and wraps around
+    /// at `u32::MAX`. This method must be called before every call to
+    /// [`process_message`], as it notes the time whenever new data is about to
+    /// be added to the frame. Does nothing, if [`timeout`] is `None`.
+    ///
+    /// [`process_message`]: #method.process_message
+    /// [`timeout`]: #structfield.timeout
+    pub fn discard_stale_frame(&mut self, now: u32) -> bool {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None          => return false,
+        };
+
+        // The frame might still be arriving, if this wasn't called for a
+        // while. Only data that has been waiting the whole time is stale.
+        if self.queue.ready() {
+            self.last_data = now;
+            return false;
+        }
+
+        let stale = (!self.buf.is_empty() || self.discarding)
+            && now.wrapping_sub(self.last_data) >= timeout;
+        if stale {
+            self.buf.clear();
+            self.discarding = false;
+        }
+
+        stale
     }
 }
 
 
 /// Error receiving data from USART
 #[derive(Debug)]
-pub enum ReceiveError {
-    /// The internal queue is full
-    QueueFull,
-
+pub enum ReceiveError<E> {
     /// An error was returned by the wrapped USART receiver
-    Usart(usart::Error<u8>),
+    Usart(E),
 }
 
 /// Error processing received USART data
 #[derive(Debug)]
 pub enum ProcessError<E> {
-    /// The internal buffer is full
+    /// The frame didn't fit into the internal buffer
+    ///
+    /// The frame has been discarded. The rest of it is skipped, as it's being
+    /// received.
     BufferFull,
 
     /// Error decoding the message
     Postcard(postcard::Error),
 
+    /// The message's checksum didn't match
+    ///
+    /// The message has been corrupted in transit.
+    ChecksumMismatch,
+
+    /// A chunk of a larger message could not be added to the reassembled one
+    ///
+    /// The partial message has been discarded.
+    Chunk(chunk::Error),
+
+    /// The host has sent the link sync sequence
+    ///
+    /// Not an error as such. The receiver has discarded its partial state, and
+    /// reset its checksum. The caller should reset whatever framing it has
+    /// negotiated for sending, and answer with the sync preamble (see
+    /// [`Tx::send_sync`]).
+    ///
+    /// [`Tx::send_sync`]: ../tx/struct.Tx.html#method.send_sync
+    LinkSync,
+
     /// Another error occurred
     ///
     /// This is an error that was returned from the user-provided closure.
     Other(E),
 }
+
+
+/// Record that a request has been received
+///
+/// `message` is the serialized request, which starts with the index of its
+/// variant.
+fn record_request(message: &[u8]) {
+    if let Some(&variant) = message.first() {
+        record(Kind::Request(variant));
+    }
+}
+
+/// Record that a request couldn't be received, and return the error
+fn rejected<E>(err: ProcessError<E>) -> ProcessError<E> {
+    record(Kind::Error(event::Error::Request));
+    err
+}
@@ -0,0 +1,208 @@
+//! Interrupt-driven sending part of the USART API
+//!
+//! A [`Tx`] that wraps a USART transmitter directly blocks until the
+//! transmitter has taken every byte. Wrapping a [`Queued`] instead, it only
+//! blocks until the data has been added to a queue, which the USART's interrupt
+//! handler drains using [`SenderInt`]. The firmware can go on handling requests
+//! in the meantime. It only blocks, if the queue is full, or when flushing.
+//!
+//! [`Tx`]: ../tx/struct.Tx.html
+//! [`Queued`]: struct.Queued.html
+//! [`SenderInt`]: struct.SenderInt.html
+
+
+use embedded_hal::{
+    blocking,
+    serial::Write,
+};
+use heapless::spsc;
+use void::Void;
+
+
+/// Control of a USART's "ready to send" interrupt
+///
+/// This is the part of sending from an interrupt handler that the serial traits
+/// of `embedded-hal` don't cover. See [`lpc8xx::Interrupt`] for lpc8xx-hal.
+///
+/// [`lpc8xx::Interrupt`]: ../lpc8xx/struct.Interrupt.html
+pub trait TxInterrupt {
+    /// Enable the interrupt, which fires when the USART is ready to send
+    fn enable(&self);
+
+    /// Disable the interrupt
+    fn disable(&self);
+
+    /// Indicates whether the USART has finished sending everything
+    fn is_idle(&self) -> bool;
+}
+
+
+/// Queue for sending data from an interrupt handler
+///
+/// Can be allocated in a `static` or another memory location with an
+/// appropriate lifetime. Once initialized, it is split into two parts:
+///
+/// - [`SenderInt`], which owns the USART transmitter, and is intended to be
+///   moved into the interrupt handler.
+/// - [`Queued`], which can be wrapped in a [`Tx`], to send data from somewhere
+///   else.
+///
+/// [`SenderInt`]: struct.SenderInt.html
+/// [`Queued`]: struct.Queued.html
+/// [`Tx`]: ../tx/struct.Tx.html
+pub struct Sender {
+    queue: spsc::Queue<u8, SEND_QUEUE_CAP>,
+}
+
+impl Sender {
+    /// Creates a new instance of `Sender`
+    pub const fn new() -> Self {
+        Self {
+            queue: spsc::Queue::new(),
+        }
+    }
+
+    /// Split the sender into its two parts
+    ///
+    /// `interrupt` controls the interrupt of the USART that `usart` belongs
+    /// to. Both parts get a copy of it.
+    pub fn split<W, I>(&mut self, usart: W, interrupt: I)
+        -> (SenderInt<W, I>, Queued<I>)
+        where
+            W: Write<u8>,
+            I: TxInterrupt + Clone,
+    {
+        let (prod, cons) = self.queue.split();
+
+        let sender_int = SenderInt {
+            usart,
+            queue:     cons,
+            interrupt: interrupt.clone(),
+        };
+        let queued = Queued {
+            queue: prod,
+            interrupt,
+        };
+
+        (sender_int, queued)
+    }
+
+    /// Iterate over the data that is still queued
+    ///
+    /// Used by the panic handler, to send a reply that was still queued,
+    /// before the panic report (see [`panic::set_sender`]).
+    ///
+    /// # Safety
+    ///
+    /// The parts returned by [`Sender::split`] must no longer be used, for
+    /// example because interrupts have been disabled for good.
+    ///
+    /// [`panic::set_sender`]: ../../panic/fn.set_sender.html
+    /// [`Sender::split`]: #method.split
+    pub unsafe fn queued(this: *const Self) -> impl Iterator<Item = u8> {
+        (*this).queue.iter().copied()
+    }
+}
+
+
+/// API for sending queued data from an interrupt handler
+///
+/// You can get an instance of this struct by calling [`Sender::split`].
+///
+/// [`Sender::split`]: struct.Sender.html#method.split
+pub struct SenderInt<'r, W, I> {
+    pub usart: W,
+
+    queue:     spsc::Consumer<'r, u8, SEND_QUEUE_CAP>,
+    interrupt: I,
+}
+
+impl<W, I> SenderInt<'_, W, I>
+    where
+        W: Write<u8>,
+        I: TxInterrupt,
+{
+    /// Send queued data
+    ///
+    /// This method should be called directly from the interrupt handler.
+    /// Passes as much of the queued data to the USART, as it takes without
+    /// blocking. Disables the interrupt, once the queue is empty. [`Queued`]
+    /// enables it again, when more data is queued.
+    ///
+    /// [`Queued`]: struct.Queued.html
+    pub fn send(&mut self) -> Result<(), W::Error> {
+        while let Some(&b) = self.queue.peek() {
+            match self.usart.write(b) {
+                Ok(()) => {
+                    self.queue.dequeue();
+                }
+                Err(nb::Error::WouldBlock) => {
+                    return Ok(());
+                }
+                Err(nb::Error::Other(err)) => {
+                    return Err(err);
+                }
+            }
+        }
+
+        self.interrupt.disable();
+        Ok(())
+    }
+}
+
+
+/// Transmitter that queues data for [`SenderInt`]
+///
+/// Implements the serial traits from `embedded-hal`, so it can be wrapped in a
+/// [`Tx`]. You can get an instance of this struct by calling
+/// [`Sender::split`].
+///
+/// [`SenderInt`]: struct.SenderInt.html
+/// [`Tx`]: ../tx/struct.Tx.html
+/// [`Sender::split`]: struct.Sender.html#method.split
+pub struct Queued<'r, I> {
+    queue:     spsc::Producer<'r, u8, SEND_QUEUE_CAP>,
+    interrupt: I,
+}
+
+impl<I> Write<u8> for Queued<'_, I>
+    where I: TxInterrupt
+{
+    type Error = Void;
+
+    /// Add a byte to the queue
+    ///
+    /// Returns `WouldBlock`, if the queue is full.
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.queue.enqueue(word)
+            .map_err(|_| nb::Error::WouldBlock)?;
+
+        // Enabled after enqueueing, so the handler can't disable the interrupt
+        // in between, leaving the byte in the queue.
+        self.interrupt.enable();
+
+        Ok(())
+    }
+
+    /// Check whether all queued data has been sent
+    ///
+    /// Returns `WouldBlock`, while there's still data in the queue, or the
+    /// USART is still sending.
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.queue.len() > 0 || !self.interrupt.is_idle() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        Ok(())
+    }
+}
+
+impl<I> blocking::serial::write::Default<u8> for Queued<'_, I>
+    where I: TxInterrupt
+{}
+
+
+// The queue holds one byte less than this. That's enough for most messages,
+// so sending them doesn't have to wait for the interrupt handler. Longer ones
+// block, until the handler has made room.
+const SEND_QUEUE_CAP: usize = 256;
@@ -1,56 +1,243 @@
 //! Sending part of the interrupt-enabled USART API
 
 
-use lpc8xx_hal::{
-    prelude::*,
-    usart::{
+use embedded_hal::blocking::serial::Write;
+use protocol::{
+    checksum::Checksum,
+    compress::{
         self,
-        state::{
-            Enabled,
-            NoThrottle,
-        },
+        Compression,
     },
+    flow::Credits,
 };
 use serde::Serialize;
-use void::{
-    ResultVoidExt,
-    Void,
-};
 
 
 /// Wraps a USART transmitter
 ///
-/// Provides some convenience methods on top of the wrapped transmitter.
-pub struct Tx<I, Mode> {
-    pub usart: usart::Tx<I, Enabled<u8, Mode>, NoThrottle>,
+/// Provides some convenience methods on top of the wrapped transmitter, which
+/// can be from any HAL that implements the blocking serial trait from
+/// `embedded-hal`. See [`lpc8xx::Tx`] for the one from lpc8xx-hal.
+///
+/// [`lpc8xx::Tx`]: ../lpc8xx/type.Tx.html
+pub struct Tx<W> {
+    pub usart: W,
+
+    /// The compression scheme used by [`send_message`]
+    ///
+    /// This is [`Compression::None`] by default. It should only be changed
+    /// after the host has agreed to the new scheme.
+    ///
+    /// [`send_message`]: #method.send_message
+    pub compression: Compression,
+
+    /// The checksum appended by [`send_message`]
+    ///
+    /// This is [`Checksum::None`] by default. It should only be changed after
+    /// the host has agreed to the new checksum.
+    ///
+    /// [`send_message`]: #method.send_message
+    pub checksum: Checksum,
+
+    /// The credits for [`send_credited`], as granted by the host
+    ///
+    /// Flow control is not enabled by default. Once the host grants credits,
+    /// check [`Credits::available`] before calling [`send_credited`].
+    ///
+    /// [`send_credited`]: #method.send_credited
+    pub credits: Credits,
 }
 
-impl<I, Mode> Tx<I, Mode>
-    where I: usart::Instance
+impl<W> Tx<W>
+    where W: Write<u8>
 {
+    /// Wrap a USART transmitter
+    ///
+    /// Starts without compression, checksum, or flow control.
+    pub fn new(usart: W) -> Self {
+        Self {
+            usart,
+            compression: Compression::None,
+            checksum:    Checksum::None,
+            credits:     Credits::new(),
+        }
+    }
+
     /// Sends raw data through the wrapped USART instance
     ///
     /// Blocks until the data has been sent.
-    pub fn send_raw(&mut self, data: &[u8]) -> Result<(), Void> {
+    pub fn send_raw(&mut self, data: &[u8]) -> Result<(), W::Error> {
         self.usart.bwrite_all(data)
     }
 
+    /// Sends the sync preamble through the wrapped USART instance
+    ///
+    /// Should be called once the firmware is ready to process messages. The
+    /// host discards everything it received before the preamble.
+    pub fn send_sync(&mut self) -> Result<(), W::Error> {
+        self.usart.bwrite_all(protocol::SYNC_PREAMBLE)
+    }
+
+    /// Resets the framing and credits, and sends the sync preamble
+    ///
+    /// Should be called when the host has sent the link sync sequence (see
+    /// [`ProcessError::LinkSync`]). The host starts over, without compression,
+    /// checksum, or flow control.
+    ///
+    /// [`ProcessError::LinkSync`]: ../rx/enum.ProcessError.html#variant.LinkSync
+    pub fn resync(&mut self) -> Result<(), W::Error> {
+        self.compression = Compression::None;
+        self.checksum    = Checksum::None;
+        self.credits     = Credits::new();
+        self.send_sync()
+    }
+
     /// Sends a message through the wrapped USART instance
     ///
     /// Accepts a message and a buffer. The buffer will be used to hold the
     /// serialized message, and must be large enough for that purpose. Any
     /// previous contents of the buffer will be ignored.
+    ///
+    /// If a compression scheme or a checksum has been set, the message is
+    /// framed (and possibly compressed) accordingly.
     pub fn send_message<T>(&mut self, message: &T, buf: &mut [u8])
-        -> Result<(), Error>
+        -> Result<(), Error<W::Error>>
         where T: Serialize
     {
-        let data = postcard::to_slice_cobs(message, buf)?;
+        let data = serialize(message, self.compression, self.checksum, buf)
+            .map_err(|err| Error::Serialize(err))?;
         self.usart.bwrite_all(data)
-            .void_unwrap();
-        Ok(())
+            .map_err(|err| Error::Usart(err))
+    }
+
+    /// Sends a message that is subject to flow control
+    ///
+    /// Works like [`send_message`], but uses up one of the credits granted by
+    /// the host. Messages that the firmware sends on its own initiative, like
+    /// data it forwards from a peripheral, should be sent this way.
+    ///
+    /// [`send_message`]: #method.send_message
+    pub fn send_credited<T>(&mut self, message: &T, buf: &mut [u8])
+        -> Result<(), Error<W::Error>>
+        where T: Serialize
+    {
+        self.credits.take();
+        self.send_message(message, buf)
     }
+
+    /// Blocks until everything has been sent
+    ///
+    /// Use this before resetting the microcontroller, to make sure the last
+    /// message isn't cut off.
+    pub fn flush(&mut self) -> Result<(), W::Error> {
+        self.usart.bflush()
+    }
+}
+
+
+/// Serialize a message into a frame
+///
+/// Used by [`Tx::send_message`], and by the panic handler, which can't use the
+/// [`Tx`].
+pub(crate) fn serialize<'b, T>(
+    message:     &T,
+    compression: Compression,
+    checksum:    Checksum,
+    buf:         &'b mut [u8],
+)
+    -> Result<&'b mut [u8], postcard::Error>
+    where T: Serialize
+{
+    match (compression, checksum) {
+        (Compression::None, Checksum::None) => {
+            postcard::to_slice_cobs(message, buf)
+        }
+        (compression, checksum) => {
+            to_slice_framed(message, compression, checksum, buf)
+        }
+    }
+}
+
+fn to_slice_framed<'b, T>(
+    message:     &T,
+    compression: Compression,
+    checksum:    Checksum,
+    buf:         &'b mut [u8],
+)
+    -> Result<&'b mut [u8], postcard::Error>
+    where T: Serialize
+{
+    let mut frame = [0; FRAME_CAP];
+
+    let frame_len = {
+        let serialized = postcard::to_slice(message, buf)?;
+
+        match compression {
+            Compression::None => {
+                frame.get_mut(.. serialized.len())
+                    .ok_or(postcard::Error::SerializeBufferFull)?
+                    .copy_from_slice(serialized);
+                serialized.len()
+            }
+            Compression::Rle => {
+                compress_into(serialized, &mut frame)?
+            }
+        }
+    };
+    let frame_len = checksum.append(&mut frame, frame_len)
+        .map_err(|_| postcard::Error::SerializeBufferFull)?;
+
+    // We need space for the COBS-encoded frame, plus the terminating `0`.
+    let frame = &frame[..frame_len];
+    if postcard_cobs::max_encoding_length(frame.len()) + 1 > buf.len() {
+        return Err(postcard::Error::SerializeBufferFull);
+    }
+
+    let len = postcard_cobs::encode(frame, buf);
+    buf[len] = 0;
+
+    Ok(&mut buf[.. len + 1])
+}
+
+fn compress_into(serialized: &[u8], frame: &mut [u8])
+    -> Result<usize, postcard::Error>
+{
+    let compressed_len = if serialized.len() >= compress::THRESHOLD {
+        compress::compress(serialized, &mut frame[1..])
+            .ok()
+            .filter(|&len| len < serialized.len())
+    }
+    else {
+        None
+    };
+
+    match compressed_len {
+        Some(len) => {
+            frame[0] = compress::FLAG_RLE;
+            Ok(1 + len)
+        }
+        None => {
+            frame[0] = compress::FLAG_RAW;
+            frame.get_mut(1 .. 1 + serialized.len())
+                .ok_or(postcard::Error::SerializeBufferFull)?
+                .copy_from_slice(serialized);
+            Ok(1 + serialized.len())
+        }
+    }
+}
+
+
+/// Error sending a message
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The message couldn't be serialized
+    Serialize(postcard::Error),
+
+    /// An error was returned by the wrapped USART transmitter
+    Usart(E),
 }
 
 
-/// Error occurred while serializing message
-pub type Error = postcard::Error;
+// Maximum size of a frame before COBS encoding. Matches the size of the
+// buffers that are typically passed to `send_message`.
+const FRAME_CAP: usize = 256;
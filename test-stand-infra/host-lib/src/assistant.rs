@@ -1,19 +1,38 @@
-use std::time::{
-    Duration,
-    Instant,
+use std::{
+    convert::TryFrom as _,
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
 use protocol::{
     AssistantToHost,
     HostToAssistant,
     InputPin,
+    MonitorPin,
     OutputPin,
     UsartMode,
+    checksum::Checksum,
+    compress::Compression,
+    i2c,
+    inject,
+    monitor,
     pin,
+    reference,
+    spi,
+    trigger,
+    units::{
+        Microseconds,
+        Nanoseconds,
+    },
+    usart,
+    waveform,
 };
 
 use crate::{
     conn::{
+        self,
         Conn,
         ConnReceiveError,
         ConnSendError,
@@ -22,12 +41,27 @@ use crate::{
         Pin,
         ReadLevelError,
     },
+    record::Direction,
+    serial::{
+        Serial,
+        SerialConfigureError,
+    },
+    voltage::{
+        Interlock,
+        UnsafeVoltageError,
+    },
 };
 
 
 /// The connection to the test assistant
+///
+/// Can also operate in "lite" mode, if no test assistant is available. In that
+/// case, host-lib plays the role of the assistant itself, by sending and
+/// receiving raw bytes through a USB/serial adapter that is connected directly
+/// to the target's USART. Only regular USART communication is supported in
+/// this mode. All other methods return an error.
 pub struct Assistant {
-    conn: Conn,
+    link: Link,
     pin5: Pin<OutputPin>,
     red_led: Pin<OutputPin>,
     green_led: Pin<InputPin>,
@@ -35,12 +69,27 @@ pub struct Assistant {
     pwm: Pin<InputPin>,
     cts: Pin<OutputPin>,
     rts: Pin<InputPin>,
+    interlock: Interlock,
 }
 
 impl Assistant {
-    pub fn new(conn: Conn) -> Self {
+    pub fn new(mut conn: Conn) -> Self {
+        conn.set_record_decoder(decode_message);
+        conn.set_panic_filter(panic_report);
+        Self::with_link(Link::Firmware(Box::new(conn)))
+    }
+
+    /// Create an assistant in lite mode
+    ///
+    /// `serial` is the connection to the USB/serial adapter that is connected
+    /// to the target's USART.
+    pub fn new_lite(serial: Serial) -> Self {
+        Self::with_link(Link::Lite(serial))
+    }
+
+    fn with_link(link: Link) -> Self {
         Self {
-            conn,
+            link,
             pin5: Pin::new(OutputPin::Pin5),
             red_led: Pin::new(OutputPin::Red),
             green_led: Pin::new(InputPin::Green),
@@ -48,77 +97,533 @@ impl Assistant {
             pwm: Pin::new(InputPin::Pwm),
             cts: Pin::new(OutputPin::Cts),
             rts: Pin::new(InputPin::Rts),
+            interlock: Interlock::default(),
+        }
+    }
+
+    /// Protect the target from voltages above the ratings of its pins
+    ///
+    /// Once this is set, the assistant refuses to drive an output pin high, if
+    /// that would exceed the rating of the target's pin. See
+    /// [`crate::voltage`].
+    pub fn set_interlock(&mut self, interlock: Interlock) {
+        self.interlock = interlock;
+    }
+
+    /// Negotiate the compression scheme for frames sent by the assistant
+    ///
+    /// Once the assistant has confirmed the compression scheme, the connection
+    /// is configured to decode compressed frames. Does nothing in lite mode, as
+    /// no frames are exchanged with an assistant then.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn negotiate_compression(&mut self,
+        compression: Compression,
+        timeout:     Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.negotiate_compression_inner(compression, timeout)
+            .map_err(|err| AssistantError::NegotiateCompression(err))
+    }
+
+    fn negotiate_compression_inner(&mut self,
+        compression: Compression,
+        timeout:     Duration,
+    )
+        -> Result<(), AssistantNegotiateCompressionError>
+    {
+        let conn = match &mut self.link {
+            Link::Firmware(conn) => conn,
+            Link::Lite(_)        => return Ok(()),
+        };
+
+        conn
+            .send(&HostToAssistant::NegotiateCompression(compression))
+            .map_err(|err| AssistantNegotiateCompressionError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantNegotiateCompressionError::Receive(err))?;
+
+        match message {
+            AssistantToHost::CompressionNegotiated(negotiated) => {
+                conn.set_compression(negotiated);
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantNegotiateCompressionError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Negotiate the checksum for frames in both directions
+    ///
+    /// Once the assistant has confirmed the checksum, the connection is
+    /// configured to use it. Does nothing in lite mode, as no frames are
+    /// exchanged with an assistant then.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn negotiate_checksum(&mut self,
+        checksum: Checksum,
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.negotiate_checksum_inner(checksum, timeout)
+            .map_err(|err| AssistantError::NegotiateChecksum(err))
+    }
+
+    fn negotiate_checksum_inner(&mut self,
+        checksum: Checksum,
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantNegotiateChecksumError>
+    {
+        let conn = match &mut self.link {
+            Link::Firmware(conn) => conn,
+            Link::Lite(_)        => return Ok(()),
+        };
+
+        conn
+            .send(&HostToAssistant::NegotiateChecksum(checksum))
+            .map_err(|err| AssistantNegotiateChecksumError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantNegotiateChecksumError::Receive(err))?;
+
+        match message {
+            AssistantToHost::ChecksumNegotiated(negotiated) => {
+                conn.set_checksum(negotiated);
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantNegotiateChecksumError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
         }
     }
 
+    /// Enable flow control for the data that the assistant receives via USART
+    ///
+    /// The assistant stops forwarding data to the host, once it has forwarded
+    /// `window` messages that the host hasn't received yet. Data that it can't
+    /// buffer in the meantime is dropped, which the waiting methods report as
+    /// an error. Does nothing in lite mode, as no frames are exchanged with an
+    /// assistant then.
+    ///
+    /// Must be called after the checksum has been negotiated.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn enable_flow_control(&mut self, window: u16)
+        -> Result<(), AssistantError>
+    {
+        let conn = match &mut self.link {
+            Link::Firmware(conn) => conn,
+            Link::Lite(_)        => return Ok(()),
+        };
+
+        conn
+            .set_flow_control::<HostToAssistant>(is_usart_receive, window)
+            .map_err(|err| AssistantError::EnableFlowControl(err))
+    }
+
     /// Instruct the assistant to set pin 5 high
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn set_pin_5_high(&mut self) -> Result<(), AssistantError> {
+        self.interlock.check(OutputPin::Pin5)?;
         self.pin5
             .set_level::<HostToAssistant>(
                 pin::Level::High,
-                &mut self.conn,
+                self.link.firmware()?,
             )
             .map_err(|err| AssistantError::SetPinHigh(err))
     }
 
     /// Instruct the assistant to set pin 5 low
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn set_pin_5_low(&mut self) -> Result<(), AssistantError> {
         self.pin5
             .set_level::<HostToAssistant>(
                 pin::Level::Low,
-                &mut self.conn,
+                self.link.firmware()?,
             )
             .map_err(|err| AssistantError::SetPinLow(err))
     }
 
     /// Instruct the assistant to set the target's input pin high
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn set_pin_high(&mut self) -> Result<(), AssistantError> {
+        self.interlock.check(OutputPin::Red)?;
         self.red_led
             .set_level::<HostToAssistant>(
                 pin::Level::High,
-                &mut self.conn,
+                self.link.firmware()?,
             )
             .map_err(|err| AssistantError::SetPinHigh(err))
     }
 
     /// Instruct the assistant to set the target's input pin low
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn set_pin_low(&mut self) -> Result<(), AssistantError> {
         self.red_led
             .set_level::<HostToAssistant>(
                 pin::Level::Low,
-                &mut self.conn,
+                self.link.firmware()?,
             )
             .map_err(|err| AssistantError::SetPinLow(err))
     }
 
     /// Instruct the assistant to disable CTS
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn disable_cts(&mut self) -> Result<(), AssistantError> {
+        self.interlock.check(OutputPin::Cts)?;
         self.cts
             .set_level::<HostToAssistant>(
                 pin::Level::High,
-                &mut self.conn,
+                self.link.firmware()?,
             )
             .map_err(|err| AssistantError::SetPinHigh(err))
     }
 
     /// Instruct the assistant to enable CTS
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn enable_cts(&mut self) -> Result<(), AssistantError> {
         self.cts
             .set_level::<HostToAssistant>(
                 pin::Level::Low,
-                &mut self.conn,
+                self.link.firmware()?,
             )
             .map_err(|err| AssistantError::SetPinLow(err))
     }
 
+    /// Instruct the assistant to perform an action when firing the trigger
+    ///
+    /// Replaces any action the assistant has been armed with before. Other test
+    /// nodes can be armed with actions of their own. Use `fire_trigger` to make
+    /// all of them happen on the same edge of the trigger line.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn arm_on_trigger(&mut self, action: trigger::Action<OutputPin>)
+        -> Result<(), AssistantError>
+    {
+        self.interlock.check_action(&action)?;
+        self.link.firmware()?
+            .send(&HostToAssistant::ArmOnTrigger(action))
+            .map_err(|err| AssistantError::ArmOnTrigger(err))
+    }
+
+    /// Instruct the assistant to fire the trigger
+    ///
+    /// Make sure that all other test nodes have confirmed they're armed, before
+    /// calling this.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn fire_trigger(&mut self) -> Result<(), AssistantError> {
+        self.link.firmware()?
+            .send(&HostToAssistant::FireTrigger)
+            .map_err(|err| AssistantError::FireTrigger(err))
+    }
+
+    /// Instruct the assistant to discard its reference clock timestamps
+    ///
+    /// Call this before starting a measurement, so the timestamps returned by
+    /// `read_reference` only cover the measurement.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn reset_reference(&mut self) -> Result<(), AssistantError> {
+        self.link.firmware()?
+            .send(&HostToAssistant::ResetReference)
+            .map_err(|err| AssistantError::ResetReference(err))
+    }
+
+    /// Read the timestamps of the reference clock input
+    ///
+    /// The reference clock, like the 1 PPS output of a GPS receiver, needs to
+    /// be connected to the assistant. Use the returned timestamps to compute
+    /// how far the assistant's clock is off.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn read_reference(&mut self, timeout: Duration)
+        -> Result<reference::Timestamps, AssistantError>
+    {
+        self.read_reference_inner(timeout)
+            .map_err(|err| AssistantError::ReadReference(err))
+    }
+
+    fn read_reference_inner(&mut self, timeout: Duration)
+        -> Result<reference::Timestamps, AssistantReadReferenceError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantReadReferenceError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::ReadReference)
+            .map_err(|err| AssistantReadReferenceError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantReadReferenceError::Receive(err))?;
+
+        match message {
+            AssistantToHost::ReferenceTimestamps(timestamps) => {
+                Ok(timestamps)
+            }
+            message => {
+                Err(
+                    AssistantReadReferenceError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Drive the trigger line like an open-drain output
+    ///
+    /// The trigger line can be shared by the test nodes as a wired-AND line,
+    /// like an active-low interrupt line. Passing `Level::Low` pulls the line
+    /// low, passing `Level::High` releases it. Returns the level of the line
+    /// afterwards, which is only high, if no other test node pulls it low.
+    ///
+    /// Drive the line low again, before firing the trigger.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn drive_shared_line(&mut self, level: pin::Level, timeout: Duration)
+        -> Result<pin::Level, AssistantError>
+    {
+        self.drive_shared_line_inner(level, timeout)
+            .map_err(|err| AssistantError::DriveSharedLine(err))
+    }
+
+    fn drive_shared_line_inner(&mut self,
+        level:   pin::Level,
+        timeout: Duration,
+    )
+        -> Result<pin::Level, AssistantDriveSharedLineError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantDriveSharedLineError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::DriveSharedLine(level))
+            .map_err(|err| AssistantDriveSharedLineError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantDriveSharedLineError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SharedLineLevel(level) => {
+                Ok(level)
+            }
+            message => {
+                Err(
+                    AssistantDriveSharedLineError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Simulate a touch of the target's capacitive touch sensor
+    ///
+    /// Passing `true` grounds the sensor, passing `false` releases it.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn simulate_touch(&mut self, touched: bool, timeout: Duration)
+        -> Result<(), AssistantError>
+    {
+        self.simulate_touch_inner(touched, timeout)
+            .map_err(|err| AssistantError::SimulateTouch(err))
+    }
+
+    fn simulate_touch_inner(&mut self, touched: bool, timeout: Duration)
+        -> Result<(), AssistantSimulateTouchError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantSimulateTouchError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::SimulateTouch(touched))
+            .map_err(|err| AssistantSimulateTouchError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantSimulateTouchError::Receive(err))?;
+
+        match message {
+            AssistantToHost::TouchSimulated => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantSimulateTouchError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Set the levels of a group of the assistant's output pins at once
+    ///
+    /// Bit `n` of `mask` and `value` stands for the `OutputPin` with the value
+    /// `n`. The pins in `mask` are set high, if their bit in `value` is set,
+    /// low otherwise.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_pin_group(&mut self, mask: u32, value: u32, timeout: Duration)
+        -> Result<(), AssistantError>
+    {
+        self.set_pin_group_inner(mask, value, timeout)
+            .map_err(|err| AssistantError::SetPinGroup(err))
+    }
+
+    fn set_pin_group_inner(&mut self,
+        mask:    u32,
+        value:   u32,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantPinGroupError>
+    {
+        self.interlock.check_group(mask, value)
+            .map_err(|err| AssistantPinGroupError::UnsafeVoltage(err))?;
+
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantPinGroupError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::SetPinGroup { mask, value })
+            .map_err(|err| AssistantPinGroupError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantPinGroupError::Receive(err))?;
+
+        match message {
+            AssistantToHost::PinGroupSet => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantPinGroupError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Read the levels of a group of the assistant's input pins at once
+    ///
+    /// Bit `n` of `mask` stands for the `InputPin` with the value `n`. Returns
+    /// a bitmask of the pins in `mask` that are high.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn read_pin_group(&mut self, mask: u32, timeout: Duration)
+        -> Result<u32, AssistantError>
+    {
+        self.read_pin_group_inner(mask, timeout)
+            .map_err(|err| AssistantError::ReadPinGroup(err))
+    }
+
+    fn read_pin_group_inner(&mut self, mask: u32, timeout: Duration)
+        -> Result<u32, AssistantPinGroupError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantPinGroupError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::ReadPinGroup { mask })
+            .map_err(|err| AssistantPinGroupError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantPinGroupError::Receive(err))?;
+
+        match message {
+            AssistantToHost::PinGroup(levels) => {
+                Ok(levels)
+            }
+            message => {
+                Err(
+                    AssistantPinGroupError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Send a pulse on one of the assistant's output pins
+    ///
+    /// Inverts the level of `pin` for `width`, then restores it. Very short
+    /// widths result in a pulse of a few cycles of the assistant's clock,
+    /// which is the shortest pulse it can send.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `width` doesn't fit into `Nanoseconds`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_pulse(&mut self,
+        pin:     OutputPin,
+        width:   Duration,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.send_pulse_inner(pin, width, timeout)
+            .map_err(|err| AssistantError::SendPulse(err))
+    }
+
+    fn send_pulse_inner(&mut self,
+        pin:     OutputPin,
+        width:   Duration,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantSendPulseError>
+    {
+        let width = Nanoseconds::try_from(width).expect("Pulse too long");
+
+        // The pulse might drive the pin high, depending on its current level.
+        self.interlock.check(pin)
+            .map_err(|err| AssistantSendPulseError::UnsafeVoltage(err))?;
+
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantSendPulseError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::SendPulse { pin, width })
+            .map_err(|err| AssistantSendPulseError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantSendPulseError::Receive(err))?;
+
+        match message {
+            AssistantToHost::PulseSent => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantSendPulseError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
     /// Indicates whether the GPIO pin on the test target is set high
     ///
     /// Uses `pin_state` internally.
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn pin_is_high(&mut self) -> Result<bool, AssistantError> {
         let pin_state = self.green_led
             .read_level::<HostToAssistant, AssistantToHost>(
                 Duration::from_millis(10),
-                &mut self.conn,
+                self.link.firmware()?,
             )?;
         Ok(pin_state.0 == pin::Level::High)
     }
@@ -126,110 +631,606 @@ impl Assistant {
     /// Indicates whether the GPIO pin on the test target is set low
     ///
     /// Uses `pin_state` internally.
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn pin_is_low(&mut self) -> Result<bool, AssistantError> {
         let pin_state = self.green_led
             .read_level::<HostToAssistant, AssistantToHost>(
                 Duration::from_millis(10),
-                &mut self.conn,
+                self.link.firmware()?,
             )?;
         Ok(pin_state.0 == pin::Level::Low)
     }
 
     /// Wait for RTS signal to be enabled
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn wait_for_rts(&mut self) -> Result<bool, AssistantError> {
         let pin_state = self.rts.read_level::<HostToAssistant, AssistantToHost>(
             Duration::from_millis(10),
-            &mut self.conn,
+            self.link.firmware()?,
         )?;
         Ok(pin_state.0 == pin::Level::Low)
     }
 
     /// Instruct assistant to send this message to the target via USART
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn send_to_target_usart(&mut self, data: &[u8])
         -> Result<(), AssistantError>
     {
-        self.conn
-            .send(&HostToAssistant::SendUsart {
-                mode: UsartMode::Regular,
-                data,
-            })
+        match &mut self.link {
+            Link::Firmware(conn) => {
+                conn
+                    .send(&HostToAssistant::SendUsart {
+                        mode: UsartMode::Regular,
+                        data,
+                    })
+                    .map_err(|err| AssistantError::UsartSend(err))
+            }
+            Link::Lite(serial) => {
+                serial.send(data)
+                    .map_err(|err| AssistantError::UsartSend(
+                        ConnSendError(err.0)
+                    ))
+            }
+        }
+    }
+
+    /// Instruct assistant to send corrupted data to the target via USART
+    ///
+    /// Works like `send_to_target_usart`, except that `corruptions` are applied
+    /// to `data` first. This allows testing how the target handles specific
+    /// transmission errors. In lite mode, the corruptions are applied by the
+    /// host instead.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_to_target_usart_corrupted(&mut self,
+        data:        &[u8],
+        corruptions: inject::Corruptions,
+    )
+        -> Result<(), AssistantError>
+    {
+        match &mut self.link {
+            Link::Firmware(conn) => {
+                conn
+                    .send(&HostToAssistant::SendUsartCorrupted {
+                        data,
+                        corruptions,
+                    })
+                    .map_err(|err| AssistantError::UsartSend(err))
+            }
+            Link::Lite(serial) => {
+                let corrupted: Vec<_> = data.iter()
+                    .enumerate()
+                    .filter_map(|(position, &byte)| {
+                        corruptions.apply(position, byte)
+                    })
+                    .collect();
+
+                serial.send(&corrupted)
+                    .map_err(|err| AssistantError::UsartSend(
+                        ConnSendError(err.0)
+                    ))
+            }
+        }
+    }
+
+    /// Instruct assistant to send data to the target with the wrong parity
+    ///
+    /// Uses odd parity where even parity is configured, and vice versa. If no
+    /// parity is configured, uses even parity, which the target is going to
+    /// see as a missing stop bit. See `configure_usart`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_to_target_usart_with_wrong_parity(&mut self, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.link.firmware()?
+            .send(&HostToAssistant::SendUsartWithWrongParity(data))
             .map_err(|err| AssistantError::UsartSend(err))
     }
 
-    /// Instruct assistant to send this message to the target's USART/DMA
-    pub fn send_to_target_usart_dma(&mut self, data: &[u8])
+    /// Instruct assistant to send data to the target at the wrong baud rate
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_to_target_usart_at_wrong_baud(&mut self,
+        data: &[u8],
+        baud: u32,
+    )
         -> Result<(), AssistantError>
     {
-        self.conn
-            .send(&HostToAssistant::SendUsart { mode: UsartMode::Dma, data })
+        self.link.firmware()?
+            .send(&HostToAssistant::SendUsartAtWrongBaud { data, baud })
             .map_err(|err| AssistantError::UsartSend(err))
     }
 
-    /// Instruct assistant to send this message to the target's sync USART
-    pub fn send_to_target_usart_sync(&mut self, data: &[u8])
+    /// Instruct assistant to send an address to the target via USART
+    ///
+    /// Sends a single word with the address bit set. That's bit 8, if 9 data
+    /// bits are configured, the most significant bit of `address` otherwise.
+    /// See `configure_usart`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_address_to_target_usart(&mut self, address: u8)
         -> Result<(), AssistantError>
     {
-        self.conn
-            .send(&HostToAssistant::SendUsart { mode: UsartMode::Sync, data })
+        self.link.firmware()?
+            .send(&HostToAssistant::SendUsartAddress(address))
             .map_err(|err| AssistantError::UsartSend(err))
     }
 
-    /// Wait to receive the provided data via USART
+    /// Instruct assistant to send a break to the target via USART
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_break_to_target_usart(&mut self)
+        -> Result<(), AssistantError>
+    {
+        self.link.firmware()?
+            .send(&HostToAssistant::SendUsartBreak)
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Wait for the assistant to detect a break from the target via USART
     ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn receive_from_target_usart(&mut self, data: &[u8], timeout: Duration)
-        -> Result<Vec<u8>, AssistantError>
+    /// Data that the assistant received is left for
+    /// `receive_from_target_usart`. Returns an error, if it times out before
+    /// the break has been detected.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn wait_for_break_from_target_usart(&mut self, timeout: Duration)
+        -> Result<(), AssistantError>
     {
-        Ok(
-            self.receive_from_target_usart_inner(
-                data,
-                timeout,
-                UsartMode::Regular,
-            )?
-        )
+        self.wait_for_break_from_target_usart_inner(timeout)
+            .map_err(|err| AssistantError::UsartWait(err))
     }
 
-    /// Wait to receive the provided data via USART in synchronous mode
+    fn wait_for_break_from_target_usart_inner(&mut self, timeout: Duration)
+        -> Result<(), AssistantUsartWaitError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantUsartWaitError::NotSupported(err))?;
+
+        let mut tmp = Vec::new();
+        let message = conn
+            .receive_matching::<AssistantToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(AssistantToHost::UsartBreakDetected)
+                )
+            })
+            .map_err(|err| AssistantUsartWaitError::Receive(err))?;
+
+        match message {
+            AssistantToHost::UsartBreakDetected => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct assistant to send data, while the target is sending
     ///
-    /// Returns the receive buffer, once the data was received. Returns an
-    /// error, if it times out before that, or an I/O error occurs.
-    pub fn receive_from_target_usart_sync(&mut self,
+    /// The assistant starts sending `data`, as soon as it receives the next
+    /// byte from the target, so both directions of the USART are busy at the
+    /// same time. Returns once the assistant has confirmed, so the target can
+    /// be instructed to send afterwards. The data from the target is received
+    /// as usual. `data` must not be longer than `usart::DUPLEX_CAP`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_to_target_usart_duplex(&mut self,
         data:    &[u8],
         timeout: Duration,
     )
-        -> Result<Vec<u8>, AssistantError>
+        -> Result<(), AssistantError>
     {
-        Ok(
-            self.receive_from_target_usart_inner(
-                data,
-                timeout,
-                UsartMode::Sync,
-            )?
-        )
+        if data.len() > usart::DUPLEX_CAP {
+            return Err(AssistantError::UsartDuplexTooLong(data.len()));
+        }
+
+        self.send_to_target_usart_duplex_inner(data, timeout)
+            .map_err(|err| AssistantError::UsartDuplex(err))
     }
 
-    pub fn receive_from_target_usart_inner(&mut self,
-        data:          &[u8],
-        timeout:       Duration,
-        expected_mode: UsartMode,
+    fn send_to_target_usart_duplex_inner(&mut self,
+        data:    &[u8],
+        timeout: Duration,
     )
-        -> Result<Vec<u8>, AssistantUsartWaitError>
+        -> Result<(), AssistantUsartDuplexError>
     {
-        let mut buf   = Vec::new();
-        let     start = Instant::now();
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantUsartDuplexError::NotSupported(err))?;
 
-        loop {
-            if buf.windows(data.len()).any(|window| window == data) {
-                return Ok(buf);
+        conn.send(&HostToAssistant::SendUsartDuplex(data))
+            .map_err(|err| AssistantUsartDuplexError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantUsartDuplexError::Receive(err))?;
+
+        match message {
+            AssistantToHost::UsartDuplexArmed => {
+                Ok(())
             }
-            if start.elapsed() > timeout {
-                return Err(AssistantUsartWaitError::Timeout);
+            message => {
+                Err(
+                    AssistantUsartDuplexError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
             }
+        }
+    }
 
+    /// Instruct the assistant to count the data it receives from the target
+    ///
+    /// Until `stop_usart_count` is called, the data the target sends in
+    /// regular mode is counted, instead of being received as usual. Returns
+    /// once the assistant has confirmed, so the target can be instructed to
+    /// send afterwards.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn start_usart_count(&mut self, timeout: Duration)
+        -> Result<(), AssistantError>
+    {
+        self.usart_count_inner(HostToAssistant::StartUsartCount, timeout)
+            .map(|_| ())
+            .map_err(|err| AssistantError::UsartCount(err))
+    }
+
+    /// Stop counting the data received from the target
+    ///
+    /// Returns the count, which includes the throughput of the target's USART
+    /// (see [`usart::Count::bytes_per_sec`]).
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn stop_usart_count(&mut self, timeout: Duration)
+        -> Result<usart::Count, AssistantError>
+    {
+        self.usart_count_inner(HostToAssistant::StopUsartCount, timeout)
+            .map(|count| count.unwrap_or_default())
+            .map_err(|err| AssistantError::UsartCount(err))
+    }
+
+    fn usart_count_inner(&mut self,
+        request: HostToAssistant,
+        timeout: Duration,
+    )
+        -> Result<Option<usart::Count>, AssistantUsartCountError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantUsartCountError::NotSupported(err))?;
+
+        conn.send(&request)
+            .map_err(|err| AssistantUsartCountError::Send(err))?;
+
+        // Data received before the count started might still be on its way.
+        // Leave it for whoever expects it.
+        let mut tmp = Vec::new();
+        let message = conn
+            .receive_matching::<AssistantToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(AssistantToHost::UsartCountStarted)
+                        | Some(AssistantToHost::UsartCounted(_))
+                )
+            })
+            .map_err(|err| AssistantUsartCountError::Receive(err))?;
+
+        match (request, message) {
+            (
+                HostToAssistant::StartUsartCount,
+                AssistantToHost::UsartCountStarted,
+            ) => {
+                Ok(None)
+            }
+            (
+                HostToAssistant::StopUsartCount,
+                AssistantToHost::UsartCounted(count),
+            ) => {
+                Ok(Some(count))
+            }
+            (_, message) => {
+                Err(
+                    AssistantUsartCountError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to pause the target in the middle of a transfer
+    ///
+    /// Asserts CTS, then deasserts it for `duration`, once the target has sent
+    /// `after` bytes with flow control. Until `stop_cts_throttle` is called,
+    /// the data the target sends is counted and timestamped, instead of being
+    /// received as usual.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `duration` doesn't fit into `Microseconds`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn throttle_cts(&mut self,
+        after:    u32,
+        duration: Duration,
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.interlock.check(OutputPin::Cts)?;
+
+        let duration = Microseconds::try_from(duration)
+            .expect("CTS pause too long");
+
+        let request = HostToAssistant::ThrottleCts { after, duration };
+        self.cts_throttle_inner(request, timeout)
+            .map(|_| ())
+            .map_err(|err| AssistantError::CtsThrottle(err))
+    }
+
+    /// Stop throttling the target, and assert CTS again
+    ///
+    /// Returns what the assistant has received, including when CTS was
+    /// deasserted and when the data around that pause arrived (see
+    /// [`usart::Throttle::gap_secs`]).
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn stop_cts_throttle(&mut self, timeout: Duration)
+        -> Result<usart::Throttle, AssistantError>
+    {
+        self.cts_throttle_inner(HostToAssistant::StopCtsThrottle, timeout)
+            .map(|throttle| throttle.unwrap_or_default())
+            .map_err(|err| AssistantError::CtsThrottle(err))
+    }
+
+    fn cts_throttle_inner(&mut self,
+        request: HostToAssistant,
+        timeout: Duration,
+    )
+        -> Result<Option<usart::Throttle>, AssistantCtsThrottleError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantCtsThrottleError::NotSupported(err))?;
+
+        conn.send(&request)
+            .map_err(|err| AssistantCtsThrottleError::Send(err))?;
+
+        // Like with counting, data received before might still be on its way.
+        let mut tmp = Vec::new();
+        let message = conn
+            .receive_matching::<AssistantToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(AssistantToHost::CtsThrottleArmed)
+                        | Some(AssistantToHost::CtsThrottled(_))
+                )
+            })
+            .map_err(|err| AssistantCtsThrottleError::Receive(err))?;
+
+        match (request, message) {
+            (
+                HostToAssistant::ThrottleCts { .. },
+                AssistantToHost::CtsThrottleArmed,
+            ) => {
+                Ok(None)
+            }
+            (
+                HostToAssistant::StopCtsThrottle,
+                AssistantToHost::CtsThrottled(throttle),
+            ) => {
+                Ok(Some(throttle))
+            }
+            (_, message) => {
+                Err(
+                    AssistantCtsThrottleError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to stream data to the target, checking the echo
+    ///
+    /// The assistant sends `pattern` over and over, until it has sent `bytes`
+    /// bytes, and compares what the target sends back against it. Until
+    /// `stop_usart_stream` is called, the data the target sends is checked,
+    /// instead of being received as usual.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn start_usart_stream(&mut self,
+        pattern: &[u8],
+        bytes:   u32,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        if pattern.len() > usart::STREAM_PATTERN_CAP {
+            return Err(AssistantError::StreamPatternTooLong(pattern.len()));
+        }
+
+        let request = HostToAssistant::StartUsartStream { pattern, bytes };
+        self.usart_stream_inner(request, timeout)
+            .map(|_| ())
+            .map_err(|err| AssistantError::UsartStream(err))
+    }
+
+    /// Stop streaming data to the target
+    ///
+    /// Returns how much was sent and received, and how much of the received
+    /// data didn't match what was sent.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn stop_usart_stream(&mut self, timeout: Duration)
+        -> Result<usart::Stream, AssistantError>
+    {
+        self.usart_stream_inner(HostToAssistant::StopUsartStream, timeout)
+            .map(|stream| stream.unwrap_or_default())
+            .map_err(|err| AssistantError::UsartStream(err))
+    }
+
+    fn usart_stream_inner(&mut self,
+        request: HostToAssistant,
+        timeout: Duration,
+    )
+        -> Result<Option<usart::Stream>, AssistantUsartStreamError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantUsartStreamError::NotSupported(err))?;
+
+        conn.send(&request)
+            .map_err(|err| AssistantUsartStreamError::Send(err))?;
+
+        // Like with counting, data received before might still be on its way.
+        let mut tmp = Vec::new();
+        let message = conn
+            .receive_matching::<AssistantToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(AssistantToHost::UsartStreamStarted)
+                        | Some(AssistantToHost::UsartStreamed(_))
+                )
+            })
+            .map_err(|err| AssistantUsartStreamError::Receive(err))?;
+
+        match (request, message) {
+            (
+                HostToAssistant::StartUsartStream { .. },
+                AssistantToHost::UsartStreamStarted,
+            ) => {
+                Ok(None)
+            }
+            (
+                HostToAssistant::StopUsartStream,
+                AssistantToHost::UsartStreamed(stream),
+            ) => {
+                Ok(Some(stream))
+            }
+            (_, message) => {
+                Err(
+                    AssistantUsartStreamError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct assistant to send this message to the target's USART/DMA
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_to_target_usart_dma(&mut self, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.link.firmware()?
+            .send(&HostToAssistant::SendUsart { mode: UsartMode::Dma, data })
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Instruct assistant to send this message to the target's sync USART
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn send_to_target_usart_sync(&mut self, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.link.firmware()?
+            .send(&HostToAssistant::SendUsart { mode: UsartMode::Sync, data })
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Wait to receive the provided data via USART
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn receive_from_target_usart(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, AssistantError>
+    {
+        Ok(
+            self.receive_from_target_usart_inner(
+                data,
+                timeout,
+                UsartMode::Regular,
+            )?
+        )
+    }
+
+    /// Wait to receive the provided data via USART in synchronous mode
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn receive_from_target_usart_sync(&mut self,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, AssistantError>
+    {
+        Ok(
+            self.receive_from_target_usart_inner(
+                data,
+                timeout,
+                UsartMode::Sync,
+            )?
+        )
+    }
+
+    pub fn receive_from_target_usart_inner(&mut self,
+        data:          &[u8],
+        timeout:       Duration,
+        expected_mode: UsartMode,
+    )
+        -> Result<Vec<u8>, AssistantUsartWaitError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        loop {
+            if buf.windows(data.len()).any(|window| window == data) {
+                return Ok(buf);
+            }
+            if start.elapsed() > timeout {
+                return Err(AssistantUsartWaitError::Timeout);
+            }
+
+            let conn = match &mut self.link {
+                Link::Firmware(conn) => {
+                    conn
+                }
+                Link::Lite(_) if expected_mode != UsartMode::Regular => {
+                    return Err(
+                        AssistantUsartWaitError::NotSupported(
+                            NotSupportedError
+                        )
+                    );
+                }
+                Link::Lite(serial) => {
+                    serial.receive(timeout, &mut buf)
+                        .map_err(|err| AssistantUsartWaitError::Receive(
+                            ConnReceiveError(err.0)
+                        ))?;
+                    continue;
+                }
+            };
+
+            // The assistant might be monitoring the same line, or send other
+            // messages in the meantime. Leave them for whoever expects them.
             let mut tmp = Vec::new();
-            let message = self.conn
-                .receive::<AssistantToHost>(timeout, &mut tmp)
+            let message = conn
+                .receive_matching::<AssistantToHost>(
+                    timeout,
+                    &mut tmp,
+                    |frame| {
+                        match conn::peek(frame) {
+                            Some(AssistantToHost::UsartReceive {
+                                mode,
+                                ..
+                            }) => {
+                                mode == expected_mode
+                            }
+                            Some(AssistantToHost::UsartDropped {
+                                mode,
+                                ..
+                            }) => {
+                                mode == expected_mode
+                            }
+                            _ => {
+                                false
+                            }
+                        }
+                    },
+                )
                 .map_err(|err| AssistantUsartWaitError::Receive(err))?;
 
             match message {
@@ -238,6 +1239,11 @@ impl Assistant {
                 => {
                     buf.extend(data)
                 }
+                AssistantToHost::UsartDropped { mode, count }
+                    if mode == expected_mode
+                => {
+                    return Err(AssistantUsartWaitError::Dropped(count));
+                }
                 _ => {
                     return Err(
                         AssistantUsartWaitError::UnexpectedMessage(
@@ -249,46 +1255,691 @@ impl Assistant {
         }
     }
 
-    /// Measures the period of changes in the timer interrupt signal
+    /// Reconfigure the assistant's USART to the target
+    ///
+    /// `mode` selects the USART that is used for that mode. The target's USART
+    /// needs to be reconfigured the same way. In lite mode, this reconfigures
+    /// the USB/serial adapter, which only supports `UsartMode::Regular`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn configure_usart(&mut self,
+        mode:    UsartMode,
+        config:  &usart::Config,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.configure_usart_inner(mode, config, timeout)
+            .map_err(|err| AssistantError::ConfigureUsart(err))
+    }
+
+    fn configure_usart_inner(&mut self,
+        mode:    UsartMode,
+        config:  &usart::Config,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantConfigureUsartError>
+    {
+        let conn = match &mut self.link {
+            Link::Firmware(conn) => {
+                conn
+            }
+            Link::Lite(serial) if mode == UsartMode::Regular => {
+                return serial.configure(config)
+                    .map_err(|err| AssistantConfigureUsartError::Serial(err));
+            }
+            Link::Lite(_) => {
+                return Err(
+                    AssistantConfigureUsartError::NotSupported(
+                        NotSupportedError
+                    )
+                );
+            }
+        };
+
+        conn
+            .send(&HostToAssistant::ConfigureUsart {
+                mode,
+                config: *config,
+            })
+            .map_err(|err| AssistantConfigureUsartError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantConfigureUsartError::Receive(err))?;
+
+        match message {
+            AssistantToHost::UsartConfigured => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantConfigureUsartError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to start monitoring a USART line
+    ///
+    /// The assistant only listens, so this works while another device is the
+    /// logical peer of the transmitter. Every byte on the line is timestamped.
+    /// Use `wait_for_monitored` to receive the bytes. Replaces any monitoring
+    /// that is already in progress.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn monitor_usart(&mut self, pin: MonitorPin, baud: u32)
+        -> Result<(), AssistantError>
+    {
+        self.link.firmware()?
+            .send(&HostToAssistant::MonitorUsart { pin, baud })
+            .map_err(|err| AssistantError::Monitor(err))
+    }
+
+    /// Instruct the assistant to fire the trigger, once it sees a pattern
+    ///
+    /// Applies to the USART line that is currently being monitored. An empty
+    /// pattern disables the trigger. Use `wait_for_pattern` to find out when
+    /// the pattern has been seen.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn trigger_on_pattern(&mut self, pattern: &[u8])
+        -> Result<(), AssistantError>
+    {
+        if pattern.len() > monitor::PATTERN_CAP {
+            return Err(AssistantError::PatternTooLong(pattern.len()));
+        }
+
+        self.link.firmware()?
+            .send(&HostToAssistant::TriggerOnPattern(pattern))
+            .map_err(|err| AssistantError::Monitor(err))
+    }
+
+    /// Instruct the assistant to stop monitoring
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn stop_monitor(&mut self) -> Result<(), AssistantError> {
+        self.link.firmware()?
+            .send(&HostToAssistant::StopMonitor)
+            .map_err(|err| AssistantError::Monitor(err))
+    }
+
+    /// Wait until the provided data has been seen on the monitored line
+    ///
+    /// Returns all bytes that have been seen up to that point, with their
+    /// timestamps. Returns an error, if it times out before that.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn wait_for_monitored(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<monitor::Byte>, AssistantError>
+    {
+        self.wait_for_monitored_inner(data, timeout)
+            .map_err(|err| AssistantError::MonitorWait(err))
+    }
+
+    fn wait_for_monitored_inner(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<monitor::Byte>, AssistantMonitorWaitError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantMonitorWaitError::NotSupported(err))?;
+
+        let mut bytes  = Vec::new();
+        let mut values = Vec::new();
+        let     start  = Instant::now();
+
+        loop {
+            if values.windows(data.len()).any(|window| window == data) {
+                return Ok(bytes);
+            }
+            let remaining = timeout.checked_sub(start.elapsed())
+                .ok_or(AssistantMonitorWaitError::Timeout)?;
+
+            let mut tmp = Vec::new();
+            let message = conn
+                .receive_matching::<AssistantToHost>(
+                    remaining,
+                    &mut tmp,
+                    |frame| {
+                        matches!(
+                            conn::peek(frame),
+                            Some(AssistantToHost::MonitoredByte(_))
+                        )
+                    },
+                )
+                .map_err(|err| AssistantMonitorWaitError::Receive(err))?;
+
+            match message {
+                AssistantToHost::MonitoredByte(byte) => {
+                    bytes.push(byte);
+                    values.push(byte.value);
+                }
+                message => {
+                    return Err(
+                        AssistantMonitorWaitError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    /// Wait until the assistant has seen the pattern on the monitored line
+    ///
+    /// Returns the timestamp of the last byte of the pattern. See
+    /// `trigger_on_pattern`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn wait_for_pattern(&mut self, timeout: Duration)
+        -> Result<u32, AssistantError>
+    {
+        self.wait_for_pattern_inner(timeout)
+            .map_err(|err| AssistantError::MonitorWait(err))
+    }
+
+    fn wait_for_pattern_inner(&mut self, timeout: Duration)
+        -> Result<u32, AssistantMonitorWaitError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantMonitorWaitError::NotSupported(err))?;
+
+        let mut tmp = Vec::new();
+        let message = conn
+            .receive_matching::<AssistantToHost>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame),
+                    Some(AssistantToHost::PatternMatched { .. })
+                )
+            })
+            .map_err(|err| AssistantMonitorWaitError::Receive(err))?;
+
+        match message {
+            AssistantToHost::PatternMatched { timestamp } => {
+                Ok(timestamp)
+            }
+            message => {
+                Err(
+                    AssistantMonitorWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Measures the period of changes in the timer interrupt signal
+    ///
+    /// Waits for changes in the GPIO signal until the given number of samples
+    /// has been measured. Returns the minimum and maximum period measured, in
+    /// milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// `samples` must be at least `1`. This method will panic, if this is not
+    /// the case.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn measure_timer_interrupt(&mut self, samples: u32, timeout: Duration)
+        -> Result<GpioPeriodMeasurement, AssistantError>
+    {
+        Self::measure_gpio_period(
+            self.link.firmware()?,
+            &mut self.blue_led,
+            samples,
+            timeout,
+        )
+    }
+
+    /// Measures the period of changes in the PWM signal
+    ///
+    /// Waits for changes in the GPIO signal until the given number of samples
+    /// has been measured. Returns the minimum and maximum period measured, in
+    /// milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// `samples` must be at least `1`. This method will panic, if this is not
+    /// the case.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn measure_pwm_signal(&mut self, samples: u32, timeout: Duration)
+        -> Result<GpioPeriodMeasurement, AssistantError>
+    {
+        Self::measure_gpio_period(
+            self.link.firmware()?,
+            &mut self.pwm,
+            samples,
+            timeout,
+        )
+    }
+
+    /// Instruct the assistant to start monitoring the edges of a pin
+    ///
+    /// The assistant timestamps every edge, to measure the period and duty
+    /// cycle of the signal. Use `stop_pin_monitoring` to get the results.
+    /// Restarts the measurement, if the pin is already being monitored.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn start_pin_monitoring(&mut self, pin: InputPin)
+        -> Result<(), AssistantError>
+    {
+        self.link.firmware()?
+            .send(&HostToAssistant::StartPinMonitoring(pin))
+            .map_err(|err| AssistantError::PinMonitor(err))
+    }
+
+    /// Instruct the assistant to stop monitoring the edges of a pin
+    ///
+    /// Returns the measurements of the signal, since `start_pin_monitoring`
+    /// was called. Returns an error, if the pin wasn't being monitored.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn stop_pin_monitoring(&mut self, pin: InputPin, timeout: Duration)
+        -> Result<pin::Signal<InputPin>, AssistantError>
+    {
+        self.stop_pin_monitoring_inner(pin, timeout)
+            .map_err(|err| AssistantError::StopPinMonitor(err))
+    }
+
+    fn stop_pin_monitoring_inner(&mut self,
+        pin:     InputPin,
+        timeout: Duration,
+    )
+        -> Result<pin::Signal<InputPin>, AssistantStopPinMonitorError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantStopPinMonitorError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::StopPinMonitoring(pin))
+            .map_err(|err| AssistantStopPinMonitorError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantStopPinMonitorError::Receive(err))?;
+
+        match message {
+            AssistantToHost::PinMonitoringResult(Some(signal))
+                if signal.pin == pin =>
+            {
+                Ok(signal)
+            }
+            AssistantToHost::PinMonitoringResult(None) => {
+                Err(AssistantStopPinMonitorError::NotMonitoring)
+            }
+            message => {
+                Err(
+                    AssistantStopPinMonitorError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to play back a waveform on its output pins
+    ///
+    /// Bit `n` of each step stands for the `OutputPin` with the value `n`.
+    /// Returns once the assistant has played back the whole waveform, so
+    /// `timeout` needs to cover the sum of all delays.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn play_waveform(&mut self,
+        waveform: &waveform::Waveform,
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.play_waveform_inner(waveform, timeout)
+            .map_err(|err| AssistantError::PlayWaveform(err))
+    }
+
+    fn play_waveform_inner(&mut self,
+        waveform: &waveform::Waveform,
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantPlayWaveformError>
+    {
+        self.interlock.check_waveform(waveform)
+            .map_err(|err| AssistantPlayWaveformError::UnsafeVoltage(err))?;
+
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantPlayWaveformError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::PlayWaveform(*waveform))
+            .map_err(|err| AssistantPlayWaveformError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantPlayWaveformError::Receive(err))?;
+
+        match message {
+            AssistantToHost::WaveformPlayed => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantPlayWaveformError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Reconfigure the assistant's emulated I2C slave
+    ///
+    /// Also clears all registers of the slave, and resets its register pointer.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn configure_i2c_slave(&mut self,
+        config:  &i2c::SlaveConfig,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.configure_i2c_slave_inner(config, timeout)
+            .map_err(|err| AssistantError::ConfigureI2cSlave(err))
+    }
+
+    fn configure_i2c_slave_inner(&mut self,
+        config:  &i2c::SlaveConfig,
+        timeout: Duration,
+    )
+        -> Result<(), AssistantI2cSlaveError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantI2cSlaveError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::ConfigureI2cSlave(*config))
+            .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::I2cSlaveConfigured => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantI2cSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Write to the registers of the assistant's emulated I2C slave
     ///
-    /// Waits for changes in the GPIO signal until the given number of samples
-    /// has been measured. Returns the minimum and maximum period measured, in
-    /// milliseconds.
+    /// Writes `data`, starting at `register`. `data` must not be longer than
+    /// `i2c::TRANSFER_CAP`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn write_i2c_registers(&mut self,
+        register: u8,
+        data:     &[u8],
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        if data.len() > i2c::TRANSFER_CAP {
+            return Err(AssistantError::I2cTransferTooLong(data.len()));
+        }
+
+        self.write_i2c_registers_inner(register, data, timeout)
+            .map_err(|err| AssistantError::WriteI2cRegisters(err))
+    }
+
+    fn write_i2c_registers_inner(&mut self,
+        register: u8,
+        data:     &[u8],
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantI2cSlaveError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantI2cSlaveError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::WriteI2cRegisters { register, data })
+            .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::I2cRegistersWritten => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantI2cSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Read the registers of the assistant's emulated I2C slave
     ///
-    /// # Panics
+    /// Reads `len` registers, starting at `register`. `len` must not be larger
+    /// than `i2c::TRANSFER_CAP`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn read_i2c_registers(&mut self,
+        register: u8,
+        len:      usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, AssistantError>
+    {
+        if len > i2c::TRANSFER_CAP {
+            return Err(AssistantError::I2cTransferTooLong(len));
+        }
+
+        self.read_i2c_registers_inner(register, len as u8, timeout)
+            .map_err(|err| AssistantError::ReadI2cRegisters(err))
+    }
+
+    fn read_i2c_registers_inner(&mut self,
+        register: u8,
+        len:      u8,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, AssistantI2cSlaveError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantI2cSlaveError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::ReadI2cRegisters { register, len })
+            .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::I2cRegisters(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    AssistantI2cSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Read the transaction log of the assistant's emulated I2C slave
     ///
-    /// `samples` must be at least `1`. This method will panic, if this is not
-    /// the case.
-    pub fn measure_timer_interrupt(&mut self, samples: u32, timeout: Duration)
-        -> Result<GpioPeriodMeasurement, AssistantError>
+    /// The log covers the transactions since it was last read, or since the
+    /// slave was configured. Reading it clears it.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn i2c_transaction_log(&mut self, timeout: Duration)
+        -> Result<i2c::TransactionLog, AssistantError>
     {
-        Self::measure_gpio_period(
-            &mut self.conn,
-            &mut self.blue_led,
-            samples,
-            timeout,
-        )
+        self.i2c_transaction_log_inner(timeout)
+            .map_err(|err| AssistantError::I2cTransactionLog(err))
     }
 
-    /// Measures the period of changes in the PWM signal
+    fn i2c_transaction_log_inner(&mut self, timeout: Duration)
+        -> Result<i2c::TransactionLog, AssistantI2cSlaveError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantI2cSlaveError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::GetI2cTransactionLog)
+            .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::I2cTransactionLog(log) => {
+                Ok(log)
+            }
+            message => {
+                Err(
+                    AssistantI2cSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Load the response of the assistant's emulated SPI slave
     ///
-    /// Waits for changes in the GPIO signal until the given number of samples
-    /// has been measured. Returns the minimum and maximum period measured, in
-    /// milliseconds.
+    /// The slave sends `response` at the start of every following transaction.
+    /// `response` must not be longer than `spi::BUF_CAP`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn load_spi_response(&mut self, response: &[u8], timeout: Duration)
+        -> Result<(), AssistantError>
+    {
+        if response.len() > spi::BUF_CAP {
+            return Err(AssistantError::SpiResponseTooLong(response.len()));
+        }
+
+        self.load_spi_response_inner(response, timeout)
+            .map_err(|err| AssistantError::LoadSpiResponse(err))
+    }
+
+    fn load_spi_response_inner(&mut self,
+        response: &[u8],
+        timeout:  Duration,
+    )
+        -> Result<(), AssistantSpiSlaveError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantSpiSlaveError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::LoadSpiResponse(response))
+            .map_err(|err| AssistantSpiSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantSpiSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SpiResponseLoaded => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    AssistantSpiSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Return what the assistant's emulated SPI slave has received
     ///
-    /// # Panics
+    /// Covers the most recent transaction, up to `spi::BUF_CAP` bytes.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn spi_received(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, AssistantError>
+    {
+        self.spi_received_inner(timeout)
+            .map_err(|err| AssistantError::SpiReceived(err))
+    }
+
+    fn spi_received_inner(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, AssistantSpiSlaveError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantSpiSlaveError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::GetSpiReceived)
+            .map_err(|err| AssistantSpiSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantSpiSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SpiReceived(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    AssistantSpiSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Transfer data to an SPI slave, with the assistant acting as the master
     ///
-    /// `samples` must be at least `1`. This method will panic, if this is not
-    /// the case.
-    pub fn measure_pwm_signal(&mut self, samples: u32, timeout: Duration)
-        -> Result<GpioPeriodMeasurement, AssistantError>
+    /// Returns the data received from the slave. Afterwards, the assistant's
+    /// emulated SPI slave is ready for the next transaction, as before.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn spi_master_transfer(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, AssistantError>
     {
-        Self::measure_gpio_period(
-            &mut self.conn,
-            &mut self.pwm,
-            samples,
-            timeout,
-        )
+        if data.len() > spi::BUF_CAP {
+            return Err(AssistantError::SpiTransferTooLong(data.len()));
+        }
+
+        self.spi_master_transfer_inner(data, timeout)
+            .map_err(|err| AssistantError::SpiMasterTransfer(err))
+    }
+
+    fn spi_master_transfer_inner(&mut self,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, AssistantSpiMasterError>
+    {
+        let conn = self.link.firmware()
+            .map_err(|err| AssistantSpiMasterError::NotSupported(err))?;
+
+        conn.send(&HostToAssistant::SpiMasterTransfer(data))
+            .map_err(|err| AssistantSpiMasterError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = conn
+            .receive::<AssistantToHost>(timeout, &mut buf)
+            .map_err(|err| AssistantSpiMasterError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SpiMasterTransferred(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    AssistantSpiMasterError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
     }
 
     fn measure_gpio_period(
@@ -310,12 +1961,12 @@ impl Assistant {
             )?;
 
         for _ in 0 .. samples {
-            let (new_state, period_ms) = pin
+            let (new_state, period) = pin
                 .read_level::<HostToAssistant, AssistantToHost>(
                     timeout,
                     conn,
                 )?;
-            print!("{:?}, {:?}\n", new_state, period_ms);
+            tracing::trace!(level = ?new_state, ?period, "sample");
 
             if new_state == state {
                 continue;
@@ -323,9 +1974,9 @@ impl Assistant {
 
             state = new_state;
 
-            let period = match period_ms {
-                Some(period_ms) => Duration::from_millis(period_ms as u64),
-                None            => continue,
+            let period = match period {
+                Some(period) => period,
+                None         => continue,
             };
 
             match &mut measurement {
@@ -351,6 +2002,7 @@ impl Assistant {
     }
 
     /// Expect to hear nothing from the target within the given timeout period
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn expect_nothing_from_target(&mut self, timeout: Duration)
         -> Result<(), AssistantError>
     {
@@ -361,9 +2013,37 @@ impl Assistant {
     fn expect_nothing_from_target_inner(&mut self, timeout: Duration)
         -> Result<(), AssistantExpectNothingError>
     {
+        let conn = match &mut self.link {
+            Link::Firmware(conn) => {
+                conn
+            }
+            Link::Lite(serial) => {
+                let mut buf = Vec::new();
+                return match serial.receive(timeout, &mut buf) {
+                    Ok(()) => {
+                        Err(
+                            AssistantExpectNothingError::UnexpectedMessage(
+                                format!("{:?}", buf)
+                            )
+                        )
+                    }
+                    Err(err) if err.is_timeout() => {
+                        Ok(())
+                    }
+                    Err(err) => {
+                        Err(
+                            AssistantExpectNothingError::Receive(
+                                ConnReceiveError(err.0)
+                            )
+                        )
+                    }
+                };
+            }
+        };
+
         loop {
             let mut tmp = Vec::new();
-            let message = self.conn
+            let message = conn
                 .receive::<AssistantToHost>(timeout, &mut tmp);
 
             match message {
@@ -388,6 +2068,66 @@ impl Assistant {
 }
 
 
+enum Link {
+    /// Connected to the test assistant firmware
+    ///
+    /// Boxed, as a connection is much larger than the serial port.
+    Firmware(Box<Conn>),
+
+    /// Lite mode, connected directly to the target's USART
+    Lite(Serial),
+}
+
+impl Link {
+    fn firmware(&mut self) -> Result<&mut Conn, NotSupportedError> {
+        match self {
+            Self::Firmware(conn) => Ok(conn),
+            Self::Lite(_)        => Err(NotSupportedError),
+        }
+    }
+}
+
+
+/// Recognizes the messages that are subject to flow control
+///
+/// See [`Assistant::enable_flow_control`].
+fn is_usart_receive(frame: &[u8]) -> bool {
+    matches!(
+        conn::peek(frame),
+        Some(AssistantToHost::UsartReceive { .. })
+    )
+}
+
+/// Recognizes panic reports sent by the assistant
+///
+/// See [`Conn::set_panic_filter`].
+fn panic_report(frame: &[u8]) -> Option<String> {
+    match conn::peek(frame)? {
+        AssistantToHost::Panic(report) => Some(report.to_string()),
+        _                              => None,
+    }
+}
+
+/// Decodes the messages exchanged with the assistant, for recordings
+///
+/// See [`Conn::set_record_decoder`].
+fn decode_message(direction: Direction, frame: &[u8]) -> Option<String> {
+    match direction {
+        Direction::Send => {
+            conn::peek::<HostToAssistant>(frame)
+                .map(|message| format!("{:?}", message))
+        }
+        Direction::Receive => {
+            conn::peek::<AssistantToHost>(frame)
+                .map(|message| format!("{:?}", message))
+        }
+        Direction::Sync => {
+            None
+        }
+    }
+}
+
+
 #[derive(Debug)]
 pub struct GpioPeriodMeasurement {
     pub min: Duration,
@@ -398,12 +2138,55 @@ pub struct GpioPeriodMeasurement {
 /// All the errors that can be returned by this API
 #[derive(Debug)]
 pub enum AssistantError {
+    ArmOnTrigger(ConnSendError),
+    ConfigureI2cSlave(AssistantI2cSlaveError),
+    ConfigureUsart(AssistantConfigureUsartError),
+    CtsThrottle(AssistantCtsThrottleError),
+    DriveSharedLine(AssistantDriveSharedLineError),
+    EnableFlowControl(ConnSendError),
     ExpectNothing(AssistantExpectNothingError),
+    FireTrigger(ConnSendError),
+    I2cTransactionLog(AssistantI2cSlaveError),
+    I2cTransferTooLong(usize),
+    LoadSpiResponse(AssistantSpiSlaveError),
+    Monitor(ConnSendError),
+    MonitorWait(AssistantMonitorWaitError),
+    NegotiateChecksum(AssistantNegotiateChecksumError),
+    NegotiateCompression(AssistantNegotiateCompressionError),
+    NotSupported(NotSupportedError),
+    PatternTooLong(usize),
+    PinMonitor(ConnSendError),
     PinRead(ReadLevelError),
+    PlayWaveform(AssistantPlayWaveformError),
+    ReadI2cRegisters(AssistantI2cSlaveError),
+    ReadPinGroup(AssistantPinGroupError),
+    ReadReference(AssistantReadReferenceError),
+    ResetReference(ConnSendError),
+    SendPulse(AssistantSendPulseError),
+    SetPinGroup(AssistantPinGroupError),
     SetPinHigh(ConnSendError),
     SetPinLow(ConnSendError),
+    SimulateTouch(AssistantSimulateTouchError),
+    SpiMasterTransfer(AssistantSpiMasterError),
+    SpiReceived(AssistantSpiSlaveError),
+    SpiResponseTooLong(usize),
+    SpiTransferTooLong(usize),
+    StopPinMonitor(AssistantStopPinMonitorError),
+    StreamPatternTooLong(usize),
+    UnsafeVoltage(UnsafeVoltageError),
+    UsartCount(AssistantUsartCountError),
+    UsartDuplex(AssistantUsartDuplexError),
+    UsartDuplexTooLong(usize),
     UsartSend(ConnSendError),
+    UsartStream(AssistantUsartStreamError),
     UsartWait(AssistantUsartWaitError),
+    WriteI2cRegisters(AssistantI2cSlaveError),
+}
+
+impl From<NotSupportedError> for AssistantError {
+    fn from(err: NotSupportedError) -> Self {
+        Self::NotSupported(err)
+    }
 }
 
 impl From<ReadLevelError> for AssistantError {
@@ -412,6 +2195,12 @@ impl From<ReadLevelError> for AssistantError {
     }
 }
 
+impl From<UnsafeVoltageError> for AssistantError {
+    fn from(err: UnsafeVoltageError) -> Self {
+        Self::UnsafeVoltage(err)
+    }
+}
+
 impl From<AssistantUsartWaitError> for AssistantError {
     fn from(err: AssistantUsartWaitError) -> Self {
         Self::UsartWait(err)
@@ -421,6 +2210,8 @@ impl From<AssistantUsartWaitError> for AssistantError {
 
 #[derive(Debug)]
 pub enum AssistantUsartWaitError {
+    Dropped(u32),
+    NotSupported(NotSupportedError),
     Receive(ConnReceiveError),
     Timeout,
     UnexpectedMessage(String),
@@ -431,3 +2222,154 @@ pub enum AssistantExpectNothingError {
     Receive(ConnReceiveError),
     UnexpectedMessage(String),
 }
+
+#[derive(Debug)]
+pub enum AssistantNegotiateCompressionError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantNegotiateChecksumError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantConfigureUsartError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    Serial(SerialConfigureError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantMonitorWaitError {
+    NotSupported(NotSupportedError),
+    Receive(ConnReceiveError),
+    Timeout,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantReadReferenceError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantDriveSharedLineError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSimulateTouchError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantCtsThrottleError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantUsartStreamError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantUsartCountError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantUsartDuplexError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantStopPinMonitorError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    NotMonitoring,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantPlayWaveformError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+    UnsafeVoltage(UnsafeVoltageError),
+}
+
+#[derive(Debug)]
+pub enum AssistantPinGroupError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+    UnsafeVoltage(UnsafeVoltageError),
+}
+
+#[derive(Debug)]
+pub enum AssistantSendPulseError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+    UnsafeVoltage(UnsafeVoltageError),
+}
+
+#[derive(Debug)]
+pub enum AssistantI2cSlaveError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSpiSlaveError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSpiMasterError {
+    NotSupported(NotSupportedError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+/// The operation is not supported, as the assistant is in lite mode
+#[derive(Clone, Copy, Debug)]
+pub struct NotSupportedError;
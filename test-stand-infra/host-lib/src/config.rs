@@ -1,40 +1,385 @@
 //! Test suite configuration
+//!
+//! The configuration is read from `test-stand.toml`. Its top level describes
+//! the default test stand. A single file can describe several stands, which
+//! share a test suite, but differ in their wiring or hardware: Each table in
+//! `[stands]` is a profile, which is laid over the top level, when it is
+//! selected through the `TEST_STAND` environment variable (see [`STAND_VAR`]).
+//!
+//! Single settings can be overridden through environment variables too, for
+//! example to point a CI job at other serial devices, without editing the
+//! file. See [`OVERRIDE_PREFIX`].
+//!
+//! The configuration is validated after it has been read. Unknown settings are
+//! rejected, so typos don't go unnoticed, and so are combinations of settings
+//! that can't work.
 
 
 use std::{
+    collections::BTreeMap,
+    env,
     fs::File,
     io::prelude::*,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Duration,
 };
 
+use protocol::{
+    checksum::Checksum,
+    compress::Compression,
+};
 use serde::Deserialize;
+use toml::{
+    Value,
+    value::Table,
+};
+
+use crate::{
+    Error,
+    conn::Retry,
+    report,
+    tolerances::Tolerances,
+    topology::Topology,
+    transport::{
+        REPLAY_PREFIX,
+        TCP_PREFIX,
+    },
+    voltage::Interlock,
+};
+
+
+/// The environment variable that selects a profile from `[stands]`
+pub const STAND_VAR: &str = "TEST_STAND";
+
+/// The prefix of the environment variables that override single settings
+///
+/// The name of the setting follows in upper case, like `TEST_STAND_TARGET` for
+/// `target`. Only the settings in [`OVERRIDABLE`] can be overridden. Values are
+/// parsed like in the configuration file, so `TEST_STAND_RECORD=true` is a
+/// boolean. Anything that doesn't parse is taken as a string, so addresses
+/// don't need to be quoted.
+pub const OVERRIDE_PREFIX: &str = "TEST_STAND_";
+
+/// The settings that can be overridden through environment variables
+pub const OVERRIDABLE: &[&str] = &[
+    "target",
+    "assistant",
+    "serial",
+    "compression",
+    "checksum",
+    "chunk_size",
+    "reference_hz",
+    "artifacts",
+    "record",
+    "report",
+];
 
-use crate::Error;
+/// Default for [`Port::baud`]
+pub const DEFAULT_BAUD: u32 = 115200;
+
+/// Default for [`Timeouts::negotiate`]
+pub const DEFAULT_NEGOTIATE_MS: u64 = 50;
+
+/// Default for [`Timeouts::reply`]
+pub const DEFAULT_REPLY_MS: u64 = 50;
+
+/// Default for [`Timeouts::read`]
+pub const DEFAULT_READ_MS: u64 = 10;
+
+/// Default for [`Timeouts::restart`]
+pub const DEFAULT_RESTART_MS: u64 = 5000;
+
+/// Default for [`Timeouts::test`]
+pub const DEFAULT_TEST_MS: u64 = 60_000;
 
 
 /// The configuration options for the test suite
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// The profile from `[stands]` that has been applied, if any
+    ///
+    /// Not read from the file itself, but selected through [`STAND_VAR`].
+    #[serde(skip)]
+    pub stand: Option<String>,
+
     /// Path to the serial device connected to the test target
+    ///
+    /// Can also be `tcp://host:port`, to connect to a target whose serial port
+    /// is reachable over the network, or `usb://vid:pid[/serial]`, to find a
+    /// target that has its own USB serial port (see
+    /// [`crate::transport::open`]).
     pub target: Option<String>,
 
     /// Path to the serial device connected to the test assistant
+    ///
+    /// Can also be `tcp://host:port`, like `target`.
     pub assistant: Option<String>,
 
+    /// Additional test targets, by name
+    ///
+    /// For test cases that involve more than one device under test, like
+    /// board-to-board communication. Specified as a `[targets]` table that
+    /// maps each name to an address, like `target`.
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+
+    /// Additional test assistants, by name
+    ///
+    /// Specified as an `[assistants]` table, like `targets`.
+    #[serde(default)]
+    pub assistants: BTreeMap<String, String>,
+
+    /// Direct connections between test nodes
+    ///
+    /// Specified as a `[[links]]` array, each entry naming a bus and the two
+    /// test nodes it connects. See [`crate::topology`].
+    #[serde(default)]
+    pub links: Topology,
+
+    /// Settings of serial ports, by the address of the test node
+    ///
+    /// Specified as a `[ports]` table, with an entry for each address that
+    /// needs settings other than the defaults, like
+    /// `[ports."/dev/ttyUSB0"]`. The address must be spelled exactly like in
+    /// `target`, `assistant`, and so on. See [`Config::port`].
+    #[serde(default)]
+    pub ports: BTreeMap<String, Port>,
+
+    /// Firmware to download to the test nodes, by the name of the test node
+    ///
+    /// Specified as a `[flash]` table, with an entry for each test node that
+    /// should be flashed before the test cases run, like `[flash.target]`.
+    /// Names are `target`, `assistant`, or a name from `targets` or
+    /// `assistants`. See [`crate::flash`].
+    #[serde(default)]
+    pub flash: BTreeMap<String, Flash>,
+
+    /// Voltage levels of the assistants' output pins, by the name of the
+    /// assistant
+    ///
+    /// Specified as a `[voltage]` table, with an entry for each pin that needs
+    /// to be protected, like `[voltage.assistant.pin5]`. Names are
+    /// `assistant`, or a name from `assistants`. See [`crate::voltage`].
+    #[serde(default)]
+    pub voltage: BTreeMap<String, Interlock>,
+
+    /// ELF files to decode defmt logs with, by the name of the test node
+    ///
+    /// Specified as a `[defmt]` table, with an entry for each test node that
+    /// logs using defmt, like `target = "path/to/firmware"`. Relative to the
+    /// test suite. Names are like in `flash`. See [`crate::defmt`].
+    #[serde(default)]
+    pub defmt: BTreeMap<String, PathBuf>,
+
     /// Path to the serial device connected to the USB/serial converter
+    ///
+    /// If no test assistant is specified, the converter is used to run the
+    /// assistant in lite mode. It needs to be connected directly to the
+    /// target's USART then. Otherwise, it is available as a raw serial
+    /// connection.
     pub serial: Option<String>,
+
+    /// Compression scheme to negotiate with the test nodes
+    ///
+    /// Frames are not compressed, if this is not specified.
+    pub compression: Option<Compression>,
+
+    /// Checksum to negotiate with the test nodes
+    ///
+    /// Frames don't carry a checksum, if this is not specified.
+    pub checksum: Option<Checksum>,
+
+    /// Size of the chunks that large requests are split into, in bytes
+    ///
+    /// Requests that serialize to more than this are split into chunks, which
+    /// the test nodes reassemble. Defaults to `protocol::chunk::DEFAULT_SIZE`,
+    /// if this is not specified. See [`crate::conn::Conn::set_chunk_size`].
+    pub chunk_size: Option<usize>,
+
+    /// Frequency of the reference clock connected to the test assistant, in Hz
+    ///
+    /// Test cases that need a reference clock, like the 1 PPS output of a GPS
+    /// receiver, can't run, if this is not specified.
+    pub reference_hz: Option<f64>,
+
+    /// Tolerances for timing and analog assertions
+    ///
+    /// Every tolerance that is not specified has a default value.
+    #[serde(default)]
+    pub tolerances: Tolerances,
+
+    /// Timeouts for communicating with the test nodes
+    ///
+    /// Every timeout that is not specified has a default value.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+
+    /// Retry operations that fail because of a corrupted frame
+    ///
+    /// Specified as a `[retry]` table, like `attempts = 2`. Useful for stands
+    /// with noisy links. If this is not specified, such failures end the test
+    /// case. See [`crate::conn::Conn::set_retry`].
+    pub retry: Option<Retry>,
+
+    /// What the hardware of this test stand provides
+    ///
+    /// Specified as a `[features]` table, like `i2c_slave = true`. Test cases
+    /// can check these, to skip what a stand can't do. See [`Features`].
+    #[serde(default)]
+    pub features: Features,
+
+    /// Directory for artifacts of the test run, like per-test logs
+    ///
+    /// Relative to the test suite. If this is not specified, logs are written
+    /// to the output of the test cases instead. See [`crate::trace`].
+    pub artifacts: Option<PathBuf>,
+
+    /// Record the traffic of each test case
+    ///
+    /// Every frame sent to or received from a test node is recorded to
+    /// `<test binary>/<test case>.jsonl` in the `artifacts` directory, next to
+    /// the log. Requires `artifacts`. See [`crate::record`].
+    #[serde(default)]
+    pub record: bool,
+
+    /// Write a report of the test run, for CI
+    ///
+    /// `"junit"` for JUnit XML, or `"json"` for JSON, including what the test
+    /// cases measured. Written to `report.xml` or `report.json` in the
+    /// `artifacts` directory, by the supervisor. Requires `artifacts`. See
+    /// [`crate::report`].
+    pub report: Option<report::Format>,
 }
 
 impl Config {
+    /// The path of the configuration file, relative to the test suite
+    pub const DEFAULT_PATH: &'static str = "test-stand.toml";
+
     /// Read configuration from the `test-stand.toml` file
     pub fn read() -> Result<Self, ConfigReadError> {
-        Self::read_inner()
-            .map_err(|err| ConfigReadError(err))
+        Self::read_from(Self::DEFAULT_PATH)
+    }
+
+    /// Read configuration from the file at `path`
+    ///
+    /// Applies the profile selected through [`STAND_VAR`], and the overrides
+    /// from the environment, then validates the result.
+    pub fn read_from(path: impl AsRef<Path>) -> Result<Self, ConfigReadError> {
+        let stand = env::var(STAND_VAR).ok()
+            .filter(|stand| !stand.is_empty());
+        let overrides = OVERRIDABLE.iter()
+            .filter_map(|&key| {
+                let var = format!("{}{}", OVERRIDE_PREFIX, key.to_uppercase());
+                env::var(var).ok()
+                    .map(|value| (key, value))
+            });
+
+        let config = Self::read_file(path.as_ref())
+            .map_err(|err| ConfigReadError::Read(err))?;
+
+        Self::from_table(config, stand.as_deref(), overrides)
+    }
+
+    /// Build the configuration from the contents of a configuration file
+    ///
+    /// Applies the profile `stand`, and the `overrides`, which map names of
+    /// settings to their values, like the environment variables do. Used by
+    /// [`Config::read_from`], which gets those from the environment.
+    pub fn from_table<'a>(
+        mut config: Table,
+        stand:      Option<&str>,
+        overrides:  impl IntoIterator<Item = (&'a str, String)>,
+    )
+        -> Result<Self, ConfigReadError>
+    {
+        let mut stands = match config.remove("stands") {
+            Some(Value::Table(stands)) => stands,
+            Some(_) => {
+                return Err(
+                    ConfigReadError::Invalid(vec![
+                        String::from(
+                            "`stands` must be a table of profiles, like \
+                            `[stands.ci]`"
+                        ),
+                    ])
+                );
+            }
+            None => Table::new(),
+        };
+
+        if let Some(name) = stand {
+            match stands.remove(name) {
+                Some(Value::Table(profile)) => {
+                    merge(&mut config, profile);
+                }
+                Some(_) => {
+                    return Err(
+                        ConfigReadError::Invalid(vec![
+                            format!("`stands.{}` must be a table", name),
+                        ])
+                    );
+                }
+                None => {
+                    return Err(
+                        ConfigReadError::UnknownStand {
+                            name:      name.to_owned(),
+                            available: stands.keys().cloned().collect(),
+                        }
+                    );
+                }
+            }
+        }
+
+        for (key, value) in overrides {
+            config.insert(key.to_owned(), parse_override(value));
+        }
+
+        let mut config: Self = Value::Table(config).try_into()
+            .map_err(|err| ConfigReadError::Read(Error::Config(err)))?;
+        config.stand = stand.map(|stand| stand.to_owned());
+
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(ConfigReadError::Invalid(problems));
+        }
+
+        Ok(config)
     }
 
-    fn read_inner() -> Result<Self, Error> {
+    /// The settings of the serial port at `address`
+    ///
+    /// Returns the defaults, if there's no entry for `address` in `[ports]`.
+    pub fn port(&self, address: &str) -> Port {
+        self.ports.get(address)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The address of the test node `node`
+    ///
+    /// `node` is `target`, `assistant`, or a name from `targets` or
+    /// `assistants`, like in `links` and `flash`.
+    pub fn address(&self, node: &str) -> Option<&str> {
+        let address = match node {
+            "target"    => self.target.as_ref(),
+            "assistant" => self.assistant.as_ref(),
+            name        => {
+                self.targets.get(name)
+                    .or_else(|| self.assistants.get(name))
+            }
+        };
+
+        address.map(|address| address.as_str())
+    }
+
+    fn read_file(path: &Path) -> Result<Table, Error> {
         // Read configuration file
         let mut config = Vec::new();
-        File::open("test-stand.toml")?
+        File::open(path)?
             .read_to_end(&mut config)?;
 
         // Parse configuration file
@@ -42,9 +387,399 @@ impl Config {
 
         Ok(config)
     }
+
+    /// Check the combination of settings
+    ///
+    /// Returns a description of every problem, including how to fix it.
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        // Entries that don't match any address aren't a problem, as the
+        // `[ports]` table can be shared by profiles that use other devices.
+        for (address, port) in &self.ports {
+            if address.starts_with(TCP_PREFIX)
+                || address.starts_with(REPLAY_PREFIX)
+            {
+                problems.push(format!(
+                    "`ports.\"{}\"` has no effect, as the address doesn't \
+                    refer to a serial port. Remove it.",
+                    address,
+                ));
+            }
+            if port.baud == Some(0) {
+                problems.push(format!(
+                    "`ports.\"{}\".baud` must not be 0. Remove it, to use \
+                    the default of {}.",
+                    address, DEFAULT_BAUD,
+                ));
+            }
+        }
+
+        if self.record && self.artifacts.is_none() {
+            problems.push(String::from(
+                "`record` requires `artifacts`. Set it to the directory the \
+                recordings should go to, like \"target/test-stand\".",
+            ));
+        }
+        if self.report.is_some() && self.artifacts.is_none() {
+            problems.push(String::from(
+                "`report` requires `artifacts`. Set it to the directory the \
+                report should go to, like \"target/test-stand\".",
+            ));
+        }
+        if self.chunk_size == Some(0) {
+            problems.push(String::from(
+                "`chunk_size` must not be 0. Remove it, to use the default.",
+            ));
+        }
+        if let Some(hz) = self.reference_hz {
+            if !(hz.is_finite() && hz > 0.0) {
+                problems.push(format!(
+                    "`reference_hz` must be a positive frequency, not {}. \
+                    Remove it, if no reference clock is connected.",
+                    hz,
+                ));
+            }
+        }
+
+        for (name, ms) in self.timeouts.configured() {
+            if ms == 0 {
+                problems.push(format!(
+                    "`timeouts.{}` must not be 0. Remove it, to use the \
+                    default.",
+                    name,
+                ));
+            }
+        }
+
+        if let Some(retry) = &self.retry {
+            if retry.quiet_ms == Some(0) {
+                problems.push(String::from(
+                    "`retry.quiet_ms` must not be 0. Remove it, to use the \
+                    default.",
+                ));
+            }
+        }
+
+        let mut probes = BTreeMap::new();
+        for (node, flash) in &self.flash {
+            if self.address(node).is_none() {
+                problems.push(format!(
+                    "`flash.{}` doesn't refer to a configured test node. Use \
+                    `target`, `assistant`, or a name from `targets` or \
+                    `assistants`.",
+                    node,
+                ));
+            }
+            if let Some(probe) = &flash.probe {
+                if let Some(other) = probes.insert(probe, node) {
+                    problems.push(format!(
+                        "`flash.{}` and `flash.{}` use the same probe ({}). \
+                        Each test node needs its own probe.",
+                        other, node, probe,
+                    ));
+                }
+            }
+        }
+
+        for node in self.defmt.keys() {
+            if self.address(node).is_none() {
+                problems.push(format!(
+                    "`defmt.{}` doesn't refer to a configured test node. Use \
+                    `target`, `assistant`, or a name from `targets` or \
+                    `assistants`.",
+                    node,
+                ));
+            }
+        }
+
+        for (node, interlock) in &self.voltage {
+            let is_assistant = match node.as_str() {
+                "assistant" => self.assistant.is_some(),
+                name        => self.assistants.contains_key(name),
+            };
+            if !is_assistant {
+                problems.push(format!(
+                    "`voltage.{}` doesn't refer to a configured test \
+                    assistant. Use `assistant`, or a name from `assistants`.",
+                    node,
+                ));
+            }
+            for (pin, rating) in interlock.configured() {
+                let voltages = [
+                    ("drive_v", rating.drive_v),
+                    ("max_v",   rating.max_v),
+                ];
+                for &(name, v) in &voltages {
+                    if !(v.is_finite() && v > 0.0) {
+                        problems.push(format!(
+                            "`voltage.{}.{}.{}` must be a positive voltage, \
+                            not {}.",
+                            node, pin, name, v,
+                        ));
+                    }
+                }
+            }
+        }
+
+        let has_assistant = self.assistant.is_some() || self.serial.is_some();
+        if self.features.has(Features::ASSISTANT) && !has_assistant {
+            problems.push(String::from(
+                "`features.assistant` is enabled, but neither `assistant` \
+                nor `serial` is set. Set one of them, or disable the feature.",
+            ));
+        }
+
+        problems
+    }
+}
+
+
+/// The settings of a serial port
+///
+/// An entry in the `[ports]` table of the configuration file. Only applies to
+/// test nodes that are connected through a serial port, and to the USB/serial
+/// converter. USB CDC devices ignore these settings.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Port {
+    /// The baud rate
+    ///
+    /// Defaults to [`DEFAULT_BAUD`], which the test nodes use for their
+    /// USARTs, if this is not specified.
+    pub baud: Option<u32>,
+
+    /// The flow control of the serial port
+    ///
+    /// Defaults to [`FlowControl::None`], if this is not specified. Not to be
+    /// confused with the flow control of the protocol (see [`crate::flow`]).
+    pub flow_control: Option<FlowControl>,
+}
+
+impl Port {
+    /// The configured baud rate, or the default
+    pub fn baud(&self) -> u32 {
+        self.baud.unwrap_or(DEFAULT_BAUD)
+    }
+
+    /// The configured flow control, or the default
+    pub fn flow_control(&self) -> FlowControl {
+        self.flow_control.unwrap_or(FlowControl::None)
+    }
+}
+
+
+/// The flow control of a serial port
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    None,
+    Software,
+    Hardware,
+}
+
+impl From<FlowControl> for serialport::FlowControl {
+    fn from(flow_control: FlowControl) -> Self {
+        match flow_control {
+            FlowControl::None     => Self::None,
+            FlowControl::Software => Self::Software,
+            FlowControl::Hardware => Self::Hardware,
+        }
+    }
+}
+
+
+/// The firmware of a test node, and how to download it
+///
+/// An entry in the `[flash]` table of the configuration file. See
+/// [`crate::flash`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Flash {
+    /// Path to the ELF file of the firmware
+    ///
+    /// Relative to the test suite. The firmware needs to be built already.
+    pub elf: PathBuf,
+
+    /// The chip of the test node, as probe-rs names it
+    ///
+    /// Like `LPC845M301JBD48`. Run `probe-rs chip list` for a list.
+    pub chip: String,
+
+    /// The serial number of the debug probe connected to the test node
+    ///
+    /// Can be left out, if only one probe is connected to the host. Run
+    /// `probe-rs list` for the serial numbers of the connected probes.
+    pub probe: Option<String>,
+}
+
+
+/// The `timeouts` section of the configuration file
+///
+/// All timeouts are optional. Use the accessors to get the configured value,
+/// or the default, if a timeout is not configured. The `with_` methods replace
+/// a timeout, for example to give a single operation more time (see
+/// [`crate::target::Target::with_timeouts`]).
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Timeouts {
+    negotiate_ms: Option<u64>,
+    reply_ms:     Option<u64>,
+    read_ms:      Option<u64>,
+    restart_ms:   Option<u64>,
+    test_ms:      Option<u64>,
+}
+
+impl Timeouts {
+    /// How long to wait for a test node to confirm connection parameters
+    ///
+    /// Used while initializing the test stand, when compression and checksum
+    /// are negotiated.
+    pub fn negotiate(&self) -> Duration {
+        Duration::from_millis(
+            self.negotiate_ms.unwrap_or(DEFAULT_NEGOTIATE_MS)
+        )
+    }
+
+    /// How long to wait for a reply, if a test case doesn't need a specific
+    /// timeout
+    ///
+    /// Stands with slow links, like a serial port that is reached over the
+    /// network, can raise this for all such test cases at once.
+    pub fn reply(&self) -> Duration {
+        Duration::from_millis(self.reply_ms.unwrap_or(DEFAULT_REPLY_MS))
+    }
+
+    /// How long to wait before reading an input of a test node, and for the
+    /// reply
+    ///
+    /// Used by the methods that read a pin or an ADC channel, and don't take a
+    /// timeout, like [`crate::target::Target::pin_is_high`]. Waiting gives
+    /// whatever event is expected to change the input some time to happen.
+    pub fn read(&self) -> Duration {
+        Duration::from_millis(self.read_ms.unwrap_or(DEFAULT_READ_MS))
+    }
+
+    /// How long to wait for a test node to start up, after it has been reset
+    ///
+    /// Used after downloading firmware to a test node, or resetting it (see
+    /// [`crate::flash`]).
+    pub fn restart(&self) -> Duration {
+        Duration::from_millis(self.restart_ms.unwrap_or(DEFAULT_RESTART_MS))
+    }
+
+    /// How long a test case may run, before it's considered hanging
+    ///
+    /// Only applies to test cases that run under a supervisor, which kills
+    /// them after this (see [`crate::supervisor`]).
+    pub fn test(&self) -> Duration {
+        Duration::from_millis(self.test_ms.unwrap_or(DEFAULT_TEST_MS))
+    }
+
+    /// Replace the timeout for replies
+    ///
+    /// Rounded down to milliseconds, like in the configuration file.
+    pub fn with_reply(mut self, timeout: Duration) -> Self {
+        self.reply_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// Replace the timeout for reading inputs
+    ///
+    /// Rounded down to milliseconds, like in the configuration file.
+    pub fn with_read(mut self, timeout: Duration) -> Self {
+        self.read_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    fn configured(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        let negotiate = self.negotiate_ms.map(|ms| ("negotiate_ms", ms));
+        let reply     = self.reply_ms.map(|ms| ("reply_ms", ms));
+        let read      = self.read_ms.map(|ms| ("read_ms", ms));
+        let restart   = self.restart_ms.map(|ms| ("restart_ms", ms));
+        let test      = self.test_ms.map(|ms| ("test_ms", ms));
+
+        negotiate.into_iter()
+            .chain(reply)
+            .chain(read)
+            .chain(restart)
+            .chain(test)
+    }
+}
+
+
+/// The `features` section of the configuration file
+///
+/// Flags that describe what the hardware of a test stand provides. Test cases
+/// check them with [`Features::has`], to skip what a stand can't do. A feature
+/// that is not specified is not provided. Names other than the constants here
+/// are up to the test suite.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Features(BTreeMap<String, bool>);
+
+impl Features {
+    /// A test assistant is connected
+    ///
+    /// Validated against the configuration: Either `assistant` or `serial` must
+    /// be set, if this is enabled.
+    pub const ASSISTANT: &'static str = "assistant";
+
+    /// The test assistant emulates an I2C slave for the target
+    pub const I2C_SLAVE: &'static str = "i2c_slave";
+
+    /// Indicates whether the feature `name` is enabled
+    pub fn has(&self, name: &str) -> bool {
+        self.0.get(name).copied().unwrap_or(false)
+    }
+}
+
+
+/// Lay the tables of a profile over the configuration
+///
+/// Settings in `profile` replace those in `config`, except for tables, which
+/// are merged. A profile that only changes one tolerance keeps the others.
+fn merge(config: &mut Table, profile: Table) {
+    for (key, value) in profile {
+        match (config.get_mut(&key), value) {
+            (Some(Value::Table(table)), Value::Table(value)) => {
+                merge(table, value);
+            }
+            (_, value) => {
+                config.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parse the value of an override, like a value in the configuration file
+///
+/// Values that aren't valid TOML are taken as strings.
+fn parse_override(value: String) -> Value {
+    format!("value = {}", value)
+        .parse::<Value>()
+        .ok()
+        .and_then(|parsed| parsed.get("value").cloned())
+        .unwrap_or(Value::String(value))
 }
 
 
 /// Error reading the configuration file
 #[derive(Debug)]
-pub struct ConfigReadError(pub Error);
+pub enum ConfigReadError {
+    /// Error reading or parsing the file
+    ///
+    /// This includes settings that don't exist, or have the wrong type.
+    Read(Error),
+
+    /// The profile selected through `TEST_STAND` doesn't exist
+    UnknownStand {
+        name:      String,
+        available: Vec<String>,
+    },
+
+    /// The settings don't work together
+    ///
+    /// Describes each problem, and how to fix it.
+    Invalid(Vec<String>),
+}
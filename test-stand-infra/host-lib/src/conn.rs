@@ -1,66 +1,784 @@
 use std::{
+    collections::VecDeque,
+    convert::TryFrom as _,
     io,
+    mem,
     slice,
-    time::Duration,
+    sync::{
+        Arc,
+        Mutex,
+        MutexGuard,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
+use protocol::{
+    LINK_SYNC,
+    SYNC_PREAMBLE,
+    checksum::Checksum,
+    chunk::{
+        self,
+        AsMessage,
+        Chunk,
+        Chunked,
+    },
+    compress::Compression,
+    flow::Grant,
+};
 use serde::{
     Deserialize,
     Serialize,
 };
-use serialport::{
-    self,
-    SerialPort,
+use test_stand_decoder::frame::{
+    decode_frame,
+    encode_frame,
+    match_sync,
+};
+
+use crate::{
+    Error,
+    config::Port,
+    flow::{
+        self,
+        FlowControl,
+    },
+    defmt,
+    log,
+    record::{
+        self,
+        Direction,
+        Recorder,
+    },
+    signal::{
+        self,
+        Registration,
+    },
+    transport::{
+        self,
+        REPLAY_PREFIX,
+        Transport,
+    },
 };
 
-use crate::Error;
+
+#[cfg(feature = "tokio")]
+mod async_conn;
+
+#[cfg(feature = "tokio")]
+pub use self::async_conn::AsyncConn;
+
+/// Recognizes reports of dropped data among the received frames
+///
+/// Is passed each decoded frame. Returns the number of dropped items, if the
+/// frame is such a report. See [`Conn::set_overrun_filter`].
+pub type OverrunFilter = fn(&[u8]) -> Option<u32>;
+
+/// Recognizes panic reports among the received frames
+///
+/// Is passed each decoded frame. Returns the panic message and location, if
+/// the frame is a panic report. See [`Conn::set_panic_filter`].
+pub type PanicFilter = fn(&[u8]) -> Option<String>;
+
+/// Recognizes heartbeats among the received frames
+///
+/// Is passed each decoded frame. Returns `true`, if the frame is a heartbeat.
+/// See [`Conn::set_heartbeat_filter`].
+pub type HeartbeatFilter = fn(&[u8]) -> bool;
+
+/// Encodes the frame that resynchronizes the firmware, after a corrupted frame
+///
+/// Is passed the checksum that is in use. Returns the encoded frame, or `None`,
+/// if it can't be encoded. See [`Conn::set_resync_frame`].
+pub type ResyncFrame = fn(Checksum) -> Option<Vec<u8>>;
+
+/// Deserialize a decoded frame, for inspecting it
+///
+/// Intended for use in the closure passed to [`Conn::receive_matching`].
+pub use test_stand_decoder::frame::peek;
+
+
+/// How long `Conn::new` listens for the sync preamble
+///
+/// If the firmware is already running, it sent the preamble long ago, so we
+/// can't wait for it. This is just long enough to discard any data that has
+/// been buffered since the firmware started.
+const SYNC_WINDOW: Duration = Duration::from_millis(50);
+
+/// Default for [`Retry::quiet`]
+pub const DEFAULT_QUIET_MS: u64 = 20;
+
+/// How long flushing the link may take at most
+///
+/// A firmware that keeps sending, like when forwarding a USART stream, would
+/// otherwise keep the link from ever being quiet.
+const FLUSH_LIMIT: Duration = Duration::from_secs(1);
 
 
 /// A connection to a firmware application
 pub struct Conn {
-    port: Box<dyn SerialPort>,
+    /// The transport, shared with actions registered by
+    /// [`Conn::send_on_interrupt`]
+    transport:   Arc<Mutex<Box<dyn Transport>>>,
+    address:     String,
+    compression: Compression,
+    checksum:    Checksum,
+
+    /// The settings of the serial port, for reopening it
+    port: Port,
+
+    /// The size of the chunks that large messages are split into
+    ///
+    /// See [`Conn::set_chunk_size`].
+    chunk_size: usize,
+
+    /// The id of the next message that is split into chunks
+    chunk_id: u8,
+
+    /// Frames that have been received, but not returned yet
+    ///
+    /// See [`Conn::receive_matching`].
+    queue: VecDeque<Vec<u8>>,
+
+    /// Recognizes log records among the received frames
+    ///
+    /// See [`Conn::set_log_filter`].
+    log_filter: Option<log::Filter>,
+
+    /// Log records that have been received, but not taken yet
+    log: Vec<log::Record>,
+
+    /// Recognizes defmt frames among the received frames
+    ///
+    /// See [`Conn::set_defmt_filter`].
+    defmt_filter: Option<defmt::Filter>,
+
+    /// Decodes the defmt frames into log records
+    ///
+    /// See [`Conn::set_defmt_decoder`].
+    #[cfg(feature = "defmt")]
+    defmt_decoder: Option<defmt::Decoder>,
+
+    /// Recognizes reports of dropped data among the received frames
+    ///
+    /// See [`Conn::set_overrun_filter`].
+    overrun_filter: Option<OverrunFilter>,
+
+    /// Recognizes panic reports among the received frames
+    ///
+    /// See [`Conn::set_panic_filter`].
+    panic_filter: Option<PanicFilter>,
+
+    /// Recognizes heartbeats, and how long the firmware may stay silent
+    ///
+    /// See [`Conn::set_heartbeat_filter`].
+    heartbeat: Option<(HeartbeatFilter, Duration)>,
+
+    /// When the last frame has been received
+    last_alive: Instant,
+
+    /// Grants credits for messages that the firmware sends on its own
+    ///
+    /// See [`Conn::set_flow_control`].
+    flow: Option<FlowControl>,
+
+    /// How to recover from corrupted frames
+    ///
+    /// See [`Conn::set_retry`].
+    retry: Option<Retry>,
+
+    /// Encodes the frame that is sent while recovering from corrupted frames
+    ///
+    /// See [`Conn::set_resync_frame`].
+    resync_frame: Option<ResyncFrame>,
+
+    /// Records the frames that are sent and received
+    ///
+    /// See [`Conn::set_recorder`].
+    recorder: Option<Recorder>,
+
+    /// Decodes the messages carried by recorded frames
+    ///
+    /// See [`Conn::set_record_decoder`].
+    record_decoder: Option<record::Decoder>,
 }
 
 impl Conn {
     /// Open the connection
     ///
-    /// `path` is the path to the serial device file that connects to the
-    /// firmware.
-    pub fn new(path: &str) -> Result<Self, ConnInitError> {
-        // The baud rate configuration is hardcoded for now. We might want to
-        // load this from the configuration file later.
-        let port = serialport::new(path, 115200)
-            .open()
-            .map_err(|err| ConnInitError(err))?;
+    /// `address` is the path to the serial device file that connects to the
+    /// firmware. Alternatively, it can be `tcp://host:port`, if the firmware's
+    /// serial port is reachable over the network (for example through a
+    /// serial-to-ethernet adapter), `usb://vid:pid[/serial]`, if the firmware
+    /// talks to the host through its own USB peripheral, or
+    /// `replay://<recording>[#<address>]`, to replay a recording (see the
+    /// [`record`] module).
+    ///
+    /// Discards any data that the firmware sent before its sync preamble (like
+    /// startup messages), or that has been buffered before the connection was
+    /// opened. If the firmware is already running, the link sync sequence
+    /// makes it discard any partial frame, and send the preamble again (see
+    /// [`Conn::resync_link`]).
+    pub fn new(address: &str) -> Result<Self, ConnInitError> {
+        Self::with_port(address, Port::default())
+    }
 
-        // Use a clone of the serialport, so `Serial` can use the same port.
-        let port = port.try_clone()
+    /// Open the connection, with the settings of `port`
+    ///
+    /// Works like [`Conn::new`]. The settings only apply, if `address` refers
+    /// to a serial port, and are kept for [`Conn::reconnect`].
+    #[tracing::instrument(level = "debug")]
+    pub fn with_port(address: &str, port: Port)
+        -> Result<Self, ConnInitError>
+    {
+        let mut conn = Self {
+            transport:      Arc::new(Mutex::new(Self::open(address, &port)?)),
+            address:        address.to_owned(),
+            compression:    Compression::None,
+            checksum:       Checksum::None,
+            port,
+            chunk_size:     chunk::DEFAULT_SIZE,
+            chunk_id:       0,
+            queue:          VecDeque::new(),
+            log_filter:     None,
+            log:            Vec::new(),
+            defmt_filter:   None,
+            #[cfg(feature = "defmt")]
+            defmt_decoder:  None,
+            overrun_filter: None,
+            panic_filter:   None,
+            heartbeat:      None,
+            last_alive:     Instant::now(),
+            flow:           None,
+            retry:          None,
+            resync_frame:   None,
+            recorder:       None,
+            record_decoder: None,
+        };
+
+        // A recording has no room for the link sync sequence, as the recorder
+        // is only set after the connection has been opened.
+        if !address.starts_with(REPLAY_PREFIX) {
+            conn.transport().write_all(LINK_SYNC)
+                .map_err(|err| ConnInitError(err.into()))?;
+        }
+        conn.discard_until_sync(SYNC_WINDOW)
             .map_err(|err| ConnInitError(err))?;
 
-        Ok(
-            Self {
-                port,
+        Ok(conn)
+    }
+
+    /// Reopen the connection and wait for the firmware to be ready
+    ///
+    /// Use this after the firmware has been reset, for example after flashing
+    /// it. Discards everything received before the firmware's sync preamble.
+    /// Returns an error, if the preamble is not received within `timeout`.
+    #[tracing::instrument(
+        level = "debug",
+        skip(self),
+        fields(address = %self.address),
+    )]
+    pub fn reconnect(&mut self, timeout: Duration)
+        -> Result<(), ConnReconnectError>
+    {
+        *self.transport() = Self::open(&self.address, &self.port)
+            .map_err(|ConnInitError(err)| ConnReconnectError(err))?;
+
+        self.resync(timeout)
+            .map_err(|ConnResyncError(err)| ConnReconnectError(err))
+    }
+
+    /// Wait for the firmware to be ready, after it has reset itself
+    ///
+    /// Works like [`Conn::reconnect`], except that it keeps using the open
+    /// connection. Use this, if the firmware resets without the serial device
+    /// going away, for example after a fault.
+    #[tracing::instrument(
+        level = "debug",
+        skip(self),
+        fields(address = %self.address),
+    )]
+    pub fn resync(&mut self, timeout: Duration)
+        -> Result<(), ConnResyncError>
+    {
+        // The firmware has been reset, so it's no longer compressing, or using
+        // checksums.
+        self.compression = Compression::None;
+        self.checksum    = Checksum::None;
+
+        // Anything queued was sent before the reset, and is no longer
+        // relevant.
+        self.queue.clear();
+
+        let synced = self.discard_until_sync(timeout)
+            .map_err(|err| ConnResyncError(err))?;
+        if !synced {
+            return Err(
+                ConnResyncError(
+                    io::Error::from(io::ErrorKind::TimedOut).into()
+                )
+            );
+        }
+        self.last_alive = Instant::now();
+
+        // The firmware has forgotten its credits, too.
+        if let Some(flow) = &mut self.flow {
+            let frame = flow.initial_grant(self.checksum)
+                .map_err(|err| ConnResyncError(err))?;
+            self.write_frame(&frame)
+                .map_err(|err| ConnResyncError(err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Resynchronize the link with the running firmware
+    ///
+    /// Works like [`Conn::resync`], except that it doesn't need the firmware to
+    /// reset. Sends the link sync sequence, which makes the firmware discard
+    /// any partial frame it has received, forget the compression and checksum
+    /// it has negotiated, and send its sync preamble. Use this, if the link got
+    /// out of sync, for example because another program wrote to it.
+    #[tracing::instrument(
+        level = "debug",
+        skip(self),
+        fields(address = %self.address),
+    )]
+    pub fn resync_link(&mut self, timeout: Duration)
+        -> Result<(), ConnResyncError>
+    {
+        self.write_frame(LINK_SYNC)
+            .map_err(|err| ConnResyncError(err))?;
+        self.resync(timeout)
+    }
+
+    fn open(address: &str, port: &Port)
+        -> Result<Box<dyn Transport>, ConnInitError>
+    {
+        transport::open_with(address, port)
+            .map_err(|err| ConnInitError(err))
+    }
+
+    fn transport(&self) -> MutexGuard<'_, Box<dyn Transport>> {
+        // An action registered by `send_on_interrupt` panicked. The transport
+        // is still usable.
+        self.transport.lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Discard received data, until the sync preamble has been received
+    ///
+    /// Returns `true`, if the preamble has been received. Returns `false`, if
+    /// nothing has been received for `timeout`, or if `timeout` has passed
+    /// without receiving the preamble.
+    fn discard_until_sync(&mut self, timeout: Duration)
+        -> Result<bool, Error>
+    {
+        let start = Instant::now();
+
+        let mut matched = 0;
+
+        while matched < SYNC_PREAMBLE.len() {
+            let remaining = match timeout.checked_sub(start.elapsed()) {
+                Some(remaining) => remaining,
+                None            => return Ok(false),
+            };
+            self.transport().set_timeout(remaining)?;
+
+            let mut b = 0; // initialized to `0`, but could be any value
+            match self.transport().read_exact(slice::from_mut(&mut b)) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    return Ok(false);
+                }
+                Err(err) => {
+                    return Err(err.into());
+                }
+            }
+
+            matched = match_sync(matched, b);
+        }
+
+        self.record(Direction::Sync, SYNC_PREAMBLE)?;
+
+        Ok(true)
+    }
+
+    /// Set the compression scheme used for received messages
+    ///
+    /// This must only be called after the firmware has agreed to use the
+    /// compression scheme. Messages sent to the firmware are never compressed.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set the checksum used for frames in both directions
+    ///
+    /// This must only be called after the firmware has agreed to use the
+    /// checksum.
+    pub fn set_checksum(&mut self, checksum: Checksum) {
+        self.checksum = checksum;
+    }
+
+    /// Set the size of the chunks that large messages are split into
+    ///
+    /// Messages that serialize to more than `size` bytes are split into chunks
+    /// of that size by [`Conn::send`]. The default is
+    /// [`chunk::DEFAULT_SIZE`]. Must be small enough, for a chunk to fit into
+    /// the receive buffer of the firmware.
+    pub fn set_chunk_size(&mut self, size: usize) {
+        self.chunk_size = size.max(1);
+    }
+
+    /// Set the filter that recognizes log records
+    ///
+    /// Received frames that are recognized by `filter` are not returned by
+    /// any of the receive methods. Instead, their log records are emitted as
+    /// `tracing` events (see [`log::Record::emit`]), and stored until they are
+    /// taken using [`Conn::take_log`].
+    pub fn set_log_filter(&mut self, filter: log::Filter) {
+        self.log_filter = Some(filter);
+    }
+
+    /// Take all log records that have been received so far
+    pub fn take_log(&mut self) -> Vec<log::Record> {
+        mem::take(&mut self.log)
+    }
+
+    /// Set the filter that recognizes defmt frames
+    ///
+    /// Received frames that are recognized by `filter` are not returned by
+    /// any of the receive methods. Instead, the defmt frames they carry are
+    /// decoded by the decoder set using [`Conn::set_defmt_decoder`], and
+    /// handled like the log records recognized by [`Conn::set_log_filter`].
+    /// They are dropped, if no decoder has been set, or if host-lib has been
+    /// built without the `defmt` feature.
+    pub fn set_defmt_filter(&mut self, filter: defmt::Filter) {
+        self.defmt_filter = Some(filter);
+    }
+
+    /// Set the decoder for defmt frames
+    ///
+    /// See [`Conn::set_defmt_filter`].
+    #[cfg(feature = "defmt")]
+    pub fn set_defmt_decoder(&mut self, decoder: defmt::Decoder) {
+        self.defmt_decoder = Some(decoder);
+    }
+
+    /// Set the filter that recognizes reports of dropped data
+    ///
+    /// When the firmware can't keep up with the data it receives, it drops some
+    /// of it, and reports that. Received frames that are recognized by
+    /// `filter` are not returned by any of the receive methods. Instead, the
+    /// receive method fails with [`Error::Overrun`], so the test case fails
+    /// with that reason, instead of a confusing one later on.
+    pub fn set_overrun_filter(&mut self, filter: OverrunFilter) {
+        self.overrun_filter = Some(filter);
+    }
+
+    /// Set the filter that recognizes panic reports
+    ///
+    /// Firmware that panics can report the panic, before it stops or resets
+    /// (see `firmware_lib::panic`). Received frames that are recognized by
+    /// `filter` are not returned by any of the receive methods. Instead, the
+    /// receive method fails with [`Error::TargetPanicked`], so the test case
+    /// fails with the firmware's panic message, instead of a timeout.
+    pub fn set_panic_filter(&mut self, filter: PanicFilter) {
+        self.panic_filter = Some(filter);
+    }
+
+    /// Set the filter that recognizes heartbeats
+    ///
+    /// Some firmware sends heartbeats periodically, so the host can tell
+    /// whether it's still running. Received frames that are recognized by
+    /// `filter` are not returned by any of the receive methods.
+    ///
+    /// If nothing at all has been received for `timeout`, the receive methods
+    /// fail with [`Error::TargetUnresponsive`], instead of waiting for the rest
+    /// of their own timeout, or failing with a plain timeout. This tells
+    /// firmware that has crashed or hangs apart from firmware that just didn't
+    /// send the expected message. `timeout` should be a few times the interval
+    /// between heartbeats.
+    pub fn set_heartbeat_filter(&mut self,
+        filter:  HeartbeatFilter,
+        timeout: Duration,
+    ) {
+        self.heartbeat = Some((filter, timeout));
+    }
+
+    /// Enable flow control for messages that the firmware sends on its own
+    ///
+    /// Grants the firmware `window` credits right away. Received frames that
+    /// are recognized by `filter` each use up one of them, and credits are
+    /// granted again as they are received, so the firmware never gets more
+    /// than `window` of these messages ahead of the host. See the [`flow`]
+    /// module.
+    ///
+    /// `M` is the type of the messages sent to the firmware, which must be able
+    /// to carry a [`Grant`]. This must only be called after the checksum has
+    /// been set, and the firmware must support flow control.
+    pub fn set_flow_control<M>(&mut self, filter: flow::Filter, window: u16)
+        -> Result<(), ConnSendError>
+        where M: From<Grant> + Serialize
+    {
+        let mut flow = FlowControl::new::<M>(filter, window);
+
+        let frame = flow.initial_grant(self.checksum)
+            .map_err(|err| ConnSendError(err))?;
+        self.write_frame(&frame)
+            .map_err(|err| ConnSendError(err))?;
+
+        self.flow = Some(flow);
+        Ok(())
+    }
+
+    /// Record every frame that is sent or received from now on
+    ///
+    /// See the [`record`] module. Use [`Conn::set_record_decoder`] to include
+    /// the messages carried by the frames in the recording.
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Set the decoder for the messages carried by recorded frames
+    ///
+    /// Frames are recorded without their messages, if no decoder is set. Only
+    /// has an effect, if a recorder has been set using [`Conn::set_recorder`].
+    pub fn set_record_decoder(&mut self, decoder: record::Decoder) {
+        self.record_decoder = Some(decoder);
+    }
+
+    /// Retry operations that fail because of a corrupted frame
+    ///
+    /// A single corrupted frame would otherwise fail a whole test case. See
+    /// [`Conn::retry`] for what happens then. No operation is retried, unless
+    /// this is called. Test stands call this with the `[retry]` table of the
+    /// configuration file.
+    pub fn set_retry(&mut self, retry: Retry) {
+        self.retry = Some(retry);
+    }
+
+    /// Set the frame that is sent while recovering from a corrupted frame
+    ///
+    /// If the corrupted frame was sent to the firmware, the firmware might
+    /// still hold part of it, and would append the retried request to that.
+    /// The resync frame ends any such partial frame, so it should carry a
+    /// message that the firmware handles without side effects, like a ping.
+    /// Without a resync frame, the firmware has to discard a partial frame on
+    /// its own.
+    pub fn set_resync_frame(&mut self, frame: ResyncFrame) {
+        self.resync_frame = Some(frame);
+    }
+
+    /// Run an operation, and retry it, if it fails because of a corrupted frame
+    ///
+    /// `corrupted` is passed the error of `op`, and indicates whether that was
+    /// caused by a corrupted frame (see [`ConnReceiveError::is_corrupted`]). If
+    /// so, all data that is still arriving is discarded, until the link has
+    /// been quiet for a bit, then the resync frame is sent, if one has been set
+    /// (see [`Conn::set_resync_frame`]). Then `op` runs again. This happens up
+    /// to [`Retry::attempts`] times, before the error is returned.
+    ///
+    /// Without a retry policy (see [`Conn::set_retry`]), `op` only runs once.
+    /// Only use this for operations that can safely be repeated, as the
+    /// firmware might have processed a request, before its reply was
+    /// corrupted.
+    pub fn retry<T, E>(&mut self,
+        mut op:    impl FnMut(&mut Self) -> Result<T, E>,
+        corrupted: impl Fn(&E) -> bool,
+    )
+        -> Result<T, E>
+    {
+        let mut attempt = 0;
+
+        loop {
+            match op(self) {
+                Err(err) if corrupted(&err) && self.recover(attempt) => {
+                    attempt += 1;
+                }
+                result => {
+                    return result;
+                }
             }
-        )
+        }
+    }
+
+    /// Prepare to retry an operation, after a corrupted frame
+    ///
+    /// `attempt` is the number of retries so far. Returns `false`, if the
+    /// operation should not be retried, because there's no retry policy, it has
+    /// been exhausted, or the link couldn't be resynchronized.
+    pub(crate) fn recover(&mut self, attempt: usize) -> bool {
+        let quiet = match &self.retry {
+            Some(retry) if attempt < retry.attempts => retry.quiet(),
+            _                                       => return false,
+        };
+
+        tracing::warn!(
+            address = %self.address,
+            attempt = attempt + 1,
+            "Corrupted frame. Retrying.",
+        );
+
+        match self.flush_and_resync(quiet) {
+            Ok(()) => {
+                true
+            }
+            Err(err) => {
+                tracing::warn!(
+                    address = %self.address,
+                    ?err,
+                    "Failed to resynchronize",
+                );
+                false
+            }
+        }
+    }
+
+    fn flush_and_resync(&mut self, quiet: Duration) -> Result<(), Error> {
+        self.flush(quiet)?;
+
+        let frame = self.resync_frame.and_then(|encode| encode(self.checksum));
+        if let Some(frame) = frame {
+            self.write_frame(&frame)?;
+
+            // Whatever the firmware replies is of no interest.
+            self.flush(quiet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discard received data, until nothing has been received for `quiet`
+    ///
+    /// Frames that have already been queued are kept, as they have been
+    /// received intact.
+    fn flush(&mut self, quiet: Duration) -> Result<(), Error> {
+        let start = Instant::now();
+        let mut discarded = Vec::new();
+
+        self.transport().set_timeout(quiet)?;
+        while start.elapsed() < FLUSH_LIMIT {
+            let mut b = 0; // initialized to `0`, but could be any value
+            match self.transport().read_exact(slice::from_mut(&mut b)) {
+                Ok(()) => {
+                    discarded.push(b);
+                }
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    break;
+                }
+                Err(err) => {
+                    return Err(err.into());
+                }
+            }
+        }
+
+        // Recorded, so a replay of the recording discards the same data.
+        if !discarded.is_empty() {
+            tracing::trace!(address = %self.address, ?discarded, "flush");
+            self.record(Direction::Receive, &discarded)?;
+        }
+
+        Ok(())
     }
 
     /// Send a message
     ///
-    /// `message` can be any type that can be serialized using `serde`.
+    /// `message` can be any type that can be serialized using `serde`, and that
+    /// can carry chunks of larger messages. If it is too large for a single
+    /// frame, it is split into chunks, as described in the [`chunk`] module.
+    /// See [`Conn::set_chunk_size`].
     pub fn send<T>(&mut self, message: &T) -> Result<(), ConnSendError>
-        where T: Serialize
+        where T: Serialize + Chunked
     {
         self.send_inner(message)
             .map_err(|err| ConnSendError(err))
     }
 
     fn send_inner<T>(&mut self, message: &T) -> Result<(), Error>
+        where T: Serialize + Chunked
+    {
+        let frames = encode_frames(
+            message,
+            self.checksum,
+            self.chunk_size,
+            &mut self.chunk_id,
+        )?;
+
+        // Hold on to the transport, so the frames of a chunked message are not
+        // interleaved with a frame sent by `send_on_interrupt`.
+        let mut transport = self.transport();
+        for frame in frames {
+            tracing::trace!(address = %self.address, ?frame, "send");
+            transport.write_all(&frame)?;
+            self.record(Direction::Send, &frame)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send a message, if the test run is interrupted
+    ///
+    /// Intended for messages that stop whatever the firmware is doing, so an
+    /// interrupted test run doesn't leave it running. See the [`signal`]
+    /// module for details.
+    ///
+    /// The message is encoded right away, so this must be called after the
+    /// checksum has been negotiated. It is sent until the returned
+    /// [`Registration`] is dropped.
+    pub fn send_on_interrupt<T>(&self, message: &T)
+        -> Result<Registration, ConnSendError>
         where T: Serialize
     {
         let mut buf = [0; 256];
+        let len = encode_frame(message, self.checksum, &mut buf)
+            .map_err(|err| ConnSendError(err.into()))?;
+        let frame = buf[..len].to_vec();
+
+        let transport = self.transport.clone();
+        let registration = signal::on_interrupt(move || {
+            // Locking the transport waits for any frame that is being sent, so
+            // the frames don't get mixed up. The process is exiting, so there's
+            // nothing to do about errors.
+            let mut transport = transport.lock()
+                .unwrap_or_else(|err| err.into_inner());
+            let _ = transport.write_all(&frame);
+            let _ = transport.flush();
+        });
 
-        let serialized = postcard::to_slice_cobs(message, &mut buf)?;
-        self.port.write_all(serialized)?;
+        Ok(registration)
+    }
+
+    /// Send the beginning of a message, without completing it
+    ///
+    /// Sends at most `len` bytes of the encoded frame, but never the byte that
+    /// terminates it. This simulates a sender that stops in the middle of a
+    /// frame, to test how the receiver recovers.
+    pub fn send_partial<T>(&mut self, message: &T, len: usize)
+        -> Result<(), ConnSendError>
+        where T: Serialize
+    {
+        self.send_partial_inner(message, len)
+            .map_err(|err| ConnSendError(err))
+    }
+
+    fn send_partial_inner<T>(&mut self, message: &T, len: usize)
+        -> Result<(), Error>
+        where T: Serialize
+    {
+        let mut buf = [0; 256];
+
+        let frame_len = encode_frame(message, self.checksum, &mut buf)?;
+        let len       = len.min(frame_len - 1);
+        tracing::trace!(
+            address = %self.address,
+            frame   = ?&buf[..len],
+            "send partial",
+        );
+        self.transport().write_all(&buf[..len])?;
+        self.record(Direction::Send, &buf[..len])?;
 
         Ok(())
     }
@@ -88,12 +806,252 @@ impl Conn {
         -> Result<T, Error>
         where T: Deserialize<'de>
     {
-        self.port.set_timeout(timeout)?;
+        self.receive_frame_inner(timeout, buf)?;
+
+        let message = postcard::from_bytes(buf)?;
+        Ok(message)
+    }
+
+    /// Receive a message, leaving unrelated messages for later
+    ///
+    /// Works like [`Conn::receive`], except that only a message whose frame is
+    /// accepted by `matches` is returned. Other messages are queued, and
+    /// returned by later calls to this method, [`Conn::receive`], or
+    /// [`Conn::receive_frame`], in the order they were received. This allows
+    /// waiting for one kind of message, without treating unrelated messages
+    /// that arrive in the meantime as errors.
+    ///
+    /// `matches` is passed the decoded frame. Use [`peek`] to deserialize it.
+    pub fn receive_matching<'de, T>(&mut self,
+        timeout: Duration,
+        buf:     &'de mut Vec<u8>,
+        matches: impl FnMut(&[u8]) -> bool,
+    )
+        -> Result<T, ConnReceiveError>
+        where T: Deserialize<'de>
+    {
+        self.receive_matching_inner(timeout, buf, matches)
+            .map_err(|err| ConnReceiveError(err))
+    }
+
+    fn receive_matching_inner<'de, T>(&mut self,
+        timeout:     Duration,
+        buf:         &'de mut Vec<u8>,
+        mut matches: impl FnMut(&[u8]) -> bool,
+    )
+        -> Result<T, Error>
+        where T: Deserialize<'de>
+    {
+        let queued = self.queue.iter().position(|frame| matches(frame));
+
+        match queued.and_then(|i| self.queue.remove(i)) {
+            Some(frame) => {
+                *buf = frame;
+            }
+            None => {
+                let start = Instant::now();
+
+                loop {
+                    let remaining = timeout.checked_sub(start.elapsed())
+                        .ok_or_else(|| self.timed_out())?;
+                    self.read_and_decode_frame(remaining, buf)?;
+
+                    if matches(buf) {
+                        break;
+                    }
+                    self.queue.push_back(buf.clone());
+                }
+            }
+        }
+
+        let message = postcard::from_bytes(buf)?;
+        Ok(message)
+    }
+
+    /// Receive a frame, without deserializing it
+    ///
+    /// Works like [`Conn::receive`], except that it leaves the decoded (and, if
+    /// necessary, decompressed) frame in `buf`, instead of deserializing it.
+    /// This is useful for tools that only need to pass on the raw message.
+    pub fn receive_frame(&mut self, timeout: Duration, buf: &mut Vec<u8>)
+        -> Result<(), ConnReceiveError>
+    {
+        self.receive_frame_inner(timeout, buf)
+            .map_err(|err| ConnReceiveError(err))
+    }
+
+    fn receive_frame_inner(&mut self, timeout: Duration, buf: &mut Vec<u8>)
+        -> Result<(), Error>
+    {
+        if let Some(frame) = self.queue.pop_front() {
+            *buf = frame;
+            return Ok(());
+        }
+
+        self.read_and_decode_frame(timeout, buf)
+    }
+
+    fn read_and_decode_frame(&mut self, timeout: Duration, buf: &mut Vec<u8>)
+        -> Result<(), Error>
+    {
+        let start = Instant::now();
+
+        loop {
+            let remaining = timeout.checked_sub(start.elapsed())
+                .ok_or_else(|| self.timed_out())?;
+
+            // Don't wait longer than the firmware may stay silent. It has at
+            // least that long from the start, as the frames it sent before
+            // might not have been read yet.
+            let wait = match self.heartbeat {
+                Some((_, limit)) => {
+                    let silent_until = self.last_alive.max(start) + limit;
+                    remaining.min(
+                        silent_until.saturating_duration_since(Instant::now())
+                    )
+                }
+                None => {
+                    remaining
+                }
+            };
+            if wait.is_zero() {
+                return Err(self.timed_out());
+            }
+            self.transport().set_timeout(wait)?;
+
+            let mut frame = Vec::new();
+            match self.read_frame(&mut frame) {
+                Err(Error::Io(err))
+                    if err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Err(self.timed_out());
+                }
+                result => {
+                    result?;
+                }
+            }
+            self.last_alive = Instant::now();
+            tracing::trace!(address = %self.address, ?frame, "receive");
+
+            // The firmware answers the link sync sequence with its preamble,
+            // which might arrive after the one that has been waited for.
+            if is_sync(&frame) {
+                self.record(Direction::Sync, &frame)?;
+                continue;
+            }
+            self.record(Direction::Receive, &frame)?;
+
+            decode_frame(&mut frame, self.checksum, self.compression, buf)?;
+
+            let grant = match &mut self.flow {
+                Some(flow) => flow.count(buf, self.checksum)?,
+                None       => None,
+            };
+            if let Some(grant) = grant {
+                self.write_frame(&grant)?;
+            }
+
+            if let Some(dropped) = self.overrun_filter.and_then(|f| f(buf)) {
+                return Err(Error::Overrun(dropped));
+            }
+
+            if let Some(report) = self.panic_filter.and_then(|f| f(buf)) {
+                return Err(Error::TargetPanicked(report));
+            }
+
+            if self.heartbeat.is_some_and(|(filter, _)| filter(buf)) {
+                continue;
+            }
+
+            if let Some(frames) = self.defmt_filter.and_then(|f| f(buf)) {
+                self.decode_defmt(&frames);
+                continue;
+            }
+
+            match self.log_filter.and_then(|filter| filter(buf)) {
+                Some(record) => {
+                    record.emit(&self.address);
+                    self.log.push(record);
+                }
+                None => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Decode defmt frames, and handle them like log records
+    fn decode_defmt(&mut self, frames: &[u8]) {
+        #[cfg(feature = "defmt")]
+        if let Some(decoder) = &mut self.defmt_decoder {
+            for record in decoder.received(frames) {
+                record.emit(&self.address);
+                self.log.push(record);
+            }
+            return;
+        }
+
+        tracing::debug!(
+            address = %self.address,
+            ?frames,
+            "No decoder for defmt frames",
+        );
+    }
+
+    /// The error that a receive method returns, once it has timed out
+    ///
+    /// Reports the firmware as unresponsive, if it sends heartbeats, but
+    /// nothing has been received for too long.
+    fn timed_out(&self) -> Error {
+        match self.heartbeat {
+            Some((_, limit)) if self.last_alive.elapsed() >= limit => {
+                Error::TargetUnresponsive(self.last_alive.elapsed())
+            }
+            _ => {
+                io::Error::from(io::ErrorKind::TimedOut).into()
+            }
+        }
+    }
+
+    fn write_frame(&self, frame: &[u8]) -> Result<(), Error> {
+        tracing::trace!(address = %self.address, ?frame, "send");
+        self.transport().write_all(frame)?;
+        self.record(Direction::Send, frame)?;
+        Ok(())
+    }
+
+    /// Record a frame, if a recorder has been set
+    fn record(&self, direction: Direction, frame: &[u8]) -> Result<(), Error> {
+        let recorder = match &self.recorder {
+            Some(recorder) => recorder,
+            None           => return Ok(()),
+        };
+
+        let message = self.record_decoder.and_then(|decoder| {
+            // Messages sent to the firmware are never compressed.
+            let compression = match direction {
+                Direction::Receive => self.compression,
+                _                  => Compression::None,
+            };
+
+            // Partial or corrupted frames are recorded without a message.
+            let mut frame = frame.to_vec();
+            let mut buf   = Vec::new();
+            decode_frame(&mut frame, self.checksum, compression, &mut buf)
+                .ok()?;
+
+            decoder(direction, &buf)
+        });
+
+        recorder.record(&self.address, direction, frame, message)
+    }
+
+    fn read_frame(&mut self, buf: &mut Vec<u8>) -> Result<(), Error> {
         buf.clear();
 
         loop {
             let mut b = 0; // initialized to `0`, but could be any value
-            self.port.read_exact(slice::from_mut(&mut b))?;
+            self.transport().read_exact(slice::from_mut(&mut b))?;
 
             buf.push(b);
 
@@ -104,15 +1062,89 @@ impl Conn {
             }
         }
 
-        let message = postcard::from_bytes_cobs(buf)?;
-        Ok(message)
+        Ok(())
     }
 }
 
 
+/// Indicates whether a received frame is part of the sync preamble
+///
+/// The preamble starts with `0`, so it's read as an empty frame, followed by
+/// the rest of it.
+fn is_sync(frame: &[u8]) -> bool {
+    frame == [0] || frame == &SYNC_PREAMBLE[1..]
+}
+
+/// Encode a message into the frames that carry it
+///
+/// Returns a single frame, if the message serializes to no more than
+/// `chunk_size` bytes. Otherwise, splits it into chunks, and returns one frame
+/// per chunk. `chunk_id` is the id for the chunks, and is incremented in that
+/// case.
+fn encode_frames<T>(
+    message:    &T,
+    checksum:   Checksum,
+    chunk_size: usize,
+    chunk_id:   &mut u8,
+)
+    -> Result<Vec<Vec<u8>>, Error>
+    where T: Serialize + Chunked
+{
+    let data = postcard::to_stdvec(message)?;
+    if data.len() <= chunk_size {
+        return Ok(vec![encode(message, checksum)?]);
+    }
+
+    // The firmware couldn't reassemble a message that large.
+    if data.len() > chunk::MESSAGE_CAP {
+        return Err(postcard::Error::SerializeBufferFull.into());
+    }
+
+    let chunks = data.chunks(chunk_size);
+    let total  = u16::try_from(chunks.len())
+        .map_err(|_| postcard::Error::SerializeBufferFull)?;
+
+    let id = *chunk_id;
+    *chunk_id = chunk_id.wrapping_add(1);
+
+    chunks
+        .enumerate()
+        .map(|(index, data)| {
+            let chunk = Chunk {
+                id,
+                index: index as u16,
+                total,
+                data,
+            };
+            encode(&AsMessage::<T>::new(chunk), checksum)
+        })
+        .collect()
+}
+
+/// Encode a message into a single frame
+pub(crate) fn encode<T>(message: &T, checksum: Checksum)
+    -> Result<Vec<u8>, Error>
+    where T: Serialize
+{
+    let mut buf = [0; 256];
+    let len = encode_frame(message, checksum, &mut buf)?;
+    Ok(buf[..len].to_vec())
+}
+
+
 /// Error initializing connection
 #[derive(Debug)]
-pub struct ConnInitError(pub serialport::Error);
+pub struct ConnInitError(pub Error);
+
+
+/// Error reconnecting
+#[derive(Debug)]
+pub struct ConnReconnectError(pub Error);
+
+
+/// Error waiting for the firmware to be ready again
+#[derive(Debug)]
+pub struct ConnResyncError(pub Error);
 
 
 /// Error sending data through a connection
@@ -135,4 +1167,90 @@ impl ConnReceiveError {
             }
         }
     }
+
+    /// Indicates whether a corrupted frame has been received
+    ///
+    /// The message can be requested again, if this is the case.
+    pub fn is_checksum_mismatch(&self) -> bool {
+        match &self.0 {
+            Error::ChecksumMismatch => true,
+            _                       => false,
+        }
+    }
+
+    /// Indicates whether a frame has been corrupted in transit
+    ///
+    /// Includes checksum mismatches, and frames that couldn't be decoded or
+    /// deserialized. Operations that fail with such an error can be retried
+    /// (see [`Conn::retry`]).
+    pub fn is_corrupted(&self) -> bool {
+        match &self.0 {
+            Error::ChecksumMismatch => true,
+            Error::Cobs             => true,
+            Error::Postcard(_)      => true,
+            _                       => false,
+        }
+    }
+
+    /// Indicates whether the firmware has reported dropped data
+    ///
+    /// See [`Conn::set_overrun_filter`].
+    pub fn is_overrun(&self) -> bool {
+        match &self.0 {
+            Error::Overrun(_) => true,
+            _                 => false,
+        }
+    }
+
+    /// Indicates whether the firmware has reported a panic
+    ///
+    /// See [`Conn::set_panic_filter`].
+    pub fn is_panic(&self) -> bool {
+        match &self.0 {
+            Error::TargetPanicked(_) => true,
+            _                        => false,
+        }
+    }
+
+    /// Indicates whether the firmware has stopped responding
+    ///
+    /// See [`Conn::set_heartbeat_filter`].
+    pub fn is_unresponsive(&self) -> bool {
+        match &self.0 {
+            Error::TargetUnresponsive(_) => true,
+            _                            => false,
+        }
+    }
 }
+
+
+/// How often to retry operations that fail because of a corrupted frame
+///
+/// The `[retry]` table of the configuration file. See [`Conn::set_retry`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Retry {
+    /// How often an operation is retried, before its error is returned
+    pub attempts: usize,
+
+    /// How long the link must be quiet, before it's considered flushed, in ms
+    ///
+    /// Use [`Retry::quiet`] to get the configured value, or the default.
+    pub quiet_ms: Option<u64>,
+}
+
+impl Retry {
+    /// Retry operations up to `attempts` times
+    pub fn new(attempts: usize) -> Self {
+        Self {
+            attempts,
+            quiet_ms: None,
+        }
+    }
+
+    /// How long the link must be quiet, before it's considered flushed
+    pub fn quiet(&self) -> Duration {
+        Duration::from_millis(self.quiet_ms.unwrap_or(DEFAULT_QUIET_MS))
+    }
+}
+
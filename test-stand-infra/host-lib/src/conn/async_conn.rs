@@ -0,0 +1,642 @@
+//! Async variant of `Conn`, based on tokio
+
+
+use std::{
+    collections::VecDeque,
+    io,
+    mem,
+    time::Duration,
+};
+
+use protocol::{
+    LINK_SYNC,
+    SYNC_PREAMBLE,
+    checksum::Checksum,
+    chunk::{
+        self,
+        Chunked,
+    },
+    compress::Compression,
+    flow::Grant,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use test_stand_decoder::frame::{
+    decode_frame,
+    encode_frame,
+    match_sync,
+};
+use tokio::{
+    io::{
+        AsyncBufReadExt as _,
+        AsyncReadExt as _,
+        AsyncWriteExt as _,
+        BufReader,
+    },
+    time::{
+        self,
+        Instant,
+    },
+};
+
+use crate::{
+    Error,
+    defmt,
+    flow::{
+        self,
+        FlowControl,
+    },
+    log,
+    transport::{
+        self,
+        AsyncTransport,
+    },
+};
+
+use super::{
+    ConnInitError,
+    ConnReceiveError,
+    ConnReconnectError,
+    ConnResyncError,
+    ConnSendError,
+    HeartbeatFilter,
+    OverrunFilter,
+    PanicFilter,
+    SYNC_WINDOW,
+    is_sync,
+};
+
+
+/// An async connection to a firmware application
+///
+/// Works like [`Conn`], except that its methods are `async`, and need to be
+/// called from within a tokio runtime. Timeouts are implemented using
+/// `tokio::time`, so waiting for a message doesn't block a thread.
+///
+/// [`Conn`]: super::Conn
+pub struct AsyncConn {
+    transport:   BufReader<Box<dyn AsyncTransport>>,
+    address:     String,
+    compression: Compression,
+    checksum:    Checksum,
+    chunk_size:  usize,
+    chunk_id:    u8,
+
+    /// The frame that is currently being received
+    ///
+    /// A read that times out is cancelled, but whatever it has received so far
+    /// is kept here. The next read continues where it left off.
+    partial: Vec<u8>,
+
+    /// Frames that have been received, but not returned yet
+    ///
+    /// See [`AsyncConn::receive_matching`].
+    queue: VecDeque<Vec<u8>>,
+
+    /// Recognizes log records among the received frames
+    log_filter: Option<log::Filter>,
+
+    /// Log records that have been received, but not taken yet
+    log: Vec<log::Record>,
+
+    /// Recognizes defmt frames among the received frames
+    defmt_filter: Option<defmt::Filter>,
+
+    /// Decodes the defmt frames into log records
+    #[cfg(feature = "defmt")]
+    defmt_decoder: Option<defmt::Decoder>,
+
+    /// Recognizes reports of dropped data among the received frames
+    overrun_filter: Option<OverrunFilter>,
+
+    /// Recognizes panic reports among the received frames
+    panic_filter: Option<PanicFilter>,
+
+    /// Recognizes heartbeats, and how long the firmware may stay silent
+    heartbeat: Option<(HeartbeatFilter, Duration)>,
+
+    /// When the last frame has been received
+    last_alive: Instant,
+
+    /// Grants credits for messages that the firmware sends on its own
+    flow: Option<FlowControl>,
+}
+
+impl AsyncConn {
+    /// Open the connection
+    ///
+    /// See [`Conn::new`](super::Conn::new).
+    #[tracing::instrument(level = "debug")]
+    pub async fn new(address: &str) -> Result<Self, ConnInitError> {
+        let mut conn = Self {
+            transport:      Self::open(address).await?,
+            address:        address.to_owned(),
+            compression:    Compression::None,
+            checksum:       Checksum::None,
+            chunk_size:     chunk::DEFAULT_SIZE,
+            chunk_id:       0,
+            partial:        Vec::new(),
+            queue:          VecDeque::new(),
+            log_filter:     None,
+            log:            Vec::new(),
+            defmt_filter:   None,
+            #[cfg(feature = "defmt")]
+            defmt_decoder:  None,
+            overrun_filter: None,
+            panic_filter:   None,
+            heartbeat:      None,
+            last_alive:     Instant::now(),
+            flow:           None,
+        };
+
+        conn.write_frame(LINK_SYNC).await
+            .map_err(|err| ConnInitError(err))?;
+        conn.discard_until_sync(SYNC_WINDOW).await
+            .map_err(|err| ConnInitError(err.into()))?;
+
+        Ok(conn)
+    }
+
+    /// Reopen the connection and wait for the firmware to be ready
+    ///
+    /// See [`Conn::reconnect`](super::Conn::reconnect).
+    #[tracing::instrument(
+        level = "debug",
+        skip(self),
+        fields(address = %self.address),
+    )]
+    pub async fn reconnect(&mut self, timeout: Duration)
+        -> Result<(), ConnReconnectError>
+    {
+        self.transport = Self::open(&self.address).await
+            .map_err(|ConnInitError(err)| ConnReconnectError(err))?;
+
+        self.resync(timeout).await
+            .map_err(|ConnResyncError(err)| ConnReconnectError(err))
+    }
+
+    /// Wait for the firmware to be ready, after it has reset itself
+    ///
+    /// See [`Conn::resync`](super::Conn::resync).
+    #[tracing::instrument(
+        level = "debug",
+        skip(self),
+        fields(address = %self.address),
+    )]
+    pub async fn resync(&mut self, timeout: Duration)
+        -> Result<(), ConnResyncError>
+    {
+        // The firmware has been reset, so it's no longer compressing, or using
+        // checksums.
+        self.compression = Compression::None;
+        self.checksum    = Checksum::None;
+
+        // Anything queued was sent before the reset, and is no longer
+        // relevant.
+        self.queue.clear();
+
+        let synced = self.discard_until_sync(timeout).await
+            .map_err(|err| ConnResyncError(err.into()))?;
+        if !synced {
+            return Err(
+                ConnResyncError(
+                    io::Error::from(io::ErrorKind::TimedOut).into()
+                )
+            );
+        }
+        self.last_alive = Instant::now();
+
+        // The firmware has forgotten its credits, too.
+        if let Some(flow) = &mut self.flow {
+            let frame = flow.initial_grant(self.checksum)
+                .map_err(|err| ConnResyncError(err))?;
+            self.write_frame(&frame).await
+                .map_err(|err| ConnResyncError(err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Resynchronize the link with the running firmware
+    ///
+    /// See [`Conn::resync_link`](super::Conn::resync_link).
+    #[tracing::instrument(
+        level = "debug",
+        skip(self),
+        fields(address = %self.address),
+    )]
+    pub async fn resync_link(&mut self, timeout: Duration)
+        -> Result<(), ConnResyncError>
+    {
+        self.write_frame(LINK_SYNC).await
+            .map_err(|err| ConnResyncError(err))?;
+        self.resync(timeout).await
+    }
+
+    async fn open(address: &str)
+        -> Result<BufReader<Box<dyn AsyncTransport>>, ConnInitError>
+    {
+        let transport = transport::open_async(address).await
+            .map_err(|err| ConnInitError(err))?;
+
+        Ok(BufReader::new(transport))
+    }
+
+    /// Discard received data, until the sync preamble has been received
+    ///
+    /// Returns `true`, if the preamble has been received. Returns `false`, if
+    /// `timeout` has passed without receiving the preamble.
+    async fn discard_until_sync(&mut self, timeout: Duration)
+        -> io::Result<bool>
+    {
+        let deadline = Instant::now() + timeout;
+
+        // Whatever was received before the preamble is garbage.
+        self.partial.clear();
+
+        let mut matched = 0;
+
+        while matched < SYNC_PREAMBLE.len() {
+            let b = match time::timeout_at(deadline, self.transport.read_u8())
+                .await
+            {
+                Ok(result) => result?,
+                Err(_)     => return Ok(false),
+            };
+
+            matched = match_sync(matched, b);
+        }
+
+        Ok(true)
+    }
+
+    /// Set the compression scheme used for received messages
+    ///
+    /// See [`Conn::set_compression`](super::Conn::set_compression).
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set the checksum used for frames in both directions
+    ///
+    /// See [`Conn::set_checksum`](super::Conn::set_checksum).
+    pub fn set_checksum(&mut self, checksum: Checksum) {
+        self.checksum = checksum;
+    }
+
+    /// Set the size of the chunks that large messages are split into
+    ///
+    /// See [`Conn::set_chunk_size`](super::Conn::set_chunk_size).
+    pub fn set_chunk_size(&mut self, size: usize) {
+        self.chunk_size = size.max(1);
+    }
+
+    /// Set the filter that recognizes log records
+    ///
+    /// See [`Conn::set_log_filter`](super::Conn::set_log_filter).
+    pub fn set_log_filter(&mut self, filter: log::Filter) {
+        self.log_filter = Some(filter);
+    }
+
+    /// Take all log records that have been received so far
+    pub fn take_log(&mut self) -> Vec<log::Record> {
+        mem::take(&mut self.log)
+    }
+
+    /// Set the filter that recognizes defmt frames
+    ///
+    /// See [`Conn::set_defmt_filter`](super::Conn::set_defmt_filter).
+    pub fn set_defmt_filter(&mut self, filter: defmt::Filter) {
+        self.defmt_filter = Some(filter);
+    }
+
+    /// Set the decoder for defmt frames
+    ///
+    /// See [`Conn::set_defmt_decoder`](super::Conn::set_defmt_decoder).
+    #[cfg(feature = "defmt")]
+    pub fn set_defmt_decoder(&mut self, decoder: defmt::Decoder) {
+        self.defmt_decoder = Some(decoder);
+    }
+
+    /// Set the filter that recognizes reports of dropped data
+    ///
+    /// See [`Conn::set_overrun_filter`](super::Conn::set_overrun_filter).
+    pub fn set_overrun_filter(&mut self, filter: OverrunFilter) {
+        self.overrun_filter = Some(filter);
+    }
+
+    /// Set the filter that recognizes panic reports
+    ///
+    /// See [`Conn::set_panic_filter`](super::Conn::set_panic_filter).
+    pub fn set_panic_filter(&mut self, filter: PanicFilter) {
+        self.panic_filter = Some(filter);
+    }
+
+    /// Set the filter that recognizes heartbeats
+    ///
+    /// See [`Conn::set_heartbeat_filter`](super::Conn::set_heartbeat_filter).
+    pub fn set_heartbeat_filter(&mut self,
+        filter:  HeartbeatFilter,
+        timeout: Duration,
+    ) {
+        self.heartbeat = Some((filter, timeout));
+    }
+
+    /// Enable flow control for messages that the firmware sends on its own
+    ///
+    /// See [`Conn::set_flow_control`](super::Conn::set_flow_control).
+    pub async fn set_flow_control<M>(&mut self,
+        filter: flow::Filter,
+        window: u16,
+    )
+        -> Result<(), ConnSendError>
+        where M: From<Grant> + Serialize
+    {
+        let mut flow = FlowControl::new::<M>(filter, window);
+
+        let frame = flow.initial_grant(self.checksum)
+            .map_err(|err| ConnSendError(err))?;
+        self.write_frame(&frame).await
+            .map_err(|err| ConnSendError(err))?;
+
+        self.flow = Some(flow);
+        Ok(())
+    }
+
+    /// Send a message
+    ///
+    /// See [`Conn::send`](super::Conn::send).
+    pub async fn send<T>(&mut self, message: &T) -> Result<(), ConnSendError>
+        where T: Serialize + Chunked
+    {
+        self.send_inner(message).await
+            .map_err(|err| ConnSendError(err))
+    }
+
+    async fn send_inner<T>(&mut self, message: &T) -> Result<(), Error>
+        where T: Serialize + Chunked
+    {
+        let frames = super::encode_frames(
+            message,
+            self.checksum,
+            self.chunk_size,
+            &mut self.chunk_id,
+        )?;
+
+        let transport = self.transport.get_mut();
+        for frame in frames {
+            tracing::trace!(address = %self.address, ?frame, "send");
+            transport.write_all(&frame).await?;
+        }
+        transport.flush().await?;
+
+        Ok(())
+    }
+
+    /// Send the beginning of a message, without completing it
+    ///
+    /// See [`Conn::send_partial`](super::Conn::send_partial).
+    pub async fn send_partial<T>(&mut self, message: &T, len: usize)
+        -> Result<(), ConnSendError>
+        where T: Serialize
+    {
+        self.send_partial_inner(message, len).await
+            .map_err(|err| ConnSendError(err))
+    }
+
+    async fn send_partial_inner<T>(&mut self, message: &T, len: usize)
+        -> Result<(), Error>
+        where T: Serialize
+    {
+        let mut buf = [0; 256];
+
+        let frame_len = encode_frame(message, self.checksum, &mut buf)?;
+        let len       = len.min(frame_len - 1);
+        tracing::trace!(
+            address = %self.address,
+            frame   = ?&buf[..len],
+            "send partial",
+        );
+
+        let transport = self.transport.get_mut();
+        transport.write_all(&buf[..len]).await?;
+        transport.flush().await?;
+
+        Ok(())
+    }
+
+    /// Receive a message
+    ///
+    /// See [`Conn::receive`](super::Conn::receive).
+    pub async fn receive<'de, T>(&mut self,
+        timeout: Duration,
+        buf:     &'de mut Vec<u8>,
+    )
+        -> Result<T, ConnReceiveError>
+        where T: Deserialize<'de>
+    {
+        self.receive_frame(timeout, buf).await?;
+
+        postcard::from_bytes(buf)
+            .map_err(|err| ConnReceiveError(err.into()))
+    }
+
+    /// Receive a message, leaving unrelated messages for later
+    ///
+    /// See [`Conn::receive_matching`](super::Conn::receive_matching).
+    pub async fn receive_matching<'de, T>(&mut self,
+        timeout:     Duration,
+        buf:         &'de mut Vec<u8>,
+        mut matches: impl FnMut(&[u8]) -> bool,
+    )
+        -> Result<T, ConnReceiveError>
+        where T: Deserialize<'de>
+    {
+        let queued = self.queue.iter().position(|frame| matches(frame));
+
+        match queued.and_then(|i| self.queue.remove(i)) {
+            Some(frame) => {
+                *buf = frame;
+            }
+            None => {
+                let deadline = Instant::now() + timeout;
+
+                loop {
+                    self.read_and_decode_frame(deadline, buf).await
+                        .map_err(|err| ConnReceiveError(err))?;
+
+                    if matches(buf) {
+                        break;
+                    }
+                    self.queue.push_back(buf.clone());
+                }
+            }
+        }
+
+        postcard::from_bytes(buf)
+            .map_err(|err| ConnReceiveError(err.into()))
+    }
+
+    /// Receive a frame, without deserializing it
+    ///
+    /// See [`Conn::receive_frame`](super::Conn::receive_frame).
+    pub async fn receive_frame(&mut self,
+        timeout: Duration,
+        buf:     &mut Vec<u8>,
+    )
+        -> Result<(), ConnReceiveError>
+    {
+        if let Some(frame) = self.queue.pop_front() {
+            *buf = frame;
+            return Ok(());
+        }
+
+        self.read_and_decode_frame(Instant::now() + timeout, buf).await
+            .map_err(|err| ConnReceiveError(err))
+    }
+
+    async fn read_and_decode_frame(&mut self,
+        deadline: Instant,
+        buf:      &mut Vec<u8>,
+    )
+        -> Result<(), Error>
+    {
+        let start = Instant::now();
+
+        loop {
+            // Don't wait longer than the firmware may stay silent. See
+            // `Conn::read_and_decode_frame`.
+            let wait = match self.heartbeat {
+                Some((_, limit)) => {
+                    deadline.min(self.last_alive.max(start) + limit)
+                }
+                None => {
+                    deadline
+                }
+            };
+
+            match self.read_frame(wait).await {
+                Err(Error::Io(err))
+                    if err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Err(self.timed_out());
+                }
+                result => {
+                    result?;
+                }
+            }
+            self.last_alive = Instant::now();
+
+            let mut frame = mem::take(&mut self.partial);
+            tracing::trace!(address = %self.address, ?frame, "receive");
+
+            // See `Conn::read_and_decode_frame`.
+            if is_sync(&frame) {
+                continue;
+            }
+
+            decode_frame(&mut frame, self.checksum, self.compression, buf)?;
+
+            let grant = match &mut self.flow {
+                Some(flow) => flow.count(buf, self.checksum)?,
+                None       => None,
+            };
+            if let Some(grant) = grant {
+                self.write_frame(&grant).await?;
+            }
+
+            if let Some(dropped) = self.overrun_filter.and_then(|f| f(buf)) {
+                return Err(Error::Overrun(dropped));
+            }
+
+            if let Some(report) = self.panic_filter.and_then(|f| f(buf)) {
+                return Err(Error::TargetPanicked(report));
+            }
+
+            if self.heartbeat.is_some_and(|(filter, _)| filter(buf)) {
+                continue;
+            }
+
+            if let Some(frames) = self.defmt_filter.and_then(|f| f(buf)) {
+                self.decode_defmt(&frames);
+                continue;
+            }
+
+            match self.log_filter.and_then(|filter| filter(buf)) {
+                Some(record) => {
+                    record.emit(&self.address);
+                    self.log.push(record);
+                }
+                None => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Decode defmt frames, and handle them like log records
+    ///
+    /// See `Conn::decode_defmt`.
+    fn decode_defmt(&mut self, frames: &[u8]) {
+        #[cfg(feature = "defmt")]
+        if let Some(decoder) = &mut self.defmt_decoder {
+            for record in decoder.received(frames) {
+                record.emit(&self.address);
+                self.log.push(record);
+            }
+            return;
+        }
+
+        tracing::debug!(
+            address = %self.address,
+            ?frames,
+            "No decoder for defmt frames",
+        );
+    }
+
+    /// The error that a receive method returns, once it has timed out
+    ///
+    /// See `Conn::timed_out`.
+    fn timed_out(&self) -> Error {
+        match self.heartbeat {
+            Some((_, limit)) if self.last_alive.elapsed() >= limit => {
+                Error::TargetUnresponsive(self.last_alive.elapsed())
+            }
+            _ => {
+                io::Error::from(io::ErrorKind::TimedOut).into()
+            }
+        }
+    }
+
+    async fn write_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        tracing::trace!(address = %self.address, ?frame, "send");
+
+        let transport = self.transport.get_mut();
+        transport.write_all(frame).await?;
+        transport.flush().await?;
+
+        Ok(())
+    }
+
+    async fn read_frame(&mut self, deadline: Instant) -> Result<(), Error> {
+        // We're using COBS encoding, so `0` signifies the end of the message.
+        let read = self.transport.read_until(0, &mut self.partial);
+        match time::timeout_at(deadline, read).await {
+            Ok(result) => {
+                result?;
+            }
+            Err(_) => {
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+            }
+        }
+
+        // If the frame isn't terminated, the connection has been closed.
+        if self.partial.last() != Some(&0) {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! defmt log frames sent by the firmware
+//!
+//! Firmware that logs using defmt can send the encoded frames over its normal
+//! connection, in between other messages (see `firmware_lib::defmt`). Unlike
+//! defmt-rtt, this doesn't require a debug probe to be attached.
+//!
+//! The frames only refer to the format strings by index. The strings themselves
+//! are in the ELF file of the firmware, which is listed in the `[defmt]` table
+//! of `test-stand.toml`. A [`Decoder`] reads them from there, and turns the
+//! frames into log records, which are handled like any other log records (see
+//! [`crate::log`]).
+//!
+//! Decoding requires the `defmt` feature. Without it, only [`Filter`] is
+//! available, and recognized defmt frames are dropped.
+
+
+#[cfg(feature = "defmt")]
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Mutex,
+};
+
+#[cfg(feature = "defmt")]
+use defmt_decoder::{
+    DecodeError,
+    StreamDecoder,
+    Table,
+};
+#[cfg(feature = "defmt")]
+use lazy_static::lazy_static;
+#[cfg(feature = "defmt")]
+use protocol::log::Level;
+
+#[cfg(feature = "defmt")]
+use crate::log::Record;
+
+
+#[cfg(feature = "defmt")]
+lazy_static! {
+    /// The tables that have been read so far, by the path of the ELF file
+    ///
+    /// A stream decoder borrows its table, so tables are leaked to be kept
+    /// around. Caching them makes sure each one is only leaked, and read, once,
+    /// no matter how many test cases connect to the firmware.
+    static ref TABLES: Mutex<BTreeMap<PathBuf, &'static Table>> =
+        Mutex::new(BTreeMap::new());
+}
+
+
+/// Recognizes defmt frames among the received frames
+///
+/// Is passed each decoded frame. Returns `Some`, with the defmt frames it
+/// carries, if it is a defmt message. See [`Conn::set_defmt_filter`].
+///
+/// [`Conn::set_defmt_filter`]: crate::Conn::set_defmt_filter
+pub type Filter = fn(&[u8]) -> Option<Vec<u8>>;
+
+
+/// Decodes the defmt frames sent by a firmware
+#[cfg(feature = "defmt")]
+pub struct Decoder {
+    table:  &'static Table,
+    stream: Box<dyn StreamDecoder + Send + Sync>,
+}
+
+#[cfg(feature = "defmt")]
+impl Decoder {
+    /// Create a decoder for the firmware in the ELF file at `elf`
+    pub fn from_elf(elf: &Path) -> Result<Self, DefmtError> {
+        // Ignore poisoning. The map is only ever inserted into.
+        let mut tables = TABLES.lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let table = match tables.get(elf) {
+            Some(table) => *table,
+            None => {
+                let data = fs::read(elf)
+                    .map_err(|err| DefmtError::Read(err))?;
+                let table = Table::parse(&data)
+                    .map_err(|err| DefmtError::Parse(err.to_string()))?
+                    .ok_or(DefmtError::NoTable)?;
+
+                let table: &'static Table = Box::leak(Box::new(table));
+                tables.insert(elf.to_owned(), table);
+                table
+            }
+        };
+
+        Ok(
+            Self {
+                table,
+                stream: table.new_stream_decoder(),
+            }
+        )
+    }
+
+    /// Decode received data
+    ///
+    /// Returns the log records of all frames that have been completed by
+    /// `data`. Frames can be split across calls. Frames that can't be decoded
+    /// are reported as errors.
+    pub fn received(&mut self, data: &[u8]) -> Vec<Record> {
+        self.stream.received(data);
+
+        let mut records = Vec::new();
+        loop {
+            match self.stream.decode() {
+                Ok(frame) => {
+                    let level = match frame.level() {
+                        Some(defmt_parser::Level::Trace) => Level::Trace,
+                        Some(defmt_parser::Level::Debug) => Level::Debug,
+                        Some(defmt_parser::Level::Info)  => Level::Info,
+                        Some(defmt_parser::Level::Warn)  => Level::Warn,
+                        Some(defmt_parser::Level::Error) => Level::Error,
+
+                        // `println!` has no level.
+                        None => Level::Info,
+                    };
+
+                    records.push(Record {
+                        level,
+                        message: frame.display_message().to_string(),
+                    });
+                }
+                Err(DecodeError::UnexpectedEof) => {
+                    break;
+                }
+                Err(DecodeError::Malformed) => {
+                    records.push(Record {
+                        level:   Level::Error,
+                        message: String::from("Malformed defmt frame"),
+                    });
+
+                    // Without framing, the decoder can't find the start of the
+                    // next frame. Anything that follows would be garbage.
+                    if !self.table.encoding().can_recover() {
+                        self.stream = self.table.new_stream_decoder();
+                        break;
+                    }
+                }
+            }
+        }
+
+        records
+    }
+}
+
+
+#[cfg(feature = "defmt")]
+#[derive(Debug)]
+pub enum DefmtError {
+    /// Error reading the ELF file
+    Read(io::Error),
+
+    /// Error parsing the ELF file
+    Parse(String),
+
+    /// The firmware in the ELF file doesn't use defmt
+    NoTable,
+}
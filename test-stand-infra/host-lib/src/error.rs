@@ -1,7 +1,12 @@
 /// Defines the error type for this library
 
 
-use std::io;
+use std::{
+    io,
+    time::Duration,
+};
+
+use protocol::compress;
 
 
 /// The result type for this library
@@ -13,12 +18,36 @@ pub type Result<T = ()> = core::result::Result<T, Error>;
 /// The error type for this library
 #[derive(Debug)]
 pub enum Error {
+    /// A received frame's checksum didn't match
+    ///
+    /// The frame has been corrupted in transit. This is not a protocol error,
+    /// so it can make sense to request the message again.
+    ChecksumMismatch,
+
+    /// A received frame could not be COBS-decoded
+    Cobs,
+
+    /// Error occurred while decompressing a received frame
+    Compress(compress::Error),
+
     /// Error occurred while deserializing the configuration file
     Config(toml::de::Error),
 
+    /// A received frame had an unknown compression flag
+    FrameFlag(u8),
+
     /// An I/O error occurred
     Io(io::Error),
 
+    /// The firmware reported that it dropped received data
+    ///
+    /// One of its queues overflowed, because it couldn't keep up. Carries the
+    /// number of dropped items. See [`crate::Conn::set_overrun_filter`].
+    Overrun(u32),
+
+    /// Error occurred while deserializing JSON
+    Json(serde_json::Error),
+
     /// An error originated from Postcard
     ///
     /// The `postcard` crate is used for (de-)serialization.
@@ -26,6 +55,41 @@ pub enum Error {
 
     /// Error occurred while accessing the serial port
     Serial(serialport::Error),
+
+    /// The firmware has panicked
+    ///
+    /// Carries the panic message and location, as reported by the firmware.
+    /// See [`crate::Conn::set_panic_filter`].
+    TargetPanicked(String),
+
+    /// The firmware has stopped responding
+    ///
+    /// It sends heartbeats, but nothing at all has been received for the
+    /// carried duration. Most likely, it has crashed, or hangs. See
+    /// [`crate::Conn::set_heartbeat_filter`].
+    TargetUnresponsive(Duration),
+}
+
+impl From<test_stand_decoder::Error> for Error {
+    fn from(err: test_stand_decoder::Error) -> Self {
+        use test_stand_decoder::Error::*;
+
+        match err {
+            ChecksumMismatch => Self::ChecksumMismatch,
+            Cobs             => Self::Cobs,
+            Compress(err)    => Self::Compress(err),
+            FrameFlag(flag)  => Self::FrameFlag(flag),
+            Io(err)          => Self::Io(err),
+            Json(err)        => Self::Json(err),
+            Postcard(err)    => Self::Postcard(err),
+        }
+    }
+}
+
+impl From<compress::Error> for Error {
+    fn from(err: compress::Error) -> Self {
+        Self::Compress(err)
+    }
 }
 
 impl From<toml::de::Error> for Error {
@@ -40,6 +104,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
 impl From<postcard::Error> for Error {
     fn from(err: postcard::Error) -> Self {
         Self::Postcard(err)
@@ -0,0 +1,213 @@
+//! Downloading firmware to the test nodes
+//!
+//! Running the test suite against stale firmware leads to failures that are
+//! hard to make sense of. There are two ways to download firmware, before the
+//! first test case runs:
+//!
+//! - [`embed`] builds a firmware crate, and downloads it using `cargo embed`.
+//!   [`TestStandBuilder::flash_firmware`] names the crates.
+//! - [`download`] doesn't build anything. It uses probe-rs to write an ELF
+//!   file that has already been built, and to reset the test node afterwards.
+//!   [`TestStand`] calls it for the firmware listed in the `[flash]` table of
+//!   the configuration file. This requires the `flash` feature.
+//!
+//! Once the firmware has been downloaded through probe-rs, [`TestStand`] waits
+//! for it to announce itself on its connection, as after any other reset. Test
+//! suites check its version afterwards, which catches an ELF file that is out
+//! of date.
+//!
+//! [`TestStand`]: crate::TestStand
+//! [`TestStandBuilder::flash_firmware`]: crate::test_stand::TestStandBuilder::flash_firmware
+
+
+use std::{
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::{
+        Command,
+        ExitStatus,
+    },
+};
+
+#[cfg(feature = "flash")]
+use probe_rs::{
+    Permissions,
+    Session,
+    flashing::{
+        self,
+        ElfLoader,
+        ElfOptions,
+        FileDownloadError,
+    },
+    probe::{
+        DebugProbeError,
+        list::Lister,
+    },
+};
+
+#[cfg(feature = "flash")]
+use crate::config::Flash;
+
+
+/// The `cargo-embed` profile used to download firmware
+///
+/// The profile must be defined in the firmware's `Embed.toml`. It needs to
+/// disable RTT, as `cargo embed` would keep running otherwise.
+pub const EMBED_PROFILE: &str = "flash";
+
+
+/// Build firmware and download it to its test node
+///
+/// `path` is the directory of the firmware crate. Runs `cargo embed` there,
+/// which uses the probe configured in the crate's `Embed.toml`. Returns after
+/// the firmware has been downloaded, while it is starting up.
+pub fn embed(path: impl AsRef<Path>) -> Result<(), EmbedError> {
+    let path = path.as_ref();
+    tracing::info!(path = %path.display(), "building and flashing firmware");
+
+    let status = Command::new("cargo")
+        .arg("embed")
+        .arg(EMBED_PROFILE)
+        .current_dir(path)
+        .status()
+        .map_err(|err| EmbedError::Io(err))?;
+
+    if !status.success() {
+        return Err(
+            EmbedError::Failed {
+                path: path.to_owned(),
+                status,
+            }
+        );
+    }
+
+    Ok(())
+}
+
+/// Download firmware to a test node, then reset it
+///
+/// Returns after the reset, while the firmware is starting up.
+#[cfg(feature = "flash")]
+pub fn download(node: &str, flash: &Flash) -> Result<(), DownloadError> {
+    tracing::info!(
+        node,
+        elf   = %flash.elf.display(),
+        chip  = %flash.chip,
+        probe = ?flash.probe,
+        "flashing firmware",
+    );
+
+    let mut session = attach(flash)?;
+
+    let loader = ElfLoader(ElfOptions::default());
+    flashing::download_file(&mut session, &flash.elf, loader)
+        .map_err(|err| DownloadError::Download {
+            elf: flash.elf.clone(),
+            err,
+        })?;
+
+    reset_core(&mut session)
+}
+
+/// Reset a test node, without downloading anything
+///
+/// Uses the probe configured for the node in the `[flash]` table, so the node
+/// can be reset even if its firmware no longer responds. Returns after the
+/// reset, while the firmware is starting up.
+#[cfg(feature = "flash")]
+pub fn reset(node: &str, flash: &Flash) -> Result<(), DownloadError> {
+    tracing::info!(
+        node,
+        chip  = %flash.chip,
+        probe = ?flash.probe,
+        "resetting",
+    );
+
+    let mut session = attach(flash)?;
+    reset_core(&mut session)
+}
+
+
+/// Open the configured probe, and attach to the chip
+#[cfg(feature = "flash")]
+fn attach(flash: &Flash) -> Result<Session, DownloadError> {
+    let probes = Lister::new().list_all();
+    let probe = match &flash.probe {
+        Some(serial) => {
+            probes.iter()
+                .find(|probe| probe.serial_number.as_ref() == Some(serial))
+        }
+        None if probes.len() > 1 => {
+            return Err(
+                DownloadError::AmbiguousProbe(
+                    probes.iter()
+                        .filter_map(|probe| probe.serial_number.clone())
+                        .collect()
+                )
+            );
+        }
+        None => {
+            probes.first()
+        }
+    };
+    let probe = probe
+        .ok_or_else(|| DownloadError::NoProbe(flash.probe.clone()))?
+        .open()
+        .map_err(|err| DownloadError::Open(err))?;
+
+    probe.attach(&flash.chip, Permissions::default())
+        .map_err(|err| DownloadError::Attach(err))
+}
+
+#[cfg(feature = "flash")]
+fn reset_core(session: &mut Session) -> Result<(), DownloadError> {
+    session.core(0)
+        .and_then(|mut core| core.reset())
+        .map_err(|err| DownloadError::Reset(err))?;
+
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum EmbedError {
+    /// `cargo embed` could not be run
+    Io(io::Error),
+
+    /// `cargo embed` reported an error
+    Failed {
+        path:   PathBuf,
+        status: ExitStatus,
+    },
+}
+
+#[cfg(feature = "flash")]
+#[derive(Debug)]
+pub enum DownloadError {
+    /// No probe is connected, or none with the configured serial number
+    NoProbe(Option<String>),
+
+    /// No probe is configured, but more than one is connected
+    ///
+    /// Contains the serial numbers of the connected probes. Set `probe` to one
+    /// of them.
+    AmbiguousProbe(Vec<String>),
+
+    /// Error opening the probe
+    Open(DebugProbeError),
+
+    /// Error connecting to the chip through the probe
+    Attach(probe_rs::Error),
+
+    /// Error writing the ELF file to the flash memory
+    Download {
+        elf: PathBuf,
+        err: FileDownloadError,
+    },
+
+    /// Error resetting the chip
+    Reset(probe_rs::Error),
+}
@@ -0,0 +1,102 @@
+//! Flow control for messages that the firmware sends on its own
+//!
+//! Some firmware sends messages without being asked, like the test assistant
+//! forwarding data it receives from the target. [`Conn`] can keep such
+//! firmware from overwhelming the host, by granting it credits for these
+//! messages, as described in `protocol::flow`. Since only the test suite knows
+//! the firmware's protocol, it tells [`Conn`] which messages are credited, by
+//! setting a [`Filter`].
+//!
+//! [`Conn`]: crate::Conn
+
+
+use protocol::{
+    checksum::Checksum,
+    flow::Grant,
+};
+use serde::Serialize;
+
+use crate::{
+    Error,
+    conn,
+};
+
+
+/// Recognizes credited messages among the received frames
+///
+/// Is passed each decoded frame. Returns `true`, if the frame used up one of
+/// the credits. See [`Conn::set_flow_control`].
+///
+/// [`Conn::set_flow_control`]: crate::Conn::set_flow_control
+pub type Filter = fn(&[u8]) -> bool;
+
+
+/// The flow control state of a connection
+pub(crate) struct FlowControl {
+    filter: Filter,
+    window: u16,
+
+    /// The credited frames received since credits were last granted
+    received: u16,
+
+    /// Encodes a grant as a frame of the firmware's protocol
+    encode: fn(Grant, Checksum) -> Result<Vec<u8>, Error>,
+}
+
+impl FlowControl {
+    /// Create a new instance of `FlowControl`
+    ///
+    /// `M` is the type of the messages that the host sends to the firmware.
+    pub(crate) fn new<M>(filter: Filter, window: u16) -> Self
+        where M: From<Grant> + Serialize
+    {
+        Self {
+            filter,
+            window: window.max(1),
+            received: 0,
+            encode: encode_grant::<M>,
+        }
+    }
+
+    /// Encode the grant that starts flow control
+    ///
+    /// Grants the full window. Must be sent whenever the firmware has started,
+    /// as it doesn't remember the credits across resets.
+    pub(crate) fn initial_grant(&mut self, checksum: Checksum)
+        -> Result<Vec<u8>, Error>
+    {
+        self.received = 0;
+        (self.encode)(Grant { credits: self.window }, checksum)
+    }
+
+    /// Count a received frame
+    ///
+    /// Returns a grant that needs to be sent, once half of the window has been
+    /// used up. This way, the firmware can keep sending, while the grant is in
+    /// transit.
+    pub(crate) fn count(&mut self, frame: &[u8], checksum: Checksum)
+        -> Result<Option<Vec<u8>>, Error>
+    {
+        if !(self.filter)(frame) {
+            return Ok(None);
+        }
+
+        self.received += 1;
+        if self.received < (self.window / 2).max(1) {
+            return Ok(None);
+        }
+
+        let grant = Grant { credits: self.received };
+        self.received = 0;
+
+        (self.encode)(grant, checksum)
+            .map(Some)
+    }
+}
+
+
+fn encode_grant<M>(grant: Grant, checksum: Checksum) -> Result<Vec<u8>, Error>
+    where M: From<Grant> + Serialize
+{
+    conn::encode(&M::from(grant), checksum)
+}
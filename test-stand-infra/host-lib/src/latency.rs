@@ -0,0 +1,291 @@
+//! Measuring how long notifications take to reach the host
+//!
+//! Test nodes notify the host of events, like pin interrupts, and timestamp
+//! them when they happen. Timing assertions made on the host are only as good
+//! as the delivery of those notifications, so before trusting them, it's worth
+//! knowing how long delivery takes, and how much that varies.
+//!
+//! A [`Clock`] relates the timestamps of a test node to the host's clock, by
+//! asking the test node for its time. A [`DeadlineMonitor`] uses it to compute
+//! the delivery latency of each notification, collects the latencies of a run
+//! in a [`Histogram`], and counts the notifications that missed a deadline.
+//! Missing a deadline is no error in itself. The deadline is soft, and it's up
+//! to the test case to decide how many misses it accepts.
+//!
+//! Like in [`crate::timestamp`], timestamps are taken by a free-running 32-bit
+//! timer that counts microseconds.
+
+
+use std::{
+    fmt,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+
+/// The number of buckets shown by the `Display` implementation of [`Histogram`]
+const DISPLAY_BUCKETS: u32 = 10;
+
+
+/// Relates the timestamps of a test node to the host's clock
+///
+/// Created from a sample of the test node's timer: the host asks for the
+/// timer's value, and notes when it sent the request, and when it received the
+/// reply. The timer is assumed to have been read halfway in between, so the
+/// result can be off by up to half of the round trip.
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    timestamp:  u32,
+    instant:    Instant,
+    round_trip: Duration,
+}
+
+impl Clock {
+    /// Create a clock from a sample of the test node's timer
+    ///
+    /// `sent` is when the request was sent, `timestamp` is the value of the
+    /// timer in the reply, and `received` is when the reply was received.
+    pub fn new(sent: Instant, timestamp: u32, received: Instant) -> Self {
+        let round_trip = received.saturating_duration_since(sent);
+
+        Self {
+            timestamp,
+            instant: sent + round_trip / 2,
+            round_trip,
+        }
+    }
+
+    /// Take another sample into account
+    ///
+    /// Keeps the sample with the shorter round trip, as it's more accurate.
+    /// Taking a few samples filters out the ones that were delayed on the way.
+    pub fn update(&mut self, sent: Instant, timestamp: u32, received: Instant) {
+        let sample = Self::new(sent, timestamp, received);
+        if sample.round_trip < self.round_trip {
+            *self = sample;
+        }
+    }
+
+    /// How far off the host's time returned by [`Clock::to_host`] can be
+    pub fn uncertainty(&self) -> Duration {
+        self.round_trip / 2
+    }
+
+    /// Convert a timestamp of the test node into the host's time
+    ///
+    /// `timestamp` needs to be within half a wrap-around of the timer (about 35
+    /// minutes) of the sample. Drift between the clocks isn't taken into
+    /// account, so update the clock regularly during long runs.
+    pub fn to_host(&self, timestamp: u32) -> Instant {
+        let offset = timestamp.wrapping_sub(self.timestamp) as i32;
+        let delta  = Duration::from_micros(offset.unsigned_abs().into());
+
+        if offset >= 0 {
+            self.instant + delta
+        }
+        else {
+            self.instant - delta
+        }
+    }
+
+    /// Compute the delivery latency of a notification
+    ///
+    /// `timestamp` is when the test node generated the notification, and
+    /// `received` is when the host received it. Returns zero, if the
+    /// notification seems to have been received before it was generated, which
+    /// can happen within the uncertainty of the clock.
+    pub fn latency(&self, timestamp: u32, received: Instant) -> Duration {
+        received.saturating_duration_since(self.to_host(timestamp))
+    }
+}
+
+
+/// The latencies measured during a run
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    latencies: Vec<Duration>,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a latency
+    pub fn record(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    /// The number of latencies that have been added
+    pub fn len(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latencies.is_empty()
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.latencies.iter().copied().min()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.latencies.iter().copied().max()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+
+        let sum: Duration = self.latencies.iter().sum();
+        Some(sum / self.latencies.len() as u32)
+    }
+
+    /// The latency that `fraction` of all latencies don't exceed
+    ///
+    /// `fraction` is between 0.0 and 1.0. For example, pass 0.99 for the 99th
+    /// percentile.
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+
+        let rank = (fraction * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+    }
+
+    /// Count the latencies in buckets of `width`
+    ///
+    /// Returns the start of each bucket, and the number of latencies in it. The
+    /// first bucket starts at zero, the last one contains the maximum.
+    pub fn buckets(&self, width: Duration) -> Vec<(Duration, usize)> {
+        let width = width.max(Duration::from_micros(1));
+        let index = |latency: Duration| {
+            (latency.as_nanos() / width.as_nanos()) as usize
+        };
+
+        let len = self.max().map(|max| index(max) + 1).unwrap_or(0);
+        let mut buckets: Vec<_> = (0 .. len)
+            .map(|i| (width * i as u32, 0))
+            .collect();
+
+        for &latency in &self.latencies {
+            buckets[index(latency)].1 += 1;
+        }
+
+        buckets
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (min, max, mean, p99) = match (
+            self.min(),
+            self.max(),
+            self.mean(),
+            self.percentile(0.99),
+        ) {
+            (Some(min), Some(max), Some(mean), Some(p99)) => {
+                (min, max, mean, p99)
+            }
+            _ => {
+                return write!(f, "no latencies measured");
+            }
+        };
+
+        writeln!(
+            f,
+            "{} latencies: min {:?}, mean {:?}, 99% {:?}, max {:?}",
+            self.len(), min, mean, p99, max,
+        )?;
+
+        // Round to whole microseconds, to keep the bucket boundaries readable.
+        let width = Duration::from_micros(
+            (max / DISPLAY_BUCKETS).as_micros() as u64 + 1
+        );
+        let buckets = self.buckets(width);
+        let largest = buckets.iter().map(|&(_, n)| n).max().unwrap_or(1);
+
+        for (start, n) in buckets {
+            let bar = "#".repeat((n * 40).div_ceil(largest));
+            let row = format!(
+                "{:>12?} .. {:>12?} | {:>6} {}",
+                start, start + width, n, bar,
+            );
+            writeln!(f, "{}", row.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Measures the delivery latency of notifications against a soft deadline
+#[derive(Clone, Debug)]
+pub struct DeadlineMonitor {
+    clock:     Clock,
+    deadline:  Duration,
+    histogram: Histogram,
+    missed:    usize,
+}
+
+impl DeadlineMonitor {
+    pub fn new(clock: Clock, deadline: Duration) -> Self {
+        Self {
+            clock,
+            deadline,
+            histogram: Histogram::new(),
+            missed:    0,
+        }
+    }
+
+    /// Access the clock, to update it during long runs
+    pub fn clock(&mut self) -> &mut Clock {
+        &mut self.clock
+    }
+
+    /// Record the delivery of a notification
+    ///
+    /// `timestamp` is when the test node generated the notification, and
+    /// `received` is when the host received it. Returns the latency.
+    pub fn record(&mut self, timestamp: u32, received: Instant) -> Duration {
+        let latency = self.clock.latency(timestamp, received);
+
+        self.histogram.record(latency);
+        if latency > self.deadline {
+            self.missed += 1;
+        }
+
+        latency
+    }
+
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    /// The number of notifications that were delivered after the deadline
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+}
+
+impl fmt::Display for DeadlineMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} of {} notifications missed the deadline of {:?} (clock \
+            uncertainty {:?})",
+            self.missed,
+            self.histogram.len(),
+            self.deadline,
+            self.clock.uncertainty(),
+        )?;
+        write!(f, "{}", self.histogram)
+    }
+}
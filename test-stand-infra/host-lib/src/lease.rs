@@ -0,0 +1,278 @@
+//! Connections that a supervisor lends to the test cases it runs
+//!
+//! Under a supervisor (see [`crate::supervisor`]), every test case runs in a
+//! process of its own. Without leases, each of them opens the devices of the
+//! test stand again, and releases them when it exits, or when it's killed. A
+//! USB serial device that goes away and comes back in between can end up at a
+//! different device file, and a test case that is killed halfway through a
+//! write leaves the device in whatever state the operating system left it in.
+//!
+//! Instead, the supervisor calls [`serve`], which opens the connection to each
+//! test node once, and keeps it open, and locked, for the whole run. Each one
+//! is served on a TCP port on the loopback interface. [`crate::TestStand`]
+//! connects to that port, instead of to the device, if one has been leased
+//! (see [`get`]). The supervisor passes the ports on to its child processes
+//! through [`LEASES_VAR`].
+//!
+//! A connection is lent to one client at a time. Once the client disconnects,
+//! because its test case finished or was killed, the next one can connect.
+//! Anything the test node sent in between is discarded by the next client,
+//! when it synchronizes with the firmware, as it would after opening the device
+//! itself.
+//!
+//! The USB/serial converter (`serial` in the configuration file) isn't leased,
+//! as test cases reconfigure its serial port. Test cases open it themselves.
+
+
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{
+        self,
+        prelude::*,
+    },
+    net::{
+        Ipv4Addr,
+        TcpListener,
+        TcpStream,
+    },
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+
+use crate::{
+    Error,
+    config::{
+        Config,
+        Port,
+    },
+    lock::{
+        self,
+        DeviceLock,
+    },
+    transport::{
+        self,
+        TCP_PREFIX,
+        Transport,
+    },
+};
+
+
+/// Set in the environment of the child processes, to the leased connections
+///
+/// Holds a JSON object, that maps the address of each test node to the address
+/// of the TCP port that its connection is served on.
+pub const LEASES_VAR: &str = "TEST_STAND_LEASES";
+
+/// How long the lender waits for data from either side, before checking the
+/// other one
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+
+lazy_static! {
+    /// The connections served by this process, by the address of the device
+    static ref LEASES: Mutex<BTreeMap<String, String>> =
+        Mutex::new(BTreeMap::new());
+}
+
+
+/// Serve the connections to all test nodes in `config`
+///
+/// Acquires the locks on the devices, opens them, and serves each one on a TCP
+/// port of its own, in a thread of its own. The devices stay open, and locked,
+/// until the process exits. Devices that are already served are skipped.
+pub fn serve(config: &Config) -> Result<(), LeaseError> {
+    // Ignore poisoning. The map is only ever inserted into.
+    let mut leases = LEASES.lock()
+        .unwrap_or_else(|err| err.into_inner());
+
+    // This process already holds the locks on the served devices. Acquiring
+    // them again would block forever.
+    let addresses = config.target.iter()
+        .chain(&config.assistant)
+        .chain(config.targets.values())
+        .chain(config.assistants.values())
+        .filter(|address| !leases.contains_key(*address))
+        .map(|address| address.as_str());
+
+    let locks = lock::acquire_all(addresses)
+        .map_err(|err| LeaseError::Lock(err))?;
+    for lock in locks {
+        let address = lock.address().to_owned();
+
+        let port   = config.port(&address);
+        let device = transport::open_with(&address, &port)
+            .map_err(|err| LeaseError::Open {
+                address: address.clone(),
+                err,
+            })?;
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .map_err(|err| LeaseError::Bind(err))?;
+        let local = listener.local_addr()
+            .map_err(|err| LeaseError::Bind(err))?;
+
+        tracing::debug!(%address, %local, "serving connection");
+        leases.insert(address, format!("{}{}", TCP_PREFIX, local));
+
+        thread::spawn(move || lend(listener, lock, port, device));
+    }
+
+    Ok(())
+}
+
+/// The address to connect to instead of the device at `address`
+///
+/// Returns `Some`, if the connection to the device has been leased, either by
+/// this process, or by the supervisor that started it.
+pub fn get(address: &str) -> Option<String> {
+    let leases = LEASES.lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if let Some(lease) = leases.get(address) {
+        return Some(lease.clone());
+    }
+
+    let inherited = env::var(LEASES_VAR).ok()?;
+    let mut inherited: BTreeMap<String, String> =
+        serde_json::from_str(&inherited).ok()?;
+    inherited.remove(address)
+}
+
+/// The value of [`LEASES_VAR`] for child processes
+///
+/// Returns `None`, if this process doesn't serve any connections.
+pub fn env_value() -> Option<String> {
+    let leases = LEASES.lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if leases.is_empty() {
+        return None;
+    }
+
+    serde_json::to_string(&*leases).ok()
+}
+
+
+/// Lend the connection to a device to one client after the other
+///
+/// If the device fails, it is reopened for the next client.
+fn lend(
+    listener: TcpListener,
+    lock:     DeviceLock,
+    port:     Port,
+    device:   Box<dyn Transport>,
+) {
+    let address    = lock.address();
+    let mut device = Some(device);
+
+    for client in listener.incoming() {
+        let client = match client {
+            Ok(client) => client,
+            Err(err) => {
+                tracing::warn!(address, ?err, "error accepting client");
+                continue;
+            }
+        };
+
+        if device.is_none() {
+            match transport::open_with(address, &port) {
+                Ok(reopened) => {
+                    device = Some(reopened);
+                }
+                Err(err) => {
+                    // Dropping the client closes its connection, which it
+                    // reports like a failing device.
+                    tracing::warn!(address, ?err, "error reopening device");
+                    continue;
+                }
+            }
+        }
+
+        // Only `Some` if the device has been (re)opened above.
+        if let Some(transport) = &mut device {
+            match pump(transport.as_mut(), client) {
+                Ok(()) => {}
+                Err(Pump::Client(err)) => {
+                    tracing::debug!(address, ?err, "client disconnected");
+                }
+                Err(Pump::Device(err)) => {
+                    tracing::warn!(address, ?err, "device failed");
+                    device = None;
+                }
+            }
+        }
+    }
+}
+
+/// Pass data between a device and a client, until the client disconnects
+fn pump(device: &mut dyn Transport, mut client: TcpStream)
+    -> Result<(), Pump>
+{
+    client.set_nodelay(true)
+        .map_err(|err| Pump::Client(err))?;
+    client.set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(|err| Pump::Client(err))?;
+    device.set_timeout(POLL_INTERVAL)
+        .map_err(|err| Pump::Device(err))?;
+
+    let mut buf = [0; 1024];
+    loop {
+        match client.read(&mut buf) {
+            Ok(0) => {
+                return Ok(());
+            }
+            Ok(n) => {
+                device.write_all(&buf[..n])
+                    .map_err(|err| Pump::Device(err))?;
+            }
+            Err(err) if is_timeout(&err) => {}
+            Err(err) => {
+                return Err(Pump::Client(err));
+            }
+        }
+
+        match device.read(&mut buf) {
+            Ok(n) => {
+                client.write_all(&buf[..n])
+                    .map_err(|err| Pump::Client(err))?;
+            }
+            Err(err) if is_timeout(&err) => {}
+            Err(err) => {
+                return Err(Pump::Device(err));
+            }
+        }
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    // Depending on the platform, a socket reports a timeout as `WouldBlock`.
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+
+/// Which side of a lent connection failed
+enum Pump {
+    Client(io::Error),
+    Device(io::Error),
+}
+
+
+#[derive(Debug)]
+pub enum LeaseError {
+    /// Error acquiring the lock on a device
+    Lock(io::Error),
+
+    /// Error opening a device
+    Open {
+        address: String,
+        err:     Error,
+    },
+
+    /// Error listening for clients
+    Bind(io::Error),
+}
@@ -4,18 +4,45 @@
 pub mod assistant;
 pub mod config;
 pub mod conn;
+pub mod defmt;
 pub mod error;
+pub mod flash;
+pub mod flow;
+pub mod latency;
+pub mod lease;
+pub mod lock;
+pub mod log;
+pub mod measurement;
+pub mod payload;
 pub mod pin;
+pub mod prelude;
+pub mod record;
+pub mod report;
+pub mod serial;
+pub mod signal;
+pub mod supervisor;
+pub mod target;
 pub mod test_stand;
+pub mod timestamp;
+pub mod tolerances;
+pub mod topology;
+pub mod trace;
+pub mod transport;
+pub mod voltage;
 
 
+pub use test_stand_decoder::schema;
+
 pub use self::{
     assistant::Assistant,
     config::Config,
     conn::Conn,
+    serial::Serial,
     error::{
         Error,
         Result,
     },
     test_stand::TestStand,
+    tolerances::Tolerances,
+    topology::Topology,
 };
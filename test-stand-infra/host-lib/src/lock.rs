@@ -0,0 +1,111 @@
+//! Locks on the devices of the test stand, shared between processes
+//!
+//! [`TestStand`] makes sure that test cases within the same process don't run
+//! in parallel. That doesn't help against other processes, like a second test
+//! suite running at the same time, or a test runner that runs each test case
+//! in its own process. Those would open the same devices, and fight over them.
+//!
+//! A [`DeviceLock`] is a file-system lock on a lock file in the temporary
+//! directory, named after the address of the device. [`TestStand`] acquires
+//! one for every test node in the configuration file, before connecting to
+//! them. Devices are identified by their address, so two addresses that refer
+//! to the same device (like a path and a `usb://` address) don't protect it
+//! from each other.
+//!
+//! [`TestStand`]: crate::TestStand
+
+
+use std::{
+    env,
+    fs::{
+        File,
+        OpenOptions,
+        TryLockError,
+    },
+    io,
+    path::PathBuf,
+};
+
+use crate::transport::REPLAY_PREFIX;
+
+
+/// A lock on a device, which no other process can hold at the same time
+///
+/// The lock is released, when this struct is dropped.
+#[derive(Debug)]
+pub struct DeviceLock {
+    address: String,
+    _file:   File,
+}
+
+impl DeviceLock {
+    /// Acquire the lock on the device at `address`
+    ///
+    /// Blocks, until no other process holds the lock.
+    pub fn acquire(address: &str) -> io::Result<Self> {
+        let path = path(address);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                // This can take as long as another test suite runs, which
+                // would look like a hang without this.
+                tracing::info!(
+                    address,
+                    path = %path.display(),
+                    "waiting for device lock",
+                );
+                file.lock()?;
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err);
+            }
+        }
+
+        Ok(
+            Self {
+                address: address.to_owned(),
+                _file:   file,
+            }
+        )
+    }
+
+    /// The address of the locked device
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+
+/// Acquire the locks on all devices in `addresses`
+///
+/// Addresses that don't refer to a device, like replays of recordings, are
+/// skipped, as are duplicates. The locks are always acquired in the same
+/// order, so two processes that need some of the same devices can't deadlock.
+pub fn acquire_all<'a>(addresses: impl IntoIterator<Item = &'a str>)
+    -> io::Result<Vec<DeviceLock>>
+{
+    let mut addresses: Vec<_> = addresses.into_iter()
+        .filter(|address| !address.starts_with(REPLAY_PREFIX))
+        .collect();
+    addresses.sort_unstable();
+    addresses.dedup();
+
+    addresses.into_iter()
+        .map(DeviceLock::acquire)
+        .collect()
+}
+
+/// The path of the lock file for the device at `address`
+pub fn path(address: &str) -> PathBuf {
+    let name: String = address.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    env::temp_dir().join(format!("test-stand-{}.lock", name))
+}
@@ -0,0 +1,70 @@
+//! Log records sent by the firmware
+//!
+//! Firmware can send log records over its normal connection, in between other
+//! messages (see `firmware_lib::log`). Since only the test suite knows the
+//! firmware's protocol, it tells [`Conn`] how to recognize log records, by
+//! setting a [`Filter`]. Log records are taken out of the stream of messages,
+//! emitted as `tracing` events, and stored until the test case asks for them.
+//!
+//! [`Conn`]: crate::Conn
+
+
+use std::fmt;
+
+use protocol::log::Level;
+
+
+/// Recognizes log records among the received frames
+///
+/// Is passed each decoded frame. Returns `Some`, if the frame is a log record.
+/// See [`Conn::set_log_filter`].
+///
+/// [`Conn::set_log_filter`]: crate::Conn::set_log_filter
+pub type Filter = fn(&[u8]) -> Option<Record>;
+
+
+/// A log record, as received from the firmware
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    /// The level of the log record
+    pub level: Level,
+
+    /// The log message
+    pub message: String,
+}
+
+impl Record {
+    /// Emit the log record as a `tracing` event
+    ///
+    /// The event has the target `firmware`, and carries the address of the
+    /// connection it was received on. See the [`trace`] module.
+    ///
+    /// [`trace`]: crate::trace
+    pub fn emit(&self, address: &str) {
+        let message = &self.message;
+
+        match self.level {
+            Level::Error => {
+                tracing::error!(target: "firmware", address, "{}", message)
+            }
+            Level::Warn => {
+                tracing::warn!(target: "firmware", address, "{}", message)
+            }
+            Level::Info => {
+                tracing::info!(target: "firmware", address, "{}", message)
+            }
+            Level::Debug => {
+                tracing::debug!(target: "firmware", address, "{}", message)
+            }
+            Level::Trace => {
+                tracing::trace!(target: "firmware", address, "{}", message)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:?}] {}", self.level, self.message)
+    }
+}
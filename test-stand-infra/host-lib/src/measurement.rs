@@ -0,0 +1,97 @@
+//! Measuring interrupt latency, using a capture timer
+//!
+//! To measure how long a test node takes to respond to an edge on one of its
+//! pins, the edge and the entry into the interrupt handler have to be
+//! timestamped by the same clock. The assistant knows when it changed the
+//! level of a pin, but its timer can't be compared to the target's (see
+//! [`crate::timestamp`]), and the host's clock is much too coarse.
+//!
+//! Instead, a capture channel of the test node's free-running timer is
+//! connected to the pin. The timer hardware captures its value when the edge
+//! arrives, and the interrupt handler timestamps its entry with the same
+//! timer. A [`Capture`] holds both timestamps. An [`InterruptLatency`]
+//! aggregates the latencies of many captures, for example one for each edge
+//! the assistant generates.
+
+
+use std::{
+    fmt,
+    time::Duration,
+};
+
+use crate::{
+    latency::Histogram,
+    timestamp,
+};
+
+
+/// The timestamps of a single edge, as reported by a test node
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Capture {
+    /// When the edge reached the pin, captured by the timer hardware
+    pub captured: u32,
+
+    /// When the interrupt handler was entered
+    pub handled: u32,
+}
+
+impl Capture {
+    /// The time between the edge and the interrupt handler being entered
+    pub fn latency(&self) -> Duration {
+        timestamp::interval(self.captured, self.handled)
+    }
+}
+
+
+/// The interrupt latencies measured over a number of repetitions
+#[derive(Clone, Debug, Default)]
+pub struct InterruptLatency {
+    histogram: Histogram,
+}
+
+impl InterruptLatency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the latency of a capture
+    ///
+    /// Returns the latency.
+    pub fn record(&mut self, capture: Capture) -> Duration {
+        let latency = capture.latency();
+        self.histogram.record(latency);
+        latency
+    }
+
+    /// The number of captures that have been recorded
+    pub fn len(&self) -> usize {
+        self.histogram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.histogram.min()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.histogram.max()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        self.histogram.mean()
+    }
+
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+}
+
+impl fmt::Display for InterruptLatency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Interrupt latency, from edge to handler:")?;
+        write!(f, "{}", self.histogram)
+    }
+}
@@ -0,0 +1,381 @@
+//! Pseudo-random payloads for test cases that transfer a lot of data
+//!
+//! A test case that sends `[1, 2, 3, 4]` doesn't notice much. Payloads that
+//! don't repeat for a long time catch dropped, duplicated, or reordered data,
+//! and a seed makes them reproducible. A [`Payload`] generates the data from a
+//! [`Pattern`] and a seed, and verifies what was received against it. Instead
+//! of only reporting that the data differs, verification reports where it
+//! starts to differ, and how many bits are wrong.
+
+
+use std::fmt;
+
+
+/// The patterns that payloads can be generated from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pattern {
+    /// Bytes that count up from the seed, wrapping around after 255
+    ///
+    /// Easy to read in a log, or on a logic analyzer.
+    Counter,
+
+    /// Bytes from a 32-bit Galois LFSR, which repeats after 2^32 - 1 steps
+    ///
+    /// Each byte is taken after 8 steps of the LFSR.
+    Lfsr,
+
+    /// The PRBS7 bit sequence from ITU-T O.150 (x^7 + x^6 + 1)
+    ///
+    /// Bits are packed into bytes, most significant bit first. Like the other
+    /// PRBS patterns, this is what bit error rate testers expect.
+    Prbs7,
+
+    /// The PRBS15 bit sequence from ITU-T O.150 (x^15 + x^14 + 1)
+    ///
+    /// O.150 specifies the inverted output of the shift register, so the
+    /// longest run is one of 15 zeros.
+    Prbs15,
+
+    /// The PRBS31 bit sequence from ITU-T O.150 (x^31 + x^28 + 1)
+    ///
+    /// Inverted, like `Prbs15`.
+    Prbs31,
+}
+
+
+/// A reproducible payload
+///
+/// The same pattern and seed always result in the same data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Payload {
+    pub pattern: Pattern,
+
+    /// The seed of the pattern
+    ///
+    /// For `Counter`, the first byte. For the other patterns, the initial
+    /// state of the shift register. Bits that don't fit into the register are
+    /// ignored. A state of all zeros would only ever produce zeros, so the
+    /// register starts with all ones instead.
+    pub seed: u32,
+}
+
+impl Payload {
+    pub fn new(pattern: Pattern, seed: u32) -> Self {
+        Self {
+            pattern,
+            seed,
+        }
+    }
+
+    /// The bytes of the payload, without end
+    pub fn bytes(&self) -> Bytes {
+        let mask = match self.pattern {
+            Pattern::Counter => 0xff,
+            Pattern::Lfsr    => u32::MAX,
+            Pattern::Prbs7   => 0x7f,
+            Pattern::Prbs15  => 0x7fff,
+            Pattern::Prbs31  => 0x7fff_ffff,
+        };
+
+        let mut state = self.seed & mask;
+        if state == 0 && self.pattern != Pattern::Counter {
+            state = mask;
+        }
+
+        Bytes {
+            pattern: self.pattern,
+            state,
+        }
+    }
+
+    /// Generate the first `len` bytes of the payload
+    pub fn generate(&self, len: usize) -> Vec<u8> {
+        self.bytes().take(len).collect()
+    }
+
+    /// Verify received data against the first `len` bytes of the payload
+    ///
+    /// Returns an error, if any byte differs, or if more or less than `len`
+    /// bytes were received.
+    pub fn verify(&self, received: &[u8], len: usize) -> Result<(), Mismatch> {
+        let mut first       = None;
+        let mut byte_errors = 0;
+        let mut bit_errors  = 0;
+
+        let pairs = self.bytes().take(len).zip(received);
+        for (offset, (expected, &received)) in pairs.enumerate() {
+            let diff = expected ^ received;
+            if diff != 0 {
+                first.get_or_insert(offset);
+                byte_errors += 1;
+                bit_errors  += u64::from(diff.count_ones());
+            }
+        }
+
+        if first.is_none() && received.len() != len {
+            first = Some(received.len().min(len));
+        }
+
+        match first {
+            None => {
+                Ok(())
+            }
+            Some(first) => {
+                Err(
+                    Mismatch {
+                        payload:      *self,
+                        expected_len: len,
+                        received_len: received.len(),
+                        first,
+                        byte_errors,
+                        bit_errors,
+                    }
+                )
+            }
+        }
+    }
+}
+
+
+/// The bytes of a payload
+///
+/// Returned by [`Payload::bytes`].
+#[derive(Clone, Debug)]
+pub struct Bytes {
+    pattern: Pattern,
+    state:   u32,
+}
+
+impl Bytes {
+    /// Advance the PRBS shift register of `order` bits by one step
+    ///
+    /// Returns the new bit. `tap` is the order of the other term of the
+    /// polynomial.
+    fn prbs_bit(&mut self, order: u32, tap: u32) -> u32 {
+        let high = self.state >> (order - 1);
+        let low  = self.state >> (tap - 1);
+        let bit  = (high ^ low) & 1;
+        let mask = (1 << order) - 1;
+
+        self.state = ((self.state << 1) | bit) & mask;
+        bit
+    }
+
+    fn prbs_byte(&mut self, order: u32, tap: u32) -> u8 {
+        (0 .. 8).fold(0, |byte, _| {
+            (byte << 1) | self.prbs_bit(order, tap) as u8
+        })
+    }
+}
+
+impl Iterator for Bytes {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = match self.pattern {
+            Pattern::Counter => {
+                let byte = self.state as u8;
+                self.state = u32::from(byte.wrapping_add(1));
+                byte
+            }
+            Pattern::Lfsr => {
+                for _ in 0 .. 8 {
+                    // x^32 + x^22 + x^2 + x + 1
+                    let lsb = self.state & 1;
+                    self.state >>= 1;
+                    if lsb != 0 {
+                        self.state ^= 0x8020_0003;
+                    }
+                }
+                self.state as u8
+            }
+            Pattern::Prbs7  => self.prbs_byte(7,  6),
+            Pattern::Prbs15 => !self.prbs_byte(15, 14),
+            Pattern::Prbs31 => !self.prbs_byte(31, 28),
+        };
+
+        Some(byte)
+    }
+}
+
+
+/// Received data that doesn't match the payload
+///
+/// Returned by [`Payload::verify`].
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The payload that the data was verified against
+    pub payload: Payload,
+
+    /// The number of bytes that were expected
+    pub expected_len: usize,
+
+    /// The number of bytes that were received
+    pub received_len: usize,
+
+    /// The offset of the first byte that differs
+    ///
+    /// If all received bytes match, the offset of the first byte that is
+    /// missing, or that is too much.
+    pub first: usize,
+
+    /// The number of received bytes that differ from the payload
+    ///
+    /// Missing or extra bytes aren't counted.
+    pub byte_errors: usize,
+
+    /// The number of bits that differ in those bytes
+    pub bit_errors: u64,
+}
+
+impl Mismatch {
+    /// The share of the compared bits that differ
+    ///
+    /// Compares the bytes that were both expected and received.
+    pub fn bit_error_rate(&self) -> f64 {
+        let compared = self.expected_len.min(self.received_len) * 8;
+        if compared == 0 {
+            return 0.0;
+        }
+
+        self.bit_errors as f64 / compared as f64
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} payload (seed {:#x}): received {} of {} bytes, first \
+            mismatch at offset {}, {} bytes ({} bits) differ",
+            self.payload.pattern,
+            self.payload.seed,
+            self.received_len,
+            self.expected_len,
+            self.first,
+            self.byte_errors,
+            self.bit_errors,
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Pattern,
+        Payload,
+    };
+
+
+    #[test]
+    fn counter_should_count_up_from_seed_and_wrap() {
+        let payload = Payload::new(Pattern::Counter, 0xfe);
+        assert_eq!(payload.generate(4), [0xfe, 0xff, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn lfsr_should_match_known_answer() {
+        let payload = Payload::new(Pattern::Lfsr, 1);
+        assert_eq!(
+            payload.generate(8),
+            [0x02, 0xc3, 0xb6, 0xb5, 0x3f, 0xcf, 0xf0, 0xa6],
+        );
+    }
+
+    #[test]
+    fn prbs7_should_match_known_answer() {
+        // All ones: 0000001 0000011 0000101 ...
+        let payload = Payload::new(Pattern::Prbs7, 0x7f);
+        assert_eq!(
+            payload.generate(8),
+            [0x02, 0x0c, 0x28, 0xf2, 0x2c, 0xea, 0x7d, 0x0e],
+        );
+    }
+
+    #[test]
+    fn prbs15_should_match_known_answer() {
+        let payload = Payload::new(Pattern::Prbs15, 0x7fff);
+        assert_eq!(
+            payload.generate(8),
+            [0xff, 0xfd, 0xff, 0xf3, 0xff, 0xd7, 0xff, 0x0f],
+        );
+    }
+
+    #[test]
+    fn prbs31_should_match_known_answer() {
+        let payload = Payload::new(Pattern::Prbs31, 0x7fff_ffff);
+        assert_eq!(
+            payload.generate(8),
+            [0xff, 0xff, 0xff, 0xf1, 0xff, 0xff, 0xff, 0x03],
+        );
+    }
+
+    #[test]
+    fn prbs_should_start_with_all_ones_for_seed_zero() {
+        for &(pattern, ones) in &[
+            (Pattern::Prbs7,  0x7f),
+            (Pattern::Prbs15, 0x7fff),
+            (Pattern::Prbs31, 0x7fff_ffff),
+        ] {
+            assert_eq!(
+                Payload::new(pattern, 0).generate(16),
+                Payload::new(pattern, ones).generate(16),
+            );
+        }
+    }
+
+    #[test]
+    fn prbs7_should_repeat_after_127_bits() {
+        // The bits repeat after 127 of them, so the bytes repeat after 127 of
+        // them too, as 127 and 8 have no common factor.
+        let bytes = Payload::new(Pattern::Prbs7, 1).generate(127 * 2);
+        assert_eq!(bytes[..127], bytes[127..]);
+        assert_ne!(bytes[..16], bytes[16..32]);
+    }
+
+    #[test]
+    fn verify_should_accept_matching_data() {
+        let payload = Payload::new(Pattern::Prbs15, 1);
+        let data    = payload.generate(64);
+
+        assert!(payload.verify(&data, 64).is_ok());
+    }
+
+    #[test]
+    fn verify_should_report_flipped_bit() {
+        let payload  = Payload::new(Pattern::Prbs15, 1);
+        let mut data = payload.generate(64);
+        data[10] ^= 0x08;
+
+        let mismatch = payload.verify(&data, 64).unwrap_err();
+        assert_eq!(mismatch.first,        10);
+        assert_eq!(mismatch.byte_errors,  1);
+        assert_eq!(mismatch.bit_errors,   1);
+        assert_eq!(mismatch.received_len, 64);
+        assert_eq!(mismatch.bit_error_rate(), 1.0 / 512.0);
+    }
+
+    #[test]
+    fn verify_should_report_short_buffer() {
+        let payload = Payload::new(Pattern::Lfsr, 1);
+        let data    = payload.generate(40);
+
+        let mismatch = payload.verify(&data, 64).unwrap_err();
+        assert_eq!(mismatch.first,        40);
+        assert_eq!(mismatch.byte_errors,  0);
+        assert_eq!(mismatch.bit_errors,   0);
+        assert_eq!(mismatch.expected_len, 64);
+        assert_eq!(mismatch.received_len, 40);
+    }
+
+    #[test]
+    fn verify_should_report_extra_bytes() {
+        let payload = Payload::new(Pattern::Counter, 0);
+        let data    = payload.generate(65);
+
+        let mismatch = payload.verify(&data, 64).unwrap_err();
+        assert_eq!(mismatch.first,        64);
+        assert_eq!(mismatch.received_len, 65);
+    }
+}
@@ -14,7 +14,10 @@ use serde::{
     Serialize,
 };
 
-use protocol::pin;
+use protocol::{
+    chunk::Chunked,
+    pin,
+};
 
 use crate::conn::{
     Conn,
@@ -52,7 +55,7 @@ impl<Id> Pin<Id>
     )
         -> Result<(), ConnSendError>
         where
-            M: From<pin::SetLevel<Id>> + Serialize,
+            M: From<pin::SetLevel<Id>> + Serialize + Chunked,
     {
         let command = pin::SetLevel { pin: self.pin, level };
         let message: M = command.into();
@@ -69,10 +72,10 @@ impl<Id> Pin<Id>
         timeout: Duration,
         conn: &mut Conn,
     )
-        -> Result<(pin::Level, Option<u32>), ReadLevelError>
+        -> Result<(pin::Level, Option<Duration>), ReadLevelError>
         where
             Id: Debug + Eq,
-            Request: From<pin::ReadLevel<Id>> + Serialize,
+            Request: From<pin::ReadLevel<Id>> + Serialize + Chunked,
             Reply: TryInto<pin::ReadLevelResult<Id>, Error=Reply>
                 + Debug
                 + Deserialize<'de>,
@@ -103,12 +106,12 @@ impl<Id> Pin<Id>
                 pin::ReadLevelResult {
                     pin,
                     level,
-                    period_ms,
+                    period,
                 }
             )
                 if pin == self.pin
             => {
-                Ok((level, period_ms))
+                Ok((level, period.map(|period| period.into())))
             }
             Err(message) => {
                 Err(
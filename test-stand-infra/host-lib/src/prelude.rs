@@ -0,0 +1,19 @@
+//! Convenient imports for test suites
+//!
+//! Test cases typically need a handful of types from all over this library.
+//! Import them all at once with `use host_lib::prelude::*;`.
+
+
+pub use std::time::Duration;
+
+pub use protocol::pin::Level;
+
+pub use crate::{
+    assistant::Assistant,
+    conn::Conn,
+    test_stand::{
+        TestStand,
+        TestStandBuilder,
+    },
+    tolerances::Tolerances,
+};
@@ -0,0 +1,356 @@
+//! Recording of the traffic of a connection, and replaying it
+//!
+//! A test case that fails only occasionally is hard to debug, if the log
+//! doesn't show what went over the wire. A [`Recorder`] records every frame
+//! that a [`Conn`] sends or receives, along with the time, and the message that
+//! the frame carries, if the connection has a [`Decoder`]. Recordings are JSON
+//! Lines files, with one [`Entry`] per line, in the order the frames went over
+//! the wire. [`TestStand`] records each test case, if `record` is enabled in
+//! the configuration file.
+//!
+//! A recording can be fed back into the test case, by replacing the address of
+//! a test node with `replay://<recording>#<address>` (see [`Replay`]). The test
+//! case then runs without hardware, receiving what the test node sent during
+//! the recording. This way, a failure can be debugged offline, for example by
+//! adding logging to the test case, or stepping through it in a debugger.
+//!
+//! Only [`Conn`] records its traffic. [`AsyncConn`] doesn't, and neither is the
+//! message registered with [`Conn::send_on_interrupt`] recorded.
+//!
+//! [`AsyncConn`]: crate::conn::AsyncConn
+//! [`Conn`]: crate::Conn
+//! [`Conn::send_on_interrupt`]: crate::Conn::send_on_interrupt
+//! [`TestStand`]: crate::TestStand
+
+
+use std::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    fs::File,
+    io::{
+        self,
+        prelude::*,
+        BufReader,
+        LineWriter,
+    },
+    path::Path,
+    sync::{
+        Arc,
+        Mutex,
+        MutexGuard,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use lazy_static::lazy_static;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    Error,
+    transport::Transport,
+};
+
+
+/// Decodes the message carried by a frame, for the recording
+///
+/// Is passed the direction and the decoded frame. Returns a description of the
+/// message, usually its `Debug` representation, or `None`, if the frame can't
+/// be deserialized. See [`Conn::set_record_decoder`].
+///
+/// [`Conn::set_record_decoder`]: crate::Conn::set_record_decoder
+pub type Decoder = fn(Direction, &[u8]) -> Option<String>;
+
+
+/// One frame of a recording
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    /// The time since the recording started, in microseconds
+    pub time_us: u64,
+
+    /// The address of the connection that carried the frame
+    pub address: String,
+
+    /// Whether the frame was sent or received
+    pub direction: Direction,
+
+    /// The frame, as it went over the wire
+    pub frame: Vec<u8>,
+
+    /// The message carried by the frame, if it could be decoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+
+/// The direction of a recorded frame
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The frame was sent to the test node
+    Send,
+
+    /// The frame was received from the test node
+    Receive,
+
+    /// The sync preamble was received from the test node
+    ///
+    /// The test node has (re-)started. The entry's frame is the preamble.
+    Sync,
+}
+
+
+/// Records the traffic of connections to a file
+///
+/// Can be cloned, to record multiple connections to the same file. Each line is
+/// written as soon as it's complete, so a recording survives a test case that
+/// panics.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    file:  LineWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Create a recording at `path`
+    ///
+    /// An existing file at `path` is replaced.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::create(path)?;
+
+        Ok(
+            Self {
+                inner: Arc::new(Mutex::new(Inner {
+                    file:  LineWriter::new(file),
+                    start: Instant::now(),
+                })),
+            }
+        )
+    }
+
+    /// Record a frame
+    ///
+    /// `message` is the description of the message carried by the frame, if
+    /// any. See [`Decoder`].
+    pub fn record(&self,
+        address:   &str,
+        direction: Direction,
+        frame:     &[u8],
+        message:   Option<String>,
+    )
+        -> Result<(), Error>
+    {
+        // Another thread panicked while recording. Each line is written in one
+        // go, so the recording is still usable.
+        let mut inner = self.inner.lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let entry = Entry {
+            time_us: inner.start.elapsed().as_micros() as u64,
+            address: address.to_owned(),
+            direction,
+            frame:   frame.to_vec(),
+            message,
+        };
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        inner.file.write_all(&line)?;
+
+        Ok(())
+    }
+}
+
+
+/// Read all entries of the recording at `path`
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<Entry>, Error> {
+    let file = File::open(path)?;
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        entries.push(serde_json::from_str(&line)?);
+    }
+
+    Ok(entries)
+}
+
+
+/// A transport that replays a recording
+///
+/// Returned by [`crate::transport::open`] for addresses like
+/// `replay://<recording>#<address>`, which replay the frames of the recording
+/// that went over the connection to `<address>`. If `#<address>` is omitted,
+/// all frames of the recording are replayed.
+///
+/// Received frames are returned by reads in the recorded order, but only once
+/// all frames that were sent before them have been written. Until then, and
+/// after the end of the recording, reads time out right away, just as they
+/// would, if the test node didn't send anything. Written data must match the
+/// recorded frames, or the write fails, as the test case no longer does what it
+/// did during the recording.
+///
+/// Reopening a replay (for example, by calling [`Conn::reconnect`]) continues
+/// where the previous one left off, so replay only the test case that has been
+/// recorded.
+///
+/// [`Conn::reconnect`]: crate::Conn::reconnect
+pub struct Replay {
+    /// The address of the replay, which identifies its progress
+    key: String,
+
+    /// The entries that haven't been replayed yet
+    entries: VecDeque<Entry>,
+
+    /// The rest of the received frame that is being read
+    received: VecDeque<u8>,
+
+    /// Data that has been written, but doesn't complete a recorded frame yet
+    written: Vec<u8>,
+}
+
+lazy_static! {
+    /// The number of entries that each replay has consumed, by its address
+    static ref PROGRESS: Mutex<BTreeMap<String, usize>> =
+        Mutex::new(BTreeMap::new());
+}
+
+impl Replay {
+    /// Open the replay at `address`, given as `<recording>[#<address>]`
+    pub fn open(address: &str) -> Result<Self, Error> {
+        let (path, node) = match address.rsplit_once('#') {
+            Some((path, node)) => (path, Some(node)),
+            None               => (address, None),
+        };
+
+        let consumed = progress()
+            .get(address)
+            .copied()
+            .unwrap_or(0);
+        let entries = read(path)?
+            .into_iter()
+            .filter(|entry| match node {
+                Some(node) => entry.address == node,
+                None       => true,
+            })
+            .skip(consumed)
+            .collect();
+
+        Ok(
+            Self {
+                key:      address.to_owned(),
+                entries,
+                received: VecDeque::new(),
+                written:  Vec::new(),
+            }
+        )
+    }
+
+    fn next_entry(&mut self) -> Option<Entry> {
+        let entry = self.entries.pop_front()?;
+        *progress().entry(self.key.clone()).or_insert(0) += 1;
+        Some(entry)
+    }
+
+    fn next_direction(&self) -> Option<Direction> {
+        self.entries.front()
+            .map(|entry| entry.direction)
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.received.is_empty() {
+            match self.next_direction() {
+                Some(Direction::Receive) | Some(Direction::Sync) => {
+                    if let Some(entry) = self.next_entry() {
+                        self.received.extend(entry.frame);
+                    }
+                }
+                Some(Direction::Send) | None => {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+            }
+        }
+
+        let len = buf.len().min(self.received.len());
+        for (b, received) in buf.iter_mut().zip(self.received.drain(..len)) {
+            *b = received;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+
+        while self.next_direction() == Some(Direction::Send) {
+            let len = self.entries[0].frame.len();
+            if self.written.len() < len {
+                break;
+            }
+
+            if self.written[..len] != self.entries[0].frame[..] {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Sent {:?}, but the recording has {:?}",
+                        &self.written[..len], self.entries[0].frame,
+                    ),
+                ));
+            }
+
+            self.written.drain(..len);
+            self.next_entry();
+        }
+
+        let sends_left = self.entries.iter()
+            .any(|entry| entry.direction == Direction::Send);
+        if !self.written.is_empty() && !sends_left {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Sent {:?} after the end of the recording",
+                    self.written,
+                ),
+            ));
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn set_timeout(&mut self, _: Duration) -> io::Result<()> {
+        // Replayed frames are available right away, so there's no need to
+        // wait for them.
+        Ok(())
+    }
+}
+
+
+fn progress() -> MutexGuard<'static, BTreeMap<String, usize>> {
+    PROGRESS.lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
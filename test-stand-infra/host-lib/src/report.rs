@@ -0,0 +1,379 @@
+//! Reports of a test run, for CI
+//!
+//! A CI system needs to know more than whether the run passed. The
+//! [`Supervisor`] knows the outcome and duration of each test case, and test
+//! cases add what they measured on the hardware, like the period of a PWM
+//! signal, or the throughput of a USART, by calling [`measure`]. At the end of
+//! the run, the results are written as JUnit XML, which most CI systems
+//! ingest, or as JSON (see [`Json`]), for tracking measurements over time.
+//!
+//! The format is selected through `report` in the configuration file, or the
+//! `TEST_STAND_REPORT` environment variable (see [`crate::config`]). The
+//! report goes into the `artifacts` directory, next to the logs.
+//!
+//! Test cases that don't run under a supervisor aren't reported. Their
+//! measurements are only logged.
+//!
+//! [`Supervisor`]: crate::supervisor::Supervisor
+
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        prelude::*,
+        BufReader,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::supervisor::{
+    Outcome,
+    Report,
+    TestResult,
+};
+
+
+/// Set by the supervisor, to the file that [`measure`] appends to
+pub const MEASUREMENTS_VAR: &str = "TEST_STAND_MEASUREMENTS";
+
+/// The version of the JSON report
+///
+/// Incremented whenever [`Json`] changes in a way that breaks its readers.
+pub const JSON_VERSION: u32 = 1;
+
+
+/// Record a measurement of the current test case
+///
+/// `unit` is free-form, like `"B/s"` or `"us"`, and only carried into the
+/// report. Under a supervisor, the measurement is added to the result of the
+/// test case. Otherwise, it is only logged.
+pub fn measure(name: &str, value: f64, unit: &str)
+    -> Result<(), MeasureError>
+{
+    // JSON has no representation for these.
+    if !value.is_finite() {
+        return Err(MeasureError::NotFinite(value));
+    }
+
+    tracing::info!("Measured {}: {} {}", name, value, unit);
+
+    let path = match env::var_os(MEASUREMENTS_VAR) {
+        Some(path) => path,
+        None       => return Ok(()),
+    };
+
+    let measurement = Measurement {
+        name:  name.to_owned(),
+        value,
+        unit:  unit.to_owned(),
+    };
+    let mut line = serde_json::to_string(&measurement)
+        .map_err(|err| MeasureError::Io(err.into()))?;
+    line.push('\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|err| MeasureError::Io(err))?;
+
+    Ok(())
+}
+
+/// Read the measurements that a test case has appended to `path`
+///
+/// Returns an empty list, if the test case didn't measure anything. Used by
+/// the supervisor, after the test case has ended.
+pub(crate) fn read_measurements(path: &Path) -> io::Result<Vec<Measurement>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut measurements = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+
+        // A test case that was killed might have left a partial line.
+        if let Ok(measurement) = serde_json::from_str(&line) {
+            measurements.push(measurement);
+        }
+    }
+
+    Ok(measurements)
+}
+
+
+/// Write the report of a test run to the `artifacts` directory
+///
+/// Writes `report.xml` or `report.json`, depending on `format`, and replaces
+/// the report of an earlier run. Returns the path of the report.
+pub fn write(report: &Report, format: Format, artifacts: &Path)
+    -> io::Result<PathBuf>
+{
+    fs::create_dir_all(artifacts)?;
+    let path = artifacts.join(format!("report.{}", format.extension()));
+
+    let contents = match format {
+        Format::Junit => junit(report),
+        Format::Json  => {
+            serde_json::to_string_pretty(&Json::from(report))
+                .map_err(io::Error::from)?
+        }
+    };
+    fs::write(&path, contents)?;
+
+    Ok(path)
+}
+
+/// Render a report as JUnit XML
+///
+/// Each test binary is a `<testsuite>`. Measurements are `<property>` elements
+/// of their `<testcase>`, with the unit appended to the value.
+pub fn junit(report: &Report) -> String {
+    let mut xml = String::new();
+
+    // Writing to a `String` can't fail.
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<testsuites tests="{}" failures="{}">"#,
+        report.results.len(),
+        report.failed().count(),
+    );
+
+    let mut binaries: Vec<&str> = Vec::new();
+    for result in &report.results {
+        if !binaries.contains(&result.binary.as_str()) {
+            binaries.push(&result.binary);
+        }
+    }
+
+    for binary in binaries {
+        let results: Vec<_> = report.results.iter()
+            .filter(|result| result.binary == binary)
+            .collect();
+        let failures = results.iter()
+            .filter(|result| result.outcome != Outcome::Passed)
+            .count();
+        let time: f64 = results.iter()
+            .map(|result| result.duration.as_secs_f64())
+            .sum();
+
+        let _ = writeln!(
+            xml,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" time="{:.3}">"#,
+            escape(binary), results.len(), failures, time,
+        );
+        for result in results {
+            junit_test_case(&mut xml, result);
+        }
+        let _ = writeln!(xml, "  </testsuite>");
+    }
+
+    let _ = writeln!(xml, "</testsuites>");
+    xml
+}
+
+fn junit_test_case(xml: &mut String, result: &TestResult) {
+    let _ = write!(
+        xml,
+        r#"    <testcase classname="{}" name="{}" time="{:.3}""#,
+        escape(&result.binary),
+        escape(&result.name),
+        result.duration.as_secs_f64(),
+    );
+
+    if result.outcome == Outcome::Passed && result.measurements.is_empty() {
+        let _ = writeln!(xml, "/>");
+        return;
+    }
+    let _ = writeln!(xml, ">");
+
+    if !result.measurements.is_empty() {
+        let _ = writeln!(xml, "      <properties>");
+        for measurement in &result.measurements {
+            let _ = writeln!(
+                xml,
+                r#"        <property name="{}" value="{} {}"/>"#,
+                escape(&measurement.name),
+                measurement.value,
+                escape(&measurement.unit),
+            );
+        }
+        let _ = writeln!(xml, "      </properties>");
+    }
+
+    let kind = match result.outcome {
+        Outcome::Passed      => None,
+        Outcome::Failed(_)   => Some("failed"),
+        Outcome::TimedOut(_) => Some("timeout"),
+    };
+    if let Some(kind) = kind {
+        let _ = writeln!(
+            xml,
+            r#"      <failure type="{}" message="{}"/>"#,
+            kind,
+            escape(&result.outcome.to_string()),
+        );
+    }
+
+    let _ = writeln!(xml, "    </testcase>");
+}
+
+/// Escape text for use in XML attributes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&'  => escaped.push_str("&amp;"),
+            '<'  => escaped.push_str("&lt;"),
+            '>'  => escaped.push_str("&gt;"),
+            '"'  => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c    => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+
+/// The format of the report
+///
+/// Configured as `report = "junit"` or `report = "json"`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Junit,
+    Json,
+}
+
+impl Format {
+    /// The file extension of reports in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Junit => "xml",
+            Self::Json  => "json",
+        }
+    }
+}
+
+
+/// A value that a test case measured on the hardware
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Measurement {
+    /// What was measured, like `"pwm_period"`
+    pub name: String,
+
+    pub value: f64,
+
+    /// The unit of `value`, like `"us"`
+    pub unit: String,
+}
+
+
+/// The JSON report
+///
+/// Serialized as an object with these fields, and one object per test case in
+/// `results`. Fields are only ever added within a [`JSON_VERSION`].
+#[derive(Debug, Serialize)]
+pub struct Json<'r> {
+    /// Always [`JSON_VERSION`]
+    pub version: u32,
+
+    pub tests:  usize,
+    pub passed: usize,
+    pub failed: usize,
+
+    pub results: Vec<JsonResult<'r>>,
+}
+
+impl<'r> From<&'r Report> for Json<'r> {
+    fn from(report: &'r Report) -> Self {
+        let failed = report.failed().count();
+
+        Self {
+            version: JSON_VERSION,
+            tests:   report.results.len(),
+            passed:  report.results.len() - failed,
+            failed,
+            results: report.results.iter().map(JsonResult::from).collect(),
+        }
+    }
+}
+
+
+/// The result of a test case in the JSON report
+#[derive(Debug, Serialize)]
+pub struct JsonResult<'r> {
+    /// The name of the test binary, without the hash that Cargo appends
+    pub binary: &'r str,
+
+    pub name: &'r str,
+
+    /// `"passed"`, `"failed"`, or `"timed_out"`
+    pub outcome: &'static str,
+
+    /// How the test case failed, if it did
+    pub message: Option<String>,
+
+    /// How long the test case ran, in seconds
+    pub duration_s: f64,
+
+    pub measurements: &'r [Measurement],
+}
+
+impl<'r> From<&'r TestResult> for JsonResult<'r> {
+    fn from(result: &'r TestResult) -> Self {
+        let (outcome, message) = match result.outcome {
+            Outcome::Passed => {
+                ("passed", None)
+            }
+            Outcome::Failed(_) => {
+                ("failed", Some(result.outcome.to_string()))
+            }
+            Outcome::TimedOut(_) => {
+                ("timed_out", Some(result.outcome.to_string()))
+            }
+        };
+
+        Self {
+            binary:       &result.binary,
+            name:         &result.name,
+            outcome,
+            message,
+            duration_s:   result.duration.as_secs_f64(),
+            measurements: &result.measurements,
+        }
+    }
+}
+
+
+/// Error recording a measurement
+#[derive(Debug)]
+pub enum MeasureError {
+    /// The measurements file could not be written
+    Io(io::Error),
+
+    /// The value is infinite or NaN
+    NotFinite(f64),
+}
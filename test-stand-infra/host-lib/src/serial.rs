@@ -0,0 +1,269 @@
+//! Raw serial connection, with expectation-based reads
+
+
+use std::{
+    io::{
+        self,
+        prelude::*,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use protocol::usart::{
+    Config,
+    Parity,
+    StopBits,
+    WordLength,
+};
+use serialport::SerialPort;
+
+use crate::{
+    Error,
+    config::Port,
+};
+
+
+/// A raw serial connection, for example through a USB/serial adapter
+///
+/// Unlike [`Conn`], this doesn't use any framing. It sends and receives raw
+/// bytes, which makes it suitable for talking to a USART of the test target
+/// directly.
+///
+/// All data sent and received is logged as `debug` events, using `tracing`
+/// (see the [`trace`] module).
+///
+/// [`Conn`]: crate::conn::Conn
+/// [`trace`]: crate::trace
+pub struct Serial {
+    port: Box<dyn SerialPort>,
+    path: String,
+}
+
+impl Serial {
+    /// Open the serial connection
+    ///
+    /// `path` is the path to the serial device file.
+    pub fn new(path: &str) -> Result<Self, SerialInitError> {
+        Self::with_port(path, &Port::default())
+    }
+
+    /// Open the serial connection, with the settings of `port`
+    ///
+    /// Works like [`Serial::new`]. The default baud rate is the one that the
+    /// test nodes use for their USARTs, so `port` only needs to change it, if
+    /// the adapter is connected to something else.
+    pub fn with_port(path: &str, port: &Port)
+        -> Result<Self, SerialInitError>
+    {
+        let port = serialport::new(path, port.baud())
+            .flow_control(port.flow_control().into())
+            .open()
+            .map_err(|err| SerialInitError(err))?;
+
+        Ok(
+            Self {
+                port,
+                path: path.to_owned(),
+            }
+        )
+    }
+
+    /// Change the configuration of the serial connection
+    ///
+    /// The test node on the other end needs to be reconfigured the same way.
+    pub fn configure(&mut self, config: &Config)
+        -> Result<(), SerialConfigureError>
+    {
+        self.configure_inner(config)
+            .map_err(|err| SerialConfigureError(err))
+    }
+
+    fn configure_inner(&mut self, config: &Config)
+        -> Result<(), serialport::Error>
+    {
+        let parity = match config.parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Even => serialport::Parity::Even,
+            Parity::Odd  => serialport::Parity::Odd,
+        };
+        let stop_bits = match config.stop_bits {
+            StopBits::One => serialport::StopBits::One,
+            StopBits::Two => serialport::StopBits::Two,
+        };
+        let data_bits = match config.word_length {
+            WordLength::Seven => serialport::DataBits::Seven,
+            WordLength::Eight => serialport::DataBits::Eight,
+            WordLength::Nine  => {
+                return Err(
+                    serialport::Error::new(
+                        serialport::ErrorKind::InvalidInput,
+                        "9 data bits are not supported",
+                    )
+                );
+            }
+        };
+
+        self.port.set_baud_rate(config.baud)?;
+        self.port.set_parity(parity)?;
+        self.port.set_stop_bits(stop_bits)?;
+        self.port.set_data_bits(data_bits)?;
+
+        tracing::debug!(path = %self.path, ?config, "configured");
+
+        Ok(())
+    }
+
+    /// Send raw data
+    pub fn send(&mut self, data: &[u8]) -> Result<(), SerialSendError> {
+        tracing::debug!(path = %self.path, ?data, "sending");
+
+        self.port.write_all(data)
+            .map_err(|err| SerialSendError(err.into()))
+    }
+
+    /// Receive whatever data is available
+    ///
+    /// Waits up to `timeout` for data to arrive, then appends everything that
+    /// was received to `buf`. Returns an error, if nothing arrives in time.
+    pub fn receive(&mut self, timeout: Duration, buf: &mut Vec<u8>)
+        -> Result<(), SerialReceiveError>
+    {
+        self.receive_inner(timeout, buf)
+            .map_err(|err| SerialReceiveError(err))
+    }
+
+    fn receive_inner(&mut self, timeout: Duration, buf: &mut Vec<u8>)
+        -> Result<(), Error>
+    {
+        self.port.set_timeout(timeout)?;
+
+        let mut tmp = [0; 256];
+        let len = self.port.read(&mut tmp)?;
+        buf.extend_from_slice(&tmp[..len]);
+
+        tracing::debug!(path = %self.path, data = ?&tmp[..len], "received");
+
+        Ok(())
+    }
+
+    /// Expect to receive exactly the provided data within `timeout`
+    ///
+    /// Returns an error, if the data doesn't arrive in time, or if anything
+    /// other than the expected data is received.
+    pub fn expect(&mut self, data: &[u8], timeout: Duration)
+        -> Result<(), SerialExpectError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        while buf.len() < data.len() {
+            let remaining = match timeout.checked_sub(start.elapsed()) {
+                Some(remaining) => remaining,
+                None            => {
+                    return Err(SerialExpectError::Timeout { received: buf });
+                }
+            };
+
+            match self.receive(remaining, &mut buf) {
+                Ok(()) => {}
+                Err(err) if err.is_timeout() => {
+                    return Err(SerialExpectError::Timeout { received: buf });
+                }
+                Err(err) => {
+                    return Err(SerialExpectError::Receive(err));
+                }
+            }
+
+            if !data.starts_with(&buf[..buf.len().min(data.len())]) {
+                break;
+            }
+        }
+
+        if buf != data {
+            return Err(
+                SerialExpectError::UnexpectedData {
+                    expected: data.to_vec(),
+                    received: buf,
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Capture all received data, until the connection goes idle
+    ///
+    /// Returns once nothing has been received for the `idle` duration, or once
+    /// `timeout` has passed, whichever happens first. Nothing being received
+    /// is not an error. An empty buffer is returned in that case.
+    pub fn capture_until_idle(&mut self, idle: Duration, timeout: Duration)
+        -> Result<Vec<u8>, SerialReceiveError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        while start.elapsed() < timeout {
+            match self.receive(idle, &mut buf) {
+                Ok(()) => {}
+                Err(err) if err.is_timeout() => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(buf)
+    }
+}
+
+
+/// Error opening a serial connection
+#[derive(Debug)]
+pub struct SerialInitError(pub serialport::Error);
+
+
+/// Error configuring a serial connection
+#[derive(Debug)]
+pub struct SerialConfigureError(pub serialport::Error);
+
+
+/// Error sending data through a serial connection
+#[derive(Debug)]
+pub struct SerialSendError(pub Error);
+
+
+/// Error receiving data from a serial connection
+#[derive(Debug)]
+pub struct SerialReceiveError(pub Error);
+
+impl SerialReceiveError {
+    pub fn is_timeout(&self) -> bool {
+        match &self.0 {
+            Error::Io(err) if err.kind() == io::ErrorKind::TimedOut => {
+                true
+            }
+            _ => {
+                false
+            }
+        }
+    }
+}
+
+
+/// Error expecting data from a serial connection
+#[derive(Debug)]
+pub enum SerialExpectError {
+    Receive(SerialReceiveError),
+
+    /// The expected data didn't arrive in time
+    Timeout {
+        received: Vec<u8>,
+    },
+
+    /// Data other than the expected data was received
+    UnexpectedData {
+        expected: Vec<u8>,
+        received: Vec<u8>,
+    },
+}
@@ -0,0 +1,125 @@
+//! Clean shutdown of interrupted test runs
+//!
+//! If a test run is interrupted, by Ctrl-C or by `SIGTERM`, the test process
+//! exits without running any destructors. Whatever the current test case has
+//! started on the test nodes, like a timer interrupt or a PWM signal, would
+//! keep running, and get in the way of the next test run.
+//!
+//! Once [`install`] has been called, `SIGINT` and `SIGTERM` are handled
+//! instead. The handler runs the actions registered with [`on_interrupt`],
+//! flushes standard output, and only then exits. The lock that keeps test cases
+//! from using the test stand concurrently only exists within the test process,
+//! so exiting releases it.
+
+
+use std::{
+    collections::BTreeMap,
+    io::{
+        self,
+        Write as _,
+    },
+    mem,
+    process,
+    sync::Mutex,
+    thread,
+};
+
+use lazy_static::lazy_static;
+use signal_hook::{
+    SIGINT,
+    SIGTERM,
+    iterator::Signals,
+};
+
+
+type Action = Box<dyn FnOnce() + Send>;
+
+
+lazy_static! {
+    static ref INSTALLED: Mutex<bool> = Mutex::new(false);
+    static ref ACTIONS: Mutex<Actions> = Mutex::new(Actions {
+        next: 0,
+        map:  BTreeMap::new(),
+    });
+}
+
+
+/// Handle `SIGINT` and `SIGTERM`, as described in the module documentation
+///
+/// Only installs the handler once. Further calls do nothing.
+/// [`TestStand::new`] calls this, so test suites don't have to.
+///
+/// [`TestStand::new`]: crate::TestStand::new
+pub fn install() -> io::Result<()> {
+    let mut installed = INSTALLED.lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if *installed {
+        return Ok(());
+    }
+
+    let signals = Signals::new([SIGINT, SIGTERM])?;
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            run_actions();
+
+            // Like a shell would report it, if the process had been killed by
+            // the signal.
+            process::exit(128 + signal);
+        }
+    });
+
+    *installed = true;
+    Ok(())
+}
+
+/// Register an action to run, if the test run is interrupted
+///
+/// The action stays registered, until the returned [`Registration`] is dropped.
+/// Actions must not block for long, as they delay the exit.
+pub fn on_interrupt(action: impl FnOnce() + Send + 'static) -> Registration {
+    let mut actions = ACTIONS.lock()
+        .unwrap_or_else(|err| err.into_inner());
+
+    let id = actions.next;
+    actions.next += 1;
+    actions.map.insert(id, Box::new(action));
+
+    Registration(id)
+}
+
+fn run_actions() {
+    // Take the actions out, so the lock isn't held while they run.
+    let actions = {
+        let mut actions = ACTIONS.lock()
+            .unwrap_or_else(|err| err.into_inner());
+        mem::take(&mut actions.map)
+    };
+
+    for (_, action) in actions {
+        action();
+    }
+
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+}
+
+
+/// An action registered with [`on_interrupt`]
+///
+/// Unregisters the action when dropped.
+#[must_use]
+pub struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut actions = ACTIONS.lock()
+            .unwrap_or_else(|err| err.into_inner());
+        actions.map.remove(&self.0);
+    }
+}
+
+
+struct Actions {
+    next: u64,
+    map:  BTreeMap<u64, Action>,
+}
@@ -0,0 +1,362 @@
+//! Running each test case in a process of its own
+//!
+//! Normally, all test cases of a test suite run in one process. A test case
+//! that hangs stalls the whole run, and one that leaves the test stand in a
+//! bad state can make every test case after it fail. In a CI run of hundreds
+//! of test cases, that makes the results of the whole run worthless.
+//!
+//! A [`Supervisor`] runs every test case of a test binary in a child process.
+//! The child connects to the test stand as usual, and holds the locks on its
+//! devices (see [`crate::lock`]) only while it runs. If it hangs, the
+//! supervisor kills it, and the operating system releases the locks. After any
+//! test case that didn't pass, the supervisor calls back into the test suite,
+//! which brings the test stand back into a known state, before the run
+//! continues.
+//!
+//! Test suites provide a binary that sets up the supervisor, as only they know
+//! how to recover their test stand. Firmware is downloaded once, before the
+//! first test case. [`crate::TestStand`] doesn't download it again in the child
+//! processes.
+//!
+//! If the binary serves the connections to the test nodes (see
+//! [`crate::lease`]), the child processes don't open the devices themselves.
+//! They connect to the leased connections instead, which stay open across test
+//! cases, no matter how those end.
+//!
+//! The outcome of each test case, and what it measured, is collected in a
+//! [`Report`], which can be written for CI (see [`crate::report`]).
+
+
+use std::{
+    env,
+    fmt,
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::{
+        self,
+        Command,
+        ExitStatus,
+        Stdio,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::{
+    lease::{
+        self,
+        LEASES_VAR,
+    },
+    report::{
+        self,
+        Measurement,
+        MEASUREMENTS_VAR,
+    },
+    trace,
+};
+
+
+/// Set in the environment of the child processes
+///
+/// See [`is_supervised`].
+pub const SUPERVISED_VAR: &str = "TEST_STAND_SUPERVISED";
+
+/// How often the supervisor checks whether a test case has finished
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+
+/// Indicates whether this process runs under a supervisor
+pub fn is_supervised() -> bool {
+    env::var_os(SUPERVISED_VAR).is_some()
+}
+
+
+/// Runs test cases in child processes, and recovers the test stand after them
+#[derive(Debug)]
+pub struct Supervisor {
+    timeout: Duration,
+}
+
+impl Supervisor {
+    /// Create a supervisor that kills test cases after `timeout`
+    ///
+    /// Test suites should pass [`crate::config::Timeouts::test`].
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+        }
+    }
+
+    /// List the test cases of a test binary
+    ///
+    /// Ignored test cases are left out.
+    pub fn list(binary: &Path) -> Result<Vec<String>, ListError> {
+        let all     = list(binary, &[])?;
+        let ignored = list(binary, &["--ignored"])?;
+
+        Ok(
+            all.into_iter()
+                .filter(|name| !ignored.contains(name))
+                .collect()
+        )
+    }
+
+    /// Run all test cases of a test binary
+    ///
+    /// The result of each test case is added to `report`, in the order they
+    /// ran. `recover` is called after every test case that failed or was killed.
+    /// It needs to bring the test stand back into a known state, and returns an
+    /// error, if that's not possible. The run stops then, as the remaining test
+    /// cases would fail for the same reason.
+    pub fn run<E>(&self,
+        binary:      &Path,
+        report:      &mut Report,
+        mut recover: impl FnMut() -> Result<(), E>,
+    )
+        -> Result<(), SupervisorError<E>>
+    {
+        let tests = Self::list(binary)
+            .map_err(|err| SupervisorError::List {
+                binary: binary.to_owned(),
+                err,
+            })?;
+
+        for name in tests {
+            let result = self.run_test(binary, &name)
+                .map_err(|err| SupervisorError::Spawn {
+                    binary: binary.to_owned(),
+                    err,
+                })?;
+            tracing::info!(
+                binary  = %result.binary,
+                outcome = %result.outcome,
+                "{}", name,
+            );
+
+            let passed = result.outcome == Outcome::Passed;
+            report.results.push(result);
+
+            if !passed {
+                recover()
+                    .map_err(|err| SupervisorError::Recover {
+                        after: name,
+                        err,
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_test(&self, binary: &Path, name: &str) -> io::Result<TestResult> {
+        // The test case appends its measurements to this file. A leftover
+        // from an earlier run would be attributed to it, so remove that first.
+        let measurements_path = env::temp_dir()
+            .join(format!("test-stand-{}.jsonl", process::id()));
+        remove_if_exists(&measurements_path)?;
+
+        let mut child = Command::new(binary)
+            .args([name, "--exact", "--quiet", "--test-threads=1"])
+            .env(SUPERVISED_VAR, "1")
+            .env(MEASUREMENTS_VAR, &measurements_path)
+            .envs(lease::env_value().map(|leases| (LEASES_VAR, leases)))
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        let start = Instant::now();
+
+        let outcome = loop {
+            if let Some(status) = child.try_wait()? {
+                if status.success() {
+                    break Outcome::Passed;
+                }
+                break Outcome::Failed(status);
+            }
+
+            if start.elapsed() > self.timeout {
+                // Killing it doesn't give the test case a chance to clean up,
+                // which is why the test stand needs to be recovered afterwards.
+                child.kill()?;
+                child.wait()?;
+                break Outcome::TimedOut(self.timeout);
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        };
+        let duration = start.elapsed();
+
+        // A test case that hung still reports what it measured before.
+        let measurements = report::read_measurements(&measurements_path)?;
+        remove_if_exists(&measurements_path)?;
+
+        Ok(
+            TestResult {
+                binary:       trace::binary_name_of(binary),
+                name:         name.to_owned(),
+                outcome,
+                duration,
+                measurements,
+            }
+        )
+    }
+}
+
+
+/// List the test cases of a test binary, as reported by the test harness
+fn list(binary: &Path, args: &[&str]) -> Result<Vec<String>, ListError> {
+    let output = Command::new(binary)
+        .args(["--list", "--format", "terse"])
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| ListError::Spawn(err))?;
+    if !output.status.success() {
+        return Err(ListError::Failed(output.status));
+    }
+
+    // Each test case is listed as `name: test`. Benchmarks are left out.
+    let tests = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_suffix(": test"))
+        .map(|name| name.to_owned())
+        .collect();
+
+    Ok(tests)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+
+/// How a test case ended
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Passed,
+
+    /// The test case failed, or its process exited abnormally
+    Failed(ExitStatus),
+
+    /// The test case ran for longer than the timeout, and was killed
+    TimedOut(Duration),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Passed            => write!(f, "ok"),
+            Self::Failed(status)    => write!(f, "FAILED ({})", status),
+            Self::TimedOut(timeout) => write!(f, "TIMED OUT ({:?})", timeout),
+        }
+    }
+}
+
+
+/// The result of a test case that a supervisor has run
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    /// The name of the test binary, without the hash that Cargo appends
+    pub binary: String,
+
+    /// The name of the test case, as the test harness lists it
+    pub name: String,
+
+    pub outcome: Outcome,
+
+    /// How long the test case ran, including connecting to the test stand
+    pub duration: Duration,
+
+    /// What the test case measured, in the order it measured it
+    ///
+    /// See [`report::measure`].
+    pub measurements: Vec<Measurement>,
+}
+
+
+/// The outcomes of all test cases that a supervisor has run
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The result of each test case, in the order they ran
+    pub results: Vec<TestResult>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indicates whether all test cases have passed
+    pub fn passed(&self) -> bool {
+        self.failed().next().is_none()
+    }
+
+    /// The results of the test cases that didn't pass
+    pub fn failed(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter()
+            .filter(|result| result.outcome != Outcome::Passed)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failed: Vec<_> = self.failed().collect();
+
+        if !failed.is_empty() {
+            writeln!(f, "failures:")?;
+            for result in &failed {
+                writeln!(f, "    {} {}", result.outcome, result.name)?;
+            }
+            writeln!(f)?;
+        }
+
+        write!(
+            f,
+            "{} test cases: {} passed, {} failed",
+            self.results.len(),
+            self.results.len() - failed.len(),
+            failed.len(),
+        )
+    }
+}
+
+
+#[derive(Debug)]
+pub enum SupervisorError<E> {
+    /// Error listing the test cases of a test binary
+    List {
+        binary: PathBuf,
+        err:    ListError,
+    },
+
+    /// Error starting a test case, or waiting for it
+    Spawn {
+        binary: PathBuf,
+        err:    io::Error,
+    },
+
+    /// The test stand couldn't be recovered after a test case
+    Recover {
+        after: String,
+        err:   E,
+    },
+}
+
+
+#[derive(Debug)]
+pub enum ListError {
+    /// Error starting the test binary
+    Spawn(io::Error),
+
+    /// The test binary exited with an error
+    Failed(ExitStatus),
+}
@@ -0,0 +1,1235 @@
+//! API for the test target, shared between test stands
+//!
+//! Every test stand has a test target, and most of what test suites do with
+//! it doesn't depend on the target hardware: Checking the firmware version,
+//! negotiating the connection, setting and reading pins, sending and receiving
+//! via USART, and so on. [`Target`] provides that part of the API once, for
+//! all test stands.
+//!
+//! Each test stand has its own messages though, so `Target` is generic over a
+//! [`Board`], which converts between those and the [`Request`]s and [`Reply`]s
+//! that `Target` understands. Test suites usually wrap `Target` in a type of
+//! their own, to add the parts of the API that are specific to their target.
+
+
+use std::{
+    convert::{
+        TryFrom,
+        TryInto,
+    },
+    fmt,
+    marker::PhantomData,
+    mem,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use protocol::{
+    UsartMode,
+    checksum::Checksum,
+    chunk::Chunked,
+    compress::Compression,
+    log::Level,
+    panic,
+    pin,
+    units::Microseconds,
+    usart,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    assistant::{
+        Assistant,
+        AssistantError,
+    },
+    config::Timeouts,
+    conn::{
+        self,
+        Conn,
+        ConnReceiveError,
+        ConnSendError,
+    },
+    log,
+    pin::{
+        Pin,
+        ReadLevelError,
+    },
+    record::Direction,
+    signal::Registration,
+};
+
+
+/// A kind of test target, and the messages its firmware understands
+///
+/// Implemented by each test suite, usually for an empty type named after the
+/// target board. The associated types are the message enums that are shared
+/// with the firmware, and the types some of their variants carry.
+pub trait Board: fmt::Debug + Sized + 'static {
+    /// The messages sent by the host
+    type Request<'r>: Serialize + Chunked + Deserialize<'r> + fmt::Debug
+        + From<pin::SetLevel<pin::PortPin>>
+        + From<pin::ReadLevel<pin::PortPin>>;
+
+    /// The messages sent by the target
+    type Reply<'r>: Deserialize<'r> + fmt::Debug
+        + TryInto<pin::ReadLevelResult<pin::PortPin>, Error = Self::Reply<'r>>;
+
+    /// The functionality that the firmware supports
+    type Capabilities: fmt::Debug;
+
+    /// The state of the target's interrupts
+    type IrqState: fmt::Debug;
+
+    /// The kind of a fault that the target reports after restarting
+    type Fault: fmt::Debug;
+
+    /// Convert a request into a message
+    fn request<'r>(request: Request<'r>) -> Self::Request<'r>;
+
+    /// Convert a message into a reply
+    ///
+    /// Returns the message unchanged, if it isn't one of the replies that
+    /// [`Target`] understands.
+    fn reply<'r>(reply: Self::Reply<'r>)
+        -> Result<Reply<'r, Self>, Self::Reply<'r>>;
+}
+
+
+/// A request that [`Target`] sends to the test target
+///
+/// Converted into the message of the test stand by [`Board::request`].
+#[derive(Debug)]
+pub enum Request<'r> {
+    NegotiateCompression(Compression),
+    NegotiateChecksum(Checksum),
+    GetCapabilities,
+    GetVersion,
+    GetIrqState,
+    Ping,
+    Quiesce,
+    SendUsart {
+        mode: UsartMode,
+        data: &'r [u8],
+    },
+    StartTimerInterrupt {
+        period: Microseconds,
+    },
+    StopTimerInterrupt,
+    StartUsartThroughputTest {
+        bytes:      u32,
+        chunk_size: u16,
+    },
+}
+
+
+/// A reply that [`Target`] receives from the test target
+///
+/// Converted from the message of the test stand by [`Board::reply`].
+#[derive(Debug)]
+pub enum Reply<'r, B: Board> {
+    CompressionNegotiated(Compression),
+    ChecksumNegotiated(Checksum),
+    Capabilities(B::Capabilities),
+    Version {
+        protocol:      u32,
+        firmware_hash: &'r str,
+        hal_version:   &'r str,
+        features:      &'r str,
+    },
+    IrqState(B::IrqState),
+    Pong,
+    Quiesced,
+    Fault(B::Fault),
+    UsartReceive {
+        mode: UsartMode,
+        data: &'r [u8],
+    },
+    Log {
+        level:   Level,
+        message: &'r str,
+    },
+    BufferOverrun {
+        dropped: u32,
+    },
+    Heartbeat,
+    Panic(panic::Report<'r>),
+    Defmt(&'r [u8]),
+    UsartThroughputSent,
+}
+
+
+/// The connection to a test target
+pub struct Target<B> {
+    conn:        Conn,
+    compression: Compression,
+    checksum:    Checksum,
+    timeouts:    Timeouts,
+    board:       PhantomData<B>,
+}
+
+impl<B> Target<B>
+    where B: Board
+{
+    /// Create a new instance of `Target`
+    ///
+    /// Sets up `conn` to recognize the log records, defmt frames, reports of
+    /// dropped data, and panic reports that the target sends, to decode its
+    /// messages for recordings, and to ping the target, when resynchronizing
+    /// after a corrupted frame.
+    pub fn new(mut conn: Conn) -> Self {
+        conn.set_log_filter(log_record::<B>);
+        conn.set_defmt_filter(defmt_frames::<B>);
+        conn.set_record_decoder(decode_message::<B>);
+        conn.set_overrun_filter(buffer_overrun::<B>);
+        conn.set_panic_filter(panic_report::<B>);
+        conn.set_resync_frame(resync_frame::<B>);
+
+        Self {
+            conn,
+            compression: Compression::None,
+            checksum:    Checksum::None,
+            timeouts:    Timeouts::default(),
+            board:       PhantomData,
+        }
+    }
+
+    /// Access the connection to the target
+    ///
+    /// For the parts of the API that are specific to a test stand.
+    pub fn conn(&mut self) -> &mut Conn {
+        &mut self.conn
+    }
+
+    /// The timeouts of the methods that don't take a timeout
+    ///
+    /// The defaults, unless they have been replaced.
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Replace the timeouts of the methods that don't take a timeout
+    ///
+    /// Test stands set these from the `timeouts` section of the configuration
+    /// file.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Replace the timeouts for a single call
+    ///
+    /// `f` is passed the current timeouts, and returns the ones to use, like
+    /// `target.with_timeouts(|t| t.with_read(timeout)).pin_is_high(pin)`. The
+    /// returned guard dereferences to the target, and restores the previous
+    /// timeouts when it is dropped.
+    pub fn with_timeouts(&mut self, f: impl FnOnce(Timeouts) -> Timeouts)
+        -> WithTimeouts<'_, Self>
+    {
+        WithTimeouts::new(self, f)
+    }
+
+    /// Take all log records that the target has sent so far
+    ///
+    /// Log records are printed as they are received, so this is only needed
+    /// by test cases that make assertions about them.
+    pub fn take_log(&mut self) -> Vec<log::Record> {
+        self.conn.take_log()
+    }
+
+    /// Negotiate the compression scheme for frames sent by the target
+    ///
+    /// The connection uses the scheme that the target replies with. The LPC845
+    /// test target always accepts the requested scheme. The STM32L4 test
+    /// target doesn't support compression, and declines by replying with
+    /// [`Compression::None`], in which case the connection stays uncompressed.
+    pub fn negotiate_compression(&mut self,
+        compression: Compression,
+        timeout:     Duration,
+    )
+        -> Result<(), TargetNegotiateCompressionError>
+    {
+        self.conn
+            .send(&B::request(Request::NegotiateCompression(compression)))
+            .map_err(|err| TargetNegotiateCompressionError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<B::Reply<'_>>(timeout, &mut tmp)
+            .map_err(|err| TargetNegotiateCompressionError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::CompressionNegotiated(negotiated)) => {
+                self.conn.set_compression(negotiated);
+                self.compression = negotiated;
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetNegotiateCompressionError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Negotiate the checksum for frames in both directions
+    ///
+    /// The target might decline the checksum, in which case frames continue to
+    /// be sent without one.
+    pub fn negotiate_checksum(&mut self,
+        checksum: Checksum,
+        timeout:  Duration,
+    )
+        -> Result<(), TargetNegotiateChecksumError>
+    {
+        self.conn
+            .send(&B::request(Request::NegotiateChecksum(checksum)))
+            .map_err(|err| TargetNegotiateChecksumError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<B::Reply<'_>>(timeout, &mut tmp)
+            .map_err(|err| TargetNegotiateChecksumError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::ChecksumNegotiated(negotiated)) => {
+                self.conn.set_checksum(negotiated);
+                self.checksum = negotiated;
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetNegotiateChecksumError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Negotiate the compression scheme and checksum again
+    ///
+    /// Call this once the target has restarted and the connection has been
+    /// resynchronized, as the target no longer compresses its frames, or uses
+    /// checksums, then. Restores whatever has been negotiated before.
+    pub fn renegotiate(&mut self, timeout: Duration)
+        -> Result<(), TargetRenegotiateError>
+    {
+        let compression = self.compression;
+        let checksum    = self.checksum;
+        self.compression = Compression::None;
+        self.checksum    = Checksum::None;
+
+        if compression != Compression::None {
+            self.negotiate_compression(compression, timeout)
+                .map_err(|err| TargetRenegotiateError::Compression(err))?;
+        }
+        if checksum != Checksum::None {
+            self.negotiate_checksum(checksum, timeout)
+                .map_err(|err| TargetRenegotiateError::Checksum(err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Ask the target which functionality it supports
+    ///
+    /// Depending on how the firmware was built, the target might only support
+    /// a subset of the test suite.
+    ///
+    /// Retried after a corrupted frame, if the connection has a retry policy
+    /// (see [`Conn::set_retry`]). So are the other methods that only ask the
+    /// target for something.
+    pub fn capabilities(&mut self, timeout: Duration)
+        -> Result<B::Capabilities, TargetCapabilitiesError>
+    {
+        self.retry(
+            |target| target.capabilities_inner(timeout),
+            |err| matches!(
+                err,
+                TargetCapabilitiesError::Receive(err) if err.is_corrupted()
+            ),
+        )
+    }
+
+    fn capabilities_inner(&mut self, timeout: Duration)
+        -> Result<B::Capabilities, TargetCapabilitiesError>
+    {
+        self.conn
+            .send(&B::request(Request::GetCapabilities))
+            .map_err(|err| TargetCapabilitiesError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<B::Reply<'_>>(timeout, &mut tmp)
+            .map_err(|err| TargetCapabilitiesError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::Capabilities(capabilities)) => {
+                Ok(capabilities)
+            }
+            message => {
+                Err(
+                    TargetCapabilitiesError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which version of the firmware it runs
+    pub fn version(&mut self, timeout: Duration)
+        -> Result<Version, TargetVersionError>
+    {
+        self.retry(
+            |target| target.version_inner(timeout),
+            |err| matches!(
+                err,
+                TargetVersionError::Receive(err) if err.is_corrupted()
+            ),
+        )
+    }
+
+    fn version_inner(&mut self, timeout: Duration)
+        -> Result<Version, TargetVersionError>
+    {
+        self.conn
+            .send(&B::request(Request::GetVersion))
+            .map_err(|err| TargetVersionError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<B::Reply<'_>>(timeout, &mut tmp)
+            .map_err(|err| TargetVersionError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::Version {
+                protocol,
+                firmware_hash,
+                hal_version,
+                features,
+            }) => {
+                Ok(
+                    Version {
+                        protocol,
+                        firmware_hash: firmware_hash.to_owned(),
+                        hal_version:   hal_version.to_owned(),
+                        features:      features
+                            .split(',')
+                            .filter(|feature| !feature.is_empty())
+                            .map(|feature| feature.to_owned())
+                            .collect(),
+                    }
+                )
+            }
+            message => {
+                Err(
+                    TargetVersionError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Ask the target which interrupts are enabled and pending
+    ///
+    /// Use this to check that a driver enables and disables its interrupts
+    /// correctly, which can't be observed from its behavior alone.
+    pub fn irq_state(&mut self, timeout: Duration)
+        -> Result<B::IrqState, TargetIrqStateError>
+    {
+        self.retry(
+            |target| target.irq_state_inner(timeout),
+            |err| matches!(
+                err,
+                TargetIrqStateError::Receive(err) if err.is_corrupted()
+            ),
+        )
+    }
+
+    fn irq_state_inner(&mut self, timeout: Duration)
+        -> Result<B::IrqState, TargetIrqStateError>
+    {
+        self.conn
+            .send(&B::request(Request::GetIrqState))
+            .map_err(|err| TargetIrqStateError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<B::Reply<'_>>(timeout, &mut tmp)
+            .map_err(|err| TargetIrqStateError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::IrqState(state)) => {
+                Ok(state)
+            }
+            message => {
+                Err(
+                    TargetIrqStateError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Check that the target is still responsive
+    pub fn ping(&mut self, timeout: Duration) -> Result<(), TargetPingError> {
+        self.retry(
+            |target| target.ping_inner(timeout),
+            |err| matches!(
+                err,
+                TargetPingError::Receive(err) if err.is_corrupted()
+            ),
+        )
+    }
+
+    fn ping_inner(&mut self, timeout: Duration)
+        -> Result<(), TargetPingError>
+    {
+        self.conn
+            .send(&B::request(Request::Ping))
+            .map_err(|err| TargetPingError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive::<B::Reply<'_>>(timeout, &mut tmp)
+            .map_err(|err| TargetPingError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::Pong) => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetPingError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Make sure the target is idle and healthy at the end of a test case
+    ///
+    /// Instructs the target to stop everything that a test case might have
+    /// started, like the timer interrupt, the PWM signal, or the reporting of
+    /// pin interrupts. Then drains all messages that the target sent, but that
+    /// the test case didn't receive, until nothing has been received for
+    /// `timeout`. Returns an error, if any of those messages report a fault, or
+    /// if there are any messages at all. Finally, checks that the target still
+    /// answers a ping.
+    ///
+    /// Test stands call this when they're dropped. Without it, a leftover
+    /// message would only show up as an unexpected message in the next test
+    /// case.
+    pub fn quiesce(&mut self, timeout: Duration)
+        -> Result<(), TargetQuiesceError<B::Fault>>
+    {
+        self.conn
+            .send(&B::request(Request::Quiesce))
+            .map_err(|err| TargetQuiesceError::Send(err))?;
+
+        let mut unexpected = Vec::new();
+        let mut quiesced   = false;
+
+        loop {
+            let mut tmp = Vec::new();
+            let result = self.conn
+                .receive::<B::Reply<'_>>(timeout, &mut tmp)
+                .map(B::reply);
+
+            match result {
+                Ok(Ok(Reply::Fault(kind))) => {
+                    return Err(TargetQuiesceError::Fault(kind));
+                }
+                Ok(Ok(Reply::Quiesced)) if !quiesced => {
+                    quiesced = true;
+                }
+                Ok(message) => {
+                    unexpected.push(self::unexpected::<B>(message));
+                }
+                Err(err) if err.is_timeout() => {
+                    break;
+                }
+                Err(err) => {
+                    return Err(TargetQuiesceError::Receive(err));
+                }
+            }
+        }
+
+        if !unexpected.is_empty() {
+            return Err(TargetQuiesceError::UnexpectedMessages(unexpected));
+        }
+        if !quiesced {
+            return Err(TargetQuiesceError::NotQuiesced);
+        }
+
+        self.ping(timeout)
+            .map_err(|err| TargetQuiesceError::Ping(err))
+    }
+
+    /// Make sure the target is quiesced, if the test run is interrupted
+    ///
+    /// Registers `Quiesce` with `host_lib::signal`, so the target stops
+    /// whatever the test case started, even if the test case never ends. The
+    /// registration lasts until the returned `Registration` is dropped.
+    pub fn quiesce_on_interrupt(&self)
+        -> Result<Registration, TargetQuiesceOnInterruptError>
+    {
+        self.conn.send_on_interrupt(&B::request(Request::Quiesce))
+            .map_err(|err| TargetQuiesceOnInterruptError(err))
+    }
+
+    /// Instruct the target to set a GPIO pin high
+    pub fn set_pin_high(&mut self, pin: pin::PortPin)
+        -> Result<(), TargetSetPinHighError>
+    {
+        Pin::new(pin)
+            .set_level::<B::Request<'_>>(
+                pin::Level::High,
+                &mut self.conn,
+            )
+            .map_err(|err| TargetSetPinHighError(err))
+    }
+
+    /// Instruct the target to set a GPIO pin low
+    pub fn set_pin_low(&mut self, pin: pin::PortPin)
+        -> Result<(), TargetSetPinLowError>
+    {
+        Pin::new(pin)
+            .set_level::<B::Request<'_>>(
+                pin::Level::Low,
+                &mut self.conn,
+            )
+            .map_err(|err| TargetSetPinLowError(err))
+    }
+
+    /// Indicates whether a GPIO pin is set high
+    ///
+    /// Uses the read timeout (see [`Timeouts::read`]).
+    pub fn pin_is_high(&mut self, pin: pin::PortPin)
+        -> Result<bool, TargetPinReadError>
+    {
+        let level = self.read_pin(pin)?;
+        Ok(level == pin::Level::High)
+    }
+
+    /// Indicates whether a GPIO pin is set low
+    ///
+    /// Uses the read timeout (see [`Timeouts::read`]).
+    pub fn pin_is_low(&mut self, pin: pin::PortPin)
+        -> Result<bool, TargetPinReadError>
+    {
+        let level = self.read_pin(pin)?;
+        Ok(level == pin::Level::Low)
+    }
+
+    fn read_pin(&mut self, pin: pin::PortPin)
+        -> Result<pin::Level, ReadLevelError>
+    {
+        let timeout = self.timeouts.read();
+
+        self.retry(
+            |target| {
+                let (level, _) = Pin::new(pin)
+                    .read_level::<B::Request<'_>, B::Reply<'_>>(
+                        timeout,
+                        &mut target.conn,
+                    )?;
+                Ok(level)
+            },
+            |err| matches!(
+                err,
+                ReadLevelError::Receive(err) if err.is_corrupted()
+            ),
+        )
+    }
+
+    /// Run `op`, and retry it, if it fails because of a corrupted frame
+    ///
+    /// Works like [`Conn::retry`], for operations that need all of the target.
+    fn retry<T, E>(&mut self,
+        mut op:    impl FnMut(&mut Self) -> Result<T, E>,
+        corrupted: impl Fn(&E) -> bool,
+    )
+        -> Result<T, E>
+    {
+        let mut attempt = 0;
+
+        loop {
+            match op(self) {
+                Err(err) if corrupted(&err) && self.conn.recover(attempt) => {
+                    attempt += 1;
+                }
+                result => {
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Instruct the target to send this message via USART
+    pub fn send_usart(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Regular)
+    }
+
+    /// Instruct the target to send this message via USART using DMA
+    pub fn send_usart_dma(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Dma)
+    }
+
+    /// Instruct the target to send this message via synchronous USART
+    pub fn send_usart_sync(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::Sync)
+    }
+
+    /// Instruct the target to send this message via USART with flow control
+    pub fn send_usart_with_flow_control(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.send_usart_inner(data, UsartMode::FlowControl)
+    }
+
+    fn send_usart_inner(&mut self, data: &[u8], mode: UsartMode)
+        -> Result<(), TargetUsartSendError>
+    {
+        self.conn
+            .send(&B::request(Request::SendUsart { mode, data }))
+            .map_err(|err| TargetUsartSendError(err))
+    }
+
+    /// Wait to receive the provided data via USART
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    pub fn wait_for_usart_rx(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Regular)
+    }
+
+    /// Wait to receive the provided data via USART/DMA
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    pub fn wait_for_usart_rx_dma(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Dma)
+    }
+
+    /// Wait to receive the provided data via synchronous USART
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    pub fn wait_for_usart_rx_sync(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Sync)
+    }
+
+    fn wait_for_usart_rx_inner(&mut self,
+        data:          &[u8],
+        timeout:       Duration,
+        expected_mode: UsartMode,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        loop {
+            if buf.windows(data.len()).any(|window| window == data) {
+                return Ok(buf);
+            }
+            if start.elapsed() > timeout {
+                return Err(TargetUsartWaitError::Timeout);
+            }
+
+            // Other messages might arrive in the meantime. Leave them for
+            // whoever expects them.
+            let mut tmp = Vec::new();
+            let message = self.conn
+                .receive_matching::<B::Reply<'_>>(timeout, &mut tmp, |frame| {
+                    matches!(
+                        conn::peek(frame).map(B::reply),
+                        Some(Ok(Reply::UsartReceive { mode, .. }))
+                            if mode == expected_mode
+                    )
+                })
+                .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+            let reply = B::reply(message);
+        match reply {
+                Ok(Reply::UsartReceive { mode, data })
+                    if mode == expected_mode =>
+                {
+                    buf.extend(data)
+                }
+                message => {
+                    return Err(
+                        TargetUsartWaitError::UnexpectedMessage(
+                            unexpected::<B>(message)
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    /// Measure the throughput of the target's USART driver
+    ///
+    /// Instructs the target to send `bytes` bytes in regular mode, as fast as
+    /// it can, passing `chunk_size` bytes at a time to its driver. Meanwhile,
+    /// the assistant counts and timestamps the data it receives. Returns the
+    /// assistant's count, which includes the throughput and the number of
+    /// receive errors. See [`usart::Count`].
+    ///
+    /// `timeout` applies to each step, so it needs to cover the time it takes
+    /// to send all of the data.
+    pub fn measure_usart_throughput(&mut self,
+        assistant:  &mut Assistant,
+        bytes:      u32,
+        chunk_size: u16,
+        timeout:    Duration,
+    )
+        -> Result<usart::Count, TargetUsartThroughputError>
+    {
+        assistant.start_usart_count(timeout)
+            .map_err(|err| TargetUsartThroughputError::Assistant(err))?;
+
+        let result = self.send_usart_throughput(bytes, chunk_size, timeout);
+
+        // Stop counting in any case, so the assistant receives data as usual
+        // again.
+        let count = assistant.stop_usart_count(timeout)
+            .map_err(|err| TargetUsartThroughputError::Assistant(err))?;
+
+        result.map(|()| count)
+    }
+
+    fn send_usart_throughput(&mut self,
+        bytes:      u32,
+        chunk_size: u16,
+        timeout:    Duration,
+    )
+        -> Result<(), TargetUsartThroughputError>
+    {
+        let request = Request::StartUsartThroughputTest { bytes, chunk_size };
+        self.conn
+            .send(&B::request(request))
+            .map_err(|err| TargetUsartThroughputError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_matching::<B::Reply<'_>>(timeout, &mut tmp, |frame| {
+                matches!(
+                    conn::peek(frame).map(B::reply),
+                    Some(Ok(Reply::UsartThroughputSent))
+                )
+            })
+            .map_err(|err| TargetUsartThroughputError::Receive(err))?;
+
+        let reply = B::reply(message);
+        match reply {
+            Ok(Reply::UsartThroughputSent) => {
+                Ok(())
+            }
+            message => {
+                Err(
+                    TargetUsartThroughputError::UnexpectedMessage(
+                        unexpected::<B>(message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Start a timer interrupt with the given period
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `period` doesn't fit into `Microseconds`.
+    pub fn start_timer_interrupt(&mut self, period: Duration)
+        -> Result<TimerInterrupt<'_, B>, TargetStartTimerInterruptError>
+    {
+        let period = Microseconds::try_from(period)
+            .expect("Timer interrupt period too long");
+
+        self.conn
+            .send(&B::request(Request::StartTimerInterrupt { period }))
+            .map_err(|err| TargetStartTimerInterruptError(err))?;
+
+        Ok(TimerInterrupt(self))
+    }
+}
+
+
+/// Recognize log records sent by the target
+///
+/// Passed to [`Conn::set_log_filter`].
+pub fn log_record<B: Board>(frame: &[u8]) -> Option<log::Record> {
+    match B::reply(conn::peek::<B::Reply<'_>>(frame)?) {
+        Ok(Reply::Log { level, message }) => {
+            Some(
+                log::Record {
+                    level,
+                    message: message.to_owned(),
+                }
+            )
+        }
+        _ => {
+            None
+        }
+    }
+}
+
+/// Recognize defmt frames sent by the target
+///
+/// Passed to [`Conn::set_defmt_filter`].
+pub fn defmt_frames<B: Board>(frame: &[u8]) -> Option<Vec<u8>> {
+    match B::reply(conn::peek::<B::Reply<'_>>(frame)?) {
+        Ok(Reply::Defmt(frames)) => Some(frames.to_vec()),
+        _                        => None,
+    }
+}
+
+/// Recognize reports of dropped data sent by the target
+///
+/// Passed to [`Conn::set_overrun_filter`].
+pub fn buffer_overrun<B: Board>(frame: &[u8]) -> Option<u32> {
+    match B::reply(conn::peek::<B::Reply<'_>>(frame)?) {
+        Ok(Reply::BufferOverrun { dropped }) => Some(dropped),
+        _                                    => None,
+    }
+}
+
+/// Recognize panic reports sent by the target
+///
+/// Passed to [`Conn::set_panic_filter`].
+pub fn panic_report<B: Board>(frame: &[u8]) -> Option<String> {
+    match B::reply(conn::peek::<B::Reply<'_>>(frame)?) {
+        Ok(Reply::Panic(report)) => Some(report.to_string()),
+        _                        => None,
+    }
+}
+
+/// Recognize heartbeats sent by the target
+///
+/// Passed to [`Conn::set_heartbeat_filter`], by test suites whose target
+/// firmware sends heartbeats.
+pub fn heartbeat<B: Board>(frame: &[u8]) -> bool {
+    match conn::peek::<B::Reply<'_>>(frame).map(B::reply) {
+        Some(Ok(Reply::Heartbeat)) => true,
+        _                          => false,
+    }
+}
+
+/// Encode the ping that resynchronizes the target, after a corrupted frame
+///
+/// Passed to [`Conn::set_resync_frame`].
+pub fn resync_frame<B: Board>(checksum: Checksum) -> Option<Vec<u8>> {
+    conn::encode(&B::request(Request::Ping), checksum).ok()
+}
+
+/// Decode the messages exchanged with the target, for recordings
+///
+/// Passed to [`Conn::set_record_decoder`].
+pub fn decode_message<B: Board>(direction: Direction, frame: &[u8])
+    -> Option<String>
+{
+    match direction {
+        Direction::Send => {
+            conn::peek::<B::Request<'_>>(frame)
+                .map(|message| format!("{:?}", message))
+        }
+        Direction::Receive => {
+            conn::peek::<B::Reply<'_>>(frame)
+                .map(|message| format!("{:?}", message))
+        }
+        Direction::Sync => {
+            None
+        }
+    }
+}
+
+/// Describe a message that wasn't expected, for an error
+///
+/// Replies that [`Target`] understands have already been converted at this
+/// point, but the original message is more useful in an error.
+fn unexpected<B: Board>(message: Result<Reply<'_, B>, B::Reply<'_>>)
+    -> String
+{
+    match message {
+        Ok(reply)    => format!("{:?}", reply),
+        Err(message) => format!("{:?}", message),
+    }
+}
+
+
+/// The version of the firmware running on the target
+#[derive(Debug)]
+pub struct Version {
+    /// The protocol version the firmware was built with
+    ///
+    /// Needs to match the `PROTOCOL_VERSION` of the test stand's messages.
+    pub protocol: u32,
+
+    /// Identifies the revision of the firmware source code
+    pub firmware_hash: String,
+
+    /// The version of the HAL that the firmware was built with
+    pub hal_version: String,
+
+    /// The Cargo features that the firmware was built with
+    pub features: Vec<String>,
+}
+
+impl Version {
+    /// Indicates whether the target runs the safe mode firmware
+    ///
+    /// The safe mode firmware only supports a few basic requests, like `ping`,
+    /// `reset`, and `flash_info`. Most test cases fail against it.
+    pub fn is_safe_mode(&self) -> bool {
+        self.features.iter().any(|feature| feature == "safe-mode")
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (protocol {:08x}, HAL {}, features: {})",
+            self.firmware_hash,
+            self.protocol,
+            self.hal_version,
+            self.features.join(", "),
+        )
+    }
+}
+
+
+/// A connection to a test target, that has [`Timeouts`]
+///
+/// Implemented by [`Target`], and by the types that test suites wrap it in, so
+/// [`WithTimeouts`] can dereference to those.
+pub trait HasTimeouts {
+    fn timeouts_mut(&mut self) -> &mut Timeouts;
+}
+
+impl<B> HasTimeouts for Target<B> {
+    fn timeouts_mut(&mut self) -> &mut Timeouts {
+        &mut self.timeouts
+    }
+}
+
+
+/// Replaces the timeouts of a target, until it is dropped
+///
+/// Returned by [`Target::with_timeouts`]. Dereferences to the target.
+pub struct WithTimeouts<'r, T: HasTimeouts> {
+    target:   &'r mut T,
+    previous: Timeouts,
+}
+
+impl<'r, T> WithTimeouts<'r, T>
+    where T: HasTimeouts
+{
+    /// Replace the timeouts of `target` with the ones returned by `f`
+    ///
+    /// For test suites that wrap [`Target`]. Test cases use
+    /// [`Target::with_timeouts`].
+    pub fn new(target: &'r mut T, f: impl FnOnce(Timeouts) -> Timeouts)
+        -> Self
+    {
+        let previous = target.timeouts_mut().clone();
+        *target.timeouts_mut() = f(previous.clone());
+
+        Self {
+            target,
+            previous,
+        }
+    }
+}
+
+impl<T> Deref for WithTimeouts<'_, T>
+    where T: HasTimeouts
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.target
+    }
+}
+
+impl<T> DerefMut for WithTimeouts<'_, T>
+    where T: HasTimeouts
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.target
+    }
+}
+
+impl<T> Drop for WithTimeouts<'_, T>
+    where T: HasTimeouts
+{
+    fn drop(&mut self) {
+        *self.target.timeouts_mut() = mem::take(&mut self.previous);
+    }
+}
+
+
+/// Represent a timer interrupt that's currently configured on the target
+///
+/// This timer interrupt will be stopped when this struct is dropped.
+pub struct TimerInterrupt<'r, B: Board>(&'r mut Target<B>);
+
+impl<B> Drop for TimerInterrupt<'_, B>
+    where B: Board
+{
+    fn drop(&mut self) {
+        (self.0).conn.send(&B::request(Request::StopTimerInterrupt))
+            .unwrap()
+    }
+}
+
+
+#[derive(Debug)]
+pub enum TargetCapabilitiesError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetNegotiateCompressionError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetNegotiateChecksumError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetRenegotiateError {
+    Compression(TargetNegotiateCompressionError),
+    Checksum(TargetNegotiateChecksumError),
+}
+
+#[derive(Debug)]
+pub enum TargetIrqStateError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetPingError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetQuiesceError<Fault> {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+
+    /// The target reported a fault that the test case didn't expect
+    Fault(Fault),
+
+    /// The target sent messages that the test case didn't receive
+    UnexpectedMessages(Vec<String>),
+
+    /// The target didn't reply to `Quiesce`
+    NotQuiesced,
+
+    /// The target didn't answer the final ping
+    Ping(TargetPingError),
+}
+
+#[derive(Debug)]
+pub struct TargetQuiesceOnInterruptError(pub ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetVersionError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub struct TargetSetPinHighError(pub ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetSetPinLowError(pub ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetPinReadError(pub ReadLevelError);
+
+impl From<ReadLevelError> for TargetPinReadError {
+    fn from(err: ReadLevelError) -> Self {
+        Self(err)
+    }
+}
+
+#[derive(Debug)]
+pub struct TargetUsartSendError(pub ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetUsartWaitError {
+    Receive(ConnReceiveError),
+    Timeout,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum TargetUsartThroughputError {
+    Assistant(AssistantError),
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub struct TargetStartTimerInterruptError(pub ConnSendError);
+
+/// Returned by the methods generated by `test-stand-derive`
+///
+/// Those send a request, and wait for a specific reply. See the `Requests`
+/// derive macro.
+#[derive(Debug)]
+pub enum TargetRequestError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
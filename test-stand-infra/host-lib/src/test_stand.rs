@@ -1,23 +1,72 @@
-use std::sync::{
-    LockResult,
-    Mutex,
-    MutexGuard,
+use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
+    io,
+    path::PathBuf,
+    sync::{
+        LockResult,
+        Mutex,
+        MutexGuard,
+    },
 };
 
 use lazy_static::lazy_static;
+use protocol::{
+    checksum::Checksum,
+    compress::Compression,
+    flow,
+};
 
 use crate::{
-    assistant::Assistant,
+    assistant::{
+        Assistant,
+        AssistantError,
+    },
     config::{
         Config,
         ConfigReadError,
+        Features,
+        Timeouts,
     },
     conn::{
         Conn,
         ConnInitError,
+        ConnResyncError,
+    },
+    flash::{
+        self,
+        EmbedError,
+    },
+    lease,
+    lock::{
+        self,
+        DeviceLock,
+    },
+    record::Recorder,
+    serial::{
+        Serial,
+        SerialInitError,
+    },
+    signal,
+    supervisor,
+    tolerances::Tolerances,
+    topology::Topology,
+    trace::{
+        self,
+        TraceInitError,
     },
 };
 
+#[cfg(feature = "defmt")]
+use crate::defmt::{
+    self,
+    DefmtError,
+};
+#[cfg(feature = "flash")]
+use crate::flash::DownloadError;
+
 
 /// An instance of the test stand
 ///
@@ -38,13 +87,151 @@ pub struct TestStand {
     /// Connection to the test assistant
     ///
     /// This field will be `Err`, if the test assistant has not been specified
-    /// in the configuration file.
+    /// in the configuration file. If only a USB/serial adapter (`serial`) has
+    /// been specified, the assistant runs in lite mode.
     pub assistant: Result<Assistant, NotConfiguredError>,
+
+    /// Connections to the additional test targets, by name
+    ///
+    /// Contains all targets from the `[targets]` table of the configuration
+    /// file. Empty, if there is no such table.
+    pub targets: BTreeMap<String, Conn>,
+
+    /// Connections to the additional test assistants, by name
+    ///
+    /// Contains all assistants from the `[assistants]` table of the
+    /// configuration file. Compression and checksum have already been
+    /// negotiated with them, like with `assistant`.
+    pub assistants: BTreeMap<String, Assistant>,
+
+    /// The direct connections between test nodes
+    ///
+    /// Contains all links from the `[[links]]` array of the configuration
+    /// file. Every test node they name has been configured.
+    pub links: Topology,
+
+    /// Connection to the USB/serial converter
+    ///
+    /// This field will be `Err`, if the converter has not been specified in
+    /// the configuration file, or if it is used by the assistant in lite mode.
+    pub serial: Result<Serial, NotConfiguredError>,
+
+    /// The compression scheme from the configuration file
+    ///
+    /// Compression has already been negotiated with the test assistant. Test
+    /// suites need to negotiate it with the test target themselves, as only
+    /// they know the target's protocol.
+    pub compression: Compression,
+
+    /// The checksum from the configuration file
+    ///
+    /// Like the compression scheme, this has already been negotiated with the
+    /// test assistant, but not with the test target.
+    pub checksum: Checksum,
+
+    /// The frequency of the reference clock from the configuration file
+    ///
+    /// This is `None`, if no reference clock is connected to the assistant.
+    pub reference_hz: Option<f64>,
+
+    /// The tolerances from the configuration file
+    pub tolerances: Tolerances,
+
+    /// The timeouts from the configuration file
+    ///
+    /// Negotiating with the test assistant already used these. Test suites
+    /// should use them for negotiating with the test target too.
+    pub timeouts: Timeouts,
+
+    /// The features of the test stand, from the configuration file
+    pub features: Features,
+
+    /// The profile from the `[stands]` table that has been applied, if any
+    pub stand: Option<String>,
+
+    /// Keeps the subscriber for this test case's log active
+    ///
+    /// See the [`trace`] module.
+    pub trace: trace::Guard,
+
+    /// Guarantees exclusive access to the devices of the test stand
+    ///
+    /// Unlike `guard`, this keeps other processes out. See the [`lock`]
+    /// module. Must not be dropped before the connections have been closed,
+    /// or another process might fail to open them.
+    pub locks: Vec<DeviceLock>,
 }
 
 impl TestStand {
     /// Create a new instance of `TestStand`
+    ///
+    /// Reads the configuration from `test-stand.toml`. Use
+    /// [`TestStand::builder`] for more control over the initialization.
     pub fn new() -> Result<Self, TestStandInitError> {
+        Self::builder().build()
+    }
+
+    /// Start building a `TestStand` instance
+    pub fn builder() -> TestStandBuilder {
+        TestStandBuilder {
+            config_path:       PathBuf::from(Config::DEFAULT_PATH),
+            require_assistant: false,
+            require_features:  Vec::new(),
+            firmware:          Vec::new(),
+        }
+    }
+}
+
+
+/// Builds a [`TestStand`] instance
+///
+/// Returned by [`TestStand::builder`].
+pub struct TestStandBuilder {
+    config_path:       PathBuf,
+    require_assistant: bool,
+    require_features:  Vec<&'static str>,
+    firmware:          Vec<PathBuf>,
+}
+
+impl TestStandBuilder {
+    /// Read the configuration from `path`, instead of `test-stand.toml`
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = path.into();
+        self
+    }
+
+    /// Fail, if no test assistant has been configured
+    ///
+    /// An assistant in lite mode satisfies this requirement.
+    pub fn require_assistant(mut self) -> Self {
+        self.require_assistant = true;
+        self
+    }
+
+    /// Fail, if the test stand doesn't have the feature `name`
+    ///
+    /// Features are configured in the `[features]` table of the configuration
+    /// file. See [`Features`].
+    pub fn require_feature(mut self, name: &'static str) -> Self {
+        self.require_features.push(name);
+        self
+    }
+
+    /// Download firmware to its test node, before connecting to it
+    ///
+    /// `path` is the directory of the firmware crate. See [`flash::embed`]
+    /// for details. Every firmware is only downloaded once per test run, even
+    /// if multiple test cases request it.
+    ///
+    /// Firmware that is listed in the `[flash]` table of the configuration
+    /// file is downloaded without calling this. See [`flash`].
+    pub fn flash_firmware(mut self, path: impl Into<PathBuf>) -> Self {
+        self.firmware.push(path.into());
+        self
+    }
+
+    /// Initialize the test stand
+    pub fn build(self) -> Result<TestStand, TestStandInitError> {
         // By default, Rust runs tests in parallel on multiple threads. This can
         // be controlled through a command-line argument and an environment
         // variable, but there doesn't seem to be a way to configure this in
@@ -60,46 +247,334 @@ impl TestStand {
         // so is not necessary, as the error case just tells us that another
         // thread holding this lock panicked. We don't care about that, as the
         // mutex is still acquired in that case.
-        lazy_static! { static ref MUTEX: Mutex<()> = Mutex::new(()); }
+        lazy_static! {
+            static ref MUTEX:   Mutex<()>                = Mutex::new(());
+            static ref FLASHED: Mutex<BTreeSet<Flashed>> =
+                Mutex::new(BTreeSet::new());
+        }
         let guard = MUTEX.lock();
 
-        let config = Config::read()
+        // Without this, an interrupted test run would exit without cleaning
+        // up. See the `signal` module.
+        signal::install()
+            .map_err(|err| TestStandInitError::Signal(err))?;
+
+        let config = Config::read_from(&self.config_path)
             .map_err(|err| TestStandInitError::ConfigRead(err))?;
 
+        // Otherwise, the test suite would silently run against whatever
+        // firmware the test nodes have.
+        if cfg!(not(feature = "flash")) && !config.flash.is_empty() {
+            return Err(TestStandInitError::FlashNotEnabled);
+        }
+
+        // Otherwise, the logs of the test nodes would silently go missing.
+        if cfg!(not(feature = "defmt")) && !config.defmt.is_empty() {
+            return Err(TestStandInitError::DefmtNotEnabled);
+        }
+
+        for &name in &self.require_features {
+            if !config.features.has(name) {
+                return Err(
+                    TestStandInitError::NotConfigured(NotConfiguredError(name))
+                );
+            }
+        }
+
+        // Installed before anything else happens, so the log covers it.
+        let trace = trace::init(config.artifacts.as_deref())
+            .map_err(|err| TestStandInitError::Trace(err))?;
+
+        // Acquired before downloading any firmware, as that would disrupt
+        // another process that is using the test stand. Whoever leased a
+        // connection holds the lock on its device already. See the `lease`
+        // module.
+        let addresses = config.target.iter()
+            .chain(&config.assistant)
+            .chain(&config.serial)
+            .chain(config.targets.values())
+            .chain(config.assistants.values())
+            .filter(|address| lease::get(address).is_none())
+            .map(|address| address.as_str());
+        let locks = lock::acquire_all(addresses)
+            .map_err(|err| TestStandInitError::Lock(err))?;
+
+        // A supervisor has downloaded the firmware already, before it started
+        // this process. See the `supervisor` module.
+        let supervised = supervisor::is_supervised();
+
+        // We're holding the test stand mutex, so no other thread is accessing
+        // this. Ignore poisoning, for the same reason as above.
+        let mut flashed = FLASHED.lock()
+            .unwrap_or_else(|err| err.into_inner());
+        for path in self.firmware {
+            if supervised || flashed.contains(&Flashed::Crate(path.clone())) {
+                continue;
+            }
+
+            flash::embed(&path)
+                .map_err(|err| TestStandInitError::Embed(err))?;
+            flashed.insert(Flashed::Crate(path));
+        }
+
+        let recorder = match (config.record, &config.artifacts) {
+            (true, Some(artifacts)) => {
+                let path = trace::artifact_path(artifacts, "jsonl")
+                    .map_err(|err| TestStandInitError::Record(err))?;
+                let recorder = Recorder::create(path)
+                    .map_err(|err| TestStandInitError::Record(err))?;
+                Some(recorder)
+            }
+            (true, None) => {
+                return Err(
+                    TestStandInitError::NotConfigured(
+                        NotConfiguredError("artifacts")
+                    )
+                );
+            }
+            (false, _) => {
+                None
+            }
+        };
+
+        let chunk_size = config.chunk_size;
+        // `node` is only needed for the optional `[defmt]` and `[flash]`
+        // tables.
+        #[cfg_attr(
+            not(any(feature = "defmt", feature = "flash")),
+            allow(unused_variables)
+        )]
+        let mut open = |node: &str, path: &str| {
+            let address  = lease::get(path).unwrap_or_else(|| path.to_owned());
+            let mut conn = Conn::with_port(&address, config.port(path))
+                .map_err(|err| TestStandInitError::ConnInit(err))?;
+            if let Some(size) = chunk_size {
+                conn.set_chunk_size(size);
+            }
+            if let Some(recorder) = &recorder {
+                conn.set_recorder(recorder.clone());
+            }
+            if let Some(retry) = &config.retry {
+                conn.set_retry(retry.clone());
+            }
+            #[cfg(feature = "defmt")]
+            if let Some(elf) = config.defmt.get(node) {
+                let decoder = defmt::Decoder::from_elf(elf)
+                    .map_err(|err| TestStandInitError::Defmt(err))?;
+                conn.set_defmt_decoder(decoder);
+            }
+
+            // The connection is opened first, so the firmware can't announce
+            // itself before anyone is listening.
+            #[cfg(feature = "flash")]
+            if let Some(settings) = config.flash.get(node) {
+                let firmware = Flashed::Node(node.to_owned());
+                if !supervised && !flashed.contains(&firmware) {
+                    flash::download(node, settings)
+                        .map_err(|err| TestStandInitError::Download(err))?;
+                    conn.resync(config.timeouts.restart())
+                        .map_err(|err| TestStandInitError::Restart(err))?;
+                    flashed.insert(firmware);
+                }
+            }
+
+            Ok(conn)
+        };
+
         let mut target    = Err(NotConfiguredError("target"));
         let mut assistant = Err(NotConfiguredError("assistant"));
+        let mut serial    = Err(NotConfiguredError("serial"));
 
-        if let Some(path) = config.target {
-            target = Ok(
-                Conn::new(&path)
-                    .map_err(|err| TestStandInitError::ConnInit(err))?
-            );
+        if let Some(path) = &config.target {
+            target = Ok(open("target", path)?);
         }
-        if let Some(path) = config.assistant {
-            let conn = Conn::new(&path)
-                .map_err(|err| TestStandInitError::ConnInit(err))?;
+        if let Some(path) = &config.assistant {
+            let conn = open("assistant", path)?;
             assistant = Ok(Assistant::new(conn));
         }
+        if let Some(path) = &config.serial {
+            let serial_conn = Serial::with_port(path, &config.port(path))
+                .map_err(|err| TestStandInitError::SerialInit(err))?;
+
+            // Without a test assistant, the USB/serial converter takes its
+            // place.
+            if assistant.is_err() {
+                assistant = Ok(Assistant::new_lite(serial_conn));
+            }
+            else {
+                serial = Ok(serial_conn);
+            }
+        }
+
+        if self.require_assistant {
+            if let Err(err) = assistant {
+                return Err(TestStandInitError::NotConfigured(err));
+            }
+        }
+
+        let mut targets = BTreeMap::new();
+        for (name, path) in &config.targets {
+            let conn = open(name, path)?;
+            targets.insert(name.clone(), conn);
+        }
+
+        let mut assistants = BTreeMap::new();
+        for (name, path) in &config.assistants {
+            let conn = open(name, path)?;
+            assistants.insert(name.clone(), Assistant::new(conn));
+        }
+
+        // `Config` has already made sure that every entry names an assistant.
+        for (name, interlock) in &config.voltage {
+            let assistant = match name.as_str() {
+                "assistant" => assistant.as_mut().ok(),
+                name        => assistants.get_mut(name),
+            };
+            if let Some(assistant) = assistant {
+                assistant.set_interlock(interlock.clone());
+            }
+        }
+
+        // A typo in a node name would otherwise just skip the test cases that
+        // use the link.
+        for link in config.links.links() {
+            for node in &link.nodes {
+                let configured = match node.as_str() {
+                    "target"    => target.is_ok(),
+                    "assistant" => assistant.is_ok(),
+                    name        => {
+                        targets.contains_key(name)
+                            || assistants.contains_key(name)
+                    }
+                };
+                if !configured {
+                    return Err(TestStandInitError::UnknownNode(node.clone()));
+                }
+            }
+        }
+
+        let compression = config.compression.unwrap_or_default();
+        let checksum    = config.checksum.unwrap_or_default();
+
+        let all_assistants = assistant.iter_mut()
+            .chain(assistants.values_mut());
+        for assistant in all_assistants {
+            negotiate(assistant, compression, checksum, &config.timeouts)
+                .map_err(|err| TestStandInitError::Assistant(err))?;
+        }
 
         Ok(
-            Self {
+            TestStand {
                 guard,
                 target,
                 assistant,
+                targets,
+                assistants,
+                links: config.links,
+                serial,
+                compression,
+                checksum,
+                reference_hz: config.reference_hz,
+                tolerances:   config.tolerances,
+                timeouts:     config.timeouts,
+                features:     config.features,
+                stand:        config.stand,
+                trace,
+                locks,
             },
         )
     }
 }
 
 
+/// Firmware that has been downloaded during this test run
+#[derive(Eq, Ord, PartialEq, PartialOrd)]
+enum Flashed {
+    /// A firmware crate, built and downloaded by [`flash::embed`]
+    Crate(PathBuf),
+
+    /// The firmware of a test node, downloaded by [`flash::download`]
+    #[cfg(feature = "flash")]
+    Node(String),
+}
+
+
+/// Negotiate compression scheme and checksum with a test assistant
+fn negotiate(
+    assistant:   &mut Assistant,
+    compression: Compression,
+    checksum:    Checksum,
+    timeouts:    &Timeouts,
+)
+    -> Result<(), AssistantError>
+{
+    if compression != Compression::None {
+        assistant.negotiate_compression(compression, timeouts.negotiate())?;
+    }
+    if checksum != Checksum::None {
+        assistant.negotiate_checksum(checksum, timeouts.negotiate())?;
+    }
+
+    assistant.enable_flow_control(flow::DEFAULT_WINDOW)?;
+
+    Ok(())
+}
+
+
 /// Error initializing the test stand
 #[derive(Debug)]
 pub enum TestStandInitError {
+    /// Error negotiating with the test assistant
+    Assistant(AssistantError),
+
     /// Error reading configuration
     ConfigRead(ConfigReadError),
 
     /// Error initializing a serial connection
     ConnInit(ConnInitError),
+
+    /// Error reading the ELF file to decode defmt logs with
+    #[cfg(feature = "defmt")]
+    Defmt(DefmtError),
+
+    /// The configuration file has a `[defmt]` table, but host-lib has been
+    /// built without the `defmt` feature
+    DefmtNotEnabled,
+
+    /// Error downloading firmware through a debug probe
+    #[cfg(feature = "flash")]
+    Download(DownloadError),
+
+    /// Error building and downloading firmware
+    Embed(EmbedError),
+
+    /// The configuration file has a `[flash]` table, but host-lib has been
+    /// built without the `flash` feature
+    FlashNotEnabled,
+
+    /// Error acquiring the lock on a device
+    Lock(io::Error),
+
+    /// A required test node has not been configured
+    NotConfigured(NotConfiguredError),
+
+    /// Error creating the recording of the test case
+    Record(io::Error),
+
+    /// A test node didn't start up, after firmware was downloaded to it
+    Restart(ConnResyncError),
+
+    /// Error opening the USB/serial converter
+    SerialInit(SerialInitError),
+
+    /// Error installing the signal handler
+    Signal(io::Error),
+
+    /// Error setting up the log of the test case
+    Trace(TraceInitError),
+
+    /// A link in the configuration file names a test node that doesn't exist
+    UnknownNode(String),
 }
 
 /// The resource you tried to access was not specified in the configuration file
@@ -0,0 +1,31 @@
+//! Support for timestamps taken by test nodes
+//!
+//! Test nodes timestamp events, like received data or pin interrupts, using a
+//! free-running 32-bit timer that counts microseconds. Measuring the time
+//! between two events from their timestamps is far more accurate than
+//! measuring it on the host, where serial latency dominates.
+//!
+//! The timer wraps around after about 71 minutes. Only timestamps from the
+//! same test node can be compared.
+
+
+use std::time::Duration;
+
+
+/// Compute the time between two timestamps
+///
+/// `later` needs to have been taken after `earlier`, but less than one
+/// wrap-around of the timer after it. The timer wrapping around in between is
+/// taken into account.
+pub fn interval(earlier: u32, later: u32) -> Duration {
+    Duration::from_micros(later.wrapping_sub(earlier).into())
+}
+
+/// Compute the time between two timestamps in timer ticks
+///
+/// Like [`interval`], but for timestamps from a timer that runs at `timer_hz`,
+/// instead of counting microseconds.
+pub fn interval_ticks(earlier: u32, later: u32, timer_hz: u32) -> Duration {
+    let ticks = later.wrapping_sub(earlier);
+    Duration::from_secs_f64(ticks as f64 / timer_hz as f64)
+}
@@ -0,0 +1,87 @@
+//! Per-stand tolerances for timing and analog assertions
+//!
+//! Test stands differ in their hardware. One might use a cheap oscillator, or
+//! long wires with a lot of capacitance. Tolerances are configured per stand,
+//! in the `tolerances` section of `test-stand.toml`, so one stand's looser
+//! hardware doesn't require loosening the assertions for every stand.
+
+
+use serde::Deserialize;
+
+
+/// Default for [`Tolerances::clock_ppm`]
+pub const DEFAULT_CLOCK_PPM: f64 = 10_000.0;
+
+/// Default for [`Tolerances::adc_offset_mv`]
+pub const DEFAULT_ADC_OFFSET_MV: f64 = 12.5;
+
+/// Default for [`Tolerances::adc_full_scale_error_mv`]
+pub const DEFAULT_ADC_FULL_SCALE_ERROR_MV: f64 = 102.0;
+
+/// Default for [`Tolerances::gpio_output_latency_ms`]
+pub const DEFAULT_GPIO_OUTPUT_LATENCY_MS: u64 = 50;
+
+/// Default for [`Tolerances::gpio_input_latency_ms`]
+pub const DEFAULT_GPIO_INPUT_LATENCY_MS: u64 = 50;
+
+/// Default for [`Tolerances::notification_latency_ms`]
+pub const DEFAULT_NOTIFICATION_LATENCY_MS: u64 = 20;
+
+
+/// The `tolerances` section of the configuration file
+///
+/// All tolerances are optional. Use the accessors to get the configured value,
+/// or the default, if a tolerance is not configured.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tolerances {
+    clock_ppm:               Option<f64>,
+    adc_offset_mv:           Option<f64>,
+    adc_full_scale_error_mv: Option<f64>,
+    gpio_output_latency_ms:  Option<u64>,
+    gpio_input_latency_ms:   Option<u64>,
+    notification_latency_ms: Option<u64>,
+}
+
+impl Tolerances {
+    /// Deviation of a test node's clock from its nominal frequency, in ppm
+    ///
+    /// The default allows for internal oscillators. Stands whose test nodes
+    /// run from a crystal can configure a much tighter value.
+    pub fn clock_ppm(&self) -> f64 {
+        self.clock_ppm.unwrap_or(DEFAULT_CLOCK_PPM)
+    }
+
+    /// Deviation of an ADC reading at 0 V, in mV
+    pub fn adc_offset_mv(&self) -> f64 {
+        self.adc_offset_mv.unwrap_or(DEFAULT_ADC_OFFSET_MV)
+    }
+
+    /// Deviation of an ADC reading at the reference voltage, in mV
+    ///
+    /// Includes both the offset and the gain error of the ADC, as well as any
+    /// voltage drop in the wiring.
+    pub fn adc_full_scale_error_mv(&self) -> f64 {
+        self.adc_full_scale_error_mv
+            .unwrap_or(DEFAULT_ADC_FULL_SCALE_ERROR_MV)
+    }
+
+    /// Latency of a GPIO output change, as observed by the host, in ms
+    pub fn gpio_output_latency_ms(&self) -> u64 {
+        self.gpio_output_latency_ms.unwrap_or(DEFAULT_GPIO_OUTPUT_LATENCY_MS)
+    }
+
+    /// Latency of a GPIO input change, as observed by the host, in ms
+    pub fn gpio_input_latency_ms(&self) -> u64 {
+        self.gpio_input_latency_ms.unwrap_or(DEFAULT_GPIO_INPUT_LATENCY_MS)
+    }
+
+    /// Deadline for delivering a notification to the host, in ms
+    ///
+    /// Measured from when the test node timestamped the event. See
+    /// [`crate::latency`].
+    pub fn notification_latency_ms(&self) -> u64 {
+        self.notification_latency_ms
+            .unwrap_or(DEFAULT_NOTIFICATION_LATENCY_MS)
+    }
+}
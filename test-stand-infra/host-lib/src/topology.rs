@@ -0,0 +1,61 @@
+//! How the test nodes of a test stand are wired to each other
+//!
+//! Usually, every test target is only wired to a test assistant. Some stands
+//! also wire test targets directly to each other, to test whether two
+//! implementations of the same bus (for example from different HALs, or on
+//! different microcontrollers) interoperate. These connections are listed in
+//! the `[[links]]` array of `test-stand.toml`, so test cases can find out which
+//! targets they can use for such a scenario, and skip it, if there are none.
+
+
+use serde::Deserialize;
+
+
+/// The wiring between test nodes, as listed in the configuration file
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Topology {
+    links: Vec<Link>,
+}
+
+impl Topology {
+    /// Access all links
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// Find the first link that uses `bus`
+    pub fn find(&self, bus: Bus) -> Option<&Link> {
+        self.links.iter().find(|link| link.bus == bus)
+    }
+}
+
+
+/// A direct connection between two test nodes
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Link {
+    /// The bus that connects the test nodes
+    pub bus: Bus,
+
+    /// The names of the test nodes
+    ///
+    /// `target` and `assistant` refer to the main test nodes, any other name
+    /// to an entry of the `[targets]` or `[assistants]` table. For buses that
+    /// have dedicated roles, the first node has the master role.
+    pub nodes: [String; 2],
+}
+
+
+/// A bus that connects test nodes
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    /// USART, with each node's TX wired to the other node's RX
+    Usart,
+
+    /// I2C, with both nodes on the same bus
+    I2c,
+
+    /// SPI, with the first node as master, and the second as slave
+    Spi,
+}
@@ -0,0 +1,167 @@
+//! Logging of host-side activity, using `tracing`
+//!
+//! host-lib emits `tracing` events and spans as it talks to the test nodes:
+//!
+//! - Every operation of [`Assistant`] runs in a `debug` span, as do connecting
+//!   and resynchronizing a [`Conn`].
+//! - Every frame sent or received is a `trace` event, carrying the address of
+//!   the connection and the raw frame.
+//! - Log records from the firmware are events with the target `firmware`, at
+//!   the level the firmware logged them with.
+//!
+//! [`init`] installs a subscriber for the current test case. Which events it
+//! records is controlled by the `TEST_STAND_LOG` environment variable, which
+//! takes the same directives as `RUST_LOG` (for example `TEST_STAND_LOG=trace`,
+//! or `TEST_STAND_LOG=info,host_lib::conn=trace`). By default, warnings and
+//! firmware log records are recorded.
+//!
+//! [`Assistant`]: crate::Assistant
+//! [`Conn`]: crate::Conn
+
+
+use std::{
+    env,
+    fs::{
+        self,
+        File,
+    },
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Mutex,
+    thread,
+};
+
+use tracing::subscriber::DefaultGuard;
+use tracing_subscriber::EnvFilter;
+
+
+/// The environment variable that controls which events are recorded
+pub const FILTER_VAR: &str = "TEST_STAND_LOG";
+
+/// The filter that is used, if `FILTER_VAR` is not set
+pub const DEFAULT_FILTER: &str = "warn,firmware=trace";
+
+
+/// Install a subscriber for the current test case
+///
+/// If `artifacts` is `Some`, events are written to a log file in that
+/// directory, at `<test binary>/<test case>.log`. An existing log file of the
+/// same test case is replaced. Otherwise, events are written to the output of
+/// the test case, which the test harness only shows, if the test case fails.
+///
+/// The subscriber is only active on the current thread, until the returned
+/// [`Guard`] is dropped. [`TestStand::new`] calls this.
+///
+/// [`TestStand::new`]: crate::TestStand::new
+pub fn init(artifacts: Option<&Path>) -> Result<Guard, TraceInitError> {
+    let filter = match env::var(FILTER_VAR) {
+        Ok(filter) => {
+            EnvFilter::try_new(filter)
+                .map_err(|err| TraceInitError::Filter(err.to_string()))?
+        }
+        Err(_) => {
+            EnvFilter::new(DEFAULT_FILTER)
+        }
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter);
+
+    let guard = match artifacts {
+        Some(artifacts) => {
+            let path = artifact_path(artifacts, "log")
+                .map_err(|err| TraceInitError::Io(err))?;
+            let file = File::create(path)
+                .map_err(|err| TraceInitError::Io(err))?;
+
+            let subscriber = builder
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .finish();
+            tracing::subscriber::set_default(subscriber)
+        }
+        None => {
+            let subscriber = builder
+                .with_test_writer()
+                .finish();
+            tracing::subscriber::set_default(subscriber)
+        }
+    };
+
+    Ok(Guard { _subscriber: guard })
+}
+
+/// The path of an artifact of the current test case, like its log
+///
+/// Returns `<artifacts>/<test binary>/<test case>.<extension>`, and creates
+/// the directory, if necessary.
+pub(crate) fn artifact_path(artifacts: &Path, extension: &str)
+    -> io::Result<PathBuf>
+{
+    let dir = artifacts.join(binary_name());
+    fs::create_dir_all(&dir)?;
+
+    Ok(dir.join(format!("{}.{}", test_name(), extension)))
+}
+
+/// The name of the test binary, without the hash that Cargo appends
+fn binary_name() -> String {
+    let exe = env::args_os().next().unwrap_or_default();
+    binary_name_of(Path::new(&exe))
+}
+
+/// The name of the test binary at `path`, without the hash that Cargo appends
+pub(crate) fn binary_name_of(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match stem.rsplit_once('-') {
+        Some((name, _)) => name.to_owned(),
+        None            => stem,
+    };
+    sanitize(&name)
+}
+
+/// The name of the current test case
+///
+/// The test harness names the thread of each test case after it.
+fn test_name() -> String {
+    let name = thread::current()
+        .name()
+        .unwrap_or("main")
+        .to_owned();
+    sanitize(&name)
+}
+
+/// Make a name usable as a file name, including names like `module::test`
+fn sanitize(name: &str) -> String {
+    name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '-' })
+        .collect()
+}
+
+
+/// Keeps the subscriber installed by [`init`] active
+///
+/// The subscriber is uninstalled when this is dropped.
+#[must_use]
+pub struct Guard {
+    _subscriber: DefaultGuard,
+}
+
+
+/// Error installing the subscriber
+#[derive(Debug)]
+pub enum TraceInitError {
+    /// The value of `TEST_STAND_LOG` is not a valid filter
+    Filter(String),
+
+    /// The log file could not be created
+    Io(io::Error),
+}
@@ -0,0 +1,247 @@
+//! Transports that carry the data of a connection to a test node
+
+
+use std::{
+    io::{
+        self,
+        prelude::*,
+    },
+    net::TcpStream,
+    time::Duration,
+};
+
+use serialport::{
+    SerialPort,
+    SerialPortType,
+};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+};
+#[cfg(feature = "tokio")]
+use tokio_serial::SerialPortBuilderExt as _;
+
+use crate::{
+    Error,
+    config::Port,
+    record::Replay,
+};
+
+
+/// Prefix of addresses that refer to a TCP transport
+pub const TCP_PREFIX: &str = "tcp://";
+
+/// Prefix of addresses that refer to a USB serial device, by its IDs
+pub const USB_PREFIX: &str = "usb://";
+
+/// Prefix of addresses that refer to the replay of a recording
+pub const REPLAY_PREFIX: &str = "replay://";
+
+
+/// A byte stream that connects the host to a test node
+///
+/// Used by [`Conn`], which implements the framing on top of it.
+///
+/// [`Conn`]: crate::conn::Conn
+pub trait Transport: Read + Write + Send {
+    /// Set the timeout for reading
+    ///
+    /// Reads that don't complete within `timeout` must fail with an error of
+    /// kind `io::ErrorKind::TimedOut`.
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout)?;
+        Ok(())
+    }
+}
+
+
+/// A TCP connection, for example to a serial-to-ethernet adapter
+pub struct Tcp(TcpStream);
+
+impl Tcp {
+    /// Connect to `address`, which is given as `host:port`
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+
+        // Messages are small, and test cases wait for the replies. Don't let
+        // them sit in a buffer.
+        stream.set_nodelay(true)?;
+
+        Ok(Self(stream))
+    }
+}
+
+impl Read for Tcp {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Depending on the platform, a read that timed out might be reported
+        // as `WouldBlock`. Report it like the serial port does.
+        self.0.read(buf)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut.into(),
+                _                         => err,
+            })
+    }
+}
+
+impl Write for Tcp {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Transport for Tcp {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        // A zero timeout is rejected, and would block forever otherwise.
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.0.set_read_timeout(Some(timeout))
+    }
+}
+
+
+/// Open the transport that `address` refers to
+///
+/// `address` is either `tcp://host:port`, `usb://vid:pid[/serial]` (see
+/// [`find_usb`]), `replay://<recording>[#<address>]` (see [`Replay`]), or the
+/// path to a serial device file. Serial ports use the default settings.
+pub fn open(address: &str) -> Result<Box<dyn Transport>, Error> {
+    open_with(address, &Port::default())
+}
+
+/// Open the transport that `address` refers to, with the settings of `port`
+///
+/// Works like [`open`]. The settings only apply to serial ports, and are
+/// ignored otherwise.
+pub fn open_with(address: &str, port: &Port)
+    -> Result<Box<dyn Transport>, Error>
+{
+    if let Some(address) = address.strip_prefix(TCP_PREFIX) {
+        return Ok(Box::new(Tcp::connect(address)?));
+    }
+    if let Some(address) = address.strip_prefix(REPLAY_PREFIX) {
+        return Ok(Box::new(Replay::open(address)?));
+    }
+    let path = match address.strip_prefix(USB_PREFIX) {
+        Some(address) => find_usb(address)?,
+        None          => address.to_owned(),
+    };
+
+    // The settings don't matter for USB CDC devices, which ignore them.
+    let port = serialport::new(path, port.baud())
+        .flow_control(port.flow_control().into())
+        .open()?;
+
+    // Use a clone of the serialport, so `Serial` can use the same port.
+    let port = port.try_clone()?;
+
+    Ok(Box::new(port))
+}
+
+
+/// An async byte stream that connects the host to a test node
+///
+/// Used by [`AsyncConn`], in place of [`Transport`]. Unlike [`Transport`], it
+/// doesn't deal with timeouts. `AsyncConn` handles those using `tokio::time`.
+///
+/// [`AsyncConn`]: crate::conn::AsyncConn
+#[cfg(feature = "tokio")]
+pub trait AsyncTransport: AsyncRead + AsyncWrite + Send + Unpin {}
+
+#[cfg(feature = "tokio")]
+impl<T> AsyncTransport for T
+    where T: AsyncRead + AsyncWrite + Send + Unpin
+{}
+
+
+/// Open the async transport that `address` refers to
+///
+/// Works like [`open`], but must be called from within a tokio runtime.
+#[cfg(feature = "tokio")]
+pub async fn open_async(address: &str)
+    -> Result<Box<dyn AsyncTransport>, Error>
+{
+    if let Some(address) = address.strip_prefix(TCP_PREFIX) {
+        let stream = tokio::net::TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        return Ok(Box::new(stream));
+    }
+
+    let path = match address.strip_prefix(USB_PREFIX) {
+        Some(address) => find_usb(address)?,
+        None          => address.to_owned(),
+    };
+
+    let port = tokio_serial::new(path, 115200)
+        .open_native_async()?;
+
+    Ok(Box::new(port))
+}
+
+
+/// Find the device file of a USB serial device
+///
+/// `address` is given as `vid:pid`, or as `vid:pid/serial`, with the vendor
+/// and product IDs in hexadecimal, like `16c0:27dd/0123456789AB`. This is how
+/// test nodes that talk to the host over their own USB peripheral are found,
+/// as the device file they get depends on the order they were plugged in.
+///
+/// The device is looked up again every time a connection is opened, so
+/// reconnecting works, even if the device file changed after a reset.
+pub fn find_usb(address: &str) -> io::Result<String> {
+    let invalid = || io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid USB address `{}{}`", USB_PREFIX, address),
+    );
+
+    let (ids, serial) = match address.split_once('/') {
+        Some((ids, serial)) => (ids, Some(serial)),
+        None                => (address, None),
+    };
+    let (vid, pid) = ids.split_once(':')
+        .ok_or_else(invalid)?;
+    let vid = u16::from_str_radix(vid, 16)
+        .map_err(|_| invalid())?;
+    let pid = u16::from_str_radix(pid, 16)
+        .map_err(|_| invalid())?;
+
+    let ports = serialport::available_ports()?;
+    let mut matches = ports
+        .into_iter()
+        .filter(|port| match &port.port_type {
+            SerialPortType::UsbPort(info) => {
+                info.vid == vid
+                    && info.pid == pid
+                    && (serial.is_none()
+                        || info.serial_number.as_deref() == serial)
+            }
+            _ => false,
+        })
+        .map(|port| port.port_name);
+
+    let path = matches.next()
+        .ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No USB serial device at `{}{}`", USB_PREFIX, address),
+        ))?;
+    if matches.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "More than one USB serial device at `{}{}`; add the serial \
+                number to the address",
+                USB_PREFIX, address,
+            ),
+        ));
+    }
+
+    Ok(path)
+}
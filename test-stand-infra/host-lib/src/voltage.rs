@@ -0,0 +1,165 @@
+//! Voltage levels of the pins that test assistants drive
+//!
+//! Not every target tolerates the voltage that an assistant drives its pins
+//! with. A pin of a 5 V assistant, wired to a pin of a 3.3 V target that isn't
+//! 5 V tolerant, can damage the target, as soon as a test case sets it high. In
+//! a shared lab, where stands are rewired often, that's easy to miss.
+//!
+//! The `[voltage]` table of `test-stand.toml` lists, for each output pin of an
+//! assistant, the voltage the assistant drives it high with, and the maximum
+//! voltage the pin of the target it's wired to is rated for. The
+//! [`Assistant`] refuses to drive a pin high, if that would exceed the rating.
+//! Pins that aren't listed are not checked.
+//!
+//! [`Assistant`]: crate::Assistant
+
+
+use protocol::{
+    OutputPin,
+    pin,
+    trigger,
+    waveform::Waveform,
+};
+use serde::Deserialize;
+
+
+/// All output pins of the test assistant
+const OUTPUT_PINS: [OutputPin; 3] = [
+    OutputPin::Pin5,
+    OutputPin::Cts,
+    OutputPin::Red,
+];
+
+
+/// The voltage levels of a test assistant's output pins
+///
+/// An entry in the `[voltage]` table of the configuration file, like
+/// `[voltage.assistant]`. Each pin is configured in a table of its own, like
+/// `[voltage.assistant.pin5]`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Interlock {
+    pin5: Option<Rating>,
+    cts:  Option<Rating>,
+    red:  Option<Rating>,
+}
+
+impl Interlock {
+    /// The voltage levels of `pin`, if they are configured
+    pub fn rating(&self, pin: OutputPin) -> Option<Rating> {
+        match pin {
+            OutputPin::Pin5 => self.pin5,
+            OutputPin::Cts  => self.cts,
+            OutputPin::Red  => self.red,
+        }
+    }
+
+    /// Check whether `pin` can safely be driven high
+    pub fn check(&self, pin: OutputPin) -> Result<(), UnsafeVoltageError> {
+        match self.rating(pin) {
+            Some(rating) if !rating.is_safe() => {
+                Err(
+                    UnsafeVoltageError {
+                        pin,
+                        drive_v: rating.drive_v,
+                        max_v:   rating.max_v,
+                    }
+                )
+            }
+            _ => {
+                Ok(())
+            }
+        }
+    }
+
+    /// Check whether `action` only drives pins high that can safely be driven
+    pub fn check_action(&self, action: &trigger::Action<OutputPin>)
+        -> Result<(), UnsafeVoltageError>
+    {
+        match action {
+            trigger::Action::SetLevel(set) if set.level == pin::Level::High => {
+                self.check(set.pin)
+            }
+            trigger::Action::SetLevel(_) => {
+                Ok(())
+            }
+        }
+    }
+
+    /// Check whether `waveform` only drives pins high that can safely be driven
+    ///
+    /// Bit `n` of each step stands for the `OutputPin` with the value `n`, like
+    /// in `Assistant::play_waveform`.
+    pub fn check_waveform(&self, waveform: &Waveform)
+        -> Result<(), UnsafeVoltageError>
+    {
+        for step in waveform.steps() {
+            for &pin in &OUTPUT_PINS {
+                if step.level(pin as u8) == Some(pin::Level::High) {
+                    self.check(pin)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check whether setting a pin group only drives safe pins high
+    ///
+    /// Bit `n` of `mask` and `value` stands for the `OutputPin` with the value
+    /// `n`, like in `Assistant::set_pin_group`.
+    pub fn check_group(&self, mask: u32, value: u32)
+        -> Result<(), UnsafeVoltageError>
+    {
+        for &pin in &OUTPUT_PINS {
+            if mask & value & 1 << pin as u32 != 0 {
+                self.check(pin)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The configured pins, by their name in the configuration file
+    pub(crate) fn configured(&self)
+        -> impl Iterator<Item = (&'static str, Rating)>
+    {
+        vec![
+            ("pin5", self.pin5),
+            ("cts",  self.cts),
+            ("red",  self.red),
+        ]
+            .into_iter()
+            .filter_map(|(name, rating)| rating.map(|rating| (name, rating)))
+    }
+}
+
+
+/// The voltage levels of one of the test assistant's output pins
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rating {
+    /// The voltage the assistant drives the pin high with, in V
+    ///
+    /// Like 3.3 or 5.0, depending on the voltage domain of the pin.
+    pub drive_v: f64,
+
+    /// The maximum voltage the pin of the target is rated for, in V
+    pub max_v: f64,
+}
+
+impl Rating {
+    /// Indicates whether driving the pin high stays within the rating
+    pub fn is_safe(&self) -> bool {
+        self.drive_v <= self.max_v
+    }
+}
+
+
+/// Driving a pin high would exceed the rating of the target's pin
+#[derive(Clone, Copy, Debug)]
+pub struct UnsafeVoltageError {
+    pub pin:     OutputPin,
+    pub drive_v: f64,
+    pub max_v:   f64,
+}
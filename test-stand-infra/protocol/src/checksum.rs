@@ -0,0 +1,221 @@
+//! Checksums for detecting corrupted frames
+//!
+//! Without a checksum, a frame that was corrupted on the serial link either
+//! fails to deserialize, or, worse, deserializes into the wrong message. There
+//! is no way to tell that apart from a protocol bug.
+//!
+//! A checksum is negotiated between host and test node. Once a checksum other
+//! than [`Checksum::None`] has been negotiated, every frame in both directions
+//! carries a CRC trailer (in little-endian byte order), which is appended after
+//! compression and before COBS encoding. The receiving side verifies and
+//! strips the trailer, before processing the frame any further.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The checksum appended to frames
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Checksum {
+    /// Frames don't carry a checksum
+    #[default]
+    None,
+
+    /// Frames carry a CRC-16/CCITT-FALSE trailer
+    Crc16,
+
+    /// Frames carry a CRC-32 (as used by Ethernet and zlib) trailer
+    Crc32,
+}
+
+impl Checksum {
+    /// The length of the trailer, in bytes
+    pub fn trailer_len(self) -> usize {
+        match self {
+            Self::None  => 0,
+            Self::Crc16 => 2,
+            Self::Crc32 => 4,
+        }
+    }
+
+    /// Append the trailer to the first `len` bytes of `buf`
+    ///
+    /// Returns the length of the frame, including the trailer. Returns an
+    /// error, if `buf` is too small to hold the trailer.
+    pub fn append(self, buf: &mut [u8], len: usize) -> Result<usize, Error> {
+        let frame_len = len + self.trailer_len();
+        let (frame, trailer) = buf.get_mut(.. frame_len)
+            .ok_or(Error::BufferTooSmall)?
+            .split_at_mut(len);
+
+        match self {
+            Self::None  => {}
+            Self::Crc16 => trailer.copy_from_slice(&crc16(frame).to_le_bytes()),
+            Self::Crc32 => trailer.copy_from_slice(&crc32(frame).to_le_bytes()),
+        }
+
+        Ok(frame_len)
+    }
+
+    /// Verify the trailer of `frame`
+    ///
+    /// Returns the frame without the trailer. Returns an error, if the frame is
+    /// too short to carry a trailer, or if the trailer doesn't match.
+    pub fn verify(self, frame: &[u8]) -> Result<&[u8], Error> {
+        let len = frame.len().checked_sub(self.trailer_len())
+            .ok_or(Error::Mismatch)?;
+        let (frame, trailer) = frame.split_at(len);
+
+        let matches = match self {
+            Self::None  => true,
+            Self::Crc16 => trailer == crc16(frame).to_le_bytes(),
+            Self::Crc32 => trailer == crc32(frame).to_le_bytes(),
+        };
+        if !matches {
+            return Err(Error::Mismatch);
+        }
+
+        Ok(frame)
+    }
+}
+
+
+/// Compute the CRC-16/CCITT-FALSE of `data`
+///
+/// This is computed bit by bit, as a lookup table would take up a considerable
+/// part of a test node's flash memory.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffff;
+
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0 .. 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            }
+            else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Compute the CRC-32 of `data`
+///
+/// Like [`crc16`], this is computed bit by bit.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff;
+
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0 .. 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            }
+            else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+
+/// Error appending or verifying a checksum
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The buffer is too small to hold the trailer
+    BufferTooSmall,
+
+    /// The trailer doesn't match the frame
+    Mismatch,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Checksum,
+        Error,
+        crc16,
+        crc32,
+    };
+
+
+    // The input that catalogues of CRC algorithms list the check values for
+    const CHECK_INPUT: &[u8] = b"123456789";
+
+    #[test]
+    fn crc16_should_match_check_value() {
+        assert_eq!(crc16(CHECK_INPUT), 0x29b1);
+    }
+
+    #[test]
+    fn crc32_should_match_check_value() {
+        assert_eq!(crc32(CHECK_INPUT), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn should_append_trailer_in_little_endian_byte_order() {
+        let mut buf = [0; 16];
+        buf[.. 9].copy_from_slice(CHECK_INPUT);
+
+        assert_eq!(Checksum::Crc16.append(&mut buf, 9), Ok(11));
+        assert_eq!(buf[9 .. 11], [0xb1, 0x29]);
+
+        assert_eq!(Checksum::Crc32.append(&mut buf, 9), Ok(13));
+        assert_eq!(buf[9 .. 13], [0x26, 0x39, 0xf4, 0xcb]);
+    }
+
+    #[test]
+    fn should_verify_and_strip_trailer() {
+        for &checksum in &[Checksum::None, Checksum::Crc16, Checksum::Crc32] {
+            let mut buf = [0; 16];
+            buf[.. 9].copy_from_slice(CHECK_INPUT);
+
+            let len = checksum.append(&mut buf, 9).unwrap();
+            assert_eq!(checksum.verify(&buf[.. len]), Ok(CHECK_INPUT));
+        }
+    }
+
+    #[test]
+    fn should_reject_any_flipped_bit() {
+        for &checksum in &[Checksum::Crc16, Checksum::Crc32] {
+            let mut buf = [0; 16];
+            buf[.. 9].copy_from_slice(CHECK_INPUT);
+            let len = checksum.append(&mut buf, 9).unwrap();
+
+            // This covers the trailer too.
+            for bit in 0 .. len * 8 {
+                let mut frame = buf;
+                frame[bit / 8] ^= 1 << (bit % 8);
+
+                assert_eq!(
+                    checksum.verify(&frame[.. len]),
+                    Err(Error::Mismatch),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_reject_frame_shorter_than_trailer() {
+        assert_eq!(Checksum::Crc16.verify(&[0]), Err(Error::Mismatch));
+        assert_eq!(Checksum::Crc32.verify(&[0; 3]), Err(Error::Mismatch));
+    }
+
+    #[test]
+    fn should_reject_too_small_buffer() {
+        let mut buf = [0; 10];
+        assert_eq!(
+            Checksum::Crc16.append(&mut buf, 9),
+            Err(Error::BufferTooSmall),
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Generic protocol for messages that don't fit into a single frame
+//!
+//! Test nodes receive frames into buffers of a fixed size, so a request that
+//! carries a lot of data (like `SendUsart`) can be too large to be sent as a
+//! single frame. The host serializes such a request, splits the serialized
+//! data into chunks, and sends each of them in a `Chunk` message of its own.
+//! The test node collects the chunks using [`Reassembly`], and processes the
+//! reassembled request like any other, once the last chunk has arrived.
+//!
+//! Chunks must be sent in order, without any other requests in between. Every
+//! chunked request uses a different `id`, so the chunks of an earlier request
+//! that was only partially received are never mixed up with the next one.
+
+
+use core::marker::PhantomData;
+
+use serde::{
+    Deserialize,
+    Serialize,
+    Serializer,
+};
+
+
+/// The maximum size of a serialized message that can be reassembled
+pub const MESSAGE_CAP: usize = 512;
+
+/// The default size of the chunks that the host splits messages into
+///
+/// Leaves enough room in the 256-byte receive buffer of the test nodes, for the
+/// other fields of the `Chunk` message, a checksum, and the COBS overhead.
+/// Messages that serialize to no more than this are sent unchanged.
+pub const DEFAULT_SIZE: usize = 128;
+
+
+/// A part of a serialized message
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Chunk<'r> {
+    /// Identifies the message that this chunk is a part of
+    pub id: u8,
+
+    /// The position of this chunk within the message, starting at `0`
+    pub index: u16,
+
+    /// The number of chunks that make up the message
+    pub total: u16,
+
+    /// The serialized data of this part of the message
+    pub data: &'r [u8],
+}
+
+
+/// A message type that can carry chunks of larger messages of the same type
+///
+/// Implemented by the message enums that the host sends to test nodes, which
+/// have a `Chunk` variant with the same fields as [`Chunk`].
+pub trait Chunked {
+    /// Serialize a message of this type that carries `chunk`
+    fn serialize_chunk<S>(chunk: Chunk<'_>, serializer: S)
+        -> Result<S::Ok, S::Error>
+        where S: Serializer;
+
+    /// Return the chunk, if this message carries one
+    fn chunk(&self) -> Option<Chunk<'_>>;
+}
+
+
+/// Serializes a [`Chunk`] as a message of type `T`
+pub struct AsMessage<'r, T> {
+    chunk:    Chunk<'r>,
+    _message: PhantomData<T>,
+}
+
+impl<'r, T> AsMessage<'r, T> {
+    /// Wrap `chunk`, to serialize it as a message of type `T`
+    pub fn new(chunk: Chunk<'r>) -> Self {
+        Self {
+            chunk,
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<T> Serialize for AsMessage<'_, T>
+    where T: Chunked
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        T::serialize_chunk(self.chunk, serializer)
+    }
+}
+
+
+/// Reassembles a message from its chunks
+///
+/// Should be allocated in a `static`, as it is rather large.
+pub struct Reassembly {
+    buf: [u8; MESSAGE_CAP],
+    len: usize,
+
+    /// The id of the message being reassembled, and the index of its next chunk
+    next: Option<(u8, u16)>,
+}
+
+impl Reassembly {
+    /// Create an empty instance of `Reassembly`
+    pub const fn new() -> Self {
+        Self {
+            buf:  [0; MESSAGE_CAP],
+            len:  0,
+            next: None,
+        }
+    }
+
+    /// Add a chunk to the message
+    ///
+    /// Returns the serialized message, once its last chunk has been added. A
+    /// chunk with index `0` starts a new message, discarding any partial one.
+    /// A chunk that doesn't continue the current message results in an error,
+    /// and also discards the partial message.
+    pub fn add(&mut self, chunk: Chunk<'_>) -> Result<Option<&[u8]>, Error> {
+        if chunk.index == 0 {
+            self.len  = 0;
+            self.next = Some((chunk.id, 0));
+        }
+
+        if self.next != Some((chunk.id, chunk.index)) {
+            self.next = None;
+            return Err(Error::OutOfOrder);
+        }
+
+        let end = self.len + chunk.data.len();
+        if end > MESSAGE_CAP {
+            self.next = None;
+            return Err(Error::TooLarge);
+        }
+
+        self.buf[self.len..end].copy_from_slice(chunk.data);
+        self.len = end;
+
+        if chunk.index >= chunk.total.saturating_sub(1) {
+            self.next = None;
+            return Ok(Some(&self.buf[..self.len]));
+        }
+
+        self.next = Some((chunk.id, chunk.index + 1));
+        Ok(None)
+    }
+
+    /// Discard the partial message, if any
+    pub fn reset(&mut self) {
+        self.len  = 0;
+        self.next = None;
+    }
+}
+
+
+/// Error reassembling a message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The chunk doesn't continue the message that is being reassembled
+    ///
+    /// A chunk has been lost, for example because it was corrupted in transit.
+    OutOfOrder,
+
+    /// The message is larger than [`MESSAGE_CAP`]
+    TooLarge,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde::{
+        Deserialize,
+        Serialize,
+        Serializer,
+    };
+
+    use super::{
+        AsMessage,
+        Chunk,
+        Chunked,
+        Error,
+        MESSAGE_CAP,
+        Reassembly,
+    };
+
+
+    #[test]
+    fn should_reassemble_message_from_its_chunks() {
+        let mut message = [0; 300];
+        for (i, b) in message.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        let mut reassembly = Reassembly::new();
+        assert_eq!(
+            reassemble(&mut reassembly, 0, &message, 128),
+            Ok(&message[..]),
+        );
+    }
+
+    #[test]
+    fn should_reassemble_message_with_a_single_chunk() {
+        let mut reassembly = Reassembly::new();
+        assert_eq!(
+            reassemble(&mut reassembly, 0, b"Hello", 128),
+            Ok(&b"Hello"[..]),
+        );
+    }
+
+    #[test]
+    fn should_reject_chunks_out_of_order() {
+        let mut reassembly = Reassembly::new();
+
+        assert_eq!(reassembly.add(chunk(0, 0, 3, b"a")), Ok(None));
+        assert_eq!(
+            reassembly.add(chunk(0, 2, 3, b"c")),
+            Err(Error::OutOfOrder),
+        );
+
+        // The partial message has been discarded.
+        assert_eq!(
+            reassembly.add(chunk(0, 1, 3, b"b")),
+            Err(Error::OutOfOrder),
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_chunk() {
+        let mut reassembly = Reassembly::new();
+
+        assert_eq!(reassembly.add(chunk(0, 0, 3, b"a")), Ok(None));
+        assert_eq!(reassembly.add(chunk(0, 1, 3, b"b")), Ok(None));
+        assert_eq!(
+            reassembly.add(chunk(0, 1, 3, b"b")),
+            Err(Error::OutOfOrder),
+        );
+    }
+
+    #[test]
+    fn should_reject_chunk_of_another_message() {
+        let mut reassembly = Reassembly::new();
+
+        assert_eq!(reassembly.add(chunk(0, 0, 2, b"a")), Ok(None));
+        assert_eq!(
+            reassembly.add(chunk(1, 1, 2, b"b")),
+            Err(Error::OutOfOrder),
+        );
+    }
+
+    #[test]
+    fn should_restart_on_first_chunk() {
+        let mut reassembly = Reassembly::new();
+
+        assert_eq!(reassembly.add(chunk(0, 0, 2, b"stale")), Ok(None));
+        assert_eq!(reassembly.add(chunk(1, 0, 2, b"a")), Ok(None));
+        assert_eq!(reassembly.add(chunk(1, 1, 2, b"b")), Ok(Some(&b"ab"[..])));
+    }
+
+    #[test]
+    fn should_reject_message_larger_than_cap() {
+        let message = [0; MESSAGE_CAP + 1];
+
+        let mut reassembly = Reassembly::new();
+        assert_eq!(
+            reassemble(&mut reassembly, 0, &message, 128),
+            Err(Error::TooLarge),
+        );
+
+        // The next message is reassembled as usual.
+        assert_eq!(
+            reassemble(&mut reassembly, 1, b"Hello", 128),
+            Ok(&b"Hello"[..]),
+        );
+    }
+
+    #[test]
+    fn should_reassemble_message_that_carries_a_chunk() {
+        // The reassembled message is passed on as is. Recognizing that it
+        // carries another chunk, and rejecting it, is up to the test node.
+        let nested = AsMessage::<Message>::new(chunk(7, 0, 1, b"nested"));
+        let mut buf = [0; 64];
+        let serialized = postcard::to_slice(&nested, &mut buf).unwrap();
+
+        let mut reassembly = Reassembly::new();
+        let reassembled = reassemble(&mut reassembly, 0, serialized, 4)
+            .unwrap();
+
+        let message: Message = postcard::from_bytes(reassembled).unwrap();
+        assert_eq!(message.chunk(), Some(chunk(7, 0, 1, b"nested")));
+    }
+
+
+    fn chunk(id: u8, index: u16, total: u16, data: &[u8]) -> Chunk<'_> {
+        Chunk {
+            id,
+            index,
+            total,
+            data,
+        }
+    }
+
+    /// Split `message` into chunks of `size`, and add them to `reassembly`
+    fn reassemble<'r>(
+        reassembly: &'r mut Reassembly,
+        id:         u8,
+        message:    &[u8],
+        size:       usize,
+    )
+        -> Result<&'r [u8], Error>
+    {
+        let total = ((message.len() + size - 1) / size) as u16;
+
+        for (index, data) in message.chunks(size).enumerate() {
+            let chunk = chunk(id, index as u16, total, data);
+            if reassembly.add(chunk)?.is_some() {
+                break;
+            }
+        }
+
+        Ok(&reassembly.buf[.. reassembly.len])
+    }
+
+
+    #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
+    enum Message<'r> {
+        Chunk {
+            id:    u8,
+            index: u16,
+            total: u16,
+            data:  &'r [u8],
+        },
+    }
+
+    impl Chunked for Message<'_> {
+        fn serialize_chunk<S>(chunk: Chunk<'_>, serializer: S)
+            -> Result<S::Ok, S::Error>
+            where S: Serializer
+        {
+            let Chunk { id, index, total, data } = chunk;
+            Message::Chunk { id, index, total, data }.serialize(serializer)
+        }
+
+        fn chunk(&self) -> Option<Chunk<'_>> {
+            match *self {
+                Self::Chunk { id, index, total, data } => {
+                    Some(chunk(id, index, total, data))
+                }
+            }
+        }
+    }
+}
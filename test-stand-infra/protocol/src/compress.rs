@@ -0,0 +1,339 @@
+//! Lightweight compression for bulk frames
+//!
+//! Test nodes can send a lot of data to the host (USART captures, for
+//! example), which can saturate the serial link. This module provides a simple
+//! run-length encoding (the PackBits scheme) that both firmware and host can
+//! use, without requiring any allocation.
+//!
+//! Compression is negotiated between host and test node. Once a compression
+//! scheme other than [`Compression::None`] has been negotiated, every frame
+//! sent from the test node to the host starts with a flag byte ([`FLAG_RAW`] or
+//! [`FLAG_RLE`]) that indicates whether the rest of the frame is compressed.
+//! The test node only compresses frames for which that actually pays off.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// Flag byte for an uncompressed frame
+pub const FLAG_RAW: u8 = 0;
+
+/// Flag byte for a run-length encoded frame
+pub const FLAG_RLE: u8 = 1;
+
+/// Frames that are shorter than this are never compressed
+///
+/// Compressing short frames isn't worth the effort, as the saving (if any)
+/// would be tiny.
+pub const THRESHOLD: usize = 32;
+
+
+/// The compression scheme used for frames from a test node to the host
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Compression {
+    /// Frames are not compressed and don't carry a flag byte
+    #[default]
+    None,
+
+    /// Frames carry a flag byte and might be run-length encoded
+    Rle,
+}
+
+
+
+/// Run-length encode `input` into `output`
+///
+/// Returns the number of bytes written to `output`. Returns an error, if
+/// `output` is too small to hold the encoded data.
+pub fn compress(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
+    let mut i = 0;
+    let mut o = 0;
+
+    while i < input.len() {
+        let run = run_length(&input[i..]);
+
+        if run >= 2 {
+            // A run of identical bytes is encoded as a control byte, followed
+            // by the repeated byte.
+            let out = output.get_mut(o .. o + 2)
+                .ok_or(Error::BufferTooSmall)?;
+            out[0] = (257 - run) as u8;
+            out[1] = input[i];
+
+            i += run;
+            o += 2;
+        }
+        else {
+            // Collect literal bytes, until the next run starts.
+            let mut len = 1;
+            while i + len < input.len()
+                && len < MAX_CHUNK
+                && run_length(&input[i + len..]) < 2
+            {
+                len += 1;
+            }
+
+            let out = output.get_mut(o .. o + 1 + len)
+                .ok_or(Error::BufferTooSmall)?;
+            out[0] = (len - 1) as u8;
+            out[1..].copy_from_slice(&input[i .. i + len]);
+
+            i += len;
+            o += 1 + len;
+        }
+    }
+
+    Ok(o)
+}
+
+/// Decode run-length encoded `input` into `output`
+///
+/// Returns the number of bytes written to `output`. Returns an error, if
+/// `input` is malformed, or `output` is too small to hold the decoded data.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
+    let mut o = 0;
+
+    decode(input, |chunk| {
+        let out = output.get_mut(o .. o + chunk.len())
+            .ok_or(Error::BufferTooSmall)?;
+        chunk.write_to(out);
+        o += chunk.len();
+        Ok(())
+    })?;
+
+    Ok(o)
+}
+
+/// Compute the length of the data that `input` decodes to
+///
+/// This can be used to allocate a buffer of the right size before calling
+/// [`decompress`].
+pub fn decompressed_len(input: &[u8]) -> Result<usize, Error> {
+    let mut len = 0;
+
+    decode(input, |chunk| {
+        len += chunk.len();
+        Ok(())
+    })?;
+
+    Ok(len)
+}
+
+
+fn run_length(data: &[u8]) -> usize {
+    match data.first() {
+        Some(&first) => {
+            data.iter()
+                .take(MAX_CHUNK)
+                .take_while(|&&b| b == first)
+                .count()
+        }
+        None => {
+            0
+        }
+    }
+}
+
+fn decode<'a>(
+    input: &'a [u8],
+    mut f: impl FnMut(Chunk<'a>) -> Result<(), Error>,
+)
+    -> Result<(), Error>
+{
+    let mut i = 0;
+
+    while let Some(&control) = input.get(i) {
+        i += 1;
+
+        match control {
+            0 ..= 127 => {
+                let len = control as usize + 1;
+                let literal = input.get(i .. i + len)
+                    .ok_or(Error::Malformed)?;
+                f(Chunk::Literal(literal))?;
+                i += len;
+            }
+            128 => {
+                // No-op, according to the PackBits scheme.
+            }
+            _ => {
+                let len = 257 - control as usize;
+                let &b = input.get(i)
+                    .ok_or(Error::Malformed)?;
+                f(Chunk::Run(b, len))?;
+                i += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+enum Chunk<'a> {
+    Literal(&'a [u8]),
+    Run(u8, usize),
+}
+
+impl Chunk<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Literal(literal) => literal.len(),
+            Self::Run(_, len)      => *len,
+        }
+    }
+
+    fn write_to(&self, out: &mut [u8]) {
+        match self {
+            Self::Literal(literal) => out.copy_from_slice(literal),
+            Self::Run(b, _)        => out.iter_mut().for_each(|o| *o = *b),
+        }
+    }
+}
+
+
+/// Error compressing or decompressing data
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The output buffer is too small
+    BufferTooSmall,
+
+    /// The compressed data is malformed
+    Malformed,
+}
+
+
+// The PackBits scheme can encode at most 128 bytes per control byte.
+const MAX_CHUNK: usize = 128;
+
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Error,
+        compress,
+        decompress,
+        decompressed_len,
+    };
+
+
+    #[test]
+    fn should_encode_empty_input_as_nothing() {
+        let mut output = [0; 4];
+        assert_eq!(compress(&[], &mut output), Ok(0));
+        assert_eq!(decompress(&[], &mut output), Ok(0));
+        assert_eq!(decompressed_len(&[]), Ok(0));
+    }
+
+    // A run of `n` bytes has the control byte `257 - n`.
+
+    #[test]
+    fn should_encode_run_as_control_byte_and_repeated_byte() {
+        let input = [7; 5];
+        assert_eq!(*encoded(&input), [252, 7]);
+        round_trip(&input);
+    }
+
+    #[test]
+    fn should_split_runs_longer_than_128() {
+        let input = [0xaa; 300];
+        assert_eq!(*encoded(&input), [129, 0xaa, 129, 0xaa, 213, 0xaa]);
+        round_trip(&input);
+    }
+
+    #[test]
+    fn should_encode_literals_with_length_prefix() {
+        let input = [1, 2, 3];
+        assert_eq!(*encoded(&input), [2, 1, 2, 3]);
+        round_trip(&input);
+    }
+
+    #[test]
+    fn should_split_literals_longer_than_128() {
+        let mut input = [0; 200];
+        for (i, b) in input.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        let encoded = encoded(&input);
+        assert_eq!(encoded.len(), 2 + input.len());
+        assert_eq!(encoded[0], 127);
+        assert_eq!(encoded[1 .. 129], input[.. 128]);
+        assert_eq!(encoded[129], 71);
+        assert_eq!(encoded[130 ..], input[128 ..]);
+        round_trip(&input);
+    }
+
+    #[test]
+    fn should_switch_between_literals_and_runs() {
+        let input = [1, 2, 2, 2, 3];
+        assert_eq!(*encoded(&input), [0, 1, 254, 2, 0, 3]);
+        round_trip(&input);
+    }
+
+    #[test]
+    fn should_skip_no_op_control_byte() {
+        let mut output = [0; 4];
+        assert_eq!(decompress(&[128, 0, 5], &mut output), Ok(1));
+        assert_eq!(output[0], 5);
+    }
+
+    #[test]
+    fn should_reject_too_small_output() {
+        let mut output = [0; 1];
+        assert_eq!(
+            compress(&[1; 4], &mut output),
+            Err(Error::BufferTooSmall),
+        );
+        assert_eq!(
+            decompress(&[253, 1], &mut output),
+            Err(Error::BufferTooSmall),
+        );
+    }
+
+    #[test]
+    fn should_reject_truncated_input() {
+        let mut output = [0; 8];
+        assert_eq!(decompress(&[2, 1], &mut output), Err(Error::Malformed));
+        assert_eq!(decompress(&[253], &mut output), Err(Error::Malformed));
+        assert_eq!(decompressed_len(&[2, 1]), Err(Error::Malformed));
+    }
+
+
+    /// Compress `input`, and return the encoded data
+    fn encoded(input: &[u8]) -> Encoded {
+        let mut encoded = Encoded {
+            buf: [0; 512],
+            len: 0,
+        };
+        encoded.len = compress(input, &mut encoded.buf).unwrap();
+        encoded
+    }
+
+    /// Check that `input` decompresses to itself after compressing it
+    fn round_trip(input: &[u8]) {
+        let encoded = encoded(input);
+        assert_eq!(decompressed_len(&encoded), Ok(input.len()));
+
+        let mut output = [0; 512];
+        let len = decompress(&encoded, &mut output).unwrap();
+        assert_eq!(output[.. len], *input);
+    }
+
+
+    struct Encoded {
+        buf: [u8; 512],
+        len: usize,
+    }
+
+    impl core::ops::Deref for Encoded {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            &self.buf[.. self.len]
+        }
+    }
+}
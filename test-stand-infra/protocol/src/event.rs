@@ -0,0 +1,119 @@
+//! Generic protocol related to the event history of test nodes
+//!
+//! Test nodes record the last few significant things they did, like handling a
+//! request or entering an interrupt handler, in a small ring buffer in RAM. If
+//! a test case times out, the host can ask a test node that is still responsive
+//! for these events, to find out what actually happened on its side.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The number of events that a test node keeps
+pub const CAP: usize = 16;
+
+
+/// A significant event that a test node has recorded
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Event {
+    /// Counts the events recorded since the test node started, from `0`
+    ///
+    /// Gaps between consecutive events mean that older events have been
+    /// overwritten in between.
+    pub seq: u32,
+
+    /// What happened
+    pub kind: Kind,
+
+    /// How many times in a row this has happened
+    ///
+    /// Consecutive events of the same kind, like the interrupts that receive
+    /// a request byte by byte, are combined into one. Saturates at
+    /// `u16::MAX`.
+    pub count: u16,
+}
+
+
+/// The kind of an [`Event`]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Kind {
+    /// A request has been received from the host
+    ///
+    /// Identified by the index of its variant in the request enum, like
+    /// `HostToTarget`, which is what the host sends as its first byte.
+    Request(u8),
+
+    /// An error has occurred
+    Error(Error),
+
+    /// An interrupt handler has been entered
+    ///
+    /// Identified by the interrupt number, as listed in the reference manual.
+    Interrupt(u8),
+}
+
+
+/// The source of an error that a test node has recorded
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Error {
+    /// A request from the host couldn't be received
+    ///
+    /// It was corrupted, couldn't be decoded, or was an incomplete chunked
+    /// request.
+    Request,
+
+    /// A USART under test has reported an error
+    Usart,
+
+    /// An I2C transaction has failed
+    I2c,
+
+    /// An SPI transaction has failed
+    Spi,
+}
+
+
+/// The events that a test node has recorded, oldest first
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Events {
+    // The length is written as a literal, so the message schema records it.
+    events: [Event; 16],
+    len:    u8,
+}
+
+impl Events {
+    /// Create an empty instance of `Events`
+    pub const fn new() -> Self {
+        const EMPTY: Event = Event {
+            seq:   0,
+            kind:  Kind::Request(0),
+            count: 0,
+        };
+
+        Self {
+            events: [EMPTY; CAP],
+            len:    0,
+        }
+    }
+
+    /// Add an event after the ones already added
+    ///
+    /// Returns the event, if [`CAP`] events have already been added.
+    pub fn push(&mut self, event: Event) -> Result<(), Event> {
+        let slot = self.events.get_mut(self.len as usize)
+            .ok_or(event)?;
+
+        *slot = event;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Access the events
+    pub fn as_slice(&self) -> &[Event] {
+        &self.events[..self.len as usize]
+    }
+}
@@ -0,0 +1,67 @@
+//! Generic protocol related to flow control
+//!
+//! Some messages are sent by test nodes on their own, like the data that the
+//! test assistant receives from the target via USART. If a test node produces
+//! them faster than the host can take them, they pile up somewhere along the
+//! way. To prevent that, the host grants credits to the test node. Sending one
+//! of these messages uses up a credit, and once a test node has run out of
+//! them, it pauses sending, until the host grants more.
+//!
+//! Test nodes start out without flow control. It is enabled by the first
+//! [`Grant`] they receive, and stays enabled until they are reset.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The number of credits that the host keeps granted, by default
+pub const DEFAULT_WINDOW: u16 = 8;
+
+
+/// Grants credits to a test node
+///
+/// The credits are added to any the test node has left.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Grant {
+    pub credits: u16,
+}
+
+
+/// Keeps track of the credits that a test node has been granted
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Credits {
+    /// The credits left, or `None`, if flow control is not enabled
+    available: Option<u16>,
+}
+
+impl Credits {
+    /// Create an instance of `Credits`, with flow control not enabled yet
+    pub const fn new() -> Self {
+        Self {
+            available: None,
+        }
+    }
+
+    /// Add the credits from a [`Grant`], enabling flow control
+    pub fn grant(&mut self, grant: Grant) {
+        let available = self.available.unwrap_or(0);
+        self.available = Some(available.saturating_add(grant.credits));
+    }
+
+    /// Indicates whether a credited message can be sent
+    ///
+    /// Always returns `true`, if flow control has not been enabled.
+    pub fn available(&self) -> bool {
+        self.available != Some(0)
+    }
+
+    /// Use up a credit, as a credited message is being sent
+    pub fn take(&mut self) {
+        if let Some(available) = &mut self.available {
+            *available = available.saturating_sub(1);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Generic protocol related to I2C
+//!
+//! A test node can emulate an I2C slave with a register map, which the host
+//! programs through messages. This allows testing I2C master drivers against
+//! a slave whose behavior is fully under the host's control, including which
+//! bytes it acknowledges, and how long it stretches the clock.
+//!
+//! The first byte of every write sets the register pointer. Further bytes of
+//! that write are written to the registers, starting at the pointer. Reads
+//! return the registers, starting at the pointer. The pointer is incremented
+//! after every register access, wrapping around at the end of the map.
+//!
+//! The slave also logs the start and stop conditions that frame its
+//! transactions, so the host can check how the master uses the bus, beyond the
+//! data it transfers.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::units;
+
+
+/// The number of registers in the register map
+///
+/// Every possible value of the register pointer addresses a register.
+pub const REGISTERS: usize = 256;
+
+/// The maximum number of registers written or read by a single message
+pub const TRANSFER_CAP: usize = 64;
+
+
+/// Configuration of an emulated I2C slave
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct SlaveConfig {
+    /// The 7-bit address that the slave responds to
+    pub address: u8,
+
+    /// Which byte the slave doesn't acknowledge
+    pub nack: Nack,
+
+    /// How long the slave stretches the clock, before handling each byte
+    ///
+    /// The clock is stretched in addition to the time the slave needs anyway,
+    /// which is why zero doesn't disable clock stretching completely.
+    pub stretch: units::Microseconds,
+}
+
+
+/// Which byte an emulated I2C slave doesn't acknowledge
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Nack {
+    /// Acknowledge all bytes
+    None,
+
+    /// Don't acknowledge the address, as if no slave was present
+    Address,
+
+    /// Don't acknowledge the written byte with the given index
+    ///
+    /// Counts from the start of each write, so index 0 is the byte that sets
+    /// the register pointer. The byte is discarded.
+    Data(u8),
+}
+
+
+/// The start and stop conditions an emulated I2C slave has seen
+///
+/// Only conditions that frame a transaction with the slave are counted, which
+/// means starts (and repeated starts) followed by the slave's address, and the
+/// stops that end a transaction with the slave. The log covers everything
+/// since it was last read, or since the slave was configured.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct TransactionLog {
+    /// The number of start conditions, including repeated starts
+    pub starts: u32,
+
+    /// The number of repeated start conditions
+    ///
+    /// A repeated start begins a new transaction with the slave, without a stop
+    /// ending the previous one.
+    pub restarts: u32,
+
+    /// The number of stop conditions
+    pub stops: u32,
+}
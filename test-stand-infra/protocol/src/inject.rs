@@ -0,0 +1,91 @@
+//! Generic protocol related to injecting errors into a byte stream
+//!
+//! A test node that forwards data to another test node can corrupt that data
+//! at precisely defined positions, by flipping bits or dropping bytes. This
+//! allows testing the error handling of protocols and drivers with surgically
+//! precise corruption, instead of random glitches.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The maximum number of corruptions that can be applied to one byte stream
+pub const CORRUPTIONS_CAP: usize = 8;
+
+
+/// A corruption of a single byte in a byte stream
+///
+/// Positions are counted from the start of the stream, starting at `0`. They
+/// refer to the original stream, before any bytes are dropped.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Corruption {
+    /// Flip the bits of the byte that are set in `mask`
+    FlipBits {
+        position: u16,
+        mask:     u8,
+    },
+
+    /// Drop the byte, instead of forwarding it
+    Drop {
+        position: u16,
+    },
+}
+
+impl Corruption {
+    fn position(&self) -> u16 {
+        match *self {
+            Self::FlipBits { position, .. } => position,
+            Self::Drop { position }         => position,
+        }
+    }
+}
+
+
+/// The corruptions to apply to a byte stream
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Corruptions([Option<Corruption>; CORRUPTIONS_CAP]);
+
+impl Corruptions {
+    /// Create an empty list of corruptions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a corruption to the list
+    ///
+    /// Returns the corruption as an error, if the list is full.
+    pub fn push(&mut self, corruption: Corruption) -> Result<(), Corruption> {
+        match self.0.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(corruption);
+                Ok(())
+            }
+            None => {
+                Err(corruption)
+            }
+        }
+    }
+
+    /// Apply the corruptions to the byte at `position`
+    ///
+    /// Returns `None`, if the byte is dropped. If multiple corruptions apply
+    /// to the same position, all of them are applied.
+    pub fn apply(&self, position: usize, byte: u8) -> Option<u8> {
+        let mut byte = Some(byte);
+
+        let corruptions = self.0.iter()
+            .flatten()
+            .filter(|corruption| corruption.position() as usize == position);
+        for corruption in corruptions {
+            byte = match corruption {
+                Corruption::FlipBits { mask, .. } => byte.map(|b| b ^ mask),
+                Corruption::Drop { .. }           => None,
+            };
+        }
+
+        byte
+    }
+}
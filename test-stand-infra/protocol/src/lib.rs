@@ -1,7 +1,31 @@
+//! Generic protocol for test stands
+//!
+//! The types in this crate are not specific to any test stand setup, and can
+//! be re-used for different test stands. A test stand builds its own messages
+//! from them.
+
+
 #![no_std]
 
 
+pub mod checksum;
+pub mod chunk;
+pub mod compress;
+pub mod event;
+pub mod flow;
+pub mod i2c;
+pub mod inject;
+pub mod log;
+pub mod monitor;
+pub mod panic;
 pub mod pin;
+pub mod reference;
+pub mod reset;
+pub mod spi;
+pub mod trigger;
+pub mod units;
+pub mod waveform;
+pub mod usart;
 
 
 use core::convert::TryFrom;
@@ -9,9 +33,46 @@ use core::convert::TryFrom;
 use serde::{
     Deserialize,
     Serialize,
+    Serializer,
 };
 
 
+/// Sync preamble, sent by a test node once it is ready
+///
+/// After a reset, a test node (or its bootloader) might send arbitrary data,
+/// before sending its first message. The host discards everything it receives
+/// until this preamble, to make sure that decoding starts with the first real
+/// message.
+///
+/// The preamble starts with `0`, to terminate any partial frame that might
+/// have been received before it, and ends with `0`, so it doesn't run into
+/// the first frame.
+pub const SYNC_PREAMBLE: &[u8] = &[0, b'S', b'Y', b'N', b'C', 0];
+
+/// Link sync sequence, sent by the host to resynchronize a test node
+///
+/// A host that attaches to a running test node might do so in the middle of a
+/// frame, and the test node would then see the start of that frame as part of
+/// the host's first one. The run of `0`s terminates any partial frame, and the
+/// frame that follows is recognized by [`is_link_sync`]. It isn't valid COBS,
+/// so it can't be mistaken for a message.
+///
+/// A test node that receives it discards its partial state, and whatever
+/// framing was negotiated, and answers with [`SYNC_PREAMBLE`].
+pub const LINK_SYNC: &[u8] = &[0, 0, 0, 0, b'L', b'S', b'Y', b'N', b'C', 0];
+
+/// The number of `0`s that [`LINK_SYNC`] starts with
+const LINK_SYNC_ZEROS: usize = 4;
+
+/// Indicates whether a received frame is the end of [`LINK_SYNC`]
+///
+/// `frame` is what has been received since the last `0`, not including the
+/// `0` that terminates it.
+pub fn is_link_sync(frame: &[u8]) -> bool {
+    frame == &LINK_SYNC[LINK_SYNC_ZEROS..LINK_SYNC.len() - 1]
+}
+
+
 /// A message from the test suite on the host to the test assistant
 #[derive(Debug, Deserialize, Serialize)]
 pub enum HostToAssistant<'r> {
@@ -21,11 +82,343 @@ pub enum HostToAssistant<'r> {
         data: &'r [u8],
     },
 
+    /// Instruct the assistant to send corrupted data to the target via USART
+    ///
+    /// Works like `SendUsart` in regular mode, except that the assistant
+    /// applies the corruptions to `data`, before sending it.
+    SendUsartCorrupted {
+        data:        &'r [u8],
+        corruptions: inject::Corruptions,
+    },
+
+    /// Instruct the assistant to send data with the wrong parity
+    ///
+    /// Works like `SendUsart` in regular mode, except that the assistant uses
+    /// odd parity where even parity is configured, and vice versa. If no parity
+    /// is configured, it uses even parity, and the target sees the parity bit
+    /// where it expects the stop bit.
+    SendUsartWithWrongParity(&'r [u8]),
+
+    /// Instruct the assistant to send data at the wrong baud rate
+    ///
+    /// Works like `SendUsart` in regular mode, except that the assistant uses
+    /// `baud` instead of the configured baud rate.
+    SendUsartAtWrongBaud {
+        data: &'r [u8],
+        baud: u32,
+    },
+
+    /// Instruct the assistant to send a break to the target via USART
+    ///
+    /// The assistant holds the target's RX line low for 2 ms, which is longer
+    /// than a whole frame at 9600 baud or faster.
+    SendUsartBreak,
+
+    /// Instruct the assistant to send data, once the target starts sending
+    ///
+    /// Works like `SendUsart` in regular mode, except that the assistant waits
+    /// for the next byte from the target, before it starts sending. If the
+    /// target sends more than that, both directions of the USART are busy at
+    /// the same time. The assistant replies with `UsartDuplexArmed`, then keeps
+    /// processing other requests while it waits. `data` must not be longer
+    /// than `usart::DUPLEX_CAP`. Empty data disarms the assistant.
+    SendUsartDuplex(&'r [u8]),
+
     /// Instruct the assistant to change level of the target's input pin
     SetPin(pin::SetLevel<OutputPin>),
 
     /// Ask the assistant for the current level of a pin
     ReadPin(pin::ReadLevel<InputPin>),
+
+    /// Ask the assistant to compress the frames it sends to the host
+    ///
+    /// The assistant replies with `CompressionNegotiated`, before it starts
+    /// using the compression scheme.
+    NegotiateCompression(compress::Compression),
+
+    /// Ask the assistant to add a checksum to frames in both directions
+    ///
+    /// The assistant replies with `ChecksumNegotiated`, before it starts using
+    /// the checksum. This request itself must not carry the new checksum yet.
+    NegotiateChecksum(checksum::Checksum),
+
+    /// Instruct the assistant to perform an action when it fires the trigger
+    ///
+    /// Replaces any action that the assistant has been armed with before.
+    ArmOnTrigger(trigger::Action<OutputPin>),
+
+    /// Instruct the assistant to fire the trigger
+    ///
+    /// The assistant drives a pulse on the trigger line. It performs its own
+    /// armed action, if any, right after the rising edge.
+    FireTrigger,
+
+    /// Instruct the assistant to discard its reference clock timestamps
+    ///
+    /// The next edge of the reference clock becomes the first one again.
+    ResetReference,
+
+    /// Ask the assistant for the timestamps of the reference clock input
+    ///
+    /// The assistant replies with `ReferenceTimestamps`.
+    ReadReference,
+
+    /// Instruct the assistant to start monitoring a USART line
+    ///
+    /// The assistant sends `MonitoredByte` for every byte it sees on the line.
+    /// Replaces any monitoring that is already in progress, including its
+    /// pattern.
+    MonitorUsart {
+        pin:  MonitorPin,
+        baud: u32,
+    },
+
+    /// Instruct the assistant to fire the trigger, once it sees a pattern
+    ///
+    /// Applies to the USART line that is currently being monitored. The
+    /// pattern can be up to `monitor::PATTERN_CAP` bytes long. An empty
+    /// pattern disables the trigger. The assistant sends `PatternMatched`,
+    /// every time it sees the pattern.
+    TriggerOnPattern(&'r [u8]),
+
+    /// Instruct the assistant to stop monitoring
+    StopMonitor,
+
+    /// Instruct the assistant to start monitoring the edges of a pin
+    ///
+    /// The assistant timestamps every edge, to measure the period and duty
+    /// cycle of the signal on the pin. Restarts the measurement, if the pin is
+    /// already being monitored.
+    StartPinMonitoring(InputPin),
+
+    /// Instruct the assistant to stop monitoring the edges of a pin
+    ///
+    /// The assistant replies with `PinMonitoringResult`.
+    StopPinMonitoring(InputPin),
+
+    /// Instruct the assistant to reconfigure its USART to the target
+    ///
+    /// The assistant replies with `UsartConfigured`, once the new configuration
+    /// is in effect. The target needs to be reconfigured the same way.
+    ConfigureUsart {
+        mode:   UsartMode,
+        config: usart::Config,
+    },
+
+    /// Instruct the assistant to play back a waveform on its output pins
+    ///
+    /// Bit `n` of each step stands for the `OutputPin` with the value `n`. The
+    /// assistant replies with `WaveformPlayed`, after the last step. It doesn't
+    /// process any other requests in the meantime.
+    PlayWaveform(waveform::Waveform),
+
+    /// Instruct the assistant to reconfigure its emulated I2C slave
+    ///
+    /// Also clears all registers, and resets the register pointer. The
+    /// assistant replies with `I2cSlaveConfigured`.
+    ConfigureI2cSlave(i2c::SlaveConfig),
+
+    /// Instruct the assistant to write to the registers of its I2C slave
+    ///
+    /// Writes `data` to the registers, starting at `register`, wrapping around
+    /// at the end of the map. `data` must not be longer than
+    /// `i2c::TRANSFER_CAP`. The assistant replies with `I2cRegistersWritten`.
+    WriteI2cRegisters {
+        register: u8,
+        data:     &'r [u8],
+    },
+
+    /// Instruct the assistant to read the registers of its I2C slave
+    ///
+    /// Reads `len` registers, starting at `register`, wrapping around at the
+    /// end of the map. `len` must not be larger than `i2c::TRANSFER_CAP`. The
+    /// assistant replies with `I2cRegisters`.
+    ReadI2cRegisters {
+        register: u8,
+        len:      u8,
+    },
+
+    /// Instruct the assistant to read the transaction log of its I2C slave
+    ///
+    /// Also clears the log. The assistant replies with `I2cTransactionLog`.
+    GetI2cTransactionLog,
+
+    /// Instruct the assistant to load the response of its SPI slave
+    ///
+    /// The slave sends the response in every following transaction, as
+    /// described in the documentation of the `spi` module. The response must
+    /// not be longer than `spi::BUF_CAP`. The assistant replies with
+    /// `SpiResponseLoaded`.
+    LoadSpiResponse(&'r [u8]),
+
+    /// Ask the assistant what its SPI slave received
+    ///
+    /// The assistant replies with `SpiReceived`, which carries the data of the
+    /// most recent transaction.
+    GetSpiReceived,
+
+    /// Instruct the assistant to transfer data as an SPI master
+    ///
+    /// The assistant switches its SPI peripheral to master mode, asserts the
+    /// slave select signal, and transfers the data, recording what the slave
+    /// sends in return. Then it deasserts the slave select signal and returns
+    /// to slave mode. Must not be longer than `spi::BUF_CAP`. The assistant
+    /// replies with `SpiMasterTransferred`.
+    SpiMasterTransfer(&'r [u8]),
+
+    /// A part of a request that is too large for a single frame
+    ///
+    /// The assistant collects the chunks, then processes the reassembled
+    /// request. See the `chunk` module.
+    Chunk {
+        id:    u8,
+        index: u16,
+        total: u16,
+        data:  &'r [u8],
+    },
+
+    /// Grant credits for forwarding data received from the target
+    ///
+    /// Each `UsartReceive` uses up a credit. Once the assistant has run out of
+    /// them, it stops forwarding, until it is granted more. The assistant
+    /// doesn't reply. See the `flow` module.
+    GrantCredits(flow::Grant),
+
+    /// Instruct the assistant to drive the trigger line like an open-drain pin
+    ///
+    /// This lets the trigger line double as a shared, active-low interrupt
+    /// line, which any test node can pull low, and which is only high while
+    /// none of them does (wired-AND). `Level::Low` pulls the line low, which
+    /// the assistant also does while the trigger isn't firing. `Level::High`
+    /// releases it to the pull-up resistors. The assistant replies with
+    /// `SharedLineLevel`.
+    ///
+    /// The trigger must not be fired while the line is released, or while
+    /// another test node pulls it low. Pull it low first.
+    DriveSharedLine(pin::Level),
+
+    /// Instruct the assistant to count the data it receives from the target
+    ///
+    /// While counting, the assistant doesn't forward data it receives from the
+    /// target in regular mode. It counts the bytes and receive errors instead,
+    /// and timestamps the first and last byte, to measure the throughput of
+    /// the target's USART. Restarts the count, if already counting. The
+    /// assistant replies with `UsartCountStarted`.
+    StartUsartCount,
+
+    /// Instruct the assistant to stop counting, and to forward data again
+    ///
+    /// The assistant replies with `UsartCounted`.
+    StopUsartCount,
+
+    /// Instruct the assistant to pause the target in the middle of a transfer
+    ///
+    /// Applies to the target's flow-controlled USART transfers. The assistant
+    /// asserts CTS, then counts the data it receives from the target in regular
+    /// mode, without forwarding it, like with `StartUsartCount`. Once it has
+    /// received `after` bytes, it deasserts CTS for `duration`, then asserts it
+    /// again. It timestamps the data, so the host can check that the target
+    /// stopped sending in the meantime. Restarts the measurement, if one is
+    /// already in progress. The assistant replies with `CtsThrottleArmed`.
+    ThrottleCts {
+        after:    u32,
+        duration: units::Microseconds,
+    },
+
+    /// Instruct the assistant to stop throttling, and to forward data again
+    ///
+    /// Asserts CTS, if it is still deasserted. The assistant replies with
+    /// `CtsThrottled`.
+    StopCtsThrottle,
+
+    /// Instruct the assistant to stream data to the target, and check the echo
+    ///
+    /// The assistant sends `pattern` to the target in regular mode, over and
+    /// over, until it has sent `bytes` bytes. It sends a few bytes at a time,
+    /// checking the data it receives from the target in between, so both
+    /// directions are busy at the same time, if the target echoes the data.
+    /// The received data isn't forwarded. `pattern` must not be longer than
+    /// `usart::STREAM_PATTERN_CAP`. Restarts the stream, if one is already in
+    /// progress. The assistant replies with `UsartStreamStarted`.
+    StartUsartStream {
+        pattern: &'r [u8],
+        bytes:   u32,
+    },
+
+    /// Instruct the assistant to stop streaming, and to forward data again
+    ///
+    /// The assistant replies with `UsartStreamed`.
+    StopUsartStream,
+
+    /// Instruct the assistant to send an address to the target via USART
+    ///
+    /// Sends `address` as a single word with the address bit set. That's bit 8,
+    /// if 9 data bits are configured (see `ConfigureUsart`), the most
+    /// significant bit of `address` otherwise. The assistant doesn't reply.
+    SendUsartAddress(u8),
+
+    /// Instruct the assistant to simulate a touch of the target's touch sensor
+    ///
+    /// `true` grounds the sensor, which looks like a very large touch to the
+    /// target's capacitive touch peripheral. `false` releases it, by switching
+    /// the assistant's pin to high-Z. The assistant replies with
+    /// `TouchSimulated`.
+    SimulateTouch(bool),
+
+    /// Instruct the assistant to set the levels of a group of its output pins
+    ///
+    /// Bit `n` of `mask` and `value` stands for the `OutputPin` with the value
+    /// `n`. The pins in `mask` are set high, if their bit in `value` is set,
+    /// low otherwise. The other pins are not changed. Pins on the same GPIO
+    /// port change at the same time, the others within a few cycles. The
+    /// assistant replies with `PinGroupSet`.
+    SetPinGroup {
+        mask:  u32,
+        value: u32,
+    },
+
+    /// Ask the assistant for the levels of a group of its input pins
+    ///
+    /// Bit `n` of `mask` stands for the `InputPin` with the value `n`. Pins on
+    /// the same GPIO port are read at the same time. The assistant replies with
+    /// `PinGroup`.
+    ReadPinGroup {
+        mask: u32,
+    },
+
+    /// Instruct the assistant to send a pulse on one of its output pins
+    ///
+    /// The assistant inverts the level of `pin` for `width`, then restores it.
+    /// Inverting the pin takes a few cycles of the assistant's 12 MHz system
+    /// clock, which is added to `width`, so very short widths result in a
+    /// pulse of those few cycles. That is short enough to test input filters.
+    /// `width` must not be longer than 1 ms, as the assistant doesn't do
+    /// anything else during the pulse. The assistant replies with `PulseSent`.
+    SendPulse {
+        pin:   OutputPin,
+        width: units::Nanoseconds,
+    },
+}
+
+impl chunk::Chunked for HostToAssistant<'_> {
+    fn serialize_chunk<S>(chunk: chunk::Chunk<'_>, serializer: S)
+        -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let chunk::Chunk { id, index, total, data } = chunk;
+        HostToAssistant::Chunk { id, index, total, data }.serialize(serializer)
+    }
+
+    fn chunk(&self) -> Option<chunk::Chunk<'_>> {
+        match *self {
+            Self::Chunk { id, index, total, data } => {
+                Some(chunk::Chunk { id, index, total, data })
+            }
+            _ => {
+                None
+            }
+        }
+    }
 }
 
 impl From<pin::SetLevel<OutputPin>> for HostToAssistant<'_> {
@@ -40,6 +433,12 @@ impl From<pin::ReadLevel<InputPin>> for HostToAssistant<'_> {
     }
 }
 
+impl From<flow::Grant> for HostToAssistant<'_> {
+    fn from(grant: flow::Grant) -> Self {
+        Self::GrantCredits(grant)
+    }
+}
+
 
 /// A message from the test assistant to the test suite on the host
 #[derive(Debug, Deserialize, Serialize)]
@@ -52,6 +451,128 @@ pub enum AssistantToHost<'r> {
 
     /// Notify the host that the level of a pin has changed
     ReadPinResult(Option<pin::ReadLevelResult<InputPin>>),
+
+    /// Reply to `NegotiateCompression`
+    ///
+    /// Carries the compression scheme the assistant is going to use from now
+    /// on. This reply itself is still sent using the previous scheme.
+    CompressionNegotiated(compress::Compression),
+
+    /// Reply to `NegotiateChecksum`
+    ///
+    /// Carries the checksum the assistant is going to use from now on. This
+    /// reply itself is still sent using the previous checksum.
+    ChecksumNegotiated(checksum::Checksum),
+
+    /// Reply to `ReadReference`
+    ReferenceTimestamps(reference::Timestamps),
+
+    /// Notify the host that a byte has been seen on the monitored USART line
+    MonitoredByte(monitor::Byte),
+
+    /// Notify the host that the pattern has been seen on the monitored line
+    ///
+    /// Carries the timestamp of the last byte of the pattern. The assistant
+    /// has fired the trigger right before sending this.
+    PatternMatched {
+        timestamp: u32,
+    },
+
+    /// Reply to `ConfigureUsart`
+    UsartConfigured,
+
+    /// Reply to `StopPinMonitoring`
+    ///
+    /// Carries `None`, if the pin wasn't being monitored.
+    PinMonitoringResult(Option<pin::Signal<InputPin>>),
+
+    /// Reply to `PlayWaveform`
+    WaveformPlayed,
+
+    /// Reply to `ConfigureI2cSlave`
+    I2cSlaveConfigured,
+
+    /// Reply to `WriteI2cRegisters`
+    I2cRegistersWritten,
+
+    /// Reply to `ReadI2cRegisters`
+    I2cRegisters(&'r [u8]),
+
+    /// Reply to `GetI2cTransactionLog`
+    I2cTransactionLog(i2c::TransactionLog),
+
+    /// Reply to `LoadSpiResponse`
+    SpiResponseLoaded,
+
+    /// Reply to `GetSpiReceived`
+    SpiReceived(&'r [u8]),
+
+    /// Reply to `SpiMasterTransfer`
+    ///
+    /// Carries the data received from the slave.
+    SpiMasterTransferred(&'r [u8]),
+
+    /// Notify the host that data from the target has been dropped
+    ///
+    /// The assistant couldn't keep up with the target, because it ran out of
+    /// credits, or because the host link is slower than the USART. `count` is
+    /// the number of bytes dropped since the last notification.
+    UsartDropped {
+        mode:  UsartMode,
+        count: u32,
+    },
+
+    /// Reply to `DriveSharedLine`, carrying the level of the line afterwards
+    SharedLineLevel(pin::Level),
+
+    /// Reply to `SendUsartDuplex`
+    UsartDuplexArmed,
+
+    /// Reply to `StartUsartCount`
+    UsartCountStarted,
+
+    /// Reply to `StopUsartCount`
+    UsartCounted(usart::Count),
+
+    /// Notify the host that the target has sent a break via USART
+    ///
+    /// Sent once the assistant detects the break, which is when the line has
+    /// been low for longer than a whole frame. The data byte of `0` that the
+    /// break looks like at first is not reported.
+    UsartBreakDetected,
+
+    /// Notify the host that the assistant has panicked
+    ///
+    /// Sent from the panic handler, on a best-effort basis. The assistant
+    /// doesn't send anything after this.
+    Panic(panic::Report<'r>),
+
+    /// Reply to `SimulateTouch`
+    TouchSimulated,
+
+    /// Reply to `SetPinGroup`
+    PinGroupSet,
+
+    /// Reply to `ReadPinGroup`
+    ///
+    /// Has a bit set for each pin in the requested mask that is high. All
+    /// other bits are cleared.
+    PinGroup(u32),
+
+    /// Reply to `SendPulse`
+    PulseSent,
+
+    /// Reply to `ThrottleCts`
+    CtsThrottleArmed,
+
+    /// Reply to `StopCtsThrottle`
+    CtsThrottled(usart::Throttle),
+
+    /// Reply to `StartUsartStream`
+    UsartStreamStarted,
+
+    /// Reply to `StopUsartStream`
+    UsartStreamed(usart::Stream),
 }
 
 impl<'r> TryFrom<AssistantToHost<'r>> for pin::ReadLevelResult<InputPin> {
@@ -92,7 +613,17 @@ pub enum InputPin {
 /// Represents one of the pins that the assistant can set
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub enum OutputPin {
-    Pin5,
-    Cts,
-    Red,
+    Pin5 = 0,
+    Cts  = 1,
+    Red  = 2,
+}
+
+/// Represents one of the USART lines that the assistant can monitor
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum MonitorPin {
+    /// The target's USART TX line
+    ///
+    /// This is the line that the target uses to send regular USART data to
+    /// the assistant.
+    TargetTx,
 }
@@ -0,0 +1,26 @@
+//! Generic protocol related to logging
+//!
+//! Test nodes can send log records to the host, over the same connection they
+//! use for all other messages. Unlike RTT, this doesn't require a debug probe
+//! to be attached. Each test stand defines its own message that carries a log
+//! record, using the types in this module.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The level of a log record
+///
+/// Ordered from the most to the least severe.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[derive(Ord, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
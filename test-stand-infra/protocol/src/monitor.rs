@@ -0,0 +1,34 @@
+//! Generic protocol related to monitoring USART lines
+//!
+//! A test assistant can passively listen to a USART line, while another device
+//! is the logical peer of the transmitter. It reports every byte it sees on
+//! the wire, together with a timestamp, which allows test cases to verify what
+//! was actually transmitted, independently of the peer's receive path.
+//!
+//! The assistant can also watch for a pattern in the monitored data, and fire
+//! the trigger line when it sees it (see the `trigger` module).
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The maximum length of a pattern that the assistant can watch for
+pub const PATTERN_CAP: usize = 16;
+
+
+/// A byte that has been seen on a monitored USART line
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Byte {
+    /// The value of the byte
+    pub value: u8,
+
+    /// The time at which the byte has been received completely
+    ///
+    /// In ticks of the assistant's timer, the same one that timestamps the
+    /// reference clock (see `reference::Timestamps`). Wraps around after 2^32
+    /// ticks.
+    pub timestamp: u32,
+}
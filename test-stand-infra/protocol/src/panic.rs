@@ -0,0 +1,44 @@
+//! Generic protocol related to panics
+//!
+//! When a test node's firmware panics, it reports the panic to the host, over
+//! the same connection it uses for all other messages. Without that, the host
+//! would only notice that the firmware no longer replies. Each test stand
+//! defines its own message that carries the report, using the type in this
+//! module.
+
+
+use core::fmt;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// A panic, as reported by a test node
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Report<'r> {
+    /// The panic message
+    ///
+    /// Truncated by the firmware, if it's too long.
+    pub message: &'r str,
+
+    /// The source file in which the panic occurred
+    pub file: &'r str,
+
+    /// The line in which the panic occurred
+    pub line: u32,
+
+    /// The column in which the panic occurred
+    pub column: u32,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "panicked at {}:{}:{}: {}",
+            self.file, self.line, self.column, self.message,
+        )
+    }
+}
@@ -9,6 +9,8 @@ use serde::{
     Serialize,
 };
 
+use crate::units;
+
 
 /// Sent by the host to command a test node to set a pin to a specific level
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
@@ -38,14 +40,107 @@ pub struct ReadLevelResult<Id> {
     /// The new level of the pin
     pub level: Level,
 
-    /// The period since the last change of this pin's level, in milliseconds
+    /// The period since the last change of this pin's level
     ///
     /// This value might not be available, because this is the first change of
     /// this pin's level, or because the test node doesn't measure the period.
     ///
     /// If the time since the last change has been too long, this value will
     /// not be reliable.
-    pub period_ms: Option<u32>,
+    pub period: Option<units::Microseconds>,
+}
+
+
+/// Sent by a test node, once it stops monitoring the edges of a pin
+///
+/// Summarizes the signal on the pin, while it was being monitored. A period
+/// lasts from one rising edge to the next, and the signal is high from the
+/// rising edge to the falling edge in between. Only complete periods are
+/// counted. All durations are in ticks of the test node's timer.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Signal<Id> {
+    /// The pin that was monitored
+    pub pin: Id,
+
+    /// The number of complete periods that were measured
+    pub periods: u32,
+
+    /// The shortest period that was measured
+    pub period_min: u32,
+
+    /// The longest period that was measured
+    pub period_max: u32,
+
+    /// The sum of all periods that were measured
+    pub period_total: u64,
+
+    /// The sum of the time the signal was high, during those periods
+    pub high_total: u64,
+
+    /// The nominal frequency of the timer, in Hz
+    pub timer_hz: u32,
+}
+
+impl<Id> Signal<Id> {
+    /// The average period in seconds
+    ///
+    /// Returns `None`, if no complete period was measured.
+    pub fn period_secs(&self) -> Option<f64> {
+        if self.periods == 0 {
+            return None;
+        }
+
+        let ticks = self.period_total as f64 / self.periods as f64;
+        Some(ticks / self.timer_hz as f64)
+    }
+
+    /// The shortest and longest period in seconds
+    ///
+    /// Returns `None`, if no complete period was measured.
+    pub fn period_range_secs(&self) -> Option<(f64, f64)> {
+        if self.periods == 0 {
+            return None;
+        }
+
+        let timer_hz = self.timer_hz as f64;
+        Some((
+            self.period_min as f64 / timer_hz,
+            self.period_max as f64 / timer_hz,
+        ))
+    }
+
+    /// The fraction of the time the signal was high, from `0.0` to `1.0`
+    ///
+    /// Returns `None`, if no complete period was measured.
+    pub fn duty_cycle(&self) -> Option<f64> {
+        if self.periods == 0 {
+            return None;
+        }
+
+        Some(self.high_total as f64 / self.period_total as f64)
+    }
+}
+
+
+/// Identifies a GPIO pin by its port and its number within that port
+///
+/// For example, PIO1_2 on an LPC845 is port 1, number 2. For microcontrollers
+/// whose ports are identified by letters, port A is 0, port B is 1, and so on.
+/// PC1 on an STM32L4 is port 2, number 1.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct PortPin {
+    pub port:   u8,
+    pub number: u8,
+}
+
+impl PortPin {
+    /// Create a new instance of `PortPin`
+    pub const fn new(port: u8, number: u8) -> Self {
+        Self {
+            port,
+            number,
+        }
+    }
 }
 
 
@@ -55,3 +150,33 @@ pub enum Level {
     High,
     Low,
 }
+
+
+/// Represents a change of the electrical level of a pin
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+
+/// The direction of a GPIO pin
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+
+/// The internal resistor that a pin is connected to
+///
+/// Not every test node supports every option. `Repeater` keeps the pin at the
+/// level it was last driven to, by enabling the pull-up while it is high, and
+/// the pull-down while it is low.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Pull {
+    None,
+    Down,
+    Up,
+    Repeater,
+}
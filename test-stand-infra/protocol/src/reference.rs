@@ -0,0 +1,67 @@
+//! Generic protocol related to the reference clock input
+//!
+//! A test assistant can have an input for an external reference clock, like
+//! the 1 PPS output of a GPS receiver, or a divided-down output of a lab
+//! frequency standard. The assistant timestamps the rising edges of the
+//! reference, using its own timer. Since the frequency of the reference is
+//! known, those timestamps reveal how far the assistant's clock is off, which
+//! makes frequencies measured by the assistant traceable to the reference.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// Timestamps of the rising edges of the reference clock
+///
+/// All timestamps are in ticks of the assistant's timer. The timer wraps
+/// around after 2^32 ticks, so the reference must be read before that much
+/// time has passed since the timestamps were reset.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Timestamps {
+    /// The number of edges captured since the timestamps were reset
+    pub edges: u32,
+
+    /// The timestamp of the first edge since the timestamps were reset
+    pub first: Option<u32>,
+
+    /// The timestamp of the most recent edge
+    pub last: Option<u32>,
+
+    /// The value of the timer at the time the timestamps were read
+    pub now: u32,
+
+    /// The nominal frequency of the timer, in Hz
+    pub timer_hz: u32,
+}
+
+impl Timestamps {
+    /// Compute the actual frequency of the timer, in Hz
+    ///
+    /// `reference_hz` is the frequency of the reference clock. Returns `None`,
+    /// if fewer than two edges have been captured.
+    pub fn timer_frequency(&self, reference_hz: f64) -> Option<f64> {
+        let (first, last) = match (self.first, self.last) {
+            (Some(first), Some(last)) if self.edges >= 2 => (first, last),
+            _ => return None,
+        };
+
+        let ticks   = last.wrapping_sub(first) as f64;
+        let periods = (self.edges - 1) as f64;
+
+        Some(ticks * reference_hz / periods)
+    }
+
+    /// Compute the deviation of the timer from its nominal frequency
+    ///
+    /// Returns the deviation in parts per million. A positive value means the
+    /// timer runs fast. See [`Timestamps::timer_frequency`] for the arguments.
+    pub fn timer_error_ppm(&self, reference_hz: f64) -> Option<f64> {
+        let actual  = self.timer_frequency(reference_hz)?;
+        let nominal = self.timer_hz as f64;
+
+        Some((actual - nominal) / nominal * 1_000_000.0)
+    }
+}
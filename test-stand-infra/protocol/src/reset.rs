@@ -0,0 +1,50 @@
+//! Generic protocol related to reset causes
+//!
+//! Each MCU records why it was last reset in a status register, like SYSRSTSTAT
+//! on the LPC845, or RCC_CSR on the STM32L4. Test nodes map that register to
+//! [`Cause`], so test cases can check which reset occurred, without knowing
+//! which MCU they're running on.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The cause of the last reset of a test node
+///
+/// The status register can record more than one cause, as some resets cause
+/// others. A test node reports the most specific one, in this order:
+/// `LowPower`, `Watchdog`, `Software`, `BrownOut`, `PowerOn`, `Pin`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Cause {
+    /// The test node was powered on
+    PowerOn,
+
+    /// The supply voltage dropped below the brown-out threshold
+    ///
+    /// The STM32L4 doesn't distinguish this from a power-on reset, and reports
+    /// both as `BrownOut`.
+    BrownOut,
+
+    /// The reset pin was pulled low, for example by a debugger
+    Pin,
+
+    /// The watchdog timed out
+    Watchdog,
+
+    /// The firmware requested a reset
+    ///
+    /// This includes resets after a panic or HardFault, and resets requested by
+    /// the host.
+    Software,
+
+    /// The test node entered a low-power mode that it isn't allowed to enter
+    ///
+    /// Only reported by the STM32L4.
+    LowPower,
+
+    /// The status register doesn't record any cause
+    Unknown,
+}
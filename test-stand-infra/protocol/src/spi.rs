@@ -0,0 +1,16 @@
+//! Generic protocol related to SPI
+//!
+//! A test node can emulate an SPI slave, whose response the host loads through
+//! messages. This allows testing SPI master drivers bit-for-bit, without
+//! depending on an external device.
+//!
+//! Every transaction starts at the beginning of the response. The slave sends
+//! one byte of the response for every byte it receives, then `0xff`, once the
+//! response is exhausted. It records the bytes it receives, which the host can
+//! query after the transaction.
+
+
+/// The maximum length of the response, and of the recorded data
+///
+/// Bytes received beyond this length are not recorded.
+pub const BUF_CAP: usize = 32;
@@ -0,0 +1,27 @@
+//! Generic protocol related to the trigger line
+//!
+//! The trigger line is a wire that connects the test assistant to all other
+//! test nodes. The host can arm every test node with an action, then instruct
+//! the assistant to fire the trigger. All armed test nodes perform their action
+//! on the same edge of the trigger signal, which makes stimuli on multiple test
+//! nodes truly simultaneous. Pacing commands over separate serial links can't
+//! achieve that.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::pin;
+
+
+/// An action that a test node performs on the rising edge of the trigger
+///
+/// An action is performed once. Afterwards, the test node needs to be armed
+/// again.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Action<Id> {
+    /// Set a pin to a specific level
+    SetLevel(pin::SetLevel<Id>),
+}
@@ -0,0 +1,98 @@
+//! Typed units for timing and analog values
+//!
+//! Messages carry durations and voltages as these types, instead of plain
+//! integers, so the unit is part of the type. Test nodes convert them into
+//! whatever their hardware needs, and the host converts them from and into
+//! `Duration`, in one place each.
+
+
+use core::{
+    convert::TryFrom,
+    time::Duration,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// A duration in microseconds
+///
+/// Covers a bit more than 71 minutes, which is plenty for the periods and
+/// timeouts that test nodes deal with.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub struct Microseconds(pub u32);
+
+impl From<Microseconds> for Duration {
+    fn from(value: Microseconds) -> Self {
+        Duration::from_micros(value.0.into())
+    }
+}
+
+impl TryFrom<Duration> for Microseconds {
+    type Error = OutOfRange;
+
+    /// Convert from a `Duration`, rounding down to whole microseconds
+    ///
+    /// Fails, if the duration doesn't fit into `Microseconds`.
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        u32::try_from(value.as_micros())
+            .map(|value| Self(value))
+            .map_err(|_| OutOfRange)
+    }
+}
+
+
+/// A duration in nanoseconds
+///
+/// For the short pulses and delays that `Microseconds` can't resolve. Covers a
+/// bit more than 4 seconds.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub struct Nanoseconds(pub u32);
+
+impl From<Nanoseconds> for Duration {
+    fn from(value: Nanoseconds) -> Self {
+        Duration::from_nanos(value.0.into())
+    }
+}
+
+impl TryFrom<Duration> for Nanoseconds {
+    type Error = OutOfRange;
+
+    /// Convert from a `Duration`
+    ///
+    /// Fails, if the duration doesn't fit into `Nanoseconds`.
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        u32::try_from(value.as_nanos())
+            .map(|value| Self(value))
+            .map_err(|_| OutOfRange)
+    }
+}
+
+
+/// A voltage in millivolts
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub struct Millivolts(pub u32);
+
+impl Millivolts {
+    /// Convert the raw result of an analog-to-digital conversion
+    ///
+    /// `bits` is the resolution of the ADC, and `reference` the voltage that
+    /// the largest possible result corresponds to.
+    pub fn from_adc(value: u16, bits: u32, reference: Millivolts) -> Self {
+        let max = (1 << bits) - 1;
+        Self(u32::from(value) * reference.0 / max)
+    }
+}
+
+
+/// A value didn't fit into the range of a unit type
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OutOfRange;
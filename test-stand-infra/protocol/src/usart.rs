@@ -0,0 +1,215 @@
+//! Generic protocol related to USART configuration
+//!
+//! All test nodes start their USARTs with the same configuration: 115200 baud,
+//! 8 data bits, no parity, 1 stop bit. The host can change that at runtime, to
+//! test how a HAL handles other configurations. Both ends of a USART link need
+//! to be reconfigured the same way, or they won't understand each other.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The maximum length of the data of `HostToAssistant::SendUsartDuplex`
+pub const DUPLEX_CAP: usize = 128;
+
+/// The maximum length of the pattern of `HostToAssistant::StartUsartStream`
+pub const STREAM_PATTERN_CAP: usize = 32;
+
+
+/// The configuration of a USART
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Config {
+    /// The baud rate
+    ///
+    /// The test node uses the closest baud rate its clock allows.
+    pub baud: u32,
+
+    /// The parity bit
+    pub parity: Parity,
+
+    /// The number of stop bits
+    pub stop_bits: StopBits,
+
+    /// The number of data bits
+    pub word_length: WordLength,
+}
+
+impl Default for Config {
+    /// The configuration that all test nodes start with
+    fn default() -> Self {
+        Self {
+            baud:        115200,
+            parity:      Parity::None,
+            stop_bits:   StopBits::One,
+            word_length: WordLength::Eight,
+        }
+    }
+}
+
+
+/// The parity bit of a USART configuration
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// The number of stop bits of a USART configuration
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// The number of data bits of a USART configuration
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum WordLength {
+    Seven,
+    Eight,
+
+    /// 8 data bits, plus the address bit of multidrop communication
+    ///
+    /// Regular data is sent with the address bit cleared. Serial ports on the
+    /// host don't support this.
+    Nine,
+}
+
+
+/// The data a test node has received via USART, while counting it
+///
+/// All timestamps are in ticks of the test node's timer. The timer wraps
+/// around after 2^32 ticks, so a count must not take longer than that.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Count {
+    /// The number of bytes received
+    pub bytes: u32,
+
+    /// The number of receive errors, like framing or parity errors
+    pub errors: u32,
+
+    /// The number of bytes dropped, because the test node couldn't keep up
+    pub dropped: u32,
+
+    /// The timestamp of the first byte
+    pub first: Option<u32>,
+
+    /// The timestamp of the last byte
+    pub last: Option<u32>,
+
+    /// The nominal frequency of the timer, in Hz
+    pub timer_hz: u32,
+}
+
+impl Count {
+    /// Compute the throughput, in bytes per second
+    ///
+    /// Covers the time from the first to the last byte, so the first byte
+    /// itself isn't counted. Returns `None`, if fewer than two bytes have been
+    /// received.
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        let (first, last) = match (self.first, self.last) {
+            (Some(first), Some(last)) if self.bytes >= 2 => (first, last),
+            _ => return None,
+        };
+
+        let ticks = last.wrapping_sub(first);
+        if ticks == 0 {
+            return None;
+        }
+
+        let secs = ticks as f64 / self.timer_hz as f64;
+        Some((self.bytes - 1) as f64 / secs)
+    }
+}
+
+
+/// The data a test node has received via USART, while throttling it with CTS
+///
+/// All timestamps are in ticks of the test node's timer. Like with `Count`,
+/// the timer must not wrap around during the measurement.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Throttle {
+    /// The number of bytes received
+    pub bytes: u32,
+
+    /// The number of bytes received while CTS was deasserted
+    ///
+    /// The sender finishes the byte it is sending when CTS is deasserted, so
+    /// this can be one, even if the sender respects CTS.
+    pub bytes_paused: u32,
+
+    /// The timestamp of the moment CTS was deasserted
+    pub deasserted: Option<u32>,
+
+    /// The timestamp of the moment CTS was asserted again
+    pub asserted: Option<u32>,
+
+    /// The timestamp of the last byte received before CTS was deasserted
+    pub last_before: Option<u32>,
+
+    /// The timestamp of the first byte received after CTS was asserted again
+    pub first_after: Option<u32>,
+
+    /// The nominal frequency of the timer, in Hz
+    pub timer_hz: u32,
+}
+
+impl Throttle {
+    /// The time CTS was deasserted, in seconds
+    ///
+    /// Returns `None`, if CTS hasn't been asserted again yet.
+    pub fn paused_secs(&self) -> Option<f64> {
+        let (deasserted, asserted) = match (self.deasserted, self.asserted) {
+            (Some(deasserted), Some(asserted)) => (deasserted, asserted),
+            _ => return None,
+        };
+
+        let ticks = asserted.wrapping_sub(deasserted);
+        Some(ticks as f64 / self.timer_hz as f64)
+    }
+
+    /// The gap in the received data around the pause, in seconds
+    ///
+    /// Covers the time from the last byte before CTS was deasserted, to the
+    /// first byte after it was asserted again. If the sender respects CTS, this
+    /// is at least as long as `paused_secs`, minus the time of one byte.
+    /// Returns `None`, if no data was received before or after the pause.
+    pub fn gap_secs(&self) -> Option<f64> {
+        let (before, after) = match (self.last_before, self.first_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => return None,
+        };
+
+        let ticks = after.wrapping_sub(before);
+        Some(ticks as f64 / self.timer_hz as f64)
+    }
+}
+
+
+/// The data a test node has streamed via USART, and what came back
+///
+/// The other end is expected to echo the stream. Every byte that comes back is
+/// compared to the byte of the stream at the same position. Once a byte is
+/// lost, all that follow are at the wrong position, so any loss shows up as
+/// mismatches.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Stream {
+    /// The number of bytes sent
+    pub sent: u32,
+
+    /// The number of bytes received
+    pub received: u32,
+
+    /// The number of received bytes that didn't match the stream
+    pub mismatches: u32,
+
+    /// The number of receive errors, like framing or parity errors
+    pub errors: u32,
+
+    /// The number of bytes dropped, because the test node couldn't keep up
+    pub dropped: u32,
+}
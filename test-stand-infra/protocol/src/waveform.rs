@@ -0,0 +1,96 @@
+//! Generic protocol related to waveform playback
+//!
+//! A test node can play back a waveform that the host has sent it, changing
+//! multiple pins at precisely timed steps. This allows generating stimulus
+//! patterns, like chip select, clock, and data lines of a bus, without writing
+//! new firmware for each pattern.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    pin,
+    units,
+};
+
+
+/// The maximum number of steps in a waveform
+pub const STEPS_CAP: usize = 16;
+
+
+/// A step of a waveform
+///
+/// Pins are identified by their index, which is bit `n` in `mask` and
+/// `levels` for the pin with index `n`. The test stand defines which pin has
+/// which index.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Step {
+    /// How long to wait before this step
+    ///
+    /// Counts from the time the previous step was due, or from the start of
+    /// playback, for the first step. Delays longer than the wrap-around period
+    /// of the test node's timer are not supported.
+    pub delay: units::Microseconds,
+
+    /// The pins that this step changes
+    pub mask: u8,
+
+    /// The new levels of the pins in `mask`
+    ///
+    /// A set bit means high, a cleared bit low. Bits that aren't set in `mask`
+    /// are ignored.
+    pub levels: u8,
+}
+
+impl Step {
+    /// The new level of the pin with the given index
+    ///
+    /// Returns `None`, if this step doesn't change the pin.
+    pub fn level(&self, index: u8) -> Option<pin::Level> {
+        let bit = 1 << index;
+
+        if self.mask & bit == 0 {
+            return None;
+        }
+
+        match self.levels & bit {
+            0 => Some(pin::Level::Low),
+            _ => Some(pin::Level::High),
+        }
+    }
+}
+
+
+/// A waveform, made up of steps that are played back in order
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Waveform([Option<Step>; STEPS_CAP]);
+
+impl Waveform {
+    /// Create an empty waveform
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step to the end of the waveform
+    ///
+    /// Returns the step as an error, if the waveform is full.
+    pub fn push(&mut self, step: Step) -> Result<(), Step> {
+        match self.0.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(step);
+                Ok(())
+            }
+            None => {
+                Err(step)
+            }
+        }
+    }
+
+    /// The steps of the waveform, in order
+    pub fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.0.iter().flatten()
+    }
+}
@@ -0,0 +1,251 @@
+//! GPIO conformance test cases
+//!
+//! Checks that the target sets its output pin and reads its input pin
+//! correctly, and that it does so within the latency bounds that the test suite
+//! specifies for its test stand.
+//!
+//! All latencies are measured on the host, from sending the command until the
+//! other test node has observed the change. This means they include the
+//! latency of the serial links, and can only be compared between test stands
+//! that use a similar host and test assistant. Interrupt latency can't be
+//! measured that way, and isn't covered yet.
+//!
+//! A target can't test pins that it needs to talk to the host. The test cases
+//! are skipped for such a target.
+
+
+use std::{
+    fmt::Debug,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use host_lib::{
+    assistant::{
+        Assistant,
+        AssistantError,
+    },
+    tolerances::Tolerances,
+};
+use protocol::pin::Level;
+
+
+/// The GPIO functionality that a test target needs to provide
+pub trait GpioTarget {
+    type Error: Debug;
+
+    /// Set the level of the output pin that is connected to the assistant
+    fn set_output(&mut self, level: Level) -> Result<(), Self::Error>;
+
+    /// Read the level of the input pin that is connected to the assistant
+    fn read_input(&mut self) -> Result<Level, Self::Error>;
+
+    /// Indicates whether the target talks to the host through the output or
+    /// the input pin
+    fn host_link_uses_pins(&mut self) -> Result<bool, Self::Error>;
+}
+
+
+/// The latency bounds that a test stand needs to stay within
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    /// From commanding the target to change its output, until the assistant
+    /// observes the new level
+    pub output: Duration,
+
+    /// From commanding the assistant to change the target's input, until the
+    /// target reads the new level
+    pub input: Duration,
+}
+
+impl Bounds {
+    /// Take the latency bounds from the test stand's tolerances
+    pub fn from_tolerances(tolerances: &Tolerances) -> Self {
+        Self {
+            output: Duration::from_millis(tolerances.gpio_output_latency_ms()),
+            input:  Duration::from_millis(tolerances.gpio_input_latency_ms()),
+        }
+    }
+}
+
+
+/// The latencies measured by [`run`]
+#[derive(Debug)]
+pub struct Report {
+    /// Latency of setting the output pin high
+    pub set: LatencyMeasurement,
+
+    /// Latency of setting the output pin low
+    pub clear: LatencyMeasurement,
+
+    /// Latency of the target reading a changed input level
+    pub input: LatencyMeasurement,
+}
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct LatencyMeasurement {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+
+/// Run all GPIO conformance test cases
+///
+/// Every latency is sampled `samples` times. Returns `None`, without testing
+/// anything, if the target's host link uses the pins under test.
+pub fn run<T>(target: &mut T,
+    assistant: &mut Assistant,
+    bounds:    &Bounds,
+    samples:   u32,
+)
+    -> Result<Option<Report>, GpioConformanceError<T::Error>>
+    where T: GpioTarget
+{
+    let skip = target.host_link_uses_pins()
+        .map_err(|err| GpioConformanceError::Target(err))?;
+    if skip {
+        return Ok(None);
+    }
+
+    let set = output_latency(target, assistant, Level::High, bounds, samples)?;
+    let clear = output_latency(target, assistant, Level::Low, bounds, samples)?;
+    let input = input_latency(target, assistant, bounds, samples)?;
+
+    Ok(
+        Some(Report {
+            set,
+            clear,
+            input,
+        })
+    )
+}
+
+/// Measure how long it takes until a change of the output is observed
+///
+/// Before every sample, the output is set to the opposite of `level`, so every
+/// sample measures an actual change.
+pub fn output_latency<T>(target: &mut T,
+    assistant: &mut Assistant,
+    level:     Level,
+    bounds:    &Bounds,
+    samples:   u32,
+)
+    -> Result<LatencyMeasurement, GpioConformanceError<T::Error>>
+    where T: GpioTarget
+{
+    let mut latencies = Vec::new();
+
+    for _ in 0 .. samples {
+        for &level in &[opposite(level), level] {
+            let start = Instant::now();
+            target.set_output(level)
+                .map_err(|err| GpioConformanceError::Target(err))?;
+
+            let latency = wait_until(start, bounds.output, || {
+                let is_high = assistant.pin_is_high()
+                    .map_err(|err| GpioConformanceError::Assistant(err))?;
+                Ok(is_high == (level == Level::High))
+            })?;
+            latencies.push(latency);
+        }
+    }
+
+    // Only every second latency was measured for the requested level.
+    Ok(measure(latencies.into_iter().skip(1).step_by(2)))
+}
+
+/// Measure how long it takes until the target reads a changed input level
+pub fn input_latency<T>(target: &mut T,
+    assistant: &mut Assistant,
+    bounds:    &Bounds,
+    samples:   u32,
+)
+    -> Result<LatencyMeasurement, GpioConformanceError<T::Error>>
+    where T: GpioTarget
+{
+    let mut latencies = Vec::new();
+
+    for _ in 0 .. samples {
+        for &level in &[Level::Low, Level::High] {
+            let start  = Instant::now();
+            let result = match level {
+                Level::High => assistant.set_pin_high(),
+                Level::Low  => assistant.set_pin_low(),
+            };
+            result.map_err(|err| GpioConformanceError::Assistant(err))?;
+
+            let latency = wait_until(start, bounds.input, || {
+                let input = target.read_input()
+                    .map_err(|err| GpioConformanceError::Target(err))?;
+                Ok(input == level)
+            })?;
+            latencies.push(latency);
+        }
+    }
+
+    Ok(measure(latencies.into_iter()))
+}
+
+
+fn opposite(level: Level) -> Level {
+    match level {
+        Level::High => Level::Low,
+        Level::Low  => Level::High,
+    }
+}
+
+/// Poll `condition`, until it is met or `bound` has passed since `start`
+fn wait_until<E>(
+    start:         Instant,
+    bound:         Duration,
+    mut condition: impl FnMut() -> Result<bool, GpioConformanceError<E>>,
+)
+    -> Result<Duration, GpioConformanceError<E>>
+{
+    loop {
+        let met     = condition()?;
+        let elapsed = start.elapsed();
+
+        if elapsed > bound {
+            return Err(
+                GpioConformanceError::ExceededBound {
+                    measured: elapsed,
+                    bound,
+                }
+            );
+        }
+        if met {
+            return Ok(elapsed);
+        }
+    }
+}
+
+fn measure(latencies: impl Iterator<Item=Duration>) -> LatencyMeasurement {
+    let mut measurement = LatencyMeasurement {
+        min: Duration::MAX,
+        max: Duration::ZERO,
+    };
+
+    for latency in latencies {
+        measurement.min = measurement.min.min(latency);
+        measurement.max = measurement.max.max(latency);
+    }
+
+    measurement
+}
+
+
+#[derive(Debug)]
+pub enum GpioConformanceError<E> {
+    Assistant(AssistantError),
+    Target(E),
+
+    /// A change wasn't observed within the bound
+    ExceededBound {
+        measured: Duration,
+        bound:    Duration,
+    },
+}
@@ -0,0 +1,9 @@
+//! Test cases that are shared between test stands
+//!
+//! Every module covers one peripheral, and defines a trait that a test suite
+//! needs to implement for its test target. The test cases themselves only use
+//! that trait and the test assistant, so they run identically on every test
+//! stand.
+
+
+pub mod gpio;